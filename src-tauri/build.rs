@@ -1,3 +1,17 @@
 fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
     tauri_build::build()
 }
+
+/// Generate the gRPC service from the published proto with a vendored `protoc`,
+/// so building with `--features grpc` needs no system protobuf install
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let mut config = tonic_prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"));
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_with_config(config, &["proto/runebook/v1/runebook.proto"], &["proto"])
+        .expect("compile runebook.proto");
+}
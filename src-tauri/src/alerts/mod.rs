@@ -0,0 +1,531 @@
+//! Alert rules over the store event stream.
+//!
+//! Rules live in the `[alerts]` config section. Each pairs a condition on
+//! what the memory store records, such as an error fingerprint, a slow command
+//! or a pattern in a command's output, with actions: a notification in every
+//! window, a canvas run, or a webhook POST, either to a URL or to one of the
+//! [`webhooks`](crate::webhooks) configured in `[webhooks]`. The [`AlertService`] consumes the
+//! store's [`EventBus`](crate::memory::EventBus) and evaluates every event as
+//! it arrives. A per-rule [`RateLimit`] keeps a failing loop from raising the
+//! same alert hundreds of times.
+//!
+//! ```toml
+//! [[alerts.rules]]
+//! name = "slow release builds"
+//! when = { kind = "duration", min_ms = 600000, command = "cargo" }
+//! actions = [{ kind = "notify" }, { kind = "hook", name = "ops" }]
+//! rate_limit = { max = 1, per_secs = 3600 }
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use crate::dispatch::{self, WindowDispatcher};
+use crate::execution::{self, CanvasDocument, ExecutionService};
+use crate::memory::{Command, Error, StoreEvent, StoreOpener};
+use crate::webhooks::{WebhookEvent, WebhookSender};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use ts_rs::TS;
+
+/// Longest alert summary, in characters
+const SUMMARY_CHARS: usize = 200;
+
+/// `[alerts]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct AlertConfig {
+    pub rules: Vec<AlertRule>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// A condition and what to do when it holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct AlertRule {
+    /// Unique among the rules; names the rule in alerts
+    pub name: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub when: AlertCondition,
+    pub actions: Vec<AlertAction>,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+}
+
+/// What an event must show for a rule to fire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+#[ts(export)]
+pub enum AlertCondition {
+    /// An error with this [fingerprint](crate::memory::Error::fingerprint),
+    /// from a failed command or recorded on its own
+    ErrorFingerprint { fingerprint: String },
+    /// A command that ran at least `min_ms`
+    Duration {
+        min_ms: u64,
+        /// Only this program, e.g. `cargo`
+        #[serde(default)]
+        command: Option<String>,
+    },
+    /// A command whose output matches the regex `pattern`
+    OutputContains {
+        pattern: String,
+        #[serde(default)]
+        command: Option<String>,
+    },
+}
+
+/// What a firing rule does
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+#[ts(export)]
+pub enum AlertAction {
+    /// An `alert-fired` event in every window
+    Notify,
+    /// Run the canvas file at `path`. Besides `variables`, its templates get
+    /// `alert_rule`, `alert_summary` and `alert_command_id`.
+    RunCanvas {
+        path: String,
+        #[serde(default)]
+        variables: HashMap<String, String>,
+    },
+    /// POST the [`Alert`] as JSON to `url`
+    Webhook { url: String },
+    /// Send the alert to the `[[webhooks.hooks]]` entry named `name`
+    Hook { name: String },
+}
+
+/// At most `max` alerts from a rule in any `per_secs` seconds; the rest are
+/// dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct RateLimit {
+    pub max: u32,
+    pub per_secs: u64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            max: 1,
+            per_secs: 300,
+        }
+    }
+}
+
+/// A rule that fired
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub rule: String,
+    pub fired_at: DateTime<Utc>,
+    pub summary: String,
+    pub command_id: Option<String>,
+    pub session_id: Option<String>,
+    /// Fingerprint of the error behind the alert, if there is one
+    pub fingerprint: Option<String>,
+}
+
+/// Program name of `command`, without its directory
+fn program(command: &Command) -> &str {
+    command
+        .command
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(&command.command)
+}
+
+fn command_line(command: &Command) -> String {
+    std::iter::once(command.command.as_str())
+        .chain(command.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn truncate(text: &str) -> String {
+    text.trim().chars().take(SUMMARY_CHARS).collect()
+}
+
+struct CompiledRule {
+    rule: AlertRule,
+    pattern: Option<Regex>,
+    /// When the rule fired within its rate-limit window, oldest first
+    fired: VecDeque<DateTime<Utc>>,
+}
+
+impl CompiledRule {
+    fn new(rule: AlertRule) -> Result<Self> {
+        if rule.name.trim().is_empty() {
+            anyhow::bail!("Alert rule without a name");
+        }
+        if rule.actions.is_empty() {
+            anyhow::bail!("Alert rule {} has no actions", rule.name);
+        }
+        if rule.rate_limit.max == 0 {
+            anyhow::bail!("Alert rule {} has a rate limit of 0", rule.name);
+        }
+        for action in &rule.actions {
+            if let AlertAction::Webhook { url } = action {
+                let parsed = url::Url::parse(url)
+                    .with_context(|| format!("Alert rule {}: invalid webhook URL", rule.name))?;
+                if !matches!(parsed.scheme(), "http" | "https") {
+                    anyhow::bail!("Alert rule {}: webhooks must be http or https", rule.name);
+                }
+            }
+        }
+        let pattern = match &rule.when {
+            AlertCondition::OutputContains { pattern, .. } => Some(
+                Regex::new(pattern)
+                    .with_context(|| format!("Alert rule {}: invalid pattern", rule.name))?,
+            ),
+            _ => None,
+        };
+        Ok(Self {
+            rule,
+            pattern,
+            fired: VecDeque::new(),
+        })
+    }
+
+    fn wants_command(&self, command: &Command, filter: &Option<String>) -> bool {
+        filter
+            .as_deref()
+            .is_none_or(|name| program(command) == name)
+    }
+
+    /// The alert `event` raises under this rule, before rate limiting
+    fn check(&self, event: &StoreEvent, output: Option<&str>, now: DateTime<Utc>) -> Option<Alert> {
+        let alert = |summary: String, command: Option<&Command>, error: Option<&Error>| Alert {
+            rule: self.rule.name.clone(),
+            fired_at: now,
+            summary: truncate(&summary),
+            command_id: command
+                .map(|c| c.id.clone())
+                .or_else(|| error.map(|e| e.command_id.clone())),
+            session_id: command
+                .map(|c| c.session_id.clone())
+                .or_else(|| error.map(|e| e.session_id.clone())),
+            fingerprint: error.map(Error::fingerprint),
+        };
+        match (&self.rule.when, event) {
+            (AlertCondition::ErrorFingerprint { fingerprint }, _) => {
+                let (command, error) = match event {
+                    StoreEvent::CommandFinished { command, error } => {
+                        (Some(&**command), error.as_ref()?)
+                    }
+                    StoreEvent::ErrorRecorded { error } => (None, error),
+                };
+                error
+                    .fingerprint()
+                    .eq_ignore_ascii_case(fingerprint.trim())
+                    .then(|| alert(error.message.clone(), command, Some(error)))
+            }
+            (
+                AlertCondition::Duration {
+                    min_ms,
+                    command: filter,
+                },
+                StoreEvent::CommandFinished { command, error },
+            ) => {
+                let duration_ms = command.awake_ms()?;
+                (duration_ms >= *min_ms && self.wants_command(command, filter)).then(|| {
+                    alert(
+                        format!(
+                            "{} took {:.1}s",
+                            command_line(command),
+                            duration_ms as f64 / 1000.0
+                        ),
+                        Some(&**command),
+                        error.as_ref(),
+                    )
+                })
+            }
+            (
+                AlertCondition::OutputContains {
+                    command: filter, ..
+                },
+                StoreEvent::CommandFinished { command, error },
+            ) => {
+                if !self.wants_command(command, filter) {
+                    return None;
+                }
+                let pattern = self.pattern.as_ref()?;
+                let line = output?.lines().find(|line| pattern.is_match(line))?;
+                Some(alert(
+                    format!("{}: {}", command_line(command), line.trim()),
+                    Some(&**command),
+                    error.as_ref(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the rule may fire at `now`, counting the firing if so
+    fn admit(&mut self, now: DateTime<Utc>) -> bool {
+        let window = Duration::seconds(self.rule.rate_limit.per_secs as i64);
+        while self.fired.front().is_some_and(|&at| now - at >= window) {
+            self.fired.pop_front();
+        }
+        if self.fired.len() >= self.rule.rate_limit.max as usize {
+            return false;
+        }
+        self.fired.push_back(now);
+        true
+    }
+}
+
+/// Compiled rules and their rate-limit state
+pub struct AlertEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl AlertEngine {
+    /// Compile `config`'s rules; any invalid rule fails the whole set
+    pub fn new(config: &AlertConfig) -> Result<Self> {
+        let mut names = std::collections::HashSet::new();
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            if !names.insert(rule.name.as_str()) {
+                anyhow::bail!("Two alert rules are named {}", rule.name);
+            }
+            rules.push(CompiledRule::new(rule.clone())?);
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn config(&self) -> AlertConfig {
+        AlertConfig {
+            rules: self.rules.iter().map(|r| r.rule.clone()).collect(),
+        }
+    }
+
+    /// Take over the rate-limit state of `previous`'s rules with the same name
+    fn keep_history(&mut self, previous: AlertEngine) {
+        let mut fired: HashMap<String, VecDeque<DateTime<Utc>>> = previous
+            .rules
+            .into_iter()
+            .map(|r| (r.rule.name, r.fired))
+            .collect();
+        for rule in &mut self.rules {
+            if let Some(history) = fired.remove(&rule.rule.name) {
+                rule.fired = history;
+            }
+        }
+    }
+
+    /// Whether evaluating `event` needs the command's output
+    pub fn needs_output(&self, event: &StoreEvent) -> bool {
+        matches!(event, StoreEvent::CommandFinished { .. })
+            && self.rules.iter().any(|r| {
+                r.rule.enabled && matches!(r.rule.when, AlertCondition::OutputContains { .. })
+            })
+    }
+
+    /// Alerts `event` raises at `now`, with the actions to take for each.
+    /// `output` is the command's output, when [`Self::needs_output`] asked
+    /// for it. Alerts over a rule's rate limit are dropped.
+    pub fn evaluate(
+        &mut self,
+        event: &StoreEvent,
+        output: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Vec<(Alert, Vec<AlertAction>)> {
+        let mut fired = Vec::new();
+        for rule in self.rules.iter_mut().filter(|r| r.rule.enabled) {
+            let Some(alert) = rule.check(event, output, now) else {
+                continue;
+            };
+            if rule.admit(now) {
+                fired.push((alert, rule.rule.actions.clone()));
+            } else {
+                log::debug!("[alerts] {} is over its rate limit", rule.rule.name);
+            }
+        }
+        fired
+    }
+}
+
+/// Carries out alert actions
+pub struct AlertActions {
+    execution: Arc<ExecutionService>,
+    /// Windows to notify; without them (headless) alerts are only logged
+    dispatcher: Option<Arc<WindowDispatcher>>,
+    webhooks: Arc<WebhookSender>,
+}
+
+impl AlertActions {
+    pub fn new(
+        execution: Arc<ExecutionService>,
+        dispatcher: Option<Arc<WindowDispatcher>>,
+        webhooks: Arc<WebhookSender>,
+    ) -> Self {
+        Self {
+            execution,
+            dispatcher,
+            webhooks,
+        }
+    }
+
+    async fn run(&self, alert: &Alert, action: &AlertAction) -> Result<()> {
+        match action {
+            AlertAction::Notify => {
+                log::info!("[alerts] {}: {}", alert.rule, alert.summary);
+                if let Some(dispatcher) = &self.dispatcher {
+                    dispatcher.dispatch(
+                        dispatch::Topic::Memory,
+                        alert.session_id.as_deref(),
+                        dispatch::ALERT_FIRED,
+                        alert,
+                    );
+                }
+            }
+            AlertAction::RunCanvas { path, variables } => {
+                let raw = execution::load_canvas_file(Path::new(path))?;
+                let canvas: CanvasDocument = serde_json::from_value(raw)
+                    .with_context(|| format!("Invalid canvas in {}", path))?;
+                let mut variables = variables.clone();
+                variables.insert("alert_rule".to_string(), alert.rule.clone());
+                variables.insert("alert_summary".to_string(), alert.summary.clone());
+                variables.insert(
+                    "alert_command_id".to_string(),
+                    alert.command_id.clone().unwrap_or_default(),
+                );
+                let report =
+                    execution::run_canvas(&self.execution, &canvas, &variables, "alert").await?;
+                if !report.success {
+                    anyhow::bail!("Canvas {} failed", path);
+                }
+            }
+            AlertAction::Webhook { url } => {
+                let event = WebhookEvent::Alert(alert.clone());
+                let delivery = self.webhooks.send_to_url(url, &event).await?;
+                if let Some(error) = delivery.error {
+                    anyhow::bail!("Webhook {} failed: {}", url, error);
+                }
+            }
+            AlertAction::Hook { name } => {
+                let event = WebhookEvent::Alert(alert.clone());
+                let delivery = self.webhooks.send(name, &event).await?;
+                if let Some(error) = delivery.error {
+                    anyhow::bail!("Webhook {} failed: {}", name, error);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The rules in effect and the config file they are saved to
+pub struct AlertService {
+    engine: Mutex<AlertEngine>,
+    config_path: PathBuf,
+}
+
+impl AlertService {
+    pub fn new(engine: AlertEngine, config_path: PathBuf) -> Self {
+        Self {
+            engine: Mutex::new(engine),
+            config_path,
+        }
+    }
+
+    pub fn rules(&self) -> Vec<AlertRule> {
+        self.engine
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .config()
+            .rules
+    }
+
+    /// Replace the rules, in effect right away and saved to the `[alerts]`
+    /// section of the config file. Rules keep their rate-limit state by name.
+    /// The engine stays locked while the file is written, so concurrent saves
+    /// leave the file and the rules in effect the same.
+    pub fn save_rules(&self, rules: Vec<AlertRule>) -> Result<()> {
+        let config = AlertConfig { rules };
+        let mut engine = AlertEngine::new(&config)?;
+        let mut current = self.engine.lock().unwrap_or_else(|e| e.into_inner());
+        crate::config::write_section(&self.config_path, "alerts", &config)?;
+        let previous = std::mem::replace(&mut *current, AlertEngine { rules: Vec::new() });
+        engine.keep_history(previous);
+        *current = engine;
+        Ok(())
+    }
+
+    /// Evaluate every event from `events` until the bus closes, carrying out
+    /// the actions of rules that fire
+    pub async fn serve(
+        self: Arc<Self>,
+        mut events: broadcast::Receiver<StoreEvent>,
+        open_store: StoreOpener,
+        actions: Arc<AlertActions>,
+    ) {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::warn!("[alerts] {} events arrived too fast to evaluate", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let output = match &event {
+                StoreEvent::CommandFinished { command, .. }
+                    if self
+                        .engine
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .needs_output(&event) =>
+                {
+                    match command_output(&open_store, &command.id).await {
+                        Ok(output) => Some(output),
+                        Err(e) => {
+                            log::warn!("[alerts] Output of {} unavailable: {:#}", command.id, e);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+            let fired = self
+                .engine
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .evaluate(&event, output.as_deref(), Utc::now());
+            for (alert, rule_actions) in fired {
+                for action in rule_actions {
+                    let (actions, alert) = (Arc::clone(&actions), alert.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = actions.run(&alert, &action).await {
+                            log::warn!("[alerts] Action of {} failed: {:#}", alert.rule, e);
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// A command's stored stdout and stderr as text
+async fn command_output(open_store: &StoreOpener, command_id: &str) -> Result<String> {
+    let store = open_store().await?;
+    let mut text = String::new();
+    for output in store.command_outputs(command_id).await? {
+        if matches!(output.stream_type.as_str(), "stdout" | "stderr") {
+            text.push_str(&crate::memory::pages::chunk_text(&output)?);
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,165 @@
+use super::*;
+
+fn finished(command: &str, duration_ms: u64, message: Option<&str>) -> StoreEvent {
+    let mut command = Command::new(
+        "s".to_string(),
+        command.to_string(),
+        vec!["build".to_string()],
+        "/src".to_string(),
+    );
+    command.duration_ms = Some(duration_ms);
+    let error = message.map(|message| {
+        Error::new(
+            command.id.clone(),
+            "s".to_string(),
+            "exit_code".to_string(),
+            "high".to_string(),
+            message.to_string(),
+        )
+    });
+    StoreEvent::CommandFinished {
+        command: Box::new(command),
+        error,
+    }
+}
+
+fn rule(name: &str, when: AlertCondition) -> AlertRule {
+    AlertRule {
+        name: name.to_string(),
+        enabled: true,
+        when,
+        actions: vec![AlertAction::Notify],
+        rate_limit: RateLimit::default(),
+    }
+}
+
+#[test]
+fn fingerprints_ignore_numbers_ids_and_paths() {
+    let error = |message: &str| {
+        Error::new(
+            "c".to_string(),
+            "s".to_string(),
+            "exit_code".to_string(),
+            "high".to_string(),
+            message.to_string(),
+        )
+    };
+    let a = error("Connection to 10.0.0.7:5432 refused after 3 attempts (req 9f86d081884c)");
+    let b = error("Connection to 10.0.0.9:5432 refused after 12 attempts (req 1b4f0e9851971998)");
+    assert_eq!(a.fingerprint(), b.fingerprint());
+    assert_eq!(a.fingerprint().len(), 16);
+    assert_eq!(
+        error("cannot open /home/a/x.toml").fingerprint(),
+        error("cannot open /tmp/y.toml").fingerprint()
+    );
+    assert_ne!(a.fingerprint(), error("Connection reset").fingerprint());
+}
+
+#[test]
+fn rules_fire_on_matching_events_within_rate_limits() {
+    let StoreEvent::CommandFinished {
+        error: Some(error), ..
+    } = finished("cargo", 10, Some("linker `cc` not found"))
+    else {
+        unreachable!()
+    };
+    let mut limited = rule(
+        "linker",
+        AlertCondition::ErrorFingerprint {
+            fingerprint: error.fingerprint(),
+        },
+    );
+    limited.rate_limit = RateLimit {
+        max: 2,
+        per_secs: 60,
+    };
+    let config = AlertConfig {
+        rules: vec![
+            limited,
+            rule(
+                "slow cargo",
+                AlertCondition::Duration {
+                    min_ms: 60_000,
+                    command: Some("cargo".to_string()),
+                },
+            ),
+            rule(
+                "oom",
+                AlertCondition::OutputContains {
+                    pattern: r"(?i)out of memory".to_string(),
+                    command: None,
+                },
+            ),
+        ],
+    };
+    let mut engine = AlertEngine::new(&config).unwrap();
+    let now = Utc::now();
+
+    let slow = finished("/usr/bin/cargo", 90_000, None);
+    assert!(engine.needs_output(&slow));
+    let fired = engine.evaluate(&slow, Some("Compiling\nfatal: Out of memory\n"), now);
+    let names: Vec<&str> = fired.iter().map(|(a, _)| a.rule.as_str()).collect();
+    assert_eq!(names, ["slow cargo", "oom"]);
+    assert_eq!(fired[0].0.summary, "/usr/bin/cargo build took 90.0s");
+    assert_eq!(
+        fired[1].0.summary,
+        "/usr/bin/cargo build: fatal: Out of memory"
+    );
+    assert!(engine
+        .evaluate(&finished("make", 90_000, None), None, now)
+        .is_empty());
+
+    // The same error from another command, and on its own, until the limit
+    let event = finished("cargo", 10, Some("linker `cc` not found"));
+    assert_eq!(engine.evaluate(&event, None, now).len(), 1);
+    let recorded = StoreEvent::ErrorRecorded { error };
+    let fired = engine.evaluate(&recorded, None, now);
+    assert_eq!(fired[0].0.fingerprint, recorded_fingerprint(&recorded));
+    assert!(engine.evaluate(&event, None, now).is_empty());
+    let later = now + Duration::seconds(61);
+    assert_eq!(engine.evaluate(&event, None, later).len(), 1);
+}
+
+fn recorded_fingerprint(event: &StoreEvent) -> Option<String> {
+    match event {
+        StoreEvent::ErrorRecorded { error } => Some(error.fingerprint()),
+        StoreEvent::CommandFinished { .. } => None,
+    }
+}
+
+#[test]
+fn saved_rules_replace_the_config_section() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, "# keep me\n[quota]\nmax_total_bytes = 1000\n").unwrap();
+
+    let service = AlertService::new(
+        AlertEngine::new(&AlertConfig::default()).unwrap(),
+        path.clone(),
+    );
+    let mut webhook = rule(
+        "deploy failed",
+        AlertCondition::ErrorFingerprint {
+            fingerprint: "0123456789abcdef".to_string(),
+        },
+    );
+    webhook.actions.push(AlertAction::Webhook {
+        url: "https://hooks.example.com/deploys".to_string(),
+    });
+    service.save_rules(vec![webhook.clone()]).unwrap();
+    assert_eq!(service.rules(), vec![webhook.clone()]);
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("# keep me\n"));
+    let config: crate::config::RunebookConfig = toml::from_str(&text).unwrap();
+    assert_eq!(config.alerts.rules, vec![webhook.clone()]);
+    assert_eq!(config.quota.max_total_bytes, Some(1000));
+
+    // Invalid rules change nothing
+    let mut invalid = webhook.clone();
+    invalid.actions = vec![AlertAction::Webhook {
+        url: "file:///etc/passwd".to_string(),
+    }];
+    assert!(service.save_rules(vec![invalid]).is_err());
+    assert_eq!(service.rules(), vec![webhook]);
+}
@@ -0,0 +1,142 @@
+// Archive creation: walks the source paths and streams them into the chosen format
+
+use crate::archive::{ArchiveFormat, ArchiveProgress, ArchiveSummary, ProgressTracker};
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Pack `sources` into `destination` using `format`.
+///
+/// Directories are added recursively with paths relative to their parent, so
+/// packing `/home/me/project` produces entries under `project/`. Single-file
+/// formats (gzip, zstd) require exactly one regular file.
+pub fn create_archive(
+    format: ArchiveFormat,
+    sources: &[PathBuf],
+    destination: &Path,
+    progress: &mut dyn FnMut(&ArchiveProgress),
+) -> Result<ArchiveSummary> {
+    if sources.is_empty() {
+        anyhow::bail!("No source paths given");
+    }
+    for source in sources {
+        if !source.exists() {
+            anyhow::bail!("Source path does not exist: {}", source.display());
+        }
+    }
+
+    let file = File::create(destination)
+        .with_context(|| format!("Failed to create {}", destination.display()))?;
+    let mut tracker = ProgressTracker::new("create", progress);
+
+    match format {
+        ArchiveFormat::Tar => {
+            write_tar(file, sources, &mut tracker)?.flush()?;
+        }
+        ArchiveFormat::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            write_tar(encoder, sources, &mut tracker)?.finish()?;
+        }
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::Encoder::new(file, 0)?;
+            write_tar(encoder, sources, &mut tracker)?.finish()?;
+        }
+        ArchiveFormat::Zip => write_zip(file, sources, &mut tracker)?,
+        ArchiveFormat::Gzip => {
+            let source = single_source(sources)?;
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            let bytes = io::copy(&mut File::open(source)?, &mut encoder)?;
+            encoder.finish()?;
+            tracker.record(source, bytes);
+        }
+        ArchiveFormat::Zstd => {
+            let source = single_source(sources)?;
+            let mut encoder = zstd::Encoder::new(file, 0)?;
+            let bytes = io::copy(&mut File::open(source)?, &mut encoder)?;
+            encoder.finish()?;
+            tracker.record(source, bytes);
+        }
+    }
+
+    Ok(tracker.summary(format, destination))
+}
+
+fn single_source(sources: &[PathBuf]) -> Result<&Path> {
+    match sources {
+        [source] if source.is_file() => Ok(source),
+        _ => anyhow::bail!(
+            "gzip/zstd compress exactly one regular file; use a tar format for directories"
+        ),
+    }
+}
+
+/// Entry name for `path` relative to the parent of the source it was found under
+fn entry_name(source: &Path, path: &Path) -> PathBuf {
+    let base = source.parent().unwrap_or_else(|| Path::new(""));
+    path.strip_prefix(base).unwrap_or(path).to_path_buf()
+}
+
+fn write_tar<W: Write>(
+    writer: W,
+    sources: &[PathBuf],
+    tracker: &mut ProgressTracker<'_>,
+) -> Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+
+    for source in sources {
+        for entry in WalkDir::new(source).follow_links(false) {
+            let entry = entry?;
+            let name = entry_name(source, entry.path());
+            builder
+                .append_path_with_name(entry.path(), &name)
+                .with_context(|| format!("Failed to add {}", entry.path().display()))?;
+            let size = if entry.file_type().is_file() {
+                entry.metadata()?.len()
+            } else {
+                0
+            };
+            tracker.record(&name, size);
+        }
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+fn write_zip(file: File, sources: &[PathBuf], tracker: &mut ProgressTracker<'_>) -> Result<()> {
+    use zip::write::SimpleFileOptions;
+
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for source in sources {
+        for entry in WalkDir::new(source).follow_links(false) {
+            let entry = entry?;
+            let name = entry_name(source, entry.path());
+            // Zip entry names always use forward slashes
+            let zip_name = name.to_string_lossy().replace('\\', "/");
+
+            if entry.file_type().is_dir() {
+                zip.add_directory(zip_name, options)?;
+                tracker.record(&name, 0);
+            } else if entry.file_type().is_file() {
+                let size = entry.metadata()?.len();
+                zip.start_file(zip_name, options.large_file(size >= u32::MAX as u64))?;
+                io::copy(&mut File::open(entry.path())?, &mut zip)?;
+                tracker.record(&name, size);
+            } else {
+                log::warn!(
+                    "[archive] Skipping non-regular file in zip: {}",
+                    entry.path().display()
+                );
+            }
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// Archive extraction with path-traversal protection
+
+use crate::archive::{safe_join, ArchiveFormat, ArchiveProgress, ArchiveSummary, ProgressTracker};
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+/// Unpack `archive` into the `destination` directory.
+///
+/// Every entry is resolved with [`safe_join`]; entries that would land outside
+/// `destination` abort the extraction. Tar symlinks and hard links are only
+/// accepted when their target stays inside the destination as well; hard
+/// links are made from the source's path under `destination`.
+pub fn extract_archive(
+    format: ArchiveFormat,
+    archive: &Path,
+    destination: &Path,
+    progress: &mut dyn FnMut(&ArchiveProgress),
+) -> Result<ArchiveSummary> {
+    fs::create_dir_all(destination)
+        .with_context(|| format!("Failed to create {}", destination.display()))?;
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    let mut tracker = ProgressTracker::new("extract", progress);
+
+    match format {
+        ArchiveFormat::Tar => extract_tar(file, destination, &mut tracker)?,
+        ArchiveFormat::TarGz => extract_tar(GzDecoder::new(file), destination, &mut tracker)?,
+        ArchiveFormat::TarZst => extract_tar(zstd::Decoder::new(file)?, destination, &mut tracker)?,
+        ArchiveFormat::Zip => extract_zip(file, destination, &mut tracker)?,
+        ArchiveFormat::Gzip => {
+            decompress_single(GzDecoder::new(file), archive, destination, &mut tracker)?
+        }
+        ArchiveFormat::Zstd => decompress_single(
+            zstd::Decoder::new(file)?,
+            archive,
+            destination,
+            &mut tracker,
+        )?,
+    }
+
+    Ok(tracker.summary(format, destination))
+}
+
+fn extract_tar<R: Read>(
+    reader: R,
+    destination: &Path,
+    tracker: &mut ProgressTracker<'_>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let target = safe_join(destination, &name)?;
+
+        let entry_type = entry.header().entry_type();
+        let link = if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let link = entry
+                .link_name()?
+                .ok_or_else(|| anyhow::anyhow!("Link entry without target: {}", name.display()))?
+                .into_owned();
+            let escapes = link
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+            if escapes {
+                anyhow::bail!(
+                    "Refusing to extract link pointing outside destination: {} -> {}",
+                    name.display(),
+                    link.display()
+                );
+            }
+            Some(link)
+        } else {
+            None
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let size = entry.size();
+        match link {
+            // tar-rs resolves a hard link's source against the process cwd, so
+            // link from the source's path under the destination instead
+            Some(link) if entry_type.is_hard_link() => {
+                let source = safe_join(destination, &link)?;
+                fs::hard_link(&source, &target).with_context(|| {
+                    format!("Failed to link {} to {}", name.display(), link.display())
+                })?;
+            }
+            _ => {
+                entry
+                    .unpack(&target)
+                    .with_context(|| format!("Failed to extract {}", name.display()))?;
+            }
+        }
+        tracker.record(&name, size);
+    }
+
+    Ok(())
+}
+
+fn extract_zip(file: File, destination: &Path, tracker: &mut ProgressTracker<'_>) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = Path::new(entry.name()).to_path_buf();
+        let target = safe_join(destination, &name)?;
+
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+            tracker.record(&name, 0);
+            continue;
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes = io::copy(&mut entry, &mut File::create(&target)?)
+            .with_context(|| format!("Failed to extract {}", name.display()))?;
+        tracker.record(&name, bytes);
+    }
+
+    Ok(())
+}
+
+/// Decompress a single-file stream next to its siblings, dropping the extension
+fn decompress_single<R: Read>(
+    mut reader: R,
+    archive: &Path,
+    destination: &Path,
+    tracker: &mut ProgressTracker<'_>,
+) -> Result<()> {
+    let name = archive
+        .file_stem()
+        .map(Path::new)
+        .ok_or_else(|| anyhow::anyhow!("Cannot derive output name from {}", archive.display()))?;
+    let target = safe_join(destination, name)?;
+
+    let bytes = io::copy(&mut reader, &mut File::create(&target)?)?;
+    tracker.record(name, bytes);
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Archive and compression operations.
+//!
+//! Rust-native pack/unpack for tar (plain, gzip, zstd), zip, and single-file
+//! gzip/zstd streams. Used by the `archive_create` / `archive_extract` Tauri
+//! commands and by archive canvas nodes. Extraction never writes outside the
+//! destination directory.
+
+pub mod create;
+pub mod extract;
+
+#[cfg(test)]
+mod tests;
+
+pub use create::create_archive;
+pub use extract::extract_archive;
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use ts_rs::TS;
+
+/// Supported archive/compression formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+    Gzip,
+    Zstd,
+}
+
+impl ArchiveFormat {
+    /// Infer the format from a file name (e.g. `logs.tar.gz`, `out.zst`)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".gz") {
+            Some(ArchiveFormat::Gzip)
+        } else if name.ends_with(".zst") {
+            Some(ArchiveFormat::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the format wraps a single file rather than a directory tree
+    pub fn is_single_file(&self) -> bool {
+        matches!(self, ArchiveFormat::Gzip | ArchiveFormat::Zstd)
+    }
+}
+
+/// Progress update emitted while packing or unpacking
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveProgress {
+    pub operation: String, // "create" or "extract"
+    pub entries_done: u64,
+    pub bytes_done: u64,
+    pub current_path: Option<String>,
+}
+
+/// Result of a completed archive operation
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveSummary {
+    pub format: ArchiveFormat,
+    pub path: String,
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// Resolve an archive entry name against the extraction root.
+///
+/// Rejects absolute paths, drive prefixes, and `..` components so a crafted
+/// archive cannot write outside `root` (zip-slip / tar path traversal).
+pub fn safe_join(root: &Path, entry: &Path) -> anyhow::Result<PathBuf> {
+    let mut out = root.to_path_buf();
+    for component in entry.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!(
+                    "Refusing to extract entry outside destination: {}",
+                    entry.display()
+                );
+            }
+        }
+    }
+    if out == root {
+        anyhow::bail!("Archive entry has an empty path");
+    }
+    Ok(out)
+}
+
+/// Counts entries/bytes and forwards progress updates to the caller
+pub(crate) struct ProgressTracker<'a> {
+    operation: &'static str,
+    entries: u64,
+    bytes: u64,
+    callback: &'a mut dyn FnMut(&ArchiveProgress),
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(
+        operation: &'static str,
+        callback: &'a mut dyn FnMut(&ArchiveProgress),
+    ) -> Self {
+        Self {
+            operation,
+            entries: 0,
+            bytes: 0,
+            callback,
+        }
+    }
+
+    pub(crate) fn record(&mut self, path: &Path, bytes: u64) {
+        self.entries += 1;
+        self.bytes += bytes;
+        (self.callback)(&ArchiveProgress {
+            operation: self.operation.to_string(),
+            entries_done: self.entries,
+            bytes_done: self.bytes,
+            current_path: Some(path.to_string_lossy().to_string()),
+        });
+    }
+
+    pub(crate) fn summary(&self, format: ArchiveFormat, path: &Path) -> ArchiveSummary {
+        ArchiveSummary {
+            format,
+            path: path.to_string_lossy().to_string(),
+            entries: self.entries,
+            bytes: self.bytes,
+        }
+    }
+}
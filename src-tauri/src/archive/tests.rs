@@ -0,0 +1,137 @@
+// Tests for archive pack/unpack and traversal protection
+
+use crate::archive::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn sample_tree(root: &Path) -> PathBuf {
+    let source = root.join("project");
+    fs::create_dir_all(source.join("src")).unwrap();
+    fs::write(source.join("README.md"), "hello").unwrap();
+    fs::write(source.join("src/main.rs"), "fn main() {}").unwrap();
+    source
+}
+
+fn roundtrip(format: ArchiveFormat, file_name: &str) {
+    let tmp = tempfile::tempdir().unwrap();
+    let source = sample_tree(tmp.path());
+    let archive = tmp.path().join(file_name);
+    let out = tmp.path().join("out");
+
+    let mut events = 0;
+    let created = create_archive(format, &[source], &archive, &mut |_| events += 1).unwrap();
+    assert_eq!(created.entries, events);
+    assert_eq!(ArchiveFormat::from_path(&archive), Some(format));
+
+    extract_archive(format, &archive, &out, &mut |_| {}).unwrap();
+    assert_eq!(
+        fs::read_to_string(out.join("project/README.md")).unwrap(),
+        "hello"
+    );
+    assert_eq!(
+        fs::read_to_string(out.join("project/src/main.rs")).unwrap(),
+        "fn main() {}"
+    );
+}
+
+#[test]
+fn test_tar_roundtrips() {
+    roundtrip(ArchiveFormat::Tar, "a.tar");
+    roundtrip(ArchiveFormat::TarGz, "a.tar.gz");
+    roundtrip(ArchiveFormat::TarZst, "a.tar.zst");
+}
+
+#[test]
+fn test_zip_roundtrip() {
+    roundtrip(ArchiveFormat::Zip, "a.zip");
+}
+
+#[test]
+fn test_single_file_compression() {
+    let tmp = tempfile::tempdir().unwrap();
+    let source = tmp.path().join("log.txt");
+    fs::write(&source, "line\n".repeat(100)).unwrap();
+
+    for (format, name) in [
+        (ArchiveFormat::Gzip, "log.txt.gz"),
+        (ArchiveFormat::Zstd, "log.txt.zst"),
+    ] {
+        let archive = tmp.path().join(name);
+        let out = tmp.path().join(format!("out-{}", name));
+        create_archive(format, std::slice::from_ref(&source), &archive, &mut |_| {}).unwrap();
+        extract_archive(format, &archive, &out, &mut |_| {}).unwrap();
+        assert_eq!(
+            fs::read(out.join("log.txt")).unwrap(),
+            fs::read(&source).unwrap()
+        );
+    }
+
+    let err = create_archive(
+        ArchiveFormat::Gzip,
+        &[tmp.path().to_path_buf()],
+        &tmp.path().join("dir.gz"),
+        &mut |_| {},
+    );
+    assert!(err.is_err());
+}
+
+#[test]
+fn test_safe_join_rejects_traversal() {
+    let root = Path::new("/tmp/dest");
+    assert_eq!(
+        safe_join(root, Path::new("a/./b.txt")).unwrap(),
+        root.join("a/b.txt")
+    );
+    assert!(safe_join(root, Path::new("../evil")).is_err());
+    assert!(safe_join(root, Path::new("a/../../evil")).is_err());
+    assert!(safe_join(root, Path::new("/etc/passwd")).is_err());
+    assert!(safe_join(root, Path::new(".")).is_err());
+}
+
+#[test]
+fn test_tar_extract_rejects_traversal_entry() {
+    let tmp = tempfile::tempdir().unwrap();
+    let archive = tmp.path().join("evil.tar");
+
+    // tar::Builder refuses `..` names, so write the raw header by hand
+    let mut header = tar::Header::new_old();
+    let name = b"../escaped.txt";
+    header.as_old_mut().name[..name.len()].copy_from_slice(name);
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_cksum();
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    builder.append(&header, &b"pwnd"[..]).unwrap();
+    builder.finish().unwrap();
+
+    let out = tmp.path().join("out");
+    let result = extract_archive(ArchiveFormat::Tar, &archive, &out, &mut |_| {});
+    assert!(result.is_err());
+    assert!(!tmp.path().join("escaped.txt").exists());
+}
+
+#[test]
+fn test_tar_hard_link_resolves_inside_destination() {
+    let tmp = tempfile::tempdir().unwrap();
+    let archive = tmp.path().join("links.tar");
+
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut file = tar::Header::new_gnu();
+    file.set_size(5);
+    file.set_mode(0o644);
+    file.set_cksum();
+    builder
+        .append_data(&mut file, "target.txt", &b"inner"[..])
+        .unwrap();
+    let mut link = tar::Header::new_gnu();
+    link.set_entry_type(tar::EntryType::Link);
+    link.set_size(0);
+    builder
+        .append_link(&mut link, "link.txt", "target.txt")
+        .unwrap();
+    builder.finish().unwrap();
+
+    let out = tmp.path().join("out");
+    extract_archive(ArchiveFormat::Tar, &archive, &out, &mut |_| {}).unwrap();
+    assert_eq!(fs::read_to_string(out.join("link.txt")).unwrap(), "inner");
+}
@@ -0,0 +1,356 @@
+//! Security audit log.
+//!
+//! Append-only JSON Lines file recording security-relevant decisions
+//! (destructive-command confirmations, blocked executions, ...). Kept outside
+//! the memory store so it survives memory wipes and works without PluresDB.
+//!
+//! Entries are hash-chained: each carries a sequence number, the previous
+//! entry's hash, and a SHA-256 over its own content and that link, so editing,
+//! removing, or reordering entries breaks the chain. [`AuditLog::verify`]
+//! walks the file and reports where. Entries written before chaining existed
+//! are reported as unchained rather than as tampering.
+//!
+//! The chain shows that the file was changed, not who changed it: someone who
+//! can rewrite the whole file can also recompute every hash. Keep a copy of
+//! the head hash from a verification elsewhere to detect that.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// `prev_hash` of the first chained entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One audit record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    /// What happened, e.g. "destructive_command.confirmed"
+    pub action: String,
+    /// Who or what initiated it ("user", "canvas", "agent", ...)
+    pub actor: String,
+    /// What it applied to, e.g. the command line
+    pub target: String,
+    pub details: Value,
+    /// Position in the chain, from 0; set when appended
+    #[serde(default)]
+    pub seq: Option<u64>,
+    #[serde(default)]
+    pub prev_hash: Option<String>,
+    /// Hex SHA-256 of this entry with `hash` unset
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(action: &str, actor: &str, target: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            action: action.to_string(),
+            actor: actor.to_string(),
+            target: target.into(),
+            details: serde_json::json!({}),
+            seq: None,
+            prev_hash: None,
+            hash: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// The hash this entry should carry, given its content and link
+    pub fn compute_hash(&self) -> Result<String> {
+        let unsigned = AuditEntry {
+            hash: None,
+            ..self.clone()
+        };
+        Ok(Sha256::digest(serde_json::to_vec(&unsigned)?)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum AuditProblemKind {
+    /// The line is not a valid entry
+    Unparseable,
+    /// The entry's content does not match its hash
+    Modified,
+    /// `prev_hash` does not match the previous entry, or sequence numbers skip
+    BrokenChain,
+    /// An entry without a hash after chained entries
+    Unchained,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct AuditProblem {
+    /// 1-based line number
+    pub line: usize,
+    pub kind: AuditProblemKind,
+    pub message: String,
+}
+
+/// Result of [`AuditLog::verify`]
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct AuditVerification {
+    pub path: PathBuf,
+    pub entries: usize,
+    /// Entries from before chaining, at the start of the file
+    pub legacy_entries: usize,
+    /// Hash of the last chained entry; record it elsewhere to detect a
+    /// wholesale rewrite later
+    pub head_hash: Option<String>,
+    pub problems: Vec<AuditProblem>,
+}
+
+impl AuditVerification {
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Append-only audit log file
+pub struct AuditLog {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Audit log in the RuneBook config directory
+    pub fn open_default() -> Self {
+        Self::new(crate::config::config_dir().join("audit.jsonl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `entry`, chaining it to the last entry in the file. The file is
+    /// locked while its tail is read and the entry written, so that several
+    /// processes sharing it (the desktop app and headless mode) extend one
+    /// chain instead of each reusing the same sequence number.
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock audit log {}", self.path.display()))?;
+
+        let (seq, prev_hash) = match last_entry(&mut file)? {
+            Some(AuditEntry {
+                seq: Some(seq),
+                hash: Some(hash),
+                ..
+            }) => (seq + 1, hash),
+            _ => (0, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            seq: Some(seq),
+            prev_hash: Some(prev_hash),
+            hash: None,
+            ..entry.clone()
+        };
+        entry.hash = Some(entry.compute_hash()?);
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        if ends_mid_line(&mut file)? {
+            // A write cut short; keep the new entry off its line
+            line.insert(0, '\n');
+        }
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Check the hash chain of the whole file
+    pub fn verify(&self) -> Result<AuditVerification> {
+        let mut report = AuditVerification {
+            path: self.path.clone(),
+            entries: 0,
+            legacy_entries: 0,
+            head_hash: None,
+            problems: Vec::new(),
+        };
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(report),
+            Err(e) => return Err(e.into()),
+        };
+
+        // (seq, hash) of the previous chained entry
+        let mut previous: Option<(u64, String)> = None;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let number = index + 1;
+            let problem = |kind, message: String| AuditProblem {
+                line: number,
+                kind,
+                message,
+            };
+            let entry: AuditEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report
+                        .problems
+                        .push(problem(AuditProblemKind::Unparseable, e.to_string()));
+                    continue;
+                }
+            };
+            report.entries += 1;
+
+            let (Some(seq), Some(prev_hash), Some(hash)) =
+                (entry.seq, &entry.prev_hash, &entry.hash)
+            else {
+                if previous.is_none() && report.problems.is_empty() {
+                    report.legacy_entries += 1;
+                } else {
+                    report.problems.push(problem(
+                        AuditProblemKind::Unchained,
+                        format!("Entry {} has no hash", entry.id),
+                    ));
+                }
+                continue;
+            };
+
+            if entry.compute_hash()? != *hash {
+                report.problems.push(problem(
+                    AuditProblemKind::Modified,
+                    format!("Entry {} does not match its hash", seq),
+                ));
+            }
+            let (expected_seq, expected_prev) = match &previous {
+                Some((seq, hash)) => (seq + 1, hash.as_str()),
+                None => (0, GENESIS_HASH),
+            };
+            if seq != expected_seq || prev_hash != expected_prev {
+                report.problems.push(problem(
+                    AuditProblemKind::BrokenChain,
+                    format!(
+                        "Expected entry {} but found entry {}; entries were removed, inserted, or reordered",
+                        expected_seq, seq
+                    ),
+                ));
+            }
+            previous = Some((seq, hash.clone()));
+        }
+        report.head_hash = previous.map(|(_, hash)| hash);
+        Ok(report)
+    }
+
+    /// Append, logging instead of failing; for call sites that must not be blocked
+    pub fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.append(&entry) {
+            log::error!("[audit] Failed to record {}: {}", entry.action, e);
+        }
+    }
+
+    /// The most recent `limit` entries, oldest first
+    pub fn recent(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries: Vec<AuditEntry> = BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+        Ok(entries)
+    }
+}
+
+/// The last entry of `file` that parses, reading backwards from the end.
+/// Lines that do not parse, such as one cut short by a crash, are skipped so
+/// the chain goes on from the last good entry; [`AuditLog::verify`] still
+/// reports them.
+fn last_entry(file: &mut std::fs::File) -> Result<Option<AuditEntry>> {
+    const CHUNK: u64 = 8192;
+    let mut start = file.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    loop {
+        let read_from = start.saturating_sub(CHUNK);
+        let mut chunk = vec![0; (start - read_from) as usize];
+        file.seek(SeekFrom::Start(read_from))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        start = read_from;
+
+        // Lines after the first newline are whole; at the start of the file, all are
+        let whole_from = match tail.iter().position(|&b| b == b'\n') {
+            _ if start == 0 => 0,
+            Some(newline) => newline + 1,
+            None => continue,
+        };
+        for line in tail[whole_from..].rsplit(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line.trim()) {
+                Ok(entry) => return Ok(Some(entry)),
+                Err(e) => log::warn!(
+                    "[audit] Skipping unreadable entry at the end of the log: {}",
+                    e
+                ),
+            }
+        }
+        if start == 0 {
+            return Ok(None);
+        }
+        tail.truncate(whole_from);
+    }
+}
+
+/// Whether `file` is non-empty and its last line has no newline
+fn ends_mid_line(file: &mut std::fs::File) -> Result<bool> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len == 0 {
+        return Ok(false);
+    }
+    let mut last = [0; 1];
+    file.seek(SeekFrom::Start(len - 1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,130 @@
+use super::*;
+
+#[test]
+fn chain_detects_edits_and_removals() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+
+    // An entry from before chaining stays readable and is reported as legacy
+    let legacy = AuditEntry::new("destructive_command.blocked", "user", "rm -rf /");
+    std::fs::write(
+        &path,
+        format!("{}\n", serde_json::to_string(&legacy).unwrap()),
+    )
+    .unwrap();
+
+    let log = AuditLog::new(path.clone());
+    for target in [
+        "kubectl delete ns staging",
+        "terraform destroy",
+        "git push -f",
+    ] {
+        log.append(
+            &AuditEntry::new("destructive_command.confirmed", "user", target)
+                .with_details(serde_json::json!({ "rule": "test" })),
+        )
+        .unwrap();
+    }
+    let report = log.verify().unwrap();
+    assert!(report.is_intact(), "{:?}", report.problems);
+    assert_eq!((report.entries, report.legacy_entries), (4, 1));
+    assert_eq!(log.recent(10).unwrap()[3].seq, Some(2));
+    let head = report.head_hash.clone().unwrap();
+
+    let original = std::fs::read_to_string(&path).unwrap();
+
+    // Editing an entry breaks its hash
+    std::fs::write(
+        &path,
+        original.replace("terraform destroy", "terraform plan"),
+    )
+    .unwrap();
+    let report = log.verify().unwrap();
+    assert_eq!(report.problems.len(), 1);
+    assert_eq!(report.problems[0].kind, AuditProblemKind::Modified);
+    assert_eq!(report.problems[0].line, 3);
+
+    // Removing one breaks the chain at the next entry
+    let lines: Vec<&str> = original.lines().collect();
+    std::fs::write(&path, format!("{}\n{}\n{}\n", lines[0], lines[1], lines[3])).unwrap();
+    let report = log.verify().unwrap();
+    assert_eq!(report.problems.len(), 1);
+    assert_eq!(report.problems[0].kind, AuditProblemKind::BrokenChain);
+
+    // Appending after verification extends the chain from the current head
+    std::fs::write(&path, &original).unwrap();
+    log.record(AuditEntry::new("destructive_command.blocked", "api", "dd"));
+    let report = log.verify().unwrap();
+    assert!(report.is_intact());
+    assert_ne!(report.head_hash.unwrap(), head);
+}
+
+#[test]
+fn append_goes_on_from_the_last_readable_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    let log = AuditLog::new(path.clone());
+    for target in ["rm -rf build", "git reset --hard"] {
+        log.append(&AuditEntry::new(
+            "destructive_command.confirmed",
+            "user",
+            target,
+        ))
+        .unwrap();
+    }
+
+    // A write cut short by a crash leaves half a line without a newline
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(br#"{"id":"cut-short","timestamp":"#)
+        .unwrap();
+    drop(file);
+
+    log.append(&AuditEntry::new("destructive_command.blocked", "api", "dd"))
+        .unwrap();
+    let entries = log.recent(10).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[2].seq, Some(2));
+    assert_eq!(entries[2].prev_hash, entries[1].hash);
+
+    // Only the damaged line is reported; the chain around it holds
+    let report = log.verify().unwrap();
+    assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+    assert_eq!(report.problems[0].kind, AuditProblemKind::Unparseable);
+    assert_eq!(report.problems[0].line, 3);
+}
+
+#[test]
+fn appends_through_separate_handles_extend_one_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+
+    // Each handle stands in for a process: they share only the file
+    let writers: Vec<_> = (0..4)
+        .map(|writer| {
+            let log = AuditLog::new(path.clone());
+            std::thread::spawn(move || {
+                for n in 0..25 {
+                    log.append(&AuditEntry::new(
+                        "destructive_command.confirmed",
+                        "user",
+                        format!("writer {} command {}", writer, n),
+                    ))
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let log = AuditLog::new(path);
+    let report = log.verify().unwrap();
+    assert!(report.is_intact(), "{:?}", report.problems);
+    assert_eq!(report.entries, 100);
+    let seqs: Vec<_> = log.recent(100).unwrap().iter().map(|e| e.seq).collect();
+    assert_eq!(seqs, (0..100).map(Some).collect::<Vec<_>>());
+}
@@ -0,0 +1,173 @@
+// Where backups are stored: a local directory, or an S3-compatible bucket with the `s3` feature
+// Destinations check what they stored against the snapshot's SHA-256
+
+use super::snapshot::sha256_file;
+use crate::storage::{open_object_store, ObjectStore, S3Config};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use ts_rs::TS;
+
+/// `[backup.destination]`, chosen by `kind`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+#[ts(export)]
+pub enum DestinationConfig {
+    /// A directory; `backups/` in the config directory when unset
+    Local {
+        path: Option<PathBuf>,
+    },
+    S3(S3Config),
+}
+
+impl Default for DestinationConfig {
+    fn default() -> Self {
+        DestinationConfig::Local { path: None }
+    }
+}
+
+/// A backup present at a destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredBackup {
+    pub name: String,
+    pub bytes: u64,
+}
+
+#[async_trait]
+pub trait BackupDestination: Send + Sync {
+    /// Where backups go, for status output
+    fn describe(&self) -> String;
+    /// Store `file` as `name`, failing unless the stored copy matches `sha256`
+    async fn put(&self, name: &str, file: &Path, sha256: &str) -> Result<()>;
+    async fn list(&self) -> Result<Vec<StoredBackup>>;
+    async fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// Backups as files in a directory
+pub struct LocalDestination {
+    dir: PathBuf,
+}
+
+impl LocalDestination {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl BackupDestination for LocalDestination {
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    async fn put(&self, name: &str, file: &Path, sha256: &str) -> Result<()> {
+        let dir = self.dir.clone();
+        let (name, file, sha256) = (name.to_string(), file.to_path_buf(), sha256.to_string());
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            // Copy under a temporary name so a partial copy never looks like a backup
+            let partial = dir.join(format!(".{}.partial", name));
+            std::fs::copy(&file, &partial)
+                .with_context(|| format!("Failed to copy backup to {}", dir.display()))?;
+            if sha256_file(&partial)? != sha256 {
+                let _ = std::fs::remove_file(&partial);
+                bail!("Copy of {} in {} is corrupt", name, dir.display());
+            }
+            std::fs::rename(&partial, dir.join(&name))?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn list(&self) -> Result<Vec<StoredBackup>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to list {}", self.dir.display()))
+            }
+        };
+        let mut backups = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                backups.push(StoredBackup {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    bytes: metadata.len(),
+                });
+            }
+        }
+        Ok(backups)
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        std::fs::remove_file(self.dir.join(name))
+            .with_context(|| format!("Failed to delete {}", name))
+    }
+}
+
+/// Backups as objects at the top of an object store's prefix
+pub struct ObjectStoreDestination {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl ObjectStoreDestination {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl BackupDestination for ObjectStoreDestination {
+    fn describe(&self) -> String {
+        self.store.describe()
+    }
+
+    async fn put(&self, name: &str, file: &Path, sha256: &str) -> Result<()> {
+        let body = tokio::fs::read(file)
+            .await
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        if hex::encode(Sha256::digest(&body)) != sha256 {
+            bail!("{} changed before upload", file.display());
+        }
+        self.store.put(name, body).await
+    }
+
+    async fn list(&self) -> Result<Vec<StoredBackup>> {
+        Ok(self
+            .store
+            .list("")
+            .await?
+            .into_iter()
+            // Artifacts share the bucket under `artifacts/`
+            .filter(|object| !object.key.contains('/'))
+            .map(|object| StoredBackup {
+                name: object.key,
+                bytes: object.bytes,
+            })
+            .collect())
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        self.store.delete(name).await
+    }
+}
+
+/// The destination `config` describes
+pub fn open_destination(config: &DestinationConfig) -> Result<Box<dyn BackupDestination>> {
+    match config {
+        DestinationConfig::Local { path } => Ok(Box::new(LocalDestination::new(
+            path.clone()
+                .unwrap_or_else(|| crate::config::config_dir().join("backups")),
+        ))),
+        DestinationConfig::S3(s3) => Ok(Box::new(ObjectStoreDestination::new(open_object_store(
+            s3,
+        )?))),
+    }
+}
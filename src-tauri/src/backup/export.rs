@@ -0,0 +1,176 @@
+// Streaming snapshot export: records go from the backend straight into the archive writer
+// Memory holds one chunk at a time however large the store is; split exports resume after their last finished part
+
+use crate::backup::snapshot::{
+    SnapshotManifest, SnapshotRecord, SnapshotWriter, DEFAULT_CHUNK_BYTES,
+};
+use crate::memory::keys::MEMORY_PREFIX;
+use crate::memory::MemoryStore;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+use ts_rs::TS;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// Start a new part once one has grown past this many bytes; one
+    /// archive when unset
+    pub part_bytes: Option<u64>,
+    /// Bytes of records buffered before they are compressed and written
+    pub chunk_bytes: usize,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            part_bytes: None,
+            chunk_bytes: DEFAULT_CHUNK_BYTES,
+        }
+    }
+}
+
+/// Sent after every chunk, and once more when the export is done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProgress {
+    /// Records written so far, including those of a resumed export
+    pub records: u64,
+    /// Records in the store when the export started
+    pub total: u64,
+    /// Bytes of records written so far, before compression
+    pub bytes: u64,
+    /// Parts finished so far
+    pub parts: usize,
+    pub elapsed_ms: u64,
+    /// Throughput of this call
+    pub records_per_second: f64,
+    pub bytes_per_second: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ExportSummary {
+    pub manifest: SnapshotManifest,
+    /// Archives written, in order
+    pub parts: Vec<String>,
+    /// Compressed size of all parts
+    pub archive_bytes: u64,
+    pub elapsed_ms: u64,
+    pub records_per_second: f64,
+    pub bytes_per_second: f64,
+    /// Whether this call finished an export an earlier one started
+    pub resumed: bool,
+}
+
+/// How fast `done` items went by in `elapsed_ms`
+fn rate(done: u64, elapsed_ms: u64) -> f64 {
+    done as f64 * 1000.0 / elapsed_ms.max(1) as f64
+}
+
+/// Counts work done by one export call
+struct Throughput {
+    started: Instant,
+    records: u64,
+    bytes: u64,
+}
+
+impl Throughput {
+    fn start(writer: &SnapshotWriter) -> Self {
+        Self {
+            started: Instant::now(),
+            records: writer.records(),
+            bytes: writer.bytes(),
+        }
+    }
+
+    fn progress(&self, writer: &SnapshotWriter, total: u64) -> ExportProgress {
+        let elapsed_ms = self.started.elapsed().as_millis() as u64;
+        ExportProgress {
+            records: writer.records(),
+            total,
+            bytes: writer.bytes(),
+            parts: writer.parts(),
+            elapsed_ms,
+            records_per_second: rate(writer.records() - self.records, elapsed_ms),
+            bytes_per_second: rate(writer.bytes() - self.bytes, elapsed_ms),
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Snapshot every `memory:` record, as stored, and `config_file` (when it
+    /// exists) to `out`, calling `progress` after each chunk.
+    ///
+    /// Records are read one at a time and written a chunk at a time, so
+    /// memory use does not grow with the store. A split export whose parts
+    /// and checkpoint are still next to `out` is resumed when called again
+    /// with the same part size; records added meanwhile after its last
+    /// record are picked up, earlier ones are not.
+    pub async fn export_snapshot(
+        &self,
+        config_file: Option<&Path>,
+        out: &Path,
+        options: &ExportOptions,
+        progress: &mut (dyn FnMut(&ExportProgress) + Send),
+    ) -> Result<ExportSummary> {
+        let (mut writer, resumed) =
+            match SnapshotWriter::resume(out, options.part_bytes, options.chunk_bytes)? {
+                Some(writer) => (writer, true),
+                None => (
+                    SnapshotWriter::start(out, options.part_bytes, options.chunk_bytes)?,
+                    false,
+                ),
+            };
+        let mut keys = self.client.list(MEMORY_PREFIX).await?;
+        keys.sort();
+        let total = keys.len() as u64;
+        let start = match writer.after() {
+            Some(after) => keys.partition_point(|k| k.as_str() <= after),
+            None => 0,
+        };
+        let throughput = Throughput::start(&writer);
+
+        for key in keys.into_iter().skip(start) {
+            // Deleted between listing and reading
+            let Some(value) = self.client.get(&key).await? else {
+                continue;
+            };
+            writer.push(&SnapshotRecord { key, value })?;
+            if writer.chunk_full() {
+                writer = tokio::task::spawn_blocking(move || -> Result<_> {
+                    writer.flush_chunk()?;
+                    Ok(writer)
+                })
+                .await??;
+                progress(&throughput.progress(&writer, total));
+            }
+        }
+
+        let config_file = config_file.map(Path::to_path_buf);
+        let (done, (manifest, parts)) = tokio::task::spawn_blocking(move || -> Result<_> {
+            writer.flush_chunk()?;
+            let done = throughput.progress(&writer, total);
+            Ok((done, writer.finish(config_file.as_deref())?))
+        })
+        .await??;
+        let done = ExportProgress {
+            parts: parts.len(),
+            ..done
+        };
+        progress(&done);
+        let mut archive_bytes = 0;
+        for part in &parts {
+            archive_bytes += std::fs::metadata(part)?.len();
+        }
+        Ok(ExportSummary {
+            manifest,
+            parts: parts.iter().map(|p| p.display().to_string()).collect(),
+            archive_bytes,
+            elapsed_ms: done.elapsed_ms,
+            records_per_second: done.records_per_second,
+            bytes_per_second: done.bytes_per_second,
+            resumed,
+        })
+    }
+}
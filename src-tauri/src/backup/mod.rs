@@ -0,0 +1,363 @@
+//! Scheduled full-state backups.
+//!
+//! With `[backup] enabled`, a [`BackupScheduler`] snapshots every memory
+//! record (canvases included) and `config.toml` into a tar.zst archive every
+//! `interval_hours` (catching up on missed runs as `catch_up` says, see
+//! [`crate::schedule`]), verifies the archive against its manifest digests, and
+//! stores it at the configured destination: a local directory, or an
+//! S3-compatible bucket in builds with the `s3` feature. Afterwards old
+//! backups are rotated out, keeping the newest of each of the last
+//! `keep_daily` days and `keep_weekly` ISO weeks.
+//!
+//! Records are streamed from the store into the archive a chunk at a time
+//! (see [`export`]), which `runebook backup export` also uses to write
+//! snapshots elsewhere, split into resumable parts if asked.
+//!
+//! The outcome of the last run is kept in `backup-status.json` in the config
+//! directory, so `runebook backup status` and the `backup_status` command can
+//! report it without the app running.
+
+pub mod destination;
+pub mod export;
+pub mod snapshot;
+
+#[cfg(test)]
+mod tests;
+
+pub use destination::{open_destination, BackupDestination, DestinationConfig, StoredBackup};
+pub use export::{ExportOptions, ExportProgress, ExportSummary};
+pub use snapshot::{snapshot_parts, verify_snapshot, write_snapshot, SnapshotManifest};
+
+use crate::health::Component;
+use crate::memory::StoreOpener;
+use crate::schedule::{CatchUp, Schedule, Tick, Ticker};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use ts_rs::TS;
+
+const NAME_PREFIX: &str = "runebook-backup-";
+const NAME_SUFFIX: &str = ".tar.zst";
+const NAME_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+/// How often the scheduler checks whether a backup is due
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// `[backup]` configuration section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    pub interval_hours: u64,
+    /// What to do about backups missed while the machine slept
+    pub catch_up: CatchUp,
+    /// Days to keep the newest backup of
+    pub keep_daily: usize,
+    /// ISO weeks to keep the newest backup of
+    pub keep_weekly: usize,
+    pub destination: DestinationConfig,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: 24,
+            catch_up: CatchUp::default(),
+            keep_daily: 7,
+            keep_weekly: 4,
+            destination: DestinationConfig::default(),
+        }
+    }
+}
+
+/// Name of a backup taken at `at`
+pub fn backup_name(at: DateTime<Utc>) -> String {
+    format!(
+        "{}{}{}",
+        NAME_PREFIX,
+        at.format(NAME_TIME_FORMAT),
+        NAME_SUFFIX
+    )
+}
+
+/// When a backup was taken, from its name; `None` for files that are not backups
+pub fn backup_time(name: &str) -> Option<DateTime<Utc>> {
+    let stamp = name.strip_prefix(NAME_PREFIX)?.strip_suffix(NAME_SUFFIX)?;
+    NaiveDateTime::parse_from_str(stamp, NAME_TIME_FORMAT)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Backups rotation removes: everything but the newest backup, the newest of
+/// each of the last `keep_daily` days, and the newest of each of the last
+/// `keep_weekly` weeks that have backups. Other files are left alone.
+pub fn expired_backups(
+    backups: &[StoredBackup],
+    keep_daily: usize,
+    keep_weekly: usize,
+) -> Vec<String> {
+    let mut dated: Vec<(DateTime<Utc>, &str)> = backups
+        .iter()
+        .filter_map(|b| Some((backup_time(&b.name)?, b.name.as_str())))
+        .collect();
+    dated.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut expired = Vec::new();
+    for (i, (at, name)) in dated.into_iter().enumerate() {
+        let day = at.date_naive();
+        let week = (at.iso_week().year(), at.iso_week().week());
+        let mut keep = i == 0;
+        if !days.contains(&day) && days.len() < keep_daily {
+            days.insert(day);
+            keep = true;
+        }
+        if !weeks.contains(&week) && weeks.len() < keep_weekly {
+            weeks.insert(week);
+            keep = true;
+        }
+        if !keep {
+            expired.push(name.to_string());
+        }
+    }
+    expired
+}
+
+/// A backup the scheduler stored
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BackupRecord {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub bytes: u64,
+    pub sha256: String,
+    pub records: u64,
+    /// Older backups rotated out after this one was stored
+    pub pruned: Vec<String>,
+}
+
+/// Outcome of the latest backup runs
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct BackupStatus {
+    pub enabled: bool,
+    pub destination: String,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub last_backup: Option<BackupRecord>,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+impl BackupStatus {
+    /// Read a status file; a missing or unreadable one is an empty status
+    pub fn load(path: &std::path::Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Path of the status file
+pub fn status_path() -> PathBuf {
+    crate::config::config_dir().join("backup-status.json")
+}
+
+pub struct BackupScheduler {
+    config: BackupConfig,
+    open_store: StoreOpener,
+    config_file: PathBuf,
+    status_path: PathBuf,
+    status: Mutex<BackupStatus>,
+    /// Held while a backup runs, so manual and scheduled runs do not overlap
+    running: tokio::sync::Mutex<()>,
+}
+
+impl BackupScheduler {
+    pub fn new(config: BackupConfig, open_store: StoreOpener) -> Self {
+        Self::with_paths(
+            config,
+            open_store,
+            crate::config::config_path(),
+            status_path(),
+        )
+    }
+
+    /// Back up `config_file` and keep the status in `status_path`
+    pub fn with_paths(
+        config: BackupConfig,
+        open_store: StoreOpener,
+        config_file: PathBuf,
+        status_path: PathBuf,
+    ) -> Self {
+        let status = BackupStatus::load(&status_path);
+        Self {
+            config,
+            open_store,
+            config_file,
+            status_path,
+            status: Mutex::new(status),
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn status(&self) -> BackupStatus {
+        let mut status = self
+            .status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        status.enabled = self.config.enabled;
+        status.destination = match open_destination(&self.config.destination) {
+            Ok(destination) => destination.describe(),
+            Err(e) => e.to_string(),
+        };
+        status.next_run_at = self.config.enabled.then(|| self.next_run(&status));
+        status
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        Schedule::every_hours(self.config.interval_hours, self.config.catch_up)
+    }
+
+    fn next_run(&self, status: &BackupStatus) -> DateTime<Utc> {
+        self.schedule().next_run(status.last_success_at, Utc::now())
+    }
+
+    /// Take, verify, store and rotate one backup now
+    pub async fn run_now(&self) -> Result<BackupRecord> {
+        let _running = self.running.lock().await;
+        let attempted = Utc::now();
+        let result = self.backup(attempted).await;
+
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.last_attempt_at = Some(attempted);
+        match &result {
+            Ok(record) => {
+                status.last_success_at = Some(attempted);
+                status.last_error = None;
+                status.consecutive_failures = 0;
+                status.last_backup = Some(record.clone());
+            }
+            Err(e) => {
+                status.last_error = Some(format!("{:#}", e));
+                status.consecutive_failures += 1;
+            }
+        }
+        if let Err(e) = self.save_status(&status) {
+            log::warn!("[backup] Status not saved: {:#}", e);
+        }
+        result
+    }
+
+    fn save_status(&self, status: &BackupStatus) -> Result<()> {
+        if let Some(parent) = self.status_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.status_path, serde_json::to_vec_pretty(status)?)
+            .with_context(|| format!("Failed to write {}", self.status_path.display()))
+    }
+
+    async fn backup(&self, at: DateTime<Utc>) -> Result<BackupRecord> {
+        let destination = open_destination(&self.config.destination)?;
+        let store = (self.open_store)().await?;
+
+        let name = backup_name(at);
+        let scratch = snapshot::Scratch::new("backup")?;
+        let archive = scratch.0.join(&name);
+        let export = store
+            .export_snapshot(
+                Some(&self.config_file),
+                &archive,
+                &ExportOptions::default(),
+                &mut |_| {},
+            )
+            .await?;
+        let (manifest, bytes, sha256) = {
+            let archive = archive.clone();
+            tokio::task::spawn_blocking(move || -> Result<_> {
+                let manifest = verify_snapshot(&archive).context("Backup failed verification")?;
+                let bytes = std::fs::metadata(&archive)?.len();
+                Ok((manifest, bytes, snapshot::sha256_file(&archive)?))
+            })
+            .await??
+        };
+        destination.put(&name, &archive, &sha256).await?;
+
+        let stored = destination.list().await?;
+        if !stored.iter().any(|b| b.name == name && b.bytes == bytes) {
+            anyhow::bail!("{} is missing from {}", name, destination.describe());
+        }
+        let pruned = expired_backups(&stored, self.config.keep_daily, self.config.keep_weekly);
+        for old in &pruned {
+            destination.delete(old).await?;
+        }
+        log::info!(
+            "[backup] Stored {} ({} records, {:.0} records/s) in {}",
+            name,
+            manifest.records,
+            export.records_per_second,
+            destination.describe()
+        );
+        Ok(BackupRecord {
+            name,
+            created_at: at,
+            bytes,
+            sha256,
+            records: manifest.records,
+            pruned,
+        })
+    }
+
+    /// Run backups whenever one is due. Returns at once when backups are disabled.
+    pub async fn run_loop(self: std::sync::Arc<Self>, health: Component) {
+        if !self.config.enabled {
+            return health.disable("Set [backup] enabled to schedule backups");
+        }
+        // Until the next run, the last one (possibly from an earlier start) counts
+        match self
+            .status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .last_error
+            .clone()
+        {
+            Some(error) => health.error(error),
+            None => health.ok(),
+        }
+        let mut ticker = Ticker::new(CHECK_INTERVAL);
+        loop {
+            if let Tick::Jumped(by) = ticker.tick().await {
+                log::info!(
+                    "[backup] Clock jumped {}s, checking for a missed backup",
+                    by.num_seconds()
+                );
+            }
+            crate::power::monitor().settled().await;
+            let last = self
+                .status
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .last_success_at;
+            // A failed run is retried at the next check
+            for _ in self.schedule().due(last, Utc::now()) {
+                match self.run_now().await {
+                    Ok(_) => health.ok(),
+                    Err(e) => {
+                        log::warn!("[backup] Backup failed: {:#}", e);
+                        health.error(format!("{:#}", e));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
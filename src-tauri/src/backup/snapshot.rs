@@ -0,0 +1,469 @@
+// Full-state snapshots: every memory record plus config.toml in a tar.zst, optionally split into parts
+// Each file in the archive is listed in manifest.json with its SHA-256, so a snapshot can be verified without the store
+
+use crate::archive::{extract_archive, ArchiveFormat};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// Snapshot layout version: 1 kept every record in `memory.jsonl`, 2 writes
+/// them in chunks under `memory/` and may split the archive into parts
+pub const SNAPSHOT_VERSION: u32 = 2;
+/// Directory every entry of a snapshot archive lives under
+const ROOT: &str = "runebook-backup";
+const MANIFEST: &str = "manifest.json";
+/// Where version 1 snapshots kept every record
+const MEMORY: &str = "memory.jsonl";
+const CONFIG: &str = "config/config.toml";
+
+/// One file of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SnapshotFile {
+    /// Path inside the snapshot, e.g. "memory.jsonl"
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// `manifest.json` of a snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// RuneBook version that wrote it
+    pub app_version: String,
+    /// Memory records across all chunks
+    pub records: u64,
+    pub files: Vec<SnapshotFile>,
+    /// Parts before the one holding the manifest, by file name; empty when
+    /// the snapshot is one archive
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<SnapshotFile>>", optional)]
+    pub parts: Vec<SnapshotFile>,
+}
+
+/// A memory record as stored in `memory.jsonl`. Values are exported as
+/// stored, so encrypted records stay encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    pub key: String,
+    pub value: Value,
+}
+
+fn digest_file(path: &Path) -> Result<(u64, String)> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut bytes = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        bytes += n as u64;
+    }
+    Ok((bytes, hex::encode(hasher.finalize())))
+}
+
+/// SHA-256 of a file, hex encoded
+pub fn sha256_file(path: &Path) -> Result<String> {
+    Ok(digest_file(path)?.1)
+}
+
+/// A scratch directory removed on drop
+pub(crate) struct Scratch(pub PathBuf);
+
+impl Scratch {
+    pub(crate) fn new(label: &str) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("runebook-{}-{}", label, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Memory records buffered before they are written as one chunk, unless
+/// the caller picks a size
+pub const DEFAULT_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// Where an unfinished multi-part snapshot left off, kept next to its parts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotCheckpoint {
+    created_at: DateTime<Utc>,
+    part_bytes: Option<u64>,
+    /// Finished parts, by file name
+    parts: Vec<SnapshotFile>,
+    /// Memory chunks in those parts
+    files: Vec<SnapshotFile>,
+    records: u64,
+    /// Bytes of JSON lines in those chunks
+    bytes: u64,
+    /// Last record written
+    after: Option<String>,
+}
+
+type PartBuilder = tar::Builder<zstd::Encoder<'static, fs::File>>;
+
+/// Writes a snapshot as it is fed records, holding no more than one chunk
+/// of them in memory.
+///
+/// Records are written as `memory/NNNNN.jsonl` chunks straight into the
+/// tar.zst. With a part size the snapshot is split into standalone archives
+/// `<out>.001`, `<out>.002`, ..., each started once the one before has
+/// grown past that size; the last holds `config.toml` and the manifest. A
+/// checkpoint is saved after every finished part, so an interrupted
+/// snapshot can be resumed after the last record of its last finished part.
+pub(crate) struct SnapshotWriter {
+    out: PathBuf,
+    chunk_bytes: usize,
+    state: SnapshotCheckpoint,
+    part: Option<PartBuilder>,
+    chunk: Vec<u8>,
+    chunk_records: u64,
+    chunk_last: Option<String>,
+}
+
+impl SnapshotWriter {
+    /// Start a snapshot at `out`, split into parts of about `part_bytes`
+    /// when given
+    pub(crate) fn start(out: &Path, part_bytes: Option<u64>, chunk_bytes: usize) -> Result<Self> {
+        let checkpoint = checkpoint_path(out);
+        if checkpoint.exists() {
+            fs::remove_file(&checkpoint)?;
+        }
+        Ok(Self::new(out, part_bytes, chunk_bytes))
+    }
+
+    fn new(out: &Path, part_bytes: Option<u64>, chunk_bytes: usize) -> Self {
+        Self {
+            out: out.to_path_buf(),
+            chunk_bytes: chunk_bytes.max(1),
+            state: SnapshotCheckpoint {
+                created_at: Utc::now(),
+                part_bytes: part_bytes.map(|b| b.max(1)),
+                parts: Vec::new(),
+                files: Vec::new(),
+                records: 0,
+                bytes: 0,
+                after: None,
+            },
+            part: None,
+            chunk: Vec::new(),
+            chunk_records: 0,
+            chunk_last: None,
+        }
+    }
+
+    /// Carry on with the snapshot at `out` an earlier writer with the same
+    /// part size left unfinished. `None` when there is none, or its finished
+    /// parts are gone or changed size.
+    pub(crate) fn resume(
+        out: &Path,
+        part_bytes: Option<u64>,
+        chunk_bytes: usize,
+    ) -> Result<Option<Self>> {
+        let Ok(data) = fs::read(checkpoint_path(out)) else {
+            return Ok(None);
+        };
+        let Ok(state) = serde_json::from_slice::<SnapshotCheckpoint>(&data) else {
+            return Ok(None);
+        };
+        let mut writer = Self::new(out, part_bytes, chunk_bytes);
+        let intact = state.part_bytes == writer.state.part_bytes
+            && state.parts.iter().enumerate().all(|(i, part)| {
+                fs::metadata(writer.part_path(i)).is_ok_and(|m| m.len() == part.bytes)
+            });
+        if !intact {
+            return Ok(None);
+        }
+        writer.state = state;
+        Ok(Some(writer))
+    }
+
+    /// Last record in a finished part
+    pub(crate) fn after(&self) -> Option<&str> {
+        self.state.after.as_deref()
+    }
+
+    /// Records written so far, including those of a resumed snapshot
+    pub(crate) fn records(&self) -> u64 {
+        self.state.records + self.chunk_records
+    }
+
+    /// Bytes of JSON lines written so far
+    pub(crate) fn bytes(&self) -> u64 {
+        self.state.bytes + self.chunk.len() as u64
+    }
+
+    /// Parts finished so far
+    pub(crate) fn parts(&self) -> usize {
+        self.state.parts.len()
+    }
+
+    /// Add `record` to the current chunk
+    pub(crate) fn push(&mut self, record: &SnapshotRecord) -> Result<()> {
+        serde_json::to_writer(&mut self.chunk, record)?;
+        self.chunk.push(b'\n');
+        self.chunk_records += 1;
+        self.chunk_last = Some(record.key.clone());
+        Ok(())
+    }
+
+    /// Whether the current chunk is due to be written
+    pub(crate) fn chunk_full(&self) -> bool {
+        self.chunk.len() >= self.chunk_bytes
+    }
+
+    /// Write the current chunk, first finishing the part when it is full
+    pub(crate) fn flush_chunk(&mut self) -> Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        if let (Some(limit), Some(part)) = (self.state.part_bytes, self.part.as_mut()) {
+            part.get_mut().flush()?;
+            if part.get_ref().get_ref().metadata()?.len() >= limit {
+                self.finish_part()?;
+                self.save_checkpoint()?;
+            }
+        }
+        let name = format!("memory/{:05}.jsonl", self.state.files.len());
+        let chunk = std::mem::take(&mut self.chunk);
+        let file = append_entry(self.open_part()?, &name, &chunk)?;
+        self.state.files.push(file);
+        self.state.records += self.chunk_records;
+        self.state.bytes += chunk.len() as u64;
+        self.state.after = self.chunk_last.take();
+        self.chunk_records = 0;
+        // Keep the allocation for the next chunk
+        self.chunk = chunk;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    /// Write what is left, `config_file` (when it exists) and the manifest.
+    /// Returns the manifest and every part, in order.
+    pub(crate) fn finish(
+        mut self,
+        config_file: Option<&Path>,
+    ) -> Result<(SnapshotManifest, Vec<PathBuf>)> {
+        self.flush_chunk()?;
+        let mut files = self.state.files.clone();
+        if let Some(config) = config_file.filter(|c| c.exists()) {
+            let data =
+                fs::read(config).with_context(|| format!("Failed to read {}", config.display()))?;
+            files.push(append_entry(self.open_part()?, CONFIG, &data)?);
+        }
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            created_at: self.state.created_at,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            records: self.state.records,
+            files,
+            parts: self.state.parts.clone(),
+        };
+        append_entry(
+            self.open_part()?,
+            MANIFEST,
+            &serde_json::to_vec_pretty(&manifest)?,
+        )?;
+        self.finish_part()?;
+        let checkpoint = checkpoint_path(&self.out);
+        if checkpoint.exists() {
+            fs::remove_file(&checkpoint)?;
+        }
+        let parts = (0..self.state.parts.len())
+            .map(|i| self.part_path(i))
+            .collect();
+        Ok((manifest, parts))
+    }
+
+    /// The `index`th part: `out` itself when the snapshot is not split
+    fn part_path(&self, index: usize) -> PathBuf {
+        match self.state.part_bytes {
+            Some(_) => numbered_part(&self.out, index),
+            None => self.out.clone(),
+        }
+    }
+
+    fn open_part(&mut self) -> Result<&mut PartBuilder> {
+        if self.part.is_none() {
+            let path = temporary(&self.part_path(self.state.parts.len()));
+            let file = fs::File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            self.part = Some(tar::Builder::new(zstd::Encoder::new(file, 0)?));
+        }
+        Ok(self.part.as_mut().expect("part was just opened"))
+    }
+
+    /// Close the current part and move it into place
+    fn finish_part(&mut self) -> Result<()> {
+        let Some(part) = self.part.take() else {
+            return Ok(());
+        };
+        part.into_inner()?.finish()?.sync_all()?;
+        let path = self.part_path(self.state.parts.len());
+        fs::rename(temporary(&path), &path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let (bytes, sha256) = digest_file(&path)?;
+        self.state.parts.push(SnapshotFile {
+            path: path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            bytes,
+            sha256,
+        });
+        Ok(())
+    }
+
+    fn save_checkpoint(&self) -> Result<()> {
+        let path = checkpoint_path(&self.out);
+        fs::write(&path, serde_json::to_vec_pretty(&self.state)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Add `data` as `name` under the snapshot root
+fn append_entry(builder: &mut PartBuilder, name: &str, data: &[u8]) -> Result<SnapshotFile> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    builder
+        .append_data(&mut header, Path::new(ROOT).join(name), data)
+        .with_context(|| format!("Failed to add {}", name))?;
+    Ok(SnapshotFile {
+        path: name.to_string(),
+        bytes: data.len() as u64,
+        sha256: hex::encode(Sha256::digest(data)),
+    })
+}
+
+/// `path` with `suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn numbered_part(out: &Path, index: usize) -> PathBuf {
+    with_suffix(out, &format!(".{:03}", index + 1))
+}
+
+fn temporary(path: &Path) -> PathBuf {
+    with_suffix(path, ".tmp")
+}
+
+fn checkpoint_path(out: &Path) -> PathBuf {
+    with_suffix(out, ".checkpoint.json")
+}
+
+/// The archives of the snapshot at `out`: `out` itself, or `out.001`,
+/// `out.002`, ... when it was split into parts
+pub fn snapshot_parts(out: &Path) -> Vec<PathBuf> {
+    if out.is_file() {
+        return vec![out.to_path_buf()];
+    }
+    (0..)
+        .map(|i| numbered_part(out, i))
+        .take_while(|part| part.is_file())
+        .collect()
+}
+
+/// Write `records` and `config_file` (when it exists) as a snapshot archive at `out`
+pub fn write_snapshot(
+    records: &[SnapshotRecord],
+    config_file: Option<&Path>,
+    out: &Path,
+) -> Result<SnapshotManifest> {
+    let mut writer = SnapshotWriter::start(out, None, DEFAULT_CHUNK_BYTES)?;
+    for record in records {
+        writer.push(record)?;
+        if writer.chunk_full() {
+            writer.flush_chunk()?;
+        }
+    }
+    Ok(writer.finish(config_file)?.0)
+}
+
+/// Unpack a snapshot and check every file against its manifest digest and
+/// every memory record parses. A split snapshot is given by the name its
+/// parts are numbered after. Returns the manifest.
+pub fn verify_snapshot(archive: &Path) -> Result<SnapshotManifest> {
+    let parts = snapshot_parts(archive);
+    let Some((last, earlier)) = parts.split_last() else {
+        bail!("{} does not exist", archive.display());
+    };
+    let scratch = Scratch::new("verify")?;
+    for part in &parts {
+        extract_archive(ArchiveFormat::TarZst, part, &scratch.0, &mut |_| {})
+            .with_context(|| format!("Failed to unpack {}", part.display()))?;
+    }
+    let root = scratch.0.join(ROOT);
+    let manifest: SnapshotManifest =
+        serde_json::from_slice(&fs::read(root.join(MANIFEST)).context("Snapshot has no manifest")?)
+            .with_context(|| format!("Invalid snapshot manifest in {}", last.display()))?;
+    if manifest.version > SNAPSHOT_VERSION {
+        bail!(
+            "Snapshot version {} is newer than this build supports ({})",
+            manifest.version,
+            SNAPSHOT_VERSION
+        );
+    }
+    if manifest.parts.len() != earlier.len() {
+        bail!(
+            "Snapshot has {} parts, manifest says {}",
+            parts.len(),
+            manifest.parts.len() + 1
+        );
+    }
+    for (part, expected) in earlier.iter().zip(&manifest.parts) {
+        let (bytes, sha256) = digest_file(part)?;
+        if bytes != expected.bytes || sha256 != expected.sha256 {
+            bail!("{} does not match its manifest digest", part.display());
+        }
+    }
+
+    let mut records = 0u64;
+    for file in &manifest.files {
+        let (bytes, sha256) = digest_file(&root.join(&file.path))
+            .with_context(|| format!("Snapshot is missing {}", file.path))?;
+        if bytes != file.bytes || sha256 != file.sha256 {
+            bail!("{} does not match its manifest digest", file.path);
+        }
+        if file.path != MEMORY && !file.path.starts_with("memory/") {
+            continue;
+        }
+        for (i, line) in BufReader::new(fs::File::open(root.join(&file.path))?)
+            .lines()
+            .enumerate()
+        {
+            serde_json::from_str::<SnapshotRecord>(&line?)
+                .with_context(|| format!("{} line {} is not a record", file.path, i + 1))?;
+            records += 1;
+        }
+    }
+    if records != manifest.records {
+        bail!(
+            "Snapshot holds {} records, manifest says {}",
+            records,
+            manifest.records
+        );
+    }
+    Ok(manifest)
+}
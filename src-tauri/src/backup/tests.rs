@@ -0,0 +1,175 @@
+// Tests for backup snapshots, rotation and destinations
+
+use crate::archive::{create_archive, extract_archive, ArchiveFormat};
+use crate::backup::snapshot::SnapshotRecord;
+use crate::backup::snapshot::SnapshotWriter;
+use crate::backup::*;
+use crate::memory::keys::MEMORY_PREFIX;
+use crate::memory::{InMemoryBackend, MemoryStore};
+use chrono::{Duration, TimeZone, Utc};
+use std::fs;
+
+fn records() -> Vec<SnapshotRecord> {
+    vec![
+        SnapshotRecord {
+            key: "memory:canvas:c1".to_string(),
+            value: serde_json::json!({ "name": "deploy" }),
+        },
+        SnapshotRecord {
+            key: "memory:command:1".to_string(),
+            value: serde_json::json!({ "command": "cargo" }),
+        },
+    ]
+}
+
+#[test]
+fn snapshot_verifies_and_detects_tampering() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config = tmp.path().join("config.toml");
+    fs::write(&config, "[backup]\nenabled = true\n").unwrap();
+    let archive = tmp.path().join("snapshot.tar.zst");
+
+    let written = write_snapshot(&records(), Some(&config), &archive).unwrap();
+    let verified = verify_snapshot(&archive).unwrap();
+    assert_eq!(verified.records, 2);
+    assert_eq!(verified.files, written.files);
+    assert_eq!(written.files.len(), 2);
+
+    // Repack with one record changed but the old manifest
+    let unpacked = tmp.path().join("unpacked");
+    extract_archive(ArchiveFormat::TarZst, &archive, &unpacked, &mut |_| {}).unwrap();
+    let memory = unpacked.join("runebook-backup/memory/00000.jsonl");
+    let text = fs::read_to_string(&memory).unwrap();
+    fs::write(&memory, text.replace("deploy", "d3ploy")).unwrap();
+    let tampered = tmp.path().join("tampered.tar.zst");
+    create_archive(
+        ArchiveFormat::TarZst,
+        &[unpacked.join("runebook-backup")],
+        &tampered,
+        &mut |_| {},
+    )
+    .unwrap();
+    let error = verify_snapshot(&tampered).unwrap_err().to_string();
+    assert!(error.contains("memory/00000.jsonl"), "{}", error);
+}
+
+#[tokio::test]
+async fn split_export_resumes_after_last_part() {
+    let tmp = tempfile::tempdir().unwrap();
+    let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+    for i in 0..30 {
+        store
+            .client
+            .put(
+                &format!("memory:command:{:02}", i),
+                &serde_json::json!({ "command": "x".repeat(200), "i": i }),
+            )
+            .await
+            .unwrap();
+    }
+    let mut keys = store.client.list(MEMORY_PREFIX).await.unwrap();
+    keys.sort();
+    // Tiny chunks and parts, so every chunk starts a part
+    let options = ExportOptions {
+        part_bytes: Some(1),
+        chunk_bytes: 1000,
+    };
+    let out = tmp.path().join("export.tar.zst");
+
+    // An export interrupted after its second part
+    let mut writer = SnapshotWriter::start(&out, options.part_bytes, options.chunk_bytes).unwrap();
+    for key in &keys {
+        let value = store.client.get(key).await.unwrap().unwrap();
+        writer
+            .push(&SnapshotRecord {
+                key: key.clone(),
+                value,
+            })
+            .unwrap();
+        if writer.chunk_full() {
+            writer.flush_chunk().unwrap();
+        }
+        if writer.parts() == 2 {
+            break;
+        }
+    }
+    drop(writer);
+
+    let mut updates = Vec::new();
+    let summary = store
+        .export_snapshot(None, &out, &options, &mut |p| updates.push(p.clone()))
+        .await
+        .unwrap();
+    assert!(summary.resumed);
+    assert!(summary.parts.len() > 2);
+    assert_eq!(summary.manifest.records, keys.len() as u64);
+    assert_eq!(summary.manifest.parts.len(), summary.parts.len() - 1);
+    let last = updates.last().unwrap();
+    assert_eq!(last.records, keys.len() as u64);
+    assert_eq!(last.parts, summary.parts.len());
+    assert!(last.records_per_second > 0.0);
+
+    assert_eq!(snapshot_parts(&out).len(), summary.parts.len());
+    let verified = verify_snapshot(&out).unwrap();
+    assert_eq!(verified.records, keys.len() as u64);
+    assert!(!tmp.path().join("export.tar.zst.checkpoint.json").exists());
+
+    // A part gone missing fails verification
+    fs::remove_file(&summary.parts[1]).unwrap();
+    assert!(verify_snapshot(&out).is_err());
+}
+
+#[test]
+fn rotation_keeps_dailies_and_weeklies() {
+    // Two backups a day for 30 days, ending on a Wednesday
+    let end = Utc.with_ymd_and_hms(2026, 3, 18, 20, 0, 0).unwrap();
+    let mut backups: Vec<StoredBackup> = (0..60)
+        .map(|i| StoredBackup {
+            name: backup_name(end - Duration::hours(12 * i)),
+            bytes: 1,
+        })
+        .collect();
+    backups.push(StoredBackup {
+        name: "notes.txt".to_string(),
+        bytes: 1,
+    });
+
+    let expired = expired_backups(&backups, 3, 2);
+    let kept: Vec<String> = backups
+        .iter()
+        .map(|b| b.name.clone())
+        .filter(|n| !expired.contains(n))
+        .collect();
+    assert_eq!(
+        kept,
+        vec![
+            "runebook-backup-20260318T200000Z.tar.zst",
+            "runebook-backup-20260317T200000Z.tar.zst",
+            "runebook-backup-20260316T200000Z.tar.zst",
+            // Newest of the previous week (Sunday the 15th)
+            "runebook-backup-20260315T200000Z.tar.zst",
+            "notes.txt",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn local_destination_stores_verified_copies() {
+    let tmp = tempfile::tempdir().unwrap();
+    let archive = tmp.path().join("snapshot.tar.zst");
+    write_snapshot(&records(), None, &archive).unwrap();
+    let sha256 = snapshot::sha256_file(&archive).unwrap();
+
+    let destination = open_destination(&DestinationConfig::Local {
+        path: Some(tmp.path().join("backups")),
+    })
+    .unwrap();
+    let name = backup_name(Utc::now());
+    assert!(destination.put(&name, &archive, "0000").await.is_err());
+    destination.put(&name, &archive, &sha256).await.unwrap();
+    let stored = destination.list().await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].name, name);
+    destination.delete(&name).await.unwrap();
+    assert!(destination.list().await.unwrap().is_empty());
+}
@@ -0,0 +1,30 @@
+// Clipboard capture: text first, falling back to an image if the clipboard holds one
+
+use crate::capture::png_artifact;
+use crate::memory::Artifact;
+use anyhow::{Context, Result};
+
+/// Read the current clipboard content into an artifact
+pub fn capture_clipboard() -> Result<Artifact> {
+    let mut clipboard = arboard::Clipboard::new().context("Clipboard is not available")?;
+
+    if let Ok(text) = clipboard.get_text() {
+        if !text.is_empty() {
+            return Ok(Artifact::new(
+                "clipboard_text".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+                text.into_bytes(),
+            ));
+        }
+    }
+
+    let image = clipboard
+        .get_image()
+        .context("Clipboard contains neither text nor an image")?;
+    png_artifact(
+        "clipboard_image",
+        image.width as u32,
+        image.height as u32,
+        image.bytes.into_owned(),
+    )
+}
@@ -0,0 +1,35 @@
+//! User-initiated capture hooks for sessions.
+//!
+//! Grabs the current clipboard content or a window screenshot and turns it into
+//! an [`Artifact`] attached to a session/command. Nothing here runs on its own:
+//! every capture is triggered by an explicit user action from the frontend.
+//! Compiled only with the `capture` feature.
+
+pub mod clipboard;
+pub mod screenshot;
+
+pub use clipboard::capture_clipboard;
+pub use screenshot::capture_screenshot;
+
+use crate::memory::Artifact;
+use anyhow::{Context, Result};
+
+/// Encode raw RGBA pixels as a PNG artifact
+pub(crate) fn png_artifact(
+    artifact_type: &str,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+) -> Result<Artifact> {
+    let image = image::RgbaImage::from_raw(width, height, rgba)
+        .context("Pixel buffer does not match image dimensions")?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .context("Failed to encode PNG")?;
+
+    let mut artifact = Artifact::new(artifact_type.to_string(), "image/png".to_string(), png);
+    artifact.metadata = serde_json::json!({ "width": width, "height": height });
+    Ok(artifact)
+}
@@ -0,0 +1,30 @@
+// Window screenshot capture
+
+use crate::capture::png_artifact;
+use crate::memory::Artifact;
+use anyhow::{Context, Result};
+
+/// Capture a window as a PNG artifact.
+///
+/// With a `title_filter`, the first non-minimized window whose title contains
+/// it is captured; otherwise the focused window is used.
+pub fn capture_screenshot(title_filter: Option<&str>) -> Result<Artifact> {
+    let windows = xcap::Window::all().context("Failed to enumerate windows")?;
+
+    let window = windows
+        .into_iter()
+        .filter(|w| !w.is_minimized().unwrap_or(true))
+        .find(|w| match title_filter {
+            Some(filter) => w.title().map(|t| t.contains(filter)).unwrap_or(false),
+            None => w.is_focused().unwrap_or(false),
+        })
+        .context("No matching window to capture")?;
+
+    let title = window.title().unwrap_or_default();
+    let image = window.capture_image().context("Failed to capture window")?;
+    let (width, height) = image.dimensions();
+
+    let mut artifact = png_artifact("screenshot", width, height, image.into_raw())?;
+    artifact.metadata["window_title"] = serde_json::json!(title);
+    Ok(artifact)
+}
@@ -0,0 +1,115 @@
+//! Staged config-file migrations.
+//!
+//! `config.toml` carries a top-level `config_version`. At startup, before the
+//! config is deserialized, every step newer than the file's version is applied
+//! in order to the TOML document (with `toml_edit`, so the user's comments and
+//! layout survive), the original is kept as `config.toml.v<old>.bak`, and the
+//! applied steps are reported.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use toml_edit::DocumentMut;
+use ts_rs::TS;
+
+/// Format version written by this build
+pub const CONFIG_VERSION: u32 = 1;
+
+const VERSION_KEY: &str = "config_version";
+
+struct Step {
+    version: u32,
+    description: &'static str,
+    apply: fn(&mut DocumentMut),
+}
+
+const STEPS: &[Step] = &[Step {
+    version: 1,
+    description: "Record the config format version",
+    apply: |_| {},
+}];
+
+/// A step that was applied
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[ts(export)]
+pub struct AppliedConfigMigration {
+    pub version: u32,
+    pub description: String,
+}
+
+/// Outcome of migrating the config file
+#[derive(Debug, Clone, Default, Serialize, TS)]
+#[ts(export)]
+pub struct ConfigMigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub applied: Vec<AppliedConfigMigration>,
+    /// Copy of the file before migration
+    pub backup: Option<PathBuf>,
+    /// The file was written by a newer RuneBook and was left untouched
+    pub newer_than_supported: bool,
+}
+
+fn file_version(doc: &DocumentMut) -> u32 {
+    doc.get(VERSION_KEY)
+        .and_then(|v| v.as_integer())
+        .and_then(|v| u32::try_from(v).ok())
+        .unwrap_or(0)
+}
+
+/// Migrate config text. Returns the new text (unchanged when nothing applied).
+pub fn migrate_text(text: &str) -> Result<(String, ConfigMigrationReport)> {
+    let mut doc: DocumentMut = text.parse().context("Invalid config TOML")?;
+    let from_version = file_version(&doc);
+    let mut report = ConfigMigrationReport {
+        from_version,
+        to_version: from_version,
+        ..Default::default()
+    };
+    if from_version > CONFIG_VERSION {
+        report.newer_than_supported = true;
+        return Ok((text.to_string(), report));
+    }
+
+    for step in STEPS.iter().filter(|s| s.version > from_version) {
+        (step.apply)(&mut doc);
+        report.applied.push(AppliedConfigMigration {
+            version: step.version,
+            description: step.description.to_string(),
+        });
+    }
+    if report.applied.is_empty() {
+        return Ok((text.to_string(), report));
+    }
+    doc.insert(VERSION_KEY, toml_edit::value(i64::from(CONFIG_VERSION)));
+    report.to_version = CONFIG_VERSION;
+    Ok((doc.to_string(), report))
+}
+
+/// Migrate the config file at `path` in place. A missing file needs nothing.
+pub fn migrate_file(path: &Path) -> Result<ConfigMigrationReport> {
+    if !path.exists() {
+        return Ok(ConfigMigrationReport {
+            from_version: CONFIG_VERSION,
+            to_version: CONFIG_VERSION,
+            ..Default::default()
+        });
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let (migrated, mut report) = migrate_text(&text)?;
+    if report.applied.is_empty() {
+        return Ok(report);
+    }
+
+    let backup = path.with_extension(format!("toml.v{}.bak", report.from_version));
+    std::fs::copy(path, &backup)
+        .with_context(|| format!("Failed to back up {}", path.display()))?;
+    // Through a temporary file, so a crash mid-write keeps the old config
+    let partial = path.with_extension("toml.partial");
+    std::fs::write(&partial, migrated)
+        .and_then(|()| std::fs::rename(&partial, path))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    report.backup = Some(backup);
+    Ok(report)
+}
@@ -0,0 +1,146 @@
+//! Application configuration.
+//!
+//! Loaded from `config.toml` in the RuneBook config directory
+//! (`$RUNEBOOK_CONFIG_DIR`, or the platform config dir + `runebook`), or in
+//! `profiles/<name>` below it for a named [profile](crate::profile).
+//! Every section has defaults, so a missing file or section is not an error.
+
+pub mod migrate;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+use crate::alerts::AlertConfig;
+use crate::backup::BackupConfig;
+use crate::deeplink::DeepLinkConfig;
+use crate::dispatch::FocusConfig;
+use crate::execution::{
+    ConcurrencyConfig, ExecutionProfile, ExecutionRequest, OutputCap, SafetyConfig,
+};
+use crate::ipc::IpcConfig;
+use crate::memory::{
+    AutoTagConfig, EncodingConfig, MemoryConfig, MirrorConfig, QuotaConfig, RankingConfig,
+    RetentionConfig, ScrubConfig,
+};
+use crate::permissions::PermissionsConfig;
+use crate::power::PowerConfig;
+use crate::server::{GrpcConfig, ServerConfig};
+use crate::storage::StorageConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::upgrade::UpdateConfig;
+use crate::validation::ValidationConfig;
+use crate::webhooks::WebhookConfig;
+
+/// Top-level configuration file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct RunebookConfig {
+    pub telemetry: TelemetryConfig,
+    pub quota: QuotaConfig,
+    pub output: EncodingConfig,
+    pub tags: AutoTagConfig,
+    pub scrub: ScrubConfig,
+    pub safety: SafetyConfig,
+    pub profiles: HashMap<String, ExecutionProfile>,
+    /// How much of each command's stdout and stderr a run keeps
+    pub command_output: OutputCap,
+    /// How many commands run at once, and which waiting run goes next
+    pub concurrency: ConcurrencyConfig,
+    pub ipc: IpcConfig,
+    pub server: ServerConfig,
+    pub grpc: GrpcConfig,
+    pub deep_links: DeepLinkConfig,
+    /// Named commands that `runebook://snippet/<name>` links may run
+    pub snippets: HashMap<String, ExecutionRequest>,
+    pub updates: UpdateConfig,
+    /// When to hold back suggestions during focused work
+    pub focus: FocusConfig,
+    /// Suggestion ranking weights, and whether outcomes are recorded
+    pub ranking: RankingConfig,
+    /// Scheduled full-state backups
+    pub backup: BackupConfig,
+    /// Object storage for backups and offloaded artifact content
+    pub storage: StorageConfig,
+    /// When stored output is recompressed, offloaded, or summarized
+    pub retention: RetentionConfig,
+    /// SQLite copy of memory for ad-hoc SQL
+    pub mirror: MirrorConfig,
+    /// Capability groups the frontend may not use
+    pub permissions: PermissionsConfig,
+    /// Limits on command arguments from the frontend
+    pub validation: ValidationConfig,
+    /// The PluresDB server behind the memory store
+    pub memory: MemoryConfig,
+    /// Rules that raise alerts on what memory records
+    pub alerts: AlertConfig,
+    /// Endpoints alerts and digests are sent to
+    pub webhooks: WebhookConfig,
+    /// What happens to running work when the machine sleeps
+    pub power: PowerConfig,
+}
+
+impl RunebookConfig {
+    /// Load configuration from `path`, falling back to defaults if it does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config in {}", path.display()))
+    }
+
+    /// Load configuration from the default location
+    pub fn load_default() -> Result<Self> {
+        Self::load(&config_path())
+    }
+
+    /// Write configuration to `path`, creating parent directories as needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let text = toml::to_string_pretty(self).context("Failed to serialize config")?;
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Replace the `name` section of the config file at `path` with `value`,
+/// leaving the rest of the file, comments included, as it was
+pub fn write_section<T: Serialize>(path: &Path, name: &str, value: &T) -> Result<()> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("Invalid config in {}", path.display()))?;
+    let section: toml_edit::DocumentMut = toml::to_string(value)
+        .context("Failed to serialize config")?
+        .parse()
+        .context("Failed to serialize config")?;
+    doc.insert(name, toml_edit::Item::Table(section.as_table().clone()));
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("toml.partial");
+    std::fs::write(&partial, doc.to_string())
+        .and_then(|()| std::fs::rename(&partial, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Directory holding the active profile's configuration
+pub fn config_dir() -> PathBuf {
+    crate::profile::Profiles::default_location().dir(crate::profile::active())
+}
+
+/// Path of the main configuration file
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
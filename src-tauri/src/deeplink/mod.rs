@@ -0,0 +1,313 @@
+//! `runebook://` deep links.
+//!
+//! Links arrive from browsers, chat messages, and documents, so they are
+//! untrusted input. They are parsed strictly into a [`DeepLink`], and parsing
+//! never acts: the frontend shows a [`DeepLinkPrompt`] and the user confirms
+//! before anything runs or is imported. The confirming command re-parses the
+//! original URL rather than trusting what the frontend sends back.
+//!
+//! ```text
+//! runebook://session/<session-id>
+//! runebook://snippet/<name>?<variable>=<value>&...
+//! runebook://card?title=...&description=...&command=...&tags=a,b
+//! ```
+//!
+//! A snippet link can only name a snippet from `[snippets.<name>]` in the
+//! config and fill in its `{{variables}}`; the command itself always comes from
+//! the config. Snippets still go through the destructive-command gate, and
+//! blocked ones are refused before the prompt. A card's command is stored as
+//! text for the user to read and is never run.
+
+use crate::execution::{ExecutionRequest, ExecutionService, PolicyPreview, ResolvedExecution};
+use crate::i18n::UserMessage;
+use crate::memory::Suggestion;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use ts_rs::TS;
+
+const SCHEME: &str = "runebook";
+const MAX_URL_LEN: usize = 4096;
+const MAX_ID_LEN: usize = 128;
+const MAX_VALUE_LEN: usize = 1024;
+const MAX_TITLE_LEN: usize = 200;
+const MAX_DESCRIPTION_LEN: usize = 2000;
+const MAX_TAGS: usize = 10;
+
+/// `[deep_links]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct DeepLinkConfig {
+    pub enabled: bool,
+    /// Allow `snippet` links (each run still needs confirmation)
+    pub run_snippets: bool,
+    /// Allow `card` links
+    pub import_cards: bool,
+}
+
+impl Default for DeepLinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            run_snippets: true,
+            import_cards: true,
+        }
+    }
+}
+
+/// A knowledge card shared as a link; imported as a suggestion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SharedCard {
+    pub title: String,
+    pub description: String,
+    pub command: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl SharedCard {
+    pub fn into_suggestion(self) -> Suggestion {
+        Suggestion {
+            id: uuid::Uuid::new_v4().to_string(),
+            suggestion_type: "tip".to_string(),
+            priority: "low".to_string(),
+            rank: 0.5,
+            title: self.title,
+            description: self.description,
+            command: self.command,
+            args: None,
+            context: serde_json::json!({ "source": "deep-link", "tags": self.tags }),
+            created_at: chrono::Utc::now(),
+            dismissed: false,
+            applied: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "action", rename_all = "snake_case")]
+#[ts(export)]
+pub enum DeepLink {
+    OpenSession {
+        session_id: String,
+    },
+    RunSnippet {
+        name: String,
+        variables: BTreeMap<String, String>,
+    },
+    ImportCard {
+        card: SharedCard,
+    },
+}
+
+fn invalid(error: impl ToString) -> UserMessage {
+    UserMessage::new("deep-link-invalid").with("error", error.to_string())
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && id != "."
+        && id != ".."
+}
+
+/// Values must be printable and bounded
+fn check_text(name: &str, value: &str, max: usize) -> Result<(), UserMessage> {
+    if value.chars().count() > max {
+        return Err(invalid(format!(
+            "{} is longer than {} characters",
+            name, max
+        )));
+    }
+    if value.chars().any(|c| c.is_control() && c != '\n') {
+        return Err(invalid(format!("{} contains control characters", name)));
+    }
+    Ok(())
+}
+
+/// Parse and validate a `runebook://` URL
+pub fn parse(raw: &str) -> Result<DeepLink, UserMessage> {
+    if raw.len() > MAX_URL_LEN {
+        return Err(invalid(format!(
+            "link is longer than {} bytes",
+            MAX_URL_LEN
+        )));
+    }
+    let url = url::Url::parse(raw).map_err(invalid)?;
+    if url.scheme() != SCHEME {
+        return Err(invalid(format!("scheme must be {}://", SCHEME)));
+    }
+    if !url.username().is_empty() || url.password().is_some() || url.port().is_some() {
+        return Err(invalid("credentials and ports are not allowed"));
+    }
+    if url.fragment().is_some() {
+        return Err(invalid("fragments are not allowed"));
+    }
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let mut query: BTreeMap<String, String> = BTreeMap::new();
+    for (key, value) in url.query_pairs() {
+        if query.insert(key.to_string(), value.to_string()).is_some() {
+            return Err(invalid(format!("parameter {} is repeated", key)));
+        }
+    }
+
+    match (url.host_str().unwrap_or_default(), segments.as_slice()) {
+        ("session", [id]) if query.is_empty() => {
+            if !valid_id(id) {
+                return Err(invalid("invalid session id"));
+            }
+            Ok(DeepLink::OpenSession {
+                session_id: id.to_string(),
+            })
+        }
+        ("snippet", [name]) => {
+            if !valid_id(name) {
+                return Err(invalid("invalid snippet name"));
+            }
+            for (key, value) in &query {
+                if !crate::execution::command::valid_env_name(key) || key.len() > MAX_ID_LEN {
+                    return Err(invalid(format!("invalid variable name {:?}", key)));
+                }
+                check_text(key, value, MAX_VALUE_LEN)?;
+                if value.contains('\n') {
+                    return Err(invalid(format!("{} contains a line break", key)));
+                }
+            }
+            Ok(DeepLink::RunSnippet {
+                name: name.to_string(),
+                variables: query,
+            })
+        }
+        ("card", []) => {
+            if let Some(unknown) = query
+                .keys()
+                .find(|k| !["title", "description", "command", "tags"].contains(&k.as_str()))
+            {
+                return Err(invalid(format!("unknown card parameter {}", unknown)));
+            }
+            let title = query.remove("title").unwrap_or_default();
+            if title.trim().is_empty() {
+                return Err(invalid("card has no title"));
+            }
+            check_text("title", &title, MAX_TITLE_LEN)?;
+            let description = query.remove("description").unwrap_or_default();
+            check_text("description", &description, MAX_DESCRIPTION_LEN)?;
+            let command = query.remove("command").filter(|c| !c.trim().is_empty());
+            if let Some(command) = &command {
+                check_text("command", command, MAX_VALUE_LEN)?;
+            }
+            let mut tags = Vec::new();
+            for tag in query.remove("tags").unwrap_or_default().split(',') {
+                if tag.trim().is_empty() {
+                    continue;
+                }
+                let tag = crate::memory::tags::normalize_tag(tag)
+                    .ok_or_else(|| invalid(format!("invalid tag {:?}", tag)))?;
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            if tags.len() > MAX_TAGS {
+                return Err(invalid(format!("more than {} tags", MAX_TAGS)));
+            }
+            Ok(DeepLink::ImportCard {
+                card: SharedCard {
+                    title,
+                    description,
+                    command,
+                    tags,
+                },
+            })
+        }
+        (host, _) => Err(invalid(format!("unsupported link {}://{}", SCHEME, host))),
+    }
+}
+
+/// A validated link and what acting on it would do, for the confirmation prompt
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct DeepLinkPrompt {
+    pub url: String,
+    pub link: DeepLink,
+    /// For snippets: the exact command and the policy it would meet
+    pub preview: Option<ResolvedExecution>,
+}
+
+/// Validates links against the configuration and the execution policy
+pub struct DeepLinkHandler {
+    config: DeepLinkConfig,
+    snippets: HashMap<String, ExecutionRequest>,
+}
+
+impl DeepLinkHandler {
+    pub fn new(config: DeepLinkConfig, snippets: HashMap<String, ExecutionRequest>) -> Self {
+        Self { config, snippets }
+    }
+
+    /// Parse `url` and check it may be acted on, without acting
+    pub fn prepare(
+        &self,
+        url: &str,
+        execution: &ExecutionService,
+    ) -> Result<DeepLinkPrompt, UserMessage> {
+        if !self.config.enabled {
+            return Err(UserMessage::new("deep-link-disabled").with("action", "any"));
+        }
+        let link = parse(url)?;
+        let preview = match &link {
+            DeepLink::RunSnippet { name, variables } => {
+                if !self.config.run_snippets {
+                    return Err(UserMessage::new("deep-link-disabled").with("action", "snippet"));
+                }
+                let resolved = execution.preview(&self.snippet_request(name, variables)?)?;
+                if let PolicyPreview::Block { rule, reason, .. } = &resolved.policy {
+                    return Err(UserMessage::new("command-blocked")
+                        .with("rule", rule)
+                        .with("reason", reason));
+                }
+                if !resolved.unresolved_variables.is_empty() {
+                    return Err(UserMessage::new("template-unresolved")
+                        .with("names", resolved.unresolved_variables.join(", ")));
+                }
+                Some(resolved)
+            }
+            DeepLink::ImportCard { .. } if !self.config.import_cards => {
+                return Err(UserMessage::new("deep-link-disabled").with("action", "card"));
+            }
+            _ => None,
+        };
+        Ok(DeepLinkPrompt {
+            url: url.to_string(),
+            link,
+            preview,
+        })
+    }
+
+    /// The configured snippet with the link's variables filled in. Link
+    /// variables cannot override the snippet's command, args, env, or cwd.
+    pub fn snippet_request(
+        &self,
+        name: &str,
+        variables: &BTreeMap<String, String>,
+    ) -> Result<ExecutionRequest, UserMessage> {
+        let mut request = self
+            .snippets
+            .get(name)
+            .cloned()
+            .ok_or_else(|| UserMessage::new("deep-link-unknown-snippet").with("name", name))?;
+        request
+            .variables
+            .extend(variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,112 @@
+use super::*;
+use crate::audit::AuditLog;
+use crate::execution::{CommandGate, SafetyConfig};
+use std::sync::Arc;
+
+#[test]
+fn parses_only_well_formed_links() {
+    assert_eq!(
+        parse("runebook://session/abc-123").unwrap(),
+        DeepLink::OpenSession {
+            session_id: "abc-123".to_string()
+        }
+    );
+    let DeepLink::RunSnippet { name, variables } =
+        parse("runebook://snippet/deploy?env=staging").unwrap()
+    else {
+        panic!("expected a snippet link");
+    };
+    assert_eq!(name, "deploy");
+    assert_eq!(variables["env"], "staging");
+
+    let DeepLink::ImportCard { card } = parse(
+        "runebook://card?title=Fix%20token&description=Set%20GITHUB_TOKEN&command=gh%20auth%20login&tags=GitHub,auth,github",
+    )
+    .unwrap() else {
+        panic!("expected a card link");
+    };
+    assert_eq!(card.title, "Fix token");
+    assert_eq!(card.command.as_deref(), Some("gh auth login"));
+    assert_eq!(card.tags, vec!["github", "auth"]);
+
+    for bad in [
+        "https://session/abc",
+        "runebook://user:pw@session/abc",
+        "runebook://session/abc?x=1",
+        "runebook://session/..",
+        "runebook://session/a/b",
+        "runebook://snippet/deploy?env=a&env=b",
+        "runebook://snippet/deploy?bad-name=x",
+        "runebook://snippet/deploy?env=a%0Ab",
+        "runebook://card?description=no%20title",
+        "runebook://card?title=t&run=yes",
+        "runebook://shell/rm",
+    ] {
+        let err = parse(bad).unwrap_err();
+        assert_eq!(err.code, "deep-link-invalid", "{}", bad);
+    }
+    assert!(parse(&format!("runebook://session/{}", "a".repeat(5000))).is_err());
+}
+
+#[test]
+fn snippet_links_only_fill_in_configured_snippets() {
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let safety = SafetyConfig {
+        destructive: crate::execution::DestructivePolicy::Block,
+        ..Default::default()
+    };
+    let execution = ExecutionService::new(
+        Arc::new(CommandGate::new(safety, audit)),
+        Default::default(),
+    );
+    let snippets = HashMap::from([
+        (
+            "greet".to_string(),
+            ExecutionRequest {
+                command: "echo".to_string(),
+                args: vec!["hello".to_string(), "{{name}}".to_string()],
+                ..Default::default()
+            },
+        ),
+        (
+            "wipe".to_string(),
+            ExecutionRequest {
+                command: "rm".to_string(),
+                args: vec!["-rf".to_string(), "{{dir}}".to_string()],
+                ..Default::default()
+            },
+        ),
+    ]);
+    let handler = DeepLinkHandler::new(DeepLinkConfig::default(), snippets);
+
+    let prompt = handler
+        .prepare("runebook://snippet/greet?name=world", &execution)
+        .unwrap();
+    assert_eq!(prompt.preview.unwrap().command_line, "echo hello world");
+
+    let missing = handler
+        .prepare("runebook://snippet/greet", &execution)
+        .unwrap_err();
+    assert_eq!(missing.code, "template-unresolved");
+    let unknown = handler
+        .prepare("runebook://snippet/other", &execution)
+        .unwrap_err();
+    assert_eq!(unknown.code, "deep-link-unknown-snippet");
+    let blocked = handler
+        .prepare("runebook://snippet/wipe?dir=/", &execution)
+        .unwrap_err();
+    assert_eq!(blocked.code, "command-blocked");
+
+    let disabled = DeepLinkHandler::new(
+        DeepLinkConfig {
+            run_snippets: false,
+            ..Default::default()
+        },
+        HashMap::new(),
+    );
+    let err = disabled
+        .prepare("runebook://snippet/greet?name=x", &execution)
+        .unwrap_err();
+    assert_eq!(err.code, "deep-link-disabled");
+}
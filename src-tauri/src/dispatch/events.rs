@@ -0,0 +1,273 @@
+//! Registry of the events published through the [`WindowDispatcher`].
+//!
+//! Every event the dispatcher sends is listed in [`EVENTS`] with its topic,
+//! the payload type it carries and the version of that payload's shape.
+//! `list_event_schemas` hands the registry to tooling, with example payloads
+//! of the current version as they go over the wire.
+//!
+//! Within a version a payload only ever gains fields. Removing, renaming or
+//! retyping a field, or changing a tag or the format of a value, makes a new
+//! version: bump `version` and add `events/<name>.v<version>.json` with the
+//! new examples; the old file stays as a record of what listeners built
+//! against it received. The dispatch tests build each payload and check it
+//! against the current examples, so a payload that drifts fails there
+//! instead of in the webview.
+//!
+//! Events sent straight to one window, such as a terminal's
+//! `terminal-output-<id>` or `open-request`, are not dispatched and not
+//! registered.
+//!
+//! [`WindowDispatcher`]: super::WindowDispatcher
+
+use super::{
+    Topic, ALERT_FIRED, ARCHIVE_PROGRESS, BACKUP_EXPORT_PROGRESS, CANVAS_DEBUG_EVENT,
+    COMMAND_OBJECTS, COMMAND_OUTPUT, FOCUS_CHANGED, JOBS_CHANGED, LOG_EVENT, MEMORY_QUOTA,
+    PROFILE_SWITCHING, PTY_EVENT, REINDEX_PROGRESS, SESSION_CHANGED, SUGGESTIONS_RESUMED,
+    SUGGESTION_ADDED, UPGRADE_REPORT, VIEW_UPDATED, WATCH_EVENT,
+};
+use crate::execution::{OutputStream, StyledSpan, TextEncoding};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use ts_rs::TS;
+
+/// An event the dispatcher publishes
+#[derive(Debug, Clone, Copy, Serialize, TS)]
+#[ts(export)]
+pub struct EventSchema {
+    pub name: &'static str,
+    pub topic: Topic,
+    /// Version of the payload's shape
+    pub version: u32,
+    /// Rust type of the payload
+    pub payload: &'static str,
+    pub description: &'static str,
+    /// Payloads of this version as sent, one for each shape the event takes
+    #[serde(serialize_with = "as_json")]
+    #[ts(type = "unknown[]")]
+    pub examples: &'static str,
+}
+
+fn as_json<S: Serializer>(json: &&'static str, serializer: S) -> Result<S::Ok, S::Error> {
+    serde_json::from_str::<Value>(json)
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
+}
+
+pub const EVENTS: &[EventSchema] = &[
+    EventSchema {
+        name: SESSION_CHANGED,
+        topic: Topic::Session,
+        version: 1,
+        payload: "SessionChanged",
+        description: "The current session was switched, or cleared",
+        examples: include_str!("events/session-changed.v1.json"),
+    },
+    EventSchema {
+        name: JOBS_CHANGED,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "JobsChanged",
+        description: "A job started or finished",
+        examples: include_str!("events/jobs-changed.v1.json"),
+    },
+    EventSchema {
+        name: WATCH_EVENT,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "WatchEvent",
+        description: "Progress of a watch started with `watch_start`",
+        examples: include_str!("events/watch-event.v1.json"),
+    },
+    EventSchema {
+        name: LOG_EVENT,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "LogEvent",
+        description: "An error found in, or the end of, a `logs_follow` stream",
+        examples: include_str!("events/log-event.v1.json"),
+    },
+    EventSchema {
+        name: CANVAS_DEBUG_EVENT,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "DebugEvent",
+        description: "A canvas debug session paused, ran a node or finished",
+        examples: include_str!("events/canvas-debug-event.v1.json"),
+    },
+    EventSchema {
+        name: COMMAND_OUTPUT,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "CommandOutputEvent",
+        description: "A chunk of live output of `execute_terminal_command_streaming`",
+        examples: include_str!("events/command-output.v1.json"),
+    },
+    EventSchema {
+        name: COMMAND_OBJECTS,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "CommandObjectsEvent",
+        description: "Objects a streamed PowerShell run emitted",
+        examples: include_str!("events/command-objects.v1.json"),
+    },
+    EventSchema {
+        name: PTY_EVENT,
+        topic: Topic::Jobs,
+        version: 1,
+        payload: "PtyEvent",
+        description: "Output or exit of a `pty_start` session",
+        examples: include_str!("events/pty-event.v1.json"),
+    },
+    EventSchema {
+        name: SUGGESTION_ADDED,
+        topic: Topic::Suggestions,
+        version: 1,
+        payload: "Suggestion",
+        description: "A new suggestion the surfacing policy let through",
+        examples: include_str!("events/suggestion-added.v1.json"),
+    },
+    EventSchema {
+        name: FOCUS_CHANGED,
+        topic: Topic::Suggestions,
+        version: 1,
+        payload: "FocusStatus",
+        description: "Focused work started or ended",
+        examples: include_str!("events/focus-changed.v1.json"),
+    },
+    EventSchema {
+        name: SUGGESTIONS_RESUMED,
+        topic: Topic::Suggestions,
+        version: 1,
+        payload: "FocusSummary",
+        description: "Suggestions held back during focus, released",
+        examples: include_str!("events/suggestions-resumed.v1.json"),
+    },
+    EventSchema {
+        name: ALERT_FIRED,
+        topic: Topic::Memory,
+        version: 1,
+        payload: "Alert",
+        description: "An alert rule fired",
+        examples: include_str!("events/alert-fired.v1.json"),
+    },
+    EventSchema {
+        name: VIEW_UPDATED,
+        topic: Topic::Memory,
+        version: 1,
+        payload: "ViewChange",
+        description: "New records matched a saved view",
+        examples: include_str!("events/view-updated.v1.json"),
+    },
+    EventSchema {
+        name: MEMORY_QUOTA,
+        topic: Topic::Memory,
+        version: 1,
+        payload: "QuotaEvent",
+        description: "A quota dropped a write or collected old output",
+        examples: include_str!("events/memory-quota.v1.json"),
+    },
+    EventSchema {
+        name: ARCHIVE_PROGRESS,
+        topic: Topic::App,
+        version: 1,
+        payload: "ArchiveProgressEvent",
+        description: "Progress of `archive_create` or `archive_extract`",
+        examples: include_str!("events/archive-progress.v1.json"),
+    },
+    EventSchema {
+        name: REINDEX_PROGRESS,
+        topic: Topic::App,
+        version: 1,
+        payload: "ReindexProgressEvent",
+        description: "A batch of `reindex_memory` was committed",
+        examples: include_str!("events/reindex-progress.v1.json"),
+    },
+    EventSchema {
+        name: BACKUP_EXPORT_PROGRESS,
+        topic: Topic::App,
+        version: 1,
+        payload: "ExportProgressEvent",
+        description: "A chunk of `backup_export` was written, or the export finished",
+        examples: include_str!("events/backup-export-progress.v1.json"),
+    },
+    EventSchema {
+        name: PROFILE_SWITCHING,
+        topic: Topic::App,
+        version: 1,
+        payload: "ProfileSwitching",
+        description: "The app is about to restart in another profile",
+        examples: include_str!("events/profile-switching.v1.json"),
+    },
+    EventSchema {
+        name: UPGRADE_REPORT,
+        topic: Topic::App,
+        version: 1,
+        payload: "UpgradeReport",
+        description: "What changed since the last version that opened the store",
+        examples: include_str!("events/upgrade-report.v1.json"),
+    },
+];
+
+/// The registered event called `name`
+pub fn schema(name: &str) -> Option<&'static EventSchema> {
+    EVENTS.iter().find(|schema| schema.name == name)
+}
+
+/// `session-changed` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionChanged {
+    pub session_id: Option<String>,
+}
+
+/// `profile-switching` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileSwitching {
+    pub from: String,
+    pub to: String,
+}
+
+/// `command-output` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutputEvent {
+    pub run_id: String,
+    pub chunk_index: u64,
+    pub stream_type: OutputStream,
+    pub text: String,
+    /// `base64` when the chunk is binary or not UTF-8
+    #[serde(skip_serializing_if = "TextEncoding::is_utf8")]
+    pub encoding: TextEncoding,
+    /// Styles of `text`, when run with `ansi` `spans`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<StyledSpan>,
+}
+
+/// `command-objects` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandObjectsEvent {
+    pub run_id: String,
+    pub objects: Vec<Value>,
+}
+
+/// `archive-progress` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveProgressEvent {
+    pub operation_id: String,
+    #[serde(flatten)]
+    pub progress: crate::archive::ArchiveProgress,
+}
+
+/// `reindex-progress` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexProgressEvent {
+    pub operation_id: String,
+    #[serde(flatten)]
+    pub progress: crate::memory::ReindexProgress,
+}
+
+/// `backup-export-progress` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgressEvent {
+    pub operation_id: String,
+    #[serde(flatten)]
+    pub progress: crate::backup::ExportProgress,
+}
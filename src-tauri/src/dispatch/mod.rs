@@ -0,0 +1,364 @@
+//! Window-aware event dispatch.
+//!
+//! Backend state that every window shows (the current session, running jobs,
+//! new suggestions, view and quota notifications) is published through a
+//! [`WindowDispatcher`] instead of being returned only to the window that
+//! issued a command. Each window may narrow what it receives with a
+//! [`WindowFilter`]; windows that never subscribed get everything. A window
+//! opened later catches up with [`WindowDispatcher::snapshot`].
+//!
+//! Delivery goes through an [`EventSink`], which the app implements over the
+//! Tauri handle, so the dispatcher itself has no UI dependency.
+//!
+//! Suggestions pass through a [`SurfacePolicy`] first, which holds them back
+//! while the user is focused (see [`surfaces`]).
+//!
+//! Every event name and the shape of its payload is registered in
+//! [`events`], which `list_event_schemas` reports to tooling.
+
+pub mod events;
+pub mod surfaces;
+
+pub use events::{
+    ArchiveProgressEvent, CommandObjectsEvent, CommandOutputEvent, EventSchema,
+    ExportProgressEvent, ProfileSwitching, ReindexProgressEvent, SessionChanged, EVENTS,
+};
+pub use surfaces::{FocusConfig, FocusStatus, FocusSummary, SurfacePolicy};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use ts_rs::TS;
+
+/// Event names published by the dispatcher
+pub const SESSION_CHANGED: &str = "session-changed";
+pub const JOBS_CHANGED: &str = "jobs-changed";
+/// Progress of a watch started with `watch_start`, as one `WatchEvent`
+pub const WATCH_EVENT: &str = "watch-event";
+/// Errors found in and the end of a `logs_follow` stream, as one `LogEvent`
+pub const LOG_EVENT: &str = "log-event";
+/// Pauses and progress of a canvas debug session, as one `DebugEvent`
+pub const CANVAS_DEBUG_EVENT: &str = "canvas-debug-event";
+/// Live output of `execute_terminal_command_streaming`, one chunk per event
+pub const COMMAND_OUTPUT: &str = "command-output";
+/// Objects a PowerShell run of `execute_terminal_command_streaming` emitted
+pub const COMMAND_OBJECTS: &str = "command-objects";
+/// Output and exit of a `pty_start` session, as one `PtyEvent`
+pub const PTY_EVENT: &str = "pty-event";
+pub const SUGGESTION_ADDED: &str = "suggestion-added";
+pub const FOCUS_CHANGED: &str = "focus-changed";
+/// Suggestions held back during focus, as one [`FocusSummary`]
+pub const SUGGESTIONS_RESUMED: &str = "suggestions-resumed";
+/// An alert rule fired, as one `Alert`
+pub const ALERT_FIRED: &str = "alert-fired";
+/// New matches of a saved view, as one `ViewChange`
+pub const VIEW_UPDATED: &str = "view-updated";
+/// A quota decision, as one `QuotaEvent`
+pub const MEMORY_QUOTA: &str = "memory-quota";
+pub const ARCHIVE_PROGRESS: &str = "archive-progress";
+pub const REINDEX_PROGRESS: &str = "reindex-progress";
+pub const BACKUP_EXPORT_PROGRESS: &str = "backup-export-progress";
+pub const PROFILE_SWITCHING: &str = "profile-switching";
+/// What changed since the last version, as one `UpgradeReport`
+pub const UPGRADE_REPORT: &str = "upgrade-report";
+
+/// What an event is about; windows subscribe by topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum Topic {
+    Session,
+    Jobs,
+    Suggestions,
+    /// Saved-view matches, quota decisions and alerts
+    Memory,
+    /// Archive progress, upgrade reports, open requests
+    App,
+}
+
+/// What a window wants to receive. Empty lists mean "everything".
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct WindowFilter {
+    pub topics: Vec<Topic>,
+    /// Only session-scoped events for these sessions; events without a
+    /// session always pass
+    pub session_ids: Vec<String>,
+}
+
+impl WindowFilter {
+    pub fn accepts(&self, topic: Topic, session_id: Option<&str>) -> bool {
+        let topic_ok = self.topics.is_empty() || self.topics.contains(&topic);
+        let session_ok = match session_id {
+            Some(id) => self.session_ids.is_empty() || self.session_ids.iter().any(|s| s == id),
+            None => true,
+        };
+        topic_ok && session_ok
+    }
+}
+
+/// Delivers an event to one window
+pub trait EventSink: Send + Sync {
+    /// Labels of the open windows
+    fn windows(&self) -> Vec<String>;
+    fn emit_to(&self, window: &str, event: &str, payload: &Value) -> Result<()>;
+}
+
+/// A command running on behalf of some window
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct JobInfo {
+    pub id: String,
+    pub command: String,
+    /// Who started it ("user", "deep-link", ...)
+    pub origin: String,
+    pub session_id: Option<String>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Backend state a newly opened window needs to catch up
+#[derive(Debug, Clone, Default, Serialize, TS)]
+#[ts(export)]
+pub struct SharedState {
+    pub current_session: Option<String>,
+    pub running_jobs: Vec<JobInfo>,
+}
+
+/// `jobs-changed` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct JobsChanged {
+    pub running: Vec<JobInfo>,
+    /// The job that just finished, with whether it succeeded
+    pub finished: Option<FinishedJob>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FinishedJob {
+    pub job: JobInfo,
+    pub success: bool,
+}
+
+#[derive(Default)]
+struct State {
+    filters: HashMap<String, WindowFilter>,
+    current_session: Option<String>,
+    jobs: BTreeMap<String, JobInfo>,
+}
+
+pub struct WindowDispatcher {
+    sink: Arc<dyn EventSink>,
+    state: Mutex<State>,
+    surfaces: SurfacePolicy,
+}
+
+impl WindowDispatcher {
+    pub fn new(sink: Arc<dyn EventSink>) -> Self {
+        Self {
+            sink,
+            state: Mutex::new(State::default()),
+            surfaces: SurfacePolicy::new(FocusConfig::default()),
+        }
+    }
+
+    /// Use `config` to detect focus instead of the defaults
+    pub fn with_focus(mut self, config: FocusConfig) -> Self {
+        self.surfaces = SurfacePolicy::new(config);
+        self
+    }
+
+    /// Replace a window's filter
+    pub fn subscribe(&self, window: &str, filter: WindowFilter) {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .filters
+            .insert(window.to_string(), filter);
+    }
+
+    /// Forget a closed window
+    pub fn unsubscribe(&self, window: &str) {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .filters
+            .remove(window);
+    }
+
+    /// Send `event` to every window whose filter accepts it. Returns the
+    /// number of windows it was delivered to.
+    pub fn dispatch<T: Serialize>(
+        &self,
+        topic: Topic,
+        session_id: Option<&str>,
+        event: &str,
+        payload: &T,
+    ) -> usize {
+        debug_assert!(
+            events::schema(event).is_some(),
+            "{} is not in dispatch::events::EVENTS",
+            event
+        );
+        let payload = match serde_json::to_value(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::warn!("[dispatch] Could not serialize {}: {}", event, e);
+                return 0;
+            }
+        };
+        let targets: Vec<String> = {
+            let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            self.sink
+                .windows()
+                .into_iter()
+                .filter(|window| {
+                    state
+                        .filters
+                        .get(window)
+                        .is_none_or(|f| f.accepts(topic, session_id))
+                })
+                .collect()
+        };
+        targets
+            .iter()
+            .filter(|window| match self.sink.emit_to(window, event, &payload) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::debug!("[dispatch] {} not delivered to {}: {}", event, window, e);
+                    false
+                }
+            })
+            .count()
+    }
+
+    pub fn snapshot(&self) -> SharedState {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        SharedState {
+            current_session: state.current_session.clone(),
+            running_jobs: state.jobs.values().cloned().collect(),
+        }
+    }
+
+    /// Switch the current session and tell every window
+    pub fn set_current_session(&self, session_id: Option<String>) {
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.current_session == session_id {
+                return;
+            }
+            state.current_session = session_id.clone();
+        }
+        self.dispatch(
+            Topic::Session,
+            session_id.as_deref(),
+            SESSION_CHANGED,
+            &SessionChanged {
+                session_id: session_id.clone(),
+            },
+        );
+    }
+
+    /// Record a job as running. Returns its id for [`Self::job_finished`].
+    pub fn job_started(&self, command: &str, origin: &str) -> String {
+        self.job_started_as(&uuid::Uuid::new_v4().to_string(), command, origin)
+    }
+
+    /// [`Self::job_started`] under a given id, such as the execution id that
+    /// `send_signal` takes
+    pub fn job_started_as(&self, id: &str, command: &str, origin: &str) -> String {
+        let job = JobInfo {
+            id: id.to_string(),
+            command: command.to_string(),
+            origin: origin.to_string(),
+            session_id: self
+                .state
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .current_session
+                .clone(),
+            started_at: Utc::now(),
+        };
+        let id = job.id.clone();
+        let session_id = job.session_id.clone();
+        let running = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.jobs.insert(id.clone(), job);
+            state.jobs.values().cloned().collect()
+        };
+        self.dispatch(
+            Topic::Jobs,
+            session_id.as_deref(),
+            JOBS_CHANGED,
+            &JobsChanged {
+                running,
+                finished: None,
+            },
+        );
+        id
+    }
+
+    pub fn job_finished(&self, id: &str, success: bool) {
+        let (job, running) = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(job) = state.jobs.remove(id) else {
+                return;
+            };
+            (job, state.jobs.values().cloned().collect())
+        };
+        let session_id = job.session_id.clone();
+        self.dispatch(
+            Topic::Jobs,
+            session_id.as_deref(),
+            JOBS_CHANGED,
+            &JobsChanged {
+                running,
+                finished: Some(FinishedJob { job, success }),
+            },
+        );
+        self.command_finished(success);
+    }
+
+    /// Count a finished command (a job, or one typed in a terminal) towards
+    /// focus detection
+    pub fn command_finished(&self, success: bool) {
+        if self.surfaces.record_command(Utc::now(), success) {
+            self.dispatch(
+                Topic::Suggestions,
+                None,
+                FOCUS_CHANGED,
+                &self.surfaces.status(),
+            );
+        }
+    }
+
+    /// Publish a new suggestion, unless the surfacing policy holds it back
+    pub fn suggestion_added(&self, suggestion: &crate::memory::Suggestion) {
+        if self.surfaces.offer(suggestion) == surfaces::Surfacing::Now {
+            self.dispatch(Topic::Suggestions, None, SUGGESTION_ADDED, suggestion);
+        }
+    }
+
+    /// End focus once commands have stopped and release the held suggestions.
+    /// Call periodically.
+    pub fn resume_suggestions(&self) -> Option<FocusSummary> {
+        let summary = self.surfaces.resume_if_idle(Utc::now())?;
+        self.dispatch(
+            Topic::Suggestions,
+            None,
+            FOCUS_CHANGED,
+            &self.surfaces.status(),
+        );
+        self.dispatch(Topic::Suggestions, None, SUGGESTIONS_RESUMED, &summary);
+        Some(summary)
+    }
+
+    pub fn focus_status(&self) -> FocusStatus {
+        self.surfaces.status()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,211 @@
+//! Suggestion surfacing policy.
+//!
+//! Decides whether a new suggestion reaches the windows now or waits. While
+//! the user is in a focused loop — commands arriving in quick succession with
+//! failures among them — non-critical suggestions are held back. Once
+//! commands stop for `resume_after_secs`, focus ends and the held suggestions
+//! are released together in one [`FocusSummary`].
+
+use crate::memory::Suggestion;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use ts_rs::TS;
+
+/// `[focus]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct FocusConfig {
+    pub enabled: bool,
+    /// Commands within `window_secs` needed to count as focused
+    pub min_commands: usize,
+    /// Failed commands within `window_secs` needed as well (0 = cadence alone)
+    pub min_failures: usize,
+    pub window_secs: u64,
+    /// Longest pause between those commands that still counts as rapid
+    pub max_gap_secs: u64,
+    /// Quiet time after the last command that ends focus
+    pub resume_after_secs: u64,
+    /// Suggestion priorities surfaced even during focus
+    pub critical_priorities: Vec<String>,
+    /// Suggestion types surfaced even during focus
+    pub critical_types: Vec<String>,
+}
+
+impl Default for FocusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_commands: 5,
+            min_failures: 1,
+            window_secs: 180,
+            max_gap_secs: 60,
+            resume_after_secs: 120,
+            critical_priorities: vec!["high".to_string()],
+            critical_types: vec!["warning".to_string()],
+        }
+    }
+}
+
+/// Command cadence over the detection window
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ActivityStats {
+    pub commands: usize,
+    pub failures: usize,
+    pub commands_per_minute: f64,
+    /// Longest pause between consecutive commands in the window
+    pub longest_gap_secs: i64,
+}
+
+/// `focus_status` result
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FocusStatus {
+    pub enabled: bool,
+    pub focused: bool,
+    pub since: Option<DateTime<Utc>>,
+    pub stats: ActivityStats,
+    pub deferred: usize,
+}
+
+/// Suggestions held back during a focus period, released when it ends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSummary {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub commands: usize,
+    pub failures: usize,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// What to do with an offered suggestion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surfacing {
+    Now,
+    Deferred,
+}
+
+#[derive(Default)]
+struct PolicyState {
+    /// (finished at, succeeded) of recent commands, oldest first
+    recent: VecDeque<(DateTime<Utc>, bool)>,
+    focused_since: Option<DateTime<Utc>>,
+    focus_commands: usize,
+    focus_failures: usize,
+    deferred: Vec<Suggestion>,
+}
+
+pub struct SurfacePolicy {
+    config: FocusConfig,
+    state: Mutex<PolicyState>,
+}
+
+impl SurfacePolicy {
+    pub fn new(config: FocusConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(PolicyState::default()),
+        }
+    }
+
+    fn stats(&self, state: &PolicyState) -> ActivityStats {
+        let commands = state.recent.len();
+        let failures = state.recent.iter().filter(|(_, ok)| !ok).count();
+        let longest_gap_secs = state
+            .recent
+            .iter()
+            .zip(state.recent.iter().skip(1))
+            .map(|((a, _), (b, _))| (*b - *a).num_seconds())
+            .max()
+            .unwrap_or(0);
+        ActivityStats {
+            commands,
+            failures,
+            commands_per_minute: commands as f64 * 60.0 / self.config.window_secs.max(1) as f64,
+            longest_gap_secs,
+        }
+    }
+
+    /// Record a finished command. Returns `true` when it starts a focus period.
+    pub fn record_command(&self, at: DateTime<Utc>, success: bool) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let window_start = at - Duration::seconds(self.config.window_secs as i64);
+        state.recent.push_back((at, success));
+        while state.recent.front().is_some_and(|(t, _)| *t < window_start) {
+            state.recent.pop_front();
+        }
+
+        if state.focused_since.is_some() {
+            state.focus_commands += 1;
+            state.focus_failures += usize::from(!success);
+            return false;
+        }
+        let stats = self.stats(&state);
+        let focused = stats.commands >= self.config.min_commands
+            && stats.failures >= self.config.min_failures
+            && stats.longest_gap_secs <= self.config.max_gap_secs as i64;
+        if focused {
+            state.focused_since = state.recent.front().map(|(t, _)| *t);
+            state.focus_commands = stats.commands;
+            state.focus_failures = stats.failures;
+        }
+        focused
+    }
+
+    fn is_critical(&self, suggestion: &Suggestion) -> bool {
+        self.config
+            .critical_priorities
+            .contains(&suggestion.priority)
+            || self
+                .config
+                .critical_types
+                .contains(&suggestion.suggestion_type)
+    }
+
+    /// Surface `suggestion` now, or hold it until focus ends
+    pub fn offer(&self, suggestion: &Suggestion) -> Surfacing {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.focused_since.is_none() || self.is_critical(suggestion) {
+            return Surfacing::Now;
+        }
+        state.deferred.push(suggestion.clone());
+        Surfacing::Deferred
+    }
+
+    /// End focus if commands have stopped, returning what was held back
+    pub fn resume_if_idle(&self, now: DateTime<Utc>) -> Option<FocusSummary> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let started_at = state.focused_since?;
+        let last = state.recent.back().map(|(t, _)| *t).unwrap_or(started_at);
+        if now - last < Duration::seconds(self.config.resume_after_secs as i64) {
+            return None;
+        }
+        let summary = FocusSummary {
+            started_at,
+            ended_at: last,
+            commands: state.focus_commands,
+            failures: state.focus_failures,
+            suggestions: std::mem::take(&mut state.deferred),
+        };
+        state.focused_since = None;
+        state.recent.clear();
+        Some(summary)
+    }
+
+    pub fn status(&self) -> FocusStatus {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        FocusStatus {
+            enabled: self.config.enabled,
+            focused: state.focused_since.is_some(),
+            since: state.focused_since,
+            stats: self.stats(&state),
+            deferred: state.deferred.len(),
+        }
+    }
+}
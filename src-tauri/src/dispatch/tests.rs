@@ -0,0 +1,556 @@
+use super::*;
+
+/// Records deliveries instead of emitting them
+#[derive(Default)]
+struct RecordingSink {
+    windows: Vec<String>,
+    sent: Mutex<Vec<(String, String, Value)>>,
+}
+
+impl EventSink for RecordingSink {
+    fn windows(&self) -> Vec<String> {
+        self.windows.clone()
+    }
+
+    fn emit_to(&self, window: &str, event: &str, payload: &Value) -> Result<()> {
+        self.sent
+            .lock()
+            .unwrap()
+            .push((window.to_string(), event.to_string(), payload.clone()));
+        Ok(())
+    }
+}
+
+fn delivered(sink: &RecordingSink, event: &str) -> Vec<String> {
+    sink.sent
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, name, _)| name == event)
+        .map(|(window, _, _)| window.clone())
+        .collect()
+}
+
+#[test]
+fn broadcasts_to_all_windows_and_honours_filters() {
+    let sink = Arc::new(RecordingSink {
+        windows: vec!["main".into(), "notes".into(), "jobs".into()],
+        ..Default::default()
+    });
+    let dispatcher = WindowDispatcher::new(Arc::clone(&sink) as Arc<dyn EventSink>);
+    dispatcher.subscribe(
+        "notes",
+        WindowFilter {
+            topics: vec![Topic::Session],
+            session_ids: vec!["s1".into()],
+        },
+    );
+    dispatcher.subscribe(
+        "jobs",
+        WindowFilter {
+            topics: vec![Topic::Jobs],
+            ..Default::default()
+        },
+    );
+
+    dispatcher.set_current_session(Some("s2".into()));
+    assert_eq!(delivered(&sink, SESSION_CHANGED), vec!["main"]);
+
+    dispatcher.set_current_session(Some("s1".into()));
+    assert_eq!(
+        delivered(&sink, SESSION_CHANGED),
+        vec!["main", "main", "notes"]
+    );
+
+    // Jobs started in s1 reach the jobs window; the notes window only wants sessions
+    let id = dispatcher.job_started("make", "user");
+    assert_eq!(delivered(&sink, JOBS_CHANGED), vec!["main", "jobs"]);
+    assert_eq!(dispatcher.snapshot().running_jobs.len(), 1);
+    dispatcher.job_finished(&id, true);
+    assert!(dispatcher.snapshot().running_jobs.is_empty());
+    assert_eq!(delivered(&sink, JOBS_CHANGED).len(), 4);
+
+    // A closed window's filter is dropped; a reopened label gets everything
+    dispatcher.unsubscribe("jobs");
+    dispatcher.dispatch(Topic::App, None, "upgrade-report", &serde_json::json!({}));
+    assert_eq!(delivered(&sink, "upgrade-report"), vec!["main", "jobs"]);
+}
+
+#[test]
+fn focus_defers_non_critical_suggestions_until_commands_stop() {
+    use surfaces::{SurfacePolicy, Surfacing};
+
+    let policy = SurfacePolicy::new(FocusConfig::default());
+    let suggestion = |priority: &str| {
+        crate::memory::Suggestion::new(
+            "tip".into(),
+            priority.into(),
+            0.5,
+            "Try watch mode".into(),
+            String::new(),
+        )
+    };
+    let start = Utc::now();
+    let at = |secs: i64| start + chrono::Duration::seconds(secs);
+
+    // Slow cadence never counts as focus
+    for i in 0..5 {
+        assert!(!policy.record_command(at(i * 90), false));
+    }
+    assert_eq!(policy.offer(&suggestion("low")), Surfacing::Now);
+
+    // Five quick commands with a failure among them start a focus period
+    let base = 1000;
+    let results = [true, false, true, false, false];
+    let entered: Vec<bool> = results
+        .iter()
+        .enumerate()
+        .map(|(i, ok)| policy.record_command(at(base + i as i64 * 20), *ok))
+        .collect();
+    assert_eq!(entered, vec![false, false, false, false, true]);
+    assert_eq!(policy.offer(&suggestion("low")), Surfacing::Deferred);
+    assert_eq!(policy.offer(&suggestion("high")), Surfacing::Now);
+    assert_eq!(policy.status().deferred, 1);
+
+    // Still typing: focus holds; quiet long enough: the batch comes out
+    assert!(policy.resume_if_idle(at(base + 100)).is_none());
+    let summary = policy.resume_if_idle(at(base + 300)).unwrap();
+    assert_eq!(summary.suggestions.len(), 1);
+    assert_eq!((summary.commands, summary.failures), (5, 3));
+    assert!(!policy.status().focused);
+    assert_eq!(policy.offer(&suggestion("low")), Surfacing::Now);
+}
+
+/// Whether `actual` is `expected` with at most fields added to its objects;
+/// where they first differ otherwise
+fn compatible(expected: &Value, actual: &Value, path: &str) -> Result<(), String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = format!("{}.{}", path, key);
+                match actual.get(key) {
+                    Some(actual) => compatible(expected, actual, &path)?,
+                    None => return Err(format!("{} was removed", path)),
+                }
+            }
+            Ok(())
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            expected
+                .iter()
+                .zip(actual)
+                .enumerate()
+                .try_for_each(|(i, (e, a))| compatible(e, a, &format!("{}[{}]", path, i)))
+        }
+        _ if expected == actual => Ok(()),
+        _ => Err(format!("{} went from {} to {}", path, expected, actual)),
+    }
+}
+
+/// Payloads built the way the backend builds them, in the order of each
+/// event's examples
+fn sample_payloads() -> HashMap<&'static str, Vec<Value>> {
+    use crate::execution::canvas::{NodeRun, NodeRunStatus};
+    use crate::execution::debug::{DebugEventKind, PauseReason};
+    use crate::execution::{
+        CanvasRunReport, DebugEvent, LogEvent, LogEventKind, OutputStream, PauseState, PtyEvent,
+        PtyEventKind, StyledSpan, TextEncoding, WatchEvent, WatchEventKind,
+    };
+    use crate::memory::ansi::{Color, Style};
+
+    fn json(payload: &impl Serialize) -> Value {
+        serde_json::to_value(payload).unwrap()
+    }
+
+    let at = DateTime::parse_from_rfc3339("2026-03-01T09:30:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let job = JobInfo {
+        id: "j1".into(),
+        command: "cargo test".into(),
+        origin: "user".into(),
+        session_id: Some("s1".into()),
+        started_at: at,
+    };
+    let suggestion = crate::memory::Suggestion {
+        id: "g1".into(),
+        suggestion_type: "command".into(),
+        priority: "high".into(),
+        rank: 0.8,
+        title: "Re-run the failed test".into(),
+        description: "parse::empty failed".into(),
+        command: Some("cargo".into()),
+        args: Some(vec!["test".into(), "parse::empty".into()]),
+        context: serde_json::json!({ "error_id": "e1" }),
+        created_at: at,
+        dismissed: false,
+        applied: false,
+    };
+    let watch = |kind| WatchEvent {
+        watch_id: "w1".into(),
+        node_id: Some("n1".into()),
+        run: 1,
+        kind,
+    };
+    let log = |kind| LogEvent {
+        follow_id: "f1".into(),
+        node_id: None,
+        command_id: "c1".into(),
+        kind,
+    };
+    let debug = |kind| DebugEvent {
+        session_id: "d1".into(),
+        canvas_id: "canvas".into(),
+        kind,
+    };
+    let output = |chunk_index, stream_type, text: &str, encoding, spans| CommandOutputEvent {
+        run_id: "r1".into(),
+        chunk_index,
+        stream_type,
+        text: text.into(),
+        encoding,
+        spans,
+    };
+    let pty = |kind| PtyEvent {
+        session_id: "p1".into(),
+        kind,
+    };
+
+    HashMap::from([
+        (
+            SESSION_CHANGED,
+            vec![
+                json(&SessionChanged {
+                    session_id: Some("s1".into()),
+                }),
+                json(&SessionChanged { session_id: None }),
+            ],
+        ),
+        (
+            JOBS_CHANGED,
+            vec![
+                json(&JobsChanged {
+                    running: vec![job.clone()],
+                    finished: None,
+                }),
+                json(&JobsChanged {
+                    running: vec![],
+                    finished: Some(FinishedJob {
+                        job,
+                        success: false,
+                    }),
+                }),
+            ],
+        ),
+        (
+            WATCH_EVENT,
+            vec![
+                json(&watch(WatchEventKind::Started {
+                    changed: vec!["src/lib.rs".into()],
+                })),
+                json(&watch(WatchEventKind::Passed { duration_ms: 1200 })),
+                json(&watch(WatchEventKind::Failed {
+                    exit_code: Some(101),
+                    duration_ms: 1500,
+                    summary: "test failed".into(),
+                })),
+                json(&watch(WatchEventKind::Cancelled)),
+                json(&watch(WatchEventKind::Suspended { slept_ms: 60000 })),
+                json(&watch(WatchEventKind::Refused {
+                    error: crate::i18n::UserMessage::new("command-cancelled"),
+                })),
+                json(&watch(WatchEventKind::Stopped)),
+            ],
+        ),
+        (
+            LOG_EVENT,
+            vec![
+                json(&log(LogEventKind::Error {
+                    severity: "high".into(),
+                    message: "connection refused".into(),
+                })),
+                json(&log(LogEventKind::Ended {
+                    exit_code: Some(0),
+                    stopped: true,
+                })),
+            ],
+        ),
+        (
+            CANVAS_DEBUG_EVENT,
+            vec![
+                json(&debug(DebugEventKind::Paused {
+                    state: Box::new(PauseState {
+                        node_id: "n1".into(),
+                        label: "Build".into(),
+                        reason: PauseReason::Breakpoint,
+                        resolved: None,
+                        error: None,
+                        upstream_blocked: false,
+                        inputs: vec![],
+                        completed: vec![],
+                    }),
+                })),
+                json(&debug(DebugEventKind::NodeFinished {
+                    run: NodeRun {
+                        node_id: "n1".into(),
+                        label: "Build".into(),
+                        status: NodeRunStatus::Skipped,
+                        outcome: None,
+                        error: None,
+                        assertions: vec![],
+                    },
+                })),
+                json(&debug(DebugEventKind::Finished {
+                    report: CanvasRunReport {
+                        canvas_id: "canvas".into(),
+                        success: true,
+                        nodes: vec![],
+                    },
+                    stopped: false,
+                })),
+            ],
+        ),
+        (
+            COMMAND_OUTPUT,
+            vec![
+                json(&output(
+                    0,
+                    OutputStream::Stdout,
+                    "Compiling runebook\n",
+                    TextEncoding::Utf8,
+                    vec![],
+                )),
+                json(&output(
+                    1,
+                    OutputStream::Stderr,
+                    "AAEC",
+                    TextEncoding::Base64,
+                    vec![],
+                )),
+                json(&output(
+                    2,
+                    OutputStream::Stdout,
+                    "ok",
+                    TextEncoding::Utf8,
+                    vec![StyledSpan {
+                        text: "ok".into(),
+                        style: Style {
+                            fg: Some(Color::Indexed(2)),
+                            bold: true,
+                            ..Default::default()
+                        },
+                    }],
+                )),
+            ],
+        ),
+        (
+            COMMAND_OBJECTS,
+            vec![json(&CommandObjectsEvent {
+                run_id: "r1".into(),
+                objects: vec![serde_json::json!({ "Name": "pwsh", "Id": 4242 })],
+            })],
+        ),
+        (
+            PTY_EVENT,
+            vec![
+                json(&pty(PtyEventKind::Output {
+                    seq: 1,
+                    data: "$ ".into(),
+                })),
+                json(&pty(PtyEventKind::Exited { exit_code: Some(0) })),
+            ],
+        ),
+        (SUGGESTION_ADDED, vec![json(&suggestion)]),
+        (
+            FOCUS_CHANGED,
+            vec![json(&FocusStatus {
+                enabled: true,
+                focused: true,
+                since: Some(at),
+                stats: surfaces::ActivityStats {
+                    commands: 6,
+                    failures: 2,
+                    commands_per_minute: 2.5,
+                    longest_gap_secs: 40,
+                },
+                deferred: 1,
+            })],
+        ),
+        (
+            SUGGESTIONS_RESUMED,
+            vec![json(&FocusSummary {
+                started_at: at,
+                ended_at: at,
+                commands: 6,
+                failures: 2,
+                suggestions: vec![suggestion],
+            })],
+        ),
+        (
+            ALERT_FIRED,
+            vec![json(&crate::alerts::Alert {
+                rule: "build-broken".into(),
+                fired_at: at,
+                summary: "cargo build failed 3 times".into(),
+                command_id: Some("c1".into()),
+                session_id: Some("s1".into()),
+                fingerprint: None,
+            })],
+        ),
+        (
+            VIEW_UPDATED,
+            vec![json(&crate::memory::views::ViewChange {
+                view_id: "v1".into(),
+                view_name: "Failures".into(),
+                new_matches: vec![crate::memory::views::ViewMatch {
+                    record_type: crate::memory::views::ViewRecordType::Error,
+                    id: "e1".into(),
+                    timestamp: at,
+                    title: "exit code 101".into(),
+                    record: serde_json::json!({ "exit_code": 101 }),
+                }],
+            })],
+        ),
+        (
+            MEMORY_QUOTA,
+            vec![json(&crate::memory::QuotaEvent {
+                scope: "total".into(),
+                session_id: None,
+                limit_bytes: 1048576,
+                used_bytes: 1040000,
+                attempted_bytes: 16384,
+                policy: crate::memory::QuotaPolicy::DropOutputs,
+                action: "dropped".into(),
+                freed_bytes: 0,
+                timestamp: at,
+            })],
+        ),
+        (
+            ARCHIVE_PROGRESS,
+            vec![json(&ArchiveProgressEvent {
+                operation_id: "op1".into(),
+                progress: crate::archive::ArchiveProgress {
+                    operation: "create".into(),
+                    entries_done: 3,
+                    bytes_done: 4096,
+                    current_path: Some("notes/today.md".into()),
+                },
+            })],
+        ),
+        (
+            REINDEX_PROGRESS,
+            vec![json(&ReindexProgressEvent {
+                operation_id: "op1".into(),
+                progress: crate::memory::ReindexProgress {
+                    kind: crate::memory::IndexKind::Search,
+                    done: 500,
+                    total: 1200,
+                },
+            })],
+        ),
+        (
+            BACKUP_EXPORT_PROGRESS,
+            vec![json(&ExportProgressEvent {
+                operation_id: "op1".into(),
+                progress: crate::backup::ExportProgress {
+                    records: 500,
+                    total: 1200,
+                    bytes: 65536,
+                    parts: 0,
+                    elapsed_ms: 250,
+                    records_per_second: 2000.0,
+                    bytes_per_second: 262144.0,
+                },
+            })],
+        ),
+        (
+            PROFILE_SWITCHING,
+            vec![json(&ProfileSwitching {
+                from: "default".into(),
+                to: "work".into(),
+            })],
+        ),
+        (
+            UPGRADE_REPORT,
+            vec![json(&crate::upgrade::UpgradeReport {
+                kind: crate::upgrade::UpgradeKind::Upgrade,
+                from_version: Some("0.2.0".into()),
+                to_version: "0.3.0".into(),
+                channel: crate::upgrade::UpdateChannel::Stable,
+                previous_channel: None,
+                config: crate::config::migrate::ConfigMigrationReport {
+                    from_version: 1,
+                    to_version: 2,
+                    applied: vec![crate::config::migrate::AppliedConfigMigration {
+                        version: 2,
+                        description: "Move [output] settings".into(),
+                    }],
+                    backup: None,
+                    newer_than_supported: false,
+                },
+                store: crate::memory::migration::MigrationStatus {
+                    current_version: 1,
+                    target_version: 1,
+                    is_up_to_date: true,
+                    pending: vec![],
+                },
+                changes: vec![crate::upgrade::DataChange {
+                    area: crate::upgrade::DataArea::Config,
+                    summary: "Config updated to version 2".into(),
+                    action_required: false,
+                }],
+            })],
+        ),
+    ])
+}
+
+#[test]
+fn payloads_keep_the_shape_of_their_registered_version() {
+    let samples = sample_payloads();
+    assert_eq!(samples.len(), EVENTS.len());
+    for schema in EVENTS {
+        assert_eq!(
+            EVENTS.iter().filter(|s| s.name == schema.name).count(),
+            1,
+            "{} is registered twice",
+            schema.name
+        );
+        let examples: Vec<Value> = serde_json::from_str(schema.examples).unwrap();
+        let payloads = samples
+            .get(schema.name)
+            .unwrap_or_else(|| panic!("no sample payloads for {}", schema.name));
+        assert!(payloads.len() >= examples.len(), "{}", schema.name);
+        for (i, (example, payload)) in examples.iter().zip(payloads).enumerate() {
+            if let Err(change) = compatible(example, payload, "") {
+                panic!(
+                    "{} v{} example {}: {}; a payload may only gain fields within a version",
+                    schema.name, schema.version, i, change
+                );
+            }
+        }
+    }
+
+    // Additions pass; removals and changed values do not
+    let v1 = serde_json::json!({ "id": "a", "tags": [{ "n": 1 }] });
+    assert!(compatible(
+        &v1,
+        &serde_json::json!({ "id": "a", "tags": [{ "n": 1, "m": 2 }], "x": 0 }),
+        ""
+    )
+    .is_ok());
+    assert_eq!(
+        compatible(&v1, &serde_json::json!({ "tags": [{ "n": 1 }] }), ""),
+        Err(".id was removed".to_string())
+    );
+    assert!(compatible(
+        &v1,
+        &serde_json::json!({ "id": "a", "tags": [{ "n": "1" }] }),
+        ""
+    )
+    .is_err());
+
+    // Tooling gets the examples as JSON, not as a string
+    let listed = serde_json::to_value(events::schema(SESSION_CHANGED).unwrap()).unwrap();
+    assert_eq!(listed["examples"][0]["session_id"], "s1");
+    assert_eq!(listed["topic"], "session");
+}
@@ -0,0 +1,187 @@
+//! Expectations on a terminal node's result.
+//!
+//! A terminal node may list assertions under `expect`; once it has run, each
+//! one is checked against the outcome and the node passes only if all of
+//! them do. A node that fails an assertion counts as failed, so the nodes
+//! below it are skipped and the canvas run reports failure, which makes a
+//! canvas usable as an executable check:
+//!
+//! ```yaml
+//! expect:
+//!   - kind: exit_code
+//!     code: 0
+//!   - kind: stdout_json
+//!     path: $.status
+//!     equals: healthy
+//!   - kind: max_duration
+//!     ms: 2000
+//!   - kind: file_exists
+//!     path: dist/index.html
+//! ```
+//!
+//! Without an `exit_code` assertion a node must also exit with status 0, as
+//! it must without any assertions; with one, that assertion decides, so a
+//! node can expect a command to fail.
+
+use super::command::CommandOutcome;
+use crate::i18n::UserMessage;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::path::Path;
+use ts_rs::TS;
+
+/// One expectation on a command's result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[ts(export)]
+pub enum Assertion {
+    /// The command exits with `code`
+    ExitCode { code: i32 },
+    /// The regex `pattern` matches somewhere in stdout
+    StdoutMatches { pattern: String },
+    /// The regex `pattern` matches somewhere in stderr
+    StderrMatches { pattern: String },
+    /// stdout is JSON in which the JSONPath `path` selects something; with
+    /// `equals`, everything it selects equals that value
+    StdoutJson {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        equals: Option<Value>,
+    },
+    /// The command finishes within `ms` milliseconds
+    MaxDuration { ms: u64 },
+    /// `path` exists once the command has run; a relative path is taken
+    /// from the node's working directory
+    FileExists { path: String },
+}
+
+/// An assertion and how the run fared against it
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct AssertionResult {
+    pub assertion: Assertion,
+    pub passed: bool,
+    /// Why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub failure: Option<UserMessage>,
+}
+
+impl Assertion {
+    /// Why the assertion can never be checked: a pattern or path that does
+    /// not parse. `None` when it is well formed.
+    pub fn problem(&self) -> Option<String> {
+        match self {
+            Assertion::StdoutMatches { pattern } | Assertion::StderrMatches { pattern } => {
+                Regex::new(pattern).err().map(|e| e.to_string())
+            }
+            Assertion::StdoutJson { path, .. } => {
+                JsonPath::parse(path).err().map(|e| e.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Check the assertion against `outcome`, with relative file paths taken
+    /// from `cwd`
+    pub fn check(&self, outcome: &CommandOutcome, cwd: Option<&Path>) -> AssertionResult {
+        let failure = match self.failure(outcome, cwd) {
+            Ok(failure) => failure,
+            Err(error) => Some(UserMessage::new("assertion-invalid").with("error", error)),
+        };
+        AssertionResult {
+            assertion: self.clone(),
+            passed: failure.is_none(),
+            failure,
+        }
+    }
+
+    fn failure(
+        &self,
+        outcome: &CommandOutcome,
+        cwd: Option<&Path>,
+    ) -> Result<Option<UserMessage>, String> {
+        Ok(match self {
+            Assertion::ExitCode { code } => (outcome.exit_code != Some(*code)).then(|| {
+                UserMessage::new("assertion-exit-code")
+                    .with("expected", code)
+                    .with(
+                        "actual",
+                        outcome
+                            .exit_code
+                            .map_or("none".to_string(), |c| c.to_string()),
+                    )
+            }),
+            Assertion::StdoutMatches { pattern } => no_match(pattern, &outcome.stdout, "stdout")?,
+            Assertion::StderrMatches { pattern } => no_match(pattern, &outcome.stderr, "stderr")?,
+            Assertion::StdoutJson { path, equals } => {
+                let query = JsonPath::parse(path).map_err(|e| e.to_string())?;
+                let json: Value = match serde_json::from_str(&outcome.stdout) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        return Ok(Some(
+                            UserMessage::new("assertion-not-json").with("error", e),
+                        ))
+                    }
+                };
+                let selected = query.query(&json).all();
+                match (selected.as_slice(), equals) {
+                    ([], _) => Some(UserMessage::new("assertion-json-missing").with("path", path)),
+                    (found, Some(expected)) => {
+                        found.iter().find(|value| **value != expected).map(|value| {
+                            UserMessage::new("assertion-json-mismatch")
+                                .with("path", path)
+                                .with("expected", expected)
+                                .with("actual", value)
+                        })
+                    }
+                    (_, None) => None,
+                }
+            }
+            Assertion::MaxDuration { ms } => (outcome.duration_ms > *ms).then(|| {
+                UserMessage::new("assertion-too-slow")
+                    .with("max_ms", ms)
+                    .with("duration_ms", outcome.duration_ms)
+            }),
+            Assertion::FileExists { path } => {
+                let full = match cwd {
+                    Some(cwd) => cwd.join(path),
+                    None => Path::new(path).to_path_buf(),
+                };
+                (!full.exists())
+                    .then(|| UserMessage::new("assertion-file-missing").with("path", path))
+            }
+        })
+    }
+}
+
+/// A failure when `pattern` matches nowhere in `text`
+fn no_match(pattern: &str, text: &str, stream: &str) -> Result<Option<UserMessage>, String> {
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    Ok((!regex.is_match(text)).then(|| {
+        UserMessage::new("assertion-no-match")
+            .with("stream", stream)
+            .with("pattern", pattern)
+    }))
+}
+
+/// Check every assertion against `outcome`
+pub fn evaluate(
+    assertions: &[Assertion],
+    outcome: &CommandOutcome,
+    cwd: Option<&Path>,
+) -> Vec<AssertionResult> {
+    assertions.iter().map(|a| a.check(outcome, cwd)).collect()
+}
+
+/// Whether a run with `results` passes: every assertion held, and the
+/// command exited 0 unless an `exit_code` assertion expected otherwise
+pub fn passed(outcome: &CommandOutcome, results: &[AssertionResult]) -> bool {
+    let exit_checked = results
+        .iter()
+        .any(|r| matches!(r.assertion, Assertion::ExitCode { .. }));
+    (exit_checked || outcome.success) && results.iter().all(|r| r.passed)
+}
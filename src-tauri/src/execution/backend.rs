@@ -0,0 +1,218 @@
+//! How a command spec becomes a process, per platform.
+//!
+//! On Unix a command is started directly with its arguments. Windows has no
+//! argv: a process gets one command line, which each program splits itself,
+//! and programs such as `npm` are `.cmd` scripts that only a shell can run.
+//! Its adapters run commands through `cmd.exe` or PowerShell instead, quoting
+//! the program and its arguments so they arrive as given, metacharacters and
+//! all, and turn the CRLF line endings of their output into `\n`.
+//!
+//! [`Backend::Auto`] picks [`Backend::Cmd`] on Windows and
+//! [`Backend::Posix`] elsewhere; a node can name one explicitly. Runs in
+//! [`ExecutionMode::Pwsh`](super::ExecutionMode::Pwsh) are PowerShell
+//! scripts already and do not go through a backend.
+
+use super::command::CommandSpec;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Turns a [`CommandSpec`] into the process that runs it
+pub trait ExecutionBackend: Send + Sync {
+    /// The process running `spec.command` with `spec.args`; cwd, env and
+    /// stdio are set by the caller
+    fn command(&self, spec: &CommandSpec) -> tokio::process::Command;
+
+    /// Whether CRLF in the output is turned into `\n`
+    fn normalizes_crlf(&self) -> bool {
+        false
+    }
+}
+
+/// Which [`ExecutionBackend`] runs a command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum Backend {
+    /// [`Backend::Cmd`] on Windows, [`Backend::Posix`] elsewhere
+    #[default]
+    Auto,
+    /// The program is started directly with its arguments
+    Posix,
+    /// `cmd.exe /d /c`, which finds `.cmd` and `.bat` scripts on `PATH`
+    Cmd,
+    /// PowerShell's call operator, `& 'program' 'arg'`
+    Powershell,
+}
+
+impl Backend {
+    /// The backend [`Backend::Auto`] stands for on this platform
+    pub fn resolve(self) -> Backend {
+        match self {
+            Backend::Auto if cfg!(windows) => Backend::Cmd,
+            Backend::Auto => Backend::Posix,
+            backend => backend,
+        }
+    }
+
+    pub fn adapter(self) -> &'static dyn ExecutionBackend {
+        match self.resolve() {
+            Backend::Cmd => &CmdBackend,
+            Backend::Powershell => &PowershellBackend,
+            _ => &PosixBackend,
+        }
+    }
+}
+
+struct PosixBackend;
+
+impl ExecutionBackend for PosixBackend {
+    fn command(&self, spec: &CommandSpec) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(&spec.command);
+        cmd.args(&spec.args);
+        cmd
+    }
+}
+
+struct CmdBackend;
+
+impl ExecutionBackend for CmdBackend {
+    fn command(&self, spec: &CommandSpec) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new("cmd.exe");
+        cmd.args(["/d", "/c"]);
+        // Appended as is: Rust's own quoting would be undone by cmd's parsing
+        let line = cmd_line(spec);
+        #[cfg(windows)]
+        cmd.raw_arg(line);
+        #[cfg(not(windows))]
+        cmd.arg(line);
+        cmd
+    }
+
+    fn normalizes_crlf(&self) -> bool {
+        true
+    }
+}
+
+struct PowershellBackend;
+
+impl ExecutionBackend for PowershellBackend {
+    fn command(&self, spec: &CommandSpec) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(powershell_program());
+        cmd.args([
+            "-NoLogo",
+            "-NoProfile",
+            "-NonInteractive",
+            "-EncodedCommand",
+        ])
+        .arg(encode_command(&powershell_script(spec)));
+        cmd
+    }
+
+    fn normalizes_crlf(&self) -> bool {
+        true
+    }
+}
+
+/// `arg` quoted so the Microsoft C runtime's argv splitting gives it back
+pub fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote escape, and so does one more
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            c => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Before the closing quote they escape too
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// The command line `cmd.exe /c` runs for `spec`: each part quoted for argv
+/// splitting, then every character `cmd` would interpret escaped with `^`,
+/// so pipes, redirections and `%VAR%` in arguments stay literal
+pub fn cmd_line(spec: &CommandSpec) -> String {
+    // `cmd` takes `/` in a program path for a switch
+    let program = if spec.command.contains("://") {
+        spec.command.clone()
+    } else {
+        spec.command.replace('/', "\\")
+    };
+    let line = std::iter::once(program.as_str())
+        .chain(spec.args.iter().map(String::as_str))
+        .map(quote_windows_arg)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut escaped = String::with_capacity(line.len() * 2);
+    for c in line.chars() {
+        if matches!(c, '(' | ')' | '%' | '!' | '^' | '"' | '<' | '>' | '&' | '|') {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `text` as a PowerShell string literal, in which nothing is expanded
+pub fn quote_powershell(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// The script that calls `spec`'s program with its arguments, exiting with
+/// its exit code. With stdin, the script's input is piped into the program.
+pub fn powershell_script(spec: &CommandSpec) -> String {
+    let call = std::iter::once(spec.command.as_str())
+        .chain(spec.args.iter().map(String::as_str))
+        .map(quote_powershell)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let input = if spec.stdin.is_some() {
+        "$input | "
+    } else {
+        ""
+    };
+    format!(
+        "{}& {}\n$__runebookOk = $?\nif ($LASTEXITCODE) {{ exit $LASTEXITCODE }}\nif (-not $__runebookOk) {{ exit 1 }}\n",
+        input, call
+    )
+}
+
+/// `script` as `-EncodedCommand` takes it: base64 of its UTF-16LE, which no
+/// command line quoting can change
+pub(super) fn encode_command(script: &str) -> String {
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    base64::engine::general_purpose::STANDARD.encode(utf16)
+}
+
+/// `pwsh` when installed, else Windows PowerShell, which always is on Windows
+fn powershell_program() -> &'static str {
+    let installed = std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path)
+            .any(|dir| dir.join("pwsh").is_file() || dir.join("pwsh.exe").is_file())
+    });
+    if installed || !cfg!(windows) {
+        "pwsh"
+    } else {
+        "powershell"
+    }
+}
+
+/// `text` with CRLF line endings turned into `\n`
+pub fn normalize_crlf(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
@@ -0,0 +1,254 @@
+//! Command benchmarks.
+//!
+//! Runs a command repeatedly through the [`ExecutionService`], hyperfine-style:
+//! warmup runs first, then timed runs. The command is admitted once, so policy
+//! and auditing apply to the benchmark as a whole. Statistics (mean, median,
+//! standard deviation, outliers) are computed here so every surface reports the
+//! same numbers, and results with the same signature can be compared.
+
+use super::command::{run_command, CommandOutcome, CommandSpec};
+use super::resolve::ExecutionRequest;
+use super::service::{failure_message, ExecutionService};
+use crate::i18n::UserMessage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+/// Most timed runs one benchmark may ask for
+pub const MAX_RUNS: u32 = 1000;
+
+/// Modified z-score above which a run counts as an outlier (Iglewicz and Hoaglin)
+const OUTLIER_Z: f64 = 3.5;
+
+fn default_runs() -> u32 {
+    10
+}
+
+fn default_warmup() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BenchmarkSpec {
+    #[serde(flatten)]
+    pub request: ExecutionRequest,
+    /// Timed runs
+    #[serde(default = "default_runs")]
+    pub runs: u32,
+    /// Untimed runs before the timed ones, to warm caches
+    #[serde(default = "default_warmup")]
+    pub warmup: u32,
+    /// Keep going when a run fails instead of stopping the benchmark
+    #[serde(default)]
+    pub ignore_failures: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BenchmarkStats {
+    pub mean_ms: f64,
+    pub median_ms: f64,
+    /// Sample standard deviation; 0 for a single run
+    pub stddev_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    /// Indices into `times_ms` of runs far from the median
+    pub outliers: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum BenchmarkVerdict {
+    Faster,
+    Slower,
+    /// The means differ by less than the runs' combined standard deviation
+    Unchanged,
+}
+
+/// How a benchmark compares with the previous one of the same signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BenchmarkComparison {
+    pub previous_id: String,
+    pub previous_started_at: DateTime<Utc>,
+    pub previous_mean_ms: f64,
+    /// This mean over the previous one; below 1 is faster
+    pub ratio: f64,
+    pub verdict: BenchmarkVerdict,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BenchmarkResult {
+    pub id: String,
+    /// Hash of the resolved command line and working directory; results with
+    /// the same signature are comparable
+    pub signature: String,
+    pub command_line: String,
+    pub cwd: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub warmup: u32,
+    /// Wall-clock time of each timed run, in run order
+    pub times_ms: Vec<f64>,
+    /// Timed runs that exited unsuccessfully (only with `ignore_failures`)
+    pub failures: u32,
+    pub stats: BenchmarkStats,
+    #[serde(default)]
+    pub comparison: Option<BenchmarkComparison>,
+}
+
+impl BenchmarkResult {
+    /// Set [`Self::comparison`] against an earlier result of the same signature
+    pub fn compare_to(&mut self, previous: &BenchmarkResult) {
+        let (current, before) = (&self.stats, &previous.stats);
+        let verdict =
+            if (current.mean_ms - before.mean_ms).abs() <= current.stddev_ms + before.stddev_ms {
+                BenchmarkVerdict::Unchanged
+            } else if current.mean_ms < before.mean_ms {
+                BenchmarkVerdict::Faster
+            } else {
+                BenchmarkVerdict::Slower
+            };
+        self.comparison = Some(BenchmarkComparison {
+            previous_id: previous.id.clone(),
+            previous_started_at: previous.started_at,
+            previous_mean_ms: before.mean_ms,
+            ratio: if before.mean_ms > 0.0 {
+                current.mean_ms / before.mean_ms
+            } else {
+                1.0
+            },
+            verdict,
+        });
+    }
+}
+
+/// Signature of a resolved command: its program, arguments and working
+/// directory. The environment is left out so secrets do not split history.
+pub fn benchmark_signature(command: &CommandSpec) -> String {
+    let mut hasher = Sha256::new();
+    for part in std::iter::once(&command.command).chain(&command.args) {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(command.cwd.as_deref().unwrap_or_default().as_bytes());
+    hex::encode(&hasher.finalize()[..8])
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Statistics of `times_ms`, which must not be empty
+pub fn benchmark_stats(times_ms: &[f64]) -> BenchmarkStats {
+    let n = times_ms.len() as f64;
+    let mut sorted = times_ms.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mean = times_ms.iter().sum::<f64>() / n;
+    let median_ms = median(&sorted);
+    let stddev_ms = if times_ms.len() > 1 {
+        (times_ms.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+    // Modified z-scores use the median absolute deviation, which a few slow
+    // runs cannot inflate the way they inflate the standard deviation
+    let mut deviations: Vec<f64> = times_ms.iter().map(|t| (t - median_ms).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    let mad = median(&deviations);
+    let outliers = if mad > 0.0 {
+        times_ms
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| 0.6745 * (*t - median_ms).abs() / mad > OUTLIER_Z)
+            .map(|(i, _)| i)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    BenchmarkStats {
+        mean_ms: mean,
+        median_ms,
+        stddev_ms,
+        min_ms: sorted[0],
+        max_ms: sorted[sorted.len() - 1],
+        outliers,
+    }
+}
+
+/// Benchmark `spec` through `service`. Destructive commands under the confirm
+/// policy need `confirmation_token`, as with a single run.
+pub async fn run_benchmark(
+    service: &ExecutionService,
+    spec: &BenchmarkSpec,
+    confirmation_token: Option<&str>,
+    actor: &str,
+) -> Result<BenchmarkResult, UserMessage> {
+    let command = service.admit(&spec.request, confirmation_token, actor)?;
+    benchmark_with(spec, &command, || async {
+        let started = Instant::now();
+        let outcome = run_command(&command)
+            .await
+            .map_err(UserMessage::wrap("command-run-failed"))?;
+        Ok((outcome, started.elapsed()))
+    })
+    .await
+}
+
+/// [`run_benchmark`] for an admitted `command`, with `run` standing in for
+/// running it once and timing it
+pub async fn benchmark_with<F, Fut>(
+    spec: &BenchmarkSpec,
+    command: &CommandSpec,
+    mut run: F,
+) -> Result<BenchmarkResult, UserMessage>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(CommandOutcome, Duration), UserMessage>>,
+{
+    if spec.runs == 0 || spec.runs > MAX_RUNS {
+        return Err(UserMessage::new("benchmark-invalid")
+            .with("error", format!("runs must be between 1 and {}", MAX_RUNS)));
+    }
+    let started_at = Utc::now();
+    for _ in 0..spec.warmup {
+        let (outcome, _) = run().await?;
+        if !outcome.success && !spec.ignore_failures {
+            return Err(failure_message(&outcome));
+        }
+    }
+    let mut times_ms = Vec::with_capacity(spec.runs as usize);
+    let mut failures = 0;
+    for _ in 0..spec.runs {
+        let (outcome, elapsed) = run().await?;
+        if !outcome.success {
+            if !spec.ignore_failures {
+                return Err(failure_message(&outcome));
+            }
+            failures += 1;
+        }
+        times_ms.push(elapsed.as_secs_f64() * 1000.0);
+    }
+    Ok(BenchmarkResult {
+        id: uuid::Uuid::new_v4().to_string(),
+        signature: benchmark_signature(command),
+        command_line: command.command_line(),
+        cwd: command.cwd.clone(),
+        started_at,
+        warmup: spec.warmup,
+        stats: benchmark_stats(&times_ms),
+        times_ms,
+        failures,
+        comparison: None,
+    })
+}
@@ -0,0 +1,417 @@
+//! Headless canvas runs.
+//!
+//! Runs the terminal nodes of a canvas document (the JSON form of
+//! `src/lib/types/canvas.ts`) in dependency order through the shared
+//! [`ExecutionService`]. Nodes downstream of a failed or refused terminal are
+//! skipped; non-terminal nodes only contribute ordering. A terminal that
+//! fails one of its [assertions](super::assertions) counts as failed.
+
+use super::assertions::{self, Assertion, AssertionResult};
+use super::backend::Backend;
+use super::command::{CommandOutcome, ExecutionMode};
+use super::resolve::{substitute, ExecutionRequest};
+use super::service::ExecutionService;
+use super::target::ExecutionTarget;
+use crate::i18n::UserMessage;
+use crate::memory::AnsiMode;
+use crate::telemetry::Span;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use ts_rs::TS;
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CanvasDocument {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub nodes: Vec<CanvasNode>,
+    #[serde(default)]
+    pub connections: Vec<CanvasConnection>,
+}
+
+/// The parts of a canvas node runs and lint need; other node fields are ignored
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CanvasNode {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub mode: ExecutionMode,
+    #[serde(default)]
+    pub ansi: AnsiMode,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub target: ExecutionTarget,
+    #[serde(default)]
+    pub inputs: Vec<CanvasPort>,
+    /// What a terminal's result must satisfy for the node to pass
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<Assertion>>", optional)]
+    pub expect: Vec<Assertion>,
+}
+
+/// An input or output port of a node
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CanvasPort {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+impl CanvasNode {
+    /// The command a terminal node runs; `None` for other nodes
+    pub fn request(&self, variables: &HashMap<String, String>) -> Option<ExecutionRequest> {
+        let command = self
+            .command
+            .as_ref()
+            .filter(|_| self.node_type == "terminal")?;
+        Some(ExecutionRequest {
+            command: command.clone(),
+            args: self.args.clone(),
+            env: self.env.clone(),
+            cwd: self.cwd.clone(),
+            profile: None,
+            variables: variables.clone(),
+            stdin: None,
+            mode: self.mode,
+            limits: Default::default(),
+            ansi: self.ansi,
+            backend: self.backend,
+            target: self.target.clone(),
+            priority: 0,
+        })
+    }
+
+    /// Check the node's assertions against `outcome`. Relative paths are taken
+    /// from its working directory, with placeholders filled in from `variables`.
+    pub fn check(
+        &self,
+        outcome: &CommandOutcome,
+        variables: &HashMap<String, String>,
+    ) -> Vec<AssertionResult> {
+        let variables: BTreeMap<String, String> = variables
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let mut unresolved = Vec::new();
+        let cwd = self
+            .cwd
+            .as_deref()
+            .map(|cwd| PathBuf::from(substitute(cwd, &variables, &mut unresolved)));
+        let expect: Vec<Assertion> = self
+            .expect
+            .iter()
+            .map(|assertion| match assertion {
+                Assertion::FileExists { path } => Assertion::FileExists {
+                    path: substitute(path, &variables, &mut unresolved),
+                },
+                other => other.clone(),
+            })
+            .collect();
+        assertions::evaluate(&expect, outcome, cwd.as_deref())
+    }
+}
+
+impl CanvasDocument {
+    /// The commands a run would execute, in node order
+    pub fn terminal_requests(&self, variables: &HashMap<String, String>) -> Vec<ExecutionRequest> {
+        self.nodes
+            .iter()
+            .filter_map(|n| n.request(variables))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CanvasConnection {
+    pub from: String,
+    pub to: String,
+    /// Input port of `to` the connection feeds
+    #[serde(default, rename = "toPort", skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub to_port: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum NodeRunStatus {
+    Succeeded,
+    Failed,
+    /// Refused before running (needs confirmation, blocked, unresolved template)
+    Refused,
+    /// Not run because an upstream terminal did not succeed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct NodeRun {
+    pub node_id: String,
+    pub label: String,
+    pub status: NodeRunStatus,
+    pub outcome: Option<CommandOutcome>,
+    pub error: Option<UserMessage>,
+    /// The node's assertions, checked against `outcome`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<AssertionResult>>", optional)]
+    pub assertions: Vec<AssertionResult>,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct CanvasRunReport {
+    pub canvas_id: String,
+    pub success: bool,
+    pub nodes: Vec<NodeRun>,
+}
+
+/// Node ids in dependency order; errors on cycles and dangling connections
+pub fn execution_order(canvas: &CanvasDocument) -> Result<Vec<String>, UserMessage> {
+    let mut indegree: HashMap<&str, usize> =
+        canvas.nodes.iter().map(|n| (n.id.as_str(), 0)).collect();
+    let mut downstream: HashMap<&str, Vec<&str>> = HashMap::new();
+    for connection in &canvas.connections {
+        for end in [&connection.from, &connection.to] {
+            if !indegree.contains_key(end.as_str()) {
+                return Err(UserMessage::new("canvas-invalid").with(
+                    "error",
+                    format!("connection references unknown node {}", end),
+                ));
+            }
+        }
+        downstream
+            .entry(connection.from.as_str())
+            .or_default()
+            .push(connection.to.as_str());
+        *indegree.get_mut(connection.to.as_str()).expect("checked") += 1;
+    }
+
+    // Kahn's algorithm, seeded in document order so the result is stable
+    let mut ready: VecDeque<&str> = canvas
+        .nodes
+        .iter()
+        .map(|n| n.id.as_str())
+        .filter(|id| indegree[id] == 0)
+        .collect();
+    let mut order = Vec::with_capacity(canvas.nodes.len());
+    while let Some(id) = ready.pop_front() {
+        order.push(id.to_string());
+        for next in downstream.get(id).into_iter().flatten() {
+            let degree = indegree.get_mut(next).expect("checked");
+            *degree -= 1;
+            if *degree == 0 {
+                ready.push_back(next);
+            }
+        }
+    }
+    if order.len() != canvas.nodes.len() {
+        return Err(UserMessage::new("canvas-invalid").with("error", "connections form a cycle"));
+    }
+    Ok(order)
+}
+
+/// Runs a canvas one terminal node at a time, in dependency order. Used by
+/// [`run_canvas`], and by the debugger to pause between nodes.
+pub struct CanvasStepper<'a> {
+    order: Vec<String>,
+    next: usize,
+    nodes: HashMap<&'a str, &'a CanvasNode>,
+    upstream: HashMap<&'a str, Vec<&'a str>>,
+    /// Nodes that must not run: a terminal that did not succeed, and everything below it
+    blocked: HashSet<&'a str>,
+    runs: Vec<NodeRun>,
+    canvas_id: String,
+}
+
+impl<'a> CanvasStepper<'a> {
+    pub fn new(canvas: &'a CanvasDocument) -> Result<Self, UserMessage> {
+        let order = execution_order(canvas)?;
+        let mut upstream: HashMap<&str, Vec<&str>> = HashMap::new();
+        for connection in &canvas.connections {
+            upstream
+                .entry(connection.to.as_str())
+                .or_default()
+                .push(connection.from.as_str());
+        }
+        Ok(Self {
+            order,
+            next: 0,
+            nodes: canvas.nodes.iter().map(|n| (n.id.as_str(), n)).collect(),
+            upstream,
+            blocked: HashSet::new(),
+            runs: Vec::new(),
+            canvas_id: canvas.id.clone(),
+        })
+    }
+
+    /// The next terminal node to run, passing over other nodes; `None` when done
+    pub fn peek(&mut self, variables: &HashMap<String, String>) -> Option<&'a CanvasNode> {
+        while let Some(id) = self.order.get(self.next) {
+            let node = self.nodes[id.as_str()];
+            if self.upstream_blocked(&node.id) {
+                self.blocked.insert(&node.id);
+            }
+            if node.request(variables).is_some() {
+                return Some(node);
+            }
+            self.next += 1;
+        }
+        None
+    }
+
+    /// Whether a node will be skipped because something above it did not succeed
+    pub fn upstream_blocked(&self, id: &str) -> bool {
+        self.upstream
+            .get(id)
+            .is_some_and(|from| from.iter().any(|f| self.blocked.contains(f)))
+    }
+
+    /// Run (or skip) the next terminal node; `None` when done
+    pub async fn step(
+        &mut self,
+        service: &ExecutionService,
+        variables: &HashMap<String, String>,
+        actor: &str,
+    ) -> Option<&NodeRun> {
+        self.step_with(variables, |_, request| async move {
+            service.execute(&request, None, actor).await
+        })
+        .await
+    }
+
+    /// [`Self::step`], with `execute` standing in for the execution service
+    pub async fn step_with<F, Fut>(
+        &mut self,
+        variables: &HashMap<String, String>,
+        execute: F,
+    ) -> Option<&NodeRun>
+    where
+        F: FnOnce(&'a CanvasNode, ExecutionRequest) -> Fut,
+        Fut: Future<Output = Result<CommandOutcome, UserMessage>>,
+    {
+        let node = self.peek(variables)?;
+        let request = node.request(variables)?;
+        self.next += 1;
+
+        let mut run = NodeRun {
+            node_id: node.id.clone(),
+            label: node.label.clone(),
+            status: NodeRunStatus::Skipped,
+            outcome: None,
+            error: None,
+            assertions: Vec::new(),
+        };
+        if !self.upstream_blocked(&node.id) {
+            match execute(node, request).await {
+                Ok(outcome) => {
+                    run.assertions = node.check(&outcome, variables);
+                    run.status = if assertions::passed(&outcome, &run.assertions) {
+                        NodeRunStatus::Succeeded
+                    } else {
+                        NodeRunStatus::Failed
+                    };
+                    run.outcome = Some(outcome);
+                }
+                Err(message) => {
+                    run.status = if message.code == "command-run-failed" {
+                        NodeRunStatus::Failed
+                    } else {
+                        NodeRunStatus::Refused
+                    };
+                    run.error = Some(message);
+                }
+            }
+        }
+        if run.status != NodeRunStatus::Succeeded {
+            self.blocked.insert(&node.id);
+        }
+        self.runs.push(run);
+        self.runs.last()
+    }
+
+    /// Terminal nodes run or skipped so far
+    pub fn runs(&self) -> &[NodeRun] {
+        &self.runs
+    }
+
+    pub fn finish(self) -> CanvasRunReport {
+        CanvasRunReport {
+            canvas_id: self.canvas_id,
+            success: self
+                .runs
+                .iter()
+                .all(|r| r.status == NodeRunStatus::Succeeded),
+            nodes: self.runs,
+        }
+    }
+}
+
+/// Run every terminal node of `canvas`. Headless callers cannot answer
+/// confirmation prompts, so destructive commands under the confirm policy are refused.
+///
+/// The run is traced as a `canvas_run` span with a `canvas_node` child for
+/// each terminal node, under which its command's span sits.
+pub async fn run_canvas(
+    service: &ExecutionService,
+    canvas: &CanvasDocument,
+    variables: &HashMap<String, String>,
+    actor: &str,
+) -> Result<CanvasRunReport, UserMessage> {
+    let mut span = Span::root("canvas_run");
+    span.set_attribute("canvas.id", canvas.id.clone());
+    let mut stepper = match CanvasStepper::new(canvas) {
+        Ok(stepper) => stepper,
+        Err(invalid) => {
+            span.fail(invalid.code);
+            service.tracer().record(span);
+            return Err(invalid);
+        }
+    };
+    loop {
+        let mut node_span = span.child("canvas_node");
+        let step = stepper.step_with(variables, |_, request| {
+            let node_span = &node_span;
+            async move {
+                service
+                    .execute_under(node_span, &request, None, actor)
+                    .await
+            }
+        });
+        let Some(run) = step.await else { break };
+        let status = serde_json::to_value(run.status).unwrap_or_default();
+        node_span.set_attribute("canvas.node.id", run.node_id.clone());
+        node_span.set_attribute("canvas.node.status", status.clone());
+        match (run.status, &run.error) {
+            (NodeRunStatus::Succeeded, _) => node_span.finish(),
+            (_, Some(error)) => node_span.fail(error.code),
+            (_, None) => node_span.fail(status.as_str().unwrap_or_default()),
+        }
+        service.tracer().record(node_span);
+    }
+    let report = stepper.finish();
+    if report.success {
+        span.finish();
+    } else {
+        span.fail("a terminal node did not succeed");
+    }
+    service.tracer().record(span);
+    Ok(report)
+}
@@ -0,0 +1,574 @@
+//! One-shot command execution for canvas terminal nodes.
+
+use super::backend::Backend;
+use super::limits::{self, ResourceLimit, ResourceLimitExceeded, ResourceLimits};
+use super::output::{self, Capture, OutputCap, SpilledOutput, TextEncoding};
+use super::pwsh::PwshRun;
+use super::running::CancelHandle;
+use super::sandbox::Shadow;
+use super::signal::{new_process_group, ProcessTree, Signal};
+use super::target::{self, ExecutionTarget};
+use crate::memory::ansi::{self, AnsiMode, AnsiParser, StyledSpan};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use ts_rs::TS;
+
+/// A command to run as a direct process (no shell)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CommandSpec {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Written to the process's stdin, which is then closed
+    #[serde(default)]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub mode: ExecutionMode,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// How much of stdout and stderr the outcome keeps
+    #[serde(default)]
+    pub output: OutputCap,
+    /// What happens to ANSI escape sequences in the output
+    #[serde(default)]
+    pub ansi: AnsiMode,
+    /// How the command is started: directly, or through `cmd.exe` or
+    /// PowerShell on Windows (see [`super::backend`])
+    #[serde(default)]
+    pub backend: Backend,
+    /// This machine, or a WSL distro (see [`super::target`])
+    #[serde(default)]
+    pub target: ExecutionTarget,
+    /// An overlay of the cwd that takes the command's writes to it; set by
+    /// sandbox previews only (see [`super::sandbox`])
+    #[serde(skip)]
+    pub shadow: Option<Shadow>,
+}
+
+/// How a command line is run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ExecutionMode {
+    /// `command` is a program, started directly with `args`
+    #[default]
+    Direct,
+    /// The command line is PowerShell, run by `pwsh`; the objects its
+    /// pipeline emits are captured as JSON (see [`super::pwsh`])
+    Pwsh,
+}
+
+impl CommandSpec {
+    /// The command line as a single display string
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.command.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The cwd as this machine reaches it, e.g. a WSL distro's directory
+    /// through `\\wsl.localhost`
+    pub fn host_cwd(&self) -> Option<String> {
+        self.cwd.as_deref().map(|cwd| self.target.host_path(cwd))
+    }
+
+    /// Whether CRLF in the output is turned into `\n`: by the Windows
+    /// backends, and for PowerShell runs on Windows
+    pub fn normalizes_crlf(&self) -> bool {
+        if !self.target.is_local() {
+            return false;
+        }
+        match self.mode {
+            ExecutionMode::Pwsh => cfg!(windows),
+            ExecutionMode::Direct => self.backend.adapter().normalizes_crlf(),
+        }
+    }
+}
+
+/// Result of a finished command
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CommandOutcome {
+    pub stdout: String,
+    pub stderr: String,
+    /// `base64` when stdout is binary or not UTF-8
+    #[serde(default, skip_serializing_if = "TextEncoding::is_utf8")]
+    #[ts(as = "Option<TextEncoding>", optional)]
+    pub stdout_encoding: TextEncoding,
+    #[serde(default, skip_serializing_if = "TextEncoding::is_utf8")]
+    #[ts(as = "Option<TextEncoding>", optional)]
+    pub stderr_encoding: TextEncoding,
+    /// Detected type of stdout, e.g. `image/png`; `text/plain` for text
+    #[serde(default = "text_plain")]
+    pub content_type: String,
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub duration_ms: u64,
+    #[serde(default = "Utc::now")]
+    pub started_at: DateTime<Utc>,
+    #[serde(default)]
+    pub termination: Termination,
+    /// Objects emitted by a [`ExecutionMode::Pwsh`] run, as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub objects: Option<Vec<serde_json::Value>>,
+    /// The limit a [`Termination::LimitExceeded`] run broke
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub limit_exceeded: Option<ResourceLimitExceeded>,
+    /// Whether stdout or stderr went over the spec's [`OutputCap`] and was
+    /// cut short
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub truncated: bool,
+    /// Bytes written to stdout and stderr, including any that were cut
+    #[serde(default)]
+    pub total_bytes: u64,
+    /// Where truncated streams were written in full
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<SpilledOutput>>", optional)]
+    pub spilled: Vec<SpilledOutput>,
+    /// Time the machine slept during the run, which `duration_ms` leaves out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub suspended_ms: Option<u64>,
+    /// Styles of stdout, with [`AnsiMode::Spans`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<StyledSpan>>", optional)]
+    pub stdout_spans: Vec<StyledSpan>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<StyledSpan>>", optional)]
+    pub stderr_spans: Vec<StyledSpan>,
+}
+
+fn text_plain() -> String {
+    "text/plain".to_string()
+}
+
+/// How a command run ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum Termination {
+    /// The process exited by itself
+    #[default]
+    Exited,
+    /// Killed when its timeout ran out
+    TimedOut,
+    /// Killed through [`RunningCommands::cancel`](super::running::RunningCommands::cancel)
+    Cancelled,
+    /// Killed for breaking one of its [`ResourceLimits`]
+    LimitExceeded,
+}
+
+/// How long output is still read once a run's process has exited or been
+/// killed
+const OUTPUT_DRAIN: Duration = Duration::from_millis(500);
+
+/// Ways to stop a run before its process exits
+#[derive(Default)]
+pub struct StopConditions {
+    pub timeout: Option<Duration>,
+    pub cancel: Option<CancelHandle>,
+}
+
+/// Environment variable names are restricted to alphanumerics and underscore
+pub fn valid_env_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Output as it is read from a running command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputChunk {
+    pub stream: OutputStream,
+    pub data: String,
+    /// `base64` when this chunk is binary or not UTF-8
+    #[serde(default, skip_serializing_if = "TextEncoding::is_utf8")]
+    pub encoding: TextEncoding,
+    /// Styles of `data`, with [`AnsiMode::Spans`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<StyledSpan>,
+}
+
+pub(super) fn build_command(
+    spec: &CommandSpec,
+    pwsh: Option<&PwshRun>,
+) -> Result<tokio::process::Command> {
+    if spec.command.trim().is_empty() {
+        anyhow::bail!("Empty command");
+    }
+
+    let mut cmd = match (&spec.target, pwsh) {
+        (ExecutionTarget::Wsl { distro }, _) => target::wsl_command(spec, distro)?,
+        (ExecutionTarget::Local, Some(run)) => run.command(),
+        (ExecutionTarget::Local, None) => spec.backend.adapter().command(spec),
+    };
+    cmd.kill_on_drop(true);
+    new_process_group(&mut cmd);
+    #[cfg(unix)]
+    limits::apply(&mut cmd, &spec.limits);
+    // A WSL target is given its cwd as an argument
+    if let Some(cwd) = spec.cwd.as_deref().filter(|c| !c.is_empty()) {
+        if spec.target.is_local() {
+            cmd.current_dir(cwd);
+        }
+    }
+    if let Some(shadow) = &spec.shadow {
+        #[cfg(target_os = "linux")]
+        super::sandbox::apply(&mut cmd, shadow)?;
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!(
+            "Cannot shadow {}: overlays need Linux",
+            shadow.lower.display()
+        );
+    }
+    for (name, value) in &spec.env {
+        if valid_env_name(name) {
+            cmd.env(name, value);
+        } else {
+            log::warn!("[execution] Skipping invalid env var name: {:?}", name);
+        }
+    }
+    Ok(cmd)
+}
+
+/// Run `spec` to completion, capturing stdout and stderr
+pub async fn run_command(spec: &CommandSpec) -> Result<CommandOutcome> {
+    run_command_until(spec, None, StopConditions::default()).await
+}
+
+/// Run `spec` to completion, sending output to `chunks` as it arrives. The
+/// returned outcome still carries stdout and stderr, up to the spec's
+/// [`OutputCap`]. A closed receiver does not stop the command.
+pub async fn run_command_streaming(
+    spec: &CommandSpec,
+    chunks: mpsc::UnboundedSender<OutputChunk>,
+) -> Result<CommandOutcome> {
+    run_command_until(spec, Some(&chunks), StopConditions::default()).await
+}
+
+/// Run `spec` until it exits or `stop` calls for it to be killed. A killed
+/// run is an `Ok` outcome with the output read so far and its
+/// [`Termination`].
+pub async fn run_command_until(
+    spec: &CommandSpec,
+    chunks: Option<&mpsc::UnboundedSender<OutputChunk>>,
+    mut stop: StopConditions,
+) -> Result<CommandOutcome> {
+    let pwsh = match spec.mode {
+        ExecutionMode::Pwsh => Some(PwshRun::prepare(spec)?),
+        ExecutionMode::Direct => None,
+    };
+    let mut cmd = build_command(spec, pwsh.as_ref())?;
+    cmd.stdin(match spec.stdin {
+        Some(_) => Stdio::piped(),
+        None => Stdio::null(),
+    })
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to run {}", spec.command))?;
+    // Kills the processes the command started too, should this future be
+    // dropped before the command exits
+    let mut tree = TreeGuard::of(&child, &spec.command);
+    // Processes the command starts before this are outside the job's limits
+    #[cfg(windows)]
+    if let Some(tree) = &tree.0 {
+        tree.limit(&spec.limits)?;
+    }
+    if let (Some(handle), Some(tree)) = (&stop.cancel, &tree.0) {
+        handle.attach(Arc::clone(tree));
+    }
+    let stdout = child.stdout.take().context("stdout not piped")?;
+    let stderr = child.stderr.take().context("stderr not piped")?;
+    let stdin = child.stdin.take();
+    let mut captured_stdout = Capture::new(OutputStream::Stdout, spec.output);
+    let mut captured_stderr = Capture::new(OutputStream::Stderr, spec.output);
+    let (termination, status) = {
+        // Write stdin while reading, so a command that only reads part of its
+        // input before writing output cannot deadlock with us
+        let read = async {
+            tokio::try_join!(
+                feed(stdin, spec.stdin.as_deref()),
+                forward(
+                    stdout,
+                    OutputStream::Stdout,
+                    spec,
+                    chunks,
+                    &mut captured_stdout
+                ),
+                forward(
+                    stderr,
+                    OutputStream::Stderr,
+                    spec,
+                    chunks,
+                    &mut captured_stderr
+                ),
+            )
+        };
+        let timed_out = async {
+            match stop.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let cancelled = async {
+            match &mut stop.cancel {
+                Some(handle) => handle.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let wall_exceeded = async {
+            match spec.limits.wall_secs {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(read, timed_out, cancelled, wall_exceeded);
+        // The command has exited when it is reaped, not when its output
+        // closes: it may close its stdio and keep running, or leave a
+        // background process holding the pipes open after it exits
+        let mut reading = true;
+        let (termination, status) = loop {
+            tokio::select! {
+                read = &mut read, if reading => {
+                    read?;
+                    reading = false;
+                }
+                status = child.wait() => break (Termination::Exited, Some(status?)),
+                _ = &mut timed_out => break (Termination::TimedOut, None),
+                _ = &mut cancelled => break (Termination::Cancelled, None),
+                _ = &mut wall_exceeded => break (Termination::LimitExceeded, None),
+            }
+        };
+        let status = match status {
+            Some(status) => status,
+            None => {
+                tree.kill();
+                child
+                    .kill()
+                    .await
+                    .with_context(|| format!("Failed to kill {}", spec.command))?;
+                child.wait().await?
+            }
+        };
+        // Take what is left in the pipes, but not for as long as something
+        // the command left behind keeps them open
+        if reading {
+            if let Ok(read) = tokio::time::timeout(OUTPUT_DRAIN, &mut read).await {
+                read?;
+            }
+        }
+        (termination, status)
+    };
+    // The command's pid may be reused once it is reaped
+    tree.0 = None;
+    let (captured_stdout, stdout_bytes, stdout_spill) = captured_stdout.finish().await;
+    let (captured_stderr, stderr_bytes, stderr_spill) = captured_stderr.finish().await;
+    let total_bytes = stdout_bytes + stderr_bytes;
+    let (termination, limit_exceeded) = match termination {
+        Termination::LimitExceeded => (
+            termination,
+            spec.limits.wall_secs.map(|max| ResourceLimitExceeded {
+                limit: ResourceLimit::WallTime,
+                max,
+            }),
+        ),
+        Termination::Exited => match limits::violation(&spec.limits, &status, &captured_stderr) {
+            Some(exceeded) => (Termination::LimitExceeded, Some(exceeded)),
+            None => (termination, None),
+        },
+        _ => (termination, None),
+    };
+
+    let (duration_ms, suspended_ms) = run_time(started_at, started);
+
+    let mut stdout = output::encode(&captured_stdout);
+    let mut stderr = output::encode(&captured_stderr);
+    if spec.normalizes_crlf() {
+        output::normalize_crlf(&mut stdout);
+        output::normalize_crlf(&mut stderr);
+    }
+    let stdout_spans = output::apply_ansi(&mut stdout, spec.ansi);
+    let stderr_spans = output::apply_ansi(&mut stderr, spec.ansi);
+    Ok(CommandOutcome {
+        stdout: stdout.text,
+        stderr: stderr.text,
+        stdout_encoding: stdout.encoding,
+        stderr_encoding: stderr.encoding,
+        content_type: stdout.content_type,
+        exit_code: status.code(),
+        success: termination == Termination::Exited && status.success(),
+        duration_ms,
+        started_at,
+        termination,
+        objects: pwsh.map(|run| run.objects()),
+        limit_exceeded,
+        truncated: total_bytes > (captured_stdout.len() + captured_stderr.len()) as u64,
+        total_bytes,
+        spilled: stdout_spill.into_iter().chain(stderr_spill).collect(),
+        suspended_ms: (suspended_ms > 0).then_some(suspended_ms),
+        stdout_spans,
+        stderr_spans,
+    })
+}
+
+/// How long a run that started at `started_at` (`started` on the monotonic
+/// clock) took, and how long the machine slept meanwhile. Some monotonic
+/// clocks keep going while the machine sleeps and some stop, so a run that
+/// slept is timed by the wall clock less the sleep.
+pub(super) fn run_time(started_at: DateTime<Utc>, started: Instant) -> (u64, u64) {
+    let ended_at = Utc::now();
+    let power = crate::power::monitor();
+    power.check();
+    let suspended_ms = power.slept_ms(started_at, ended_at);
+    let duration_ms = match suspended_ms {
+        0 => started.elapsed().as_millis() as u64,
+        slept => ((ended_at - started_at).num_milliseconds().max(0) as u64).saturating_sub(slept),
+    };
+    (duration_ms, suspended_ms)
+}
+
+/// Kills a run's process tree when dropped, unless it has been emptied
+pub(super) struct TreeGuard(pub(super) Option<Arc<ProcessTree>>);
+
+impl TreeGuard {
+    /// Guard the tree of `child`, or nothing when it cannot be tracked
+    pub(super) fn of(child: &tokio::process::Child, command: &str) -> Self {
+        Self(child.id().and_then(|pid| match ProcessTree::of(pid) {
+            Ok(tree) => Some(Arc::new(tree)),
+            Err(e) => {
+                log::warn!("[execution] {:#}; only {} itself can be killed", e, command);
+                None
+            }
+        }))
+    }
+
+    pub(super) fn kill(&self) {
+        if let Some(tree) = &self.0 {
+            if let Err(e) = tree.signal(Signal::Kill) {
+                log::warn!("[execution] {:#}", e);
+            }
+        }
+    }
+}
+
+impl Drop for TreeGuard {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Write `input` to the child's stdin and close it. A command that exits
+/// without reading all of it is not an error.
+pub(super) async fn feed(
+    stdin: Option<tokio::process::ChildStdin>,
+    input: Option<&str>,
+) -> Result<()> {
+    let (Some(mut stdin), Some(input)) = (stdin, input) else {
+        return Ok(());
+    };
+    match stdin.write_all(input.as_bytes()).await {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("Failed to write stdin"),
+    }
+}
+
+/// Copy `reader` into `captured`, forwarding each read it keeps as a chunk.
+/// Output past the cap is still read, so the command does not block on a
+/// full pipe. A character split across reads is sent whole with the next one,
+/// and so is a CR the spec's CRLF normalization needs the next read to place.
+pub(super) async fn forward(
+    mut reader: impl AsyncRead + Unpin,
+    stream: OutputStream,
+    spec: &CommandSpec,
+    chunks: Option<&mpsc::UnboundedSender<OutputChunk>>,
+    captured: &mut Capture,
+) -> Result<()> {
+    let (mode, crlf) = (spec.ansi, spec.normalizes_crlf());
+    let mut buf = [0u8; 8192];
+    let mut pending = Vec::new();
+    // Carries sequences and styles across chunks
+    let mut parser = AnsiParser::new();
+    loop {
+        let n = reader.read(&mut buf).await?;
+        let kept = captured.push(&buf[..n]).await;
+        let Some(chunks) = chunks else {
+            if n == 0 {
+                return Ok(());
+            }
+            continue;
+        };
+        pending.extend_from_slice(kept);
+        let complete = if n == 0 {
+            pending.len()
+        } else {
+            let complete = output::complete_len(&pending);
+            match pending[..complete].last() {
+                Some(b'\r') if crlf => complete - 1,
+                _ => complete,
+            }
+        };
+        if complete > 0 || (n == 0 && !mode.is_keep()) {
+            let mut encoded = output::encode(&pending[..complete]);
+            if crlf {
+                output::normalize_crlf(&mut encoded);
+            }
+            let (data, spans) = match (mode, encoded.encoding) {
+                (AnsiMode::Keep, _) | (_, TextEncoding::Base64) => (encoded.text, Vec::new()),
+                (mode, TextEncoding::Utf8) => {
+                    let mut spans = parser.feed(&encoded.text);
+                    if n == 0 {
+                        spans.extend(parser.finish());
+                    }
+                    let data = ansi::plain_text(&spans);
+                    (
+                        data,
+                        if mode == AnsiMode::Spans {
+                            spans
+                        } else {
+                            Vec::new()
+                        },
+                    )
+                }
+            };
+            if !data.is_empty() {
+                let _ = chunks.send(OutputChunk {
+                    stream,
+                    data,
+                    encoding: encoded.encoding,
+                    spans,
+                });
+            }
+            pending.drain(..complete);
+        }
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Step debugger for canvas runs.
+//!
+//! A debug session runs a canvas like [`super::run_canvas`], but pauses
+//! before terminal nodes that have a breakpoint (or before every node while
+//! stepping). At each pause the session reports the node's resolved command,
+//! the output of the nodes connected to its inputs, and every run so far,
+//! then waits for a [`DebugCommand`]: `step` runs the node and pauses at the
+//! next one, `continue` runs to the next breakpoint, `stop` ends the session.
+//!
+//! The protocol is events out, commands in: [`DebugEvent`]s go to the
+//! channel given to [`DebugManager::start`] (the app forwards them as
+//! `canvas-debug-event`), and commands come back through
+//! [`DebugManager::command`].
+
+use super::canvas::{
+    CanvasDocument, CanvasNode, CanvasRunReport, CanvasStepper, NodeRun, NodeRunStatus,
+};
+use super::resolve::ResolvedExecution;
+use super::service::ExecutionService;
+use crate::i18n::UserMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use ts_rs::TS;
+
+/// A canvas to debug
+#[derive(Debug, Clone, Deserialize, TS)]
+#[ts(export)]
+pub struct DebugSpec {
+    pub canvas: CanvasDocument,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    /// Node ids to pause before
+    #[serde(default)]
+    pub breakpoints: Vec<String>,
+    /// Pause before the first terminal node, as if stepping
+    #[serde(default)]
+    pub pause_on_start: bool,
+}
+
+/// What the UI tells a paused session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case", tag = "command")]
+#[ts(export)]
+pub enum DebugCommand {
+    /// Run the paused node, then pause before the next one; while running,
+    /// pause before the next node
+    Step,
+    /// Run until the next breakpoint
+    Continue,
+    /// Skip everything not yet run and end the session
+    Stop,
+    /// Replace the breakpoints; allowed while running
+    SetBreakpoints { node_ids: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PauseReason {
+    Breakpoint,
+    Step,
+}
+
+/// The output a connected upstream node produced
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct NodeInput {
+    pub from: String,
+    /// Input port of the paused node, when the canvas records it
+    pub port: Option<String>,
+    /// `None` for nodes without a run (non-terminal nodes) or without output
+    pub status: Option<NodeRunStatus>,
+    pub stdout: Option<String>,
+}
+
+/// Everything there is to inspect at a pause
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct PauseState {
+    pub node_id: String,
+    pub label: String,
+    pub reason: PauseReason,
+    /// The command as it will run; `None` when it does not resolve
+    pub resolved: Option<ResolvedExecution>,
+    pub error: Option<UserMessage>,
+    /// Whether the node will be skipped because an upstream node did not succeed
+    pub upstream_blocked: bool,
+    pub inputs: Vec<NodeInput>,
+    /// Terminal nodes run or skipped so far, in order
+    pub completed: Vec<NodeRun>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum DebugEventKind {
+    Paused {
+        state: Box<PauseState>,
+    },
+    NodeFinished {
+        run: NodeRun,
+    },
+    /// The run ended; after a stop the report only has the nodes that ran
+    Finished {
+        report: CanvasRunReport,
+        stopped: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugEvent {
+    pub session_id: String,
+    pub canvas_id: String,
+    #[serde(flatten)]
+    pub kind: DebugEventKind,
+}
+
+struct DebugSession {
+    commands: mpsc::UnboundedSender<DebugCommand>,
+    paused: Option<PauseState>,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, DebugSession>>>;
+
+/// Running debug sessions, each a background task
+#[derive(Default)]
+pub struct DebugManager {
+    sessions: Sessions,
+}
+
+impl DebugManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a debug session and return its id. Events go to `events`
+    /// until the run finishes or is stopped.
+    pub fn start(
+        &self,
+        execution: Arc<ExecutionService>,
+        spec: DebugSpec,
+        events: mpsc::UnboundedSender<DebugEvent>,
+    ) -> Result<String, UserMessage> {
+        // Reject cycles and dangling connections before there is a session
+        CanvasStepper::new(&spec.canvas)?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let (commands, received) = mpsc::unbounded_channel();
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                id.clone(),
+                DebugSession {
+                    commands,
+                    paused: None,
+                },
+            );
+        let sessions = Arc::clone(&self.sessions);
+        let session_id = id.clone();
+        tokio::spawn(async move {
+            debug_canvas(&execution, spec, &session_id, &sessions, received, &events).await;
+            sessions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&session_id);
+        });
+        Ok(id)
+    }
+
+    /// Send a command to a session; false when there is no such session
+    pub fn command(&self, id: &str, command: DebugCommand) -> bool {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .is_some_and(|s| s.commands.send(command).is_ok())
+    }
+
+    /// Stop every session, e.g. before the profile is switched
+    pub fn stop_all(&self) {
+        for session in self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+        {
+            let _ = session.commands.send(DebugCommand::Stop);
+        }
+    }
+
+    /// Sessions still running or paused
+    pub fn session_count(&self) -> usize {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+
+    /// Where a session is paused; `None` while it runs or when there is no such session
+    pub fn inspect(&self, id: &str) -> Option<PauseState> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)?
+            .paused
+            .clone()
+    }
+}
+
+/// What a node's inputs are fed from, given the runs so far
+fn node_inputs(canvas: &CanvasDocument, node: &CanvasNode, runs: &[NodeRun]) -> Vec<NodeInput> {
+    canvas
+        .connections
+        .iter()
+        .filter(|c| c.to == node.id)
+        .map(|c| {
+            let run = runs.iter().find(|r| r.node_id == c.from);
+            NodeInput {
+                from: c.from.clone(),
+                port: c.to_port.clone(),
+                status: run.map(|r| r.status),
+                stdout: run
+                    .and_then(|r| r.outcome.as_ref())
+                    .map(|o| o.stdout.clone()),
+            }
+        })
+        .collect()
+}
+
+async fn debug_canvas(
+    execution: &ExecutionService,
+    spec: DebugSpec,
+    session_id: &str,
+    sessions: &Sessions,
+    mut commands: mpsc::UnboundedReceiver<DebugCommand>,
+    events: &mpsc::UnboundedSender<DebugEvent>,
+) {
+    let send = |kind| {
+        let _ = events.send(DebugEvent {
+            session_id: session_id.to_string(),
+            canvas_id: spec.canvas.id.clone(),
+            kind,
+        });
+    };
+    let Ok(mut stepper) = CanvasStepper::new(&spec.canvas) else {
+        return;
+    };
+    let mut breakpoints: HashSet<String> = spec.breakpoints.iter().cloned().collect();
+    let mut stepping = spec.pause_on_start;
+    let mut stopped = false;
+
+    while let Some(node) = stepper.peek(&spec.variables) {
+        // Commands sent while running apply from the next node
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                DebugCommand::SetBreakpoints { node_ids } => {
+                    breakpoints = node_ids.into_iter().collect()
+                }
+                DebugCommand::Step => stepping = true,
+                DebugCommand::Stop => stopped = true,
+                DebugCommand::Continue => {}
+            }
+        }
+        if stopped {
+            break;
+        }
+        let reason = if stepping {
+            Some(PauseReason::Step)
+        } else if breakpoints.contains(&node.id) {
+            Some(PauseReason::Breakpoint)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            let preview = node
+                .request(&spec.variables)
+                .map(|request| execution.preview(&request));
+            let state = PauseState {
+                node_id: node.id.clone(),
+                label: node.label.clone(),
+                reason,
+                resolved: preview.clone().and_then(Result::ok),
+                error: preview.and_then(Result::err),
+                upstream_blocked: stepper.upstream_blocked(&node.id),
+                inputs: node_inputs(&spec.canvas, node, stepper.runs()),
+                completed: stepper.runs().to_vec(),
+            };
+            if let Some(session) = sessions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_mut(session_id)
+            {
+                session.paused = Some(state.clone());
+            }
+            send(DebugEventKind::Paused {
+                state: Box::new(state),
+            });
+
+            let command = loop {
+                match commands.recv().await {
+                    Some(DebugCommand::SetBreakpoints { node_ids }) => {
+                        breakpoints = node_ids.into_iter().collect();
+                    }
+                    other => break other,
+                }
+            };
+            if let Some(session) = sessions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_mut(session_id)
+            {
+                session.paused = None;
+            }
+            match command {
+                Some(DebugCommand::Step) => stepping = true,
+                Some(DebugCommand::Continue) => stepping = false,
+                // Stop, or the manager is gone
+                _ => {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+        if let Some(run) = stepper.step(execution, &spec.variables, "user").await {
+            send(DebugEventKind::NodeFinished { run: run.clone() });
+        }
+    }
+
+    let mut report = stepper.finish();
+    if stopped {
+        report.success = false;
+    }
+    send(DebugEventKind::Finished { report, stopped });
+}
@@ -0,0 +1,145 @@
+//! Dry runs: everything execution does short of starting the process.
+//!
+//! A dry run resolves a request as [`preview`](super::ExecutionService::preview)
+//! does — profile, templates and policy — and then checks what starting it
+//! needs: that no placeholder is left, that the policy does not block it,
+//! that the working directory exists and that the program can be found on
+//! `PATH`. It reports the process that would start, as the backend or target
+//! builds it, so a command can be shown exactly before anyone runs it. Nothing
+//! is admitted, so no confirmation token is issued and nothing is audited.
+
+use super::command::{build_command, CommandSpec, ExecutionMode};
+use super::resolve::{PolicyPreview, ResolvedExecution};
+use crate::i18n::UserMessage;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// What running a request would do
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct DryRun {
+    #[serde(flatten)]
+    pub resolved: ResolvedExecution,
+    /// Program and arguments of the process that would start; for PowerShell
+    /// runs, `pwsh` and the script
+    pub process: Vec<String>,
+    /// Where the program was found on this machine; `None` when it was not,
+    /// or when it runs in a WSL distro
+    pub program_path: Option<String>,
+    /// Why the command would not run; empty when it would, perhaps after
+    /// confirmation
+    pub problems: Vec<UserMessage>,
+}
+
+impl DryRun {
+    /// Check everything `resolved` needs to start
+    pub fn check(resolved: ResolvedExecution) -> Self {
+        let spec = &resolved.spec;
+        let mut problems = Vec::new();
+        if !resolved.unresolved_variables.is_empty() {
+            problems.push(
+                UserMessage::new("template-unresolved")
+                    .with("names", resolved.unresolved_variables.join(", ")),
+            );
+        }
+        if let PolicyPreview::Block { rule, reason, .. } = &resolved.policy {
+            problems.push(
+                UserMessage::new("command-blocked")
+                    .with("rule", rule)
+                    .with("reason", reason),
+            );
+        }
+        let process = match spec.mode {
+            ExecutionMode::Pwsh if spec.target.is_local() => {
+                vec!["pwsh".to_string(), spec.command_line()]
+            }
+            _ => match build_command(spec, None) {
+                Ok(cmd) => {
+                    let cmd = cmd.as_std();
+                    std::iter::once(cmd.get_program())
+                        .chain(cmd.get_args())
+                        .map(|part| part.to_string_lossy().into_owned())
+                        .collect()
+                }
+                Err(e) => {
+                    problems.push(
+                        UserMessage::new("command-run-failed").with("error", format!("{:#}", e)),
+                    );
+                    Vec::new()
+                }
+            },
+        };
+        let mut program_path = None;
+        if spec.target.is_local() {
+            let cwd = spec.cwd.as_deref().filter(|c| !c.is_empty()).map(Path::new);
+            if let Some(cwd) = cwd.filter(|cwd| !cwd.is_dir()) {
+                problems.push(UserMessage::new("dry-run-cwd-missing").with("cwd", cwd.display()));
+            }
+            let program = match spec.mode {
+                ExecutionMode::Pwsh => "pwsh",
+                ExecutionMode::Direct => spec.command.as_str(),
+            };
+            program_path = find_program(program, spec, cwd);
+            if program_path.is_none() && !program.trim().is_empty() {
+                problems
+                    .push(UserMessage::new("dry-run-program-not-found").with("program", program));
+            }
+        }
+        Self {
+            process,
+            program_path: program_path.map(|p| p.display().to_string()),
+            problems,
+            resolved,
+        }
+    }
+
+    /// Whether the command would start, once confirmed if the policy asks
+    pub fn would_run(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Where `program` is: a path is taken from `cwd` when relative, a bare name
+/// is looked up on the `PATH` the command would get. On Windows the
+/// extensions in `PATHEXT` are tried too.
+fn find_program(program: &str, spec: &CommandSpec, cwd: Option<&Path>) -> Option<PathBuf> {
+    if program.trim().is_empty() {
+        return None;
+    }
+    let extensions: Vec<String> = if cfg!(windows) {
+        let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+        std::iter::once(String::new())
+            .chain(
+                pathext
+                    .split(';')
+                    .filter(|e| !e.is_empty())
+                    .map(String::from),
+            )
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+    let found = |base: PathBuf| {
+        extensions
+            .iter()
+            .map(|ext| {
+                let mut candidate = base.clone().into_os_string();
+                candidate.push(ext);
+                PathBuf::from(candidate)
+            })
+            .find(|candidate| candidate.is_file())
+    };
+    let path = Path::new(program);
+    if path.components().count() > 1 || path.is_absolute() {
+        return match cwd {
+            Some(cwd) if path.is_relative() => found(cwd.join(path)),
+            _ => found(path.to_path_buf()),
+        };
+    }
+    let search = match spec.env.get("PATH") {
+        Some(path) => path.into(),
+        None => std::env::var_os("PATH")?,
+    };
+    std::env::split_paths(&search).find_map(|dir| found(dir.join(program)))
+}
@@ -0,0 +1,398 @@
+//! Running commands as administrator.
+//!
+//! A command that fails with a permission error is reported as
+//! `command-needs-elevation` instead of a plain failure, and the frontend can
+//! offer to run it again elevated. On Unix that goes through `sudo`: when
+//! sudo wants a password, the user types it into a dialog window the backend
+//! opens for that one command, and it reaches `sudo -S` on stdin. The
+//! password is never written to disk, logged or kept once the command has
+//! run, and `-k` keeps sudo from caching the credentials either. On Windows
+//! the command runs through `Start-Process -Verb RunAs`, so the UAC prompt
+//! is the system's own and RuneBook never sees a password.
+//!
+//! Elevated runs are admitted like any other run, and each one is recorded
+//! with a provenance record saying how it was elevated.
+
+use super::backend::{cmd_line, encode_command, quote_powershell};
+use super::command::{run_command, CommandOutcome, CommandSpec, ExecutionMode};
+use super::resolve::ExecutionRequest;
+use super::service::{self, ExecutionService};
+use crate::i18n::UserMessage;
+use crate::memory::{MemoryStore, Provenance};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+use ts_rs::TS;
+
+/// Exit codes that mean "not allowed": 126 from shells for a file that
+/// cannot be executed, 77 (`EX_NOPERM`) from sysexits
+const PERMISSION_EXIT_CODES: [i32; 2] = [126, 77];
+
+/// What tools print when they lack the rights to do something
+const PERMISSION_MARKERS: [&str; 9] = [
+    "permission denied",
+    "operation not permitted",
+    "access is denied",
+    "eacces",
+    "eperm",
+    "are you root",
+    "must be run as root",
+    "requires elevation",
+    "run as administrator",
+];
+
+/// `ERROR_CANCELLED`, which the UAC script exits with when the prompt is declined
+const UAC_CANCELLED: i32 = 1223;
+
+/// How a command gets administrator rights
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ElevationMethod {
+    /// `sudo`, with the password on stdin when it asks for one
+    Sudo,
+    /// The Windows UAC prompt
+    Uac,
+}
+
+impl ElevationMethod {
+    /// The method this platform uses
+    pub fn for_host() -> Self {
+        if cfg!(windows) {
+            ElevationMethod::Uac
+        } else {
+            ElevationMethod::Sudo
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ElevationMethod::Sudo => "sudo",
+            ElevationMethod::Uac => "uac",
+        }
+    }
+}
+
+/// Whether `outcome` failed for lack of rights, so running it elevated may help
+pub fn needs_elevation(outcome: &CommandOutcome) -> bool {
+    if outcome.success {
+        return false;
+    }
+    if outcome
+        .exit_code
+        .is_some_and(|code| PERMISSION_EXIT_CODES.contains(&code))
+    {
+        return true;
+    }
+    let stderr = outcome.stderr.to_lowercase();
+    PERMISSION_MARKERS.iter().any(|m| stderr.contains(m))
+}
+
+/// A password typed into the elevation dialog. It does not print, and its
+/// memory is zeroed when it is dropped.
+#[derive(Deserialize, TS)]
+#[serde(transparent)]
+#[ts(export)]
+pub struct Password(String);
+
+impl Password {
+    pub fn new(password: String) -> Self {
+        Self(password)
+    }
+
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Password(..)")
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// Zero `text`'s bytes before it is freed
+fn wipe(text: &mut String) {
+    // SAFETY: zero bytes are valid UTF-8
+    unsafe { text.as_mut_vec() }.fill(0);
+    text.clear();
+}
+
+/// Password dialogs waiting for an answer, by prompt id
+#[derive(Default)]
+pub struct ElevationPrompts {
+    pending: Mutex<HashMap<String, PendingPrompt>>,
+}
+
+struct PendingPrompt {
+    command_line: String,
+    reply: oneshot::Sender<Option<Password>>,
+}
+
+impl ElevationPrompts {
+    /// Ask for the password to run `command_line`. Returns the prompt id and
+    /// the answer, which is `None` when the user cancels.
+    pub fn open(&self, command_line: String) -> (String, oneshot::Receiver<Option<Password>>) {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (reply, answer) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                id.clone(),
+                PendingPrompt {
+                    command_line,
+                    reply,
+                },
+            );
+        (id, answer)
+    }
+
+    /// The command line prompt `id` asks about
+    pub fn command_line(&self, id: &str) -> Option<String> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .map(|p| p.command_line.clone())
+    }
+
+    /// Answer prompt `id`; `None` cancels it. Returns whether it was waiting.
+    pub fn answer(&self, id: &str, password: Option<Password>) -> bool {
+        match self
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+        {
+            Some(prompt) => {
+                // A dropped receiver means the run is already gone
+                let _ = prompt.reply.send(password);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// An elevated run and how it was elevated
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct ElevatedRun {
+    pub command_line: String,
+    pub method: ElevationMethod,
+    /// Whether the user typed a password for it
+    pub password_used: bool,
+    pub outcome: CommandOutcome,
+    #[serde(skip)]
+    pub spec: CommandSpec,
+}
+
+/// Admit `request` and run it as administrator with `method`. `ask` is
+/// called with the command line when sudo needs a password, and returns it,
+/// or `None` when the user cancels.
+pub async fn run_elevated<F, Fut>(
+    service: &ExecutionService,
+    request: &ExecutionRequest,
+    confirmation_token: Option<&str>,
+    actor: &str,
+    method: ElevationMethod,
+    ask: F,
+) -> Result<ElevatedRun, UserMessage>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Option<Password>>,
+{
+    let spec = service.admit(request, confirmation_token, actor)?;
+    if !spec.target.is_local() || spec.mode != ExecutionMode::Direct {
+        return Err(UserMessage::new("elevation-unsupported").with(
+            "error",
+            "only direct commands on this machine can run elevated",
+        ));
+    }
+    let command_line = spec.command_line();
+
+    let (outcome, password_used) = match method {
+        ElevationMethod::Sudo => {
+            let probe = sudo(&["-n", "-k", "-v"], None)
+                .await
+                .map_err(UserMessage::wrap("elevation-unsupported"))?;
+            let password = if probe.success {
+                None
+            } else {
+                Some(
+                    ask(command_line.clone())
+                        .await
+                        .ok_or_else(|| UserMessage::new("elevation-cancelled"))?,
+                )
+            };
+            if let Some(password) = &password {
+                // Check the password alone first, so a wrong one is not
+                // retried against the command's own input
+                let mut input = format!("{}\n", password.expose());
+                let checked = sudo(&["-S", "-k", "-p", "", "-v"], Some(&mut input)).await;
+                let checked = checked.map_err(UserMessage::wrap("elevation-failed"))?;
+                if !checked.success {
+                    return Err(
+                        UserMessage::new("elevation-denied").with("error", checked.stderr.trim())
+                    );
+                }
+            }
+            let mut elevated = sudo_spec(&spec, password.is_some());
+            if let Some(password) = &password {
+                let input = elevated.stdin.take().unwrap_or_default();
+                elevated.stdin = Some(format!("{}\n{}", password.expose(), input));
+            }
+            let outcome = run_command(&elevated).await;
+            if let Some(input) = &mut elevated.stdin {
+                wipe(input);
+            }
+            (
+                outcome.map_err(UserMessage::wrap("elevation-failed"))?,
+                password.is_some(),
+            )
+        }
+        ElevationMethod::Uac => (run_uac(&spec).await?, false),
+    };
+    Ok(ElevatedRun {
+        command_line,
+        method,
+        password_used,
+        outcome,
+        spec,
+    })
+}
+
+/// Run `sudo` with `args`, writing `stdin` to it and wiping it afterwards
+async fn sudo(args: &[&str], stdin: Option<&mut String>) -> Result<CommandOutcome> {
+    let mut spec = CommandSpec {
+        command: "sudo".to_string(),
+        args: args.iter().map(|a| a.to_string()).collect(),
+        stdin: stdin.as_ref().map(|s| s.to_string()),
+        ..Default::default()
+    };
+    let outcome = run_command(&spec).await;
+    if let Some(input) = &mut spec.stdin {
+        wipe(input);
+    }
+    if let Some(input) = stdin {
+        wipe(input);
+    }
+    outcome
+}
+
+/// `spec` run through `sudo`: `-S` reads the password from stdin when there
+/// is one, `-n` fails rather than prompt when there is not. `spec`'s
+/// environment is passed through, which sudo would otherwise reset.
+pub fn sudo_spec(spec: &CommandSpec, with_password: bool) -> CommandSpec {
+    let flags: &[&str] = if with_password {
+        &["-S", "-k", "-p", ""]
+    } else {
+        &["-n", "-k"]
+    };
+    let mut args: Vec<String> = flags.iter().map(|a| a.to_string()).collect();
+    if !spec.env.is_empty() {
+        let mut names: Vec<&str> = spec.env.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        args.push(format!("--preserve-env={}", names.join(",")));
+    }
+    args.push("--".to_string());
+    args.push(spec.command.clone());
+    args.extend(spec.args.iter().cloned());
+    CommandSpec {
+        command: "sudo".to_string(),
+        args,
+        ..spec.clone()
+    }
+}
+
+/// The PowerShell script that starts `spec` elevated through `cmd.exe`,
+/// waits for it and exits with its exit code, or [`UAC_CANCELLED`] when the
+/// prompt is declined. The elevated process cannot share our pipes, so its
+/// output goes to `stdout` and `stderr` files. Environment overrides do not
+/// cross the UAC boundary.
+pub fn uac_script(spec: &CommandSpec, stdout: &str, stderr: &str) -> String {
+    let cd = match spec.host_cwd() {
+        Some(cwd) => format!("cd /d \"{}\" && ", cwd),
+        None => String::new(),
+    };
+    let line = format!(
+        "/d /s /c \"{}{} 1> \"{}\" 2> \"{}\"\"",
+        cd,
+        cmd_line(spec),
+        stdout,
+        stderr
+    );
+    format!(
+        "try {{\n$p = Start-Process -FilePath 'cmd.exe' -ArgumentList {} -Verb RunAs -WindowStyle Hidden -Wait -PassThru\n}} catch {{ exit {} }}\nexit $p.ExitCode\n",
+        quote_powershell(&line),
+        UAC_CANCELLED
+    )
+}
+
+async fn run_uac(spec: &CommandSpec) -> Result<CommandOutcome, UserMessage> {
+    let id = uuid::Uuid::new_v4().simple();
+    let stdout = std::env::temp_dir().join(format!("runebook-elevated-{}.out", id));
+    let stderr = std::env::temp_dir().join(format!("runebook-elevated-{}.err", id));
+    let script = uac_script(
+        spec,
+        &stdout.display().to_string(),
+        &stderr.display().to_string(),
+    );
+    let elevated = CommandSpec {
+        command: "powershell".to_string(),
+        args: [
+            "-NoLogo",
+            "-NoProfile",
+            "-NonInteractive",
+            "-EncodedCommand",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .chain(std::iter::once(encode_command(&script)))
+        .collect(),
+        stdin: None,
+        cwd: None,
+        env: HashMap::new(),
+        ..spec.clone()
+    };
+    let outcome = run_command(&elevated).await;
+    let read = |path: &std::path::Path| {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let _ = std::fs::remove_file(path);
+        text
+    };
+    let (out, err) = (read(&stdout), read(&stderr));
+    let mut outcome = outcome.map_err(UserMessage::wrap("elevation-failed"))?;
+    if outcome.exit_code == Some(UAC_CANCELLED) {
+        return Err(UserMessage::new("elevation-cancelled"));
+    }
+    outcome.stdout = out;
+    outcome.stderr = err;
+    Ok(outcome)
+}
+
+/// Store `run` as a command of the execution session, with provenance
+/// recording that it ran elevated and how
+pub async fn record_elevated(store: &MemoryStore, run: &ElevatedRun, actor: &str) -> Result<()> {
+    let command = service::record(store, &run.spec, &run.outcome, actor).await?;
+    let mut provenance =
+        Provenance::new("command".to_string(), command.id.clone(), actor.to_string());
+    provenance.tool = Some(run.method.as_str().to_string());
+    provenance.metadata = serde_json::json!({
+        "elevated": true,
+        "method": run.method,
+        "password_used": run.password_used,
+    });
+    store.store_provenance(provenance).await
+}
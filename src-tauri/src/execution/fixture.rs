@@ -0,0 +1,259 @@
+//! Record/replay fixtures for canvas regression tests.
+//!
+//! Recording runs a canvas for real and writes a [`CanvasFixture`]: the
+//! canvas, its variables, and for every terminal node the resolved command
+//! line, the status and the output (scrubbed with the capture-stage rules,
+//! since fixtures end up in repositories). Replaying checks that a canvas
+//! still behaves the same:
+//!
+//! - [`ReplayMode::Mocked`] (the default) executes nothing. Nodes return their
+//!   recorded output, and the replay checks that every node resolves to the
+//!   same command line and ends in the same status. It is deterministic and
+//!   safe to run in CI against an edited canvas.
+//! - [`ReplayMode::Live`] runs the commands again and also compares exit codes
+//!   and stdout, except for nodes whose `compare_output` is turned off.
+
+use super::canvas::{run_canvas, CanvasDocument, CanvasRunReport, NodeRunStatus};
+use super::service::ExecutionService;
+use super::simulate::{simulate_canvas, NodeMock, SimulationSpec};
+use crate::i18n::UserMessage;
+use crate::memory::{ScrubStage, Scrubber};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use ts_rs::TS;
+
+pub const FIXTURE_VERSION: u32 = 1;
+
+fn default_true() -> bool {
+    true
+}
+
+/// What one terminal node did in the recorded run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FixtureNode {
+    pub node_id: String,
+    /// `None` when the command did not resolve
+    pub command_line: Option<String>,
+    pub status: NodeRunStatus,
+    /// `None` for nodes that were skipped or refused
+    pub output: Option<NodeMock>,
+    /// Compare output in live replays; turn off for output that changes between runs
+    #[serde(default = "default_true")]
+    pub compare_output: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CanvasFixture {
+    pub version: u32,
+    pub recorded_at: DateTime<Utc>,
+    pub canvas: CanvasDocument,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    pub nodes: Vec<FixtureNode>,
+}
+
+impl CanvasFixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let fixture: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid fixture {}", path.display()))?;
+        anyhow::ensure!(
+            fixture.version == FIXTURE_VERSION,
+            "Fixture version {} is not supported",
+            fixture.version
+        );
+        Ok(fixture)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ReplayMode {
+    #[default]
+    Mocked,
+    Live,
+}
+
+/// One way the replay differs from the fixture
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, TS)]
+#[ts(export)]
+pub struct ReplayMismatch {
+    pub node_id: String,
+    /// "command_line", "status", "exit_code", "stdout", "missing" or "unexpected"
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct ReplayReport {
+    pub canvas_id: String,
+    pub mode: ReplayMode,
+    pub passed: bool,
+    pub mismatches: Vec<ReplayMismatch>,
+    pub report: CanvasRunReport,
+}
+
+/// The resolved (and scrubbed) command line of every terminal node, by node id
+fn command_lines(
+    service: &ExecutionService,
+    scrubber: &Scrubber,
+    canvas: &CanvasDocument,
+    variables: &HashMap<String, String>,
+) -> HashMap<String, Option<String>> {
+    canvas
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            let request = node.request(variables)?;
+            let resolved = service.preview(&request).ok();
+            let line = resolved.map(|r| scrubbed(scrubber, &r.command_line));
+            Some((node.id.clone(), line))
+        })
+        .collect()
+}
+
+fn scrubbed(scrubber: &Scrubber, text: &str) -> String {
+    scrubber.scrub(text, ScrubStage::Capture).0
+}
+
+/// Run `canvas` for real and capture a fixture of the run
+pub async fn record_canvas(
+    service: &ExecutionService,
+    scrubber: &Scrubber,
+    canvas: &CanvasDocument,
+    variables: &HashMap<String, String>,
+) -> Result<CanvasFixture, UserMessage> {
+    let report = run_canvas(service, canvas, variables, "fixture").await?;
+    let mut lines = command_lines(service, scrubber, canvas, variables);
+    let nodes = report
+        .nodes
+        .iter()
+        .map(|run| FixtureNode {
+            node_id: run.node_id.clone(),
+            command_line: lines.remove(&run.node_id).flatten(),
+            status: run.status,
+            output: run.outcome.as_ref().map(|outcome| NodeMock {
+                stdout: scrubbed(scrubber, &outcome.stdout),
+                stderr: scrubbed(scrubber, &outcome.stderr),
+                ..NodeMock::from_outcome(outcome)
+            }),
+            compare_output: true,
+        })
+        .collect();
+    Ok(CanvasFixture {
+        version: FIXTURE_VERSION,
+        recorded_at: Utc::now(),
+        canvas: canvas.clone(),
+        variables: variables.clone(),
+        nodes,
+    })
+}
+
+/// Replay `fixture` against `canvas` (the fixture's own canvas when `None`)
+pub async fn replay_fixture(
+    service: &ExecutionService,
+    scrubber: &Scrubber,
+    fixture: &CanvasFixture,
+    canvas: Option<&CanvasDocument>,
+    mode: ReplayMode,
+) -> Result<ReplayReport, UserMessage> {
+    let canvas = canvas.unwrap_or(&fixture.canvas);
+    let report = match mode {
+        ReplayMode::Mocked => {
+            let spec = SimulationSpec {
+                canvas: canvas.clone(),
+                variables: fixture.variables.clone(),
+                mocks: fixture
+                    .nodes
+                    .iter()
+                    .filter_map(|n| Some((n.node_id.clone(), n.output.clone()?)))
+                    .collect(),
+                recorded: None,
+            };
+            simulate_canvas(&spec).await?
+        }
+        ReplayMode::Live => run_canvas(service, canvas, &fixture.variables, "fixture").await?,
+    };
+    let lines = command_lines(service, scrubber, canvas, &fixture.variables);
+
+    let mut mismatches = Vec::new();
+    let mut mismatch = |node_id: &str, field: &str, expected: String, actual: String| {
+        mismatches.push(ReplayMismatch {
+            node_id: node_id.to_string(),
+            field: field.to_string(),
+            expected,
+            actual,
+        })
+    };
+    for expected in &fixture.nodes {
+        let Some(run) = report.nodes.iter().find(|r| r.node_id == expected.node_id) else {
+            mismatch(&expected.node_id, "missing", "a run".into(), "none".into());
+            continue;
+        };
+        let line = lines.get(&run.node_id).cloned().flatten();
+        if line != expected.command_line {
+            mismatch(
+                &run.node_id,
+                "command_line",
+                expected.command_line.clone().unwrap_or_default(),
+                line.unwrap_or_default(),
+            );
+        }
+        // Mocked replays only return recorded output; a node that never ran
+        // (refused, skipped, failed to start) cannot be told apart there
+        let neither_ran =
+            mode == ReplayMode::Mocked && run.outcome.is_none() && expected.output.is_none();
+        if run.status != expected.status && !neither_ran {
+            mismatch(
+                &run.node_id,
+                "status",
+                format!("{:?}", expected.status),
+                format!("{:?}", run.status),
+            );
+        }
+        let (Some(outcome), Some(output)) = (&run.outcome, &expected.output) else {
+            continue;
+        };
+        if mode == ReplayMode::Live && expected.compare_output {
+            if outcome.exit_code != Some(output.exit_code) {
+                mismatch(
+                    &run.node_id,
+                    "exit_code",
+                    output.exit_code.to_string(),
+                    format!("{:?}", outcome.exit_code),
+                );
+            }
+            let stdout = scrubbed(scrubber, &outcome.stdout);
+            if stdout != output.stdout {
+                mismatch(&run.node_id, "stdout", output.stdout.clone(), stdout);
+            }
+        }
+    }
+    for run in &report.nodes {
+        if !fixture.nodes.iter().any(|n| n.node_id == run.node_id) {
+            mismatch(&run.node_id, "unexpected", "none".into(), "a run".into());
+        }
+    }
+
+    Ok(ReplayReport {
+        canvas_id: canvas.id.clone(),
+        mode,
+        passed: mismatches.is_empty(),
+        mismatches,
+        report,
+    })
+}
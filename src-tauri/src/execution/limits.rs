@@ -0,0 +1,213 @@
+//! Resource limits for spawned commands.
+//!
+//! A request (or its profile) may cap a command's CPU time, wall-clock time
+//! and memory. Wall-clock time is enforced here, by killing the process tree
+//! like a timeout. CPU time and memory are enforced by the OS: rlimits
+//! (`RLIMIT_CPU`, `RLIMIT_AS`) set between fork and exec on Unix, and the
+//! command's Job Object on Windows. A run that breaks a limit ends with
+//! [`Termination::LimitExceeded`](super::Termination::LimitExceeded) and a
+//! [`ResourceLimitExceeded`] in its outcome.
+//!
+//! The OS does not say which limit killed a process, so that is inferred from
+//! how it ended: `SIGXCPU` for CPU time, an allocation failure for memory.
+//! [`ExecutionService`](super::ExecutionService) records every violation in
+//! the memory store as a `resource_limit_exceeded` error.
+
+use super::command::CommandOutcome;
+use crate::memory::{Command, Error};
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+use ts_rs::TS;
+
+/// Caps for one command; `None` leaves a resource unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ResourceLimits {
+    /// CPU time, user and system, in seconds
+    pub cpu_secs: Option<u64>,
+    /// Time from spawn to exit, in seconds
+    pub wall_secs: Option<u64>,
+    /// Address space (Unix) or committed memory of the process tree (Windows)
+    pub memory_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// These limits, with `fallback`'s where these have none
+    pub fn or(self, fallback: ResourceLimits) -> Self {
+        Self {
+            cpu_secs: self.cpu_secs.or(fallback.cpu_secs),
+            wall_secs: self.wall_secs.or(fallback.wall_secs),
+            memory_bytes: self.memory_bytes.or(fallback.memory_bytes),
+        }
+    }
+}
+
+/// Which limit a run broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ResourceLimit {
+    CpuTime,
+    WallTime,
+    Memory,
+}
+
+impl ResourceLimit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResourceLimit::CpuTime => "cpu_time",
+            ResourceLimit::WallTime => "wall_time",
+            ResourceLimit::Memory => "memory",
+        }
+    }
+}
+
+/// A command killed for breaking one of its [`ResourceLimits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ResourceLimitExceeded {
+    pub limit: ResourceLimit,
+    /// The configured limit, in seconds or bytes
+    pub max: u64,
+}
+
+impl std::fmt::Display for ResourceLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.limit {
+            ResourceLimit::CpuTime => write!(f, "CPU time limit of {}s exceeded", self.max),
+            ResourceLimit::WallTime => write!(f, "Wall-clock limit of {}s exceeded", self.max),
+            ResourceLimit::Memory => write!(f, "Memory limit of {} bytes exceeded", self.max),
+        }
+    }
+}
+
+impl std::error::Error for ResourceLimitExceeded {}
+
+/// Messages runtimes print when an allocation fails
+const ALLOCATION_FAILURES: &[&str] = &[
+    "out of memory",
+    "cannot allocate memory",
+    "memory allocation of",
+    "std::bad_alloc",
+    "memoryerror",
+    "failed to reserve",
+];
+
+/// Have `cmd` start with the CPU and memory rlimits of `limits`
+#[cfg(unix)]
+pub(super) fn apply(cmd: &mut tokio::process::Command, limits: &ResourceLimits) {
+    let (cpu_secs, memory_bytes) = (limits.cpu_secs, limits.memory_bytes);
+    if cpu_secs.is_none() && memory_bytes.is_none() {
+        return;
+    }
+    // SAFETY: the closure runs between fork and exec and only calls
+    // setrlimit, which is async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(secs) = cpu_secs {
+                // SIGXCPU at the soft limit; SIGKILL a second later should the
+                // command catch it
+                let limit = libc::rlimit {
+                    rlim_cur: secs as libc::rlim_t,
+                    rlim_max: secs.saturating_add(1) as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(bytes) = memory_bytes {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes as libc::rlim_t,
+                    rlim_max: bytes as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// The limit `status` shows the command broke, if any. `stderr` is searched
+/// for allocation failures when a memory limit is set.
+pub(super) fn violation(
+    limits: &ResourceLimits,
+    status: &ExitStatus,
+    stderr: &[u8],
+) -> Option<ResourceLimitExceeded> {
+    let exceeded = |limit, max| Some(ResourceLimitExceeded { limit, max });
+    if let Some(max) = limits.cpu_secs {
+        if cpu_exhausted(status) {
+            return exceeded(ResourceLimit::CpuTime, max);
+        }
+    }
+    if let Some(max) = limits.memory_bytes {
+        let stderr = String::from_utf8_lossy(stderr).to_lowercase();
+        if !status.success()
+            && (memory_exhausted(status) || ALLOCATION_FAILURES.iter().any(|m| stderr.contains(m)))
+        {
+            return exceeded(ResourceLimit::Memory, max);
+        }
+    }
+    None
+}
+
+#[cfg(unix)]
+fn cpu_exhausted(status: &ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    // SIGKILL alone is not enough: the OOM killer and others send it too
+    status.signal() == Some(libc::SIGXCPU)
+}
+
+/// Aborts from a failed allocation
+#[cfg(unix)]
+fn memory_exhausted(status: &ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    matches!(status.signal(), Some(libc::SIGABRT | libc::SIGSEGV))
+}
+
+/// Windows ends a process over its job's time limit with this exit code
+#[cfg(windows)]
+fn cpu_exhausted(status: &ExitStatus) -> bool {
+    status.code() == Some(windows_sys::Win32::Foundation::ERROR_NOT_ENOUGH_QUOTA as i32)
+}
+
+#[cfg(windows)]
+fn memory_exhausted(status: &ExitStatus) -> bool {
+    status.code() == Some(windows_sys::Win32::Foundation::STATUS_NO_MEMORY)
+}
+
+/// The `resource_limit_exceeded` error recorded for `command`, a run that
+/// broke `exceeded`
+pub fn violation_error(
+    command: &Command,
+    outcome: &CommandOutcome,
+    exceeded: &ResourceLimitExceeded,
+    actor: &str,
+) -> Error {
+    let mut error = Error::new(
+        command.id.clone(),
+        command.session_id.clone(),
+        "resource_limit_exceeded".to_string(),
+        "high".to_string(),
+        exceeded.to_string(),
+    );
+    error.exit_code = outcome.exit_code;
+    let stderr = outcome.stderr.trim();
+    if !stderr.is_empty() {
+        let skip = stderr.chars().count().saturating_sub(500);
+        error.stderr_snippet = Some(stderr.chars().skip(skip).collect());
+    }
+    error.context = serde_json::json!({
+        "limit": exceeded.limit,
+        "max": exceeded.max,
+        "actor": actor,
+    });
+    error
+}
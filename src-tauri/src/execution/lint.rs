@@ -0,0 +1,315 @@
+//! Canvas linting.
+//!
+//! Checks a canvas file for problems that only show up when it runs, or that
+//! should not be shared at all:
+//!
+//! - terminal nodes with `{{name}}` placeholders that have no value
+//! - display and transform nodes with an input port nothing is connected to
+//! - commands written for a shell (pipes, `&&`, redirects, `$VAR`, `~`), which
+//!   fail or mean something else under direct exec
+//! - working directories and path arguments covering `/` or the home directory
+//! - secret literals anywhere in a node's config, found with the capture-stage
+//!   scrub rules and by env names such as `API_TOKEN` holding a literal value
+//! - assertions whose regex or JSONPath does not parse
+//! - connections to unknown nodes and cycles
+//!
+//! Findings carry a [`UserMessage`] per rule, so the UI can show them in its
+//! own words. Secret values are never part of a finding.
+
+use super::canvas::{execution_order, CanvasDocument, CanvasNode};
+use super::policy::CommandPolicy;
+use super::resolve::{resolve_execution, ExecutionRequest};
+use super::safety::{program_name, DestructivePolicy, SHELLS};
+use crate::i18n::UserMessage;
+use crate::memory::{ScrubStage, Scrubber};
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+use ts_rs::TS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum LintSeverity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct LintFinding {
+    pub severity: LintSeverity,
+    /// `None` for findings about the canvas as a whole
+    pub node_id: Option<String>,
+    pub message: UserMessage,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct LintReport {
+    pub canvas_id: String,
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|f| f.severity == LintSeverity::Error)
+    }
+}
+
+/// Read a canvas file: JSON for `.json`, YAML (the frontend's format) otherwise
+pub fn load_canvas_file(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+    {
+        serde_json::from_str(&text)
+            .with_context(|| format!("Invalid canvas JSON in {}", path.display()))
+    } else {
+        serde_yaml::from_str(&text)
+            .with_context(|| format!("Invalid canvas YAML in {}", path.display()))
+    }
+}
+
+/// Lint a canvas document. `variables` are the values a run would be given.
+pub fn lint_canvas(
+    raw: &Value,
+    scrubber: &Scrubber,
+    variables: &HashMap<String, String>,
+) -> Result<LintReport> {
+    let canvas: CanvasDocument =
+        serde_json::from_value(raw.clone()).context("Not a canvas document")?;
+    let mut findings = Vec::new();
+    if let Err(message) = execution_order(&canvas) {
+        findings.push(LintFinding {
+            severity: LintSeverity::Error,
+            node_id: None,
+            message,
+        });
+    }
+
+    let raw_nodes = raw.get("nodes").and_then(Value::as_array);
+    for (i, node) in canvas.nodes.iter().enumerate() {
+        let mut finding = |severity, message: UserMessage| {
+            findings.push(LintFinding {
+                severity,
+                node_id: Some(node.id.clone()),
+                message: message.with("node", node_label(node)),
+            })
+        };
+        if let Some(request) = node.request(variables) {
+            for name in unresolved_variables(&request)? {
+                finding(
+                    LintSeverity::Error,
+                    UserMessage::new("lint-unresolved-variable").with("variable", name),
+                );
+            }
+            for token in shell_syntax(node) {
+                finding(
+                    LintSeverity::Error,
+                    UserMessage::new("lint-shell-syntax").with("token", token),
+                );
+            }
+            for path in broad_paths(node) {
+                finding(
+                    LintSeverity::Warning,
+                    UserMessage::new("lint-broad-path").with("path", path),
+                );
+            }
+        }
+        for error in node.expect.iter().filter_map(|a| a.problem()) {
+            finding(
+                LintSeverity::Error,
+                UserMessage::new("lint-invalid-assertion").with("error", error),
+            );
+        }
+        for port in unconnected_inputs(&canvas, node) {
+            finding(
+                LintSeverity::Warning,
+                UserMessage::new("lint-unconnected-input").with("port", port),
+            );
+        }
+        if let Some(raw_node) = raw_nodes.and_then(|nodes| nodes.get(i)) {
+            for (field, rule) in secret_literals(raw_node, scrubber) {
+                finding(
+                    LintSeverity::Error,
+                    UserMessage::new("lint-secret-literal")
+                        .with("field", field)
+                        .with("rule", rule),
+                );
+            }
+        }
+    }
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    Ok(LintReport {
+        canvas_id: canvas.id,
+        findings,
+    })
+}
+
+fn node_label(node: &CanvasNode) -> &str {
+    if node.label.is_empty() {
+        &node.id
+    } else {
+        &node.label
+    }
+}
+
+fn unresolved_variables(request: &ExecutionRequest) -> Result<Vec<String>> {
+    let policy = CommandPolicy::from(DestructivePolicy::Allow);
+    Ok(resolve_execution(request, &HashMap::new(), &policy)?.unresolved_variables)
+}
+
+/// Shell syntax in a terminal node that direct exec passes through literally
+fn shell_syntax(node: &CanvasNode) -> Vec<String> {
+    let Some(command) = &node.command else {
+        return Vec::new();
+    };
+    // An explicit shell gets its script as one argument
+    if SHELLS.contains(&program_name(command)) {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    if command.trim().contains(char::is_whitespace) {
+        found.push(command.trim().to_string());
+    }
+    for arg in &node.args {
+        let token = match arg.as_str() {
+            "|" | "||" | "&&" | ";" | "&" | ">" | ">>" | "<" | "2>" | "2>&1" => Some(arg.clone()),
+            _ if arg.contains("$(") => Some("$(".to_string()),
+            _ if arg.contains('`') => Some("`".to_string()),
+            _ if arg == "~" || arg.starts_with("~/") => Some("~".to_string()),
+            _ => env_reference().find(arg).map(|m| m.as_str().to_string()),
+        };
+        if let Some(token) = token {
+            if !found.contains(&token) {
+                found.push(token);
+            }
+        }
+    }
+    found
+}
+
+fn env_reference() -> &'static Regex {
+    static ENV: OnceLock<Regex> = OnceLock::new();
+    ENV.get_or_init(|| Regex::new(r"\$\{?[A-Za-z_][A-Za-z0-9_]*\}?").unwrap())
+}
+
+/// The whole file system or home directory, or everything below it
+fn broad_path(path: &str) -> bool {
+    let trimmed = path.trim().trim_end_matches(['/', '\\']);
+    let trimmed = trimmed
+        .strip_suffix("/**")
+        .or_else(|| trimmed.strip_suffix("/*"))
+        .unwrap_or(trimmed);
+    matches!(
+        trimmed,
+        "" | "~" | "$HOME" | "${HOME}" | "{{home}}" | "{{ home }}" | "**" | "*"
+    ) || (trimmed.len() == 2 && trimmed.ends_with(':'))
+        || dirs::home_dir().is_some_and(|home| Path::new(trimmed) == home)
+}
+
+fn broad_paths(node: &CanvasNode) -> Vec<String> {
+    let mut paths: Vec<String> = node
+        .cwd
+        .iter()
+        .filter(|cwd| !cwd.is_empty() && broad_path(cwd))
+        .cloned()
+        .collect();
+    for arg in &node.args {
+        let path_like = arg.starts_with(['/', '~', '$', '{']) || arg.contains("**");
+        if path_like && broad_path(arg) && !paths.contains(arg) {
+            paths.push(arg.clone());
+        }
+    }
+    paths
+}
+
+/// Input ports of a display or transform node with no incoming connection
+fn unconnected_inputs(canvas: &CanvasDocument, node: &CanvasNode) -> Vec<String> {
+    if !matches!(node.node_type.as_str(), "display" | "transform") {
+        return Vec::new();
+    }
+    let incoming: Vec<Option<&str>> = canvas
+        .connections
+        .iter()
+        .filter(|c| c.to == node.id)
+        .map(|c| c.to_port.as_deref())
+        .collect();
+    node.inputs
+        .iter()
+        .filter(|port| {
+            !incoming.iter().any(|to_port| match to_port {
+                Some(to_port) => *to_port == port.id || *to_port == port.name,
+                // Older canvases do not record ports
+                None => true,
+            })
+        })
+        .map(|port| {
+            if port.name.is_empty() {
+                port.id.clone()
+            } else {
+                port.name.clone()
+            }
+        })
+        .collect()
+}
+
+fn secret_env_name() -> &'static Regex {
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    NAME.get_or_init(|| {
+        Regex::new(r"(?i)(token|secret|passw(or)?d|api_?key|private_?key|credential)").unwrap()
+    })
+}
+
+/// Fields of a raw node holding a secret, with the rule that found each
+fn secret_literals(node: &Value, scrubber: &Scrubber) -> Vec<(String, String)> {
+    let mut strings = Vec::new();
+    collect_strings(node, String::new(), &mut strings);
+    let mut found = Vec::new();
+    for (field, text) in strings {
+        let (_, hits) = scrubber.scrub(text, ScrubStage::Capture);
+        if let Some(hit) = hits.first() {
+            found.push((field, hit.rule.clone()));
+            continue;
+        }
+        // A literal value in a secret-looking env var; references are fine
+        if let Some(name) = field.strip_prefix("env.") {
+            let literal = !text.is_empty() && !text.contains("{{") && !text.starts_with('$');
+            if literal && secret_env_name().is_match(name) {
+                found.push((field, "env-name".to_string()));
+            }
+        }
+    }
+    found
+}
+
+fn collect_strings<'a>(value: &'a Value, path: String, out: &mut Vec<(String, &'a str)>) {
+    match value {
+        Value::String(text) => out.push((path, text)),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_strings(item, format!("{}[{}]", path, i), out);
+            }
+        }
+        Value::Object(fields) => {
+            for (name, item) in fields {
+                let path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                collect_strings(item, path, out);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,419 @@
+//! Container log ingestion: follow `docker logs -f` or `kubectl logs -f`.
+//!
+//! A [`LogFollowSpec`] names a container or pod; following it runs the
+//! matching CLI through the [`ExecutionService`], so the usual safety gate
+//! applies, and stores the stream as one long-running command with
+//! [`LogIngest`]. The log is then chunked, compressed, searchable and
+//! followable with `tail_output` like any command's output, and error lines
+//! become error records as they arrive. Callers get a short [`LogEvent`]
+//! stream (new errors, the end) instead of the log itself.
+
+use super::command::{build_command, CommandSpec};
+use super::resolve::ExecutionRequest;
+use super::service::ExecutionService;
+use super::signal::{ProcessTree, Signal};
+use crate::i18n::UserMessage;
+use crate::memory::logs::LogIngest;
+use crate::memory::{LiveOutputs, StoreOpener};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, oneshot};
+use ts_rs::TS;
+
+/// Output is stored in chunks of at most this many bytes per stream
+const MAX_CHUNK_BYTES: usize = 32 * 1024;
+/// Output is stored at least this often while the log is written to
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a log stream comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+#[ts(export)]
+pub enum LogSource {
+    Docker {
+        container: String,
+    },
+    Kubernetes {
+        pod: String,
+        #[serde(default)]
+        namespace: Option<String>,
+        /// Container in a pod with several
+        #[serde(default)]
+        container: Option<String>,
+        /// kubeconfig context; the current one when unset
+        #[serde(default)]
+        context: Option<String>,
+    },
+}
+
+impl LogSource {
+    /// Short name for the stream, such as `docker:web` or `k8s:prod/api-7f9c`
+    pub fn label(&self) -> String {
+        match self {
+            LogSource::Docker { container } => format!("docker:{}", container),
+            LogSource::Kubernetes {
+                pod,
+                namespace,
+                container,
+                ..
+            } => {
+                let mut label =
+                    format!("k8s:{}/{}", namespace.as_deref().unwrap_or("default"), pod);
+                if let Some(container) = container {
+                    label.push('/');
+                    label.push_str(container);
+                }
+                label
+            }
+        }
+    }
+}
+
+/// A log stream to follow
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LogFollowSpec {
+    #[serde(flatten)]
+    pub source: LogSource,
+    /// Lines of history to start with; all of it when unset
+    #[serde(default)]
+    pub tail: Option<u32>,
+    /// Only lines newer than this: a duration (`10m`) or, for Docker, a timestamp
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Canvas node the stream belongs to, echoed in its events
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+impl LogFollowSpec {
+    /// The `docker logs` or `kubectl logs` command that follows the stream
+    pub fn request(&self) -> ExecutionRequest {
+        let mut args = vec!["logs".to_string(), "-f".to_string()];
+        let mut option = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                args.push(name.to_string());
+                args.push(value);
+            }
+        };
+        let (command, name) = match &self.source {
+            LogSource::Docker { container } => ("docker", container),
+            LogSource::Kubernetes {
+                pod,
+                namespace,
+                container,
+                context,
+            } => {
+                option("-n", namespace.clone());
+                option("-c", container.clone());
+                option("--context", context.clone());
+                ("kubectl", pod)
+            }
+        };
+        option("--tail", self.tail.map(|t| t.to_string()));
+        option("--since", self.since.clone());
+        // A name starting with `-` is still a name
+        args.push("--".to_string());
+        args.push(name.clone());
+        ExecutionRequest {
+            command: command.to_string(),
+            args,
+            ..Default::default()
+        }
+    }
+}
+
+/// A followed stream, as listed by [`LogFollowManager::list`]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LogFollowInfo {
+    pub id: String,
+    pub node_id: Option<String>,
+    pub source: String,
+    /// The stored command holding the log, for `tail_output` and search
+    pub command_id: String,
+    pub command_line: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// What happened in a followed stream
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum LogEventKind {
+    /// An error line not seen before in this stream
+    Error { severity: String, message: String },
+    /// The stream ended: stopped with `logs_stop`, or the CLI exited, e.g.
+    /// because the container is gone
+    Ended {
+        exit_code: Option<i32>,
+        stopped: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEvent {
+    pub follow_id: String,
+    pub node_id: Option<String>,
+    pub command_id: String,
+    #[serde(flatten)]
+    pub kind: LogEventKind,
+}
+
+type Follows = Arc<Mutex<HashMap<String, (LogFollowInfo, oneshot::Sender<()>)>>>;
+
+/// Followed log streams, each a background task
+#[derive(Default)]
+pub struct LogFollowManager {
+    follows: Follows,
+}
+
+impl LogFollowManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start following. Events go to `events` until the stream ends.
+    pub async fn start(
+        &self,
+        execution: Arc<ExecutionService>,
+        open_store: StoreOpener,
+        live: Arc<LiveOutputs>,
+        spec: LogFollowSpec,
+        events: mpsc::UnboundedSender<LogEvent>,
+    ) -> Result<LogFollowInfo, UserMessage> {
+        let request = spec.request();
+        let command = execution.admit(&request, None, "logs")?;
+        let mut child = build_command(&command, None)
+            .and_then(|mut cmd| {
+                cmd.stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+                Ok(cmd.spawn()?)
+            })
+            .map_err(UserMessage::wrap("log-follow-failed"))?;
+        let tree = child.id().and_then(|pid| ProcessTree::of(pid).ok());
+
+        let source = spec.source.label();
+        let store = (open_store)()
+            .await
+            .map_err(UserMessage::wrap("memory-store-unavailable"))?;
+        let ingest = LogIngest::start(
+            Arc::new(store),
+            &live,
+            &source,
+            &command.command,
+            command.args.clone(),
+            &cwd(&command),
+        )
+        .await
+        .map_err(UserMessage::wrap("log-follow-failed"))?;
+
+        let info = LogFollowInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            node_id: spec.node_id.clone(),
+            source,
+            command_id: ingest.command_id().to_string(),
+            command_line: command.command_line(),
+            started_at: Utc::now(),
+        };
+        let (stop, stopped) = oneshot::channel();
+        self.follows
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(info.id.clone(), (info.clone(), stop));
+
+        let (chunks, received) = mpsc::unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(read_stream(stdout, "stdout", chunks.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(read_stream(stderr, "stderr", chunks));
+        }
+
+        let follows = Arc::clone(&self.follows);
+        let follow = Follow {
+            id: info.id.clone(),
+            node_id: spec.node_id,
+            command_id: info.command_id.clone(),
+            events,
+        };
+        tokio::spawn(async move {
+            follow
+                .run(ingest, &live, child, tree, received, stopped)
+                .await;
+            follows
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&follow.id);
+        });
+        Ok(info)
+    }
+
+    /// Stop following a stream. Returns whether it was followed.
+    pub fn stop(&self, id: &str) -> bool {
+        match self
+            .follows
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+        {
+            Some((_, stop)) => {
+                let _ = stop.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<LogFollowInfo> {
+        let mut follows: Vec<LogFollowInfo> = self
+            .follows
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|(info, _)| info.clone())
+            .collect();
+        follows.sort_by_key(|f| f.started_at);
+        follows
+    }
+}
+
+fn cwd(command: &CommandSpec) -> String {
+    command
+        .cwd
+        .clone()
+        .filter(|c| !c.is_empty())
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .map(|d| d.display().to_string())
+        })
+        .unwrap_or_default()
+}
+
+/// Send what `stream` outputs to `chunks` until it closes
+async fn read_stream(
+    mut stream: impl tokio::io::AsyncRead + Unpin,
+    name: &'static str,
+    chunks: mpsc::UnboundedSender<(&'static str, Vec<u8>)>,
+) {
+    let mut buf = vec![0u8; 8192];
+    while let Ok(n @ 1..) = stream.read(&mut buf).await {
+        if chunks.send((name, buf[..n].to_vec())).is_err() {
+            return;
+        }
+    }
+}
+
+/// Event sending for one followed stream
+struct Follow {
+    id: String,
+    node_id: Option<String>,
+    command_id: String,
+    events: mpsc::UnboundedSender<LogEvent>,
+}
+
+impl Follow {
+    fn emit(&self, kind: LogEventKind) {
+        let _ = self.events.send(LogEvent {
+            follow_id: self.id.clone(),
+            node_id: self.node_id.clone(),
+            command_id: self.command_id.clone(),
+            kind,
+        });
+    }
+
+    fn emit_errors(&self, errors: Vec<crate::memory::Error>) {
+        for error in errors {
+            self.emit(LogEventKind::Error {
+                severity: error.severity,
+                message: error.message,
+            });
+        }
+    }
+
+    /// Store `pending` output, reporting errors found in it. A store that
+    /// fails ends the stream, since its output would be lost.
+    async fn flush(
+        &self,
+        ingest: &mut LogIngest,
+        live: &LiveOutputs,
+        pending: &mut HashMap<&'static str, Vec<u8>>,
+    ) -> anyhow::Result<()> {
+        for (stream, data) in pending.iter_mut() {
+            if data.is_empty() {
+                continue;
+            }
+            let errors = ingest.push(live, stream, std::mem::take(data)).await?;
+            self.emit_errors(errors);
+        }
+        Ok(())
+    }
+
+    async fn run(
+        &self,
+        mut ingest: LogIngest,
+        live: &LiveOutputs,
+        mut child: tokio::process::Child,
+        tree: Option<ProcessTree>,
+        mut chunks: mpsc::UnboundedReceiver<(&'static str, Vec<u8>)>,
+        mut stopped: oneshot::Receiver<()>,
+    ) {
+        let mut pending: HashMap<&'static str, Vec<u8>> = HashMap::new();
+        let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+        let mut stop = false;
+        let mut failed = false;
+        loop {
+            tokio::select! {
+                _ = &mut stopped => {
+                    stop = true;
+                    break;
+                }
+                chunk = chunks.recv() => {
+                    let Some((stream, data)) = chunk else { break };
+                    let buffered = pending.entry(stream).or_default();
+                    buffered.extend_from_slice(&data);
+                    if buffered.len() >= MAX_CHUNK_BYTES {
+                        if let Err(e) = self.flush(&mut ingest, live, &mut pending).await {
+                            log::warn!("[logs] Stopped following {}: {:#}", self.command_id, e);
+                            failed = true;
+                            break;
+                        }
+                    }
+                }
+                _ = flush.tick() => {
+                    if let Err(e) = self.flush(&mut ingest, live, &mut pending).await {
+                        log::warn!("[logs] Stopped following {}: {:#}", self.command_id, e);
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if stop || failed {
+            // `kubectl logs` leaves nothing behind, but a wrapper script might
+            if let Some(tree) = &tree {
+                let _ = tree.signal(Signal::Kill);
+            }
+            let _ = child.kill().await;
+        }
+        let exit_code = child.wait().await.ok().and_then(|status| status.code());
+        if !failed {
+            let _ = self.flush(&mut ingest, live, &mut pending).await;
+        }
+        match ingest.finish(live, exit_code, stop).await {
+            Ok(errors) => self.emit_errors(errors),
+            Err(e) => log::warn!("[logs] Failed to finish {}: {:#}", self.command_id, e),
+        }
+        self.emit(LogEventKind::Ended {
+            exit_code,
+            stopped: stop,
+        });
+    }
+}
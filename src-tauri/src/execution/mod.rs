@@ -1,5 +1,79 @@
-//! Parallel execution system.
+//! Parallel execution system, one-shot, pipelined and PTY-backed command execution,
+//! benchmarks, sandboxed previews, watch mode, and canvas linting, debugging,
+//! simulation and record/replay fixtures.
 
+pub mod assertions;
+pub mod backend;
+pub mod benchmark;
+pub mod canvas;
+pub mod command;
+pub mod debug;
+pub mod dry_run;
+pub mod elevation;
+pub mod fixture;
+pub mod limits;
+pub mod lint;
+pub mod logs;
+pub mod output;
+pub mod pipeline;
+pub mod policy;
+pub mod project;
+pub mod pty;
+pub mod pwsh;
+pub mod queue;
+pub mod resolve;
 pub mod runner;
+pub mod running;
+pub mod safety;
+pub mod sandbox;
+pub mod service;
+pub mod signal;
+pub mod simulate;
+pub mod target;
+pub mod test_report;
+pub mod watch;
 
+pub use crate::memory::ansi::{AnsiMode, StyledSpan};
+pub use assertions::{Assertion, AssertionResult};
+pub use backend::{Backend, ExecutionBackend};
+pub use benchmark::{run_benchmark, BenchmarkResult, BenchmarkSpec};
+pub use canvas::{run_canvas, CanvasDocument, CanvasRunReport};
+pub use command::{
+    run_command, run_command_streaming, run_command_until, CommandOutcome, CommandSpec,
+    ExecutionMode, OutputChunk, OutputStream, StopConditions, Termination,
+};
+pub use debug::{DebugCommand, DebugEvent, DebugManager, DebugSpec, PauseState};
+pub use dry_run::DryRun;
+pub use elevation::{
+    record_elevated, run_elevated, ElevatedRun, ElevationMethod, ElevationPrompts, Password,
+};
+pub use fixture::{record_canvas, replay_fixture, CanvasFixture, ReplayMode, ReplayReport};
+pub use limits::{ResourceLimit, ResourceLimitExceeded, ResourceLimits};
+pub use lint::{lint_canvas, load_canvas_file, LintFinding, LintReport, LintSeverity};
+pub use logs::{LogEvent, LogEventKind, LogFollowInfo, LogFollowManager, LogFollowSpec, LogSource};
+pub use output::{EncodedOutput, OutputCap, SpilledOutput, TextEncoding};
+pub use pipeline::{
+    run_pipeline_until, PipelineOutcome, PipelineSpec, PipelineStage, StageOutcome,
+};
+pub use policy::{CommandPolicy, PolicyDecision, PolicyRule, PolicySource, RuleAction};
+pub use project::{Project, ProjectProfile};
+pub use pty::{PtyDimensions, PtyEvent, PtyEventKind, PtyInfo, PtyManager, PtyScreen};
+pub use queue::{ConcurrencyConfig, QueueOrder, QueueSnapshot, QueuedExecution};
+pub use resolve::{
+    resolve_execution, ExecutionProfile, ExecutionRequest, PolicyPreview, ResolvedExecution,
+};
 pub use runner::*;
+pub use running::{CancelHandle, RunningCommands};
+pub use safety::{Admission, CommandGate, DestructivePolicy, SafetyConfig};
+pub use sandbox::{
+    run_sandboxed, ChangeKind, FileChange, SandboxReport, SandboxSpec, ShadowStrategy,
+};
+pub use service::ExecutionService;
+pub use signal::{ProcessTree, Signal};
+pub use simulate::{simulate_canvas, NodeMock, SimulationSpec};
+pub use target::ExecutionTarget;
+pub use test_report::TestReportFormat;
+pub use watch::{WatchEvent, WatchEventKind, WatchInfo, WatchManager, WatchSpec};
+
+#[cfg(test)]
+mod tests;
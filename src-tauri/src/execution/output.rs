@@ -0,0 +1,251 @@
+//! Caps on the output a command run keeps.
+//!
+//! A command that prints gigabytes would otherwise be held in memory and sent
+//! over IPC in full. Each stream keeps its first [`OutputCap::max_bytes`]; the
+//! rest is counted but dropped, and not streamed either. With
+//! [`OutputCap::spill`] set, a stream that goes over is written in full to a
+//! temporary file instead, which the outcome and the memory store point to.
+//!
+//! Output that is binary or not UTF-8 (`curl` of an image, `tar -c`) is
+//! [`encode`]d as base64 rather than decoded lossily, with its detected
+//! content type. Escape sequences in text output are kept, stripped, or
+//! parsed into styled spans, per the spec's [`AnsiMode`].
+
+use super::command::OutputStream;
+use crate::memory::ansi::{self, AnsiMode, StyledSpan};
+use crate::memory::content::detect_content;
+use crate::memory::{Artifact, Command};
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use ts_rs::TS;
+
+/// Bytes of each stream a run keeps unless configured otherwise
+pub const DEFAULT_MAX_OUTPUT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// How much of a command's output is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct OutputCap {
+    /// Bytes of stdout, and of stderr, kept in the outcome and streamed
+    pub max_bytes: u64,
+    /// Write a stream that goes over `max_bytes` in full to a temporary file
+    pub spill: bool,
+}
+
+impl Default for OutputCap {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            spill: false,
+        }
+    }
+}
+
+/// The full output of a truncated stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SpilledOutput {
+    pub stream: OutputStream,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+/// One stream of a run, held to its [`OutputCap`]
+pub(super) struct Capture {
+    stream: OutputStream,
+    cap: OutputCap,
+    kept: Vec<u8>,
+    total: u64,
+    spill: Option<(tokio::fs::File, PathBuf)>,
+}
+
+impl Capture {
+    pub(super) fn new(stream: OutputStream, cap: OutputCap) -> Self {
+        Self {
+            stream,
+            cap,
+            kept: Vec::new(),
+            total: 0,
+            spill: None,
+        }
+    }
+
+    /// Take `data` as read from the stream, returning the part that is kept
+    pub(super) async fn push<'a>(&mut self, data: &'a [u8]) -> &'a [u8] {
+        self.total += data.len() as u64;
+        let room = (self.cap.max_bytes as usize).saturating_sub(self.kept.len());
+        let (kept, over) = data.split_at(room.min(data.len()));
+        self.kept.extend_from_slice(kept);
+        if !over.is_empty() {
+            self.spill(over).await;
+        }
+        kept
+    }
+
+    /// Write to the spill file, opening it with what was kept so far. A
+    /// spill that fails is logged and given up; the run goes on.
+    async fn spill(&mut self, over: &[u8]) {
+        if !self.cap.spill {
+            return;
+        }
+        if let Err(e) = self.write_spill(over).await {
+            log::warn!("[execution] Full output not spilled: {:#}", e);
+            self.cap.spill = false;
+            self.spill = None;
+        }
+    }
+
+    async fn write_spill(&mut self, over: &[u8]) -> Result<()> {
+        if self.spill.is_none() {
+            let (mut file, path) = spill_file(self.stream).await?;
+            file.write_all(&self.kept).await?;
+            self.spill = Some((file, path));
+        }
+        if let Some((file, _)) = &mut self.spill {
+            file.write_all(over).await?;
+        }
+        Ok(())
+    }
+
+    /// The kept bytes, the total the stream wrote, and where it was spilled
+    pub(super) async fn finish(mut self) -> (Vec<u8>, u64, Option<SpilledOutput>) {
+        let spilled = match self.spill.take() {
+            Some((mut file, path)) => match file.flush().await {
+                Ok(()) => Some(SpilledOutput {
+                    stream: self.stream,
+                    path,
+                    size_bytes: self.total,
+                }),
+                Err(e) => {
+                    log::warn!("[execution] Full output not spilled: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        (self.kept, self.total, spilled)
+    }
+}
+
+async fn spill_file(stream: OutputStream) -> Result<(tokio::fs::File, PathBuf)> {
+    let name = match stream {
+        OutputStream::Stdout => "stdout",
+        OutputStream::Stderr => "stderr",
+    };
+    let path = std::env::temp_dir().join(format!(
+        "runebook-output-{}.{}.log",
+        uuid::Uuid::new_v4(),
+        name
+    ));
+    let file = tokio::fs::File::create(&path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok((file, path))
+}
+
+/// An artifact pointing at a spilled stream of `command`. The content stays
+/// in the file; the artifact holds its path.
+pub fn spill_artifact(command: &Command, spilled: &SpilledOutput) -> Artifact {
+    let mut artifact = Artifact::new(
+        "output_file".to_string(),
+        "text/plain".to_string(),
+        Vec::new(),
+    );
+    artifact.session_id = Some(command.session_id.clone());
+    artifact.command_id = Some(command.id.clone());
+    artifact.size_bytes = spilled.size_bytes;
+    artifact.source = "capture".to_string();
+    artifact.metadata = serde_json::json!({
+        "stream": spilled.stream,
+        "path": spilled.path,
+    });
+    artifact
+}
+
+/// How a stream's text is carried in an outcome or chunk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum TextEncoding {
+    /// UTF-8 text, as the command wrote it
+    #[default]
+    Utf8,
+    /// The bytes the command wrote, base64-encoded: binary output, or text
+    /// that is not UTF-8
+    Base64,
+}
+
+impl TextEncoding {
+    pub fn is_utf8(&self) -> bool {
+        *self == TextEncoding::Utf8
+    }
+}
+
+/// Output bytes made fit for JSON without losing any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedOutput {
+    pub text: String,
+    pub encoding: TextEncoding,
+    /// Detected type, e.g. `image/png`; `text/plain` for text
+    pub content_type: String,
+}
+
+/// `bytes` as text when they are UTF-8 without NULs, base64 otherwise,
+/// whatever signature they start with. A sequence cut off at the end, as
+/// truncation leaves one, still counts as UTF-8.
+pub fn encode(bytes: &[u8]) -> EncodedOutput {
+    let utf8 = match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if utf8 && !bytes.contains(&0) {
+        EncodedOutput {
+            text: String::from_utf8_lossy(bytes).into_owned(),
+            encoding: TextEncoding::Utf8,
+            content_type: "text/plain".to_string(),
+        }
+    } else {
+        EncodedOutput {
+            text: base64::engine::general_purpose::STANDARD.encode(bytes),
+            encoding: TextEncoding::Base64,
+            content_type: detect_content(bytes).mime_type,
+        }
+    }
+}
+
+/// Turn the CRLF line endings of text `output` into `\n`, as the Windows
+/// backends do; base64 output is left alone
+pub(super) fn normalize_crlf(output: &mut EncodedOutput) {
+    if output.encoding.is_utf8() {
+        output.text = super::backend::normalize_crlf(&output.text);
+    }
+}
+
+/// Where a UTF-8 sequence cut off at the end of `bytes` starts; `bytes.len()`
+/// when there is none. Holding that tail back until the next read keeps a
+/// character split across reads whole.
+pub(super) fn complete_len(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => bytes.len(),
+    }
+}
+
+/// Handle the escape sequences of UTF-8 `output` per `mode`: they are removed
+/// from its text unless kept, and with [`AnsiMode::Spans`] its styles are
+/// returned
+pub(super) fn apply_ansi(output: &mut EncodedOutput, mode: AnsiMode) -> Vec<StyledSpan> {
+    if mode.is_keep() || !output.encoding.is_utf8() {
+        return Vec::new();
+    }
+    let spans = ansi::parse(&output.text);
+    output.text = ansi::plain_text(&spans);
+    match mode {
+        AnsiMode::Spans => spans,
+        _ => Vec::new(),
+    }
+}
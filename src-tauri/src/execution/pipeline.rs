@@ -0,0 +1,372 @@
+//! Pipelines without a shell.
+//!
+//! Commands run as direct processes, so shell operators are never
+//! interpreted and `ps aux | grep foo` cannot be written as one command. A
+//! pipeline runs each stage as its own process and wires one stage's stdout
+//! to the next one's stdin here, copying between the pipes, so no shell is
+//! involved at any point. Each stage is resolved and gated as a command of
+//! its own (see [`ExecutionService::execute_pipeline`](super::ExecutionService::execute_pipeline));
+//! the pipeline takes one `[concurrency]` slot and is killed as a whole.
+//!
+//! As in a shell, the pipeline's status is the last stage's, or with
+//! `pipefail` the last failing stage's. A stage that stops reading early, as
+//! `head` does, closes its end of the pipe, and the stage before it gets a
+//! broken pipe; that alone does not fail the pipeline.
+
+use super::command::{
+    build_command, feed, forward, run_time, CommandOutcome, CommandSpec, ExecutionMode,
+    OutputStream, StopConditions, Termination, TreeGuard,
+};
+use super::limits::{self, ResourceLimit, ResourceLimitExceeded};
+use super::output::{self, Capture, TextEncoding};
+use super::resolve::ExecutionRequest;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+use tokio::task::JoinSet;
+use ts_rs::TS;
+
+/// How long output is still read once a pipeline's stages have exited or
+/// been killed
+const OUTPUT_DRAIN: Duration = Duration::from_secs(1);
+
+/// One command of a pipeline
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct PipelineStage {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Set for this stage on top of the pipeline's `env`
+    pub env: HashMap<String, String>,
+    /// Token from a `confirmation-required` refusal of this stage
+    pub confirmation_token: Option<String>,
+}
+
+/// Commands to run with each one's stdout piped into the next one's stdin
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PipelineSpec {
+    /// What every stage shares: cwd, env, profile, variables, limits, target
+    /// and so on. Its `command` and `args` are not used, and its `stdin`
+    /// goes to the first stage.
+    #[serde(flatten)]
+    pub request: ExecutionRequest,
+    pub stages: Vec<PipelineStage>,
+    /// Fail when any stage fails, as `set -o pipefail` does, rather than
+    /// only when the last one does
+    #[serde(default)]
+    pub pipefail: bool,
+}
+
+impl PipelineSpec {
+    /// The request each stage is resolved from
+    pub fn stage_requests(&self) -> Vec<ExecutionRequest> {
+        self.stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let mut request = self.request.clone();
+                request.command = stage.command.clone();
+                request.args = stage.args.clone();
+                request.env.extend(stage.env.clone());
+                if i > 0 {
+                    request.stdin = None;
+                }
+                request
+            })
+            .collect()
+    }
+}
+
+/// How one stage of a pipeline ended
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StageOutcome {
+    pub command_line: String,
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub stderr: String,
+    #[serde(default, skip_serializing_if = "TextEncoding::is_utf8")]
+    #[ts(as = "Option<TextEncoding>", optional)]
+    pub stderr_encoding: TextEncoding,
+}
+
+/// Result of a finished pipeline
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PipelineOutcome {
+    /// The last stage's stdout; the exit code, stderr and success of the
+    /// stage the pipeline's status comes from; and the timing, termination
+    /// and output totals of the whole pipeline
+    #[serde(flatten)]
+    pub outcome: CommandOutcome,
+    /// Each stage, in order
+    pub stages: Vec<StageOutcome>,
+}
+
+/// A stage's stderr, once read to the end, or `None` for a pipe between stages
+type StageTask = Result<Option<(usize, Capture)>>;
+
+/// Run `specs` as a pipeline until every stage exits or `stop` calls for the
+/// pipeline to be killed. As with [`run_command_until`](super::run_command_until),
+/// a killed pipeline is an `Ok` outcome with the output read so far.
+pub async fn run_pipeline_until(
+    specs: &[CommandSpec],
+    pipefail: bool,
+    mut stop: StopConditions,
+) -> Result<PipelineOutcome> {
+    let Some(last) = specs.len().checked_sub(1) else {
+        anyhow::bail!("Empty pipeline");
+    };
+    if let Some(spec) = specs.iter().find(|s| s.mode == ExecutionMode::Pwsh) {
+        anyhow::bail!(
+            "{} runs as PowerShell, which has pipelines of its own; run it as a single command",
+            spec.command
+        );
+    }
+
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let mut children = Vec::with_capacity(specs.len());
+    // Kill every stage's processes, should this future be dropped early
+    let mut trees = Vec::with_capacity(specs.len());
+    for (i, spec) in specs.iter().enumerate() {
+        let mut cmd = build_command(spec, None)?;
+        cmd.stdin(match (i, &spec.stdin) {
+            (0, None) => Stdio::null(),
+            _ => Stdio::piped(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+        let child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to run {}", spec.command))?;
+        let tree = TreeGuard::of(&child, &spec.command);
+        #[cfg(windows)]
+        if let Some(tree) = &tree.0 {
+            tree.limit(&spec.limits)?;
+        }
+        trees.push(tree);
+        children.push(child);
+    }
+    // Signals go to the first stage; the others see its output end
+    if let (Some(handle), Some(tree)) = (&stop.cancel, &trees[0].0) {
+        handle.attach(Arc::clone(tree));
+    }
+
+    let mut tasks: JoinSet<StageTask> = JoinSet::new();
+    for (i, (spec, child)) in specs.iter().zip(&mut children).enumerate() {
+        let stderr = child.stderr.take().context("stderr not piped")?;
+        let spec = spec.clone();
+        tasks.spawn(async move {
+            let mut captured = Capture::new(OutputStream::Stderr, spec.output);
+            forward(stderr, OutputStream::Stderr, &spec, None, &mut captured).await?;
+            Ok(Some((i, captured)))
+        });
+    }
+    for i in 0..last {
+        let stdout = children[i].stdout.take().context("stdout not piped")?;
+        let stdin = children[i + 1].stdin.take().context("stdin not piped")?;
+        tasks.spawn(async move { pipe(stdout, stdin).await.map(|()| None) });
+    }
+    let stdin = children[0].stdin.take();
+    let stdout = children[last].stdout.take().context("stdout not piped")?;
+    let mut captured_stdout = Capture::new(OutputStream::Stdout, specs[last].output);
+    let mut captured_stderr: Vec<Option<Capture>> = specs.iter().map(|_| None).collect();
+    let wall_secs = specs.iter().filter_map(|s| s.limits.wall_secs).min();
+
+    let (termination, statuses) = {
+        let read = async {
+            tokio::try_join!(
+                feed(stdin, specs[0].stdin.as_deref()),
+                forward(
+                    stdout,
+                    OutputStream::Stdout,
+                    &specs[last],
+                    None,
+                    &mut captured_stdout
+                ),
+                drain(&mut tasks, &mut captured_stderr),
+            )
+        };
+        let timed_out = async {
+            match stop.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let cancelled = async {
+            match &mut stop.cancel {
+                Some(handle) => handle.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let wall_exceeded = async {
+            match wall_secs {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(read, timed_out, cancelled, wall_exceeded);
+        // The pipeline has exited when its stages are reaped, not when its
+        // output closes: as with a single command, something a stage left
+        // behind may hold the pipes open, or a stage may close them and run on
+        let mut reading = true;
+        let (termination, statuses) = loop {
+            tokio::select! {
+                read = &mut read, if reading => {
+                    read?;
+                    reading = false;
+                }
+                statuses = wait_all(&mut children) => {
+                    break (Termination::Exited, Some(statuses?));
+                }
+                _ = &mut timed_out => break (Termination::TimedOut, None),
+                _ = &mut cancelled => break (Termination::Cancelled, None),
+                _ = &mut wall_exceeded => break (Termination::LimitExceeded, None),
+            }
+        };
+        let statuses = match statuses {
+            Some(statuses) => statuses,
+            None => {
+                for tree in &trees {
+                    tree.kill();
+                }
+                for (spec, child) in specs.iter().zip(&mut children) {
+                    child
+                        .kill()
+                        .await
+                        .with_context(|| format!("Failed to kill {}", spec.command))?;
+                }
+                wait_all(&mut children).await?
+            }
+        };
+        // Keep what the stages wrote before they exited or were killed, but
+        // not for as long as something left behind keeps the pipes open
+        if reading {
+            if let Ok(read) = tokio::time::timeout(OUTPUT_DRAIN, &mut read).await {
+                read?;
+            }
+        }
+        (termination, statuses)
+    };
+    // The stages' pids may be reused once they are reaped
+    for tree in &mut trees {
+        tree.0 = None;
+    }
+
+    let (captured_stdout, mut total_bytes, stdout_spill) = captured_stdout.finish().await;
+    let mut kept_bytes = captured_stdout.len();
+    let mut spilled: Vec<_> = stdout_spill.into_iter().collect();
+    let mut limit_exceeded = match termination {
+        Termination::LimitExceeded => wall_secs.map(|max| ResourceLimitExceeded {
+            limit: ResourceLimit::WallTime,
+            max,
+        }),
+        _ => None,
+    };
+    let mut stages = Vec::with_capacity(specs.len());
+    let mut stderr_spans = Vec::with_capacity(specs.len());
+    for ((spec, status), captured) in specs.iter().zip(&statuses).zip(captured_stderr) {
+        let (captured, bytes, spill) = match captured {
+            Some(captured) => captured.finish().await,
+            None => (Vec::new(), 0, None),
+        };
+        total_bytes += bytes;
+        kept_bytes += captured.len();
+        spilled.extend(spill);
+        if termination == Termination::Exited && limit_exceeded.is_none() {
+            limit_exceeded = limits::violation(&spec.limits, status, &captured);
+        }
+        let mut stderr = output::encode(&captured);
+        if spec.normalizes_crlf() {
+            output::normalize_crlf(&mut stderr);
+        }
+        stderr_spans.push(output::apply_ansi(&mut stderr, spec.ansi));
+        stages.push(StageOutcome {
+            command_line: spec.command_line(),
+            exit_code: status.code(),
+            success: status.success(),
+            stderr: stderr.text,
+            stderr_encoding: stderr.encoding,
+        });
+    }
+    let termination = match (termination, &limit_exceeded) {
+        (Termination::Exited, Some(_)) => Termination::LimitExceeded,
+        _ => termination,
+    };
+    let (duration_ms, suspended_ms) = run_time(started_at, started);
+
+    let reported = if pipefail {
+        stages.iter().rposition(|s| !s.success).unwrap_or(last)
+    } else {
+        last
+    };
+    let mut stdout = output::encode(&captured_stdout);
+    if specs[last].normalizes_crlf() {
+        output::normalize_crlf(&mut stdout);
+    }
+    let stdout_spans = output::apply_ansi(&mut stdout, specs[last].ansi);
+    let stage = &stages[reported];
+    Ok(PipelineOutcome {
+        outcome: CommandOutcome {
+            stdout: stdout.text,
+            stderr: stage.stderr.clone(),
+            stdout_encoding: stdout.encoding,
+            stderr_encoding: stage.stderr_encoding,
+            content_type: stdout.content_type,
+            exit_code: stage.exit_code,
+            success: termination == Termination::Exited && stage.success,
+            duration_ms,
+            started_at,
+            termination,
+            objects: None,
+            limit_exceeded,
+            truncated: total_bytes > kept_bytes as u64,
+            total_bytes,
+            spilled,
+            suspended_ms: (suspended_ms > 0).then_some(suspended_ms),
+            stdout_spans,
+            stderr_spans: stderr_spans.swap_remove(reported),
+        },
+        stages,
+    })
+}
+
+/// Copy one stage's stdout into the next one's stdin, closing it at the end.
+/// When the next stage stops reading, the copy ends and dropping `from`
+/// gives the stage before it a broken pipe of its own, as in a shell.
+async fn pipe(mut from: ChildStdout, mut to: ChildStdin) -> Result<()> {
+    match tokio::io::copy(&mut from, &mut to).await {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result
+            .map(drop)
+            .context("Failed to pipe output between stages"),
+    }
+}
+
+/// Wait for every stage to exit, in order
+async fn wait_all(children: &mut [Child]) -> std::io::Result<Vec<ExitStatus>> {
+    let mut statuses = Vec::with_capacity(children.len());
+    for child in children {
+        statuses.push(child.wait().await?);
+    }
+    Ok(statuses)
+}
+
+/// Wait for the stderr readers and pipes in `tasks`, keeping each stage's
+/// stderr in its place in `captured`
+async fn drain(tasks: &mut JoinSet<StageTask>, captured: &mut [Option<Capture>]) -> Result<()> {
+    while let Some(joined) = tasks.join_next().await {
+        if let Some((i, stderr)) = joined?? {
+            captured[i] = Some(stderr);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Configurable command policy.
+//!
+//! `[[safety.rules]]` allow, deny, or require confirmation for command lines
+//! matching a regex, optionally only in one directory tree. Rules are tried in
+//! order and the first match decides, so an allow rule ahead of a broader deny
+//! carves out an exception. Commands no rule matches are refused when
+//! `default_deny` is set (an allowlist); otherwise the destructive-command
+//! classifier and the `destructive` policy decide, as they would without rules.
+//! Before any of that, a project's `allowed_commands` (see [`super::project`])
+//! refuses programs it does not list.
+
+use super::command::CommandSpec;
+use super::project::Project;
+use super::safety::{classify, DestructivePolicy, PolicyMatch, SafetyConfig};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// What a rule does with the commands it matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum RuleAction {
+    /// Run, without asking even if the command looks destructive
+    Allow,
+    Confirm,
+    Deny,
+}
+
+/// One `[[safety.rules]]` entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PolicyRule {
+    /// Names the rule in refusals and the audit log
+    pub name: String,
+    /// Regex matched against the command line, e.g. `^kubectl .*--context prod`
+    pub pattern: String,
+    /// Only commands run in this directory or below it; `~` is the home directory
+    #[serde(default)]
+    pub cwd: Option<String>,
+    pub action: RuleAction,
+    /// Shown when the rule holds a command back; defaults to naming the rule
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Which part of the policy made a decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PolicySource {
+    /// A `[[safety.rules]]` entry
+    Rule,
+    /// `default_deny`, with no rule matching
+    Default,
+    /// The destructive-command classifier
+    Destructive,
+    /// `allowed_commands` in the project's `.runebook.toml`
+    Project,
+}
+
+/// What the policy does with one command, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyDecision {
+    pub action: RuleAction,
+    pub source: PolicySource,
+    /// The rule that decided; `None` for a command nothing objects to
+    pub matched: Option<PolicyMatch>,
+}
+
+struct CompiledRule {
+    rule: PolicyRule,
+    pattern: Regex,
+    cwd: Option<PathBuf>,
+}
+
+/// Rules and destructive-command policy from `[safety]`, compiled
+pub struct CommandPolicy {
+    rules: Vec<CompiledRule>,
+    default_deny: bool,
+    destructive: DestructivePolicy,
+    /// Why the configured rules could not be used; every command is refused
+    invalid: Option<String>,
+}
+
+impl Default for CommandPolicy {
+    fn default() -> Self {
+        Self::from(DestructivePolicy::Confirm)
+    }
+}
+
+impl From<DestructivePolicy> for CommandPolicy {
+    /// No rules, only the destructive-command policy
+    fn from(destructive: DestructivePolicy) -> Self {
+        Self {
+            rules: Vec::new(),
+            default_deny: false,
+            destructive,
+            invalid: None,
+        }
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            home.join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+impl CommandPolicy {
+    /// Compile `config`'s rules; an invalid pattern or a duplicate name fails
+    /// the whole set
+    pub fn new(config: &SafetyConfig) -> Result<Self> {
+        let mut rules: Vec<CompiledRule> = Vec::with_capacity(config.rules.len());
+        for rule in &config.rules {
+            if rule.name.trim().is_empty() {
+                bail!("A command policy rule has no name");
+            }
+            if rules.iter().any(|r| r.rule.name == rule.name) {
+                bail!("Two command policy rules are named {:?}", rule.name);
+            }
+            let pattern = Regex::new(&rule.pattern).with_context(|| {
+                format!("Invalid pattern in command policy rule {:?}", rule.name)
+            })?;
+            rules.push(CompiledRule {
+                rule: rule.clone(),
+                pattern,
+                cwd: rule.cwd.as_deref().map(expand_home),
+            });
+        }
+        Ok(Self {
+            rules,
+            default_deny: config.default_deny,
+            destructive: config.destructive,
+            invalid: None,
+        })
+    }
+
+    /// A policy that refuses everything, for when the configured one does not
+    /// compile: failing open would drop the deny rules
+    pub fn invalid(error: &anyhow::Error) -> Self {
+        Self {
+            invalid: Some(format!("{:#}", error)),
+            ..Self::from(DestructivePolicy::Block)
+        }
+    }
+
+    pub fn destructive(&self) -> DestructivePolicy {
+        self.destructive
+    }
+
+    /// Decide what to do with `spec`; nothing is audited or remembered
+    pub fn decide(&self, spec: &CommandSpec) -> PolicyDecision {
+        if let Some(error) = &self.invalid {
+            return PolicyDecision {
+                action: RuleAction::Deny,
+                source: PolicySource::Default,
+                matched: Some(PolicyMatch::new(
+                    "invalid-policy",
+                    format!("The command policy in config.toml is invalid: {}", error),
+                )),
+            };
+        }
+
+        // A project can only narrow what runs, so its allowlist goes first
+        match Project::for_spec(spec) {
+            Ok(Some(project)) if !project.allows(spec) => {
+                return PolicyDecision {
+                    action: RuleAction::Deny,
+                    source: PolicySource::Project,
+                    matched: Some(PolicyMatch::new(
+                        "project-allowlist",
+                        format!(
+                            "{} is not in allowed_commands of {}",
+                            spec.command,
+                            project.file().display()
+                        ),
+                    )),
+                };
+            }
+            Err(error) => {
+                return PolicyDecision {
+                    action: RuleAction::Deny,
+                    source: PolicySource::Project,
+                    matched: Some(PolicyMatch::new(
+                        "invalid-project-profile",
+                        format!("{:#}", error),
+                    )),
+                };
+            }
+            _ => {}
+        }
+
+        let line = spec.command_line();
+        let cwd = spec
+            .cwd
+            .as_deref()
+            .filter(|c| !c.is_empty())
+            .map(expand_home)
+            .or_else(|| std::env::current_dir().ok());
+        let in_scope = |scope: &Path| cwd.as_deref().is_some_and(|cwd| cwd.starts_with(scope));
+        if let Some(CompiledRule { rule, .. }) = self
+            .rules
+            .iter()
+            .find(|r| r.pattern.is_match(&line) && r.cwd.as_deref().map_or(true, in_scope))
+        {
+            let reason = rule
+                .reason
+                .clone()
+                .unwrap_or_else(|| format!("Matches the {:?} policy rule", rule.name));
+            return PolicyDecision {
+                action: rule.action,
+                source: PolicySource::Rule,
+                matched: Some(PolicyMatch::new(&rule.name, reason)),
+            };
+        }
+
+        if self.default_deny {
+            return PolicyDecision {
+                action: RuleAction::Deny,
+                source: PolicySource::Default,
+                matched: Some(PolicyMatch::new(
+                    "default-deny",
+                    "No command policy rule allows this command",
+                )),
+            };
+        }
+
+        let matched = classify(&spec.command, &spec.args);
+        let action = match (&matched, self.destructive) {
+            (None, _) | (Some(_), DestructivePolicy::Allow) => RuleAction::Allow,
+            (Some(_), DestructivePolicy::Confirm) => RuleAction::Confirm,
+            (Some(_), DestructivePolicy::Block) => RuleAction::Deny,
+        };
+        PolicyDecision {
+            action,
+            source: PolicySource::Destructive,
+            matched,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Per-directory execution profiles.
+//!
+//! A `.runebook.toml` in a command's working directory, or the nearest
+//! directory above it that has one, sets defaults for every command run in
+//! that project:
+//!
+//! ```toml
+//! path = ["node_modules/.bin", "scripts"]   # put ahead of PATH
+//! allowed_commands = ["npm", "node", "git"]
+//! timeout_secs = 600
+//!
+//! [env]
+//! NODE_ENV = "development"
+//! ```
+//!
+//! Its `env` is the lowest layer: a named profile and the request both
+//! override it. `path` entries are relative to the file's directory and go in
+//! front of the `PATH` the command would otherwise get. `timeout_secs` is the
+//! wall-clock limit when neither request nor profile sets one. A non-empty
+//! `allowed_commands` only narrows what may run: other programs are refused,
+//! and the listed ones still go through `[safety]`.
+
+use super::command::{CommandSpec, ExecutionMode};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the file looked for in a command's working directory and above
+pub const PROJECT_FILE: &str = ".runebook.toml";
+
+/// A `.runebook.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectProfile {
+    pub env: HashMap<String, String>,
+    /// Directories put ahead of `PATH`, relative to the file's directory
+    pub path: Vec<String>,
+    /// Programs that may run in the project; any when empty
+    pub allowed_commands: Vec<String>,
+    /// Wall-clock limit in seconds, unless the request or profile sets one
+    pub timeout_secs: Option<u64>,
+}
+
+/// A `.runebook.toml` and the directory it applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub root: PathBuf,
+    pub profile: ProjectProfile,
+}
+
+impl Project {
+    /// The project `cwd` is in: the nearest `.runebook.toml` in `cwd` or a
+    /// directory above it. A file that does not parse is an error rather
+    /// than skipped, so a broken allowlist never fails open.
+    pub fn discover(cwd: &Path) -> Result<Option<Self>> {
+        for dir in cwd.ancestors() {
+            let file = dir.join(PROJECT_FILE);
+            if !file.is_file() {
+                continue;
+            }
+            let text = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let profile =
+                toml::from_str(&text).with_context(|| format!("Invalid {}", file.display()))?;
+            return Ok(Some(Self {
+                root: dir.to_path_buf(),
+                profile,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The project `spec` runs in, from its working directory or, without
+    /// one, the app's
+    pub fn for_spec(spec: &CommandSpec) -> Result<Option<Self>> {
+        if !spec.target.is_local() {
+            return Ok(None);
+        }
+        match spec.cwd.as_deref().filter(|c| !c.is_empty()) {
+            Some(cwd) => Self::discover(Path::new(cwd)),
+            None => match std::env::current_dir() {
+                Ok(cwd) => Self::discover(&cwd),
+                Err(_) => Ok(None),
+            },
+        }
+    }
+
+    pub fn file(&self) -> PathBuf {
+        self.root.join(PROJECT_FILE)
+    }
+
+    /// Whether `allowed_commands` lets `spec`'s program run. Programs are
+    /// compared by file name, without `.exe` and the like on Windows.
+    pub fn allows(&self, spec: &CommandSpec) -> bool {
+        if self.profile.allowed_commands.is_empty() {
+            return true;
+        }
+        let program = match spec.mode {
+            ExecutionMode::Pwsh => spec.command.split_whitespace().next().unwrap_or_default(),
+            ExecutionMode::Direct => spec.command.as_str(),
+        };
+        let name = program_name(program);
+        self.profile
+            .allowed_commands
+            .iter()
+            .any(|allowed| program_name(allowed) == name)
+    }
+
+    /// Fold the project's defaults into `spec`: its env under the spec's,
+    /// its `path` ahead of `PATH`, and its timeout when the spec has none
+    pub fn apply(&self, spec: &mut CommandSpec) -> Result<()> {
+        for (name, value) in &self.profile.env {
+            spec.env
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        if !self.profile.path.is_empty() {
+            let inherited = match spec.env.get("PATH") {
+                Some(path) => Some(path.into()),
+                None => std::env::var_os("PATH"),
+            };
+            let dirs = self
+                .profile
+                .path
+                .iter()
+                .map(|dir| self.root.join(dir))
+                .chain(inherited.iter().flat_map(std::env::split_paths));
+            let path = std::env::join_paths(dirs)
+                .with_context(|| format!("Invalid path entry in {}", self.file().display()))?;
+            spec.env
+                .insert("PATH".to_string(), path.to_string_lossy().into_owned());
+        }
+        if spec.limits.wall_secs.is_none() {
+            spec.limits.wall_secs = self.profile.timeout_secs;
+        }
+        Ok(())
+    }
+}
+
+/// `program`'s file name, lowercased and without an executable extension on
+/// Windows, where both are insignificant
+fn program_name(program: &str) -> String {
+    let name = Path::new(program)
+        .file_name()
+        .map_or_else(|| program.to_string(), |n| n.to_string_lossy().into_owned());
+    if cfg!(windows) {
+        let name = name.to_ascii_lowercase();
+        for ext in [".exe", ".cmd", ".bat", ".com"] {
+            if let Some(stem) = name.strip_suffix(ext) {
+                return stem.to_string();
+            }
+        }
+        name
+    } else {
+        name
+    }
+}
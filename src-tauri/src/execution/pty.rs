@@ -0,0 +1,388 @@
+//! PTY-backed execution for interactive commands.
+//!
+//! `top`, `vim`, and anything else that checks `isatty` misbehave when their
+//! output is piped. [`PtyManager::start`] runs an admitted command on a
+//! pseudo-terminal instead: the frontend forwards raw keystrokes with
+//! [`PtyManager::write`], follows the view's size with [`PtyManager::resize`],
+//! and receives [`PtyEvent`]s as output arrives.
+//!
+//! Each session keeps the last [`SCREEN_BUFFER_BYTES`] of output. A view that
+//! attaches after the command started repaints from [`PtyManager::screen`],
+//! then applies the output events numbered after the screen's `seq`. Finished
+//! sessions stay around until [`PtyManager::close`], so their last screen can
+//! still be read.
+
+use super::command::CommandSpec;
+use super::resolve::ExecutionRequest;
+use super::service::ExecutionService;
+use super::signal::{ProcessTree, Signal};
+use crate::i18n::UserMessage;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use ts_rs::TS;
+
+/// Output kept per session for repainting a view
+pub const SCREEN_BUFFER_BYTES: usize = 256 * 1024;
+
+/// Terminal size in character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PtyDimensions {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for PtyDimensions {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+impl From<PtyDimensions> for PtySize {
+    fn from(size: PtyDimensions) -> Self {
+        PtySize {
+            rows: size.rows,
+            cols: size.cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+}
+
+/// A PTY session, as listed by [`PtyManager::list`]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PtyInfo {
+    pub id: String,
+    pub command_line: String,
+    pub size: PtyDimensions,
+    pub started_at: DateTime<Utc>,
+}
+
+/// What happened in a PTY session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum PtyEventKind {
+    /// Output numbered from 1, exactly as the program wrote it, escape
+    /// sequences included
+    Output { seq: u64, data: String },
+    /// The program ended; no exit code when it could not be collected
+    Exited { exit_code: Option<i32> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PtyEvent {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub kind: PtyEventKind,
+}
+
+/// The buffered output of a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PtyScreen {
+    /// The last [`SCREEN_BUFFER_BYTES`] of output
+    pub data: String,
+    /// Number of the last output event included in `data`
+    pub seq: u64,
+    pub size: PtyDimensions,
+    pub running: bool,
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Default)]
+struct Screen {
+    bytes: VecDeque<u8>,
+    seq: u64,
+    running: bool,
+    exit_code: Option<i32>,
+}
+
+impl Screen {
+    /// Add output; returns its sequence number
+    fn push(&mut self, data: &[u8]) -> u64 {
+        self.bytes.extend(data);
+        let excess = self.bytes.len().saturating_sub(SCREEN_BUFFER_BYTES);
+        self.bytes.drain(..excess);
+        self.seq += 1;
+        self.seq
+    }
+}
+
+struct PtySession {
+    info: PtyInfo,
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    /// The program leads its own session, so this reaches what it started
+    tree: Option<ProcessTree>,
+    screen: Arc<Mutex<Screen>>,
+}
+
+/// Take the complete UTF-8 text from the front of `pending`; a character
+/// split across reads stays behind for the next one
+fn take_text(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => pending.len(),
+    };
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    text
+}
+
+/// Commands running on pseudo-terminals, each read by a background thread
+#[derive(Default)]
+pub struct PtyManager {
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+}
+
+impl PtyManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve and gate `request` like any other run, then start it on a new
+    /// PTY of `size`. Output and the exit go to `events`.
+    pub fn start(
+        &self,
+        execution: &ExecutionService,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+        size: PtyDimensions,
+        events: mpsc::UnboundedSender<PtyEvent>,
+    ) -> Result<PtyInfo, UserMessage> {
+        let spec = execution.admit(request, confirmation_token, actor)?;
+        self.spawn(&spec, size, events)
+            .map_err(UserMessage::wrap("pty-start-failed"))
+    }
+
+    /// Start an already admitted command on a new PTY
+    pub fn spawn(
+        &self,
+        spec: &CommandSpec,
+        size: PtyDimensions,
+        events: mpsc::UnboundedSender<PtyEvent>,
+    ) -> Result<PtyInfo> {
+        if spec.command.trim().is_empty() {
+            anyhow::bail!("Empty command");
+        }
+        let pair = native_pty_system()
+            .openpty(size.into())
+            .context("Failed to open a PTY")?;
+
+        let mut cmd = CommandBuilder::new(&spec.command);
+        cmd.args(&spec.args);
+        if let Some(cwd) = spec.cwd.as_deref().filter(|c| !c.is_empty()) {
+            cmd.cwd(cwd);
+        }
+        // Full-screen programs need to know what they are drawing on
+        if cmd.get_env("TERM").is_none() {
+            cmd.env("TERM", "xterm-256color");
+        }
+        for (name, value) in &spec.env {
+            if super::command::valid_env_name(name) {
+                cmd.env(name, value);
+            }
+        }
+        let mut child = pair
+            .slave
+            .spawn_command(cmd)
+            .with_context(|| format!("Failed to run {}", spec.command))?;
+        // Only the child holds the slave side, so reads end when it exits
+        drop(pair.slave);
+        let tree = child
+            .process_id()
+            .and_then(|pid| match ProcessTree::of(pid) {
+                Ok(tree) => Some(tree),
+                Err(e) => {
+                    log::warn!("[pty] {:#}; only {} itself can be killed", e, spec.command);
+                    None
+                }
+            });
+
+        let mut reader = pair.master.try_clone_reader()?;
+        let writer = pair.master.take_writer()?;
+        let info = PtyInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            command_line: spec.command_line(),
+            size,
+            started_at: Utc::now(),
+        };
+        let screen = Arc::new(Mutex::new(Screen {
+            running: true,
+            ..Screen::default()
+        }));
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                info.id.clone(),
+                PtySession {
+                    info: info.clone(),
+                    master: pair.master,
+                    writer,
+                    killer: child.clone_killer(),
+                    tree,
+                    screen: Arc::clone(&screen),
+                },
+            );
+
+        let id = info.id.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            let mut pending = Vec::new();
+            // Linux reports EIO rather than EOF once the child is gone
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                pending.extend_from_slice(&buf[..n]);
+                let data = take_text(&mut pending);
+                if data.is_empty() {
+                    continue;
+                }
+                let seq = screen
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(data.as_bytes());
+                let _ = events.send(PtyEvent {
+                    session_id: id.clone(),
+                    kind: PtyEventKind::Output { seq, data },
+                });
+            }
+            let exit_code = child.wait().ok().map(|status| status.exit_code() as i32);
+            {
+                let mut screen = screen.lock().unwrap_or_else(|e| e.into_inner());
+                screen.running = false;
+                screen.exit_code = exit_code;
+            }
+            let _ = events.send(PtyEvent {
+                session_id: id,
+                kind: PtyEventKind::Exited { exit_code },
+            });
+        });
+        Ok(info)
+    }
+
+    fn with_session<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut PtySession) -> Result<T>,
+    ) -> Result<T, UserMessage> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let session = sessions
+            .get_mut(id)
+            .ok_or_else(|| UserMessage::new("pty-not-found").with("id", id))?;
+        f(session).map_err(UserMessage::wrap("pty-io-failed"))
+    }
+
+    /// Forward keystrokes to the program, control sequences included
+    pub fn write(&self, id: &str, data: &[u8]) -> Result<(), UserMessage> {
+        self.with_session(id, |session| {
+            session.writer.write_all(data)?;
+            Ok(session.writer.flush()?)
+        })
+    }
+
+    /// Change the terminal size; the program gets SIGWINCH
+    pub fn resize(&self, id: &str, size: PtyDimensions) -> Result<(), UserMessage> {
+        self.with_session(id, |session| {
+            session.master.resize(size.into())?;
+            session.info.size = size;
+            Ok(())
+        })
+    }
+
+    /// The buffered output, for repainting a view
+    pub fn screen(&self, id: &str) -> Result<PtyScreen, UserMessage> {
+        self.with_session(id, |session| {
+            let screen = session.screen.lock().unwrap_or_else(|e| e.into_inner());
+            let (front, back) = screen.bytes.as_slices();
+            Ok(PtyScreen {
+                data: String::from_utf8_lossy(&[front, back].concat()).into_owned(),
+                seq: screen.seq,
+                size: session.info.size,
+                running: screen.running,
+                exit_code: screen.exit_code,
+            })
+        })
+    }
+
+    /// End a session, killing its program if it still runs. Returns whether
+    /// the session existed.
+    pub fn close(&self, id: &str) -> bool {
+        let Some(mut session) = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+        else {
+            return false;
+        };
+        if session
+            .screen
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .running
+        {
+            if let Some(tree) = &session.tree {
+                let _ = tree.signal(Signal::Kill);
+            }
+            let _ = session.killer.kill();
+        }
+        true
+    }
+
+    /// Deliver `signal` to the program and everything it started; `None` if
+    /// there is no session `id`. A program that already exited is not an
+    /// error.
+    pub fn signal(&self, id: &str, signal: Signal) -> Option<Result<()>> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let session = sessions.get_mut(id)?;
+        if !session
+            .screen
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .running
+        {
+            return Some(Ok(()));
+        }
+        Some(match &session.tree {
+            // Windows console programs, and any program whose tree is
+            // unknown, get Ctrl+C the way a user types it
+            _ if signal == Signal::Interrupt && (cfg!(windows) || session.tree.is_none()) => {
+                session
+                    .writer
+                    .write_all(b"\x03")
+                    .and_then(|_| session.writer.flush())
+                    .context("Failed to interrupt the PTY session")
+            }
+            Some(tree) => tree.signal(signal),
+            None => session
+                .killer
+                .kill()
+                .context("Failed to kill the PTY session"),
+        })
+    }
+
+    pub fn list(&self) -> Vec<PtyInfo> {
+        let mut sessions: Vec<PtyInfo> = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|session| session.info.clone())
+            .collect();
+        sessions.sort_by_key(|info| info.started_at);
+        sessions
+    }
+}
@@ -0,0 +1,121 @@
+//! PowerShell object streams.
+//!
+//! In [`ExecutionMode::Pwsh`](super::command::ExecutionMode::Pwsh) the command
+//! line is PowerShell, run by `pwsh`. A wrapper script tees the objects its
+//! pipeline emits: each one is written as a line of `ConvertTo-Json` to a side
+//! file, and the same object goes on to `Out-Default`, so stdout still carries
+//! the usual formatted text. The run's outcome has both, the text in `stdout`
+//! and the objects in `objects`, so display nodes and analyzers get typed data
+//! from cmdlets instead of parsing tables.
+
+use super::command::CommandSpec;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::PathBuf;
+
+/// Objects kept per run; the rest are dropped
+pub const MAX_OBJECTS: usize = 10_000;
+
+/// How deep `ConvertTo-Json` follows nested properties
+const JSON_DEPTH: u32 = 3;
+
+/// Environment variable naming the file objects are written to
+const OBJECT_STREAM_ENV: &str = "RUNEBOOK_OBJECT_STREAM";
+
+/// The wrapper script for `command_line`. With `piped_input`, the script's
+/// stdin is piped into the command line.
+pub fn wrap_script(command_line: &str, piped_input: bool) -> String {
+    let invocation = if piped_input {
+        format!("$input | & {{ $input | {} }}", command_line)
+    } else {
+        format!("& {{ {} }}", command_line)
+    };
+    format!(
+        r#"$__runebookObjects = [System.IO.StreamWriter]::new($env:{env})
+try {{
+    {invocation} | ForEach-Object {{
+        $__runebookObjects.WriteLine((ConvertTo-Json -InputObject $_ -Compress -Depth {depth} -WarningAction SilentlyContinue))
+        $_
+    }} | Out-Default
+    $__runebookOk = $?
+}} finally {{
+    $__runebookObjects.Dispose()
+}}
+if ($LASTEXITCODE) {{ exit $LASTEXITCODE }}
+if (-not $__runebookOk) {{ exit 1 }}
+"#,
+        env = OBJECT_STREAM_ENV,
+        invocation = invocation,
+        depth = JSON_DEPTH,
+    )
+}
+
+/// Objects in an object stream, at most [`MAX_OBJECTS`]. Lines that are not
+/// JSON are skipped.
+pub fn parse_objects(ndjson: &str) -> Vec<Value> {
+    ndjson
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .take(MAX_OBJECTS)
+        .collect()
+}
+
+/// Scratch directory of one PowerShell run, holding the wrapper script and
+/// the object stream; removed when dropped
+pub struct PwshRun {
+    dir: PathBuf,
+}
+
+impl PwshRun {
+    /// Write the wrapper script for `spec`
+    pub fn prepare(spec: &CommandSpec) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("runebook-pwsh-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let run = Self { dir };
+        std::fs::write(
+            run.script_path(),
+            wrap_script(&spec.command_line(), spec.stdin.is_some()),
+        )
+        .context("Failed to write the PowerShell wrapper")?;
+        Ok(run)
+    }
+
+    fn script_path(&self) -> PathBuf {
+        self.dir.join("run.ps1")
+    }
+
+    fn objects_path(&self) -> PathBuf {
+        self.dir.join("objects.ndjson")
+    }
+
+    /// `pwsh` running the wrapper script
+    pub fn command(&self) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new("pwsh");
+        cmd.args([
+            "-NoLogo",
+            "-NoProfile",
+            "-NonInteractive",
+            "-ExecutionPolicy",
+            "Bypass",
+            "-File",
+        ])
+        .arg(self.script_path())
+        .env(OBJECT_STREAM_ENV, self.objects_path());
+        cmd
+    }
+
+    /// Objects the run emitted; none if it never got to write any
+    pub fn objects(&self) -> Vec<Value> {
+        std::fs::read_to_string(self.objects_path())
+            .map(|ndjson| parse_objects(&ndjson))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for PwshRun {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,205 @@
+//! Limit on how many commands run at once.
+//!
+//! Every run through [`ExecutionService`](super::ExecutionService) takes a
+//! slot from the [`ExecutionQueue`] once it has been admitted, and gives it
+//! back when the process exits. Runs that find every slot taken wait in
+//! line, first come first served or by the request's `priority`, so a large
+//! canvas or a burst of API calls cannot start dozens of processes at once:
+//!
+//! ```toml
+//! [concurrency]
+//! max_concurrent = 4
+//! order = "priority"
+//! ```
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use ts_rs::TS;
+
+/// The `[concurrency]` config section
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ConcurrencyConfig {
+    /// Most commands running at once; one per CPU core when unset
+    pub max_concurrent: Option<usize>,
+    pub order: QueueOrder,
+}
+
+impl ConcurrencyConfig {
+    /// `max_concurrent`, or the number of CPU cores; never zero
+    pub fn limit(&self) -> usize {
+        self.max_concurrent
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(usize::from)
+                    .unwrap_or(4)
+            })
+            .max(1)
+    }
+}
+
+/// Which waiting run gets the next free slot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum QueueOrder {
+    /// The one that has waited longest
+    #[default]
+    Fifo,
+    /// The one with the highest `priority`; the longest waiting among equals
+    Priority,
+}
+
+/// A run holding or waiting for a slot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct QueuedExecution {
+    pub id: String,
+    pub command_line: String,
+    pub actor: String,
+    pub priority: i32,
+    pub queued_at: DateTime<Utc>,
+    /// When it got its slot; `None` while it waits
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+impl QueuedExecution {
+    pub fn new(command_line: String, actor: &str, priority: i32) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            command_line,
+            actor: actor.to_string(),
+            priority,
+            queued_at: Utc::now(),
+            started_at: None,
+        }
+    }
+}
+
+/// What the queue holds right now, as `execution_queue` reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct QueueSnapshot {
+    pub max_concurrent: usize,
+    pub order: QueueOrder,
+    /// Runs holding a slot, in the order they started
+    pub running: Vec<QueuedExecution>,
+    /// Runs waiting for one, next in line first
+    pub waiting: Vec<QueuedExecution>,
+}
+
+struct Waiter {
+    entry: QueuedExecution,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: Vec<QueuedExecution>,
+    /// In arrival order
+    waiting: Vec<Waiter>,
+}
+
+pub struct ExecutionQueue {
+    max_concurrent: usize,
+    order: QueueOrder,
+    state: Mutex<QueueState>,
+}
+
+/// A slot in the queue, given back when dropped. Dropping it while it is
+/// still waiting leaves the line.
+pub struct QueuePermit {
+    queue: Arc<ExecutionQueue>,
+    id: String,
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        self.queue.release(&self.id);
+    }
+}
+
+impl ExecutionQueue {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        Self {
+            max_concurrent: config.limit(),
+            order: config.order,
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    /// Wait for a slot for `entry`
+    pub async fn acquire(self: &Arc<Self>, mut entry: QueuedExecution) -> QueuePermit {
+        let permit = QueuePermit {
+            queue: Arc::clone(self),
+            id: entry.id.clone(),
+        };
+        let woken = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.running.len() < self.max_concurrent && state.waiting.is_empty() {
+                entry.started_at = Some(Utc::now());
+                state.running.push(entry);
+                return permit;
+            }
+            let (wake, woken) = oneshot::channel();
+            state.waiting.push(Waiter { entry, wake });
+            woken
+        };
+        // Only `release` sends, and it keeps the sender until it does
+        let _ = woken.await;
+        permit
+    }
+
+    /// Give back `id`'s slot to the next run in line, or take `id` out of line
+    fn release(&self, id: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(at) = state.waiting.iter().position(|w| w.entry.id == id) {
+            state.waiting.remove(at);
+            return;
+        }
+        state.running.retain(|e| e.id != id);
+        while state.running.len() < self.max_concurrent {
+            let Some(next) = self.next(&state.waiting) else {
+                break;
+            };
+            let Waiter { mut entry, wake } = state.waiting.remove(next);
+            entry.started_at = Some(Utc::now());
+            state.running.push(entry);
+            // A waiter whose run has gone away is dropped with its slot;
+            // its permit's release finds it running and frees the slot again
+            let _ = wake.send(());
+        }
+    }
+
+    /// Index in `waiting` of the run that goes next
+    fn next(&self, waiting: &[Waiter]) -> Option<usize> {
+        match self.order {
+            QueueOrder::Fifo => (!waiting.is_empty()).then_some(0),
+            // `max_by_key` keeps the last of equals; reversed, the first
+            QueueOrder::Priority => waiting
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|(_, w)| w.entry.priority)
+                .map(|(at, _)| at),
+        }
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut waiting: Vec<&Waiter> = state.waiting.iter().collect();
+        if self.order == QueueOrder::Priority {
+            // Stable, so equal priorities stay in arrival order
+            waiting.sort_by_key(|w| std::cmp::Reverse(w.entry.priority));
+        }
+        QueueSnapshot {
+            max_concurrent: self.max_concurrent,
+            order: self.order,
+            running: state.running.clone(),
+            waiting: waiting.into_iter().map(|w| w.entry.clone()).collect(),
+        }
+    }
+}
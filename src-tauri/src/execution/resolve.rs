@@ -0,0 +1,235 @@
+//! Execution request resolution.
+//!
+//! Turns what a canvas node asks for into the exact process that would run:
+//! template substitution, profile/env merging, the project's `.runebook.toml`,
+//! target selection, and policy evaluation. `execute_terminal_command` and
+//! `preview_execution` share this path, so a preview shows precisely what
+//! execution would do.
+
+use super::backend::Backend;
+use super::command::{CommandSpec, ExecutionMode};
+use super::limits::ResourceLimits;
+use super::policy::{CommandPolicy, PolicyDecision, PolicySource, RuleAction};
+use super::project::Project;
+use super::target::ExecutionTarget;
+use crate::memory::AnsiMode;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use ts_rs::TS;
+
+/// Named environment/cwd preset, from `[profiles.<name>]` in the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ExecutionProfile {
+    pub env: HashMap<String, String>,
+    pub cwd: Option<String>,
+    /// Template variables available to commands run with this profile
+    pub variables: HashMap<String, String>,
+    /// Limits for commands run with this profile, unless the request sets them
+    pub limits: ResourceLimits,
+}
+
+/// What a caller asks to run, before resolution
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ExecutionRequest {
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub cwd: Option<String>,
+    pub profile: Option<String>,
+    /// Values for `{{name}}` placeholders in command, args, env, and cwd
+    pub variables: HashMap<String, String>,
+    /// Written to the command's stdin, e.g. the data of a connected node;
+    /// without it stdin is empty. Placeholders are not substituted here.
+    pub stdin: Option<String>,
+    /// Run the command line as PowerShell and capture its objects
+    pub mode: ExecutionMode,
+    /// CPU, wall-clock and memory caps; each one the request leaves unset
+    /// comes from the profile
+    pub limits: ResourceLimits,
+    /// Keep, strip, or parse into styled spans the ANSI sequences in output
+    pub ansi: AnsiMode,
+    /// Start the command directly or through a Windows shell
+    pub backend: Backend,
+    /// Run on this machine or in a WSL distro
+    pub target: ExecutionTarget,
+    /// Place in line when runs wait for a slot and `[concurrency]` orders
+    /// them by priority; higher goes first
+    pub priority: i32,
+}
+
+/// Policy outcome a command would get, without issuing a confirmation token.
+/// `source` says whether a configured rule, `default_deny`, or the
+/// destructive-command classifier decided.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case", tag = "decision")]
+#[ts(export)]
+pub enum PolicyPreview {
+    Run,
+    Confirm {
+        rule: String,
+        reason: String,
+        source: PolicySource,
+    },
+    Block {
+        rule: String,
+        reason: String,
+        source: PolicySource,
+    },
+}
+
+impl PolicyPreview {
+    pub fn evaluate(spec: &CommandSpec, policy: &CommandPolicy) -> Self {
+        let PolicyDecision {
+            action,
+            source,
+            matched,
+        } = policy.decide(spec);
+        match (action, matched) {
+            (RuleAction::Allow, _) | (_, None) => PolicyPreview::Run,
+            (RuleAction::Confirm, Some(m)) => PolicyPreview::Confirm {
+                rule: m.rule,
+                reason: m.reason,
+                source,
+            },
+            (RuleAction::Deny, Some(m)) => PolicyPreview::Block {
+                rule: m.rule,
+                reason: m.reason,
+                source,
+            },
+        }
+    }
+}
+
+/// Fully resolved execution, as returned by `preview_execution`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ResolvedExecution {
+    pub spec: CommandSpec,
+    pub command_line: String,
+    pub profile: Option<String>,
+    /// Where the process runs: "local", or "wsl:<distro>"
+    pub target: String,
+    pub policy: PolicyPreview,
+    /// Placeholders with no value; execution refuses to run while any remain
+    pub unresolved_variables: Vec<String>,
+    /// The `.runebook.toml` whose defaults were merged in
+    pub project: Option<String>,
+}
+
+/// Replace `{{name}}` placeholders, collecting names that have no value
+pub(super) fn substitute(
+    template: &str,
+    variables: &BTreeMap<String, String>,
+    unresolved: &mut Vec<String>,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = rest[start + 2..start + 2 + len].trim();
+        match variables.get(name) {
+            Some(value) => out.push_str(value),
+            None => {
+                if !unresolved.iter().any(|u| u == name) {
+                    unresolved.push(name.to_string());
+                }
+                out.push_str(&rest[start..start + 4 + len]);
+            }
+        }
+        rest = &rest[start + 4 + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Resolve a request against the configured profiles and command policy
+pub fn resolve_execution(
+    request: &ExecutionRequest,
+    profiles: &HashMap<String, ExecutionProfile>,
+    policy: &CommandPolicy,
+) -> anyhow::Result<ResolvedExecution> {
+    let profile = match &request.profile {
+        Some(name) => Some(
+            profiles
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown execution profile: {}", name))?,
+        ),
+        None => None,
+    };
+
+    // Later sources win: built-ins < profile < request
+    let mut variables = BTreeMap::new();
+    if let Some(home) = dirs::home_dir() {
+        variables.insert("home".to_string(), home.to_string_lossy().into_owned());
+    }
+    variables.insert(
+        "date".to_string(),
+        chrono::Local::now().format("%Y-%m-%d").to_string(),
+    );
+    if let Some(profile) = profile {
+        variables.extend(profile.variables.clone());
+    }
+    variables.extend(request.variables.clone());
+
+    let cwd = request
+        .cwd
+        .clone()
+        .filter(|c| !c.is_empty())
+        .or_else(|| profile.and_then(|p| p.cwd.clone()));
+    if let Some(cwd) = &cwd {
+        variables.insert("cwd".to_string(), cwd.clone());
+    }
+
+    let mut unresolved = Vec::new();
+    let mut env: HashMap<String, String> = profile.map(|p| p.env.clone()).unwrap_or_default();
+    env.extend(request.env.clone());
+    let env = env
+        .into_iter()
+        .map(|(name, value)| {
+            let value = substitute(&value, &variables, &mut unresolved);
+            (name, value)
+        })
+        .collect();
+
+    let mut spec = CommandSpec {
+        command: substitute(&request.command, &variables, &mut unresolved),
+        args: request
+            .args
+            .iter()
+            .map(|a| substitute(a, &variables, &mut unresolved))
+            .collect(),
+        env,
+        cwd: cwd.map(|c| substitute(&c, &variables, &mut unresolved)),
+        stdin: request.stdin.clone(),
+        mode: request.mode,
+        limits: request
+            .limits
+            .or(profile.map(|p| p.limits).unwrap_or_default()),
+        output: Default::default(),
+        ansi: request.ansi,
+        backend: request.backend,
+        target: request.target.clone(),
+        shadow: None,
+    };
+    let project = Project::for_spec(&spec)?;
+    if let Some(project) = &project {
+        project.apply(&mut spec)?;
+    }
+
+    Ok(ResolvedExecution {
+        command_line: spec.command_line(),
+        policy: PolicyPreview::evaluate(&spec, policy),
+        profile: request.profile.clone(),
+        target: spec.target.to_string(),
+        unresolved_variables: unresolved,
+        project: project.map(|p| p.file().display().to_string()),
+        spec,
+    })
+}
@@ -3,6 +3,7 @@
 use crate::agents::*;
 use crate::core::coordination::CoordinationHandle;
 use crate::orchestrator::{create_execution_plan, ExecutionCoordinator};
+use crate::telemetry::{Span, Tracer};
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
 
@@ -16,6 +17,7 @@ pub struct ParallelExecutionRunner {
     agent4: Arc<Mutex<Agent4>>,
     agent5: Arc<Mutex<Agent5>>,
     agent6: Arc<Mutex<Agent6>>,
+    tracer: Arc<Tracer>,
 }
 
 impl ParallelExecutionRunner {
@@ -34,13 +36,36 @@ impl ParallelExecutionRunner {
                 agent4: Arc::new(Mutex::new(Agent4::new())),
                 agent5: Arc::new(Mutex::new(Agent5::new())),
                 agent6: Arc::new(Mutex::new(Agent6::new())),
+                tracer: Arc::new(Tracer::disabled()),
             },
             coordination_handle,
         )
     }
 
+    /// Export a trace span for the run and each phase through `tracer`
+    pub fn with_tracer(mut self, tracer: Arc<Tracer>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
     /// Execute all agents according to the parallel execution plan
     pub async fn execute(&mut self) -> Result<(), String> {
+        let span = Span::root("parallel_execution");
+        let result = self.execute_phases(&span).await;
+        self.record(span, result)
+    }
+
+    /// Record `span` as finished or failed by `result`, and pass `result` on
+    fn record<T>(&self, mut span: Span, result: Result<T, String>) -> Result<T, String> {
+        match &result {
+            Ok(_) => span.finish(),
+            Err(e) => span.fail(e.clone()),
+        }
+        self.tracer.record(span);
+        result
+    }
+
+    async fn execute_phases(&mut self, run_span: &Span) -> Result<(), String> {
         log::info!("Starting parallel execution...");
 
         // Phase 1: Orchestrator (already done via create_execution_plan)
@@ -48,30 +73,36 @@ impl ParallelExecutionRunner {
 
         // Phase 2: Agent 1 and Agent 2 run in parallel
         log::info!("Phase 2: Starting Agent 1 and Agent 2 in parallel...");
-        let agent1_handle = {
-            let agent = Arc::clone(&self.agent1);
-            let handle = self.coordination_handle.clone();
-            tokio::spawn(async move {
-                let mut agent = agent.lock().await;
-                agent.initialize(handle.clone()).await?;
-                agent.execute().await
-            })
-        };
-
-        let agent2_handle = {
-            let agent = Arc::clone(&self.agent2);
-            let handle = self.coordination_handle.clone();
-            tokio::spawn(async move {
-                let mut agent = agent.lock().await;
-                agent.initialize(handle.clone()).await?;
-                agent.execute().await
-            })
-        };
-
-        // Wait for both to complete
-        let (result1, result2) = tokio::join!(agent1_handle, agent2_handle);
-        result1.map_err(|e| format!("Agent 1 error: {:?}", e))??;
-        result2.map_err(|e| format!("Agent 2 error: {:?}", e))??;
+        let phase_span = run_span.child("phase-2-parallel-agents");
+        let result = async {
+            let agent1_handle = {
+                let agent = Arc::clone(&self.agent1);
+                let handle = self.coordination_handle.clone();
+                tokio::spawn(async move {
+                    let mut agent = agent.lock().await;
+                    agent.initialize(handle.clone()).await?;
+                    agent.execute().await
+                })
+            };
+
+            let agent2_handle = {
+                let agent = Arc::clone(&self.agent2);
+                let handle = self.coordination_handle.clone();
+                tokio::spawn(async move {
+                    let mut agent = agent.lock().await;
+                    agent.initialize(handle.clone()).await?;
+                    agent.execute().await
+                })
+            };
+
+            // Wait for both to complete
+            let (result1, result2) = tokio::join!(agent1_handle, agent2_handle);
+            result1.map_err(|e| format!("Agent 1 error: {:?}", e))??;
+            result2.map_err(|e| format!("Agent 2 error: {:?}", e))??;
+            Ok::<(), String>(())
+        }
+        .await;
+        self.record(phase_span, result)?;
 
         // Process coordination messages
         self.coordinator
@@ -82,11 +113,14 @@ impl ParallelExecutionRunner {
 
         // Phase 3: Agent 3 starts after Agent 2 publishes APIs
         log::info!("Phase 3: Starting Agent 3 (after Agent 2 APIs published)...");
-        {
+        let phase_span = run_span.child("phase-3-analysis");
+        let result = async {
             let mut agent = self.agent3.lock().await;
             agent.initialize(self.coordination_handle.clone()).await?;
-            agent.execute().await?;
+            agent.execute().await
         }
+        .await;
+        self.record(phase_span, result)?;
 
         // Process coordination messages
         self.coordinator
@@ -97,44 +131,53 @@ impl ParallelExecutionRunner {
 
         // Phase 4: Agent 4 starts after Agent 3 writes suggestions
         log::info!("Phase 4: Starting Agent 4 (after Agent 3 writes suggestions)...");
-        {
+        let phase_span = run_span.child("phase-4-surfaces");
+        let result = async {
             let mut agent = self.agent4.lock().await;
             agent.initialize(self.coordination_handle.clone()).await?;
-            agent.execute().await?;
+            agent.execute().await
         }
+        .await;
+        self.record(phase_span, result)?;
 
         // Phase 5: Agent 5 and Agent 6 run continuously
         log::info!("Phase 5: Starting Agent 5 and Agent 6 (continuous)...");
-        let agent5_handle = {
-            let agent = Arc::clone(&self.agent5);
-            let handle = self.coordination_handle.clone();
-            tokio::spawn(async move {
-                let mut agent = agent.lock().await;
-                agent.initialize(handle.clone()).await?;
-                agent.execute().await
-            })
-        };
-
-        let agent6_handle = {
-            let agent = Arc::clone(&self.agent6);
-            let handle = self.coordination_handle.clone();
-            tokio::spawn(async move {
-                let mut agent = agent.lock().await;
-                agent.initialize(handle.clone()).await?;
-                agent.execute().await
-            })
-        };
-
-        // Wait for continuous agents (they run in background)
-        let (result5, result6) = tokio::join!(agent5_handle, agent6_handle);
-        result5.map_err(|e| format!("Agent 5 error: {:?}", e))??;
-        result6.map_err(|e| format!("Agent 6 error: {:?}", e))??;
-
-        // Finalize Agent 6
-        {
-            let mut agent = self.agent6.lock().await;
-            agent.finalize().await?;
+        let phase_span = run_span.child("phase-5-continuous");
+        let result = async {
+            let agent5_handle = {
+                let agent = Arc::clone(&self.agent5);
+                let handle = self.coordination_handle.clone();
+                tokio::spawn(async move {
+                    let mut agent = agent.lock().await;
+                    agent.initialize(handle.clone()).await?;
+                    agent.execute().await
+                })
+            };
+
+            let agent6_handle = {
+                let agent = Arc::clone(&self.agent6);
+                let handle = self.coordination_handle.clone();
+                tokio::spawn(async move {
+                    let mut agent = agent.lock().await;
+                    agent.initialize(handle.clone()).await?;
+                    agent.execute().await
+                })
+            };
+
+            // Wait for continuous agents (they run in background)
+            let (result5, result6) = tokio::join!(agent5_handle, agent6_handle);
+            result5.map_err(|e| format!("Agent 5 error: {:?}", e))??;
+            result6.map_err(|e| format!("Agent 6 error: {:?}", e))??;
+
+            // Finalize Agent 6
+            {
+                let mut agent = self.agent6.lock().await;
+                agent.finalize().await?;
+            }
+            Ok::<(), String>(())
         }
+        .await;
+        self.record(phase_span, result)?;
 
         log::info!("Parallel execution completed!");
         Ok(())
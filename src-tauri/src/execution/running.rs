@@ -0,0 +1,142 @@
+//! Registry of cancellable command runs.
+//!
+//! A caller that wants a run to be cancellable registers it under an
+//! execution id and passes the returned [`CancelHandle`] to the run.
+//! [`RunningCommands::cancel`] then signals the handle, and the run kills its
+//! process tree. Once its process is spawned, the run attaches its
+//! [`ProcessTree`] to the handle, so [`RunningCommands::signal`] can reach it.
+//! Dropping the handle, which happens when the run ends, unregisters the id.
+
+use super::signal::{ProcessTree, Signal};
+use crate::i18n::UserMessage;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+#[derive(Debug)]
+struct Run {
+    serial: u64,
+    cancel: oneshot::Sender<()>,
+    tree: Option<Arc<ProcessTree>>,
+}
+
+type Runs = Arc<Mutex<HashMap<String, Run>>>;
+
+/// Commands running under an execution id
+#[derive(Default)]
+pub struct RunningCommands {
+    runs: Runs,
+    serial: AtomicU64,
+}
+
+impl RunningCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a run under `id`, which must not belong to another running command
+    pub fn register(&self, id: &str) -> Result<CancelHandle, UserMessage> {
+        let mut runs = self.runs.lock().unwrap_or_else(|e| e.into_inner());
+        if runs.contains_key(id) {
+            return Err(UserMessage::new("execution-id-in-use").with("id", id));
+        }
+        let serial = self.serial.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        runs.insert(
+            id.to_string(),
+            Run {
+                serial,
+                cancel: sender,
+                tree: None,
+            },
+        );
+        Ok(CancelHandle {
+            id: id.to_string(),
+            serial,
+            receiver,
+            runs: Arc::clone(&self.runs),
+        })
+    }
+
+    /// Cancel the run registered under `id`. Returns whether one was running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self
+            .runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+        {
+            Some(run) => run.cancel.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Cancel every registered run
+    pub fn cancel_all(&self) {
+        for (_, run) in self.runs.lock().unwrap_or_else(|e| e.into_inner()).drain() {
+            let _ = run.cancel.send(());
+        }
+    }
+
+    /// Deliver `signal` to the process tree of the run registered under `id`;
+    /// `None` if there is no such run
+    pub fn signal(&self, id: &str, signal: Signal) -> Option<anyhow::Result<()>> {
+        let tree = self
+            .runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)?
+            .tree
+            .clone();
+        Some(match tree {
+            Some(tree) => tree.signal(signal),
+            None => Err(anyhow::anyhow!("Run {} has not started a process yet", id)),
+        })
+    }
+}
+
+/// Held by a registered run; resolves [`cancelled`](Self::cancelled) once
+/// the run is cancelled
+#[derive(Debug)]
+pub struct CancelHandle {
+    id: String,
+    serial: u64,
+    receiver: oneshot::Receiver<()>,
+    runs: Runs,
+}
+
+impl CancelHandle {
+    /// Make the run's process tree reachable through [`RunningCommands::signal`]
+    pub fn attach(&self, tree: Arc<ProcessTree>) {
+        if let Some(run) = self
+            .runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(&self.id)
+            .filter(|run| run.serial == self.serial)
+        {
+            run.tree = Some(tree);
+        }
+    }
+
+    /// Wait until the run is cancelled; never resolves otherwise
+    pub async fn cancelled(&mut self) {
+        if (&mut self.receiver).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl Drop for CancelHandle {
+    fn drop(&mut self) {
+        let mut runs = self.runs.lock().unwrap_or_else(|e| e.into_inner());
+        // The id may already belong to a newer run
+        if runs
+            .get(&self.id)
+            .is_some_and(|run| run.serial == self.serial)
+        {
+            runs.remove(&self.id);
+        }
+    }
+}
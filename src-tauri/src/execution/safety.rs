@@ -0,0 +1,349 @@
+//! Destructive-command detection and confirmation gating.
+//!
+//! Commands such as `rm -rf *`, `DROP TABLE` via psql, `terraform destroy`, or
+//! force pushes are classified before spawning. Depending on policy they run,
+//! require a confirmation round-trip, or are refused; every decision is audited.
+//! Configured rules ([`super::policy`]) are consulted first.
+
+use super::command::CommandSpec;
+use super::policy::{CommandPolicy, PolicyRule, PolicySource, RuleAction};
+use crate::audit::{AuditEntry, AuditLog};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+/// What to do with a destructive command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum DestructivePolicy {
+    Allow,
+    Confirm,
+    Block,
+}
+
+/// `[safety]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct SafetyConfig {
+    pub destructive: DestructivePolicy,
+    /// How long a confirmation token stays valid
+    pub confirmation_ttl_secs: u64,
+    /// Refuse commands no rule allows
+    pub default_deny: bool,
+    /// Allow, confirm and deny rules, tried in order before classification
+    pub rules: Vec<PolicyRule>,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            destructive: DestructivePolicy::Confirm,
+            confirmation_ttl_secs: 120,
+            default_deny: false,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Why a command was held back or refused: a destructive classification or
+/// a configured rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyMatch {
+    pub rule: String,
+    pub reason: String,
+}
+
+impl PolicyMatch {
+    pub(crate) fn new(rule: &str, reason: impl Into<String>) -> Self {
+        Self {
+            rule: rule.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+const SQL_CLIENTS: &[&str] = &[
+    "psql",
+    "mysql",
+    "mariadb",
+    "sqlite3",
+    "sqlcmd",
+    "cockroach",
+    "clickhouse-client",
+];
+const SQL_DESTRUCTIVE: &[&str] = &["DROP TABLE", "DROP DATABASE", "DROP SCHEMA", "TRUNCATE"];
+pub(crate) const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "fish"];
+
+pub(crate) fn program_name(command: &str) -> &str {
+    command.rsplit(['/', '\\']).next().unwrap_or(command)
+}
+
+/// Targets that cover far more than the user likely means
+fn broad_target(target: &str) -> bool {
+    let trimmed = target.trim_end_matches('/');
+    matches!(trimmed, "" | "~" | "." | ".." | "*" | "$HOME" | ".*")
+        || target.contains(['*', '?'])
+        // Top-level directories such as /usr or /home
+        || (trimmed.starts_with('/') && trimmed.matches('/').count() == 1)
+}
+
+/// Classify a command line; `None` if it is not considered destructive
+pub fn classify(command: &str, args: &[String]) -> Option<PolicyMatch> {
+    let program = program_name(command);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match program {
+        "sudo" | "doas" | "env" | "nohup" | "time" => {
+            let (command, inner) = wrapped_command(program, &args)?;
+            classify(&command, &inner)
+        }
+        p if SHELLS.contains(&p) => {
+            let script = args
+                .iter()
+                .position(|a| *a == "-c")
+                .and_then(|i| args.get(i + 1))?;
+            classify_script(script)
+        }
+        "rm" => {
+            let flags: String = args
+                .iter()
+                .filter(|a| a.starts_with('-') && !a.starts_with("--"))
+                .map(|a| &a[1..])
+                .collect();
+            let recursive = flags.contains(['r', 'R']) || args.contains(&"--recursive");
+            let targets: Vec<&&str> = args.iter().filter(|a| !a.starts_with('-')).collect();
+            let broad: Vec<&&str> = targets
+                .iter()
+                .copied()
+                .filter(|t| broad_target(t))
+                .collect();
+            (recursive && !broad.is_empty()).then(|| {
+                PolicyMatch::new(
+                    "rm-broad",
+                    format!(
+                        "Recursive delete of {}",
+                        broad.iter().map(|t| **t).collect::<Vec<_>>().join(" ")
+                    ),
+                )
+            })
+        }
+        p if SQL_CLIENTS.contains(&p) => {
+            let sql = args.join(" ").to_uppercase();
+            SQL_DESTRUCTIVE
+                .iter()
+                .find(|stmt| sql.contains(*stmt))
+                .map(|stmt| PolicyMatch::new("sql-drop", format!("{} via {}", stmt, p)))
+        }
+        "terraform" | "tofu" | "terragrunt" => {
+            let sub = args.iter().find(|a| !a.starts_with('-'))?;
+            (*sub == "destroy" || (*sub == "apply" && args.contains(&"-destroy")))
+                .then(|| PolicyMatch::new("terraform-destroy", "Destroys managed infrastructure"))
+        }
+        "git" => {
+            let sub = args.iter().position(|a| !a.starts_with('-'))?;
+            if args[sub] != "push" {
+                return None;
+            }
+            args[sub + 1..]
+                .iter()
+                .find(|a| {
+                    a.starts_with("--force")
+                        || **a == "-f"
+                        || **a == "--mirror"
+                        || **a == "--delete"
+                        || a.starts_with('+')
+                })
+                .map(|a| PolicyMatch::new("git-force-push", format!("git push {}", a)))
+        }
+        "kubectl" => (args.first() == Some(&"delete"))
+            .then(|| PolicyMatch::new("kubectl-delete", "Deletes cluster resources")),
+        "dd" => args
+            .iter()
+            .find(|a| a.starts_with("of=/dev/"))
+            .map(|a| PolicyMatch::new("dd-device", format!("Overwrites device ({})", a))),
+        p if p.starts_with("mkfs") => Some(PolicyMatch::new("mkfs", "Formats a filesystem")),
+        _ => None,
+    }
+}
+
+/// Short and long options of a command wrapper that take a value
+fn value_options(wrapper: &str) -> (&'static str, &'static [&'static str]) {
+    match wrapper {
+        "sudo" | "doas" => (
+            "ugCDph",
+            &[
+                "--user",
+                "--group",
+                "--close-from",
+                "--chdir",
+                "--prompt",
+                "--host",
+            ],
+        ),
+        "env" => ("uCS", &["--unset", "--chdir", "--split-string"]),
+        "time" => ("fo", &["--format", "--output"]),
+        _ => ("", &[]),
+    }
+}
+
+/// The command a wrapper such as `sudo -u root` or `env -C dir` runs, past
+/// its options, their values and `NAME=value` assignments
+fn wrapped_command(wrapper: &str, args: &[&str]) -> Option<(String, Vec<String>)> {
+    let (short, long) = value_options(wrapper);
+    let owned = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        if *arg == "--" {
+            i += 1;
+            break;
+        } else if arg.starts_with("--") {
+            if long.contains(arg) {
+                i += 1;
+            }
+        } else if let Some(cluster) = arg.strip_prefix('-').filter(|c| !c.is_empty()) {
+            // As getopt: an option taking a value takes the rest of its
+            // cluster, or else the next argument
+            if let Some(at) = cluster.find(|c| short.contains(c)) {
+                let value = match &cluster[at + 1..] {
+                    "" => {
+                        i += 1;
+                        *args.get(i)?
+                    }
+                    attached => attached,
+                };
+                if wrapper == "env" && cluster[at..].starts_with('S') {
+                    // `env -S` splits its value into the command and its first arguments
+                    let mut words = value.split_whitespace().map(String::from);
+                    let command = words.next()?;
+                    return Some((command, words.chain(owned(&args[i + 1..])).collect()));
+                }
+            }
+        } else if !arg.contains('=') {
+            break;
+        }
+        i += 1;
+    }
+    Some((args.get(i)?.to_string(), owned(args.get(i + 1..)?)))
+}
+
+/// Classify each simple command of a `sh -c` script (split on `;`, `&&`, `||`, `|`)
+fn classify_script(script: &str) -> Option<PolicyMatch> {
+    script
+        .split([';', '&', '|', '\n'])
+        .map(str::split_whitespace)
+        .find_map(|mut words| {
+            let command = words.next()?;
+            let args: Vec<String> = words
+                .map(|w| w.trim_matches(['"', '\'']).to_string())
+                .collect();
+            classify(command, &args)
+        })
+}
+
+/// Outcome of gating a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Run,
+    NeedsConfirmation { token: String, reason: PolicyMatch },
+    Blocked(PolicyMatch),
+}
+
+/// Gate in front of the executor: classifies commands, issues and redeems
+/// single-use confirmation tokens, and audits every destructive decision
+pub struct CommandGate {
+    config: SafetyConfig,
+    policy: CommandPolicy,
+    audit: Arc<AuditLog>,
+    pending: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl CommandGate {
+    /// Invalid rules in `config` are logged, and the gate then refuses every
+    /// command until they are fixed
+    pub fn new(config: SafetyConfig, audit: Arc<AuditLog>) -> Self {
+        let policy = CommandPolicy::new(&config).unwrap_or_else(|e| {
+            log::warn!("Refusing all commands: {:#}", e);
+            CommandPolicy::invalid(&e)
+        });
+        Self {
+            config,
+            policy,
+            audit,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &CommandPolicy {
+        &self.policy
+    }
+
+    fn fingerprint(spec: &CommandSpec) -> String {
+        let mut parts = vec![spec.cwd.clone().unwrap_or_default(), spec.command.clone()];
+        parts.extend(spec.args.iter().cloned());
+        parts.join("\0")
+    }
+
+    /// Decide whether `spec` may run. A token previously returned in
+    /// [`Admission::NeedsConfirmation`] for the same command confirms it.
+    pub fn admit(&self, spec: &CommandSpec, confirmation: Option<&str>, actor: &str) -> Admission {
+        let decision = self.policy.decide(spec);
+        let Some(reason) = decision.matched else {
+            return Admission::Run;
+        };
+        let (prefix, source) = match decision.source {
+            PolicySource::Destructive => ("destructive_command", "destructive"),
+            PolicySource::Rule => ("command_policy", "rule"),
+            PolicySource::Default => ("command_policy", "default"),
+            PolicySource::Project => ("command_policy", "project"),
+        };
+        let details = serde_json::json!({
+            "rule": reason.rule,
+            "reason": reason.reason,
+            "source": source,
+            "cwd": spec.cwd,
+        });
+        let audit = |outcome: &str| {
+            let action = format!("{}.{}", prefix, outcome);
+            self.audit.record(
+                AuditEntry::new(&action, actor, spec.command_line()).with_details(details.clone()),
+            );
+        };
+
+        match decision.action {
+            // Commands an allow rule lets through are not audited; an
+            // allowlist would otherwise log every run
+            RuleAction::Allow if decision.source == PolicySource::Rule => Admission::Run,
+            RuleAction::Allow => {
+                audit("allowed");
+                Admission::Run
+            }
+            RuleAction::Deny => {
+                audit("blocked");
+                Admission::Blocked(reason)
+            }
+            RuleAction::Confirm => {
+                let fingerprint = Self::fingerprint(spec);
+                let ttl = Duration::from_secs(self.config.confirmation_ttl_secs);
+                let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+                pending.retain(|_, (_, issued)| issued.elapsed() < ttl);
+
+                if let Some(token) = confirmation {
+                    if pending.get(token).is_some_and(|(f, _)| *f == fingerprint) {
+                        pending.remove(token);
+                        audit("confirmed");
+                        return Admission::Run;
+                    }
+                }
+
+                let token = uuid::Uuid::new_v4().to_string();
+                pending.insert(token.clone(), (fingerprint, Instant::now()));
+                audit("confirmation_requested");
+                Admission::NeedsConfirmation { token, reason }
+            }
+        }
+    }
+}
@@ -0,0 +1,624 @@
+//! "What would this change?" previews of commands.
+//!
+//! The command runs against a shadow of its working directory, and the files
+//! it adds, modifies or deletes there are reported as diffs while the real
+//! directory stays as it was. On Linux the shadow is an overlay mount in a
+//! private user and mount namespace: the real directory is the lower layer,
+//! every write lands in a scratch upper layer, and the upper layer is read
+//! back once the command exits. Where overlays are unavailable, on other
+//! platforms or on kernels that refuse unprivileged ones, the directory is
+//! copied and the command runs in the copy.
+//!
+//! Only the working directory is shadowed. Writes elsewhere, such as to a
+//! package cache under `$HOME`, happen for real, and so does anything the
+//! command does over the network. Previews are admitted like any other run.
+
+use super::command::{run_command, CommandOutcome, CommandSpec};
+use super::resolve::ExecutionRequest;
+use super::service::ExecutionService;
+use crate::i18n::UserMessage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+use walkdir::WalkDir;
+
+/// Largest working directory the copy fallback copies, in bytes
+pub const DEFAULT_MAX_COPY_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Largest file, before or after the run, that gets a diff
+const MAX_DIFF_BYTES: u64 = 1024 * 1024;
+
+/// Most changes one report lists
+const MAX_CHANGES: usize = 1000;
+
+fn default_max_copy_bytes() -> u64 {
+    DEFAULT_MAX_COPY_BYTES
+}
+
+/// How the working directory is shadowed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ShadowStrategy {
+    /// An overlay where possible, else a copy
+    #[default]
+    Auto,
+    /// An overlay mount; Linux only
+    Overlay,
+    /// A copy of the directory
+    Copy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SandboxSpec {
+    #[serde(flatten)]
+    pub request: ExecutionRequest,
+    #[serde(default)]
+    pub strategy: ShadowStrategy,
+    /// Largest working directory a copy is made of, in bytes
+    #[serde(default = "default_max_copy_bytes")]
+    pub max_copy_bytes: u64,
+}
+
+/// An overlay of `lower` whose writes go to `upper`; `work` is the overlay's
+/// own scratch directory, on the same filesystem as `upper`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shadow {
+    pub lower: PathBuf,
+    pub upper: PathBuf,
+    pub work: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// A file the command would change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileChange {
+    /// Relative to the working directory, with `/` separators
+    pub path: String,
+    pub kind: ChangeKind,
+    pub bytes_before: Option<u64>,
+    pub bytes_after: Option<u64>,
+    /// Unified diff; `None` for binary files and files over 1 MiB
+    pub diff: Option<String>,
+    pub binary: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SandboxReport {
+    pub command_line: String,
+    /// The real working directory
+    pub cwd: String,
+    /// How it was shadowed: `overlay` or `copy`
+    pub strategy: ShadowStrategy,
+    pub outcome: CommandOutcome,
+    /// Sorted by path
+    pub changes: Vec<FileChange>,
+    /// More files changed than `changes` lists
+    pub truncated: bool,
+}
+
+/// Run `spec` against a shadow of its working directory and report what it
+/// would change there. Destructive commands under the confirm policy need
+/// `confirmation_token`, as with a real run.
+pub async fn run_sandboxed(
+    service: &ExecutionService,
+    spec: &SandboxSpec,
+    confirmation_token: Option<&str>,
+    actor: &str,
+) -> Result<SandboxReport, UserMessage> {
+    let command = service.admit(&spec.request, confirmation_token, actor)?;
+    if !command.target.is_local() {
+        return Err(UserMessage::new("sandbox-unsupported").with(
+            "error",
+            "only commands run on this machine can be previewed",
+        ));
+    }
+    if spec.strategy == ShadowStrategy::Overlay && !cfg!(target_os = "linux") {
+        return Err(UserMessage::new("sandbox-unsupported")
+            .with("error", "overlay shadows need Linux; use the copy strategy"));
+    }
+    let failed =
+        |e: anyhow::Error| UserMessage::new("sandbox-failed").with("error", format!("{:#}", e));
+    let cwd = match command.cwd.as_deref().filter(|c| !c.is_empty()) {
+        Some(cwd) => PathBuf::from(cwd),
+        None => std::env::current_dir()
+            .context("No working directory")
+            .map_err(failed)?,
+    };
+    let cwd = cwd
+        .canonicalize()
+        .with_context(|| format!("Cannot preview in {}", cwd.display()))
+        .map_err(failed)?;
+    let scratch = Scratch::create().map_err(failed)?;
+
+    if spec.strategy != ShadowStrategy::Copy && cfg!(target_os = "linux") {
+        match run_in_overlay(&command, &cwd, &scratch.0).await {
+            Ok((outcome, changes)) => {
+                return Ok(report(
+                    &command,
+                    &cwd,
+                    ShadowStrategy::Overlay,
+                    outcome,
+                    changes,
+                ))
+            }
+            // The namespace or mount was refused before the command started
+            Err(e) if spec.strategy == ShadowStrategy::Auto && refused_overlay(&e) => {
+                log::info!("[sandbox] Overlay unavailable, copying instead: {:#}", e);
+            }
+            Err(e) => return Err(failed(e)),
+        }
+    }
+    let (outcome, changes) = run_in_copy(&command, &cwd, &scratch.0, spec.max_copy_bytes)
+        .await
+        .map_err(failed)?;
+    Ok(report(
+        &command,
+        &cwd,
+        ShadowStrategy::Copy,
+        outcome,
+        changes,
+    ))
+}
+
+fn report(
+    command: &CommandSpec,
+    cwd: &Path,
+    strategy: ShadowStrategy,
+    outcome: CommandOutcome,
+    mut changes: Vec<FileChange>,
+) -> SandboxReport {
+    let truncated = changes.len() > MAX_CHANGES;
+    changes.truncate(MAX_CHANGES);
+    SandboxReport {
+        command_line: command.command_line(),
+        cwd: cwd.display().to_string(),
+        strategy,
+        outcome,
+        changes,
+        truncated,
+    }
+}
+
+/// Whether `error` is the kernel refusing the overlay, as opposed to the
+/// command itself failing to start
+fn refused_overlay(error: &anyhow::Error) -> bool {
+    error
+        .root_cause()
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() != std::io::ErrorKind::NotFound)
+}
+
+/// A scratch directory, removed when dropped
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn create() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("runebook-sandbox-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        // The overlay leaves a directory nobody may read in its work dir
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(
+                self.0.join("work").join("work"),
+                fs::Permissions::from_mode(0o700),
+            );
+        }
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            log::warn!("[sandbox] Failed to remove {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+async fn run_in_overlay(
+    command: &CommandSpec,
+    cwd: &Path,
+    scratch: &Path,
+) -> Result<(CommandOutcome, Vec<FileChange>)> {
+    let shadow = Shadow {
+        lower: cwd.to_path_buf(),
+        upper: scratch.join("upper"),
+        work: scratch.join("work"),
+    };
+    fs::create_dir_all(&shadow.upper)?;
+    fs::create_dir_all(&shadow.work)?;
+    let spec = CommandSpec {
+        cwd: Some(cwd.display().to_string()),
+        shadow: Some(shadow.clone()),
+        ..command.clone()
+    };
+    let outcome = run_command(&spec).await?;
+    let changes = tokio::task::spawn_blocking(move || {
+        diff_pairs(overlay_pairs(&shadow.lower, &shadow.upper)?)
+    })
+    .await??;
+    Ok((outcome, changes))
+}
+
+async fn run_in_copy(
+    command: &CommandSpec,
+    cwd: &Path,
+    scratch: &Path,
+    max_copy_bytes: u64,
+) -> Result<(CommandOutcome, Vec<FileChange>)> {
+    let copy = scratch.join("tree");
+    {
+        let (cwd, copy) = (cwd.to_path_buf(), copy.clone());
+        tokio::task::spawn_blocking(move || copy_tree(&cwd, &copy, max_copy_bytes)).await??;
+    }
+    let spec = CommandSpec {
+        cwd: Some(copy.display().to_string()),
+        ..command.clone()
+    };
+    let outcome = run_command(&spec).await?;
+    let cwd = cwd.to_path_buf();
+    let changes =
+        tokio::task::spawn_blocking(move || diff_pairs(tree_pairs(&cwd, &copy)?)).await??;
+    Ok((outcome, changes))
+}
+
+/// Copy the tree at `from` to `to`, keeping symlinks as symlinks
+fn copy_tree(from: &Path, to: &Path, max_bytes: u64) -> Result<()> {
+    let mut copied = 0u64;
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from)?);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+            #[cfg(not(unix))]
+            log::debug!("[sandbox] Not copying symlink {}", entry.path().display());
+        } else if file_type.is_file() {
+            copied += entry.metadata()?.len();
+            if copied > max_bytes {
+                anyhow::bail!(
+                    "{} holds more than {} bytes to copy; use the overlay strategy or raise max_copy_bytes",
+                    from.display(),
+                    max_bytes
+                );
+            }
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// A file's path relative to the working directory, with where it was before
+/// the run and where it is after; `None` when it did not exist
+type Pair = (String, Option<PathBuf>, Option<PathBuf>);
+
+fn relative(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Files and symlinks under `root`, by relative path
+fn files(root: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let mut files = BTreeMap::new();
+    for entry in WalkDir::new(root).min_depth(1) {
+        let entry = entry?;
+        if !entry.file_type().is_dir() {
+            files.insert(relative(entry.path(), root), entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Every file in either tree, paired up
+fn tree_pairs(before: &Path, after: &Path) -> Result<Vec<Pair>> {
+    let mut after = files(after)?;
+    let mut pairs: Vec<Pair> = files(before)?
+        .into_iter()
+        .map(|(path, was)| {
+            let now = after.remove(&path);
+            (path, Some(was), now)
+        })
+        .collect();
+    pairs.extend(after.into_iter().map(|(path, now)| (path, None, Some(now))));
+    Ok(pairs)
+}
+
+/// The files an overlay's `upper` layer changed in `lower`. Deletions are
+/// whiteouts: character devices numbered 0/0. A directory whose lower
+/// contents were all replaced is marked opaque.
+fn overlay_pairs(lower: &Path, upper: &Path) -> Result<Vec<Pair>> {
+    let mut pairs = Vec::new();
+    for entry in WalkDir::new(upper).min_depth(1).sort_by_file_name() {
+        let entry = entry?;
+        let path = relative(entry.path(), upper);
+        let before = lower.join(entry.path().strip_prefix(upper)?);
+        let lower_meta = fs::symlink_metadata(&before).ok();
+        let lower_dir = lower_meta.as_ref().is_some_and(|m| m.is_dir());
+        if is_whiteout(&entry) {
+            if lower_dir {
+                for (nested, was) in files(&before)? {
+                    pairs.push((format!("{}/{}", path, nested), Some(was), None));
+                }
+            } else if lower_meta.is_some() {
+                pairs.push((path, Some(before), None));
+            }
+        } else if entry.file_type().is_dir() {
+            if lower_dir && is_opaque(entry.path()) {
+                // What the upper layer does not have again is gone
+                for (nested, was) in files(&before)? {
+                    if fs::symlink_metadata(entry.path().join(&nested)).is_err() {
+                        pairs.push((format!("{}/{}", path, nested), Some(was), None));
+                    }
+                }
+            } else if lower_meta.is_some() && !lower_dir {
+                pairs.push((path, Some(before), None));
+            }
+        } else {
+            if lower_dir {
+                for (nested, was) in files(&before)? {
+                    pairs.push((format!("{}/{}", path, nested), Some(was), None));
+                }
+            }
+            let before = lower_meta.filter(|m| !m.is_dir()).map(|_| before);
+            pairs.push((path, before, Some(entry.into_path())));
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(unix)]
+fn is_whiteout(entry: &walkdir::DirEntry) -> bool {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    entry.file_type().is_char_device() && entry.metadata().is_ok_and(|m| m.rdev() == 0)
+}
+
+#[cfg(not(unix))]
+fn is_whiteout(_entry: &walkdir::DirEntry) -> bool {
+    false
+}
+
+/// Whether the overlay marked `dir` opaque; unprivileged overlays use the
+/// `user.` xattr namespace
+#[cfg(target_os = "linux")]
+fn is_opaque(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut value = [0u8; 1];
+    // SAFETY: both strings are NUL-terminated and `value` is as long as given
+    let len = unsafe {
+        libc::lgetxattr(
+            path.as_ptr(),
+            c"user.overlay.opaque".as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+        )
+    };
+    len == 1 && value[0] == b'y'
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_opaque(_dir: &Path) -> bool {
+    false
+}
+
+/// A file's size, and its bytes when small enough to diff
+type Entry = (u64, Option<Vec<u8>>);
+
+/// The [`Entry`] of `path`; a symlink's bytes are its target
+fn read_entry(path: &Path) -> Result<Entry> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?
+            .to_string_lossy()
+            .into_owned()
+            .into_bytes();
+        return Ok((target.len() as u64, Some(target)));
+    }
+    if meta.len() > MAX_DIFF_BYTES {
+        return Ok((meta.len(), None));
+    }
+    Ok((meta.len(), Some(fs::read(path)?)))
+}
+
+/// Whether two large files hold the same bytes
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    let (mut a, mut b) = (fs::File::open(a)?, fs::File::open(b)?);
+    let (mut buf_a, mut buf_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(b.read(&mut buf_b)? == 0);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+fn text(bytes: &[u8]) -> Option<&str> {
+    if bytes.iter().take(8000).any(|b| *b == 0) {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()
+}
+
+/// The bytes a side of a diff has: none for a missing file, unread for a
+/// large one
+fn contents(entry: &Option<Entry>) -> Option<&[u8]> {
+    match entry {
+        None => Some(&[]),
+        Some((_, bytes)) => bytes.as_deref(),
+    }
+}
+
+/// The change from `before` to `after`, if the bytes differ
+fn diff_pair((path, before, after): Pair) -> Result<Option<FileChange>> {
+    let before_entry = before.as_deref().map(read_entry).transpose()?;
+    let after_entry = after.as_deref().map(read_entry).transpose()?;
+    let kind = match (&before_entry, &after_entry) {
+        (None, None) => return Ok(None),
+        (None, Some(_)) => ChangeKind::Added,
+        (Some(_), None) => ChangeKind::Deleted,
+        (Some((size_a, bytes_a)), Some((size_b, bytes_b))) => {
+            let same = match (bytes_a, bytes_b) {
+                _ if size_a != size_b => false,
+                (Some(a), Some(b)) => a == b,
+                _ => same_contents(before.as_deref().unwrap(), after.as_deref().unwrap())?,
+            };
+            if same {
+                return Ok(None);
+            }
+            ChangeKind::Modified
+        }
+    };
+    let (old, new) = (contents(&before_entry), contents(&after_entry));
+    let texts = old.and_then(text).zip(new.and_then(text));
+    let binary = [old, new]
+        .into_iter()
+        .any(|b| b.is_some_and(|b| text(b).is_none()));
+    let diff = texts.map(|(old, new)| {
+        similar::TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+            .to_string()
+    });
+    Ok(Some(FileChange {
+        bytes_before: before_entry.map(|(size, _)| size),
+        bytes_after: after_entry.map(|(size, _)| size),
+        path,
+        kind,
+        diff,
+        binary,
+    }))
+}
+
+fn diff_pairs(mut pairs: Vec<Pair>) -> Result<Vec<FileChange>> {
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut changes = Vec::new();
+    for pair in pairs {
+        if let Some(change) = diff_pair(pair)? {
+            changes.push(change);
+            // One over, so the report can tell it was cut
+            if changes.len() > MAX_CHANGES {
+                break;
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Have `cmd` start in a user and mount namespace of its own, with `shadow`
+/// mounted over its cwd. Its user and group ids map to themselves, so files
+/// it writes are owned as usual.
+#[cfg(target_os = "linux")]
+pub(super) fn apply(cmd: &mut tokio::process::Command, shadow: &Shadow) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    // Overlay options are separated by `,` and layers by `:`
+    let escape = |path: &Path| {
+        let mut escaped = Vec::new();
+        for b in path.as_os_str().as_bytes() {
+            if matches!(b, b'\\' | b',' | b':') {
+                escaped.push(b'\\');
+            }
+            escaped.push(*b);
+        }
+        escaped
+    };
+    let mut options = b"lowerdir=".to_vec();
+    options.extend(escape(&shadow.lower));
+    options.extend(b",upperdir=");
+    options.extend(escape(&shadow.upper));
+    options.extend(b",workdir=");
+    options.extend(escape(&shadow.work));
+    options.extend(b",userxattr");
+    let options = CString::new(options).context("Invalid overlay path")?;
+    let target = CString::new(shadow.lower.as_os_str().as_bytes()).context("Invalid cwd")?;
+    // SAFETY: getuid and getgid cannot fail
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{} {} 1", uid, uid);
+    let gid_map = format!("{} {} 1", gid, gid);
+
+    // SAFETY: the closure runs between fork and exec and only makes system
+    // calls, with strings prepared beforehand
+    unsafe {
+        cmd.pre_exec(move || {
+            let check = |result: libc::c_int| {
+                if result == 0 {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::last_os_error())
+                }
+            };
+            check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS))?;
+            write_proc(c"/proc/self/setgroups", b"deny")?;
+            write_proc(c"/proc/self/uid_map", uid_map.as_bytes())?;
+            write_proc(c"/proc/self/gid_map", gid_map.as_bytes())?;
+            // Keep the overlay out of every other namespace
+            check(libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            ))?;
+            check(libc::mount(
+                c"overlay".as_ptr(),
+                target.as_ptr(),
+                c"overlay".as_ptr(),
+                0,
+                options.as_ptr().cast(),
+            ))?;
+            // The cwd was entered before the mount; enter the overlay instead
+            check(libc::chdir(target.as_ptr()))
+        });
+    }
+    Ok(())
+}
+
+/// Write `contents` to a `/proc` file, between fork and exec
+#[cfg(target_os = "linux")]
+unsafe fn write_proc(path: &std::ffi::CStr, contents: &[u8]) -> std::io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+    let error = std::io::Error::last_os_error();
+    libc::close(fd);
+    if written == contents.len() as isize {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
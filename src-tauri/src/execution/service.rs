@@ -0,0 +1,445 @@
+//! Shared execution pipeline.
+//!
+//! Resolve → gate → run, as used by the `execute_terminal_command` and
+//! `execute_pipeline` Tauri commands, the HTTP API, and canvas runs. Errors
+//! are [`UserMessage`]s so every front end reports refusals (confirmation
+//! required, blocked, unresolved templates) with the same codes. Every run,
+//! refused or not, is traced as a `command_execution` or
+//! `pipeline_execution` span.
+
+use super::command::{
+    run_command_until, CommandOutcome, CommandSpec, OutputChunk, StopConditions, Termination,
+};
+use super::dry_run::DryRun;
+use super::elevation;
+use super::limits;
+use super::output::{self, OutputCap};
+use super::pipeline::{run_pipeline_until, PipelineOutcome, PipelineSpec};
+use super::queue::{
+    ConcurrencyConfig, ExecutionQueue, QueuePermit, QueueSnapshot, QueuedExecution,
+};
+use super::resolve::{
+    resolve_execution, ExecutionProfile, ExecutionRequest, PolicyPreview, ResolvedExecution,
+};
+use super::safety::{Admission, CommandGate};
+use crate::i18n::UserMessage;
+use crate::memory::{Command, MemoryStore, StoreOpener};
+use crate::telemetry::{Span, Tracer};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+pub struct ExecutionService {
+    gate: Arc<CommandGate>,
+    profiles: HashMap<String, ExecutionProfile>,
+    output: OutputCap,
+    /// Slots runs wait for once admitted
+    queue: Arc<ExecutionQueue>,
+    /// Where runs that break their resource limits or spill their output
+    /// are recorded
+    open_store: Option<StoreOpener>,
+    tracer: Arc<Tracer>,
+}
+
+/// Session of the runs an [`ExecutionService`] records in memory
+pub const EXECUTION_SESSION: &str = "execution";
+
+/// Span of a command run
+pub const COMMAND_SPAN: &str = "command_execution";
+
+/// Span of a pipeline run
+pub const PIPELINE_SPAN: &str = "pipeline_execution";
+
+impl ExecutionService {
+    pub fn new(gate: Arc<CommandGate>, profiles: HashMap<String, ExecutionProfile>) -> Self {
+        Self {
+            gate,
+            profiles,
+            output: OutputCap::default(),
+            queue: Arc::new(ExecutionQueue::new(&ConcurrencyConfig::default())),
+            open_store: None,
+            tracer: Arc::new(Tracer::disabled()),
+        }
+    }
+
+    /// Keep at most `output` of each command's stdout and stderr
+    pub fn with_output_cap(mut self, output: OutputCap) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Run at most `concurrency.max_concurrent` commands at once; the rest wait
+    pub fn with_concurrency(mut self, concurrency: &ConcurrencyConfig) -> Self {
+        self.queue = Arc::new(ExecutionQueue::new(concurrency));
+        self
+    }
+
+    /// Runs holding a slot, and runs waiting for one
+    pub fn queue(&self) -> QueueSnapshot {
+        self.queue.snapshot()
+    }
+
+    /// Record runs that break their resource limits, or spill their output
+    /// to a file, in the memory store
+    pub fn with_store(mut self, open_store: StoreOpener) -> Self {
+        self.open_store = Some(open_store);
+        self
+    }
+
+    /// Export a span for each run through `tracer`
+    pub fn with_tracer(mut self, tracer: Arc<Tracer>) -> Self {
+        self.tracer = tracer;
+        self
+    }
+
+    /// Where runs' spans go, for callers that trace the work around them
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    /// Resolve `request` exactly as [`execute`](Self::execute) would, without running it
+    pub fn preview(&self, request: &ExecutionRequest) -> Result<ResolvedExecution, UserMessage> {
+        let mut resolved = resolve_execution(request, &self.profiles, self.gate.policy())
+            .map_err(UserMessage::wrap("command-resolve-failed"))?;
+        resolved.spec.output = self.output;
+        Ok(resolved)
+    }
+
+    /// [`preview`](Self::preview) `request`, then check that its cwd exists and
+    /// its program can be found, without admitting or running it
+    pub fn dry_run(&self, request: &ExecutionRequest) -> Result<DryRun, UserMessage> {
+        Ok(DryRun::check(self.preview(request)?))
+    }
+
+    /// Resolve, gate, and run `request`. A non-zero exit is an `Ok` outcome;
+    /// callers decide whether that is an error for them.
+    pub async fn execute(
+        &self,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+    ) -> Result<CommandOutcome, UserMessage> {
+        self.execute_until(
+            request,
+            confirmation_token,
+            actor,
+            None,
+            StopConditions::default(),
+        )
+        .await
+    }
+
+    /// [`execute`](Self::execute), sending output to `chunks` while the command runs
+    pub async fn execute_streaming(
+        &self,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+        chunks: mpsc::UnboundedSender<OutputChunk>,
+    ) -> Result<CommandOutcome, UserMessage> {
+        self.execute_until(
+            request,
+            confirmation_token,
+            actor,
+            Some(chunks),
+            StopConditions::default(),
+        )
+        .await
+    }
+
+    /// [`execute`](Self::execute), killing the command once `stop` calls for
+    /// it; the outcome's `termination` says why it ended. Output goes to
+    /// `chunks` when set. Time spent waiting for a slot does not count
+    /// towards the timeout, but cancelling takes the run out of line.
+    pub async fn execute_until(
+        &self,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+        chunks: Option<mpsc::UnboundedSender<OutputChunk>>,
+        stop: StopConditions,
+    ) -> Result<CommandOutcome, UserMessage> {
+        let span = Span::root(COMMAND_SPAN);
+        self.execute_in(span, request, confirmation_token, actor, chunks, stop)
+            .await
+    }
+
+    /// [`execute`](Self::execute), traced as a child of `parent`
+    pub async fn execute_under(
+        &self,
+        parent: &Span,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+    ) -> Result<CommandOutcome, UserMessage> {
+        let span = parent.child(COMMAND_SPAN);
+        self.execute_in(
+            span,
+            request,
+            confirmation_token,
+            actor,
+            None,
+            StopConditions::default(),
+        )
+        .await
+    }
+
+    async fn execute_in(
+        &self,
+        mut span: Span,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+        chunks: Option<mpsc::UnboundedSender<OutputChunk>>,
+        stop: StopConditions,
+    ) -> Result<CommandOutcome, UserMessage> {
+        span.set_attribute("process.executable.name", request.command.clone());
+        span.set_attribute("runebook.actor", actor);
+        let result = self
+            .run_until(request, confirmation_token, actor, chunks, stop)
+            .await;
+        self.record_span(span, result.as_ref());
+        result
+    }
+
+    async fn run_until(
+        &self,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+        chunks: Option<mpsc::UnboundedSender<OutputChunk>>,
+        mut stop: StopConditions,
+    ) -> Result<CommandOutcome, UserMessage> {
+        let spec = self.admit(request, confirmation_token, actor)?;
+        let _permit = self
+            .wait_for_slot(
+                QueuedExecution::new(spec.command_line(), actor, request.priority),
+                &mut stop,
+            )
+            .await?;
+        let outcome = run_command_until(&spec, chunks.as_ref(), stop)
+            .await
+            .map_err(UserMessage::wrap("command-run-failed"))?;
+        let notable = outcome.limit_exceeded.is_some()
+            || !outcome.spilled.is_empty()
+            || outcome.suspended_ms.is_some();
+        if let (true, Some(open_store)) = (notable, &self.open_store) {
+            let recorded = async {
+                let store = open_store().await?;
+                record(&store, &spec, &outcome, actor).await.map(drop)
+            };
+            if let Err(e) = recorded.await {
+                log::warn!("[execution] Run of {} not recorded: {:#}", spec.command, e);
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Resolve and gate each stage of `spec` as [`execute`](Self::execute)
+    /// would a single command, then run the stages as one pipeline holding
+    /// one slot. A refused stage's message carries its index as `stage`.
+    /// Confirmation tokens are spent as stages are admitted, so every stage
+    /// that needs one is asked for it before any stage is admitted.
+    pub async fn execute_pipeline(
+        &self,
+        spec: &PipelineSpec,
+        actor: &str,
+        stop: StopConditions,
+    ) -> Result<PipelineOutcome, UserMessage> {
+        let mut span = Span::root(PIPELINE_SPAN);
+        span.set_attribute("pipeline.stages", spec.stages.len());
+        span.set_attribute("runebook.actor", actor);
+        let result = self.run_pipeline(spec, actor, stop).await;
+        self.record_span(span, result.as_ref().map(|pipeline| &pipeline.outcome));
+        result
+    }
+
+    async fn run_pipeline(
+        &self,
+        spec: &PipelineSpec,
+        actor: &str,
+        mut stop: StopConditions,
+    ) -> Result<PipelineOutcome, UserMessage> {
+        if spec.stages.is_empty() {
+            return Err(UserMessage::new("pipeline-empty"));
+        }
+        let requests = spec.stage_requests();
+        for (i, (request, stage)) in requests.iter().zip(&spec.stages).enumerate() {
+            let unconfirmed = stage.confirmation_token.is_none()
+                && matches!(self.preview(request)?.policy, PolicyPreview::Confirm { .. });
+            if unconfirmed {
+                if let Err(refusal) = self.admit(request, None, actor) {
+                    return Err(refusal.with("stage", i));
+                }
+            }
+        }
+        let mut specs = Vec::with_capacity(requests.len());
+        for (i, (request, stage)) in requests.iter().zip(&spec.stages).enumerate() {
+            specs.push(
+                self.admit(request, stage.confirmation_token.as_deref(), actor)
+                    .map_err(|refusal| refusal.with("stage", i))?,
+            );
+        }
+        let command_line = specs
+            .iter()
+            .map(CommandSpec::command_line)
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let _permit = self
+            .wait_for_slot(
+                QueuedExecution::new(command_line, actor, spec.request.priority),
+                &mut stop,
+            )
+            .await?;
+        run_pipeline_until(&specs, spec.pipefail, stop)
+            .await
+            .map_err(UserMessage::wrap("command-run-failed"))
+    }
+
+    /// Close `span` with how the run ended and hand it to the tracer
+    fn record_span(&self, mut span: Span, result: Result<&CommandOutcome, &UserMessage>) {
+        match result {
+            Ok(outcome) => {
+                if let Some(code) = outcome.exit_code {
+                    span.set_attribute("process.exit_code", code);
+                }
+                span.set_attribute("runebook.duration_ms", outcome.duration_ms);
+                if outcome.success {
+                    span.finish();
+                } else {
+                    span.fail(failure_message(outcome).code);
+                }
+            }
+            Err(refusal) => span.fail(refusal.code),
+        }
+        self.tracer.record(span);
+    }
+
+    /// Wait in line for a slot for `entry`; cancelling takes it out of line
+    async fn wait_for_slot(
+        &self,
+        entry: QueuedExecution,
+        stop: &mut StopConditions,
+    ) -> Result<QueuePermit, UserMessage> {
+        let slot = self.queue.acquire(entry);
+        Ok(match stop.cancel.as_mut() {
+            Some(cancel) => tokio::select! {
+                permit = slot => permit,
+                _ = cancel.cancelled() => return Err(UserMessage::new("command-cancelled")),
+            },
+            None => slot.await,
+        })
+    }
+
+    /// Resolve `request` and pass it through the safety gate, without running it
+    pub(crate) fn admit(
+        &self,
+        request: &ExecutionRequest,
+        confirmation_token: Option<&str>,
+        actor: &str,
+    ) -> Result<CommandSpec, UserMessage> {
+        let resolved = self.preview(request)?;
+        if !resolved.unresolved_variables.is_empty() {
+            return Err(UserMessage::new("template-unresolved")
+                .with("names", resolved.unresolved_variables.join(", ")));
+        }
+        let spec = resolved.spec;
+
+        match self.gate.admit(&spec, confirmation_token, actor) {
+            Admission::Run => {}
+            Admission::NeedsConfirmation { token, reason } => {
+                return Err(UserMessage::new("confirmation-required")
+                    .with("token", token)
+                    .with("rule", reason.rule)
+                    .with("reason", reason.reason));
+            }
+            Admission::Blocked(reason) => {
+                return Err(UserMessage::new("command-blocked")
+                    .with("rule", reason.rule)
+                    .with("reason", reason.reason));
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// `command-failed` message for an unsuccessful outcome, or
+/// `command-needs-elevation` when it failed for lack of rights, or
+/// `command-timed-out` / `command-cancelled` / `resource-limit-exceeded` for
+/// a killed one
+pub fn failure_message(outcome: &CommandOutcome) -> UserMessage {
+    match outcome.termination {
+        Termination::TimedOut => {
+            return UserMessage::new("command-timed-out").with("duration_ms", outcome.duration_ms)
+        }
+        Termination::Cancelled => return UserMessage::new("command-cancelled"),
+        Termination::LimitExceeded => {
+            let message = UserMessage::new("resource-limit-exceeded");
+            return match &outcome.limit_exceeded {
+                Some(exceeded) => message
+                    .with("limit", exceeded.limit.as_str())
+                    .with("reason", exceeded),
+                None => message.with("limit", "unknown").with("reason", "unknown"),
+            };
+        }
+        Termination::Exited => {}
+    }
+    let code = if elevation::needs_elevation(outcome) {
+        "command-needs-elevation"
+    } else {
+        "command-failed"
+    };
+    UserMessage::new(code)
+        .with(
+            "exit_code",
+            outcome
+                .exit_code
+                .map_or("unknown".to_string(), |c| c.to_string()),
+        )
+        .with("stderr", outcome.stderr.trim())
+}
+
+/// Store a run as a command of [`EXECUTION_SESSION`], with an error for the
+/// limit it broke and an artifact for each stream it spilled. A run the
+/// machine slept through is stored `suspended`.
+pub(super) async fn record(
+    store: &MemoryStore,
+    spec: &CommandSpec,
+    outcome: &CommandOutcome,
+    actor: &str,
+) -> Result<Command> {
+    let mut command = Command::new(
+        EXECUTION_SESSION.to_string(),
+        spec.command.clone(),
+        spec.args.clone(),
+        spec.cwd.clone().unwrap_or_default(),
+    );
+    // The record spans the whole run, sleep included, like captured commands
+    let duration_ms = outcome.duration_ms + outcome.suspended_ms.unwrap_or(0);
+    command.started_at = outcome.started_at;
+    command.ended_at =
+        Some(outcome.started_at + chrono::Duration::milliseconds(duration_ms as i64));
+    command.duration_ms = Some(duration_ms);
+    command.suspended_ms = outcome.suspended_ms;
+    command.exit_code = outcome.exit_code;
+    command.success = outcome.success;
+    if let Some(exceeded) = &outcome.limit_exceeded {
+        command
+            .tags
+            .push(format!("limit:{}", exceeded.limit.as_str()));
+    }
+    store.store_command(command.clone()).await?;
+
+    if let Some(exceeded) = &outcome.limit_exceeded {
+        store
+            .store_error(limits::violation_error(&command, outcome, exceeded, actor))
+            .await?;
+    }
+    for spilled in &outcome.spilled {
+        store
+            .store_artifact(output::spill_artifact(&command, spilled))
+            .await?;
+    }
+    Ok(command)
+}
@@ -0,0 +1,210 @@
+//! Signals for whole process trees.
+//!
+//! Killing only a command's own process leaves whatever it started running:
+//! `npm run dev` is gone, the `node` it spawned is not. Commands therefore
+//! start as the leader of a new process group on Unix, and are assigned to a
+//! Job Object on Windows. A [`ProcessTree`] then reaches every process below
+//! the command, so a signal or a kill covers them all.
+//!
+//! Windows has no signals: `SIGINT` becomes a Ctrl+Break event for the
+//! command's console process group, and `SIGTERM` and `SIGKILL` terminate the
+//! whole job.
+
+#[cfg(windows)]
+use super::limits::ResourceLimits;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Signals `send_signal` delivers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum Signal {
+    #[serde(rename = "SIGINT")]
+    Interrupt,
+    #[serde(rename = "SIGTERM")]
+    Terminate,
+    #[serde(rename = "SIGKILL")]
+    Kill,
+}
+
+/// Have `cmd` start its own process group, so [`ProcessTree::of`] can reach
+/// everything it spawns
+pub fn new_process_group(cmd: &mut tokio::process::Command) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    #[cfg(windows)]
+    cmd.creation_flags(windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP);
+}
+
+/// A command's process and everything it started
+#[derive(Debug)]
+pub struct ProcessTree {
+    pid: u32,
+    #[cfg(windows)]
+    job: JobHandle,
+}
+
+impl ProcessTree {
+    /// The tree below `pid`, which must lead its own process group: a
+    /// command started after [`new_process_group`], or a PTY session
+    #[cfg(unix)]
+    pub fn of(pid: u32) -> Result<Self> {
+        Ok(Self { pid })
+    }
+
+    /// The tree below `pid`, assigning it to a new Job Object. Processes it
+    /// spawned before this call are not in the job.
+    #[cfg(windows)]
+    pub fn of(pid: u32) -> Result<Self> {
+        Ok(Self {
+            pid,
+            job: JobHandle::assign(pid)?,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Cap the CPU time of each process in the tree and the memory of all
+    /// of them together
+    #[cfg(windows)]
+    pub fn limit(&self, limits: &ResourceLimits) -> Result<()> {
+        self.job.limit(limits)
+    }
+
+    /// Deliver `signal` to every process in the tree. A tree whose processes
+    /// all exited is not an error.
+    #[cfg(unix)]
+    pub fn signal(&self, signal: Signal) -> Result<()> {
+        let signal = match signal {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        };
+        // SAFETY: killpg only takes plain integers
+        if unsafe { libc::killpg(self.pid as libc::pid_t, signal) } == 0 {
+            return Ok(());
+        }
+        match std::io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+            e => Err(anyhow::Error::new(e)
+                .context(format!("Failed to signal process group {}", self.pid))),
+        }
+    }
+
+    /// Deliver `signal` to every process in the tree
+    #[cfg(windows)]
+    pub fn signal(&self, signal: Signal) -> Result<()> {
+        use windows_sys::Win32::System::Console::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+        use windows_sys::Win32::System::JobObjects::TerminateJobObject;
+
+        // SAFETY: the job handle stays open for as long as `self` lives
+        let delivered = unsafe {
+            match signal {
+                Signal::Interrupt => GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, self.pid),
+                Signal::Terminate | Signal::Kill => TerminateJobObject(self.job.0 as _, 1),
+            }
+        };
+        if delivered == 0 {
+            return Err(anyhow::Error::new(std::io::Error::last_os_error())
+                .context(format!("Failed to signal process tree {}", self.pid)));
+        }
+        Ok(())
+    }
+}
+
+/// An open Job Object, closed on drop
+#[cfg(windows)]
+#[derive(Debug)]
+struct JobHandle(isize);
+
+#[cfg(windows)]
+impl JobHandle {
+    fn assign(pid: u32) -> Result<Self> {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+        };
+
+        // SAFETY: every handle opened here is checked, and closed unless it
+        // is kept in the returned JobHandle
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                anyhow::bail!(
+                    "Failed to create a job object: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            let job = Self(job as isize);
+            let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+            if process.is_null() {
+                anyhow::bail!(
+                    "Failed to open process {}: {}",
+                    pid,
+                    std::io::Error::last_os_error()
+                );
+            }
+            let assigned = AssignProcessToJobObject(job.0 as _, process);
+            let error = std::io::Error::last_os_error();
+            CloseHandle(process);
+            if assigned == 0 {
+                anyhow::bail!("Failed to assign process {} to a job: {}", pid, error);
+            }
+            Ok(job)
+        }
+    }
+
+    fn limit(&self, limits: &ResourceLimits) -> Result<()> {
+        use windows_sys::Win32::System::JobObjects::{
+            JobObjectExtendedLimitInformation, SetInformationJobObject,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_JOB_MEMORY,
+            JOB_OBJECT_LIMIT_PROCESS_TIME,
+        };
+
+        // SAFETY: the structure is plain data, for which all zeroes is valid
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        if let Some(secs) = limits.cpu_secs {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+            // In 100-nanosecond units
+            info.BasicLimitInformation.PerProcessUserTimeLimit =
+                secs.saturating_mul(10_000_000).min(i64::MAX as u64) as i64;
+        }
+        if let Some(bytes) = limits.memory_bytes {
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+            info.JobMemoryLimit = bytes.min(usize::MAX as u64) as usize;
+        }
+        if info.BasicLimitInformation.LimitFlags == 0 {
+            return Ok(());
+        }
+        // SAFETY: `info` outlives the call and its size is passed with it
+        let set = unsafe {
+            SetInformationJobObject(
+                self.0 as _,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const _,
+                std::mem::size_of_val(&info) as u32,
+            )
+        };
+        if set == 0 {
+            anyhow::bail!(
+                "Failed to set job limits: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from CreateJobObjectW and is closed once
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0 as _);
+        }
+    }
+}
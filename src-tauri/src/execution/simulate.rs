@@ -0,0 +1,127 @@
+//! Simulated canvas runs.
+//!
+//! A simulation walks a canvas exactly like [`super::run_canvas`], but no
+//! command runs: each terminal node returns a [`NodeMock`] instead, so a
+//! canvas's downstream logic and failure branches can be tried safely. Mocks
+//! are given per node, or taken from the report of a real run; a node with
+//! neither is refused with `simulation-mock-missing`.
+
+use super::canvas::{CanvasDocument, CanvasRunReport, CanvasStepper};
+use super::command::{CommandOutcome, Termination};
+use crate::i18n::UserMessage;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+
+/// What a simulated terminal node returns
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct NodeMock {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+    pub duration_ms: u64,
+}
+
+impl NodeMock {
+    pub fn from_outcome(outcome: &CommandOutcome) -> Self {
+        Self {
+            stdout: outcome.stdout.clone(),
+            stderr: outcome.stderr.clone(),
+            exit_code: outcome.exit_code.unwrap_or(-1),
+            duration_ms: outcome.duration_ms,
+        }
+    }
+
+    fn outcome(&self) -> CommandOutcome {
+        CommandOutcome {
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+            stdout_encoding: Default::default(),
+            stderr_encoding: Default::default(),
+            content_type: "text/plain".to_string(),
+            exit_code: Some(self.exit_code),
+            success: self.exit_code == 0,
+            duration_ms: self.duration_ms,
+            started_at: Utc::now(),
+            termination: Termination::Exited,
+            objects: None,
+            limit_exceeded: None,
+            truncated: false,
+            total_bytes: (self.stdout.len() + self.stderr.len()) as u64,
+            spilled: Vec::new(),
+            suspended_ms: None,
+            stdout_spans: Vec::new(),
+            stderr_spans: Vec::new(),
+        }
+    }
+}
+
+/// The parts of a [`CanvasRunReport`] a simulation replays
+#[derive(Debug, Clone, Default, Deserialize, TS)]
+#[ts(export)]
+pub struct RecordedRun {
+    pub nodes: Vec<RecordedNode>,
+}
+
+#[derive(Debug, Clone, Deserialize, TS)]
+#[ts(export)]
+pub struct RecordedNode {
+    pub node_id: String,
+    /// `None` for nodes that were skipped or refused
+    #[serde(default)]
+    pub outcome: Option<CommandOutcome>,
+}
+
+/// A canvas to simulate and the results its terminal nodes return
+#[derive(Debug, Clone, Deserialize, TS)]
+#[ts(export)]
+pub struct SimulationSpec {
+    pub canvas: CanvasDocument,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    /// Mocks by node id; they win over `recorded`
+    #[serde(default)]
+    pub mocks: HashMap<String, NodeMock>,
+    /// A real run of the canvas whose results are replayed
+    #[serde(default)]
+    pub recorded: Option<RecordedRun>,
+}
+
+impl SimulationSpec {
+    /// Mocks by node id, recorded results first and explicit mocks over them
+    pub fn all_mocks(&self) -> HashMap<String, NodeMock> {
+        let mut mocks: HashMap<String, NodeMock> = self
+            .recorded
+            .iter()
+            .flat_map(|run| &run.nodes)
+            .filter_map(|node| {
+                let outcome = node.outcome.as_ref()?;
+                Some((node.node_id.clone(), NodeMock::from_outcome(outcome)))
+            })
+            .collect();
+        mocks.extend(self.mocks.clone());
+        mocks
+    }
+}
+
+/// Run `spec.canvas` with every terminal node returning its mock
+pub async fn simulate_canvas(spec: &SimulationSpec) -> Result<CanvasRunReport, UserMessage> {
+    let mocks = spec.all_mocks();
+    let mut stepper = CanvasStepper::new(&spec.canvas)?;
+    while stepper
+        .step_with(&spec.variables, |node, _| {
+            let mock = mocks.get(&node.id);
+            async move {
+                mock.map(NodeMock::outcome).ok_or_else(|| {
+                    UserMessage::new("simulation-mock-missing").with("node", &node.id)
+                })
+            }
+        })
+        .await
+        .is_some()
+    {}
+    Ok(stepper.finish())
+}
@@ -0,0 +1,158 @@
+//! Where a command runs: on this machine, or in a WSL distro.
+//!
+//! A [`ExecutionTarget::Wsl`] command is started through
+//! `wsl.exe -d <distro> --exec`, so it runs directly in the distro, without a
+//! shell, just as local commands do. Its cwd is given to WSL in Linux form:
+//! `C:\src\app` becomes `/mnt/c/src/app` and `\\wsl$\Ubuntu\home\me` becomes
+//! `/home/me`. Where RuneBook itself needs the directory, as watch mode does,
+//! the Linux form is turned back into a Windows path. The spec's env is
+//! passed into the distro through `WSLENV`.
+//!
+//! Resource limits apply to `wsl.exe`, not to the Linux processes it starts.
+
+use super::command::{valid_env_name, CommandSpec, ExecutionMode};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use ts_rs::TS;
+
+/// Where a command runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[ts(export)]
+pub enum ExecutionTarget {
+    /// This machine
+    #[default]
+    Local,
+    /// A WSL distro, by name as `wsl.exe --list` shows it
+    Wsl { distro: String },
+}
+
+impl fmt::Display for ExecutionTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionTarget::Local => write!(f, "local"),
+            ExecutionTarget::Wsl { distro } => write!(f, "wsl:{}", distro),
+        }
+    }
+}
+
+impl ExecutionTarget {
+    pub fn is_local(&self) -> bool {
+        *self == ExecutionTarget::Local
+    }
+
+    /// `path` on the target as this machine reaches it
+    pub fn host_path(&self, path: &str) -> String {
+        match self {
+            ExecutionTarget::Local => path.to_string(),
+            ExecutionTarget::Wsl { distro } => to_windows_path(path, distro),
+        }
+    }
+}
+
+/// Distro names are letters, digits, `.`, `-` and `_`
+fn valid_distro(distro: &str) -> bool {
+    !distro.is_empty()
+        && distro
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// `wsl.exe`'s arguments for running `spec` in `distro`
+pub fn wsl_args(spec: &CommandSpec, distro: &str) -> Vec<String> {
+    let mut args = vec!["-d".to_string(), distro.to_string()];
+    if let Some(cwd) = spec.cwd.as_deref().filter(|c| !c.is_empty()) {
+        args.push("--cd".to_string());
+        args.push(to_linux_path(cwd));
+    }
+    args.push("--exec".to_string());
+    args.push(spec.command.clone());
+    args.extend(spec.args.iter().cloned());
+    args
+}
+
+/// `wsl.exe` running `spec` in `distro`; its cwd is left to WSL
+pub(super) fn wsl_command(spec: &CommandSpec, distro: &str) -> Result<tokio::process::Command> {
+    if !valid_distro(distro) {
+        anyhow::bail!("Invalid WSL distro name: {:?}", distro);
+    }
+    if spec.mode == ExecutionMode::Pwsh {
+        anyhow::bail!("PowerShell mode cannot run in a WSL distro");
+    }
+    if !cfg!(windows) {
+        anyhow::bail!("WSL targets are only available on Windows");
+    }
+    let mut cmd = tokio::process::Command::new("wsl.exe");
+    cmd.args(wsl_args(spec, distro))
+        // wsl.exe writes its own messages as UTF-16 otherwise
+        .env("WSL_UTF8", "1");
+    let mut shared: Vec<&str> = spec
+        .env
+        .keys()
+        .map(String::as_str)
+        .filter(|name| valid_env_name(name) && *name != "WSLENV")
+        .collect();
+    if !shared.is_empty() {
+        shared.sort_unstable();
+        let inherited = std::env::var("WSLENV").unwrap_or_default();
+        let wslenv = std::iter::once(inherited.as_str())
+            .filter(|v| !v.is_empty())
+            .chain(shared)
+            .collect::<Vec<_>>()
+            .join(":");
+        cmd.env("WSLENV", wslenv);
+    }
+    Ok(cmd)
+}
+
+/// `path` as WSL sees it: drive paths under `/mnt`, paths into a distro's
+/// share (`\\wsl$\<distro>\...`, `\\wsl.localhost\<distro>\...`) from its
+/// root. Linux paths are kept.
+pub fn to_linux_path(path: &str) -> String {
+    if path.starts_with('/') {
+        return path.to_string();
+    }
+    let slashed = path.replace('\\', "/");
+    let lower = slashed.to_ascii_lowercase();
+    for share in ["//wsl$/", "//wsl.localhost/"] {
+        if lower.starts_with(share) {
+            let rest = &slashed[share.len()..];
+            return match rest.find('/') {
+                Some(slash) => rest[slash..].to_string(),
+                None => "/".to_string(),
+            };
+        }
+    }
+    let bytes = slashed.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        let drive = (bytes[0] as char).to_ascii_lowercase();
+        let rest = slashed[2..].trim_start_matches('/');
+        return if rest.is_empty() {
+            format!("/mnt/{}", drive)
+        } else {
+            format!("/mnt/{}/{}", drive, rest)
+        };
+    }
+    slashed
+}
+
+/// `path` in `distro` as Windows reaches it: `/mnt/c/...` on its drive,
+/// the rest through `\\wsl.localhost\<distro>`. Other paths are kept.
+pub fn to_windows_path(path: &str, distro: &str) -> String {
+    if !path.starts_with('/') {
+        return path.to_string();
+    }
+    let mut parts = path.trim_start_matches('/').splitn(3, '/');
+    if let (Some("mnt"), Some(drive)) = (parts.next(), parts.next()) {
+        if drive.len() == 1 && drive.as_bytes()[0].is_ascii_alphabetic() {
+            let rest = parts.next().unwrap_or("").replace('/', "\\");
+            return format!("{}:\\{}", drive.to_ascii_uppercase(), rest);
+        }
+    }
+    format!(
+        "\\\\wsl.localhost\\{}{}",
+        distro,
+        path.trim_end_matches('/').replace('/', "\\")
+    )
+}
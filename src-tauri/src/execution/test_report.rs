@@ -0,0 +1,204 @@
+//! JUnit XML and TAP reports of canvas runs.
+//!
+//! A canvas whose terminal nodes carry [assertions](super::assertions) is a
+//! check, and CI systems read check results as JUnit XML or TAP. Each
+//! terminal node becomes one test case, named by its label (or id), with its
+//! duration; a failed node carries the assertions it failed, or its exit
+//! status and stderr, a refused node the reason it was refused, and a
+//! skipped node is marked skipped.
+
+use super::canvas::{CanvasRunReport, NodeRun, NodeRunStatus};
+use super::service::failure_message;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A format test results are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestReportFormat {
+    Junit,
+    /// Test Anything Protocol, version 13
+    Tap,
+}
+
+impl TestReportFormat {
+    /// `report` in this format
+    pub fn render(self, report: &CanvasRunReport, suite: &str) -> String {
+        match self {
+            TestReportFormat::Junit => junit(report, suite),
+            TestReportFormat::Tap => tap(report),
+        }
+    }
+
+    /// Write `report` to `path` in this format; `-` writes to stdout
+    pub fn write(self, report: &CanvasRunReport, suite: &str, path: &Path) -> Result<()> {
+        let text = self.render(report, suite);
+        if path == Path::new("-") {
+            print!("{}", text);
+            return Ok(());
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn name(node: &NodeRun) -> &str {
+    if node.label.is_empty() {
+        &node.node_id
+    } else {
+        &node.label
+    }
+}
+
+fn seconds(node: &NodeRun) -> f64 {
+    node.outcome
+        .as_ref()
+        .map_or(0.0, |o| o.duration_ms as f64 / 1000.0)
+}
+
+/// Why `node` did not pass, one line per reason; empty when it did
+pub fn failure_lines(node: &NodeRun) -> Vec<String> {
+    match node.status {
+        NodeRunStatus::Succeeded => Vec::new(),
+        NodeRunStatus::Skipped => vec!["an upstream node did not succeed".to_string()],
+        NodeRunStatus::Refused | NodeRunStatus::Failed => {
+            let mut lines: Vec<String> = node
+                .assertions
+                .iter()
+                .filter_map(|a| a.failure.as_ref().map(ToString::to_string))
+                .collect();
+            if let Some(error) = &node.error {
+                lines.push(error.to_string());
+            }
+            match &node.outcome {
+                Some(outcome) if lines.is_empty() => {
+                    lines.push(failure_message(outcome).to_string())
+                }
+                _ => {}
+            }
+            lines
+        }
+    }
+}
+
+/// `text` with the characters XML reserves escaped, and control characters
+/// XML 1.0 cannot hold dropped
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `report` as a JUnit XML document with one `<testsuite>` named `suite`
+pub fn junit(report: &CanvasRunReport, suite: &str) -> String {
+    let count = |status| report.nodes.iter().filter(|n| n.status == status).count();
+    let time: f64 = report.nodes.iter().map(seconds).sum();
+    // The same totals go on <testsuites> and its one <testsuite>
+    let totals = format!(
+        "name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+        xml_escape(suite),
+        report.nodes.len(),
+        count(NodeRunStatus::Failed),
+        count(NodeRunStatus::Refused),
+        count(NodeRunStatus::Skipped),
+        time
+    );
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<testsuites {}>\n", totals));
+    xml.push_str(&format!("  <testsuite {}>\n", totals));
+    for node in &report.nodes {
+        xml.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            xml_escape(&report.canvas_id),
+            xml_escape(name(node)),
+            seconds(node)
+        ));
+        let lines = failure_lines(node);
+        let element = match node.status {
+            NodeRunStatus::Succeeded => {
+                xml.push_str("/>\n");
+                continue;
+            }
+            NodeRunStatus::Failed => "failure",
+            NodeRunStatus::Refused => "error",
+            NodeRunStatus::Skipped => "skipped",
+        };
+        xml.push_str(&format!(
+            ">\n      <{} message=\"{}\">{}</{}>\n    </testcase>\n",
+            element,
+            xml_escape(lines.first().map_or("", String::as_str)),
+            xml_escape(&lines.join("\n")),
+            element
+        ));
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// `text` as a double-quoted YAML scalar, for TAP diagnostics
+fn yaml_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+/// `report` as TAP version 13, with a YAML diagnostic block per failure
+pub fn tap(report: &CanvasRunReport) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", report.nodes.len());
+    for (i, node) in report.nodes.iter().enumerate() {
+        // `#` starts a directive, so it cannot appear in a description
+        let description = name(node).replace('#', "\\#");
+        match node.status {
+            NodeRunStatus::Succeeded => {
+                out.push_str(&format!("ok {} - {}\n", i + 1, description));
+            }
+            NodeRunStatus::Skipped => {
+                out.push_str(&format!(
+                    "ok {} - {} # SKIP an upstream node did not succeed\n",
+                    i + 1,
+                    description
+                ));
+            }
+            NodeRunStatus::Failed | NodeRunStatus::Refused => {
+                out.push_str(&format!("not ok {} - {}\n", i + 1, description));
+                let lines = failure_lines(node);
+                out.push_str("  ---\n");
+                out.push_str(&format!(
+                    "  message: {}\n",
+                    yaml_string(lines.first().map_or("", String::as_str))
+                ));
+                let severity = match node.status {
+                    NodeRunStatus::Refused => "error",
+                    _ => "fail",
+                };
+                out.push_str(&format!("  severity: {}\n", severity));
+                if let Some(outcome) = &node.outcome {
+                    out.push_str(&format!("  duration_ms: {}\n", outcome.duration_ms));
+                    if let Some(code) = outcome.exit_code {
+                        out.push_str(&format!("  exit_code: {}\n", code));
+                    }
+                }
+                if lines.len() > 1 {
+                    out.push_str("  failures:\n");
+                    for line in &lines {
+                        out.push_str(&format!("    - {}\n", yaml_string(line)));
+                    }
+                }
+                out.push_str("  ...\n");
+            }
+        }
+    }
+    out
+}
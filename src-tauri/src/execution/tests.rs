@@ -0,0 +1,1909 @@
+use super::command::CommandSpec;
+use super::policy::*;
+use super::resolve::*;
+use super::safety::*;
+use crate::audit::AuditLog;
+use std::sync::Arc;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn detects_destructive_commands() {
+    assert!(classify("rm", &args(&["-rf", "*"])).is_some());
+    assert!(classify("/bin/rm", &args(&["-r", "/usr"])).is_some());
+    assert!(classify("psql", &args(&["-c", "drop table users"])).is_some());
+    assert!(classify("terraform", &args(&["destroy", "-auto-approve"])).is_some());
+    assert!(classify("git", &args(&["push", "--force", "origin", "main"])).is_some());
+    assert!(classify("git", &args(&["push", "origin", "+main"])).is_some());
+    assert!(classify("sudo", &args(&["rm", "-rf", "/"])).is_some());
+    assert!(classify("bash", &args(&["-c", "cd /tmp && rm -rf ./*"])).is_some());
+}
+
+#[test]
+fn looks_past_wrapper_options_and_their_values() {
+    // The values of `-u`, `-C` and the like are not the wrapped command
+    assert!(classify("sudo", &args(&["-u", "root", "rm", "-rf", "/"])).is_some());
+    assert!(classify("sudo", &args(&["-E", "-u", "root", "--", "rm", "-rf", "/"])).is_some());
+    assert!(classify("sudo", &args(&["-uroot", "rm", "-rf", "/"])).is_some());
+    assert!(classify("sudo", &args(&["--user", "root", "rm", "-rf", "/"])).is_some());
+    assert!(classify("doas", &args(&["-u", "admin", "terraform", "destroy"])).is_some());
+    assert!(classify(
+        "env",
+        &args(&["-u", "HOME", "-C", "/srv", "rm", "-rf", "/"])
+    )
+    .is_some());
+    assert!(classify("env", &args(&["-S", "rm -rf", "/"])).is_some());
+    assert!(classify("time", &args(&["-o", "t.log", "git", "push", "-f"])).is_some());
+    assert!(classify("sudo", &args(&["-u", "rm", "ls", "-la"])).is_none());
+}
+
+#[test]
+fn ignores_ordinary_commands() {
+    assert!(classify("rm", &args(&["-f", "build/out.o"])).is_none());
+    assert!(classify("rm", &args(&["-rf", "target/debug"])).is_none());
+    assert!(classify("git", &args(&["push", "origin", "main"])).is_none());
+    assert!(classify("psql", &args(&["-c", "select * from users"])).is_none());
+    assert!(classify("terraform", &args(&["plan"])).is_none());
+}
+
+#[test]
+fn confirmation_token_is_single_use_and_bound_to_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = CommandGate::new(SafetyConfig::default(), Arc::clone(&audit));
+    let spec = CommandSpec {
+        command: "rm".into(),
+        args: args(&["-rf", "*"]),
+        ..Default::default()
+    };
+
+    let Admission::NeedsConfirmation { token, .. } = gate.admit(&spec, None, "user") else {
+        panic!("expected confirmation request");
+    };
+    let other = CommandSpec {
+        args: args(&["-rf", "/"]),
+        ..spec.clone()
+    };
+    assert!(matches!(
+        gate.admit(&other, Some(&token), "user"),
+        Admission::NeedsConfirmation { .. }
+    ));
+    assert_eq!(gate.admit(&spec, Some(&token), "user"), Admission::Run);
+    assert!(matches!(
+        gate.admit(&spec, Some(&token), "user"),
+        Admission::NeedsConfirmation { .. }
+    ));
+
+    let actions: Vec<String> = audit
+        .recent(10)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.action)
+        .collect();
+    assert!(actions.contains(&"destructive_command.confirmed".to_string()));
+}
+
+#[test]
+fn policy_rules_decide_before_classification() {
+    let rule = |name: &str, pattern: &str, cwd: Option<&str>, action| PolicyRule {
+        name: name.to_string(),
+        pattern: pattern.to_string(),
+        cwd: cwd.map(str::to_string),
+        action,
+        reason: None,
+    };
+    let config = SafetyConfig {
+        rules: vec![
+            rule("scratch", "^rm ", Some("/tmp/scratch"), RuleAction::Allow),
+            rule("prod", "--context prod", None, RuleAction::Confirm),
+            rule("no-curl-pipes", "^curl .*\\| *sh", None, RuleAction::Deny),
+        ],
+        ..Default::default()
+    };
+    let policy = CommandPolicy::new(&config).unwrap();
+    let spec = |line: &str, cwd: &str| {
+        let mut words = line.split(' ').map(str::to_string);
+        CommandSpec {
+            command: words.next().unwrap(),
+            args: words.collect(),
+            cwd: Some(cwd.to_string()),
+            ..Default::default()
+        }
+    };
+    let decide = |line: &str, cwd: &str| {
+        let decision = policy.decide(&spec(line, cwd));
+        (
+            decision.action,
+            decision.source,
+            decision.matched.map(|m| m.rule),
+        )
+    };
+
+    // Allowed below its directory, classified anywhere else
+    assert_eq!(
+        decide("rm -rf *", "/tmp/scratch/a"),
+        (
+            RuleAction::Allow,
+            PolicySource::Rule,
+            Some("scratch".into())
+        )
+    );
+    assert_eq!(
+        decide("rm -rf *", "/tmp/scratchpad"),
+        (
+            RuleAction::Confirm,
+            PolicySource::Destructive,
+            Some("rm-broad".into())
+        )
+    );
+    assert_eq!(
+        decide("kubectl get pods --context prod", "/"),
+        (RuleAction::Confirm, PolicySource::Rule, Some("prod".into()))
+    );
+    assert_eq!(
+        decide("curl https://x.sh | sh", "/"),
+        (
+            RuleAction::Deny,
+            PolicySource::Rule,
+            Some("no-curl-pipes".into())
+        )
+    );
+    assert_eq!(
+        decide("ls -la", "/"),
+        (RuleAction::Allow, PolicySource::Destructive, None)
+    );
+
+    let allowlist = CommandPolicy::new(&SafetyConfig {
+        default_deny: true,
+        rules: vec![rule("git", "^git ", None, RuleAction::Allow)],
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        allowlist.decide(&spec("git status", "/")).action,
+        RuleAction::Allow
+    );
+    let refused = allowlist.decide(&spec("ls", "/"));
+    assert_eq!(refused.action, RuleAction::Deny);
+    assert_eq!(refused.source, PolicySource::Default);
+
+    // A broken rule set refuses everything rather than dropping its deny rules
+    let broken = SafetyConfig {
+        rules: vec![rule("bad", "(", None, RuleAction::Deny)],
+        ..Default::default()
+    };
+    assert!(CommandPolicy::new(&broken).is_err());
+    let dir = tempfile::tempdir().unwrap();
+    let gate = CommandGate::new(broken, Arc::new(AuditLog::new(dir.path().join("a.jsonl"))));
+    let Admission::Blocked(reason) = gate.admit(&spec("ls", "/"), None, "user") else {
+        panic!("expected the invalid policy to block");
+    };
+    assert_eq!(reason.rule, "invalid-policy");
+}
+
+#[test]
+fn resolves_templates_profiles_and_policy() {
+    let profiles = std::collections::HashMap::from([(
+        "staging".to_string(),
+        ExecutionProfile {
+            env: [("STAGE".to_string(), "staging".to_string())].into(),
+            cwd: Some("/srv/app".to_string()),
+            variables: [("dir".to_string(), "build".to_string())].into(),
+            ..Default::default()
+        },
+    )]);
+    let request = ExecutionRequest {
+        command: "rm".to_string(),
+        args: args(&["-rf", "{{dir}}/*", "{{missing}}"]),
+        env: [("STAGE".to_string(), "{{stage}}".to_string())].into(),
+        profile: Some("staging".to_string()),
+        variables: [("stage".to_string(), "override".to_string())].into(),
+        ..Default::default()
+    };
+
+    let resolved = resolve_execution(&request, &profiles, &CommandPolicy::default()).unwrap();
+    assert_eq!(resolved.spec.cwd.as_deref(), Some("/srv/app"));
+    assert_eq!(resolved.spec.args, args(&["-rf", "build/*", "{{missing}}"]));
+    assert_eq!(resolved.spec.env["STAGE"], "override");
+    assert_eq!(resolved.unresolved_variables, vec!["missing".to_string()]);
+    assert!(matches!(resolved.policy, PolicyPreview::Confirm { .. }));
+
+    let unknown = ExecutionRequest {
+        profile: Some("prod".to_string()),
+        ..request
+    };
+    assert!(resolve_execution(&unknown, &profiles, &CommandPolicy::default()).is_err());
+}
+
+#[test]
+fn orders_canvas_nodes_by_connections() {
+    use super::canvas::{execution_order, CanvasDocument};
+
+    let canvas: CanvasDocument = serde_json::from_value(serde_json::json!({
+        "id": "c",
+        "nodes": [
+            { "id": "report", "type": "display" },
+            { "id": "test", "type": "terminal", "command": "cargo", "args": ["test"] },
+            { "id": "build", "type": "terminal", "command": "cargo", "args": ["build"] }
+        ],
+        "connections": [
+            { "from": "build", "to": "test", "fromPort": "stdout", "toPort": "in" },
+            { "from": "test", "to": "report", "fromPort": "stdout", "toPort": "in" }
+        ]
+    }))
+    .unwrap();
+    assert_eq!(
+        execution_order(&canvas).unwrap(),
+        ["build", "test", "report"]
+    );
+
+    let mut cyclic = canvas.clone();
+    cyclic.connections[0].from = "report".to_string();
+    assert_eq!(execution_order(&cyclic).unwrap_err().code, "canvas-invalid");
+}
+
+#[test]
+fn lints_canvas_problems() {
+    use super::lint::{lint_canvas, LintSeverity};
+    use crate::memory::{ScrubConfig, Scrubber};
+    use std::collections::HashMap;
+
+    let raw: serde_json::Value = serde_yaml::from_str(
+        r#"
+id: lint
+nodes:
+  - id: deploy
+    type: terminal
+    label: Deploy
+    command: kubectl
+    args: [apply, -f, "{{manifest}}", "|", tee, "$HOME/log"]
+    env: { API_TOKEN: hunter2, REGION: "{{region}}" }
+    cwd: /
+    inputs: []
+  - id: wrapped
+    type: terminal
+    command: sh
+    args: [-c, "ls | wc -l"]
+    cwd: ./build
+  - id: report
+    type: display
+    inputs: [{ id: input, name: input }, { id: errors, name: errors }]
+connections:
+  - { from: deploy, to: report, fromPort: stdout, toPort: input }
+"#,
+    )
+    .unwrap();
+    let scrubber = Scrubber::new(&ScrubConfig::default()).unwrap();
+    let variables = HashMap::from([("region".to_string(), "eu".to_string())]);
+    let report = lint_canvas(&raw, &scrubber, &variables).unwrap();
+
+    let found: Vec<(&str, &str)> = report
+        .findings
+        .iter()
+        .map(|f| (f.node_id.as_deref().unwrap_or(""), f.message.code))
+        .collect();
+    assert!(found.contains(&("deploy", "lint-unresolved-variable")));
+    assert!(found.contains(&("deploy", "lint-shell-syntax")));
+    assert!(found.contains(&("deploy", "lint-broad-path")));
+    assert!(found.contains(&("deploy", "lint-secret-literal")));
+    assert!(found.contains(&("report", "lint-unconnected-input")));
+    assert!(!found.iter().any(|(node, _)| *node == "wrapped"));
+    assert_eq!(
+        found
+            .iter()
+            .filter(|f| **f == ("report", "lint-unconnected-input"))
+            .count(),
+        1
+    );
+    assert!(report.has_errors());
+    assert_eq!(report.findings[0].severity, LintSeverity::Error);
+    // Findings name the field, never the secret
+    let json = serde_json::to_string(&report).unwrap();
+    assert!(!json.contains("hunter2"), "{}", json);
+}
+
+#[tokio::test]
+async fn simulates_canvas_with_mocks_and_recorded_results() {
+    use super::canvas::NodeRunStatus;
+    use super::simulate::*;
+
+    let canvas = serde_json::json!({
+        "id": "c",
+        "nodes": [
+            { "id": "fetch", "type": "terminal", "command": "curl", "args": ["https://example.com"] },
+            { "id": "deploy", "type": "terminal", "command": "rm", "args": ["-rf", "/srv/app"] },
+            { "id": "notify", "type": "terminal", "command": "notify-send", "args": ["done"] },
+            { "id": "report", "type": "display" }
+        ],
+        "connections": [
+            { "from": "fetch", "to": "deploy" },
+            { "from": "deploy", "to": "notify" },
+            { "from": "deploy", "to": "report" }
+        ]
+    });
+    // A recorded real run supplies `fetch` and `deploy`; the failure branch
+    // is tried by mocking a failing deploy
+    let spec: SimulationSpec = serde_json::from_value(serde_json::json!({
+        "canvas": canvas,
+        "recorded": {
+            "canvas_id": "c",
+            "success": true,
+            "nodes": [
+                { "node_id": "fetch", "label": "", "status": "succeeded", "error": null,
+                  "outcome": { "stdout": "page", "stderr": "", "exit_code": 0, "success": true, "duration_ms": 5 } },
+                { "node_id": "deploy", "label": "", "status": "succeeded", "error": null,
+                  "outcome": { "stdout": "", "stderr": "", "exit_code": 0, "success": true, "duration_ms": 9 } }
+            ]
+        },
+        "mocks": { "deploy": { "stderr": "disk full", "exit_code": 1 } }
+    }))
+    .unwrap();
+    let report = simulate_canvas(&spec).await.unwrap();
+    let statuses: Vec<_> = report.nodes.iter().map(|n| n.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            NodeRunStatus::Succeeded,
+            NodeRunStatus::Failed,
+            NodeRunStatus::Skipped
+        ]
+    );
+    assert_eq!(report.nodes[0].outcome.as_ref().unwrap().stdout, "page");
+    assert!(!report.success);
+
+    // Without a failing mock, `notify` has no result to return and is refused
+    let mut spec = spec;
+    spec.mocks.clear();
+    let report = simulate_canvas(&spec).await.unwrap();
+    assert_eq!(report.nodes[2].status, NodeRunStatus::Refused);
+    assert_eq!(
+        report.nodes[2].error.as_ref().unwrap().code,
+        "simulation-mock-missing"
+    );
+}
+
+#[tokio::test]
+async fn replays_recorded_fixtures() {
+    use super::canvas::CanvasDocument;
+    use super::fixture::*;
+    use super::service::ExecutionService;
+    use crate::memory::{ScrubConfig, Scrubber};
+    use std::collections::HashMap;
+
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = ExecutionService::new(gate, Default::default());
+    let scrubber = Scrubber::new(&ScrubConfig::default()).unwrap();
+    let canvas: CanvasDocument = serde_json::from_value(serde_json::json!({
+        "id": "c",
+        "nodes": [
+            { "id": "hello", "type": "terminal", "command": "echo", "args": ["{{greeting}}"] },
+            { "id": "secret", "type": "terminal", "command": "sh", "args": ["-c", "echo password=s3cr3t$((40 + 2))xyz"] },
+            { "id": "fail", "type": "terminal", "command": "sh", "args": ["-c", "exit 3"] },
+            { "id": "after", "type": "terminal", "command": "echo", "args": ["unreachable"] }
+        ],
+        "connections": [{ "from": "fail", "to": "after" }]
+    }))
+    .unwrap();
+    let variables = HashMap::from([("greeting".to_string(), "hi".to_string())]);
+    let fixture = record_canvas(&execution, &scrubber, &canvas, &variables)
+        .await
+        .unwrap();
+    let path = dir.path().join("fixture.json");
+    fixture.save(&path).unwrap();
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("s3cr3t42xyz"), "{}", saved);
+    let fixture = CanvasFixture::load(&path).unwrap();
+    assert_eq!(fixture.nodes[0].output.as_ref().unwrap().stdout, "hi\n");
+
+    for mode in [ReplayMode::Mocked, ReplayMode::Live] {
+        let replay = replay_fixture(&execution, &scrubber, &fixture, None, mode)
+            .await
+            .unwrap();
+        assert!(replay.passed, "{:?}", replay.mismatches);
+    }
+
+    // An edited canvas resolves a different command and no longer fails
+    let mut edited = canvas.clone();
+    edited.nodes[0].args = vec!["hello".to_string()];
+    edited.nodes[2].args = vec!["-c".to_string(), "exit 0".to_string()];
+    let replay = replay_fixture(
+        &execution,
+        &scrubber,
+        &fixture,
+        Some(&edited),
+        ReplayMode::Live,
+    )
+    .await
+    .unwrap();
+    let fields: Vec<(&str, &str)> = replay
+        .mismatches
+        .iter()
+        .map(|m| (m.node_id.as_str(), m.field.as_str()))
+        .collect();
+    assert!(!replay.passed);
+    assert!(fields.contains(&("hello", "command_line")));
+    assert!(fields.contains(&("hello", "stdout")));
+    assert!(fields.contains(&("fail", "status")));
+    assert!(fields.contains(&("fail", "command_line")));
+}
+
+async fn next_debug(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<super::DebugEvent>,
+) -> super::debug::DebugEventKind {
+    tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap()
+        .kind
+}
+
+#[tokio::test]
+async fn debugger_pauses_at_breakpoints_and_steps() {
+    use super::canvas::NodeRunStatus;
+    use super::debug::*;
+    use super::service::ExecutionService;
+    use tokio::sync::mpsc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = Arc::new(ExecutionService::new(gate, Default::default()));
+    let spec: DebugSpec = serde_json::from_value(serde_json::json!({
+        "canvas": {
+            "id": "c",
+            "nodes": [
+                { "id": "a", "type": "terminal", "command": "echo", "args": ["one"] },
+                { "id": "b", "type": "terminal", "command": "echo", "args": ["{{word}}"] },
+                { "id": "c", "type": "terminal", "command": "echo", "args": ["three"] }
+            ],
+            "connections": [
+                { "from": "a", "to": "b", "toPort": "in" },
+                { "from": "b", "to": "c" }
+            ]
+        },
+        "variables": { "word": "two" },
+        "breakpoints": ["b"]
+    }))
+    .unwrap();
+    let manager = DebugManager::new();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let id = manager.start(execution, spec, tx).unwrap();
+
+    assert!(
+        matches!(next_debug(&mut rx).await, DebugEventKind::NodeFinished { run } if run.node_id == "a")
+    );
+    let DebugEventKind::Paused { state } = next_debug(&mut rx).await else {
+        panic!("expected a pause at b");
+    };
+    assert_eq!(
+        (state.node_id.as_str(), state.reason),
+        ("b", PauseReason::Breakpoint)
+    );
+    assert_eq!(state.resolved.unwrap().spec.args, ["two"]);
+    assert_eq!(state.inputs[0].stdout.as_deref(), Some("one\n"));
+    assert_eq!(state.completed.len(), 1);
+    assert_eq!(manager.inspect(&id).unwrap().node_id, "b");
+
+    assert!(manager.command(&id, DebugCommand::Step));
+    assert!(
+        matches!(next_debug(&mut rx).await, DebugEventKind::NodeFinished { run } if run.node_id == "b")
+    );
+    let DebugEventKind::Paused { state } = next_debug(&mut rx).await else {
+        panic!("expected a pause at c");
+    };
+    assert_eq!(
+        (state.node_id.as_str(), state.reason),
+        ("c", PauseReason::Step)
+    );
+
+    assert!(manager.command(&id, DebugCommand::Stop));
+    let DebugEventKind::Finished { report, stopped } = next_debug(&mut rx).await else {
+        panic!("expected the run to finish");
+    };
+    assert!(stopped && !report.success);
+    assert_eq!(report.nodes.len(), 2);
+    assert!(report
+        .nodes
+        .iter()
+        .all(|n| n.status == NodeRunStatus::Succeeded));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!manager.command(&id, DebugCommand::Continue));
+}
+
+async fn next(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<super::WatchEvent>,
+) -> (u32, super::WatchEventKind) {
+    let event = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    (event.run, event.kind)
+}
+
+#[tokio::test]
+async fn watch_reruns_on_change_and_reports_results() {
+    use super::service::ExecutionService;
+    use super::watch::*;
+    use tokio::sync::mpsc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = Arc::new(ExecutionService::new(gate, Default::default()));
+    let watched = dir.path().join("src");
+    std::fs::create_dir(&watched).unwrap();
+
+    let spec: WatchSpec = serde_json::from_value(serde_json::json!({
+        "request": {
+            "command": "sh",
+            "args": ["-c", "test ! -e fail"],
+            "cwd": watched.display().to_string()
+        },
+        "debounce_ms": 50,
+        "poll_ms": 50,
+        "node_id": "tests"
+    }))
+    .unwrap();
+    let manager = WatchManager::new();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let info = manager.start(Arc::clone(&execution), spec, tx).unwrap();
+
+    let started = WatchEventKind::Started { changed: vec![] };
+    assert_eq!(next(&mut rx).await, (1, started));
+    assert!(matches!(
+        next(&mut rx).await,
+        (1, WatchEventKind::Passed { .. })
+    ));
+
+    std::fs::write(watched.join("fail"), "").unwrap();
+    let started = WatchEventKind::Started {
+        changed: vec!["fail".to_string()],
+    };
+    assert_eq!(next(&mut rx).await, (2, started));
+    assert!(matches!(
+        next(&mut rx).await,
+        (
+            2,
+            WatchEventKind::Failed {
+                exit_code: Some(1),
+                ..
+            }
+        )
+    ));
+
+    assert_eq!(manager.list().len(), 1);
+    assert!(manager.stop(&info.id));
+    assert_eq!(next(&mut rx).await, (2, WatchEventKind::Stopped));
+    assert!(manager.list().is_empty());
+
+    // Nobody is around to confirm each run of a destructive command
+    let destructive: WatchSpec = serde_json::from_value(serde_json::json!({
+        "request": { "command": "rm", "args": ["-rf", "*"], "cwd": watched.display().to_string() }
+    }))
+    .unwrap();
+    let (tx, _rx) = mpsc::unbounded_channel();
+    let refused = manager.start(execution, destructive, tx).unwrap_err();
+    assert_eq!(refused.code, "watch-destructive");
+}
+
+#[tokio::test]
+async fn benchmarks_report_stats_outliers_and_comparison() {
+    use super::benchmark::*;
+    use super::command::{CommandOutcome, Termination};
+    use std::time::Duration;
+
+    let outcome = |success: bool| CommandOutcome {
+        stdout: String::new(),
+        stderr: "boom".to_string(),
+        stdout_encoding: Default::default(),
+        stderr_encoding: Default::default(),
+        content_type: "text/plain".to_string(),
+        exit_code: Some(i32::from(!success)),
+        success,
+        duration_ms: 0,
+        started_at: chrono::Utc::now(),
+        termination: Termination::Exited,
+        objects: None,
+        limit_exceeded: None,
+        truncated: false,
+        total_bytes: 0,
+        spilled: Vec::new(),
+        suspended_ms: None,
+        stdout_spans: Vec::new(),
+        stderr_spans: Vec::new(),
+    };
+    let spec = |runs: u32| BenchmarkSpec {
+        request: ExecutionRequest {
+            command: "sleep".to_string(),
+            ..Default::default()
+        },
+        runs,
+        warmup: 2,
+        ignore_failures: false,
+    };
+    let command = CommandSpec {
+        command: "sleep".to_string(),
+        args: args(&["0.01"]),
+        env: Default::default(),
+        cwd: Some("/tmp".to_string()),
+        stdin: None,
+        mode: Default::default(),
+        limits: Default::default(),
+        output: Default::default(),
+        ansi: Default::default(),
+        backend: Default::default(),
+        target: Default::default(),
+        shadow: None,
+    };
+
+    // Two warmups are untimed; one slow run stands out from the others
+    let mut times = vec![999, 999, 10, 12, 11, 10, 12, 11, 50].into_iter();
+    let result = benchmark_with(&spec(7), &command, || {
+        let ms = times.next().unwrap();
+        async move { Ok((outcome(true), Duration::from_millis(ms))) }
+    })
+    .await
+    .unwrap();
+    assert_eq!(result.times_ms.len(), 7);
+    assert_eq!(result.stats.median_ms, 11.0);
+    assert_eq!((result.stats.min_ms, result.stats.max_ms), (10.0, 50.0));
+    assert_eq!(result.stats.outliers, vec![6]);
+    assert!((result.stats.mean_ms - 16.571).abs() < 0.01);
+
+    let mut faster = benchmark_with(&spec(3), &command, || async {
+        Ok((outcome(true), Duration::from_millis(5)))
+    })
+    .await
+    .unwrap();
+    assert_eq!(faster.signature, result.signature);
+    faster.compare_to(&result);
+    let comparison = faster.comparison.unwrap();
+    assert_eq!(comparison.verdict, BenchmarkVerdict::Unchanged);
+    assert!(comparison.ratio < 0.35);
+
+    let failed = benchmark_with(&spec(3), &command, || async {
+        Ok((outcome(false), Duration::from_millis(5)))
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(failed.code, "command-failed");
+    assert_eq!(
+        benchmark_with(&spec(0), &command, || async {
+            Ok((outcome(true), Duration::ZERO))
+        })
+        .await
+        .unwrap_err()
+        .code,
+        "benchmark-invalid"
+    );
+}
+
+#[tokio::test]
+async fn pty_runs_interactive_commands_with_input_and_resize() {
+    use super::pty::*;
+    use tokio::sync::mpsc;
+
+    let manager = PtyManager::new();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let spec = CommandSpec {
+        command: "sh".to_string(),
+        args: args(&[
+            "-c",
+            "test -t 1 && echo tty; read line; stty size; echo got $line",
+        ]),
+        ..CommandSpec::default()
+    };
+    let info = manager.spawn(&spec, PtyDimensions::default(), tx).unwrap();
+    assert_eq!(manager.list().len(), 1);
+
+    // Everything up to the exit, as a view following the events would see it
+    let mut output = String::new();
+    let read_until = |rx: &mut mpsc::UnboundedReceiver<PtyEvent>, output: &mut String, text| {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !output.contains(text) {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "no {:?} in {:?}",
+                text,
+                output
+            );
+            match rx.try_recv() {
+                Ok(PtyEvent {
+                    kind: PtyEventKind::Output { data, .. },
+                    ..
+                }) => output.push_str(&data),
+                Ok(event) => panic!("unexpected {:?}", event),
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+    };
+    read_until(&mut rx, &mut output, "tty");
+
+    manager
+        .resize(
+            &info.id,
+            PtyDimensions {
+                cols: 100,
+                rows: 40,
+            },
+        )
+        .unwrap();
+    manager.write(&info.id, b"ping\r").unwrap();
+    read_until(&mut rx, &mut output, "got ping");
+    assert!(output.contains("40 100"));
+
+    let exited = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Some(PtyEvent {
+                kind: PtyEventKind::Exited { exit_code },
+                ..
+            }) = rx.recv().await
+            {
+                return exit_code;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(exited, Some(0));
+
+    // The finished session keeps its screen until it is closed
+    let screen = manager.screen(&info.id).unwrap();
+    assert!(!screen.running);
+    assert!(screen.data.contains("got ping"));
+    assert_eq!(screen.size.rows, 40);
+    assert!(manager.close(&info.id));
+    assert_eq!(manager.screen(&info.id).unwrap_err().code, "pty-not-found");
+}
+
+#[tokio::test]
+async fn hung_commands_time_out_or_are_cancelled() {
+    use super::command::*;
+    use super::running::RunningCommands;
+    use std::time::{Duration, Instant};
+
+    // Output written before the kill is kept
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "echo started; sleep 30"]),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let stop = StopConditions {
+        timeout: Some(Duration::from_millis(300)),
+        cancel: None,
+    };
+    let outcome = run_command_until(&spec, None, stop).await.unwrap();
+    assert_eq!(outcome.termination, Termination::TimedOut);
+    assert!(!outcome.success);
+    assert_eq!(outcome.stdout, "started\n");
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(
+        super::service::failure_message(&outcome).code,
+        "command-timed-out"
+    );
+
+    let running = Arc::new(RunningCommands::new());
+    let stop = StopConditions {
+        timeout: None,
+        cancel: Some(running.register("run-1").unwrap()),
+    };
+    assert_eq!(
+        running.register("run-1").unwrap_err().code,
+        "execution-id-in-use"
+    );
+    let run = tokio::spawn(async move { run_command_until(&spec, None, stop).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(running.cancel("run-1"));
+    let outcome = run.await.unwrap().unwrap();
+    assert_eq!(outcome.termination, Termination::Cancelled);
+    assert!(!running.cancel("run-1"));
+    // The id is free again once the run ends
+    drop(running.register("run-1").unwrap());
+
+    let quick = CommandSpec {
+        command: "true".into(),
+        ..Default::default()
+    };
+    let stop = StopConditions {
+        timeout: Some(Duration::from_secs(30)),
+        cancel: None,
+    };
+    let outcome = run_command_until(&quick, None, stop).await.unwrap();
+    assert_eq!(outcome.termination, Termination::Exited);
+    assert!(outcome.success);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn runs_end_when_the_process_does_not_when_its_output_does() {
+    use super::command::*;
+    use std::time::{Duration, Instant};
+
+    // Closing its output does not stop a command from timing out
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "exec >&- 2>&-; sleep 30"]),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let stop = StopConditions {
+        timeout: Some(Duration::from_millis(200)),
+        cancel: None,
+    };
+    let outcome = run_command_until(&spec, None, stop).await.unwrap();
+    assert_eq!(outcome.termination, Termination::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    // A background process holding the pipes open does not keep a finished
+    // command running until its timeout
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "echo done; sleep 5 &"]),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let stop = StopConditions {
+        timeout: Some(Duration::from_secs(3)),
+        cancel: None,
+    };
+    let outcome = run_command_until(&spec, None, stop).await.unwrap();
+    assert_eq!(outcome.termination, Termination::Exited);
+    assert!(outcome.success);
+    assert_eq!(outcome.stdout, "done\n");
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn signals_reach_the_whole_process_tree() {
+    use super::command::*;
+    use super::running::RunningCommands;
+    use super::signal::Signal;
+    use std::time::Duration;
+
+    // A zombie has exited; nothing may be left to reap it in a container
+    let exited = |pid: &str| {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .map_or(true, |stat| stat.contains(") Z "))
+    };
+
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "sleep 30 & echo $!; wait"]),
+        ..Default::default()
+    };
+    let running = Arc::new(RunningCommands::new());
+    assert!(running.signal("run-1", Signal::Terminate).is_none());
+    let stop = StopConditions {
+        timeout: None,
+        cancel: Some(running.register("run-1").unwrap()),
+    };
+    let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let run = tokio::spawn(async move { run_command_until(&spec, Some(&chunks), stop).await });
+    let grandchild = received.recv().await.unwrap().data.trim().to_string();
+    assert!(!exited(&grandchild));
+
+    running.signal("run-1", Signal::Terminate).unwrap().unwrap();
+    let outcome = tokio::time::timeout(Duration::from_secs(10), run)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(outcome.termination, Termination::Exited);
+    assert!(!outcome.success);
+    for _ in 0..50 {
+        if exited(&grandchild) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(exited(&grandchild));
+
+    // A timeout kills the grandchild too
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "sleep 30 & echo $!; sleep 30"]),
+        ..Default::default()
+    };
+    let stop = StopConditions {
+        timeout: Some(Duration::from_millis(300)),
+        cancel: None,
+    };
+    let outcome = run_command_until(&spec, None, stop).await.unwrap();
+    assert_eq!(outcome.termination, Termination::TimedOut);
+    let grandchild = outcome.stdout.trim().to_string();
+    for _ in 0..50 {
+        if exited(&grandchild) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(exited(&grandchild));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn resource_limits_stop_commands() {
+    use super::command::*;
+    use super::limits::*;
+    use std::time::{Duration, Instant};
+
+    // Request limits win; the profile fills in the rest
+    let profiles = std::collections::HashMap::from([(
+        "capped".to_string(),
+        ExecutionProfile {
+            limits: ResourceLimits {
+                cpu_secs: Some(60),
+                wall_secs: Some(600),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )]);
+    let request = ExecutionRequest {
+        command: "make".into(),
+        profile: Some("capped".into()),
+        limits: ResourceLimits {
+            wall_secs: Some(5),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let resolved = resolve_execution(&request, &profiles, &CommandPolicy::default()).unwrap();
+    assert_eq!(
+        resolved.spec.limits,
+        ResourceLimits {
+            cpu_secs: Some(60),
+            wall_secs: Some(5),
+            memory_bytes: None,
+        }
+    );
+
+    let started = Instant::now();
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "echo started; sleep 30"]),
+        limits: ResourceLimits {
+            wall_secs: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let outcome = run_command(&spec).await.unwrap();
+    assert_eq!(outcome.termination, Termination::LimitExceeded);
+    assert_eq!(outcome.stdout, "started\n");
+    assert_eq!(
+        outcome.limit_exceeded,
+        Some(ResourceLimitExceeded {
+            limit: ResourceLimit::WallTime,
+            max: 1,
+        })
+    );
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let message = super::service::failure_message(&outcome);
+    assert_eq!(message.code, "resource-limit-exceeded");
+
+    // A busy loop is stopped by the kernel once it has used its CPU time
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "while :; do :; done"]),
+        limits: ResourceLimits {
+            cpu_secs: Some(1),
+            wall_secs: Some(30),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let outcome = run_command(&spec).await.unwrap();
+    assert_eq!(outcome.termination, Termination::LimitExceeded);
+    assert_eq!(
+        outcome.limit_exceeded.map(|e| e.limit),
+        Some(ResourceLimit::CpuTime)
+    );
+
+    // Limits a command stays under change nothing
+    let spec = CommandSpec {
+        command: "true".into(),
+        limits: ResourceLimits {
+            cpu_secs: Some(10),
+            wall_secs: Some(10),
+            memory_bytes: Some(1 << 30),
+        },
+        ..Default::default()
+    };
+    let outcome = run_command(&spec).await.unwrap();
+    assert_eq!(outcome.termination, Termination::Exited);
+    assert!(outcome.success && outcome.limit_exceeded.is_none());
+}
+
+#[tokio::test]
+async fn output_over_the_cap_is_truncated_or_spilled() {
+    use super::command::*;
+    use super::output::*;
+
+    let spec = |spill| CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", "head -c 100000 /dev/zero | tr '\\0' x; echo oops >&2"]),
+        output: OutputCap {
+            max_bytes: 1000,
+            spill,
+        },
+        ..Default::default()
+    };
+    let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let outcome = run_command_streaming(&spec(false), chunks).await.unwrap();
+    assert!(outcome.success && outcome.truncated);
+    assert_eq!(outcome.stdout, "x".repeat(1000));
+    assert_eq!(outcome.stderr, "oops\n");
+    assert_eq!(outcome.total_bytes, 100_005);
+    assert!(outcome.spilled.is_empty());
+    // Only the kept output is streamed
+    let mut streamed = 0;
+    while let Some(chunk) = received.recv().await {
+        streamed += chunk.data.len();
+    }
+    assert_eq!(streamed, 1005);
+
+    let outcome = run_command(&spec(true)).await.unwrap();
+    assert_eq!(outcome.stdout.len(), 1000);
+    assert_eq!(outcome.spilled.len(), 1);
+    let spilled = &outcome.spilled[0];
+    assert_eq!(spilled.stream, OutputStream::Stdout);
+    assert_eq!(spilled.size_bytes, 100_000);
+    let full = std::fs::read_to_string(&spilled.path).unwrap();
+    std::fs::remove_file(&spilled.path).unwrap();
+    assert_eq!(full, "x".repeat(100_000));
+
+    let command = crate::memory::Command::new("s".into(), "sh".into(), Vec::new(), "/".into());
+    let artifact = spill_artifact(&command, spilled);
+    assert_eq!(artifact.command_id.as_deref(), Some(command.id.as_str()));
+    assert_eq!(artifact.size_bytes, 100_000);
+    assert_eq!(artifact.metadata["path"], spilled.path.to_str().unwrap());
+
+    // Output under the cap is left alone
+    let outcome = run_command(&CommandSpec {
+        command: "echo".into(),
+        args: args(&["hi"]),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert!(!outcome.truncated);
+    assert_eq!((outcome.stdout.as_str(), outcome.total_bytes), ("hi\n", 3));
+}
+
+#[tokio::test]
+async fn binary_output_is_returned_as_base64() {
+    use super::command::*;
+    use super::output::*;
+
+    let spec = CommandSpec {
+        command: "sh".into(),
+        args: args(&["-c", r"printf '\211PNG\r\n\032\n\000\001'; echo text >&2"]),
+        ..Default::default()
+    };
+    let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let outcome = run_command_streaming(&spec, chunks).await.unwrap();
+    assert_eq!(outcome.stdout, "iVBORw0KGgoAAQ==");
+    assert_eq!(outcome.stdout_encoding, TextEncoding::Base64);
+    assert_eq!(outcome.content_type, "image/png");
+    assert_eq!(
+        (outcome.stderr.as_str(), outcome.stderr_encoding),
+        ("text\n", TextEncoding::Utf8)
+    );
+    let json = serde_json::to_value(&outcome).unwrap();
+    assert_eq!(json["stdout_encoding"], "base64");
+    assert!(json.get("stderr_encoding").is_none());
+    while let Some(chunk) = received.recv().await {
+        let expected = match chunk.stream {
+            OutputStream::Stdout => TextEncoding::Base64,
+            OutputStream::Stderr => TextEncoding::Utf8,
+        };
+        assert_eq!(chunk.encoding, expected);
+    }
+
+    // A character cut off by a read, or by truncation, is still text
+    assert_eq!(complete_len("café".as_bytes()), 5);
+    assert_eq!(complete_len(&"café".as_bytes()[..4]), 3);
+    let cut = encode(&"café".as_bytes()[..4]);
+    assert_eq!(cut.encoding, TextEncoding::Utf8);
+    // Text in another encoding is not UTF-8 either
+    let latin1 = encode(b"caf\xe9 au lait");
+    assert_eq!(latin1.encoding, TextEncoding::Base64);
+    assert_eq!(latin1.content_type, "text/plain");
+    // Text that starts like a file signature is still text
+    for text in [&b"MZ\n"[..], b"%PDF-1.7\n", b"\x7fELF is a format\n"] {
+        let out = encode(text);
+        assert_eq!(out.encoding, TextEncoding::Utf8);
+        assert_eq!(out.text.as_bytes(), text);
+        assert_eq!(out.content_type, "text/plain");
+    }
+}
+
+#[tokio::test]
+async fn ansi_sequences_are_stripped_or_parsed_into_spans() {
+    use super::command::*;
+    use crate::memory::ansi::{AnsiMode, Color, Style};
+
+    let spec = |ansi| CommandSpec {
+        command: "sh".into(),
+        args: args(&[
+            "-c",
+            r"printf '\033[1;31merror\033[0m: \033]0;title\007bad\033[2K\n'",
+        ]),
+        ansi,
+        ..Default::default()
+    };
+    let kept = run_command(&spec(AnsiMode::Keep)).await.unwrap();
+    assert!(kept.stdout.starts_with("\x1b[1;31m"));
+    assert!(kept.stdout_spans.is_empty());
+
+    let stripped = run_command(&spec(AnsiMode::Strip)).await.unwrap();
+    assert_eq!(stripped.stdout, "error: bad\n");
+    assert!(stripped.stdout_spans.is_empty());
+
+    let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let parsed = run_command_streaming(&spec(AnsiMode::Spans), chunks)
+        .await
+        .unwrap();
+    assert_eq!(parsed.stdout, "error: bad\n");
+    let red = Style {
+        fg: Some(Color::Indexed(1)),
+        bold: true,
+        ..Default::default()
+    };
+    let styles: Vec<_> = parsed
+        .stdout_spans
+        .iter()
+        .map(|s| (s.text.as_str(), s.style))
+        .collect();
+    assert_eq!(styles, vec![("error", red), (": bad\n", Style::default())]);
+    let mut streamed = String::new();
+    while let Some(chunk) = received.recv().await {
+        assert!(!chunk.spans.is_empty());
+        streamed.push_str(&chunk.data);
+    }
+    assert_eq!(streamed, "error: bad\n");
+}
+
+#[tokio::test]
+async fn stdin_is_written_to_the_command() {
+    use super::command::*;
+
+    let request = ExecutionRequest {
+        command: "jq".into(),
+        stdin: Some("{\"a\": 1}".into()),
+        ..Default::default()
+    };
+    let resolved =
+        resolve_execution(&request, &Default::default(), &CommandPolicy::default()).unwrap();
+    assert_eq!(resolved.spec.stdin.as_deref(), Some("{\"a\": 1}"));
+
+    // More input than a pipe buffers, echoed back while it is still written
+    let input = "line\n".repeat(100_000);
+    let spec = CommandSpec {
+        command: "cat".into(),
+        stdin: Some(input.clone()),
+        ..Default::default()
+    };
+    let outcome = run_command(&spec).await.unwrap();
+    assert!(outcome.success);
+    assert_eq!(outcome.stdout, input);
+
+    // A command that stops reading early still succeeds
+    let spec = CommandSpec {
+        command: "head".into(),
+        args: args(&["-n", "1"]),
+        stdin: Some(input),
+        ..Default::default()
+    };
+    let outcome = run_command(&spec).await.unwrap();
+    assert!(outcome.success);
+    assert_eq!(outcome.stdout, "line\n");
+
+    // Without stdin the command reads an empty input
+    let spec = CommandSpec {
+        command: "cat".into(),
+        ..Default::default()
+    };
+    assert_eq!(run_command(&spec).await.unwrap().stdout, "");
+}
+
+#[test]
+fn pwsh_mode_tees_pipeline_objects_as_json() {
+    use super::command::ExecutionMode;
+    use super::pwsh::*;
+
+    let request: ExecutionRequest = serde_json::from_value(serde_json::json!({
+        "command": "Get-Process",
+        "args": ["-Name", "pwsh"],
+        "mode": "pwsh"
+    }))
+    .unwrap();
+    let resolved =
+        resolve_execution(&request, &Default::default(), &CommandPolicy::default()).unwrap();
+    assert_eq!(resolved.spec.mode, ExecutionMode::Pwsh);
+
+    let script = wrap_script(&resolved.spec.command_line(), false);
+    assert!(script.contains("& { Get-Process -Name pwsh } | ForEach-Object"));
+    assert!(script.contains("ConvertTo-Json -InputObject $_ -Compress"));
+    assert!(script.contains("| Out-Default"));
+    let piped = wrap_script("ConvertFrom-Json", true);
+    assert!(piped.contains("$input | & { $input | ConvertFrom-Json }"));
+
+    let objects = parse_objects("{\"Name\":\"pwsh\",\"Id\":42}\n\nnot json\n\"text\"\n");
+    assert_eq!(
+        objects,
+        vec![
+            serde_json::json!({ "Name": "pwsh", "Id": 42 }),
+            serde_json::json!("text")
+        ]
+    );
+}
+
+#[test]
+fn windows_backends_quote_arguments_literally() {
+    use super::backend::*;
+
+    assert_eq!(quote_windows_arg("plain"), "plain");
+    assert_eq!(quote_windows_arg(""), "\"\"");
+    assert_eq!(quote_windows_arg("two words"), "\"two words\"");
+    assert_eq!(quote_windows_arg(r#"say "hi""#), r#""say \"hi\"""#);
+    assert_eq!(
+        quote_windows_arg(r"C:\dir with space\"),
+        r#""C:\dir with space\\""#
+    );
+    assert_eq!(quote_windows_arg(r"C:\no\spaces\"), r"C:\no\spaces\");
+
+    let spec = CommandSpec {
+        command: "./scripts/build.cmd".to_string(),
+        args: args(&["a & b", "%PATH%", "x|y"]),
+        backend: Backend::Cmd,
+        ..CommandSpec::default()
+    };
+    assert_eq!(
+        cmd_line(&spec),
+        r#".\scripts\build.cmd ^"a ^& b^" ^%PATH^% x^|y"#
+    );
+
+    let spec = CommandSpec {
+        command: r"C:\Program Files\Tool\tool.exe".to_string(),
+        args: args(&["it's", "$env:HOME"]),
+        stdin: Some("input".to_string()),
+        backend: Backend::Powershell,
+        ..CommandSpec::default()
+    };
+    let script = powershell_script(&spec);
+    assert!(
+        script.starts_with("$input | & 'C:\\Program Files\\Tool\\tool.exe' 'it''s' '$env:HOME'\n")
+    );
+    assert!(script.contains("exit $LASTEXITCODE"));
+
+    // Windows backends normalize line endings; Auto depends on the platform
+    assert!(spec.normalizes_crlf());
+    assert_eq!(
+        Backend::Auto.resolve(),
+        if cfg!(windows) {
+            Backend::Cmd
+        } else {
+            Backend::Posix
+        }
+    );
+    assert!(!CommandSpec::default().normalizes_crlf() || cfg!(windows));
+    let mut output = super::output::encode(b"one\r\ntwo\r\n");
+    super::output::normalize_crlf(&mut output);
+    assert_eq!(output.text, "one\ntwo\n");
+}
+
+#[test]
+fn wsl_targets_translate_paths_and_wrap_commands() {
+    use super::target::*;
+
+    assert_eq!(to_linux_path(r"C:\src\app"), "/mnt/c/src/app");
+    assert_eq!(to_linux_path("D:/"), "/mnt/d");
+    assert_eq!(to_linux_path(r"\\wsl$\Ubuntu\home\me"), "/home/me");
+    assert_eq!(to_linux_path(r"\\WSL.localhost\Debian"), "/");
+    assert_eq!(to_linux_path("/home/me"), "/home/me");
+    assert_eq!(to_windows_path("/mnt/c/src/app", "Ubuntu"), r"C:\src\app");
+    assert_eq!(
+        to_windows_path("/home/me/", "Ubuntu"),
+        r"\\wsl.localhost\Ubuntu\home\me"
+    );
+    assert_eq!(to_windows_path(r"C:\src", "Ubuntu"), r"C:\src");
+
+    let target = ExecutionTarget::Wsl {
+        distro: "Ubuntu-22.04".to_string(),
+    };
+    let spec = CommandSpec {
+        command: "make".to_string(),
+        args: args(&["-j4", "all"]),
+        cwd: Some(r"C:\src\app".to_string()),
+        target: target.clone(),
+        ..CommandSpec::default()
+    };
+    assert_eq!(
+        wsl_args(&spec, "Ubuntu-22.04"),
+        args(&[
+            "-d",
+            "Ubuntu-22.04",
+            "--cd",
+            "/mnt/c/src/app",
+            "--exec",
+            "make",
+            "-j4",
+            "all"
+        ])
+    );
+    assert_eq!(spec.host_cwd().as_deref(), Some(r"C:\src\app"));
+    assert!(!spec.normalizes_crlf());
+
+    let request: ExecutionRequest = serde_json::from_value(serde_json::json!({
+        "command": "ls",
+        "target": { "kind": "wsl", "distro": "Ubuntu-22.04" }
+    }))
+    .unwrap();
+    let resolved =
+        resolve_execution(&request, &Default::default(), &CommandPolicy::default()).unwrap();
+    assert_eq!(resolved.target, "wsl:Ubuntu-22.04");
+    assert_eq!(resolved.spec.target, target);
+    assert_eq!(ExecutionTarget::default().to_string(), "local");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn sandbox_previews_changes_without_touching_the_tree() {
+    use super::sandbox::*;
+    use super::service::ExecutionService;
+
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = ExecutionService::new(gate, Default::default());
+    let tree = dir.path().join("tree");
+    std::fs::create_dir_all(tree.join("old")).unwrap();
+    std::fs::write(tree.join("a.txt"), "one\ntwo\n").unwrap();
+    std::fs::write(tree.join("old/b.txt"), "bye\n").unwrap();
+
+    // Auto takes the overlay where the kernel allows it; both report the same
+    for strategy in [ShadowStrategy::Copy, ShadowStrategy::Auto] {
+        let spec = SandboxSpec {
+            request: ExecutionRequest {
+                command: "sh".to_string(),
+                args: args(&[
+                    "-c",
+                    "echo three >> a.txt && rm -r old && printf '\\0' > new.bin",
+                ]),
+                cwd: Some(tree.display().to_string()),
+                ..Default::default()
+            },
+            strategy,
+            max_copy_bytes: 1024,
+        };
+        let report = run_sandboxed(&execution, &spec, None, "user")
+            .await
+            .unwrap();
+        assert!(report.outcome.success, "{}", report.outcome.stderr);
+        assert_eq!(
+            report
+                .changes
+                .iter()
+                .map(|c| (c.path.as_str(), c.kind, c.binary))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a.txt", ChangeKind::Modified, false),
+                ("new.bin", ChangeKind::Added, true),
+                ("old/b.txt", ChangeKind::Deleted, false),
+            ]
+        );
+        assert!(report.changes[0]
+            .diff
+            .as_ref()
+            .unwrap()
+            .contains(" two\n+three\n"));
+        assert_eq!(report.changes[1].diff, None);
+        assert_eq!(
+            std::fs::read_to_string(tree.join("a.txt")).unwrap(),
+            "one\ntwo\n"
+        );
+        assert!(tree.join("old/b.txt").exists());
+        assert!(!tree.join("new.bin").exists());
+    }
+
+    let large = SandboxSpec {
+        request: ExecutionRequest {
+            command: "true".to_string(),
+            cwd: Some(tree.display().to_string()),
+            ..Default::default()
+        },
+        strategy: ShadowStrategy::Copy,
+        max_copy_bytes: 4,
+    };
+    let refused = run_sandboxed(&execution, &large, None, "user")
+        .await
+        .unwrap_err();
+    assert_eq!(refused.code, "sandbox-failed");
+}
+
+#[tokio::test]
+async fn permission_failures_offer_elevation() {
+    use super::command::CommandOutcome;
+    use super::elevation::*;
+
+    let outcome: CommandOutcome = serde_json::from_value(serde_json::json!({
+        "stdout": "",
+        "stderr": "mkdir: cannot create directory '/opt/tool': Permission denied\n",
+        "exit_code": 1,
+        "success": false,
+        "duration_ms": 3,
+    }))
+    .unwrap();
+    assert!(needs_elevation(&outcome));
+    assert_eq!(
+        super::service::failure_message(&outcome).code,
+        "command-needs-elevation"
+    );
+    let other = CommandOutcome {
+        stderr: "no such file".to_string(),
+        ..outcome.clone()
+    };
+    assert!(!needs_elevation(&other));
+    assert_eq!(
+        super::service::failure_message(&other).code,
+        "command-failed"
+    );
+
+    let spec = CommandSpec {
+        command: "apt-get".into(),
+        args: args(&["install", "-y", "jq"]),
+        env: [("DEBIAN_FRONTEND".to_string(), "noninteractive".to_string())].into(),
+        stdin: Some("yes\n".into()),
+        ..Default::default()
+    };
+    let elevated = sudo_spec(&spec, true);
+    assert_eq!(elevated.command, "sudo");
+    assert_eq!(
+        elevated.args,
+        args(&[
+            "-S",
+            "-k",
+            "-p",
+            "",
+            "--preserve-env=DEBIAN_FRONTEND",
+            "--",
+            "apt-get",
+            "install",
+            "-y",
+            "jq",
+        ])
+    );
+    assert_eq!(elevated.stdin.as_deref(), Some("yes\n"));
+    assert_eq!(
+        sudo_spec(&spec, false).args[..3],
+        args(&["-n", "-k", "--preserve-env=DEBIAN_FRONTEND"])[..]
+    );
+
+    // The password only travels through the prompt, and never prints
+    let prompts = ElevationPrompts::default();
+    let (id, answer) = prompts.open(spec.command_line());
+    assert_eq!(
+        prompts.command_line(&id).as_deref(),
+        Some("apt-get install -y jq")
+    );
+    assert!(prompts.answer(&id, Some(Password::new("hunter2".to_string()))));
+    let password = answer.await.unwrap().unwrap();
+    assert_eq!(format!("{:?}", password), "Password(..)");
+    assert!(!prompts.answer(&id, None));
+    assert_eq!(prompts.command_line(&id), None);
+}
+
+#[tokio::test]
+async fn queue_limits_concurrent_runs_and_orders_waiters() {
+    use super::queue::*;
+
+    let config = ConcurrencyConfig {
+        max_concurrent: Some(1),
+        order: QueueOrder::Priority,
+    };
+    let queue = Arc::new(ExecutionQueue::new(&config));
+    let first = queue
+        .acquire(QueuedExecution::new("first".into(), "user", 0))
+        .await;
+
+    let (done, mut finished) = tokio::sync::mpsc::unbounded_channel();
+    let mut waiters = Vec::new();
+    for (name, priority) in [("low", 0), ("high", 5), ("cancelled", 9), ("later", 0)] {
+        let (waiting, done) = (Arc::clone(&queue), done.clone());
+        waiters.push(tokio::spawn(async move {
+            let _permit = waiting
+                .acquire(QueuedExecution::new(name.into(), "user", priority))
+                .await;
+            done.send(name).unwrap();
+        }));
+        // Arrive in order
+        while queue.snapshot().waiting.len() < waiters.len() {
+            tokio::task::yield_now().await;
+        }
+    }
+    let snapshot = queue.snapshot();
+    assert_eq!(snapshot.running.len(), 1);
+    assert_eq!(
+        snapshot
+            .waiting
+            .iter()
+            .map(|e| e.command_line.as_str())
+            .collect::<Vec<_>>(),
+        vec!["cancelled", "high", "low", "later"]
+    );
+
+    // A waiter that goes away leaves the line
+    waiters.remove(2).abort();
+    while queue.snapshot().waiting.len() == 4 {
+        tokio::task::yield_now().await;
+    }
+    drop(first);
+    let mut order = Vec::new();
+    for _ in 0..3 {
+        order.push(finished.recv().await.unwrap());
+    }
+    assert_eq!(order, vec!["high", "low", "later"]);
+    let snapshot = queue.snapshot();
+    assert!(snapshot.running.is_empty() && snapshot.waiting.is_empty());
+}
+
+#[tokio::test]
+async fn assertions_gate_downstream_nodes() {
+    use super::canvas::NodeRunStatus;
+    use super::lint::lint_canvas;
+    use super::simulate::*;
+    use crate::memory::{ScrubConfig, Scrubber};
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("built.txt"), "").unwrap();
+    let spec: SimulationSpec = serde_json::from_value(serde_json::json!({
+        "canvas": {
+            "id": "checks",
+            "nodes": [
+                { "id": "health", "type": "terminal", "command": "curl", "cwd": "{{dir}}",
+                  "expect": [
+                    { "kind": "stdout_json", "path": "$.checks[*].ok", "equals": true },
+                    { "kind": "stdout_matches", "pattern": "\"status\":\\s*\"up\"" },
+                    { "kind": "max_duration", "ms": 50 },
+                    { "kind": "file_exists", "path": "built.txt" }
+                  ] },
+                { "id": "negative", "type": "terminal", "command": "false",
+                  "expect": [{ "kind": "exit_code", "code": 1 }] },
+                { "id": "slow", "type": "terminal", "command": "make",
+                  "expect": [
+                    { "kind": "max_duration", "ms": 50 },
+                    { "kind": "file_exists", "path": "{{dir}}/missing.txt" }
+                  ] },
+                { "id": "deploy", "type": "terminal", "command": "deploy" }
+            ],
+            "connections": [{ "from": "slow", "to": "deploy" }]
+        },
+        "variables": { "dir": dir.path().display().to_string() },
+        "mocks": {
+            "health": { "stdout": "{\"status\": \"up\", \"checks\": [{\"ok\": true}, {\"ok\": true}]}" },
+            "negative": { "exit_code": 1 },
+            "slow": { "duration_ms": 900 },
+            "deploy": {}
+        }
+    }))
+    .unwrap();
+    let report = simulate_canvas(&spec).await.unwrap();
+    let statuses: Vec<_> = report.nodes.iter().map(|n| n.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            NodeRunStatus::Succeeded,
+            NodeRunStatus::Succeeded,
+            NodeRunStatus::Failed,
+            NodeRunStatus::Skipped
+        ]
+    );
+    assert!(report.nodes[0].assertions.iter().all(|a| a.passed));
+    let failures: Vec<_> = report.nodes[2]
+        .assertions
+        .iter()
+        .filter_map(|a| a.failure.as_ref().map(|f| f.code.as_str()))
+        .collect();
+    assert_eq!(failures, ["assertion-too-slow", "assertion-file-missing"]);
+    assert!(!report.success);
+
+    // A JSONPath that does not parse is caught by lint
+    let canvas = serde_json::json!({
+        "id": "bad",
+        "nodes": [{ "id": "n", "type": "terminal", "command": "true",
+                    "expect": [{ "kind": "stdout_json", "path": "$[" }] }]
+    });
+    let scrubber = Scrubber::new(&ScrubConfig::default()).unwrap();
+    let report = lint_canvas(&canvas, &scrubber, &Default::default()).unwrap();
+    assert_eq!(report.findings[0].message.code, "lint-invalid-assertion");
+}
+
+#[tokio::test]
+async fn check_reports_list_each_node() {
+    use super::simulate::*;
+    use super::test_report::{junit, tap};
+
+    let spec: SimulationSpec = serde_json::from_value(serde_json::json!({
+        "canvas": {
+            "id": "ci",
+            "nodes": [
+                { "id": "build", "type": "terminal", "label": "build <release>", "command": "make" },
+                { "id": "smoke", "type": "terminal", "label": "smoke #1", "command": "curl",
+                  "expect": [{ "kind": "exit_code", "code": 0 }] },
+                { "id": "deploy", "type": "terminal", "command": "deploy" }
+            ],
+            "connections": [{ "from": "smoke", "to": "deploy" }]
+        },
+        "mocks": {
+            "build": { "duration_ms": 1500 },
+            "smoke": { "exit_code": 7 },
+            "deploy": {}
+        }
+    }))
+    .unwrap();
+    let report = simulate_canvas(&spec).await.unwrap();
+
+    let xml = junit(&report, "CI & checks");
+    assert!(xml.contains(
+        "<testsuite name=\"CI &amp; checks\" tests=\"3\" failures=\"1\" errors=\"0\" skipped=\"1\""
+    ));
+    assert!(
+        xml.contains("<testcase classname=\"ci\" name=\"build &lt;release&gt;\" time=\"1.500\"/>")
+    );
+    assert!(xml.contains("<failure message=\"Expected exit status 0, got 7\">"));
+    assert!(xml.contains("name=\"deploy\" time=\"0.000\">\n      <skipped "));
+
+    let tap = tap(&report);
+    let lines: Vec<&str> = tap.lines().collect();
+    assert_eq!(
+        &lines[..4],
+        [
+            "TAP version 13",
+            "1..3",
+            "ok 1 - build <release>",
+            "not ok 2 - smoke \\#1"
+        ]
+    );
+    assert!(lines.contains(&"  message: \"Expected exit status 0, got 7\""));
+    assert!(lines.contains(&"  exit_code: 7"));
+    assert_eq!(
+        lines.last(),
+        Some(&"ok 3 - deploy # SKIP an upstream node did not succeed")
+    );
+}
+
+#[test]
+fn dry_runs_check_without_running() {
+    use super::service::ExecutionService;
+
+    let dir = tempfile::tempdir().unwrap();
+    let audit_path = dir.path().join("audit.jsonl");
+    let gate = Arc::new(CommandGate::new(
+        SafetyConfig::default(),
+        Arc::new(AuditLog::new(audit_path.clone())),
+    ));
+    let execution = ExecutionService::new(gate, Default::default());
+    let request = |command: &str, args: &[&str], cwd: &str| ExecutionRequest {
+        command: command.to_string(),
+        args: args.iter().map(|a| a.to_string()).collect(),
+        cwd: Some(cwd.to_string()),
+        variables: [("name".to_string(), "out.txt".to_string())].into(),
+        ..Default::default()
+    };
+    let cwd = dir.path().display().to_string();
+
+    let dry = execution
+        .dry_run(&request("sh", &["-c", "touch {{name}}"], &cwd))
+        .unwrap();
+    assert!(dry.would_run(), "{:?}", dry.problems);
+    assert_eq!(dry.process, ["sh", "-c", "touch out.txt"]);
+    assert!(dry.program_path.unwrap().ends_with("sh"));
+    assert!(!dir.path().join("out.txt").exists());
+
+    // A destructive command is only previewed: no token, nothing audited
+    let dry = execution
+        .dry_run(&request("rm", &["-rf", "*"], &cwd))
+        .unwrap();
+    assert!(matches!(dry.resolved.policy, PolicyPreview::Confirm { .. }));
+    assert!(dry.would_run());
+    assert!(!audit_path.exists());
+
+    let missing = dir.path().join("missing").display().to_string();
+    let dry = execution
+        .dry_run(&request("no-such-program-xyz", &["{{other}}"], &missing))
+        .unwrap();
+    let codes: Vec<_> = dry.problems.iter().map(|p| p.code.as_str()).collect();
+    assert_eq!(
+        codes,
+        [
+            "template-unresolved",
+            "dry-run-cwd-missing",
+            "dry-run-program-not-found"
+        ]
+    );
+    assert!(dry.program_path.is_none());
+}
+
+#[test]
+fn project_file_sets_defaults_and_narrows_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join(super::project::PROJECT_FILE),
+        "path = [\"bin\"]\nallowed_commands = [\"git\", \"make\"]\ntimeout_secs = 30\n\n\
+         [env]\nSTAGE = \"dev\"\nREGION = \"eu\"\n",
+    )
+    .unwrap();
+    let nested = dir.path().join("src/app");
+    std::fs::create_dir_all(&nested).unwrap();
+    let request = |command: &str| ExecutionRequest {
+        command: command.into(),
+        cwd: Some(nested.display().to_string()),
+        env: [("REGION".to_string(), "us".to_string())].into(),
+        ..Default::default()
+    };
+
+    let resolved = resolve_execution(
+        &request("make"),
+        &Default::default(),
+        &CommandPolicy::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        resolved.project.as_deref(),
+        Some(&*dir.path().join(".runebook.toml").display().to_string())
+    );
+    assert_eq!(resolved.spec.env["STAGE"], "dev");
+    // The request's env wins over the project's
+    assert_eq!(resolved.spec.env["REGION"], "us");
+    let path = std::env::split_paths(&resolved.spec.env["PATH"]).next();
+    assert_eq!(path, Some(dir.path().join("bin")));
+    assert_eq!(resolved.spec.limits.wall_secs, Some(30));
+    assert_eq!(resolved.policy, PolicyPreview::Run);
+
+    let refused = resolve_execution(
+        &request("curl"),
+        &Default::default(),
+        &CommandPolicy::default(),
+    )
+    .unwrap();
+    assert!(matches!(
+        refused.policy,
+        PolicyPreview::Block {
+            source: PolicySource::Project,
+            ..
+        }
+    ));
+
+    // A broken file refuses commands instead of dropping its allowlist
+    std::fs::write(
+        dir.path().join(".runebook.toml"),
+        "allowed_commands = \"git\"\n",
+    )
+    .unwrap();
+    assert!(resolve_execution(
+        &request("git"),
+        &Default::default(),
+        &CommandPolicy::default()
+    )
+    .is_err());
+    let decision = CommandPolicy::default().decide(&CommandSpec {
+        command: "git".into(),
+        cwd: Some(nested.display().to_string()),
+        ..Default::default()
+    });
+    assert_eq!(decision.action, RuleAction::Deny);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pipelines_wire_stages_without_a_shell() {
+    use super::command::{StopConditions, Termination};
+    use super::pipeline::*;
+    use super::service::ExecutionService;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = ExecutionService::new(gate, Default::default());
+    let stage = |command: &str, list: &[&str]| PipelineStage {
+        command: command.into(),
+        args: args(list),
+        ..Default::default()
+    };
+    let pipeline = |stages: Vec<PipelineStage>| PipelineSpec {
+        request: ExecutionRequest {
+            cwd: Some(dir.path().display().to_string()),
+            stdin: Some("pear\napple\nfig\n".into()),
+            ..Default::default()
+        },
+        stages,
+        pipefail: false,
+    };
+
+    let spec = pipeline(vec![
+        stage("cat", &[]),
+        stage("sort", &[]),
+        stage("head", &["-n", "2"]),
+    ]);
+    let outcome = execution
+        .execute_pipeline(&spec, "test", StopConditions::default())
+        .await
+        .unwrap();
+    assert!(outcome.outcome.success);
+    assert_eq!(outcome.outcome.stdout, "apple\nfig\n");
+    assert_eq!(outcome.stages.len(), 3);
+    // `head` stops reading early; the endless stage before it is not a failure
+    let spec = pipeline(vec![stage("yes", &[]), stage("head", &["-n", "1"])]);
+    let outcome = execution
+        .execute_pipeline(&spec, "test", StopConditions::default())
+        .await
+        .unwrap();
+    assert_eq!(outcome.outcome.stdout, "y\n");
+    assert!(outcome.outcome.success);
+
+    // Only the last stage counts, unless pipefail is set
+    let failing = stage("sh", &["-c", "echo oops >&2; exit 3"]);
+    let mut spec = pipeline(vec![failing, stage("cat", &[])]);
+    let outcome = execution
+        .execute_pipeline(&spec, "test", StopConditions::default())
+        .await
+        .unwrap();
+    assert!(outcome.outcome.success);
+    assert_eq!(outcome.stages[0].exit_code, Some(3));
+    spec.pipefail = true;
+    let outcome = execution
+        .execute_pipeline(&spec, "test", StopConditions::default())
+        .await
+        .unwrap();
+    assert!(!outcome.outcome.success);
+    assert_eq!(outcome.outcome.exit_code, Some(3));
+    assert_eq!(outcome.outcome.stderr, "oops\n");
+
+    // The pipeline ends when its stages exit, not when their output closes
+    let stop = |timeout| StopConditions {
+        timeout: Some(timeout),
+        cancel: None,
+    };
+    let closing = stage("sh", &["-c", "exec >&- 2>&-; sleep 30"]);
+    let spec = pipeline(vec![stage("cat", &[]), closing]);
+    let started = std::time::Instant::now();
+    let outcome = execution
+        .execute_pipeline(&spec, "test", stop(Duration::from_millis(200)))
+        .await
+        .unwrap();
+    assert_eq!(outcome.outcome.termination, Termination::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let leaving = stage("sh", &["-c", "cat; sleep 5 &"]);
+    let spec = pipeline(vec![stage("cat", &[]), leaving]);
+    let started = std::time::Instant::now();
+    let outcome = execution
+        .execute_pipeline(&spec, "test", stop(Duration::from_secs(3)))
+        .await
+        .unwrap();
+    assert_eq!(outcome.outcome.termination, Termination::Exited);
+    assert_eq!(outcome.outcome.stdout, "pear\napple\nfig\n");
+    assert!(started.elapsed() < Duration::from_secs(3));
+
+    // A stage that needs confirmation holds back the whole pipeline
+    let spec = pipeline(vec![stage("ls", &[]), stage("rm", &["-rf", "*"])]);
+    let refused = execution
+        .execute_pipeline(&spec, "test", StopConditions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(refused.code, "confirmation-required");
+    assert_eq!(refused.params["stage"], "1");
+    let empty = execution
+        .execute_pipeline(&pipeline(Vec::new()), "test", StopConditions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(empty.code, "pipeline-empty");
+}
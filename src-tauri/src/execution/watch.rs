@@ -0,0 +1,455 @@
+//! Watch mode: re-run a command whenever watched files change.
+//!
+//! A [`WatchSpec`] pairs an [`ExecutionRequest`] with the paths to watch, like
+//! a built-in `cargo watch`. Saves are debounced, a run still going when the
+//! next one is due is cancelled, and every run goes through the
+//! [`ExecutionService`] so the usual resolution and safety gate apply. Callers
+//! get a short [`WatchEvent`] stream (started, passed, failed, cancelled)
+//! instead of the full output.
+//!
+//! Files are found by polling modification times, which works the same on
+//! every platform and for network mounts.
+//!
+//! When the machine wakes from sleep, a watch either restarts the run the
+//! sleep interrupted, whose connections are likely gone, or stops, per
+//! [`WatchManager::with_resume_on_wake`].
+
+use super::resolve::{ExecutionRequest, PolicyPreview};
+use super::service::ExecutionService;
+use crate::i18n::UserMessage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use ts_rs::TS;
+
+/// Quiet period after the last change before a run starts
+pub const DEFAULT_DEBOUNCE_MS: u64 = 300;
+/// How often watched paths are scanned
+pub const DEFAULT_POLL_MS: u64 = 500;
+/// Directories never worth watching
+const SKIPPED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+/// Scanning stops after this many files
+const MAX_FILES: usize = 50_000;
+/// Length of a failure summary
+const SUMMARY_CHARS: usize = 200;
+
+fn default_debounce_ms() -> u64 {
+    DEFAULT_DEBOUNCE_MS
+}
+
+fn default_poll_ms() -> u64 {
+    DEFAULT_POLL_MS
+}
+
+/// A command to re-run and the files that trigger it
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WatchSpec {
+    pub request: ExecutionRequest,
+    /// Files or directories, relative to the command's cwd; the cwd itself when empty
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// File or directory names to skip, in addition to `.git`, `target` and `node_modules`
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    #[serde(default = "default_poll_ms")]
+    pub poll_ms: u64,
+    /// Canvas node the watch belongs to, echoed in its events
+    #[serde(default)]
+    pub node_id: Option<String>,
+}
+
+impl WatchSpec {
+    /// Watched roots, resolved against the cwd the command runs in
+    fn roots(&self, cwd: Option<&str>) -> Vec<PathBuf> {
+        let cwd = PathBuf::from(cwd.filter(|c| !c.is_empty()).unwrap_or("."));
+        if self.paths.is_empty() {
+            return vec![cwd];
+        }
+        self.paths.iter().map(|p| cwd.join(p)).collect()
+    }
+}
+
+/// A running watch, as listed by [`WatchManager::list`]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WatchInfo {
+    pub id: String,
+    pub node_id: Option<String>,
+    pub command_line: String,
+    pub paths: Vec<String>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// What happened in a watch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum WatchEventKind {
+    /// A run started; `changed` is empty for the initial run
+    Started {
+        changed: Vec<String>,
+    },
+    Passed {
+        duration_ms: u64,
+    },
+    Failed {
+        exit_code: Option<i32>,
+        duration_ms: u64,
+        /// Last line of stderr, or of stdout when stderr is empty
+        summary: String,
+    },
+    /// The run was still going when files changed again, or the machine slept
+    Cancelled,
+    /// The machine woke from sleep
+    Suspended {
+        slept_ms: u64,
+    },
+    /// The execution service refused the run; the watch stops
+    Refused {
+        error: UserMessage,
+    },
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchEvent {
+    pub watch_id: String,
+    pub node_id: Option<String>,
+    /// Run number, from 1; 0 for events outside a run
+    pub run: u32,
+    #[serde(flatten)]
+    pub kind: WatchEventKind,
+}
+
+/// Modification times of the files under `roots`
+pub fn scan(roots: &[PathBuf], ignore: &[String]) -> HashMap<PathBuf, SystemTime> {
+    let mut files = HashMap::new();
+    for root in roots {
+        let walker = walkdir::WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|entry| {
+                let name = entry.file_name().to_string_lossy();
+                entry.depth() == 0
+                    || !(SKIPPED_DIRS.contains(&name.as_ref()) || ignore.iter().any(|i| *i == name))
+            });
+        for entry in walker.filter_map(Result::ok) {
+            if files.len() >= MAX_FILES {
+                return files;
+            }
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if let Some(modified) = entry.metadata().ok().and_then(|m| m.modified().ok()) {
+                files.insert(entry.into_path(), modified);
+            }
+        }
+    }
+    files
+}
+
+/// Files added, modified or removed between two scans, sorted
+pub fn changed_files(
+    before: &HashMap<PathBuf, SystemTime>,
+    after: &HashMap<PathBuf, SystemTime>,
+) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = after
+        .iter()
+        .filter(|(path, modified)| before.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .chain(before.keys().filter(|p| !after.contains_key(*p)).cloned())
+        .collect();
+    changed.sort();
+    changed
+}
+
+fn summary(stdout: &str, stderr: &str) -> String {
+    let text = if stderr.trim().is_empty() {
+        stdout
+    } else {
+        stderr
+    };
+    text.lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("")
+        .trim()
+        .chars()
+        .take(SUMMARY_CHARS)
+        .collect()
+}
+
+type Watches = Arc<Mutex<HashMap<String, (WatchInfo, oneshot::Sender<()>)>>>;
+
+/// Running watches, each a background task
+pub struct WatchManager {
+    watches: Watches,
+    resume_on_wake: bool,
+}
+
+impl Default for WatchManager {
+    fn default() -> Self {
+        Self {
+            watches: Watches::default(),
+            resume_on_wake: true,
+        }
+    }
+}
+
+impl WatchManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether watches go on after the machine sleeps (the default) or stop
+    pub fn with_resume_on_wake(mut self, resume: bool) -> Self {
+        self.resume_on_wake = resume;
+        self
+    }
+
+    /// Start watching. The first run starts right away; events go to
+    /// `events` until the watch is stopped or a run is refused.
+    /// Destructive commands are refused up front, since nobody is there to
+    /// confirm each run.
+    pub fn start(
+        &self,
+        execution: Arc<ExecutionService>,
+        spec: WatchSpec,
+        events: mpsc::UnboundedSender<WatchEvent>,
+    ) -> Result<WatchInfo, UserMessage> {
+        let resolved = execution.preview(&spec.request)?;
+        if !resolved.unresolved_variables.is_empty() {
+            return Err(UserMessage::new("template-unresolved")
+                .with("names", resolved.unresolved_variables.join(", ")));
+        }
+        if let PolicyPreview::Confirm { reason, .. } | PolicyPreview::Block { reason, .. } =
+            &resolved.policy
+        {
+            return Err(UserMessage::new("watch-destructive").with("reason", reason));
+        }
+        let roots = spec.roots(resolved.spec.host_cwd().as_deref());
+        if let Some(missing) = roots.iter().find(|r| !r.exists()) {
+            return Err(UserMessage::new("watch-invalid")
+                .with("error", format!("{} does not exist", missing.display())));
+        }
+
+        let info = WatchInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            node_id: spec.node_id.clone(),
+            command_line: resolved.command_line,
+            paths: roots.iter().map(|r| r.display().to_string()).collect(),
+            started_at: Utc::now(),
+        };
+        let (stop, stopped) = oneshot::channel();
+        self.watches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(info.id.clone(), (info.clone(), stop));
+
+        let watches = Arc::clone(&self.watches);
+        let id = info.id.clone();
+        let resume_on_wake = self.resume_on_wake;
+        tokio::spawn(async move {
+            let run = WatchRun {
+                id: id.clone(),
+                node_id: spec.node_id.clone(),
+                events,
+                resume_on_wake,
+            };
+            run.watch(execution, &spec, roots, stopped).await;
+            watches
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&id);
+        });
+        Ok(info)
+    }
+
+    /// Stop a watch, cancelling its current run. Returns whether it was running.
+    pub fn stop(&self, id: &str) -> bool {
+        match self
+            .watches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+        {
+            Some((_, stop)) => {
+                let _ = stop.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<WatchInfo> {
+        let mut watches: Vec<WatchInfo> = self
+            .watches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|(info, _)| info.clone())
+            .collect();
+        watches.sort_by_key(|w| w.started_at);
+        watches
+    }
+}
+
+/// Event sending for one watch
+struct WatchRun {
+    id: String,
+    node_id: Option<String>,
+    events: mpsc::UnboundedSender<WatchEvent>,
+    resume_on_wake: bool,
+}
+
+type RunTask = JoinHandle<Result<super::CommandOutcome, UserMessage>>;
+
+impl WatchRun {
+    fn emit(&self, run: u32, kind: WatchEventKind) {
+        let _ = self.events.send(WatchEvent {
+            watch_id: self.id.clone(),
+            node_id: self.node_id.clone(),
+            run,
+            kind,
+        });
+    }
+
+    fn spawn(&self, execution: &Arc<ExecutionService>, request: &ExecutionRequest) -> RunTask {
+        let execution = Arc::clone(execution);
+        let request = request.clone();
+        // Aborting the task drops the child process, which kills it
+        tokio::spawn(async move { execution.execute(&request, None, "watch").await })
+    }
+
+    async fn watch(
+        &self,
+        execution: Arc<ExecutionService>,
+        spec: &WatchSpec,
+        roots: Vec<PathBuf>,
+        mut stopped: oneshot::Receiver<()>,
+    ) {
+        let rescan = |roots: Vec<PathBuf>, ignore: Vec<String>| {
+            tokio::task::spawn_blocking(move || scan(&roots, &ignore))
+        };
+        let mut files = rescan(roots.clone(), spec.ignore.clone())
+            .await
+            .unwrap_or_default();
+        let debounce = Duration::from_millis(spec.debounce_ms);
+        let mut tick = tokio::time::interval(Duration::from_millis(spec.poll_ms.max(50)));
+        let mut pending: Vec<PathBuf> = Vec::new();
+        let mut last_change = Instant::now();
+        let mut wakes = crate::power::monitor().subscribe();
+
+        let mut run = 1;
+        self.emit(
+            run,
+            WatchEventKind::Started {
+                changed: Vec::new(),
+            },
+        );
+        let mut running: Option<RunTask> = Some(self.spawn(&execution, &spec.request));
+
+        loop {
+            tokio::select! {
+                _ = &mut stopped => {
+                    if let Some(task) = running.take() {
+                        task.abort();
+                        self.emit(run, WatchEventKind::Cancelled);
+                    }
+                    self.emit(run, WatchEventKind::Stopped);
+                    return;
+                }
+                finished = async { running.as_mut().unwrap().await }, if running.is_some() => {
+                    running = None;
+                    let kind = match finished {
+                        Ok(Ok(outcome)) if outcome.success => WatchEventKind::Passed {
+                            duration_ms: outcome.duration_ms,
+                        },
+                        Ok(Ok(outcome)) => WatchEventKind::Failed {
+                            exit_code: outcome.exit_code,
+                            duration_ms: outcome.duration_ms,
+                            summary: summary(&outcome.stdout, &outcome.stderr),
+                        },
+                        Ok(Err(error)) => {
+                            self.emit(run, WatchEventKind::Refused { error });
+                            self.emit(run, WatchEventKind::Stopped);
+                            return;
+                        }
+                        Err(e) => WatchEventKind::Failed {
+                            exit_code: None,
+                            duration_ms: 0,
+                            summary: e.to_string(),
+                        },
+                    };
+                    self.emit(run, kind);
+                }
+                woke = wakes.recv() => {
+                    let Ok(sleep) = woke else {
+                        continue;
+                    };
+                    self.emit(run, WatchEventKind::Suspended {
+                        slept_ms: sleep.duration_ms(),
+                    });
+                    let interrupted = running.take();
+                    if let Some(task) = &interrupted {
+                        task.abort();
+                        self.emit(run, WatchEventKind::Cancelled);
+                    }
+                    if !self.resume_on_wake {
+                        self.emit(run, WatchEventKind::Stopped);
+                        return;
+                    }
+                    if interrupted.is_some() {
+                        run += 1;
+                        self.emit(run, WatchEventKind::Started {
+                            changed: Vec::new(),
+                        });
+                        running = Some(self.spawn(&execution, &spec.request));
+                    }
+                }
+                _ = tick.tick() => {
+                    let Ok(now) = rescan(roots.clone(), spec.ignore.clone()).await else {
+                        continue;
+                    };
+                    let changed = changed_files(&files, &now);
+                    files = now;
+                    if !changed.is_empty() {
+                        pending.extend(changed);
+                        last_change = Instant::now();
+                    }
+                    if pending.is_empty() || last_change.elapsed() < debounce {
+                        continue;
+                    }
+                    if let Some(task) = running.take() {
+                        task.abort();
+                        self.emit(run, WatchEventKind::Cancelled);
+                    }
+                    pending.sort();
+                    pending.dedup();
+                    run += 1;
+                    self.emit(run, WatchEventKind::Started {
+                        changed: pending.drain(..).map(|p| display_path(&p, &roots)).collect(),
+                    });
+                    running = Some(self.spawn(&execution, &spec.request));
+                }
+            }
+        }
+    }
+}
+
+/// `path` relative to the watched root containing it
+fn display_path(path: &Path, roots: &[PathBuf]) -> String {
+    roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
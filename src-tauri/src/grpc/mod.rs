@@ -0,0 +1,440 @@
+//! gRPC service (feature `grpc`).
+//!
+//! A tonic implementation of `proto/runebook/v1/runebook.proto`, mirroring the
+//! HTTP API for clients that prefer generated stubs or need streamed output.
+//! It runs over the same [`ApiState`] as the HTTP API, so policy, auditing,
+//! quotas, the bearer tokens, and workspace roles are shared.
+//!
+//! Refusals are status errors whose message is the JSON-serialized
+//! [`UserMessage`], with a status code derived from the message code.
+
+pub mod proto {
+    tonic::include_proto!("runebook.v1");
+}
+
+use crate::execution::{self, run_canvas, CanvasDocument, ExecutionRequest};
+use crate::i18n::UserMessage;
+use crate::memory::{self, MemoryStore};
+use crate::server::rbac::{Action, Grant};
+use crate::server::{ApiState, GrpcConfig};
+use anyhow::{Context, Result};
+use proto::runebook_server::{Runebook, RunebookServer};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Code, Request, Response, Status};
+
+/// Status for a refusal or failure
+pub fn status(message: UserMessage) -> Status {
+    let code = match message.code {
+        "unauthorized" => Code::Unauthenticated,
+        "forbidden" => Code::PermissionDenied,
+        "confirmation-required" => Code::FailedPrecondition,
+        "command-blocked" => Code::PermissionDenied,
+        "template-unresolved" | "command-resolve-failed" | "canvas-invalid" | "invalid-request" => {
+            Code::InvalidArgument
+        }
+        "canvas-not-found" => Code::NotFound,
+        "memory-store-unavailable" => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    let body = serde_json::to_string(&message).unwrap_or_else(|_| message.to_string());
+    Status::new(code, body)
+}
+
+fn json<T: Serialize>(value: &T) -> Result<Response<proto::JsonDocument>, Status> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| status(UserMessage::wrap("invalid-request")(e)))?;
+    Ok(Response::new(proto::JsonDocument { json }))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, Status> {
+    serde_json::from_str(json).map_err(|e| status(UserMessage::wrap("invalid-request")(e)))
+}
+
+impl From<proto::ExecutionRequest> for ExecutionRequest {
+    fn from(request: proto::ExecutionRequest) -> Self {
+        let mode = match request.mode() {
+            proto::ExecutionMode::Pwsh => execution::ExecutionMode::Pwsh,
+            _ => execution::ExecutionMode::Direct,
+        };
+        Self {
+            command: request.command,
+            args: request.args,
+            env: request.env,
+            cwd: request.cwd,
+            profile: request.profile,
+            variables: request.variables,
+            stdin: request.stdin,
+            mode,
+            limits: Default::default(),
+            ansi: Default::default(),
+            backend: Default::default(),
+            target: Default::default(),
+            priority: 0,
+        }
+    }
+}
+
+impl From<execution::CommandOutcome> for proto::CommandOutcome {
+    fn from(outcome: execution::CommandOutcome) -> Self {
+        Self {
+            stdout_base64: !outcome.stdout_encoding.is_utf8(),
+            stderr_base64: !outcome.stderr_encoding.is_utf8(),
+            content_type: outcome.content_type,
+            stdout: outcome.stdout,
+            stderr: outcome.stderr,
+            exit_code: outcome.exit_code,
+            success: outcome.success,
+            duration_ms: outcome.duration_ms,
+            objects: outcome
+                .objects
+                .unwrap_or_default()
+                .iter()
+                .map(|object| object.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl From<execution::OutputChunk> for proto::ExecuteEvent {
+    fn from(chunk: execution::OutputChunk) -> Self {
+        let stream = match chunk.stream {
+            execution::OutputStream::Stdout => proto::OutputStream::Stdout,
+            execution::OutputStream::Stderr => proto::OutputStream::Stderr,
+        };
+        Self {
+            event: Some(proto::execute_event::Event::Output(proto::OutputChunk {
+                stream: stream.into(),
+                data: chunk.data,
+                base64: !chunk.encoding.is_utf8(),
+            })),
+        }
+    }
+}
+
+impl From<memory::CanvasSummary> for proto::CanvasSummary {
+    fn from(summary: memory::CanvasSummary) -> Self {
+        Self {
+            id: summary.id,
+            name: summary.name,
+            timestamp: summary.timestamp,
+        }
+    }
+}
+
+pub struct GrpcService {
+    state: Arc<ApiState>,
+}
+
+impl GrpcService {
+    pub fn new(state: Arc<ApiState>) -> Self {
+        Self { state }
+    }
+
+    async fn store(&self) -> Result<MemoryStore, Status> {
+        (self.state.open_store)()
+            .await
+            .map_err(|e| status(UserMessage::wrap("memory-store-unavailable")(e)))
+    }
+
+    /// The caller's grant, attached by [`check_token`]
+    fn grant<T>(request: &Request<T>) -> Result<Grant, Status> {
+        request
+            .extensions()
+            .get::<Grant>()
+            .cloned()
+            .ok_or_else(|| status(UserMessage::new("unauthorized")))
+    }
+
+    /// Memory RPCs return data from every workspace, so they need a role that
+    /// applies everywhere
+    fn require_global<T>(request: &Request<T>, action: Action) -> Result<(), Status> {
+        Self::grant(request)?.require(action, None).map_err(status)
+    }
+
+    fn request(request: Option<proto::ExecutionRequest>) -> Result<ExecutionRequest, Status> {
+        request.map(Into::into).ok_or_else(|| {
+            status(UserMessage::new("invalid-request").with("error", "missing request"))
+        })
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::ExecuteEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Runebook for GrpcService {
+    async fn execute(
+        &self,
+        request: Request<proto::ExecuteRequest>,
+    ) -> Result<Response<proto::CommandOutcome>, Status> {
+        let grant = Self::grant(&request)?;
+        let request = request.into_inner();
+        let execution_request = Self::request(request.request)?;
+        self.state
+            .authorize_run(&grant, &execution_request)
+            .map_err(status)?;
+        let outcome = self
+            .state
+            .execution
+            .execute(
+                &execution_request,
+                request.confirmation_token.as_deref(),
+                "grpc",
+            )
+            .await
+            .map_err(status)?;
+        Ok(Response::new(outcome.into()))
+    }
+
+    type StreamExecuteStream = EventStream;
+
+    async fn stream_execute(
+        &self,
+        request: Request<proto::ExecuteRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let grant = Self::grant(&request)?;
+        let request = request.into_inner();
+        let execution_request = Self::request(request.request)?;
+        self.state
+            .authorize_run(&grant, &execution_request)
+            .map_err(status)?;
+        let token = request.confirmation_token;
+        let state = Arc::clone(&self.state);
+        let (events, receiver) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let (chunks, mut chunk_rx) = mpsc::unbounded_channel();
+            let run = state.execution.execute_streaming(
+                &execution_request,
+                token.as_deref(),
+                "grpc",
+                chunks,
+            );
+            tokio::pin!(run);
+            let result = loop {
+                tokio::select! {
+                    Some(chunk) = chunk_rx.recv() => {
+                        let _ = events.send(Ok(chunk.into())).await;
+                    }
+                    result = &mut run => break result,
+                }
+            };
+            while let Ok(chunk) = chunk_rx.try_recv() {
+                let _ = events.send(Ok(chunk.into())).await;
+            }
+            let last = result.map_err(status).map(|outcome| proto::ExecuteEvent {
+                event: Some(proto::execute_event::Event::Finished(outcome.into())),
+            });
+            let _ = events.send(last).await;
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn preview_execution(
+        &self,
+        request: Request<proto::ExecutionRequest>,
+    ) -> Result<Response<proto::JsonDocument>, Status> {
+        let grant = Self::grant(&request)?;
+        let request = request.into_inner().into();
+        self.state.authorize_run(&grant, &request).map_err(status)?;
+        json(&self.state.execution.preview(&request).map_err(status)?)
+    }
+
+    async fn list_sessions(
+        &self,
+        request: Request<proto::ListSessionsRequest>,
+    ) -> Result<Response<proto::JsonDocument>, Status> {
+        Self::require_global(&request, Action::QueryMemory)?;
+        let sessions = self
+            .store()
+            .await?
+            .list_sessions_tagged(&request.into_inner().tags)
+            .await
+            .map_err(|e| status(UserMessage::wrap("memory-list-sessions-failed")(e)))?;
+        json(&sessions)
+    }
+
+    async fn recent_errors(
+        &self,
+        request: Request<proto::RecentErrorsRequest>,
+    ) -> Result<Response<proto::JsonDocument>, Status> {
+        Self::require_global(&request, Action::QueryMemory)?;
+        let request = request.into_inner();
+        let errors = self
+            .store()
+            .await?
+            .query_recent_errors_tagged(
+                request.limit.map(|l| l as usize),
+                None,
+                request.severity.as_deref(),
+                &request.tags,
+            )
+            .await
+            .map_err(|e| status(UserMessage::wrap("memory-query-errors-failed")(e)))?;
+        json(&errors)
+    }
+
+    async fn get_suggestions(
+        &self,
+        request: Request<proto::GetSuggestionsRequest>,
+    ) -> Result<Response<proto::JsonDocument>, Status> {
+        Self::require_global(&request, Action::QueryMemory)?;
+        let request = request.into_inner();
+        let suggestions = self
+            .store()
+            .await?
+            .get_suggestions(
+                request.priority.as_deref(),
+                request.limit.map(|l| l as usize),
+            )
+            .await
+            .map_err(|e| status(UserMessage::wrap("memory-suggestions-failed")(e)))?;
+        json(&suggestions)
+    }
+
+    async fn run_canvas(
+        &self,
+        request: Request<proto::RunCanvasRequest>,
+    ) -> Result<Response<proto::JsonDocument>, Status> {
+        let grant = Self::grant(&request)?;
+        let request = request.into_inner();
+        let canvas: CanvasDocument = match request.canvas {
+            Some(proto::run_canvas_request::Canvas::CanvasJson(json)) => parse(&json)?,
+            Some(proto::run_canvas_request::Canvas::CanvasId(id)) => {
+                let document = self
+                    .store()
+                    .await?
+                    .canvas_load(&id)
+                    .await
+                    .map_err(|e| status(UserMessage::wrap("canvas-store-failed")(e)))?
+                    .ok_or_else(|| status(UserMessage::new("canvas-not-found").with("id", &id)))?;
+                serde_json::from_value(document)
+                    .map_err(|e| status(UserMessage::wrap("canvas-invalid")(e)))?
+            }
+            None => {
+                return Err(status(
+                    UserMessage::new("invalid-request").with("error", "missing canvas"),
+                ))
+            }
+        };
+        for node_request in canvas.terminal_requests(&request.variables) {
+            self.state
+                .authorize_run(&grant, &node_request)
+                .map_err(status)?;
+        }
+        let report = run_canvas(&self.state.execution, &canvas, &request.variables, "grpc")
+            .await
+            .map_err(status)?;
+        json(&report)
+    }
+
+    async fn save_canvas(
+        &self,
+        request: Request<proto::JsonDocument>,
+    ) -> Result<Response<proto::CanvasSummary>, Status> {
+        Self::require_global(&request, Action::Run)?;
+        let canvas: serde_json::Value = parse(&request.into_inner().json)?;
+        let summary = self
+            .store()
+            .await?
+            .canvas_save(&canvas)
+            .await
+            .map_err(|e| status(UserMessage::wrap("canvas-invalid")(e)))?;
+        Ok(Response::new(summary.into()))
+    }
+
+    async fn get_canvas(
+        &self,
+        request: Request<proto::CanvasId>,
+    ) -> Result<Response<proto::JsonDocument>, Status> {
+        Self::require_global(&request, Action::QueryMemory)?;
+        let id = request.into_inner().id;
+        let canvas = self
+            .store()
+            .await?
+            .canvas_load(&id)
+            .await
+            .map_err(|e| status(UserMessage::wrap("canvas-store-failed")(e)))?
+            .ok_or_else(|| status(UserMessage::new("canvas-not-found").with("id", &id)))?;
+        json(&canvas)
+    }
+
+    async fn list_canvases(
+        &self,
+        request: Request<proto::ListCanvasesRequest>,
+    ) -> Result<Response<proto::ListCanvasesResponse>, Status> {
+        Self::require_global(&request, Action::QueryMemory)?;
+        let canvases = self
+            .store()
+            .await?
+            .canvas_list()
+            .await
+            .map_err(|e| status(UserMessage::wrap("canvas-store-failed")(e)))?;
+        Ok(Response::new(proto::ListCanvasesResponse {
+            canvases: canvases.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn delete_canvas(
+        &self,
+        request: Request<proto::CanvasId>,
+    ) -> Result<Response<proto::DeleteCanvasResponse>, Status> {
+        Self::require_global(&request, Action::Administer)?;
+        self.store()
+            .await?
+            .canvas_delete(&request.into_inner().id)
+            .await
+            .map_err(|e| status(UserMessage::wrap("canvas-store-failed")(e)))?;
+        Ok(Response::new(proto::DeleteCanvasResponse {}))
+    }
+}
+
+/// Reject calls without `authorization: Bearer <token>` metadata, and attach
+/// the token's [`Grant`] for the handlers
+fn check_token(state: &ApiState, mut request: Request<()>) -> Result<Request<()>, Status> {
+    let grant = request
+        .metadata()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .and_then(|token| state.authenticate(token))
+        .ok_or_else(|| status(UserMessage::new("unauthorized")))?;
+    request.extensions_mut().insert(grant);
+    Ok(request)
+}
+
+/// Serve the gRPC service until the task is dropped
+pub async fn serve(config: &GrpcConfig, state: ApiState) -> Result<()> {
+    let addr: SocketAddr = config
+        .bind
+        .parse()
+        .with_context(|| format!("Invalid gRPC bind address {}", config.bind))?;
+    if !addr.ip().is_loopback() {
+        log::warn!(
+            "[grpc] Listening on non-loopback address {}; anyone who can reach it needs only the API token",
+            addr
+        );
+    }
+    let state = Arc::new(state);
+    // Binding happens inside `serve`; a failure there marks the service down
+    state.health.component("grpc").ok();
+    let auth = Arc::clone(&state);
+    let service = RunebookServer::with_interceptor(GrpcService::new(state), move |request| {
+        check_token(&auth, request)
+    });
+    log::info!("[grpc] gRPC service listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve(addr)
+        .await
+        .context("gRPC server failed")
+}
+
+#[cfg(test)]
+mod tests;
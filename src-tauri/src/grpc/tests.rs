@@ -0,0 +1,84 @@
+use super::*;
+use crate::audit::AuditLog;
+use crate::execution::{CommandGate, ExecutionService, SafetyConfig};
+use tokio_stream::StreamExt;
+
+fn service(dir: &std::path::Path) -> GrpcService {
+    let audit = Arc::new(AuditLog::new(dir.join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = Arc::new(ExecutionService::new(gate, Default::default()));
+    let open_store =
+        crate::memory::store_opener(|| async { Err(anyhow::anyhow!("store offline")) });
+    GrpcService::new(Arc::new(ApiState::new(
+        execution,
+        open_store,
+        "secret".to_string(),
+    )))
+}
+
+/// A request as `check_token` passes it on for the `[server]` token
+fn owner<T>(message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.extensions_mut().insert(Grant::owner());
+    request
+}
+
+fn execute_request(command: &str, args: &[&str]) -> proto::ExecuteRequest {
+    proto::ExecuteRequest {
+        request: Some(proto::ExecutionRequest {
+            command: command.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        }),
+        confirmation_token: None,
+    }
+}
+
+#[tokio::test]
+async fn grpc_streams_output_and_maps_refusals() {
+    let dir = tempfile::tempdir().unwrap();
+    let service = service(dir.path());
+
+    let mut with_token = Request::new(());
+    with_token
+        .metadata_mut()
+        .insert("authorization", "Bearer secret".parse().unwrap());
+    assert!(check_token(&service.state, with_token).is_ok());
+    let denied = check_token(&service.state, Request::new(())).unwrap_err();
+    assert_eq!(denied.code(), Code::Unauthenticated);
+
+    let refused = service
+        .execute(owner(execute_request("rm", &["-rf", "*"])))
+        .await
+        .unwrap_err();
+    assert_eq!(refused.code(), Code::FailedPrecondition);
+    let body: serde_json::Value = serde_json::from_str(refused.message()).unwrap();
+    assert_eq!(body["code"], "confirmation-required");
+
+    #[cfg(unix)]
+    {
+        let mut events = service
+            .stream_execute(owner(execute_request("echo", &["streamed"])))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut output = String::new();
+        let mut finished = None;
+        while let Some(event) = events.next().await {
+            match event.unwrap().event.unwrap() {
+                proto::execute_event::Event::Output(chunk) => output.push_str(&chunk.data),
+                proto::execute_event::Event::Finished(outcome) => finished = Some(outcome),
+            }
+        }
+        assert_eq!(output, "streamed\n");
+        let finished = finished.expect("final event carries the outcome");
+        assert!(finished.success);
+        assert_eq!(finished.stdout, "streamed\n");
+    }
+
+    let missing = service
+        .list_canvases(owner(proto::ListCanvasesRequest {}))
+        .await
+        .unwrap_err();
+    assert_eq!(missing.code(), Code::Unavailable);
+}
@@ -0,0 +1,421 @@
+//! System health.
+//!
+//! Background services (backups, retention, the artifact lifecycle, the SQLite
+//! mirror, telemetry export, the capture socket and the API servers) each hold a
+//! [`Component`] handle and report through it as they start, succeed and fail.
+//! The [`HealthRegistry`] they share adds on-demand probes of the storage
+//! backend and turns everything into one [`SystemHealth`] snapshot, which backs
+//! the `system_health` command, the status bar indicator and `runebook doctor`.
+//!
+//! Analyzers run in the frontend, which reports their health with
+//! `health_report_analyzers`; running jobs (watches, debug sessions, commands
+//! being captured) are counted when the snapshot is taken. The services
+//! themselves run under the [`supervisor`], which restarts them when they die.
+
+pub mod supervisor;
+
+#[cfg(test)]
+mod tests;
+
+pub use supervisor::{supervise, RestartPolicy};
+
+use crate::memory::StoreOpener;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use ts_rs::TS;
+
+/// Counters that hold work waiting to be written or sent
+pub const QUEUE_COUNTERS: &[&str] = &["queued_writes", "pending_spans"];
+
+/// Status of one component, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum HealthStatus {
+    Ok,
+    /// Switched off in the configuration, or not running by choice
+    Disabled,
+    /// Running, but has not finished a round yet
+    Starting,
+    /// Running; the last round failed
+    Degraded,
+    /// Not running, or unreachable
+    Down,
+}
+
+impl HealthStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthStatus::Ok => "ok",
+            HealthStatus::Disabled => "disabled",
+            HealthStatus::Starting => "starting",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Down => "down",
+        }
+    }
+
+    /// How much this status weighs on the overall one; disabled and starting
+    /// components are not a problem
+    fn severity(self) -> u8 {
+        match self {
+            HealthStatus::Ok | HealthStatus::Disabled | HealthStatus::Starting => 0,
+            HealthStatus::Degraded => 1,
+            HealthStatus::Down => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LastError {
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    /// One line on what the component is doing or why it is off
+    #[serde(default)]
+    pub detail: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// Seconds since `started_at`; 0 while disabled or down
+    #[serde(default)]
+    pub uptime_secs: u64,
+    #[serde(default)]
+    pub last_ok_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<LastError>,
+    /// Failures since the component started
+    #[serde(default)]
+    pub error_count: u64,
+    /// Times the watchdog restarted the component after it died
+    #[serde(default)]
+    pub restarts: u64,
+    #[serde(default)]
+    pub last_restart_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub counters: BTreeMap<String, u64>,
+}
+
+impl ComponentHealth {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: HealthStatus::Starting,
+            detail: None,
+            started_at: Utc::now(),
+            uptime_secs: 0,
+            last_ok_at: None,
+            last_error: None,
+            error_count: 0,
+            restarts: 0,
+            last_restart_at: None,
+            counters: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SystemHealth {
+    /// The worst component status
+    pub status: HealthStatus,
+    pub checked_at: DateTime<Utc>,
+    /// Writes and spans waiting for their destination
+    pub queue_depth: u64,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl SystemHealth {
+    /// Plain-text report for `runebook doctor`
+    pub fn render(&self) -> String {
+        let mut out = format!("RuneBook health: {}\n", self.status.as_str());
+        for component in &self.components {
+            out.push_str(&format!(
+                "  {:<20} {:<9}",
+                component.name,
+                component.status.as_str()
+            ));
+            if component.uptime_secs > 0 {
+                out.push_str(&format!(" up {}", format_duration(component.uptime_secs)));
+            }
+            for (name, value) in &component.counters {
+                out.push_str(&format!(" {}={}", name, value));
+            }
+            out.push('\n');
+            if let Some(at) = component.last_restart_at {
+                out.push_str(&format!(
+                    "    restarted {} times, last at {}\n",
+                    component.restarts,
+                    at.to_rfc3339()
+                ));
+            }
+            if let Some(detail) = &component.detail {
+                out.push_str(&format!("    {}\n", detail));
+            }
+            if let Some(error) = &component.last_error {
+                out.push_str(&format!(
+                    "    last error ({} total) at {}: {}\n",
+                    component.error_count,
+                    error.at.to_rfc3339(),
+                    error.message
+                ));
+            }
+        }
+        out
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// Health of one frontend analyzer, as the analysis queue records it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase", default)]
+#[ts(export)]
+pub struct AnalyzerHealth {
+    pub runs: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub consecutive_failures: u64,
+    pub disabled: bool,
+    pub last_error: Option<String>,
+}
+
+/// Health of every component, shared by the services that report into it
+#[derive(Clone, Default)]
+pub struct HealthRegistry {
+    components: Arc<Mutex<BTreeMap<String, ComponentHealth>>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle for reporting on `name`; the component is listed as starting
+    /// until it reports otherwise
+    pub fn component(&self, name: &str) -> Component {
+        self.components
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(name.to_string())
+            .or_insert_with(|| ComponentHealth::new(name));
+        Component {
+            name: name.to_string(),
+            registry: self.clone(),
+        }
+    }
+
+    /// Open the store and check that its backend answers. Logged batches that
+    /// were not applied yet are counted as `queued_writes`.
+    pub async fn probe_storage(&self, open_store: &StoreOpener) {
+        let storage = self.component("storage");
+        let store = match open_store().await {
+            Ok(store) => store,
+            Err(e) => return storage.down(format!("{:#}", e)),
+        };
+        match store.health_check().await {
+            Ok(true) => storage.ok(),
+            Ok(false) => return storage.down("The backend does not respond"),
+            Err(e) => return storage.down(format!("{:#}", e)),
+        }
+        match store.pending_writes().await {
+            Ok(pending) => storage.counter("queued_writes", pending as u64),
+            Err(e) => storage.error(format!("Counting queued writes failed: {:#}", e)),
+        }
+    }
+
+    /// Record the analyzer health the frontend reported. Any analyzer disabled
+    /// after repeated failures degrades the component.
+    pub fn report_analyzers(&self, analyzers: &BTreeMap<String, AnalyzerHealth>) {
+        let total = |f: fn(&AnalyzerHealth) -> u64| analyzers.values().map(f).sum::<u64>();
+        let disabled: Vec<String> = analyzers
+            .iter()
+            .filter(|(_, h)| h.disabled)
+            .map(|(name, h)| match &h.last_error {
+                Some(error) => format!("{}: {}", name, error),
+                None => name.clone(),
+            })
+            .collect();
+        self.update("analyzers", |c| {
+            c.counters.insert("runs".to_string(), total(|h| h.runs));
+            c.counters
+                .insert("timeouts".to_string(), total(|h| h.timeouts));
+            c.counters
+                .insert("disabled".to_string(), disabled.len() as u64);
+            // Failures are counted by the frontend; reporting the same ones
+            // again must not count them twice
+            c.error_count = total(|h| h.failures);
+            c.detail = Some(format!("{} analyzers", analyzers.len()));
+            if disabled.is_empty() {
+                c.status = HealthStatus::Ok;
+                c.last_ok_at = Some(Utc::now());
+                return;
+            }
+            c.status = HealthStatus::Degraded;
+            let message = format!("Disabled after repeated failures: {}", disabled.join("; "));
+            if c.last_error.as_ref().map(|e| &e.message) != Some(&message) {
+                c.last_error = Some(LastError {
+                    message,
+                    at: Utc::now(),
+                });
+            }
+        });
+    }
+
+    pub fn snapshot(&self) -> SystemHealth {
+        let now = Utc::now();
+        let components: Vec<ComponentHealth> = self
+            .components
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .map(|mut component| {
+                component.uptime_secs = match component.status {
+                    HealthStatus::Disabled | HealthStatus::Down => 0,
+                    _ => (now - component.started_at).num_seconds().max(0) as u64,
+                };
+                component
+            })
+            .collect();
+        let status = components
+            .iter()
+            .map(|c| c.status)
+            .max_by_key(|status| status.severity())
+            .filter(|status| status.severity() > 0)
+            .unwrap_or(HealthStatus::Ok);
+        let queue_depth = components
+            .iter()
+            .flat_map(|c| &c.counters)
+            .filter(|(name, _)| QUEUE_COUNTERS.contains(&name.as_str()))
+            .map(|(_, value)| value)
+            .sum();
+        SystemHealth {
+            status,
+            checked_at: now,
+            queue_depth,
+            components,
+        }
+    }
+
+    fn update(&self, name: &str, change: impl FnOnce(&mut ComponentHealth)) {
+        let mut components = self.components.lock().unwrap_or_else(|e| e.into_inner());
+        change(
+            components
+                .entry(name.to_string())
+                .or_insert_with(|| ComponentHealth::new(name)),
+        );
+    }
+}
+
+/// Reporting handle of one component
+#[derive(Clone)]
+pub struct Component {
+    name: String,
+    registry: HealthRegistry,
+}
+
+impl Component {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        self.registry
+            .components
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&self.name)
+            .map_or(HealthStatus::Starting, |c| c.status)
+    }
+
+    /// The last round succeeded
+    pub fn ok(&self) {
+        self.registry.update(&self.name, |c| {
+            c.status = HealthStatus::Ok;
+            c.last_ok_at = Some(Utc::now());
+        });
+    }
+
+    /// The last round failed; the component keeps running
+    pub fn error(&self, error: impl Display) {
+        self.fail(HealthStatus::Degraded, error);
+    }
+
+    /// The component stopped or cannot reach what it depends on
+    pub fn down(&self, error: impl Display) {
+        self.fail(HealthStatus::Down, error);
+    }
+
+    fn fail(&self, status: HealthStatus, error: impl Display) {
+        let message = error.to_string();
+        self.registry.update(&self.name, |c| {
+            c.status = status;
+            c.error_count += 1;
+            c.last_error = Some(LastError {
+                message,
+                at: Utc::now(),
+            });
+        });
+    }
+
+    /// Switched off; `reason` says how to switch it on
+    pub fn disable(&self, reason: impl Into<String>) {
+        let reason = reason.into();
+        self.registry.update(&self.name, |c| {
+            c.status = HealthStatus::Disabled;
+            c.detail = Some(reason);
+        });
+    }
+
+    /// The component died; `reason` is kept unless it reported its own error
+    /// on the way down
+    fn stopped(&self, reason: String) {
+        self.registry.update(&self.name, |c| {
+            if c.status != HealthStatus::Down {
+                c.status = HealthStatus::Down;
+                c.error_count += 1;
+                c.last_error = Some(LastError {
+                    message: reason,
+                    at: Utc::now(),
+                });
+            }
+            c.restarts += 1;
+            c.last_restart_at = Some(Utc::now());
+        });
+    }
+
+    /// Started again after [`Self::stopped`]; uptime counts from now
+    fn relaunch(&self) {
+        self.registry.update(&self.name, |c| {
+            c.status = HealthStatus::Starting;
+            c.started_at = Utc::now();
+        });
+    }
+
+    pub fn detail(&self, detail: impl Into<String>) {
+        let detail = detail.into();
+        self.registry
+            .update(&self.name, |c| c.detail = Some(detail));
+    }
+
+    pub fn counter(&self, name: &str, value: u64) {
+        self.registry.update(&self.name, |c| {
+            c.counters.insert(name.to_string(), value);
+        });
+    }
+}
@@ -0,0 +1,84 @@
+//! Watchdog for background services.
+//!
+//! Every background loop (backups, retention, the artifact lifecycle, the
+//! mirror, telemetry export, view polling, the capture socket and the API
+//! servers) runs under [`supervise`]. A loop that panics or returns is started
+//! again after a backoff that doubles with each quick failure, instead of
+//! leaving the service silently dead. Restarts are counted on the loop's
+//! [`Component`], so they show up in `system_health`, `runebook doctor` and
+//! the Grafana `service_restarts` table.
+//!
+//! A loop that disabled its component before returning has nothing to do, so
+//! it is not restarted.
+
+use super::{Component, HealthStatus};
+use std::any::Any;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Wait before the first restart
+    pub initial_backoff: Duration,
+    /// The backoff doubles up to this
+    pub max_backoff: Duration,
+    /// A run this long counts as recovered; the backoff starts over
+    pub stable_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            stable_after: Duration::from_secs(600),
+        }
+    }
+}
+
+/// Run `task` and restart it whenever it panics or returns. Returns when the
+/// task disabled its component, or when the runtime shuts down.
+pub async fn supervise<F, Fut>(component: Component, policy: RestartPolicy, task: F)
+where
+    F: Fn(Component) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = policy.initial_backoff;
+    loop {
+        let started = Instant::now();
+        let outcome = tokio::spawn(task(component.clone())).await;
+        if component.status() == HealthStatus::Disabled {
+            return;
+        }
+        let reason = match outcome {
+            Ok(()) => "Stopped unexpectedly".to_string(),
+            Err(e) if e.is_panic() => format!("Panicked: {}", panic_message(&*e.into_panic())),
+            // Cancelled: the runtime is shutting down
+            Err(_) => return,
+        };
+        if started.elapsed() >= policy.stable_after {
+            backoff = policy.initial_backoff;
+        }
+        log::warn!(
+            "[watchdog] {}: {}; restarting in {:?}",
+            component.name(),
+            reason,
+            backoff
+        );
+        component.stopped(reason);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+        component.relaunch();
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -0,0 +1,157 @@
+// Tests for component health reporting and probes
+
+use crate::health::*;
+use crate::memory::{store_opener, InMemoryBackend, MemoryStore, WriteBatch};
+
+#[test]
+fn snapshot_reports_the_worst_component() {
+    let registry = HealthRegistry::new();
+    let backup = registry.component("backup");
+    registry.component("mirror").disable("off");
+    registry.component("views");
+
+    // Disabled and starting components do not count against the system
+    let health = registry.snapshot();
+    assert_eq!(health.status, HealthStatus::Ok);
+    assert_eq!(
+        health
+            .components
+            .iter()
+            .map(|c| (c.name.as_str(), c.status))
+            .collect::<Vec<_>>(),
+        vec![
+            ("backup", HealthStatus::Starting),
+            ("mirror", HealthStatus::Disabled),
+            ("views", HealthStatus::Starting),
+        ]
+    );
+
+    backup.error("disk full");
+    backup.error("disk full");
+    let telemetry = registry.component("telemetry");
+    telemetry.counter("pending_spans", 3);
+    let health = registry.snapshot();
+    assert_eq!(health.status, HealthStatus::Degraded);
+    assert_eq!(health.queue_depth, 3);
+    let failed = &health.components[0];
+    assert_eq!(failed.error_count, 2);
+    assert_eq!(failed.last_error.as_ref().unwrap().message, "disk full");
+    assert!(health.render().contains("disk full"));
+
+    // Recovering keeps the last error for the report
+    backup.ok();
+    registry.component("http_api").down("address in use");
+    let health = registry.snapshot();
+    assert_eq!(health.status, HealthStatus::Down);
+    assert_eq!(health.components[0].status, HealthStatus::Ok);
+    assert!(health.components[0].last_ok_at.is_some());
+    assert!(health.components[0].last_error.is_some());
+
+    let analyzers = [
+        ("lint".to_string(), AnalyzerHealth::default()),
+        (
+            "hangs".to_string(),
+            AnalyzerHealth {
+                runs: 5,
+                failures: 5,
+                disabled: true,
+                last_error: Some("timed out".to_string()),
+                ..Default::default()
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+    registry.report_analyzers(&analyzers);
+    registry.report_analyzers(&analyzers);
+    let health = registry.snapshot();
+    let reported = health
+        .components
+        .iter()
+        .find(|c| c.name == "analyzers")
+        .unwrap();
+    assert_eq!(reported.status, HealthStatus::Degraded);
+    // Reporting the same failures again does not count them twice
+    assert_eq!(reported.error_count, 5);
+    assert_eq!(reported.counters["disabled"], 1);
+    assert!(reported
+        .last_error
+        .as_ref()
+        .unwrap()
+        .message
+        .contains("hangs: timed out"));
+}
+
+#[tokio::test]
+async fn storage_probe_counts_queued_writes() {
+    let registry = HealthRegistry::new();
+    let offline = store_opener(|| async { Err(anyhow::anyhow!("connection refused")) });
+    registry.probe_storage(&offline).await;
+    let storage = registry.snapshot().components.remove(0);
+    assert_eq!(storage.status, HealthStatus::Down);
+    assert!(storage
+        .last_error
+        .unwrap()
+        .message
+        .contains("connection refused"));
+
+    let online = store_opener(|| async {
+        let backend = InMemoryBackend::new();
+        // A batch a crashed writer left in the write-ahead log
+        let mut batch = WriteBatch::new();
+        batch.put("memory:command:a", serde_json::json!({}));
+        batch.put("memory:command:b", serde_json::json!({}));
+        crate::memory::MemoryBackend::put(
+            &backend,
+            &crate::memory::RecordKind::Wal.key(&batch.id),
+            &serde_json::to_value(&batch)?,
+        )
+        .await?;
+        MemoryStore::new(backend).await
+    });
+    registry.probe_storage(&online).await;
+    let health = registry.snapshot();
+    assert_eq!(health.status, HealthStatus::Ok);
+    assert_eq!(health.components[0].counters["queued_writes"], 1);
+    assert_eq!(health.queue_depth, 1);
+}
+
+#[tokio::test]
+async fn watchdog_restarts_dead_tasks_until_disabled() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let registry = HealthRegistry::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let policy = RestartPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(4),
+        stable_after: Duration::from_secs(60),
+    };
+    let counted = Arc::clone(&runs);
+    // Panics, then returns without a word, then finds itself switched off
+    supervise(registry.component("mirror"), policy, move |component| {
+        let run = counted.fetch_add(1, Ordering::SeqCst);
+        async move {
+            match run {
+                0 => panic!("mirror file is corrupt"),
+                1 => {}
+                _ => component.disable("off"),
+            }
+        }
+    })
+    .await;
+
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    let mirror = registry.snapshot().components.remove(0);
+    assert_eq!(mirror.status, HealthStatus::Disabled);
+    assert_eq!(mirror.restarts, 2);
+    assert_eq!(mirror.error_count, 2);
+    assert!(mirror.last_restart_at.is_some());
+    assert_eq!(
+        mirror.last_error.unwrap().message,
+        "Stopped unexpectedly",
+        "the latest death is reported"
+    );
+}
@@ -0,0 +1,200 @@
+## Terminal sessions
+
+terminal-open-failed = Failed to start terminal: { $error }
+terminal-not-found = Terminal { $id } not found
+terminal-state-unavailable = Terminal state is unavailable: { $error }
+terminal-write-failed = Failed to write to terminal: { $error }
+terminal-resize-failed = Failed to resize terminal: { $error }
+terminal-kill-failed = Failed to stop terminal: { $error }
+output-encoding-invalid = Failed to configure output decoding: { $error }
+
+## Command execution
+
+confirmation-required = This command needs confirmation ({ $reason }). Confirm to run it.
+command-blocked = Refused to run this command: { $reason }
+command-run-failed = Failed to run command: { $error }
+command-resolve-failed = Failed to resolve command: { $error }
+template-unresolved = No value for template variables: { $names }
+dry-run-cwd-missing = Working directory { $cwd } does not exist
+dry-run-program-not-found = { $program } was not found on PATH
+pipeline-empty = A pipeline needs at least one command
+command-failed = Command exited with status { $exit_code }: { $stderr }
+command-needs-elevation = Command was not allowed to run (status { $exit_code }): { $stderr }. It may need to run as administrator.
+command-timed-out = Command was stopped after { $duration_ms } ms
+command-cancelled = Command was cancelled
+resource-limit-exceeded = Command was stopped: { $reason }
+execution-id-in-use = A command is already running with id { $id }
+execution-not-found = No running command with id { $id }
+job-not-found = No running job with id { $id }
+signal-failed = Could not signal the job: { $error }
+canvas-invalid = Cannot run canvas: { $error }
+assertion-invalid = Assertion can never pass: { $error }
+assertion-exit-code = Expected exit status { $expected }, got { $actual }
+assertion-no-match = { $stream } does not match { $pattern }
+assertion-not-json = stdout is not JSON: { $error }
+assertion-json-missing = Nothing in stdout at { $path }
+assertion-json-mismatch = { $path } is { $actual }, expected { $expected }
+assertion-too-slow = Took { $duration_ms } ms, more than { $max_ms } ms
+assertion-file-missing = { $path } does not exist
+watch-invalid = Cannot watch: { $error }
+watch-destructive = Watch mode does not re-run commands that need confirmation ({ $reason })
+watch-not-found = No watch with id { $id }
+log-follow-failed = Failed to follow the log: { $error }
+log-follow-not-found = No followed log with id { $id }
+pty-start-failed = Failed to start the command on a terminal: { $error }
+pty-not-found = No terminal session with id { $id }
+pty-io-failed = Terminal session error: { $error }
+debug-session-not-found = No canvas debug session with id { $id }
+fixture-read-failed = Failed to read canvas fixture: { $error }
+fixture-write-failed = Failed to save canvas fixture: { $error }
+simulation-mock-missing = No mock result for node { $node }; simulations never run commands
+benchmark-invalid = Cannot benchmark: { $error }
+benchmark-save-failed = Failed to save benchmark: { $error }
+benchmark-history-failed = Failed to list benchmarks: { $error }
+sandbox-unsupported = Cannot preview this command: { $error }
+sandbox-failed = Failed to preview the command: { $error }
+elevation-unsupported = Cannot run this command as administrator: { $error }
+elevation-cancelled = Running as administrator was cancelled
+elevation-denied = Administrator rights were refused: { $error }
+elevation-failed = Failed to run the command as administrator: { $error }
+elevation-prompt-not-found = No password prompt { $id } is waiting
+elevation-record-failed = Failed to record the elevated run: { $error }
+
+## Permissions
+
+capability-denied = { $command } needs the { $capability } capability, which is turned off in [permissions]
+command-unknown = Unknown command { $command }
+
+## Argument validation
+
+invalid-argument = Invalid { $argument } ({ $rule }): { $error }
+
+## Canvas linting
+
+canvas-lint-failed = Failed to lint canvas: { $error }
+lint-unresolved-variable = { $node }: no value for template variable { $variable }
+lint-unconnected-input = { $node }: nothing is connected to input { $port }
+lint-shell-syntax = { $node }: { $token } needs a shell; run the command through sh -c or split it into arguments
+lint-broad-path = { $node }: { $path } covers the whole file system or home directory
+lint-secret-literal = { $node }: { $field } holds a secret ({ $rule }); use a template variable instead
+lint-invalid-assertion = { $node }: assertion can never pass: { $error }
+
+## Memory store
+
+memory-store-unavailable = Failed to initialize memory store: { $error }
+migration-failed = Memory migration failed: { $error }
+memory-list-sessions-failed = Failed to list sessions: { $error }
+memory-query-errors-failed = Failed to query errors: { $error }
+diagnostics-failed = Failed to build diagnostics: { $error }
+memory-suggestions-failed = Failed to get suggestions: { $error }
+memory-usage-failed = Failed to compute usage report: { $error }
+memory-reindex-failed = Failed to rebuild memory indexes: { $error }
+time-report-failed = Failed to compute time report: { $error }
+analytics-export-failed = Failed to export analytics: { $error }
+history-import-failed = Failed to import shell history: { $error }
+handoff-export-failed = Failed to export the session handoff: { $error }
+handoff-import-failed = Failed to import the session handoff: { $error }
+demo-seed-failed = Failed to add the demo data: { $error }
+artifact-store-failed = Failed to store artifact: { $error }
+memory-list-artifacts-failed = Failed to list artifacts: { $error }
+artifact-not-found = No artifact with id { $id }
+artifact-read-failed = Failed to read artifact: { $error }
+output-read-failed = Failed to read command output: { $error }
+tag-update-failed = Failed to update tags: { $error }
+tag-list-failed = Failed to list tags: { $error }
+view-save-failed = Failed to save view: { $error }
+view-delete-failed = Failed to delete view: { $error }
+view-list-failed = Failed to list views: { $error }
+view-evaluate-failed = Failed to evaluate view: { $error }
+bookmark-save-failed = Failed to save bookmark: { $error }
+bookmark-read-failed = Failed to read bookmarks: { $error }
+bookmark-delete-failed = Failed to delete bookmark: { $error }
+bookmark-not-found = No bookmark with id { $id }
+session-not-found = No session with id { $id }
+memory-delete-failed = Failed to delete memory records: { $error }
+suggestion-not-found = No suggestion with id { $id }
+suggestion-feedback-failed = Failed to record suggestion feedback: { $error }
+suggestion-explain-failed = Failed to explain the suggestion: { $error }
+preferences-read-failed = Failed to read preferences: { $error }
+preferences-save-failed = Failed to update preferences: { $error }
+rank-eval-failed = Failed to evaluate ranking: { $error }
+
+## Archives
+
+archive-format-unknown = Cannot infer archive format from { $path }
+archive-create-failed = Failed to create archive: { $error }
+archive-extract-failed = Failed to extract archive: { $error }
+
+## Backups
+
+backup-failed = Backup failed: { $error }
+backup-export-failed = Export failed: { $error }
+
+## Alerts
+
+alert-rules-invalid = Alert rules not saved: { $error }
+webhook-log-failed = Failed to read the webhook delivery log: { $error }
+
+## Onboarding
+
+onboarding-failed = Setup step failed: { $error }
+
+## Profiles
+
+profile-failed = Profile operation failed: { $error }
+profile-not-found = No profile named { $name }
+profile-switch-failed = Could not switch profiles: { $error }
+
+## Capture
+
+capture-failed = Capture failed: { $error }
+capture-unsupported = This build of RuneBook was compiled without the `capture` feature
+
+## Opening files and links
+
+open-file-failed = Failed to open { $path }: { $error }
+deep-link-invalid = Invalid RuneBook link: { $error }
+deep-link-disabled = RuneBook links of this kind ({ $action }) are disabled in the configuration
+deep-link-unknown-snippet = No snippet named { $name } is configured
+deep-link-import-failed = Failed to import the shared card: { $error }
+
+## Windows
+
+window-open-failed = Failed to open a new window: { $error }
+
+## Audit log
+
+audit-verify-failed = Failed to verify the audit log: { $error }
+
+## Execution receipts
+
+receipt-sign-failed = Failed to sign the execution receipt: { $error }
+receipt-invalid = Receipt is not valid: { $error }
+
+## Background work
+
+task-failed = Background task failed: { $error }
+
+## Memory inspection (text output)
+
+inspect-title = === RuneBook Cognitive Memory ===
+inspect-sessions = Sessions: { $count }
+inspect-errors = Recent Errors: { $count }
+inspect-suggestions = Active Suggestions: { $count }
+inspect-recent-sessions = === Recent Sessions ===
+inspect-recent-errors = === Recent Errors ===
+inspect-top-suggestions = === Top Suggestions ===
+
+## HTTP and gRPC API
+
+unauthorized = Missing or invalid API token
+forbidden = This API token may not { $action } in { $workspace }
+metric-unknown = Unknown metric { $metric }
+metrics-failed = Failed to compute metrics: { $error }
+token-not-found = No active API token with id { $id }
+share-not-found = No active share with id { $id }
+observe-failed = Failed to observe the shared session: { $error }
+token-store-failed = Failed to update API tokens: { $error }
+invalid-request = Invalid request: { $error }
+canvas-not-found = No saved canvas with id { $id }
+canvas-store-failed = Failed to access saved canvases: { $error }
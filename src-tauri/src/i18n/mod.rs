@@ -0,0 +1,150 @@
+//! User-facing messages.
+//!
+//! Errors returned to the frontend are [`UserMessage`]s: a stable message code
+//! plus named parameters, so the UI can map codes to its own copy. The same
+//! codes are rendered on the Rust side from a Fluent catalog (`locales/*.ftl`)
+//! for text output and as a fallback `message` field.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+use ts_rs::TS;
+use unic_langid::LanguageIdentifier;
+
+/// Bundled catalogs; the first entry is the fallback locale
+const LOCALES: &[(&str, &str)] = &[("en-US", include_str!("locales/en-US.ftl"))];
+
+/// A localizable message: code plus named parameters. It goes over the wire
+/// with the message rendered in the default locale as `message`.
+#[derive(Debug, Clone, PartialEq, Eq, TS)]
+#[ts(
+    export,
+    type = "{ code: string, params: Record<string, string>, message: string }"
+)]
+pub struct UserMessage {
+    pub code: &'static str,
+    pub params: BTreeMap<String, String>,
+}
+
+impl UserMessage {
+    pub fn new(code: &'static str) -> Self {
+        Self {
+            code,
+            params: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Adapter for `map_err`: wraps any error as the `error` parameter of `code`
+    pub fn wrap<E: fmt::Display>(code: &'static str) -> impl FnOnce(E) -> Self {
+        move |e| Self::new(code).with("error", e)
+    }
+}
+
+impl fmt::Display for UserMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&catalog().format(self.code, &self.params))
+    }
+}
+
+impl std::error::Error for UserMessage {}
+
+impl Serialize for UserMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("UserMessage", 3)?;
+        state.serialize_field("code", self.code)?;
+        state.serialize_field("params", &self.params)?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// Fluent catalog for one locale
+pub struct Catalog {
+    bundle: FluentBundle<FluentResource>,
+}
+
+impl Catalog {
+    /// Load the catalog for `locale` (e.g. "de-DE" or "de_DE.UTF-8"),
+    /// falling back to the default locale when it is not bundled
+    pub fn new(locale: &str) -> Self {
+        let wanted = locale.split('.').next().unwrap_or(locale).replace('_', "-");
+        let (id, source) = LOCALES
+            .iter()
+            .find(|(id, _)| id.eq_ignore_ascii_case(&wanted))
+            .or_else(|| {
+                let language = wanted.split('-').next().unwrap_or_default();
+                LOCALES.iter().find(|(id, _)| {
+                    id.split('-')
+                        .next()
+                        .is_some_and(|l| l.eq_ignore_ascii_case(language))
+                })
+            })
+            .unwrap_or(&LOCALES[0]);
+
+        let langid: LanguageIdentifier = id.parse().expect("bundled locale id is valid");
+        let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+        bundle.set_use_isolating(false);
+        let resource =
+            FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, errors)| {
+                log::warn!("[i18n] Errors in {} catalog: {:?}", id, errors);
+                resource
+            });
+        bundle
+            .add_resource(resource)
+            .expect("bundled catalog has no duplicate ids");
+
+        Self { bundle }
+    }
+
+    /// Render `code` with `params`; unknown codes render as the code itself
+    pub fn format(&self, code: &str, params: &BTreeMap<String, String>) -> String {
+        let Some(pattern) = self.bundle.get_message(code).and_then(|m| m.value()) else {
+            return code.to_string();
+        };
+
+        let mut args = FluentArgs::new();
+        for (name, value) in params {
+            args.set(name.as_str(), value.as_str());
+        }
+        let mut errors = Vec::new();
+        self.bundle
+            .format_pattern(pattern, Some(&args), &mut errors)
+            .into_owned()
+    }
+
+    /// Whether the catalog defines `code`
+    pub fn has(&self, code: &str) -> bool {
+        self.bundle.has_message(code)
+    }
+}
+
+/// Process-wide catalog for `$RUNEBOOK_LANG`, else `$LANG`
+pub fn catalog() -> &'static Catalog {
+    static CATALOG: OnceLock<Catalog> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let locale = std::env::var("RUNEBOOK_LANG")
+            .or_else(|_| std::env::var("LANG"))
+            .unwrap_or_default();
+        Catalog::new(&locale)
+    })
+}
+
+/// Render a message for text output
+pub fn tr(code: &'static str, params: &[(&str, &dyn fmt::Display)]) -> String {
+    let params = params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    catalog().format(code, &params)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,35 @@
+use super::*;
+
+#[test]
+fn formats_code_with_params() {
+    let catalog = Catalog::new("en-US");
+    let message = UserMessage::new("terminal-not-found").with("id", "abc");
+    assert_eq!(
+        catalog.format(message.code, &message.params),
+        "Terminal abc not found"
+    );
+}
+
+#[test]
+fn unknown_locale_falls_back_to_default() {
+    let catalog = Catalog::new("xx_YY.UTF-8");
+    assert!(catalog.has("memory-store-unavailable"));
+}
+
+#[test]
+fn unknown_code_renders_as_code() {
+    let catalog = Catalog::new("en-US");
+    assert_eq!(
+        catalog.format("no-such-message", &BTreeMap::new()),
+        "no-such-message"
+    );
+}
+
+#[test]
+fn serializes_code_params_and_message() {
+    let message = UserMessage::wrap("archive-create-failed")("disk full");
+    let value = serde_json::to_value(&message).unwrap();
+    assert_eq!(value["code"], "archive-create-failed");
+    assert_eq!(value["params"]["error"], "disk full");
+    assert!(value["message"].as_str().unwrap().contains("disk full"));
+}
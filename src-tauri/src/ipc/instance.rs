@@ -0,0 +1,89 @@
+//! Single-instance hand-off.
+//!
+//! Before starting the desktop app, a launch checks whether another instance
+//! owns the capture socket. If one answers, the launch forwards the files it
+//! was asked to open (e.g. `runebook canvas.yaml` from a file manager, or a
+//! `runebook://` link the OS passed on the command line) and exits instead of starting a second app on the same data directory.
+
+use super::protocol::{IpcRequest, IpcResponse};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// What a launch was asked to open
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LaunchRequest {
+    /// Absolute file paths
+    pub paths: Vec<PathBuf>,
+    /// `runebook://` links, passed through unparsed; see [`crate::deeplink`]
+    pub urls: Vec<String>,
+}
+
+impl LaunchRequest {
+    /// Sort command-line arguments into links and files. Files are made
+    /// absolute against the current directory since the running instance has
+    /// its own.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Self {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let mut request = Self::default();
+        for arg in args.into_iter().filter(|arg| !arg.starts_with('-')) {
+            if arg.starts_with("runebook:") {
+                request.urls.push(arg);
+            } else {
+                request.paths.push(cwd.join(arg));
+            }
+        }
+        request
+    }
+}
+
+/// Send one request to the instance listening on `socket` and read its
+/// answer; `None` when no instance is running
+#[cfg(unix)]
+pub fn request_running_instance(
+    socket: &Path,
+    request: &IpcRequest,
+) -> Result<Option<IpcResponse>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    let Ok(mut stream) = UnixStream::connect(socket) else {
+        return Ok(None);
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    Ok(Some(serde_json::from_str(&response)?))
+}
+
+#[cfg(not(unix))]
+pub fn request_running_instance(
+    _socket: &Path,
+    _request: &IpcRequest,
+) -> Result<Option<IpcResponse>> {
+    Ok(None)
+}
+
+/// Forward `request` to the instance listening on `socket`. Returns `false`
+/// when no instance is running, so the caller should start normally.
+pub fn forward_to_running_instance(socket: &Path, request: &LaunchRequest) -> Result<bool> {
+    let open = IpcRequest::Open {
+        paths: request.paths.clone(),
+        urls: request.urls.clone(),
+    };
+    match request_running_instance(socket, &open)? {
+        None => Ok(false),
+        Some(IpcResponse::Ok { .. }) => Ok(true),
+        Some(IpcResponse::Error { message }) => {
+            anyhow::bail!("Running instance refused: {}", message)
+        }
+        Some(other) => anyhow::bail!("Unexpected response from running instance: {:?}", other),
+    }
+}
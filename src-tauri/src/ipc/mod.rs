@@ -0,0 +1,67 @@
+//! External capture IPC server.
+//!
+//! A local socket that processes outside the app (shell hooks, the VS Code
+//! extension, ...) use to forward command start/finish events into memory and
+//! to pull suggestions back. The wire format is newline-delimited JSON; see
+//! [`protocol`] for the messages. Only the current user can connect: the
+//! socket lives in the RuneBook config directory with owner-only permissions.
+//!
+//! The socket also makes the desktop app single-instance: a second launch
+//! hands its open request to the running app (see [`instance`]) and exits.
+
+pub mod instance;
+pub mod protocol;
+pub mod server;
+
+pub use protocol::{ExitSummary, IpcRequest, IpcResponse, PROTOCOL_VERSION};
+pub use server::{CaptureServer, OpenHandler};
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// `[ipc]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct IpcConfig {
+    pub enabled: bool,
+    /// Socket path; defaults to `runebook.sock` in the config directory
+    pub socket_path: Option<PathBuf>,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            socket_path: None,
+        }
+    }
+}
+
+impl IpcConfig {
+    /// The `[ipc]` section of the config file at `path`, read on its own so a
+    /// second launch can hand off to the running app before it loads or
+    /// migrates the config. A missing or unreadable file gives the defaults.
+    pub fn load(path: &Path) -> Self {
+        #[derive(Deserialize, Default)]
+        #[serde(default)]
+        struct File {
+            ipc: IpcConfig,
+        }
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| toml::from_str::<File>(&text).ok())
+            .unwrap_or_default()
+            .ipc
+    }
+
+    pub fn socket_path(&self) -> PathBuf {
+        self.socket_path
+            .clone()
+            .unwrap_or_else(|| crate::config::config_dir().join("runebook.sock"))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,253 @@
+//! Wire protocol for the capture socket.
+//!
+//! Each line is one JSON object tagged by `type`; the server answers every
+//! request with exactly one response line, in order. Example exchange:
+//!
+//! ```text
+//! > {"type":"hello","client":"vscode","version":"0.3.0"}
+//! < {"type":"hello","protocol":1,"server":"runebook"}
+//! > {"type":"command_start","terminal_id":"1","command_line":"cargo test","cwd":"/src/app","workspace":"/src/app"}
+//! < {"type":"ok","command_id":"..."}
+//! > {"type":"command_output","terminal_id":"1","data":"running 12 tests\n"}
+//! < {"type":"ok","command_id":"..."}
+//! > {"type":"command_finish","terminal_id":"1","exit_code":0}
+//! < {"type":"ok","command_id":"..."}
+//! > {"type":"command_start","terminal_id":"1","command_line":"cargo test","cwd":"/src/app"}
+//! < {"type":"ok","command_id":"..."}
+//! > {"type":"command_finish","terminal_id":"1","exit_code":101,"summary":true}
+//! < {"type":"summary","command_id":"...","summary":{"exit_code":101,"error_class":"test_failure","suggestion_id":"...","line":"..."}}
+//! > {"type":"suggestions","workspace":"/src/app","limit":5}
+//! < {"type":"suggestions","suggestions":[...]}
+//! > {"type":"suggestion_feedback","suggestion_id":"...","accepted":true}
+//! < {"type":"ok"}
+//! ```
+
+use crate::health::SystemHealth;
+use crate::memory::{format_suggestion, Error, Suggestion, SuggestionFormat, Verbosity};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Bumped on incompatible protocol changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+fn default_stream() -> String {
+    "stdout".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpcRequest {
+    /// Identify the client; optional, defaults to "shell"
+    Hello {
+        client: String,
+        #[serde(default)]
+        version: Option<String>,
+    },
+    /// A command started in terminal `terminal_id`
+    CommandStart {
+        terminal_id: String,
+        command_line: String,
+        cwd: String,
+        /// Shell running in the terminal ("bash", "zsh", "pwsh", ...)
+        #[serde(default)]
+        shell: Option<String>,
+        /// Workspace folder the terminal belongs to, if any
+        #[serde(default)]
+        workspace: Option<String>,
+        /// Environment the command runs with, for explaining flaky commands.
+        /// Names that look like secrets are dropped and values are scrubbed.
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+    },
+    /// Output of the running command in `terminal_id`, stored as it arrives
+    /// so the UI can follow it
+    CommandOutput {
+        terminal_id: String,
+        data: String,
+        /// "stdout", "stderr", or "objects" for one JSON document per line,
+        /// such as the objects of a PowerShell pipeline
+        #[serde(default = "default_stream")]
+        stream: String,
+    },
+    /// The running command in `terminal_id` finished
+    CommandFinish {
+        terminal_id: String,
+        #[serde(default)]
+        exit_code: Option<i32>,
+        /// Answer with a `summary` a shell hook can print under a failed command
+        #[serde(default)]
+        summary: bool,
+    },
+    /// Raw terminal output (e.g. from a WezTerm or Kitty pane). Commands are
+    /// delimited by the OSC 133/633 shell integration marks in the stream
+    Output {
+        terminal_id: String,
+        data: String,
+        #[serde(default)]
+        cwd: Option<String>,
+        #[serde(default)]
+        shell: Option<String>,
+        #[serde(default)]
+        workspace: Option<String>,
+    },
+    /// The terminal was closed; ends its session
+    TerminalClosed {
+        terminal_id: String,
+    },
+    /// Suggestions relevant to `workspace` (all suggestions if omitted)
+    Suggestions {
+        #[serde(default)]
+        workspace: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+        /// Also render each suggestion for this surface ("terminal", "tmux", ...)
+        #[serde(default)]
+        format: Option<SuggestionFormat>,
+    },
+    /// The user accepted (applied) or dismissed a suggestion from `suggestions`
+    SuggestionFeedback {
+        suggestion_id: String,
+        accepted: bool,
+    },
+    /// Sent by a second RuneBook launch: open `paths` (absolute) and `urls`
+    /// (`runebook://` links) in this instance and bring its window forward.
+    /// Both may be empty.
+    Open {
+        #[serde(default)]
+        paths: Vec<PathBuf>,
+        #[serde(default)]
+        urls: Vec<String>,
+    },
+    /// Component health of this instance, for `runebook doctor`
+    Health,
+    Ping,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpcResponse {
+    Hello {
+        protocol: u32,
+        server: String,
+    },
+    Ok {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        command_id: Option<String>,
+    },
+    Suggestions {
+        suggestions: Vec<Suggestion>,
+        /// `suggestions` rendered in the requested format, in the same order
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rendered: Option<Vec<String>>,
+    },
+    /// Answer to a `command_finish` that asked for a summary; `summary` is
+    /// absent when the command succeeded
+    Summary {
+        command_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        summary: Option<ExitSummary>,
+    },
+    /// Commands completed by an `output` request
+    Captured {
+        command_ids: Vec<String>,
+        /// False while the stream has carried no shell integration marks
+        marks_seen: bool,
+    },
+    Health {
+        health: SystemHealth,
+    },
+    Pong,
+    Error {
+        message: String,
+    },
+}
+
+/// What went wrong with a failed command, in one line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExitSummary {
+    pub exit_code: Option<i32>,
+    /// Type of the classified error ("test_failure", "compile_error"); `None`
+    /// when no parser recognised the output
+    pub error_class: Option<String>,
+    /// Top suggestion for the failure, for `suggestion_feedback`
+    pub suggestion_id: Option<String>,
+    /// Ready to print, e.g. `runebook: exit 101, test_failure: 1 test failed:
+    /// parse::empty -> Re-run only the 1 failed test - parse::empty (run:
+    /// cargo test parse::empty)`
+    pub line: String,
+}
+
+/// Longest error message kept in a summary line, in characters
+const SUMMARY_MESSAGE_CHARS: usize = 120;
+
+impl ExitSummary {
+    /// The summary at the user's `verbosity`: terse leaves out the error
+    /// message and shows only the suggested command, verbose keeps the whole
+    /// message on the line
+    pub fn new(
+        exit_code: Option<i32>,
+        error: Option<&Error>,
+        suggestion: Option<&Suggestion>,
+        verbosity: Verbosity,
+    ) -> Self {
+        let mut line = match exit_code {
+            Some(code) => format!("runebook: exit {}", code),
+            None => "runebook: failed".to_string(),
+        };
+        if let Some(error) = error {
+            match verbosity {
+                Verbosity::Terse => line.push_str(&format!(", {}", error.error_type)),
+                Verbosity::Normal => {
+                    let message = error.message.lines().next().unwrap_or("");
+                    let mut message: String = message.chars().take(SUMMARY_MESSAGE_CHARS).collect();
+                    if message.len() < error.message.len() {
+                        message.push('…');
+                    }
+                    line.push_str(&format!(", {}: {}", error.error_type, message));
+                }
+                Verbosity::Verbose => {
+                    let message = error.message.lines().collect::<Vec<_>>().join(" | ");
+                    line.push_str(&format!(", {}: {}", error.error_type, message));
+                }
+            }
+        }
+        if let Some(suggestion) = suggestion {
+            let shown = match (verbosity, &suggestion.command) {
+                (Verbosity::Terse, Some(command)) => command.clone(),
+                _ => format_suggestion(suggestion, SuggestionFormat::Plain),
+            };
+            line.push_str(&format!(" -> {}", shown));
+        }
+        Self {
+            exit_code,
+            error_class: error.map(|e| e.error_type.clone()),
+            suggestion_id: suggestion.map(|s| s.id.clone()),
+            line,
+        }
+    }
+}
+
+impl IpcResponse {
+    pub fn ok() -> Self {
+        IpcResponse::Ok { command_id: None }
+    }
+
+    pub fn error(message: impl ToString) -> Self {
+        IpcResponse::Error {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Whether a suggestion applies to `workspace`: suggestions recorded for a
+/// directory inside it, or suggestions without any location
+pub fn suggestion_in_workspace(suggestion: &Suggestion, workspace: &str) -> bool {
+    let location = ["cwd", "workspace"]
+        .iter()
+        .find_map(|k| suggestion.context.get(*k).and_then(|v| v.as_str()));
+    match location {
+        None => true,
+        Some(dir) => std::path::Path::new(dir).starts_with(workspace),
+    }
+}
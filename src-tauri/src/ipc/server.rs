@@ -0,0 +1,492 @@
+//! Capture socket server.
+//!
+//! Every connection is one client. Terminals it reports get their own memory
+//! session (created on the first command), and the command running in each
+//! terminal is tracked until its finish event arrives. Clients that forward the
+//! raw terminal stream instead get commands delimited by its OSC 133/633 marks.
+
+use super::instance::LaunchRequest;
+use super::protocol::{
+    suggestion_in_workspace, ExitSummary, IpcRequest, IpcResponse, PROTOCOL_VERSION,
+};
+use crate::health::HealthRegistry;
+use crate::memory::flaky::sanitize_env;
+use crate::memory::shell_integration::{CommandSegmenter, OscParser};
+use crate::memory::tags::git_head;
+use crate::memory::{
+    format_suggestion, Command, LiveOutputs, MemoryEvent, MemoryStore, Session, StoreOpener,
+};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Per-terminal state within a connection
+struct Terminal {
+    session_id: String,
+    cwd: String,
+    running: Option<Command>,
+    /// Shell integration state for raw `output` streams
+    parser: OscParser,
+    segmenter: CommandSegmenter,
+}
+
+/// Per-connection state
+struct Connection {
+    client: String,
+    terminals: HashMap<String, Terminal>,
+}
+
+impl Connection {
+    fn new() -> Self {
+        Self {
+            client: "shell".to_string(),
+            terminals: HashMap::new(),
+        }
+    }
+}
+
+/// Receives `open` requests forwarded by a second launch
+pub type OpenHandler = Arc<dyn Fn(LaunchRequest) + Send + Sync>;
+
+pub struct CaptureServer {
+    path: PathBuf,
+    open_store: StoreOpener,
+    open_handler: Option<OpenHandler>,
+    live: Arc<LiveOutputs>,
+    health: HealthRegistry,
+}
+
+impl CaptureServer {
+    pub fn new(path: PathBuf, open_store: StoreOpener) -> Self {
+        Self {
+            path,
+            open_store,
+            open_handler: None,
+            live: Arc::new(LiveOutputs::new()),
+            health: HealthRegistry::new(),
+        }
+    }
+
+    /// Share running commands' output with these followers (e.g. the UI)
+    pub fn with_live_outputs(mut self, live: Arc<LiveOutputs>) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// Report into, and answer `health` requests from, the app's health registry
+    pub fn with_health(mut self, health: HealthRegistry) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Accept `open` requests; without a handler they are refused (headless mode)
+    pub fn with_open_handler(mut self, handler: OpenHandler) -> Self {
+        self.open_handler = Some(handler);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accept connections until the task is dropped
+    #[cfg(unix)]
+    pub async fn serve(self: Arc<Self>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // A stale socket from a previous run blocks bind; a live one belongs
+        // to another instance and must not be taken over
+        if self.path.exists() {
+            if std::os::unix::net::UnixStream::connect(&self.path).is_ok() {
+                anyhow::bail!(
+                    "Another RuneBook instance is listening on {}",
+                    self.path.display()
+                );
+            }
+            std::fs::remove_file(&self.path)?;
+        }
+        // Bound under an owner-only umask, so the socket is never open to
+        // other users, not even between bind and chmod
+        // SAFETY: umask only swaps the process's file mode mask
+        let umask = unsafe { libc::umask(0o077) };
+        let bound = tokio::net::UnixListener::bind(&self.path);
+        // SAFETY: as above, restoring the mask it returned
+        unsafe { libc::umask(umask) };
+        let listener = bound.with_context(|| format!("Failed to bind {}", self.path.display()))?;
+        std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600))?;
+        log::info!("[ipc] Listening on {}", self.path.display());
+        self.health.component("capture_socket").ok();
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                let (reader, writer) = stream.into_split();
+                if let Err(e) = server.handle_connection(reader, writer).await {
+                    log::debug!("[ipc] Connection closed: {}", e);
+                }
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub async fn serve(self: Arc<Self>) -> Result<()> {
+        anyhow::bail!("The capture socket is only available on Unix platforms")
+    }
+
+    /// Serve one client until it disconnects
+    pub async fn handle_connection<R, W>(&self, reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = BufReader::new(reader).lines();
+        let mut conn = Connection::new();
+        let result = async {
+            while let Some(line) = lines.next_line().await? {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let response = match serde_json::from_str::<IpcRequest>(&line) {
+                    Ok(request) => self
+                        .handle(&mut conn, request)
+                        .await
+                        .unwrap_or_else(IpcResponse::error),
+                    Err(e) => IpcResponse::error(format!("Invalid request: {}", e)),
+                };
+                let mut out = serde_json::to_vec(&response)?;
+                out.push(b'\n');
+                writer.write_all(&out).await?;
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        // Terminals left open when the client goes away end with it
+        let open: Vec<String> = conn.terminals.keys().cloned().collect();
+        for terminal_id in open {
+            if let Err(e) = self.close_terminal(&mut conn, &terminal_id).await {
+                log::debug!("[ipc] Failed to end session for {}: {}", terminal_id, e);
+            }
+        }
+        result
+    }
+
+    async fn handle(&self, conn: &mut Connection, request: IpcRequest) -> Result<IpcResponse> {
+        match request {
+            IpcRequest::Hello { client, version } => {
+                log::info!(
+                    "[ipc] Client connected: {} {}",
+                    client,
+                    version.as_deref().unwrap_or("")
+                );
+                conn.client = client;
+                Ok(IpcResponse::Hello {
+                    protocol: PROTOCOL_VERSION,
+                    server: "runebook".to_string(),
+                })
+            }
+            IpcRequest::Ping => Ok(IpcResponse::Pong),
+            IpcRequest::Health => {
+                self.health.probe_storage(&self.open_store).await;
+                Ok(IpcResponse::Health {
+                    health: self.health.snapshot(),
+                })
+            }
+            IpcRequest::Open { paths, urls } => match &self.open_handler {
+                Some(handler) => {
+                    log::info!("[ipc] Open request from another launch: {:?}", paths);
+                    handler(LaunchRequest { paths, urls });
+                    Ok(IpcResponse::ok())
+                }
+                None => Ok(IpcResponse::error(
+                    "This RuneBook instance does not open files",
+                )),
+            },
+            IpcRequest::CommandStart {
+                terminal_id,
+                command_line,
+                cwd,
+                shell,
+                workspace,
+                env,
+            } => {
+                let store = (self.open_store)().await?;
+                let words = shlex::split(&command_line)
+                    .unwrap_or_else(|| command_line.split_whitespace().map(String::from).collect());
+                let Some((program, args)) = words.split_first() else {
+                    return Ok(IpcResponse::error("Empty command line"));
+                };
+
+                let terminal = self
+                    .terminal(&store, conn, &terminal_id, &cwd, shell, workspace)
+                    .await?;
+                let mut command = Command::new(
+                    terminal.session_id.clone(),
+                    program.clone(),
+                    args.to_vec(),
+                    cwd,
+                );
+                command.env_summary = sanitize_env(&env);
+                command.git_commit = git_head(Path::new(&command.cwd));
+                let command_id = command.id.clone();
+                store
+                    .begin_live_command(&self.live, command.clone())
+                    .await?;
+                terminal.running = Some(command);
+                Ok(IpcResponse::Ok {
+                    command_id: Some(command_id),
+                })
+            }
+            IpcRequest::CommandOutput {
+                terminal_id,
+                data,
+                stream,
+            } => {
+                let Some(command_id) = conn
+                    .terminals
+                    .get(&terminal_id)
+                    .and_then(|t| t.running.as_ref())
+                    .map(|c| c.id.clone())
+                else {
+                    return Ok(IpcResponse::error(format!(
+                        "No running command in terminal {}",
+                        terminal_id
+                    )));
+                };
+                let store = (self.open_store)().await?;
+                store
+                    .append_live_output(&self.live, &command_id, &stream, data.into_bytes())
+                    .await?;
+                Ok(IpcResponse::Ok {
+                    command_id: Some(command_id),
+                })
+            }
+            IpcRequest::CommandFinish {
+                terminal_id,
+                exit_code,
+                summary,
+            } => {
+                let Some(mut command) = conn
+                    .terminals
+                    .get_mut(&terminal_id)
+                    .and_then(|t| t.running.take())
+                else {
+                    return Ok(IpcResponse::error(format!(
+                        "No running command in terminal {}",
+                        terminal_id
+                    )));
+                };
+                let store = (self.open_store)().await?;
+                let ended = Utc::now();
+                command.ended_at = Some(ended);
+                command.duration_ms = Some((ended - command.started_at).num_milliseconds() as u64);
+                command.exit_code = exit_code;
+                command.success = exit_code == Some(0);
+                let command_id = command.id.clone();
+                let error = store
+                    .store_finished_command(command.clone(), Vec::new())
+                    .await?;
+                self.live.finish(&command_id, exit_code);
+                let rerun = store.track_classified_run(&command, error.as_ref()).await?;
+                store.track_flaky_run(&command).await?;
+                if !summary {
+                    return Ok(IpcResponse::Ok {
+                        command_id: Some(command_id),
+                    });
+                }
+                let summary = if command.success {
+                    None
+                } else {
+                    // A fresh re-run suggestion is the answer to this failure;
+                    // otherwise the best one for the directory
+                    let suggestion = match rerun {
+                        Some(rerun) => Some(rerun),
+                        None => {
+                            let mut suggestions = store.get_suggestions(None, None).await?;
+                            suggestions.retain(|s| suggestion_in_workspace(s, &command.cwd));
+                            store
+                                .surface_suggestions("exit-summary", suggestions, 1, None)
+                                .await?
+                                .pop()
+                        }
+                    };
+                    Some(ExitSummary::new(
+                        exit_code,
+                        error.as_ref(),
+                        suggestion.as_ref(),
+                        store.preferences().await?.verbosity(),
+                    ))
+                };
+                Ok(IpcResponse::Summary {
+                    command_id,
+                    summary,
+                })
+            }
+            IpcRequest::Output {
+                terminal_id,
+                data,
+                cwd,
+                shell,
+                workspace,
+            } => {
+                let store = (self.open_store)().await?;
+                let terminal = self
+                    .terminal(
+                        &store,
+                        conn,
+                        &terminal_id,
+                        cwd.as_deref().unwrap_or(""),
+                        shell,
+                        workspace,
+                    )
+                    .await?;
+                let segments = terminal.parser.feed(data.as_bytes());
+                let mut command_ids = Vec::new();
+                for captured in terminal.segmenter.push(segments) {
+                    command_ids.push(
+                        store
+                            .store_captured_command(&terminal.session_id, captured, &terminal.cwd)
+                            .await?,
+                    );
+                }
+                Ok(IpcResponse::Captured {
+                    command_ids,
+                    marks_seen: terminal.segmenter.saw_marks(),
+                })
+            }
+            IpcRequest::TerminalClosed { terminal_id } => {
+                self.close_terminal(conn, &terminal_id).await?;
+                Ok(IpcResponse::ok())
+            }
+            IpcRequest::Suggestions {
+                workspace,
+                limit,
+                format,
+            } => {
+                let store = (self.open_store)().await?;
+                let mut suggestions = store.get_suggestions(None, None).await?;
+                if let Some(workspace) = &workspace {
+                    suggestions.retain(|s| suggestion_in_workspace(s, workspace));
+                }
+                let suggestions = store
+                    .surface_suggestions("ipc", suggestions, limit.unwrap_or(10), None)
+                    .await?;
+                let rendered = format.map(|format| {
+                    suggestions
+                        .iter()
+                        .map(|s| format_suggestion(s, format))
+                        .collect()
+                });
+                Ok(IpcResponse::Suggestions {
+                    suggestions,
+                    rendered,
+                })
+            }
+            IpcRequest::SuggestionFeedback {
+                suggestion_id,
+                accepted,
+            } => {
+                let store = (self.open_store)().await?;
+                if store.suggestion_feedback(&suggestion_id, accepted).await? {
+                    Ok(IpcResponse::ok())
+                } else {
+                    Ok(IpcResponse::error(format!(
+                        "No suggestion {}",
+                        suggestion_id
+                    )))
+                }
+            }
+        }
+    }
+
+    /// State for `terminal_id`, starting its session on first use
+    async fn terminal<'c>(
+        &self,
+        store: &MemoryStore,
+        conn: &'c mut Connection,
+        terminal_id: &str,
+        cwd: &str,
+        shell: Option<String>,
+        workspace: Option<String>,
+    ) -> Result<&'c mut Terminal> {
+        if !conn.terminals.contains_key(terminal_id) {
+            let session_id = self
+                .start_session(store, conn, terminal_id, cwd, shell, workspace)
+                .await?;
+            conn.terminals.insert(
+                terminal_id.to_string(),
+                Terminal {
+                    session_id,
+                    cwd: cwd.to_string(),
+                    running: None,
+                    parser: OscParser::new(),
+                    segmenter: CommandSegmenter::new(),
+                },
+            );
+        }
+        Ok(conn
+            .terminals
+            .get_mut(terminal_id)
+            .expect("terminal registered above"))
+    }
+
+    async fn start_session(
+        &self,
+        store: &MemoryStore,
+        conn: &Connection,
+        terminal_id: &str,
+        cwd: &str,
+        shell: Option<String>,
+        workspace: Option<String>,
+    ) -> Result<String> {
+        let mut session = Session::new(
+            shell.unwrap_or_else(|| "unknown".to_string()),
+            cwd.to_string(),
+        );
+        session.metadata = serde_json::json!({
+            "client": conn.client,
+            "terminal_id": terminal_id,
+            "workspace": workspace,
+        });
+        let session_id = session.id.clone();
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_start".to_string(),
+                timestamp: session.started_at,
+                session_id: session_id.clone(),
+                data: serde_json::to_value(&session)?,
+                provenance: None,
+            })
+            .await?;
+        Ok(session_id)
+    }
+
+    /// End the session of a terminal; a command still running is stored unfinished
+    async fn close_terminal(&self, conn: &mut Connection, terminal_id: &str) -> Result<()> {
+        let Some(terminal) = conn.terminals.remove(terminal_id) else {
+            return Ok(());
+        };
+        if let Some(command) = &terminal.running {
+            self.live.finish(&command.id, None);
+        }
+        let store = (self.open_store)().await?;
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_end".to_string(),
+                timestamp: Utc::now(),
+                session_id: terminal.session_id,
+                data: serde_json::json!({ "client": conn.client, "terminal_id": terminal_id }),
+                provenance: None,
+            })
+            .await
+    }
+}
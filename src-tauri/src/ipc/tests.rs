@@ -0,0 +1,197 @@
+use super::protocol::suggestion_in_workspace;
+use super::*;
+use crate::memory::store_opener;
+use crate::memory::{Suggestion, Verbosity};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+fn suggestion(context: serde_json::Value) -> Suggestion {
+    Suggestion {
+        id: "s".to_string(),
+        suggestion_type: "tip".to_string(),
+        priority: "low".to_string(),
+        rank: 1.0,
+        title: "t".to_string(),
+        description: String::new(),
+        command: None,
+        args: None,
+        context,
+        created_at: chrono::Utc::now(),
+        dismissed: false,
+        applied: false,
+    }
+}
+
+#[test]
+fn parses_vscode_requests() {
+    let request: IpcRequest = serde_json::from_str(
+        r#"{"type":"command_start","terminal_id":"1","command_line":"cargo test","cwd":"/w","workspace":"/w"}"#,
+    )
+    .unwrap();
+    assert!(matches!(request, IpcRequest::CommandStart { workspace: Some(w), .. } if w == "/w"));
+
+    let request: IpcRequest =
+        serde_json::from_str(r#"{"type":"command_finish","terminal_id":"1"}"#).unwrap();
+    assert_eq!(
+        request,
+        IpcRequest::CommandFinish {
+            terminal_id: "1".to_string(),
+            exit_code: None,
+            summary: false,
+        }
+    );
+
+    let request: IpcRequest =
+        serde_json::from_str(r#"{"type":"command_output","terminal_id":"1","data":"ok\n"}"#)
+            .unwrap();
+    assert!(matches!(request, IpcRequest::CommandOutput { stream, .. } if stream == "stdout"));
+}
+
+#[test]
+fn filters_suggestions_by_workspace() {
+    let inside = suggestion(serde_json::json!({ "cwd": "/src/app/crates" }));
+    let outside = suggestion(serde_json::json!({ "cwd": "/src/other" }));
+    let global = suggestion(serde_json::json!({}));
+    assert!(suggestion_in_workspace(&inside, "/src/app"));
+    assert!(!suggestion_in_workspace(&outside, "/src/app"));
+    assert!(suggestion_in_workspace(&global, "/src/app"));
+}
+
+#[tokio::test]
+async fn answers_each_line_without_a_store() {
+    let opener = store_opener(|| async { Err(anyhow::anyhow!("store offline")) });
+    let server = CaptureServer::new("unused.sock".into(), opener);
+
+    let (client, server_side) = tokio::io::duplex(4096);
+    let (server_read, server_write) = tokio::io::split(server_side);
+    let task = tokio::spawn(async move {
+        server
+            .handle_connection(server_read, server_write)
+            .await
+            .unwrap()
+    });
+
+    let (client_read, mut client_write) = tokio::io::split(client);
+    client_write
+        .write_all(
+            b"{\"type\":\"hello\",\"client\":\"vscode\"}\nnot json\n{\"type\":\"suggestions\"}\n",
+        )
+        .await
+        .unwrap();
+    let mut lines = BufReader::new(client_read).lines();
+    let hello = lines.next_line().await.unwrap().unwrap();
+    assert!(hello.contains("\"protocol\":1"));
+    let invalid = lines.next_line().await.unwrap().unwrap();
+    assert!(invalid.contains("Invalid request"));
+    let offline = lines.next_line().await.unwrap().unwrap();
+    assert!(offline.contains("store offline"));
+
+    client_write.shutdown().await.unwrap();
+    task.await.unwrap();
+}
+
+#[test]
+fn reads_the_ipc_section_on_its_own() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    assert!(IpcConfig::load(&path).enabled);
+
+    // Other sections are not looked at, even ones this build would reject
+    std::fs::write(
+        &path,
+        "[safety]\ndestructive = 42\n\n[ipc]\nenabled = false\nsocket_path = \"/tmp/rb.sock\"\n",
+    )
+    .unwrap();
+    let config = IpcConfig::load(&path);
+    assert!(!config.enabled);
+    assert_eq!(config.socket_path(), PathBuf::from("/tmp/rb.sock"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn second_launch_hands_off_open_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("runebook.sock");
+    let (opened_tx, mut opened) = tokio::sync::mpsc::unbounded_channel();
+    let on_open: OpenHandler = std::sync::Arc::new(move |paths| {
+        let _ = opened_tx.send(paths);
+    });
+
+    // No instance yet: the launch starts normally
+    let request = instance::LaunchRequest {
+        paths: vec![dir.path().join("canvas.yaml")],
+        urls: vec!["runebook://session/abc".to_string()],
+    };
+    assert!(!instance::forward_to_running_instance(&socket, &request).unwrap());
+
+    let server = std::sync::Arc::new(
+        CaptureServer::new(
+            socket.clone(),
+            store_opener(|| async { Err(anyhow::anyhow!("store offline")) }),
+        )
+        .with_open_handler(on_open),
+    );
+    tokio::spawn(std::sync::Arc::clone(&server).serve());
+    while !socket.exists() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    // Only the owner can connect
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o077, 0);
+
+    let forward = {
+        let socket = socket.clone();
+        let request = request.clone();
+        tokio::task::spawn_blocking(move || {
+            instance::forward_to_running_instance(&socket, &request)
+        })
+    };
+    assert!(forward.await.unwrap().unwrap());
+    assert_eq!(opened.recv().await.unwrap(), request);
+
+    // A second server must not take over the live socket
+    assert!(server.serve().await.is_err());
+}
+
+#[test]
+fn summarises_failed_command_in_one_line() {
+    let mut error = crate::memory::Error::new(
+        "c".to_string(),
+        "s".to_string(),
+        "test_failure".to_string(),
+        "medium".to_string(),
+        "1 test failed: parse::empty\nmore detail".to_string(),
+    );
+    error.exit_code = Some(101);
+    let mut rerun = suggestion(serde_json::json!({}));
+    rerun.title = "Re-run only the 1 failed test".to_string();
+    rerun.command = Some("cargo test parse::empty".to_string());
+
+    let summary = ExitSummary::new(Some(101), Some(&error), Some(&rerun), Verbosity::Normal);
+    assert_eq!(summary.error_class.as_deref(), Some("test_failure"));
+    assert_eq!(summary.suggestion_id.as_deref(), Some("s"));
+    assert_eq!(
+        summary.line,
+        "runebook: exit 101, test_failure: 1 test failed: parse::empty… -> Re-run only the 1 failed test (run: cargo test parse::empty)"
+    );
+    assert!(!summary.line.contains('\n'));
+    let terse = ExitSummary::new(Some(101), Some(&error), Some(&rerun), Verbosity::Terse);
+    assert_eq!(
+        terse.line,
+        "runebook: exit 101, test_failure -> cargo test parse::empty"
+    );
+    let verbose = ExitSummary::new(Some(101), Some(&error), None, Verbosity::Verbose);
+    assert_eq!(
+        verbose.line,
+        "runebook: exit 101, test_failure: 1 test failed: parse::empty | more detail"
+    );
+
+    let bare = ExitSummary::new(Some(1), None, None, Verbosity::Normal);
+    assert_eq!(bare.line, "runebook: exit 1");
+    let response = serde_json::to_string(&IpcResponse::Summary {
+        command_id: "c".to_string(),
+        summary: None,
+    })
+    .unwrap();
+    assert_eq!(response, r#"{"type":"summary","command_id":"c"}"#);
+}
@@ -1,19 +1,49 @@
 pub mod agents;
+pub mod alerts;
+pub mod archive;
+pub mod audit;
+pub mod backup;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod config;
 pub mod core;
+pub mod deeplink;
+pub mod dispatch;
 pub mod execution;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod i18n;
+pub mod ipc;
 pub mod memory;
+pub mod observe;
+pub mod onboarding;
 pub mod orchestrator;
+pub mod permissions;
+pub mod power;
+pub mod profile;
+pub mod receipts;
+pub mod schedule;
+pub mod server;
+pub mod ssh;
+pub mod storage;
+pub mod telemetry;
+pub mod upgrade;
+pub mod validation;
+pub mod webhooks;
 
 use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 
+use i18n::{tr, UserMessage};
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
+use validation::{Env, FilePath, Id, Raw, Uuid, Valid};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
-fn greet(name: &str) -> String {
+fn greet(name: Valid<String>) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
@@ -40,18 +70,22 @@ impl PtyManager {
 type PtyState = Arc<Mutex<PtyManager>>;
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn spawn_terminal(
     state: tauri::State<'_, PtyState>,
+    tracer: tauri::State<'_, Arc<telemetry::Tracer>>,
+    output_encoding: tauri::State<'_, memory::EncodingConfig>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
     app: AppHandle,
-    shell: Option<String>,
-    cwd: Option<String>,
-    env: Option<HashMap<String, String>>,
+    shell: Valid<Option<String>, Raw>,
+    cwd: Valid<Option<String>, Raw>,
+    env: Valid<Option<HashMap<String, String>>, Env>,
     cols: Option<u16>,
     rows: Option<u16>,
-) -> Result<String, String> {
+) -> Result<String, UserMessage> {
     let terminal_id = uuid::Uuid::new_v4().to_string();
 
-    let shell_cmd = shell.unwrap_or_else(|| {
+    let shell_cmd = shell.into_inner().unwrap_or_else(|| {
         if cfg!(windows) {
             "powershell.exe".to_string()
         } else {
@@ -67,44 +101,83 @@ async fn spawn_terminal(
         pixel_height: 0,
     };
 
-    let pair = pty_system.openpty(pty_size).map_err(|e| e.to_string())?;
+    let pair = pty_system
+        .openpty(pty_size)
+        .map_err(UserMessage::wrap("terminal-open-failed"))?;
 
     let mut cmd = CommandBuilder::new(&shell_cmd);
-    if let Some(ref cwd_path) = cwd {
+    if let Some(cwd_path) = cwd.as_ref() {
         if !cwd_path.is_empty() {
             cmd.cwd(cwd_path);
         }
     }
-    if let Some(env_vars) = env {
-        for (k, v) in env_vars {
-            // Validate env var names: only allow alphanumeric and underscore.
-            if k.chars().all(|c| c.is_alphanumeric() || c == '_') {
-                cmd.env(k, v);
-            } else {
-                log::warn!("[spawn_terminal] Skipping invalid env var name: {:?}", k);
-            }
-        }
+    // Variable names were checked by the `Env` rule
+    for (k, v) in env.into_inner().unwrap_or_default() {
+        cmd.env(k, v);
     }
 
-    let child = pair.slave.spawn_command(cmd).map_err(|e| e.to_string())?;
+    let mut span = telemetry::Span::root("terminal_session");
+    span.set_attribute("terminal.id", terminal_id.clone());
+    span.set_attribute("terminal.shell", shell_cmd.clone());
+
+    let child = pair
+        .slave
+        .spawn_command(cmd)
+        .map_err(UserMessage::wrap("terminal-open-failed"))?;
     // Close the slave side in the parent process
     drop(pair.slave);
 
     let master = pair.master;
-    let writer = master.take_writer().map_err(|e| e.to_string())?;
-    let mut reader = master.try_clone_reader().map_err(|e| e.to_string())?;
+    let writer = master
+        .take_writer()
+        .map_err(UserMessage::wrap("terminal-open-failed"))?;
+    let mut reader = master
+        .try_clone_reader()
+        .map_err(UserMessage::wrap("terminal-open-failed"))?;
 
     // Spawn a thread to read PTY output and emit Tauri events
     let tid = terminal_id.clone();
     let app_clone = app.clone();
     let state_arc = Arc::clone(state.inner());
+    let tracer_arc = Arc::clone(tracer.inner());
+    let scrubber = Arc::clone(scrubber.inner());
+    let mut decoder = memory::encoding::OutputDecoder::new(output_encoding.inner())
+        .map_err(UserMessage::wrap("output-encoding-invalid"))?;
     std::thread::spawn(move || {
         let mut buf = [0u8; 4096];
+        let mut marks = memory::shell_integration::OscParser::new();
+        let mut segmenter = memory::shell_integration::CommandSegmenter::new();
         loop {
             match reader.read(&mut buf) {
                 Ok(0) | Err(_) => break,
                 Ok(n) => {
-                    let data = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let chunk = &buf[..n];
+
+                    // Shells with OSC 133/633 integration mark exact command boundaries
+                    for command in segmenter.push(marks.feed(chunk)) {
+                        if let Some(dispatcher) =
+                            app_clone.try_state::<Arc<dispatch::WindowDispatcher>>()
+                        {
+                            dispatcher.command_finished(command.exit_code == Some(0));
+                        }
+                        let _ = app_clone.emit(
+                            &format!("terminal-command-{}", tid),
+                            serde_json::json!({
+                                "command_line": scrubber
+                                    .scrub(&command.command_line, memory::ScrubStage::Display)
+                                    .0,
+                                "cwd": command.cwd,
+                                "exit_code": command.exit_code,
+                                "duration_ms": (command.ended_at - command.started_at)
+                                    .num_milliseconds(),
+                                "output_bytes": command.output.len(),
+                            }),
+                        );
+                    }
+
+                    // Rules see one read at a time; a secret split across reads is missed
+                    let (data, _) =
+                        scrubber.scrub(&decoder.decode(chunk), memory::ScrubStage::Display);
                     let _ = app_clone.emit(&format!("terminal-output-{}", tid), data);
                 }
             }
@@ -121,9 +194,19 @@ async fn spawn_terminal(
             .map(|status| if status.success() { 0 } else { 1 })
             .unwrap_or(-1);
         let _ = app_clone.emit(&format!("terminal-exit-{}", tid), exit_code);
+
+        span.set_attribute("process.exit_code", exit_code);
+        if exit_code == 0 {
+            span.finish();
+        } else {
+            span.fail(format!("exit code {}", exit_code));
+        }
+        tracer_arc.record(span);
     });
 
-    let mut mgr = state.lock().map_err(|e| e.to_string())?;
+    let mut mgr = state
+        .lock()
+        .map_err(UserMessage::wrap("terminal-state-unavailable"))?;
     mgr.sessions.insert(
         terminal_id.clone(),
         PtySession {
@@ -139,35 +222,39 @@ async fn spawn_terminal(
 #[tauri::command]
 async fn write_terminal(
     state: tauri::State<'_, PtyState>,
-    terminal_id: String,
-    data: String,
-) -> Result<(), String> {
-    let mut mgr = state.lock().map_err(|e| e.to_string())?;
+    terminal_id: Valid<String, Uuid>,
+    data: Valid<String, Raw>,
+) -> Result<(), UserMessage> {
+    let mut mgr = state
+        .lock()
+        .map_err(UserMessage::wrap("terminal-state-unavailable"))?;
     let session = mgr
         .sessions
-        .get_mut(&terminal_id)
-        .ok_or_else(|| format!("Terminal {} not found", terminal_id))?;
+        .get_mut(&*terminal_id)
+        .ok_or_else(|| UserMessage::new("terminal-not-found").with("id", &*terminal_id))?;
     session
         .writer
         .write_all(data.as_bytes())
-        .map_err(|e| e.to_string())
+        .map_err(UserMessage::wrap("terminal-write-failed"))
 }
 
 #[tauri::command]
 async fn resize_terminal(
     state: tauri::State<'_, PtyState>,
-    terminal_id: String,
+    terminal_id: Valid<String, Uuid>,
     cols: u16,
     rows: u16,
-) -> Result<(), String> {
+) -> Result<(), UserMessage> {
     // resize() sends a non-blocking TIOCSWINSZ ioctl (SIGWINCH signal) —
     // it does not perform blocking I/O, so holding the lock during the call
     // is safe and avoids a window where the session appears "not found".
-    let mgr = state.lock().map_err(|e| e.to_string())?;
+    let mgr = state
+        .lock()
+        .map_err(UserMessage::wrap("terminal-state-unavailable"))?;
     let session = mgr
         .sessions
-        .get(&terminal_id)
-        .ok_or_else(|| format!("Terminal {} not found", terminal_id))?;
+        .get(&*terminal_id)
+        .ok_or_else(|| UserMessage::new("terminal-not-found").with("id", &*terminal_id))?;
     session
         .master
         .resize(PtySize {
@@ -176,18 +263,31 @@ async fn resize_terminal(
             pixel_width: 0,
             pixel_height: 0,
         })
-        .map_err(|e| e.to_string())
+        .map_err(UserMessage::wrap("terminal-resize-failed"))
 }
 
 #[tauri::command]
 async fn kill_terminal(
     state: tauri::State<'_, PtyState>,
-    terminal_id: String,
-) -> Result<(), String> {
-    let mut mgr = state.lock().map_err(|e| e.to_string())?;
-    if let Some(mut session) = mgr.sessions.remove(&terminal_id) {
+    terminal_id: Valid<String, Uuid>,
+) -> Result<(), UserMessage> {
+    let mut mgr = state
+        .lock()
+        .map_err(UserMessage::wrap("terminal-state-unavailable"))?;
+    if let Some(mut session) = mgr.sessions.remove(&*terminal_id) {
+        // The shell leads its own session; take whatever it started with it
+        if let Some(tree) = session
+            .child
+            .process_id()
+            .and_then(|pid| execution::ProcessTree::of(pid).ok())
+        {
+            let _ = tree.signal(execution::Signal::Kill);
+        }
         // First, attempt to terminate the child process.
-        session.child.kill().map_err(|e| e.to_string())?;
+        session
+            .child
+            .kill()
+            .map_err(UserMessage::wrap("terminal-kill-failed"))?;
         // Then, wait for the child to exit to ensure it is properly reaped
         // and does not remain as a zombie process on supported platforms.
         let _ = session.child.wait();
@@ -195,43 +295,655 @@ async fn kill_terminal(
     Ok(())
 }
 
+// ── Command execution ─────────────────────────────────────────────────────────
+
+/// Run a canvas terminal node's command and return its stdout.
+///
+/// Commands the policy holds back (destructive ones, or those a
+/// `[[safety.rules]]` entry requires confirmation for) may first fail with
+/// `confirmation-required`, carrying a `token`; invoking again with `confirmation_token` set to it runs the command.
+/// With `execution_id`, [`cancel_command`] can stop the run; with `timeout_ms`,
+/// it is killed once that much time has passed.
+/// `stdin`, such as the data of a connected node, is written to the command's
+/// standard input. With `mode` `pwsh` the command line is PowerShell.
+/// `limits` caps CPU time, wall-clock time and memory; a command that breaks
+/// one fails with `resource-limit-exceeded`.
+/// Binary stdout, or stdout that is not UTF-8, comes back base64-encoded;
+/// [`execute_with_receipt`] returns the whole outcome, which says so in
+/// `stdout_encoding` and carries the detected `content_type`.
+/// `ansi` `strip` removes escape sequences from the output; `spans` also
+/// parses its colors, which the outcome and output events carry as `spans`.
+/// `backend` `cmd` or `powershell` starts the command through that Windows
+/// shell, with its arguments quoted and CRLF output normalized; the default
+/// picks `cmd` on Windows and starts it directly elsewhere.
+/// `target` `{ kind: "wsl", distro }` runs the command in that WSL distro,
+/// with a Windows `cwd` translated to its Linux form.
+/// `priority` places the run in line when every `[concurrency]` slot is taken.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn execute_terminal_command(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    running: tauri::State<'_, Arc<execution::RunningCommands>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    execution_id: Valid<Option<String>, Id>,
+    timeout_ms: Option<u64>,
+    command: Valid<String, Raw>,
+    args: Valid<Vec<String>, Raw>,
+    env: Valid<HashMap<String, String>, Env>,
+    cwd: Valid<String, Raw>,
+    profile: Valid<Option<String>, Id>,
+    variables: Valid<Option<HashMap<String, String>>, Raw>,
+    stdin: Valid<Option<String>, Raw>,
+    mode: Option<execution::ExecutionMode>,
+    limits: Option<execution::ResourceLimits>,
+    ansi: Option<execution::AnsiMode>,
+    backend: Option<execution::Backend>,
+    target: Option<execution::ExecutionTarget>,
+    priority: Option<i32>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+) -> Result<String, UserMessage> {
+    let request = execution::ExecutionRequest {
+        command: command.into_inner(),
+        args: args.into_inner(),
+        env: env.into_inner(),
+        cwd: Some(cwd.into_inner()),
+        profile: profile.into_inner(),
+        variables: variables.into_inner().unwrap_or_default(),
+        stdin: stdin.into_inner(),
+        mode: mode.unwrap_or_default(),
+        limits: limits.unwrap_or_default(),
+        ansi: ansi.unwrap_or_default(),
+        backend: backend.unwrap_or_default(),
+        target: target.unwrap_or_default(),
+        priority: priority.unwrap_or_default(),
+    };
+    // Every run is registered, so its job id always reaches `send_signal`
+    let execution_id = execution_id
+        .into_inner()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let stop = execution::StopConditions {
+        timeout: timeout_ms.map(std::time::Duration::from_millis),
+        cancel: Some(running.register(&execution_id)?),
+    };
+    let job = dispatcher.job_started_as(&execution_id, &request.command, "user");
+    let outcome = execution
+        .execute_until(&request, confirmation_token.as_deref(), "user", None, stop)
+        .await;
+    dispatcher.job_finished(&job, outcome.as_ref().is_ok_and(|o| o.success));
+    let mut outcome = outcome?;
+    scrub_for_display(&scrubber, &mut outcome);
+    if outcome.success {
+        Ok(outcome.stdout)
+    } else {
+        Err(execution::service::failure_message(&outcome))
+    }
+}
+
+/// Run `spec.stages` as a pipeline: each stage's stdout is wired to the next
+/// one's stdin by RuneBook rather than a shell, so `ps aux | grep foo` runs
+/// without shell operators. Every stage is resolved and gated on its own; a
+/// refusal names the stage by index as `stage`, and each stage takes its own
+/// `confirmation_token`. `spec`'s cwd, env, profile and the rest apply to all
+/// stages, and its stdin goes to the first. Resolves with the outcome once
+/// the pipeline ends, failed or not; `stages` has each one's exit code and
+/// stderr. `execution_id` works with [`cancel_command`] as for single commands.
+#[tauri::command]
+async fn execute_pipeline(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    running: tauri::State<'_, Arc<execution::RunningCommands>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    execution_id: Valid<Option<String>, Id>,
+    timeout_ms: Option<u64>,
+    spec: Valid<execution::PipelineSpec>,
+) -> Result<execution::PipelineOutcome, UserMessage> {
+    let execution_id = execution_id
+        .into_inner()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let stop = execution::StopConditions {
+        timeout: timeout_ms.map(std::time::Duration::from_millis),
+        cancel: Some(running.register(&execution_id)?),
+    };
+    let label = spec
+        .stages
+        .iter()
+        .map(|stage| stage.command.as_str())
+        .collect::<Vec<_>>()
+        .join(" | ");
+    let job = dispatcher.job_started_as(&execution_id, &label, "user");
+    let outcome = execution.execute_pipeline(&spec, "user", stop).await;
+    dispatcher.job_finished(&job, outcome.as_ref().is_ok_and(|o| o.outcome.success));
+    let mut outcome = outcome?;
+    scrub_for_display(&scrubber, &mut outcome.outcome);
+    for stage in &mut outcome.stages {
+        if stage.stderr_encoding.is_utf8() {
+            stage.stderr = scrubber.scrub(&stage.stderr, memory::ScrubStage::Display).0;
+        }
+    }
+    Ok(outcome)
+}
+
+/// [`execute_terminal_command`], with output sent as `command-output` events
+/// tagged with `run_id` while the command runs. Every chunk has been sent
+/// when the invoke resolves, and so have the objects of a PowerShell run, as
+/// one `command-objects` event. `run_id` is also the execution id
+/// [`cancel_command`] takes.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn execute_terminal_command_streaming(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    running: tauri::State<'_, Arc<execution::RunningCommands>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    run_id: Valid<String, Id>,
+    timeout_ms: Option<u64>,
+    command: Valid<String, Raw>,
+    args: Valid<Vec<String>, Raw>,
+    env: Valid<HashMap<String, String>, Env>,
+    cwd: Valid<String, Raw>,
+    profile: Valid<Option<String>, Id>,
+    variables: Valid<Option<HashMap<String, String>>, Raw>,
+    stdin: Valid<Option<String>, Raw>,
+    mode: Option<execution::ExecutionMode>,
+    limits: Option<execution::ResourceLimits>,
+    ansi: Option<execution::AnsiMode>,
+    backend: Option<execution::Backend>,
+    target: Option<execution::ExecutionTarget>,
+    priority: Option<i32>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+) -> Result<String, UserMessage> {
+    let request = execution::ExecutionRequest {
+        command: command.into_inner(),
+        args: args.into_inner(),
+        env: env.into_inner(),
+        cwd: Some(cwd.into_inner()),
+        profile: profile.into_inner(),
+        variables: variables.into_inner().unwrap_or_default(),
+        stdin: stdin.into_inner(),
+        mode: mode.unwrap_or_default(),
+        limits: limits.unwrap_or_default(),
+        ansi: ansi.unwrap_or_default(),
+        backend: backend.unwrap_or_default(),
+        target: target.unwrap_or_default(),
+        priority: priority.unwrap_or_default(),
+    };
+    let stop = execution::StopConditions {
+        timeout: timeout_ms.map(std::time::Duration::from_millis),
+        cancel: Some(running.register(&run_id)?),
+    };
+    let (chunks, mut received) = tokio::sync::mpsc::unbounded_channel::<execution::OutputChunk>();
+    let forwarder = {
+        let dispatcher = Arc::clone(&dispatcher);
+        let scrubber = Arc::clone(&scrubber);
+        let run_id = run_id.to_string();
+        tauri::async_runtime::spawn(async move {
+            let mut chunk_index = 0;
+            while let Some(chunk) = received.recv().await {
+                let event = dispatch::CommandOutputEvent {
+                    run_id: run_id.clone(),
+                    chunk_index,
+                    stream_type: chunk.stream,
+                    // Scrubbing base64 would only corrupt it
+                    text: match chunk.encoding {
+                        execution::TextEncoding::Utf8 => {
+                            scrubber.scrub(&chunk.data, memory::ScrubStage::Display).0
+                        }
+                        execution::TextEncoding::Base64 => chunk.data,
+                    },
+                    encoding: chunk.encoding,
+                    spans: scrub_spans_for_display(&scrubber, chunk.spans),
+                };
+                dispatcher.dispatch(
+                    dispatch::Topic::Jobs,
+                    None,
+                    dispatch::COMMAND_OUTPUT,
+                    &event,
+                );
+                chunk_index += 1;
+            }
+        })
+    };
+    let job = dispatcher.job_started_as(&run_id, &request.command, "user");
+    let outcome = execution
+        .execute_until(
+            &request,
+            confirmation_token.as_deref(),
+            "user",
+            Some(chunks),
+            stop,
+        )
+        .await;
+    dispatcher.job_finished(&job, outcome.as_ref().is_ok_and(|o| o.success));
+    // The sender is gone once the command ends, so this waits for the last chunk
+    let _ = forwarder.await;
+    let mut outcome = outcome?;
+    scrub_for_display(&scrubber, &mut outcome);
+    if let Some(objects) = outcome.objects.take() {
+        dispatcher.dispatch(
+            dispatch::Topic::Jobs,
+            None,
+            dispatch::COMMAND_OBJECTS,
+            &dispatch::CommandObjectsEvent {
+                run_id: run_id.into_inner(),
+                objects,
+            },
+        );
+    }
+    if outcome.success {
+        Ok(outcome.stdout)
+    } else {
+        Err(execution::service::failure_message(&outcome))
+    }
+}
+
+/// Kill a command started with an execution id. Its invoke then fails with
+/// `command-cancelled`.
+#[tauri::command]
+fn cancel_command(
+    running: tauri::State<'_, Arc<execution::RunningCommands>>,
+    execution_id: Valid<String, Id>,
+) -> Result<(), UserMessage> {
+    if running.cancel(&execution_id) {
+        Ok(())
+    } else {
+        Err(UserMessage::new("execution-not-found").with("id", &*execution_id))
+    }
+}
+
+/// Deliver `SIGINT`, `SIGTERM` or `SIGKILL` to a running job and every
+/// process it started. `job_id` is the job's id in `jobs-changed`, which for
+/// a command is its execution id and for a PTY session its id.
+#[tauri::command]
+fn send_signal(
+    running: tauri::State<'_, Arc<execution::RunningCommands>>,
+    ptys: tauri::State<'_, Arc<execution::PtyManager>>,
+    job_id: Valid<String, Id>,
+    signal: execution::Signal,
+) -> Result<(), UserMessage> {
+    running
+        .signal(&job_id, signal)
+        .or_else(|| ptys.signal(&job_id, signal))
+        .ok_or_else(|| UserMessage::new("job-not-found").with("id", &*job_id))?
+        .map_err(UserMessage::wrap("signal-failed"))
+}
+
+/// Apply display scrub rules to a command's captured text output
+fn scrub_for_display(scrubber: &memory::Scrubber, outcome: &mut execution::CommandOutcome) {
+    if outcome.stdout_encoding.is_utf8() {
+        outcome.stdout = scrubber
+            .scrub(&outcome.stdout, memory::ScrubStage::Display)
+            .0;
+    }
+    if outcome.stderr_encoding.is_utf8() {
+        outcome.stderr = scrubber
+            .scrub(&outcome.stderr, memory::ScrubStage::Display)
+            .0;
+    }
+    for object in outcome.objects.iter_mut().flatten() {
+        scrub_json_for_display(scrubber, object);
+    }
+    outcome.stdout_spans =
+        scrub_spans_for_display(scrubber, std::mem::take(&mut outcome.stdout_spans));
+    outcome.stderr_spans =
+        scrub_spans_for_display(scrubber, std::mem::take(&mut outcome.stderr_spans));
+}
+
+/// Apply display scrub rules to the text of styled spans
+fn scrub_spans_for_display(
+    scrubber: &memory::Scrubber,
+    mut spans: Vec<execution::StyledSpan>,
+) -> Vec<execution::StyledSpan> {
+    for span in &mut spans {
+        span.text = scrubber.scrub(&span.text, memory::ScrubStage::Display).0;
+    }
+    spans
+}
+
+/// Apply display scrub rules to every string in a JSON value
+fn scrub_json_for_display(scrubber: &memory::Scrubber, value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => {
+            *text = scrubber.scrub(text, memory::ScrubStage::Display).0;
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                scrub_json_for_display(scrubber, item);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for field in fields.values_mut() {
+                scrub_json_for_display(scrubber, field);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Show what the configured scrub rules do to `sample` and check their fixtures
+#[tauri::command]
+fn scrub_preview(
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    sample: Valid<String, Raw>,
+    stage: Option<memory::ScrubStage>,
+) -> memory::scrub::ScrubPreview {
+    scrubber.preview(&sample, stage.unwrap_or(memory::ScrubStage::Capture))
+}
+
+/// Resolve a command exactly as `execute_terminal_command` would, without
+/// running it. `policy` names the rule that would hold it back, and whether a
+/// configured rule or the destructive-command classifier decided.
+#[tauri::command]
+async fn preview_execution(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    spec: Valid<execution::ExecutionRequest>,
+) -> Result<execution::ResolvedExecution, UserMessage> {
+    execution.preview(&spec)
+}
+
+/// Everything [`preview_execution`] reports, plus the exact process that
+/// would start, where its program was found, and the `problems` (an
+/// unresolved placeholder, a blocking rule, a missing cwd or program) that
+/// would stop it. Nothing runs, and no confirmation token is issued.
+#[tauri::command]
+async fn execute_dry_run(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    spec: Valid<execution::ExecutionRequest>,
+) -> Result<execution::DryRun, UserMessage> {
+    execution.dry_run(&spec)
+}
+
+/// Re-run a command whenever files under its cwd (or `spec.paths`) change.
+/// Progress arrives as `watch-event`s; returns the new watch.
+#[tauri::command]
+async fn watch_start(
+    app: AppHandle,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    watches: tauri::State<'_, Arc<execution::WatchManager>>,
+    spec: Valid<execution::WatchSpec>,
+) -> Result<execution::WatchInfo, UserMessage> {
+    let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let info = watches.start(Arc::clone(&execution), spec.into_inner(), events)?;
+    let dispatcher = window_dispatcher(&app);
+    tauri::async_runtime::spawn(async move {
+        while let Some(event) = received.recv().await {
+            dispatcher.dispatch(dispatch::Topic::Jobs, None, dispatch::WATCH_EVENT, &event);
+        }
+    });
+    Ok(info)
+}
+
+/// Stop a watch started with `watch_start`, cancelling its current run
+#[tauri::command]
+fn watch_stop(
+    watches: tauri::State<'_, Arc<execution::WatchManager>>,
+    id: Valid<String, Uuid>,
+) -> Result<(), UserMessage> {
+    if watches.stop(&id) {
+        Ok(())
+    } else {
+        Err(UserMessage::new("watch-not-found").with("id", &*id))
+    }
+}
+
+/// Run `spec.canvas`, pausing at its breakpoints. Pauses and node results
+/// arrive as `canvas-debug-event`s; returns the session id.
+#[tauri::command]
+async fn canvas_debug_start(
+    app: AppHandle,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    debugger: tauri::State<'_, Arc<execution::DebugManager>>,
+    spec: Valid<execution::DebugSpec>,
+) -> Result<String, UserMessage> {
+    let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let id = debugger.start(Arc::clone(&execution), spec.into_inner(), events)?;
+    let dispatcher = window_dispatcher(&app);
+    tauri::async_runtime::spawn(async move {
+        while let Some(event) = received.recv().await {
+            dispatcher.dispatch(
+                dispatch::Topic::Jobs,
+                None,
+                dispatch::CANVAS_DEBUG_EVENT,
+                &event,
+            );
+        }
+    });
+    Ok(id)
+}
+
+/// Step, continue, stop, or change the breakpoints of a debug session
+#[tauri::command]
+fn canvas_debug_command(
+    debugger: tauri::State<'_, Arc<execution::DebugManager>>,
+    id: Valid<String, Uuid>,
+    command: Valid<execution::DebugCommand>,
+) -> Result<(), UserMessage> {
+    if debugger.command(&id, command.into_inner()) {
+        Ok(())
+    } else {
+        Err(UserMessage::new("debug-session-not-found").with("id", &*id))
+    }
+}
+
+/// Where a debug session is paused, or `None` while it runs
+#[tauri::command]
+fn canvas_debug_inspect(
+    debugger: tauri::State<'_, Arc<execution::DebugManager>>,
+    id: Valid<String, Uuid>,
+) -> Option<execution::PauseState> {
+    debugger.inspect(&id)
+}
+
+#[tauri::command]
+fn watch_list(
+    watches: tauri::State<'_, Arc<execution::WatchManager>>,
+) -> Vec<execution::WatchInfo> {
+    watches.list()
+}
+
+/// Follow a container's or pod's log into memory, as one running command
+/// whose output `tail_output` follows. Errors found in it and its end arrive
+/// as `log-event`s; returns the followed stream.
+#[tauri::command]
+async fn logs_follow(
+    app: AppHandle,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    open_store: tauri::State<'_, memory::StoreOpener>,
+    live: tauri::State<'_, Arc<memory::LiveOutputs>>,
+    follows: tauri::State<'_, Arc<execution::LogFollowManager>>,
+    spec: Valid<execution::LogFollowSpec>,
+) -> Result<execution::LogFollowInfo, UserMessage> {
+    let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let info = follows
+        .start(
+            Arc::clone(&execution),
+            Arc::clone(&open_store),
+            Arc::clone(&live),
+            spec.into_inner(),
+            events,
+        )
+        .await?;
+    let dispatcher = window_dispatcher(&app);
+    let job = dispatcher.job_started_as(&info.id, &info.command_line, "logs");
+    tauri::async_runtime::spawn(async move {
+        while let Some(event) = received.recv().await {
+            dispatcher.dispatch(dispatch::Topic::Jobs, None, dispatch::LOG_EVENT, &event);
+            if let execution::LogEventKind::Ended { exit_code, stopped } = event.kind {
+                dispatcher.job_finished(&job, stopped || exit_code == Some(0));
+            }
+        }
+    });
+    Ok(info)
+}
+
+/// Stop following a log started with `logs_follow`
+#[tauri::command]
+fn logs_stop(
+    follows: tauri::State<'_, Arc<execution::LogFollowManager>>,
+    id: Valid<String, Uuid>,
+) -> Result<(), UserMessage> {
+    if follows.stop(&id) {
+        Ok(())
+    } else {
+        Err(UserMessage::new("log-follow-not-found").with("id", &*id))
+    }
+}
+
+#[tauri::command]
+fn logs_list(
+    follows: tauri::State<'_, Arc<execution::LogFollowManager>>,
+) -> Vec<execution::LogFollowInfo> {
+    follows.list()
+}
+
+// ── PTY execution ─────────────────────────────────────────────────────────────
+
+/// Run a command on a pseudo-terminal, for programs like `top` or `vim` that
+/// need one. Output and the exit arrive as `pty-event`s; a view that missed
+/// some repaints from `pty_screen` first.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn pty_start(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    ptys: tauri::State<'_, Arc<execution::PtyManager>>,
+    spec: Valid<execution::ExecutionRequest>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+    cols: Option<u16>,
+    rows: Option<u16>,
+) -> Result<execution::PtyInfo, UserMessage> {
+    let size = execution::PtyDimensions {
+        cols: cols.unwrap_or(80),
+        rows: rows.unwrap_or(24),
+    };
+    let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let info = ptys.start(
+        &execution,
+        &spec,
+        confirmation_token.as_deref(),
+        "user",
+        size,
+        events,
+    )?;
+    let job = dispatcher.job_started_as(&info.id, &info.command_line, "user");
+    let dispatcher = Arc::clone(&dispatcher);
+    let scrubber = Arc::clone(&scrubber);
+    tauri::async_runtime::spawn(async move {
+        while let Some(mut event) = received.recv().await {
+            match &mut event.kind {
+                execution::PtyEventKind::Output { data, .. } => {
+                    *data = scrubber.scrub(data, memory::ScrubStage::Display).0;
+                }
+                execution::PtyEventKind::Exited { exit_code } => {
+                    dispatcher.job_finished(&job, *exit_code == Some(0));
+                }
+            }
+            dispatcher.dispatch(dispatch::Topic::Jobs, None, dispatch::PTY_EVENT, &event);
+        }
+    });
+    Ok(info)
+}
+
+/// Forward raw input (keystrokes, control sequences) to a PTY session
+#[tauri::command]
+fn pty_write(
+    ptys: tauri::State<'_, Arc<execution::PtyManager>>,
+    id: Valid<String, Uuid>,
+    data: Valid<String, Raw>,
+) -> Result<(), UserMessage> {
+    ptys.write(&id, data.as_bytes())
+}
+
+#[tauri::command]
+fn pty_resize(
+    ptys: tauri::State<'_, Arc<execution::PtyManager>>,
+    id: Valid<String, Uuid>,
+    cols: u16,
+    rows: u16,
+) -> Result<(), UserMessage> {
+    ptys.resize(&id, execution::PtyDimensions { cols, rows })
+}
+
+/// The session's buffered output; `pty-event`s numbered after its `seq` follow it
+#[tauri::command]
+fn pty_screen(
+    ptys: tauri::State<'_, Arc<execution::PtyManager>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    id: Valid<String, Uuid>,
+) -> Result<execution::PtyScreen, UserMessage> {
+    let mut screen = ptys.screen(&id)?;
+    screen.data = scrubber.scrub(&screen.data, memory::ScrubStage::Display).0;
+    Ok(screen)
+}
+
+/// End a PTY session, killing its program if it still runs
+#[tauri::command]
+fn pty_close(
+    ptys: tauri::State<'_, Arc<execution::PtyManager>>,
+    id: Valid<String, Uuid>,
+) -> Result<(), UserMessage> {
+    if ptys.close(&id) {
+        Ok(())
+    } else {
+        Err(UserMessage::new("pty-not-found").with("id", &*id))
+    }
+}
+
+#[tauri::command]
+fn pty_list(ptys: tauri::State<'_, Arc<execution::PtyManager>>) -> Vec<execution::PtyInfo> {
+    ptys.list()
+}
+
 // ── Memory inspection ─────────────────────────────────────────────────────────
 
 #[tauri::command]
 async fn memory_inspect(
-    host: Option<String>,
+    host: Valid<Option<String>, Id>,
     port: Option<u16>,
-    data_dir: Option<String>,
-) -> Result<String, String> {
+    data_dir: Valid<Option<String>, FilePath>,
+) -> Result<String, UserMessage> {
     use crate::memory::*;
 
-    let host = host.as_deref().unwrap_or("localhost");
-    let port = port.unwrap_or(34567);
-    let data_dir = data_dir.as_deref().unwrap_or("./pluresdb-data");
+    let configured = MemoryConfig::configured();
+    let host = host.as_deref().unwrap_or(&configured.host);
+    let port = port.unwrap_or(configured.port);
+    let data_dir = data_dir
+        .into_inner()
+        .unwrap_or_else(|| configured.data_dir().to_string_lossy().into_owned());
 
-    match init_memory_store(host, port, data_dir).await {
+    match init_memory_store(host, port, &data_dir).await {
         Ok(store) => {
             let sessions = store
                 .list_sessions()
                 .await
-                .map_err(|e| format!("Failed to list sessions: {}", e))?;
+                .map_err(UserMessage::wrap("memory-list-sessions-failed"))?;
             let errors = store
                 .query_recent_errors(Some(10), None, None)
                 .await
-                .map_err(|e| format!("Failed to query errors: {}", e))?;
+                .map_err(UserMessage::wrap("memory-query-errors-failed"))?;
             let suggestions = store
                 .get_suggestions(None, Some(10))
                 .await
-                .map_err(|e| format!("Failed to get suggestions: {}", e))?;
+                .map_err(UserMessage::wrap("memory-suggestions-failed"))?;
 
             let mut output = String::new();
-            output.push_str("=== RuneBook Cognitive Memory ===\n\n");
-            output.push_str(&format!("Sessions: {}\n", sessions.len()));
-            output.push_str(&format!("Recent Errors: {}\n", errors.len()));
-            output.push_str(&format!("Active Suggestions: {}\n\n", suggestions.len()));
+            output.push_str(&format!("{}\n\n", tr("inspect-title", &[])));
+            output.push_str(&format!(
+                "{}\n",
+                tr("inspect-sessions", &[("count", &sessions.len())])
+            ));
+            output.push_str(&format!(
+                "{}\n",
+                tr("inspect-errors", &[("count", &errors.len())])
+            ));
+            output.push_str(&format!(
+                "{}\n\n",
+                tr("inspect-suggestions", &[("count", &suggestions.len())])
+            ));
 
             if !sessions.is_empty() {
-                output.push_str("=== Recent Sessions ===\n");
+                output.push_str(&format!("{}\n", tr("inspect-recent-sessions", &[])));
                 for session in sessions.iter().take(5) {
                     output.push_str(&format!(
                         "  {} - {} (started: {})\n",
@@ -244,48 +956,4071 @@ async fn memory_inspect(
             }
 
             if !errors.is_empty() {
-                output.push_str("=== Recent Errors ===\n");
+                output.push_str(&format!("{}\n", tr("inspect-recent-errors", &[])));
                 for error in errors.iter().take(5) {
                     output.push_str(&format!(
-                        "  [{}] {} - {}\n",
-                        error.severity, error.error_type, error.message
+                        "  [{}] {} - {} ({})\n",
+                        error.severity,
+                        error.error_type,
+                        error.message,
+                        error.fingerprint()
                     ));
                 }
                 output.push('\n');
             }
 
             if !suggestions.is_empty() {
-                output.push_str("=== Top Suggestions ===\n");
+                output.push_str(&format!("{}\n", tr("inspect-top-suggestions", &[])));
                 for suggestion in suggestions.iter().take(5) {
                     output.push_str(&format!(
-                        "  [{}] {} - {}\n",
-                        suggestion.priority, suggestion.title, suggestion.description
+                        "  [{}] {}\n",
+                        suggestion.priority,
+                        format_suggestion(suggestion, SuggestionFormat::Plain)
                     ));
                 }
             }
 
             Ok(output)
         }
-        Err(e) => Err(format!("Failed to initialize memory store: {}", e)),
+        Err(e) => Err(UserMessage::wrap("memory-store-unavailable")(e)),
     }
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    // Initialize logger (ignore error if already initialized)
-    let _ = env_logger::try_init();
+// ── Archives ──────────────────────────────────────────────────────────────────
 
-    tauri::Builder::default()
-        .manage(Arc::new(Mutex::new(PtyManager::new())) as PtyState)
+fn resolve_archive_format(
+    format: Option<archive::ArchiveFormat>,
+    path: &str,
+) -> Result<archive::ArchiveFormat, UserMessage> {
+    format
+        .or_else(|| archive::ArchiveFormat::from_path(std::path::Path::new(path)))
+        .ok_or_else(|| UserMessage::new("archive-format-unknown").with("path", path))
+}
+
+#[tauri::command]
+async fn archive_create(
+    app: AppHandle,
+    sources: Valid<Vec<String>, FilePath>,
+    destination: Valid<String, FilePath>,
+    format: Option<archive::ArchiveFormat>,
+    operation_id: Valid<Option<String>, Id>,
+) -> Result<archive::ArchiveSummary, UserMessage> {
+    let format = resolve_archive_format(format, &destination)?;
+    let operation_id = operation_id
+        .into_inner()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let sources: Vec<std::path::PathBuf> =
+        sources.into_inner().into_iter().map(Into::into).collect();
+    let destination = destination.into_inner();
+    let dispatcher = window_dispatcher(&app);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        archive::create_archive(
+            format,
+            &sources,
+            std::path::Path::new(&destination),
+            &mut |progress| {
+                dispatcher.dispatch(
+                    dispatch::Topic::App,
+                    None,
+                    dispatch::ARCHIVE_PROGRESS,
+                    &dispatch::ArchiveProgressEvent {
+                        operation_id: operation_id.clone(),
+                        progress: progress.clone(),
+                    },
+                );
+            },
+        )
+    })
+    .await
+    .map_err(UserMessage::wrap("task-failed"))?
+    .map_err(UserMessage::wrap("archive-create-failed"))
+}
+
+#[tauri::command]
+async fn archive_extract(
+    app: AppHandle,
+    archive_path: Valid<String, FilePath>,
+    destination: Valid<String, FilePath>,
+    format: Option<archive::ArchiveFormat>,
+    operation_id: Valid<Option<String>, Id>,
+) -> Result<archive::ArchiveSummary, UserMessage> {
+    let format = resolve_archive_format(format, &archive_path)?;
+    let operation_id = operation_id
+        .into_inner()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let (archive_path, destination) = (archive_path.into_inner(), destination.into_inner());
+    let dispatcher = window_dispatcher(&app);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        archive::extract_archive(
+            format,
+            std::path::Path::new(&archive_path),
+            std::path::Path::new(&destination),
+            &mut |progress| {
+                dispatcher.dispatch(
+                    dispatch::Topic::App,
+                    None,
+                    dispatch::ARCHIVE_PROGRESS,
+                    &dispatch::ArchiveProgressEvent {
+                        operation_id: operation_id.clone(),
+                        progress: progress.clone(),
+                    },
+                );
+            },
+        )
+    })
+    .await
+    .map_err(UserMessage::wrap("task-failed"))?
+    .map_err(UserMessage::wrap("archive-extract-failed"))
+}
+
+/// Rebuild the search mirror, secondary stats and line-offset indexes (or
+/// the `kinds` given) from primary records, `batch_size` records at a time,
+/// with a `reindex-progress` event after each batch. An interrupted rebuild
+/// resumes where it stopped when called again with the same kinds.
+#[tauri::command]
+async fn reindex_memory(
+    app: AppHandle,
+    kinds: Option<Vec<memory::IndexKind>>,
+    batch_size: Option<usize>,
+    operation_id: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::ReindexReport, UserMessage> {
+    let operation_id = operation_id
+        .into_inner()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let store = open_memory_store(host.into_inner(), port).await?;
+    let dispatcher = window_dispatcher(&app);
+    store
+        .reindex(
+            &kinds.unwrap_or_default(),
+            batch_size.unwrap_or(memory::reindex::DEFAULT_REINDEX_BATCH),
+            &config.mirror.path(),
+            &mut |progress| {
+                dispatcher.dispatch(
+                    dispatch::Topic::App,
+                    None,
+                    dispatch::REINDEX_PROGRESS,
+                    &dispatch::ReindexProgressEvent {
+                        operation_id: operation_id.clone(),
+                        progress: progress.clone(),
+                    },
+                );
+            },
+        )
+        .await
+        .map_err(UserMessage::wrap("memory-reindex-failed"))
+}
+
+/// Storage consumed per record type, session, and workspace, with the largest outputs
+#[tauri::command]
+async fn memory_usage_report(
+    top_n: Option<usize>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::UsageReport, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .usage_report(top_n.unwrap_or(10))
+        .await
+        .map_err(UserMessage::wrap("memory-usage-failed"))
+}
+
+/// Where command time went over `range` ("today", "week", "month", "<n>d",
+/// or "YYYY-MM-DD..YYYY-MM-DD"), grouped by project, tag, or command
+#[tauri::command]
+async fn time_report(
+    range: Valid<Option<String>>,
+    group_by: Option<memory::TimeGroupBy>,
+    top_commands: Option<usize>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::TimeReport, UserMessage> {
+    let (from, to) =
+        memory::stats::parse_range(range.as_deref().unwrap_or("month"), chrono::Utc::now())
+            .map_err(UserMessage::wrap("time-report-failed"))?;
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .time_report(
+            from,
+            to,
+            group_by.unwrap_or(memory::TimeGroupBy::Project),
+            top_commands.unwrap_or(5),
+        )
+        .await
+        .map_err(UserMessage::wrap("time-report-failed"))
+}
+
+/// Write commands, errors and daily durations over `range` to `destination`
+/// as Parquet (default) or CSV tables, with a manifest documenting each column
+#[tauri::command]
+async fn export_analytics(
+    range: Valid<Option<String>>,
+    format: Option<memory::AnalyticsFormat>,
+    destination: Valid<String, FilePath>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::AnalyticsManifest, UserMessage> {
+    let (from, to) =
+        memory::stats::parse_range(range.as_deref().unwrap_or("month"), chrono::Utc::now())
+            .map_err(UserMessage::wrap("analytics-export-failed"))?;
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .export_analytics(
+            from,
+            to,
+            format.unwrap_or_default(),
+            std::path::Path::new(&*destination),
+        )
+        .await
+        .map_err(UserMessage::wrap("analytics-export-failed"))
+}
+
+/// Import shell history (bash, zsh, fish, or atuin) into an `imported-<source>`
+/// session. `path` defaults to the shell's usual history location.
+#[tauri::command]
+async fn history_import(
+    app: AppHandle,
+    source: memory::history::HistorySource,
+    path: Valid<Option<String>, FilePath>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::history::ImportSummary, UserMessage> {
+    let store = open_guarded_store(&app, host.into_inner(), port).await?;
+    store
+        .import_history(source, path.as_deref().map(std::path::Path::new))
+        .await
+        .map_err(UserMessage::wrap("history-import-failed"))
+}
+
+/// Pack a session's recent context (commands, errors, open suggestions, output
+/// tails, working directory state) into a bundle encrypted with `passphrase`,
+/// for `handoff_import` on another machine
+#[tauri::command]
+async fn handoff_export(
+    session_id: Valid<String, Id>,
+    passphrase: Valid<String, Raw>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<String, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    let handoff = store
+        .export_handoff(&session_id)
+        .await
+        .map_err(UserMessage::wrap("handoff-export-failed"))?;
+    let passphrase = passphrase.into_inner();
+    // Deriving the key takes a moment on purpose
+    tauri::async_runtime::spawn_blocking(move || memory::handoff::seal(&handoff, &passphrase))
+        .await
+        .map_err(UserMessage::wrap("task-failed"))?
+        .map_err(UserMessage::wrap("handoff-export-failed"))
+}
+
+/// Decrypt a bundle from `handoff_export` and store the session it carries
+#[tauri::command]
+async fn handoff_import(
+    app: AppHandle,
+    bundle: Valid<String, Raw>,
+    passphrase: Valid<String, Raw>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::HandoffSummary, UserMessage> {
+    let (bundle, passphrase) = (bundle.into_inner(), passphrase.into_inner());
+    let handoff =
+        tauri::async_runtime::spawn_blocking(move || memory::handoff::open(&bundle, &passphrase))
+            .await
+            .map_err(UserMessage::wrap("task-failed"))?
+            .map_err(UserMessage::wrap("handoff-import-failed"))?;
+    let store = open_guarded_store(&app, host.into_inner(), port).await?;
+    store
+        .import_handoff(handoff)
+        .await
+        .map_err(UserMessage::wrap("handoff-import-failed"))
+}
+
+/// Fill memory with a demo session and example canvas in the demo workspace,
+/// replacing earlier demo data
+#[tauri::command]
+async fn demo_seed(
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::DemoSummary, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .seed_demo_data()
+        .await
+        .map_err(UserMessage::wrap("demo-seed-failed"))
+}
+
+/// Remove the demo data; returns the number of records removed
+#[tauri::command]
+async fn demo_wipe(
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<usize, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .wipe_demo_data()
+        .await
+        .map_err(UserMessage::wrap("memory-delete-failed"))
+}
+
+// ── Memory store ──────────────────────────────────────────────────────────────
+
+async fn open_memory_store(
+    host: Option<String>,
+    port: Option<u16>,
+) -> Result<memory::MemoryStore, UserMessage> {
+    let configured = memory::MemoryConfig::configured();
+    let host = host.as_deref().unwrap_or(&configured.host);
+    let port = port.unwrap_or(configured.port);
+    memory::init_memory_store(host, port, &configured.data_dir().to_string_lossy())
+        .await
+        .map_err(UserMessage::wrap("memory-store-unavailable"))
+}
+
+/// Open the memory store with the app-wide quota guard, output encoding,
+/// auto-tagger, and scrubber attached
+async fn open_guarded_store(
+    app: &AppHandle,
+    host: Option<String>,
+    port: Option<u16>,
+) -> Result<memory::MemoryStore, UserMessage> {
+    let quota = app.state::<Arc<memory::QuotaGuard>>();
+    let output_encoding = app.state::<memory::EncodingConfig>();
+    let auto_tagger = app.state::<Arc<memory::AutoTagger>>();
+    let scrubber = app.state::<Arc<memory::Scrubber>>();
+    let ranking = app.state::<memory::RankingConfig>();
+    let events = app.state::<Arc<memory::EventBus>>();
+    Ok(open_memory_store(host, port)
+        .await?
+        .with_quota(Arc::clone(quota.inner()))
+        .with_output_encoding(output_encoding.inner().clone())
+        .with_auto_tagger(Arc::clone(auto_tagger.inner()))
+        .with_scrubber(Arc::clone(scrubber.inner()))
+        .with_ranking(ranking.inner().clone())
+        .with_remote_store(app.state::<RemoteStore>().0.clone())
+        .with_event_bus(Arc::clone(events.inner())))
+}
+
+/// Open the memory store able to read offloaded content back from object storage
+async fn open_reading_store(
+    remote: &RemoteStore,
+    host: Option<String>,
+    port: Option<u16>,
+) -> Result<memory::MemoryStore, UserMessage> {
+    Ok(open_memory_store(host, port)
+        .await?
+        .with_remote_store(remote.0.clone()))
+}
+
+// ── Tags ──────────────────────────────────────────────────────────────────────
+
+/// Add tags to a session or command. Returns the resulting tags.
+#[tauri::command]
+async fn tag_add(
+    target: memory::TagTarget,
+    id: Valid<String, Id>,
+    tags: Valid<Vec<String>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<String>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .tag_add(target, &id, &tags)
+        .await
+        .map_err(UserMessage::wrap("tag-update-failed"))
+}
+
+/// Remove tags from a session or command. Returns the remaining tags.
+#[tauri::command]
+async fn tag_remove(
+    target: memory::TagTarget,
+    id: Valid<String, Id>,
+    tags: Valid<Vec<String>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<String>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .tag_remove(target, &id, &tags)
+        .await
+        .map_err(UserMessage::wrap("tag-update-failed"))
+}
+
+/// Tags with usage counts, for one session/command or the whole store
+#[tauri::command]
+async fn tag_list(
+    target: Option<memory::TagTarget>,
+    id: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::TagCount>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .tag_list(target.zip(id.as_deref()))
+        .await
+        .map_err(UserMessage::wrap("tag-list-failed"))
+}
+
+/// Sessions carrying all of `tags` (all sessions when empty)
+#[tauri::command]
+async fn memory_list_sessions(
+    tags: Valid<Option<Vec<String>>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::Session>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .list_sessions_tagged(&tags.into_inner().unwrap_or_default())
+        .await
+        .map_err(UserMessage::wrap("memory-list-sessions-failed"))
+}
+
+/// Recent errors whose command or session carries all of `tags`
+#[tauri::command]
+async fn memory_recent_errors(
+    limit: Option<usize>,
+    severity: Valid<Option<String>, Id>,
+    tags: Valid<Option<Vec<String>>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::Error>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .query_recent_errors_tagged(
+            limit,
+            None,
+            severity.as_deref(),
+            &tags.into_inner().unwrap_or_default(),
+        )
+        .await
+        .map_err(UserMessage::wrap("memory-query-errors-failed"))
+}
+
+/// LSP diagnostics for the files under `path` that failed commands
+/// complained about
+#[tauri::command]
+async fn diagnostics_for_path(
+    path: Valid<String>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::FileDiagnostics>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .diagnostics_for_path(&path)
+        .await
+        .map_err(UserMessage::wrap("diagnostics-failed"))
+}
+
+/// Artifacts filtered by session, command, and tags
+#[tauri::command]
+async fn memory_list_artifacts(
+    session_id: Valid<Option<String>, Id>,
+    command_id: Valid<Option<String>, Id>,
+    tags: Valid<Option<Vec<String>>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::Artifact>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .list_artifacts_tagged(
+            session_id.as_deref(),
+            command_id.as_deref(),
+            &tags.into_inner().unwrap_or_default(),
+        )
+        .await
+        .map_err(UserMessage::wrap("memory-list-artifacts-failed"))
+}
+
+/// Bucket holding offloaded artifact content, when `[storage.s3]` is set
+struct RemoteStore(Option<Arc<dyn storage::ObjectStore>>);
+
+/// One artifact with its content, fetched from object storage if it was offloaded
+#[tauri::command]
+async fn memory_get_artifact(
+    remote: tauri::State<'_, RemoteStore>,
+    id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::Artifact, UserMessage> {
+    let store = open_reading_store(&remote, host.into_inner(), port).await?;
+    store
+        .get_artifact(&id)
+        .await
+        .map_err(UserMessage::wrap("artifact-read-failed"))?
+        .ok_or_else(|| UserMessage::new("artifact-not-found").with("id", &*id))
+}
+
+/// Lines `offset_lines..offset_lines + limit_lines` of a command's stored
+/// output, so the UI can scroll large outputs without loading them whole
+#[tauri::command]
+async fn read_output_page(
+    cache: tauri::State<'_, Arc<memory::OutputPageCache>>,
+    remote: tauri::State<'_, RemoteStore>,
+    command_id: Valid<String, Id>,
+    offset_lines: usize,
+    limit_lines: usize,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::OutputPage, UserMessage> {
+    let store = open_reading_store(&remote, host.into_inner(), port).await?;
+    cache
+        .page(&store, &command_id, offset_lines, limit_lines)
+        .await
+        .map_err(UserMessage::wrap("output-read-failed"))
+}
+
+/// The last `limit_lines` lines of a command's stored output
+#[tauri::command]
+async fn read_output_tail(
+    cache: tauri::State<'_, Arc<memory::OutputPageCache>>,
+    remote: tauri::State<'_, RemoteStore>,
+    command_id: Valid<String, Id>,
+    limit_lines: usize,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::OutputPage, UserMessage> {
+    let store = open_reading_store(&remote, host.into_inner(), port).await?;
+    cache
+        .tail(&store, &command_id, limit_lines)
+        .await
+        .map_err(UserMessage::wrap("output-read-failed"))
+}
+
+/// Stream a command's output to `on_event`: what is stored, then with
+/// `follow` the live output until the command finishes. Works the same for
+/// running and finished commands.
+#[tauri::command]
+async fn tail_output(
+    live: tauri::State<'_, Arc<memory::LiveOutputs>>,
+    remote: tauri::State<'_, RemoteStore>,
+    command_id: Valid<String, Id>,
+    follow: bool,
+    on_event: tauri::ipc::Channel<memory::TailEvent>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<(), UserMessage> {
+    let store = open_reading_store(&remote, host.into_inner(), port).await?;
+    memory::live::tail_output(&store, &live, &command_id, follow, |event| {
+        on_event.send(event).map_err(anyhow::Error::from)
+    })
+    .await
+    .map_err(UserMessage::wrap("output-read-failed"))
+}
+
+// ── Session sharing ───────────────────────────────────────────────────────────
+
+/// Share a live session read-only: mints a token with which observers follow
+/// its command starts and scrubbed output over the HTTP API. Shown once.
+#[tauri::command]
+async fn share_session_start(
+    shares: tauri::State<'_, Arc<observe::ShareRegistry>>,
+    session_id: Valid<String, Id>,
+) -> Result<observe::ShareGrant, UserMessage> {
+    Ok(shares.start(&session_id))
+}
+
+/// Stop sharing a session, revoking its tokens; returns how many were active
+#[tauri::command]
+async fn share_session_stop(
+    shares: tauri::State<'_, Arc<observe::ShareRegistry>>,
+    session_id: Valid<String, Id>,
+) -> Result<usize, UserMessage> {
+    Ok(shares.stop(&session_id))
+}
+
+/// Revoke one share token, ending its observations
+#[tauri::command]
+async fn share_revoke(
+    shares: tauri::State<'_, Arc<observe::ShareRegistry>>,
+    share_id: Valid<String, Id>,
+) -> Result<(), UserMessage> {
+    if !shares.revoke(&share_id) {
+        return Err(UserMessage::new("share-not-found").with("id", &*share_id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn share_list(
+    shares: tauri::State<'_, Arc<observe::ShareRegistry>>,
+) -> Result<Vec<observe::Share>, UserMessage> {
+    Ok(shares.list())
+}
+
+/// Follow a session another RuneBook shares, sending its events to
+/// `on_event` until the share ends or `observe_stop` is called
+#[tauri::command]
+async fn observe_session(
+    observations: tauri::State<'_, Arc<observe::client::Observations>>,
+    observation_id: Valid<String, Id>,
+    base_url: Valid<String, Raw>,
+    session_id: Valid<String, Id>,
+    token: Valid<String, Raw>,
+    on_event: tauri::ipc::Channel<memory::SessionEvent>,
+) -> Result<(), UserMessage> {
+    observations
+        .follow(
+            &observation_id,
+            base_url.into_inner(),
+            session_id.into_inner(),
+            token.into_inner(),
+            move |event| on_event.send(event).map_err(anyhow::Error::from),
+        )
+        .await
+        .map_err(UserMessage::wrap("observe-failed"))
+}
+
+#[tauri::command]
+async fn observe_stop(
+    observations: tauri::State<'_, Arc<observe::client::Observations>>,
+    observation_id: Valid<String, Id>,
+) -> Result<bool, UserMessage> {
+    Ok(observations.stop(&observation_id))
+}
+
+/// Objects a command emitted, such as a PowerShell pipeline's, captured
+/// in its `objects` stream
+#[tauri::command]
+async fn command_objects(
+    remote: tauri::State<'_, RemoteStore>,
+    command_id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<serde_json::Value>, UserMessage> {
+    let store = open_reading_store(&remote, host.into_inner(), port).await?;
+    store
+        .command_objects(&command_id)
+        .await
+        .map_err(UserMessage::wrap("output-read-failed"))
+}
+
+/// Number of lines in a command's stored output
+#[tauri::command]
+async fn output_line_count(
+    cache: tauri::State<'_, Arc<memory::OutputPageCache>>,
+    remote: tauri::State<'_, RemoteStore>,
+    command_id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<usize, UserMessage> {
+    let store = open_reading_store(&remote, host.into_inner(), port).await?;
+    cache
+        .line_count(&store, &command_id)
+        .await
+        .map_err(UserMessage::wrap("output-read-failed"))
+}
+
+// ── Suggestion ranking ────────────────────────────────────────────────────────
+
+/// Record that the user accepted (applied) or dismissed a suggestion
+#[tauri::command]
+async fn suggestion_feedback(
+    app: AppHandle,
+    suggestion_id: Valid<String, Id>,
+    accepted: bool,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<(), UserMessage> {
+    let found = open_guarded_store(&app, host.into_inner(), port)
+        .await?
+        .suggestion_feedback(&suggestion_id, accepted)
+        .await
+        .map_err(UserMessage::wrap("suggestion-feedback-failed"))?;
+    if found {
+        Ok(())
+    } else {
+        Err(UserMessage::new("suggestion-not-found").with("id", &*suggestion_id))
+    }
+}
+
+/// Why a suggestion is shown: the analyzer that produced it, the records that
+/// triggered it, its ranking breakdown, and what would make it stop appearing
+#[tauri::command]
+async fn explain_suggestion(
+    remote: tauri::State<'_, RemoteStore>,
+    suggestion_id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::SuggestionExplanation, UserMessage> {
+    open_reading_store(&remote, host.into_inner(), port)
+        .await?
+        .explain_suggestion(&suggestion_id)
+        .await
+        .map_err(UserMessage::wrap("suggestion-explain-failed"))?
+        .ok_or_else(|| UserMessage::new("suggestion-not-found").with("id", &*suggestion_id))
+}
+
+/// What has been learned about the user: muted suggestion reasons, preferred
+/// fix style and package manager, and summary verbosity
+#[tauri::command]
+async fn preferences_get(
+    remote: tauri::State<'_, RemoteStore>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::Preferences, UserMessage> {
+    open_reading_store(&remote, host.into_inner(), port)
+        .await?
+        .preferences()
+        .await
+        .map_err(UserMessage::wrap("preferences-read-failed"))
+}
+
+/// Set a preference; learning no longer changes it
+#[tauri::command]
+async fn preferences_set(
+    app: AppHandle,
+    choice: memory::PreferenceChoice,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::Preferences, UserMessage> {
+    open_guarded_store(&app, host.into_inner(), port)
+        .await?
+        .set_preference(choice)
+        .await
+        .map_err(UserMessage::wrap("preferences-save-failed"))
+}
+
+/// Forget a preference, or with `name` `all` every one. `reason_key`
+/// unmutes a single suggestion reason.
+#[tauri::command]
+async fn preferences_reset(
+    app: AppHandle,
+    name: memory::PreferenceName,
+    reason_key: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::Preferences, UserMessage> {
+    open_guarded_store(&app, host.into_inner(), port)
+        .await?
+        .reset_preferences(name, reason_key.as_deref())
+        .await
+        .map_err(UserMessage::wrap("preferences-save-failed"))
+}
+
+/// Dismiss a suggestion and never show another for the same reason
+#[tauri::command]
+async fn suggestion_mute(
+    app: AppHandle,
+    suggestion_id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::MutedReason, UserMessage> {
+    open_guarded_store(&app, host.into_inner(), port)
+        .await?
+        .mute_suggestion_reason(&suggestion_id)
+        .await
+        .map_err(UserMessage::wrap("preferences-save-failed"))?
+        .ok_or_else(|| UserMessage::new("suggestion-not-found").with("id", &*suggestion_id))
+}
+
+/// Replay recorded suggestion outcomes against alternative ranking weights
+#[tauri::command]
+async fn rank_eval(
+    app: AppHandle,
+    alternatives: Valid<HashMap<String, memory::RankingConfig>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::RankEvalReport, UserMessage> {
+    let mut alternatives: Vec<_> = alternatives.into_inner().into_iter().collect();
+    alternatives.sort_by(|a, b| a.0.cmp(&b.0));
+    open_guarded_store(&app, host.into_inner(), port)
+        .await?
+        .rank_eval(&alternatives)
+        .await
+        .map_err(UserMessage::wrap("rank-eval-failed"))
+}
+
+// ── Alerts ────────────────────────────────────────────────────────────────────
+
+/// The alert rules in effect
+#[tauri::command]
+fn alert_rules_list(alerts: tauri::State<'_, Arc<alerts::AlertService>>) -> Vec<alerts::AlertRule> {
+    alerts.rules()
+}
+
+/// Replace the alert rules. They apply to the next event and are saved to
+/// the `[alerts]` section of `config.toml`.
+#[tauri::command]
+fn alert_rules_save(
+    alerts: tauri::State<'_, Arc<alerts::AlertService>>,
+    rules: Valid<Vec<alerts::AlertRule>>,
+) -> Result<(), UserMessage> {
+    alerts
+        .save_rules(rules.into_inner())
+        .map_err(UserMessage::wrap("alert-rules-invalid"))
+}
+
+// ── Webhooks ──────────────────────────────────────────────────────────────────
+
+/// The newest webhook deliveries, newest first: to one hook when `hook` is
+/// set, and to URLs given directly in alert actions under the URL
+#[tauri::command]
+fn webhook_deliveries(
+    webhooks: tauri::State<'_, Arc<webhooks::WebhookSender>>,
+    hook: Valid<Option<String>>,
+    limit: Option<usize>,
+) -> Result<Vec<webhooks::Delivery>, UserMessage> {
+    webhooks
+        .log()
+        .recent(hook.as_deref(), None, limit.unwrap_or(50))
+        .map_err(UserMessage::wrap("webhook-log-failed"))
+}
+
+// ── Smart views ───────────────────────────────────────────────────────────────
+
+/// How often saved views are re-evaluated for `view-updated` notifications
+const VIEW_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Create a saved view, or update the one with `view_id`
+#[tauri::command]
+async fn view_save(
+    view_id: Valid<Option<String>, Uuid>,
+    name: Valid<String>,
+    description: Valid<Option<String>>,
+    filter: Valid<memory::ViewFilter>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::SavedView, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    let name = name.into_inner();
+    let mut view = match view_id.into_inner() {
+        Some(id) => store
+            .view_get(&id)
+            .await
+            .map_err(UserMessage::wrap("view-save-failed"))?,
+        None => memory::SavedView::new(name.clone(), memory::ViewFilter::default()),
+    };
+    view.name = name;
+    view.description = description.into_inner();
+    view.filter = filter.into_inner();
+    store
+        .view_save(view)
+        .await
+        .map_err(UserMessage::wrap("view-save-failed"))
+}
+
+#[tauri::command]
+async fn view_delete(
+    view_id: Valid<String, Uuid>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<(), UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .view_delete(&view_id)
+        .await
+        .map_err(UserMessage::wrap("view-delete-failed"))
+}
+
+#[tauri::command]
+async fn view_list(
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::SavedView>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .view_list()
+        .await
+        .map_err(UserMessage::wrap("view-list-failed"))
+}
+
+/// Records currently matching a saved view, newest first
+#[tauri::command]
+async fn view_evaluate(
+    view_id: Valid<String, Uuid>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::ViewMatch>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .view_evaluate(&view_id)
+        .await
+        .map_err(UserMessage::wrap("view-evaluate-failed"))
+}
+
+/// Bookmark a command run, or a range of its output lines. Returns the
+/// bookmark with its permalink id.
+#[tauri::command]
+async fn bookmark_add(
+    command_id: Valid<String, Id>,
+    lines: Option<memory::LineRange>,
+    note: Valid<Option<String>>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::Bookmark, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .bookmark_add(&command_id, lines, note.into_inner())
+        .await
+        .map_err(UserMessage::wrap("bookmark-save-failed"))
+}
+
+/// A bookmark by permalink id, with its pinned excerpt
+#[tauri::command]
+async fn resolve_bookmark(
+    id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::ResolvedBookmark, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .resolve_bookmark(&id)
+        .await
+        .map_err(UserMessage::wrap("bookmark-read-failed"))?
+        .ok_or_else(|| UserMessage::new("bookmark-not-found").with("id", &*id))
+}
+
+#[tauri::command]
+async fn bookmark_list(
+    session_id: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<memory::Bookmark>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .bookmark_list(session_id.as_deref())
+        .await
+        .map_err(UserMessage::wrap("bookmark-read-failed"))
+}
+
+#[tauri::command]
+async fn bookmark_delete(
+    id: Valid<String, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<(), UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .bookmark_delete(&id)
+        .await
+        .map_err(UserMessage::wrap("bookmark-delete-failed"))
+}
+
+/// Periodically re-evaluate saved views and emit `view-updated` for new matches
+async fn watch_views(app: AppHandle, health: health::Component) {
+    let mut watcher = memory::ViewWatcher::new();
+    let mut interval = tokio::time::interval(VIEW_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        // The memory store is optional; skip this round if it is not running
+        let Ok(store) = open_memory_store(None, None).await else {
+            continue;
+        };
+        match watcher.poll(&store).await {
+            Ok(changes) => {
+                health.ok();
+                let dispatcher = window_dispatcher(&app);
+                for change in changes {
+                    dispatcher.dispatch(
+                        dispatch::Topic::Memory,
+                        None,
+                        dispatch::VIEW_UPDATED,
+                        &change,
+                    );
+                }
+            }
+            Err(e) => {
+                log::debug!("[views] Poll failed: {}", e);
+                health.error(format!("{:#}", e));
+            }
+        }
+    }
+}
+
+// ── Background services ───────────────────────────────────────────────────────
+
+/// Run a background service under the watchdog, which restarts it with backoff
+/// when it panics or stops
+fn spawn_supervised<F, Fut>(component: health::Component, task: F)
+where
+    F: Fn(health::Component) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    tauri::async_runtime::spawn(health::supervise(
+        component,
+        health::RestartPolicy::default(),
+        task,
+    ));
+}
+
+/// Evaluate alert rules on everything the stores write
+fn spawn_alerts(
+    service: &Arc<alerts::AlertService>,
+    events: &Arc<memory::EventBus>,
+    open_store: &memory::StoreOpener,
+    actions: alerts::AlertActions,
+    health: &health::HealthRegistry,
+) {
+    let (service, events, open_store, actions) = (
+        Arc::clone(service),
+        Arc::clone(events),
+        Arc::clone(open_store),
+        Arc::new(actions),
+    );
+    spawn_supervised(health.component("alerts"), move |component| {
+        component.ok();
+        Arc::clone(&service).serve(
+            events.subscribe(),
+            Arc::clone(&open_store),
+            Arc::clone(&actions),
+        )
+    });
+}
+
+/// Send `[webhooks.digest]` digests when they are due
+fn spawn_digest(
+    config: &webhooks::DigestConfig,
+    webhooks: &Arc<webhooks::WebhookSender>,
+    open_store: &memory::StoreOpener,
+    health: &health::HealthRegistry,
+) {
+    let (config, webhooks, open_store) =
+        (config.clone(), Arc::clone(webhooks), Arc::clone(open_store));
+    spawn_supervised(health.component("webhook_digest"), move |component| {
+        webhooks::run_digest_loop(
+            config.clone(),
+            Arc::clone(&webhooks),
+            Arc::clone(&open_store),
+            component,
+        )
+    });
+}
+
+/// Start the maintenance loops: backups, the artifact lifecycle, output
+/// retention (dropping summarized commands from `page_cache`) and the mirror
+fn spawn_maintenance(
+    config: &config::RunebookConfig,
+    open_store: &memory::StoreOpener,
+    backup: &Arc<backup::BackupScheduler>,
+    remote: Option<Arc<dyn storage::ObjectStore>>,
+    page_cache: Option<Arc<memory::OutputPageCache>>,
+    health: &health::HealthRegistry,
+) {
+    let backup = Arc::clone(backup);
+    spawn_supervised(health.component("backup"), move |component| {
+        Arc::clone(&backup).run_loop(component)
+    });
+    let (policy, open) = (config.storage.artifacts.clone(), Arc::clone(open_store));
+    spawn_supervised(health.component("artifact_lifecycle"), move |component| {
+        storage::run_lifecycle_loop(remote.clone(), policy.clone(), Arc::clone(&open), component)
+    });
+    let (retention, open) = (config.retention.clone(), Arc::clone(open_store));
+    spawn_supervised(health.component("retention"), move |component| {
+        memory::retention::run_retention_loop(
+            retention.clone(),
+            Arc::clone(&open),
+            page_cache.clone(),
+            component,
+        )
+    });
+    let (mirror, open) = (config.mirror.clone(), Arc::clone(open_store));
+    spawn_supervised(health.component("mirror"), move |component| {
+        memory::mirror::run_mirror_loop(mirror.clone(), Arc::clone(&open), component)
+    });
+}
+
+// ── External capture socket ───────────────────────────────────────────────────
+
+/// Run the capture socket used by shell hooks and editor integrations
+async fn serve_capture_socket(
+    open_store: memory::StoreOpener,
+    path: std::path::PathBuf,
+    live: Arc<memory::LiveOutputs>,
+    on_open: Option<ipc::OpenHandler>,
+    health: health::HealthRegistry,
+) {
+    let mut server = ipc::CaptureServer::new(path, open_store)
+        .with_live_outputs(live)
+        .with_health(health.clone());
+    if let Some(on_open) = on_open {
+        server = server.with_open_handler(on_open);
+    }
+    if let Err(e) = Arc::new(server).serve().await {
+        log::warn!("[ipc] Capture socket unavailable: {}", e);
+        health.component("capture_socket").down(format!("{:#}", e));
+    }
+}
+
+/// Compile the `[scrub]` rules. A broken user rule must not switch scrubbing
+/// off entirely, so it falls back to the built-in patterns.
+fn load_scrubber(config: &memory::ScrubConfig) -> memory::Scrubber {
+    let scrubber = memory::Scrubber::new(config).unwrap_or_else(|e| {
+        log::warn!("Custom scrub rules disabled: {:#}", e);
+        memory::Scrubber::new(&memory::ScrubConfig {
+            rules: Vec::new(),
+            ..config.clone()
+        })
+        .expect("built-in scrub rules compile")
+    });
+    for fixture in scrubber.check_fixtures().iter().filter(|f| !f.passed) {
+        log::warn!(
+            "Scrub rule {} fixture failed: {:?} became {:?}, expected {:?}",
+            fixture.rule,
+            fixture.input,
+            fixture.actual,
+            fixture.expected
+        );
+    }
+    scrubber
+}
+
+/// Memory store opener for background services, with the same quota guard,
+/// output encoding, auto-tagger, scrubber, ranking and event bus as
+/// `open_guarded_store`
+fn guarded_store_opener(
+    quota: Arc<memory::QuotaGuard>,
+    output_encoding: memory::EncodingConfig,
+    auto_tagger: Arc<memory::AutoTagger>,
+    scrubber: Arc<memory::Scrubber>,
+    ranking: memory::RankingConfig,
+    remote: Option<Arc<dyn storage::ObjectStore>>,
+    events: Arc<memory::EventBus>,
+) -> memory::StoreOpener {
+    memory::store_opener(move || {
+        let quota = Arc::clone(&quota);
+        let output_encoding = output_encoding.clone();
+        let auto_tagger = Arc::clone(&auto_tagger);
+        let scrubber = Arc::clone(&scrubber);
+        let ranking = ranking.clone();
+        let remote = remote.clone();
+        let events = Arc::clone(&events);
+        async move {
+            Ok(memory::open_configured_store()
+                .await?
+                .with_quota(quota)
+                .with_output_encoding(output_encoding)
+                .with_auto_tagger(auto_tagger)
+                .with_scrubber(scrubber)
+                .with_ranking(ranking)
+                .with_remote_store(remote)
+                .with_event_bus(events))
+        }
+    })
+}
+
+// ── Opening files and links (single instance, deep links) ───────────────────
+
+/// What RuneBook was launched with, until the frontend takes it
+struct PendingLaunch(Mutex<ipc::instance::LaunchRequest>);
+
+/// Handles `open` requests from later launches and the OS: brings the window
+/// forward and emits `open-request` with the files and links
+fn open_handler(app: AppHandle) -> ipc::OpenHandler {
+    Arc::new(move |request| {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+        // Only the main window opens launch files, so they are not opened twice
+        if !request.paths.is_empty() || !request.urls.is_empty() {
+            let _ = app.emit_to("main", "open-request", request);
+        }
+    })
+}
+
+/// Files and links RuneBook was launched with; returns them once
+#[tauri::command]
+fn take_launch_request(pending: tauri::State<'_, PendingLaunch>) -> ipc::instance::LaunchRequest {
+    std::mem::take(&mut *pending.0.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Validate a `runebook://` link and describe what it would do, for the
+/// confirmation prompt. Nothing is run or imported.
+#[tauri::command]
+fn deep_link_prepare(
+    url: Valid<String, Raw>,
+    deep_links: tauri::State<'_, Arc<deeplink::DeepLinkHandler>>,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+) -> Result<deeplink::DeepLinkPrompt, UserMessage> {
+    deep_links.prepare(&url, &execution)
+}
+
+/// Run the snippet named by a confirmed `runebook://snippet/...` link. The link
+/// is validated again; destructive snippets still need `confirmation_token`.
+#[tauri::command]
+async fn deep_link_run_snippet(
+    url: Valid<String, Raw>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+    deep_links: tauri::State<'_, Arc<deeplink::DeepLinkHandler>>,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+) -> Result<execution::CommandOutcome, UserMessage> {
+    let prompt = deep_links.prepare(&url, &execution)?;
+    let deeplink::DeepLink::RunSnippet { name, variables } = prompt.link else {
+        return Err(UserMessage::new("deep-link-invalid").with("error", "not a snippet link"));
+    };
+    let request = deep_links.snippet_request(&name, &variables)?;
+    let job = dispatcher.job_started(&request.command, "deep-link");
+    let outcome = execution
+        .execute(&request, confirmation_token.as_deref(), "deep-link")
+        .await;
+    dispatcher.job_finished(&job, outcome.as_ref().is_ok_and(|o| o.success));
+    outcome
+}
+
+/// Import the knowledge card from a confirmed `runebook://card?...` link as a suggestion
+#[tauri::command]
+async fn deep_link_import_card(
+    app: AppHandle,
+    url: Valid<String, Raw>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::Suggestion, UserMessage> {
+    let deep_links = app.state::<Arc<deeplink::DeepLinkHandler>>();
+    let execution = app.state::<Arc<execution::ExecutionService>>();
+    let prompt = deep_links.prepare(&url, &execution)?;
+    let deeplink::DeepLink::ImportCard { card } = prompt.link else {
+        return Err(UserMessage::new("deep-link-invalid").with("error", "not a card link"));
+    };
+    let suggestion = card.into_suggestion();
+    let store = open_guarded_store(&app, host.into_inner(), port).await?;
+    store
+        .persist_suggestion(suggestion.clone())
+        .await
+        .map_err(UserMessage::wrap("deep-link-import-failed"))?;
+    if let Err(e) = store
+        .surface_suggestions("window", vec![suggestion.clone()], 1, None)
+        .await
+    {
+        log::debug!("[ranking] Impression not recorded: {:#}", e);
+    }
+    window_dispatcher(&app).suggestion_added(&suggestion);
+    Ok(suggestion)
+}
+
+/// Benchmark a command: `spec.warmup` untimed runs, then `spec.runs` timed
+/// ones. The result is stored and compared with the previous benchmark of the
+/// same command line and directory.
+#[tauri::command]
+async fn benchmark_run(
+    app: AppHandle,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    spec: Valid<execution::BenchmarkSpec>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<execution::BenchmarkResult, UserMessage> {
+    let job = dispatcher.job_started(&spec.request.command, "user");
+    let result =
+        execution::run_benchmark(&execution, &spec, confirmation_token.as_deref(), "user").await;
+    dispatcher.job_finished(&job, result.is_ok());
+    let mut result = result?;
+    let store = open_guarded_store(&app, host.into_inner(), port).await?;
+    store
+        .record_benchmark(&mut result)
+        .await
+        .map_err(UserMessage::wrap("benchmark-save-failed"))?;
+    Ok(result)
+}
+
+/// Stored benchmarks, oldest first; only those of `signature` when set
+#[tauri::command]
+async fn benchmark_history(
+    signature: Valid<Option<String>, Raw>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<Vec<execution::BenchmarkResult>, UserMessage> {
+    let store = open_memory_store(host.into_inner(), port).await?;
+    store
+        .list_benchmarks(signature.as_deref())
+        .await
+        .map_err(UserMessage::wrap("benchmark-history-failed"))
+}
+
+/// Commands holding a `[concurrency]` slot, and those waiting for one
+#[tauri::command]
+fn execution_queue(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+) -> execution::QueueSnapshot {
+    execution.queue()
+}
+
+/// Run a command against a shadow of its working directory and report the
+/// files it would add, modify or delete there, leaving the real ones alone
+#[tauri::command]
+async fn sandbox_preview(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    spec: Valid<execution::SandboxSpec>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+) -> Result<execution::SandboxReport, UserMessage> {
+    let job = dispatcher.job_started(&spec.request.command, "user");
+    let report =
+        execution::run_sandboxed(&execution, &spec, confirmation_token.as_deref(), "user").await;
+    dispatcher.job_finished(&job, report.as_ref().is_ok_and(|r| r.outcome.success));
+    report
+}
+
+/// Run `request` as administrator: through sudo, asking for the password in
+/// a dialog window when sudo wants one, or through the UAC prompt on Windows.
+/// The run is recorded with provenance saying it was elevated.
+#[tauri::command]
+async fn execute_elevated(
+    app: AppHandle,
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    prompts: tauri::State<'_, Arc<execution::ElevationPrompts>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    request: Valid<execution::ExecutionRequest>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+) -> Result<execution::ElevatedRun, UserMessage> {
+    let (dialog_app, prompts) = (app.clone(), Arc::clone(&prompts));
+    let ask = |command_line: String| async move {
+        let (id, answer) = prompts.open(command_line);
+        if let Err(e) = open_elevation_dialog(&dialog_app, &prompts, &id) {
+            log::warn!("[elevation] Password dialog did not open: {:#}", e);
+            prompts.answer(&id, None);
+        }
+        let password = answer.await.ok().flatten();
+        if let Some(window) = dialog_app.get_webview_window(&format!("elevate-{}", id)) {
+            let _ = window.close();
+        }
+        password
+    };
+
+    let job = dispatcher.job_started(&request.command, "user");
+    let run = execution::run_elevated(
+        &execution,
+        &request,
+        confirmation_token.as_deref(),
+        "user",
+        execution::ElevationMethod::for_host(),
+        ask,
+    )
+    .await;
+    dispatcher.job_finished(&job, run.as_ref().is_ok_and(|r| r.outcome.success));
+    let run = run?;
+    let recorded = async {
+        let store = open_guarded_store(&app, None, None).await?;
+        execution::record_elevated(&store, &run, "user")
+            .await
+            .map_err(UserMessage::wrap("elevation-record-failed"))
+    };
+    if let Err(e) = recorded.await {
+        log::warn!(
+            "[elevation] Elevated run of {} not recorded: {}",
+            run.command_line,
+            e
+        );
+    }
+    Ok(run)
+}
+
+/// The dialog that asks for the password for prompt `id`, which it finds in
+/// its label. Only this window can read the prompt or answer it; closing it
+/// cancels the run.
+fn open_elevation_dialog(
+    app: &AppHandle,
+    prompts: &Arc<execution::ElevationPrompts>,
+    id: &str,
+) -> tauri::Result<()> {
+    let url = tauri::WebviewUrl::App("elevate".into());
+    let window = tauri::WebviewWindowBuilder::new(app, format!("elevate-{}", id), url)
+        .title("Run as administrator")
+        .inner_size(440.0, 240.0)
+        .resizable(false)
+        .always_on_top(true)
+        .center()
+        .focused(true)
+        .build()?;
+    let (prompts, id) = (Arc::clone(prompts), id.to_string());
+    window.on_window_event(move |event| {
+        if let tauri::WindowEvent::Destroyed = event {
+            prompts.answer(&id, None);
+        }
+    });
+    Ok(())
+}
+
+/// The command line the elevation dialog asks about. Only its own window may ask.
+#[tauri::command]
+fn elevation_prompt(
+    window: tauri::WebviewWindow,
+    prompts: tauri::State<'_, Arc<execution::ElevationPrompts>>,
+    id: Valid<String, Uuid>,
+) -> Result<String, UserMessage> {
+    if window.label() != format!("elevate-{}", *id) {
+        return Err(UserMessage::new("elevation-prompt-not-found").with("id", &*id));
+    }
+    prompts
+        .command_line(&id)
+        .ok_or_else(|| UserMessage::new("elevation-prompt-not-found").with("id", &*id))
+}
+
+/// Answer the elevation dialog: the password, or `None` to cancel
+#[tauri::command]
+fn elevation_respond(
+    window: tauri::WebviewWindow,
+    prompts: tauri::State<'_, Arc<execution::ElevationPrompts>>,
+    id: Valid<String, Uuid>,
+    password: Valid<Option<execution::Password>, Raw>,
+) -> Result<(), UserMessage> {
+    if window.label() != format!("elevate-{}", *id) || !prompts.answer(&id, password.into_inner()) {
+        return Err(UserMessage::new("elevation-prompt-not-found").with("id", &*id));
+    }
+    Ok(())
+}
+
+/// Run `spec.canvas` without executing anything: terminal nodes return their
+/// mocks, or the results recorded in `spec.recorded`
+#[tauri::command]
+async fn canvas_simulate(
+    spec: Valid<execution::SimulationSpec>,
+) -> Result<execution::CanvasRunReport, UserMessage> {
+    execution::simulate_canvas(&spec).await
+}
+
+/// Run `canvas` for real and save a fixture of the run to `fixture_path`
+#[tauri::command]
+async fn canvas_record(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    canvas: Valid<execution::CanvasDocument>,
+    fixture_path: Valid<String, FilePath>,
+    variables: Valid<Option<HashMap<String, String>>, Raw>,
+) -> Result<execution::CanvasFixture, UserMessage> {
+    let variables = variables.into_inner().unwrap_or_default();
+    let fixture = execution::record_canvas(&execution, &scrubber, &canvas, &variables).await?;
+    fixture
+        .save(std::path::Path::new(&fixture_path))
+        .map_err(UserMessage::wrap("fixture-write-failed"))?;
+    Ok(fixture)
+}
+
+/// Check a canvas (the fixture's own when `canvas` is unset) against a recorded fixture
+#[tauri::command]
+async fn canvas_replay(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    fixture_path: Valid<String, FilePath>,
+    canvas: Valid<Option<execution::CanvasDocument>>,
+    mode: Option<execution::ReplayMode>,
+) -> Result<execution::ReplayReport, UserMessage> {
+    let fixture = execution::CanvasFixture::load(std::path::Path::new(&fixture_path))
+        .map_err(UserMessage::wrap("fixture-read-failed"))?;
+    execution::replay_fixture(
+        &execution,
+        &scrubber,
+        &fixture,
+        canvas.as_ref(),
+        mode.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Check a canvas file for problems before it is run or shared
+#[tauri::command]
+fn canvas_lint(
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    path: Valid<String, FilePath>,
+    variables: Valid<Option<HashMap<String, String>>, Raw>,
+) -> Result<execution::LintReport, UserMessage> {
+    execution::load_canvas_file(std::path::Path::new(&path))
+        .and_then(|raw| {
+            execution::lint_canvas(&raw, &scrubber, &variables.into_inner().unwrap_or_default())
+        })
+        .map_err(UserMessage::wrap("canvas-lint-failed"))
+}
+
+/// Read a canvas file passed on the command line or forwarded by another launch
+#[tauri::command]
+async fn read_canvas_file(path: Valid<String, FilePath>) -> Result<String, UserMessage> {
+    tokio::fs::read_to_string(&path).await.map_err(|e| {
+        UserMessage::new("open-file-failed")
+            .with("path", &path)
+            .with("error", e)
+    })
+}
+
+// ── Windows ───────────────────────────────────────────────────────────────────
+
+/// Delivers dispatcher events to webview windows
+struct WebviewSink(AppHandle);
+
+impl dispatch::EventSink for WebviewSink {
+    fn windows(&self) -> Vec<String> {
+        self.0.webview_windows().into_keys().collect()
+    }
+
+    fn emit_to(
+        &self,
+        window: &str,
+        event: &str,
+        payload: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        Ok(self.0.emit_to(window, event, payload)?)
+    }
+}
+
+/// How often focus is checked for having ended (see `dispatch::surfaces`)
+const FOCUS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+fn window_dispatcher(app: &AppHandle) -> Arc<dispatch::WindowDispatcher> {
+    Arc::clone(app.state::<Arc<dispatch::WindowDispatcher>>().inner())
+}
+
+/// Limit which broadcast events the calling window receives
+#[tauri::command]
+fn window_subscribe(
+    window: tauri::WebviewWindow,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    filter: Valid<dispatch::WindowFilter>,
+) {
+    dispatcher.subscribe(window.label(), filter.into_inner());
+}
+
+/// Whether suggestions are being held back for focused work, and why
+#[tauri::command]
+fn focus_status(
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+) -> dispatch::FocusStatus {
+    dispatcher.focus_status()
+}
+
+/// Every event the backend dispatches, with its payload's version and
+/// examples, for tooling that checks a frontend against it
+#[tauri::command]
+fn list_event_schemas() -> Vec<dispatch::EventSchema> {
+    dispatch::EVENTS.to_vec()
+}
+
+/// Current session and running jobs, for a window catching up
+#[tauri::command]
+fn window_state(
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+) -> dispatch::SharedState {
+    dispatcher.snapshot()
+}
+
+/// Switch the current session in every window
+#[tauri::command]
+fn session_set_current(
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    session_id: Valid<Option<String>, Id>,
+) {
+    dispatcher.set_current_session(session_id.into_inner());
+}
+
+/// Open another RuneBook window. Returns its label.
+#[tauri::command]
+async fn open_window(app: AppHandle) -> Result<String, UserMessage> {
+    let label = format!("runebook-{}", uuid::Uuid::new_v4().simple());
+    tauri::WebviewWindowBuilder::new(&app, &label, tauri::WebviewUrl::default())
+        .title("RuneBook")
+        .inner_size(800.0, 600.0)
+        .build()
+        .map_err(UserMessage::wrap("window-open-failed"))?;
+    Ok(label)
+}
+
+// ── Upgrades ──────────────────────────────────────────────────────────────────
+
+/// Report from startup upgrade detection, once it has run
+#[derive(Default)]
+struct UpgradeState(Mutex<Option<upgrade::UpgradeReport>>);
+
+/// Compare this build with the store's version record and run light migrations
+async fn detect_upgrade(
+    open_store: memory::StoreOpener,
+    updates: upgrade::UpdateConfig,
+    config_migration: config::migrate::ConfigMigrationReport,
+) -> anyhow::Result<upgrade::UpgradeReport> {
+    let store = open_store().await?;
+    let report = upgrade::detect(
+        &store,
+        env!("CARGO_PKG_VERSION"),
+        &updates,
+        config_migration,
+    )
+    .await?;
+    for change in &report.changes {
+        log::info!("[upgrade] {}", change.summary);
+    }
+    Ok(report)
+}
+
+/// The startup upgrade report ("what changed for your data"), if detection has run
+#[tauri::command]
+fn upgrade_report(state: tauri::State<'_, UpgradeState>) -> Option<upgrade::UpgradeReport> {
+    state.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Run store migrations that were deferred at startup
+#[tauri::command]
+async fn upgrade_run_migrations(
+    app: AppHandle,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<memory::migration::MigrationStatus, UserMessage> {
+    let store = open_guarded_store(&app, host.into_inner(), port).await?;
+    let status = upgrade::run_deferred_migrations(&store)
+        .await
+        .map_err(UserMessage::wrap("migration-failed"))?;
+    if let Some(report) = app
+        .state::<UpgradeState>()
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+    {
+        report.store = status.clone();
+        report
+            .changes
+            .retain(|c| !c.action_required || c.area != upgrade::DataArea::Memory);
+    }
+    Ok(status)
+}
+
+// ── Backups ───────────────────────────────────────────────────────────────────
+
+/// Outcome of the last backup run, the destination, and when the next one is due
+#[tauri::command]
+fn backup_status(backup: tauri::State<'_, Arc<backup::BackupScheduler>>) -> backup::BackupStatus {
+    backup.status()
+}
+
+/// Take a backup now, whether or not scheduled backups are enabled
+#[tauri::command]
+async fn backup_run_now(
+    backup: tauri::State<'_, Arc<backup::BackupScheduler>>,
+) -> Result<backup::BackupRecord, UserMessage> {
+    backup
+        .run_now()
+        .await
+        .map_err(UserMessage::wrap("backup-failed"))
+}
+
+/// Snapshot the memory store and config to `destination`, streaming records
+/// into the archive with a `backup-export-progress` event after each chunk.
+/// With `part_mb` the snapshot is split into parts of about that many MiB,
+/// and an interrupted export resumes when called again with the same size.
+#[tauri::command]
+async fn backup_export(
+    app: AppHandle,
+    destination: Valid<String, FilePath>,
+    part_mb: Option<u64>,
+    operation_id: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<backup::ExportSummary, UserMessage> {
+    let operation_id = operation_id
+        .into_inner()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let store = open_memory_store(host.into_inner(), port).await?;
+    let dispatcher = window_dispatcher(&app);
+    let options = backup::ExportOptions {
+        part_bytes: part_mb.map(|mb| mb * 1024 * 1024),
+        ..Default::default()
+    };
+    store
+        .export_snapshot(
+            Some(&config::config_path()),
+            std::path::Path::new(&*destination),
+            &options,
+            &mut |progress| {
+                dispatcher.dispatch(
+                    dispatch::Topic::App,
+                    None,
+                    dispatch::BACKUP_EXPORT_PROGRESS,
+                    &dispatch::ExportProgressEvent {
+                        operation_id: operation_id.clone(),
+                        progress: progress.clone(),
+                    },
+                );
+            },
+        )
+        .await
+        .map_err(UserMessage::wrap("backup-export-failed"))
+}
+
+// ── Schedule ──────────────────────────────────────────────────────────────────
+
+/// Hours ahead `schedule_next_runs` looks by default
+const SCHEDULE_HORIZON_HOURS: u64 = 24 * 7;
+/// Hours ahead `schedule_ical` covers by default
+const ICAL_HORIZON_HOURS: u64 = 24 * 90;
+/// Furthest ahead either looks
+const MAX_HORIZON_HOURS: u64 = 24 * 366;
+
+fn schedule_horizon(hours: Option<u64>, default: u64) -> chrono::Duration {
+    chrono::Duration::hours(hours.unwrap_or(default).min(MAX_HORIZON_HOURS) as i64)
+}
+
+/// Upcoming backups and digests within `horizon_hours`, in time order
+#[tauri::command]
+fn schedule_next_runs(horizon_hours: Option<u64>) -> Vec<schedule::ScheduledRun> {
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    schedule::next_runs(
+        &schedule::configured_jobs(&config),
+        chrono::Utc::now(),
+        schedule_horizon(horizon_hours, SCHEDULE_HORIZON_HOURS),
+    )
+}
+
+/// The backup and digest schedule as an iCalendar feed
+#[tauri::command]
+fn schedule_ical(horizon_hours: Option<u64>) -> String {
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    schedule::to_ical(
+        &schedule::configured_jobs(&config),
+        chrono::Utc::now(),
+        schedule_horizon(horizon_hours, ICAL_HORIZON_HOURS),
+    )
+}
+
+// ── System health ─────────────────────────────────────────────────────────────
+
+/// Status of the background services, the storage backend and running jobs,
+/// for the status bar indicator
+#[tauri::command]
+async fn system_health(
+    health: tauri::State<'_, health::HealthRegistry>,
+    open_store: tauri::State<'_, memory::StoreOpener>,
+    watches: tauri::State<'_, Arc<execution::WatchManager>>,
+    debug: tauri::State<'_, Arc<execution::DebugManager>>,
+    live: tauri::State<'_, Arc<memory::LiveOutputs>>,
+) -> Result<health::SystemHealth, UserMessage> {
+    let jobs = health.component("jobs");
+    jobs.counter("watches", watches.list().len() as u64);
+    jobs.counter("debug_sessions", debug.session_count() as u64);
+    jobs.counter("running_commands", live.running_count() as u64);
+    jobs.ok();
+    health.probe_storage(open_store.inner()).await;
+    Ok(health.snapshot())
+}
+
+/// Record the frontend analyzers' health, keyed by analyzer name
+#[tauri::command]
+fn health_report_analyzers(
+    health: tauri::State<'_, health::HealthRegistry>,
+    analyzers: Valid<std::collections::BTreeMap<String, health::AnalyzerHealth>>,
+) {
+    health.report_analyzers(&analyzers);
+}
+
+// ── Onboarding ────────────────────────────────────────────────────────────────
+
+/// Setup wizard progress; `current_step` is the step to show
+#[tauri::command]
+fn onboarding_state(
+    onboarding: tauri::State<'_, Arc<onboarding::Onboarding>>,
+) -> onboarding::OnboardingState {
+    onboarding.state()
+}
+
+/// Record the answer to a setup step. A backend is only accepted once the
+/// memory store opens on it.
+#[tauri::command]
+async fn onboarding_complete_step(
+    onboarding: tauri::State<'_, Arc<onboarding::Onboarding>>,
+    answer: Valid<onboarding::StepAnswer>,
+) -> Result<onboarding::OnboardingState, UserMessage> {
+    if let onboarding::StepAnswer::Backend { host, port } = &*answer {
+        let data_dir = memory::MemoryConfig::configured().data_dir();
+        memory::init_memory_store(host, *port, &data_dir.to_string_lossy())
+            .await
+            .map_err(UserMessage::wrap("memory-store-unavailable"))?;
+    }
+    onboarding
+        .complete_step(answer.into_inner())
+        .map_err(UserMessage::wrap("onboarding-failed"))
+}
+
+/// Pass over an optional setup step
+#[tauri::command]
+fn onboarding_skip_step(
+    onboarding: tauri::State<'_, Arc<onboarding::Onboarding>>,
+    step: onboarding::OnboardingStep,
+) -> Result<onboarding::OnboardingState, UserMessage> {
+    onboarding
+        .skip_step(step)
+        .map_err(UserMessage::wrap("onboarding-failed"))
+}
+
+/// Start the setup wizard over from the first step
+#[tauri::command]
+fn onboarding_reset(
+    onboarding: tauri::State<'_, Arc<onboarding::Onboarding>>,
+) -> Result<onboarding::OnboardingState, UserMessage> {
+    onboarding
+        .reset()
+        .map_err(UserMessage::wrap("onboarding-failed"))
+}
+
+// ── Profiles ──────────────────────────────────────────────────────────────────
+
+/// Name of the profile this process runs
+#[tauri::command]
+fn profile_current() -> String {
+    profile::active().to_string()
+}
+
+#[tauri::command]
+fn profile_list() -> Result<Vec<profile::ProfileInfo>, UserMessage> {
+    profile::Profiles::default_location()
+        .list()
+        .map_err(UserMessage::wrap("profile-failed"))
+}
+
+/// Create an empty profile; switching to it starts the setup wizard
+#[tauri::command]
+fn profile_create(name: Valid<String, Id>) -> Result<profile::ProfileInfo, UserMessage> {
+    profile::Profiles::default_location()
+        .create(&name)
+        .map_err(UserMessage::wrap("profile-failed"))
+}
+
+/// Delete a profile and all of its data; not the default or the active one
+#[tauri::command]
+fn profile_delete(name: Valid<String, Id>) -> Result<(), UserMessage> {
+    profile::Profiles::default_location()
+        .delete(&name)
+        .map_err(UserMessage::wrap("profile-failed"))
+}
+
+/// Stop everything this process runs for its profile: terminals, PTY
+/// sessions, watches, followed logs, debug sessions and cancellable commands
+fn tear_down_sessions(app: &AppHandle) {
+    if let Ok(mut terminals) = app.state::<PtyState>().lock() {
+        for (_, mut session) in terminals.sessions.drain() {
+            let _ = session.child.kill();
+            let _ = session.child.wait();
+        }
+    }
+    let ptys = app.state::<Arc<execution::PtyManager>>();
+    for info in ptys.list() {
+        ptys.close(&info.id);
+    }
+    let watches = app.state::<Arc<execution::WatchManager>>();
+    for info in watches.list() {
+        watches.stop(&info.id);
+    }
+    let follows = app.state::<Arc<execution::LogFollowManager>>();
+    for info in follows.list() {
+        follows.stop(&info.id);
+    }
+    app.state::<Arc<execution::DebugManager>>().stop_all();
+    app.state::<Arc<execution::RunningCommands>>().cancel_all();
+}
+
+/// Switch to another profile. Its sessions are torn down and RuneBook starts
+/// again on the new profile, so every service is initialized from that
+/// profile's config; windows get a `profile-switching` event first.
+#[tauri::command]
+async fn profile_switch(
+    app: AppHandle,
+    tracer: tauri::State<'_, Arc<telemetry::Tracer>>,
+    name: Valid<String, Id>,
+) -> Result<(), UserMessage> {
+    if *name == profile::active() {
+        return Ok(());
+    }
+    if !profile::Profiles::default_location().exists(&name) {
+        return Err(UserMessage::new("profile-not-found").with("name", &*name));
+    }
+    let exe = std::env::current_exe().map_err(UserMessage::wrap("profile-switch-failed"))?;
+
+    window_dispatcher(&app).dispatch(
+        dispatch::Topic::App,
+        None,
+        dispatch::PROFILE_SWITCHING,
+        &dispatch::ProfileSwitching {
+            from: profile::active().to_string(),
+            to: (*name).clone(),
+        },
+    );
+    tear_down_sessions(&app);
+    if let Err(e) = tracer.flush().await {
+        log::warn!("[profile] Failed to flush traces before switching: {}", e);
+    }
+    std::process::Command::new(exe)
+        .args(["--profile", &name])
+        .spawn()
+        .map_err(UserMessage::wrap("profile-switch-failed"))?;
+    log::info!("Switching from profile {} to {}", profile::active(), &*name);
+    app.exit(0);
+    Ok(())
+}
+
+// ── Permissions ───────────────────────────────────────────────────────────────
+
+/// Check every invoke against the `[permissions]` policy before `handler` sees it
+fn permission_checked(
+    policy: permissions::PermissionPolicy,
+    handler: impl Fn(tauri::ipc::Invoke) -> bool + Send + Sync + 'static,
+) -> impl Fn(tauri::ipc::Invoke) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        if let Err(denied) = policy.check(invoke.message.command()) {
+            log::warn!("[permissions] Refused: {}", denied);
+            invoke.resolver.reject(denied);
+            return true;
+        }
+        handler(invoke)
+    }
+}
+
+/// Capability groups turned off in `[permissions]`, so the UI can hide what
+/// would be refused
+#[tauri::command]
+fn permissions_policy(
+    policy: tauri::State<'_, permissions::PermissionPolicy>,
+) -> permissions::PermissionPolicy {
+    policy.inner().clone()
+}
+
+// ── Validation ────────────────────────────────────────────────────────────────
+
+/// Commands take `Valid<T, R>` arguments to have them checked against the
+/// `[validation]` policy before the command body runs
+impl<'de, T, R, Rt> tauri::ipc::CommandArg<'de, Rt> for validation::Valid<T, R>
+where
+    T: serde::Deserialize<'de> + validation::Validate,
+    R: validation::Rule,
+    Rt: tauri::Runtime,
+{
+    fn from_command(
+        command: tauri::ipc::CommandItem<'de, Rt>,
+    ) -> Result<Self, tauri::ipc::InvokeError> {
+        let webview = command.message.webview();
+        let policy = webview.state::<validation::ValidationPolicy>();
+        let (name, argument) = (command.name, command.key);
+        if let tauri::ipc::InvokeBody::Json(body) = command.message.payload() {
+            if let Some(raw) = body.get(argument) {
+                policy
+                    .check_raw(argument, raw)
+                    .map_err(|e| invalid_argument(name, e))?;
+            }
+        }
+        let value = T::deserialize(command).map_err(tauri::ipc::InvokeError::from_error)?;
+        validation::Valid::new(value, argument, &policy).map_err(|e| invalid_argument(name, e))
+    }
+}
+
+fn invalid_argument(command: &str, error: validation::ValidationError) -> tauri::ipc::InvokeError {
+    log::warn!("[validation] Rejected {}: {}", command, error);
+    UserMessage::from(error).into()
+}
+
+// ── HTTP API ──────────────────────────────────────────────────────────────────
+
+/// API state accepting the `[server]` token and tokens from `runebook token mint`
+fn api_state(
+    token: String,
+    execution: Arc<execution::ExecutionService>,
+    open_store: memory::StoreOpener,
+) -> server::ApiState {
+    let tokens = server::rbac::TokenRegistry::new(server::rbac::TokenStore::default_path());
+    server::ApiState::new(execution, open_store, token).with_tokens(Arc::new(tokens))
+}
+
+/// Serve the HTTP API with the configured (or generated) bearer token, and
+/// the feeds of sessions shared in `shares`
+async fn serve_api(
+    config: server::ServerConfig,
+    execution: Arc<execution::ExecutionService>,
+    open_store: memory::StoreOpener,
+    shares: Arc<observe::ShareRegistry>,
+    live: Arc<memory::LiveOutputs>,
+    health: health::HealthRegistry,
+) -> anyhow::Result<()> {
+    let served = match config.resolve_token() {
+        Ok(token) => {
+            let state = api_state(token, execution, open_store)
+                .with_sharing(shares, live)
+                .with_health(health.clone());
+            server::serve(&config, state).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &served {
+        health.component("http_api").down(format!("{:#}", e));
+    }
+    served
+}
+
+/// Serve the gRPC service, sharing the HTTP API's token
+#[cfg(feature = "grpc")]
+async fn serve_grpc(
+    config: config::RunebookConfig,
+    execution: Arc<execution::ExecutionService>,
+    open_store: memory::StoreOpener,
+    health: health::HealthRegistry,
+) {
+    let served = match config.server.resolve_token() {
+        Ok(token) => {
+            let state = api_state(token, execution, open_store).with_health(health.clone());
+            grpc::serve(&config.grpc, state).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = served {
+        log::warn!("[grpc] gRPC service unavailable: {:#}", e);
+        health.component("grpc").down(format!("{:#}", e));
+    }
+}
+
+// ── Session artifacts (clipboard / screenshot) ───────────────────────────────
+
+#[cfg(feature = "capture")]
+fn capture_artifact(
+    kind: &str,
+    window_title: Option<&str>,
+) -> Result<memory::Artifact, UserMessage> {
+    let artifact = match kind {
+        "clipboard" => capture::capture_clipboard(),
+        _ => capture::capture_screenshot(window_title),
+    };
+    artifact.map_err(UserMessage::wrap("capture-failed"))
+}
+
+#[cfg(not(feature = "capture"))]
+fn capture_artifact(
+    _kind: &str,
+    _window_title: Option<&str>,
+) -> Result<memory::Artifact, UserMessage> {
+    Err(UserMessage::new("capture-unsupported"))
+}
+
+async fn attach_artifact(
+    app: AppHandle,
+    kind: &'static str,
+    window_title: Option<String>,
+    session_id: Option<String>,
+    command_id: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+) -> Result<String, UserMessage> {
+    let mut artifact = tauri::async_runtime::spawn_blocking(move || {
+        capture_artifact(kind, window_title.as_deref())
+    })
+    .await
+    .map_err(UserMessage::wrap("task-failed"))??;
+    artifact.session_id = session_id;
+    artifact.command_id = command_id;
+
+    let store = open_guarded_store(&app, host, port).await?;
+    let id = artifact.id.clone();
+    store
+        .store_artifact(artifact)
+        .await
+        .map_err(UserMessage::wrap("artifact-store-failed"))?;
+    Ok(id)
+}
+
+/// Attach the current clipboard content to a session/command. Returns the artifact id.
+#[tauri::command]
+async fn attach_clipboard(
+    app: AppHandle,
+    session_id: Valid<Option<String>, Id>,
+    command_id: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<String, UserMessage> {
+    attach_artifact(
+        app,
+        "clipboard",
+        None,
+        session_id.into_inner(),
+        command_id.into_inner(),
+        host.into_inner(),
+        port,
+    )
+    .await
+}
+
+/// Attach a window screenshot to a session/command. Returns the artifact id.
+#[tauri::command]
+async fn attach_screenshot(
+    app: AppHandle,
+    window_title: Valid<Option<String>>,
+    session_id: Valid<Option<String>, Id>,
+    command_id: Valid<Option<String>, Id>,
+    host: Valid<Option<String>, Id>,
+    port: Option<u16>,
+) -> Result<String, UserMessage> {
+    attach_artifact(
+        app,
+        "screenshot",
+        window_title.into_inner(),
+        session_id.into_inner(),
+        command_id.into_inner(),
+        host.into_inner(),
+        port,
+    )
+    .await
+}
+
+// ── Audit log ─────────────────────────────────────────────────────────────────
+
+/// Check the audit log's hash chain and report where it was tampered with
+#[tauri::command]
+async fn verify_audit_log(
+    audit_log: tauri::State<'_, Arc<audit::AuditLog>>,
+) -> Result<audit::AuditVerification, UserMessage> {
+    let audit_log = Arc::clone(audit_log.inner());
+    tauri::async_runtime::spawn_blocking(move || audit_log.verify())
+        .await
+        .map_err(UserMessage::wrap("task-failed"))?
+        .map_err(UserMessage::wrap("audit-verify-failed"))
+}
+
+// ── Execution receipts ────────────────────────────────────────────────────────
+
+/// A run together with its signed receipt
+#[derive(serde::Serialize, ts_rs::TS)]
+#[ts(export)]
+struct ReceiptedRun<T> {
+    result: T,
+    receipt: receipts::ExecutionReceipt,
+}
+
+/// [`execute_terminal_command`], returning the outcome (successful or not)
+/// with a signed receipt of what ran
+#[tauri::command]
+async fn execute_with_receipt(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    scrubber: tauri::State<'_, Arc<memory::Scrubber>>,
+    signer: tauri::State<'_, receipts::LazySigner>,
+    spec: Valid<execution::ExecutionRequest>,
+    confirmation_token: Valid<Option<String>, Uuid>,
+) -> Result<ReceiptedRun<execution::CommandOutcome>, UserMessage> {
+    let signer = signer
+        .get()
+        .map_err(UserMessage::wrap("receipt-sign-failed"))?;
+    let resolved = execution.preview(&spec)?;
+    let job = dispatcher.job_started(&spec.command, "user");
+    let outcome = execution
+        .execute(&spec, confirmation_token.as_deref(), "user")
+        .await;
+    dispatcher.job_finished(&job, outcome.as_ref().is_ok_and(|o| o.success));
+    let mut outcome = outcome?;
+    // Digests cover the output as produced; only the returned copy is scrubbed
+    let receipt = signer
+        .command_receipt(&resolved, &outcome, "user")
+        .map_err(UserMessage::wrap("receipt-sign-failed"))?;
+    scrub_for_display(&scrubber, &mut outcome);
+    Ok(ReceiptedRun {
+        result: outcome,
+        receipt,
+    })
+}
+
+/// Run every terminal node of `canvas` and sign a receipt covering all of them
+#[tauri::command]
+async fn canvas_run_with_receipt(
+    execution: tauri::State<'_, Arc<execution::ExecutionService>>,
+    dispatcher: tauri::State<'_, Arc<dispatch::WindowDispatcher>>,
+    signer: tauri::State<'_, receipts::LazySigner>,
+    canvas: Valid<execution::CanvasDocument>,
+    variables: Valid<Option<HashMap<String, String>>, Raw>,
+) -> Result<ReceiptedRun<execution::CanvasRunReport>, UserMessage> {
+    let signer = signer
+        .get()
+        .map_err(UserMessage::wrap("receipt-sign-failed"))?;
+    let variables = variables.into_inner().unwrap_or_default();
+    let label = if canvas.name.is_empty() {
+        &canvas.id
+    } else {
+        &canvas.name
+    };
+    let job = dispatcher.job_started(label, "user");
+    let report = execution::run_canvas(&execution, &canvas, &variables, "user").await;
+    dispatcher.job_finished(&job, report.as_ref().is_ok_and(|r| r.success));
+    let report = report?;
+    let nodes: HashMap<&str, &execution::canvas::CanvasNode> =
+        canvas.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    let receipt = signer
+        .canvas_receipt(&canvas, &report, "user", |node_id| {
+            let request = nodes.get(node_id)?.request(&variables)?;
+            execution.preview(&request).ok()
+        })
+        .map_err(UserMessage::wrap("receipt-sign-failed"))?;
+    Ok(ReceiptedRun {
+        result: report,
+        receipt,
+    })
+}
+
+/// Check a receipt's signature, optionally requiring it to come from `expected_key`
+#[tauri::command]
+fn receipt_verify(
+    receipt: Valid<receipts::ExecutionReceipt>,
+    expected_key: Valid<Option<String>, Id>,
+) -> Result<(), UserMessage> {
+    receipts::verify(&receipt, expected_key.as_deref())
+        .map_err(UserMessage::wrap("receipt-invalid"))
+}
+
+/// This machine's receipt signing key, to publish alongside exported receipts
+#[tauri::command]
+fn receipt_public_key(
+    signer: tauri::State<'_, receipts::LazySigner>,
+) -> Result<String, UserMessage> {
+    Ok(signer
+        .get()
+        .map_err(UserMessage::wrap("receipt-sign-failed"))?
+        .public_key())
+}
+
+// ── Telemetry ─────────────────────────────────────────────────────────────────
+
+/// Record a span produced by the frontend (e.g. analyzer runs) for OTLP export
+#[tauri::command]
+fn record_trace_span(
+    tracer: tauri::State<'_, Arc<telemetry::Tracer>>,
+    span: Valid<telemetry::Span>,
+) -> Result<(), UserMessage> {
+    tracer.record(span.into_inner());
+    Ok(())
+}
+
+/// Services shared by the desktop app and headless mode
+struct Services {
+    config: config::RunebookConfig,
+    quota: Arc<memory::QuotaGuard>,
+    auto_tagger: Arc<memory::AutoTagger>,
+    scrubber: Arc<memory::Scrubber>,
+    audit_log: Arc<audit::AuditLog>,
+    execution: Arc<execution::ExecutionService>,
+    tracer: Arc<telemetry::Tracer>,
+    open_store: memory::StoreOpener,
+    events: Arc<memory::EventBus>,
+    alerts: Arc<alerts::AlertService>,
+    webhooks: Arc<webhooks::WebhookSender>,
+    backup: Arc<backup::BackupScheduler>,
+    remote: Option<Arc<dyn storage::ObjectStore>>,
+    config_migration: config::migrate::ConfigMigrationReport,
+    health: health::HealthRegistry,
+}
+
+impl Services {
+    fn load() -> Self {
+        // Initialize logger (ignore error if already initialized)
+        let _ = env_logger::try_init();
+
+        // Bring an older config file up to the current format before reading it
+        let config_migration = config::migrate::migrate_file(&config::config_path())
+            .unwrap_or_else(|e| {
+                log::warn!("Config migration failed: {:#}", e);
+                Default::default()
+            });
+        let config = config::RunebookConfig::load_default().unwrap_or_else(|e| {
+            log::warn!("Using default configuration: {}", e);
+            config::RunebookConfig::default()
+        });
+        let quota = Arc::new(memory::QuotaGuard::new(config.quota.clone()));
+        let audit_log = Arc::new(audit::AuditLog::open_default());
+        let gate = Arc::new(execution::CommandGate::new(
+            config.safety.clone(),
+            Arc::clone(&audit_log),
+        ));
+        let auto_tagger = memory::AutoTagger::new(config.tags.clone()).unwrap_or_else(|e| {
+            log::warn!("Auto-tagging disabled: {}", e);
+            memory::AutoTagger::new(memory::AutoTagConfig {
+                enabled: false,
+                matchers: Vec::new(),
+                ..Default::default()
+            })
+            .expect("empty matcher set compiles")
+        });
+        let auto_tagger = Arc::new(auto_tagger);
+        let scrubber = Arc::new(load_scrubber(&config.scrub));
+        let remote = storage::configured_store(&config.storage).unwrap_or_else(|e| {
+            log::warn!("[storage] Object storage unavailable: {:#}", e);
+            None
+        });
+        let events = Arc::new(memory::EventBus::new());
+        let open_store = guarded_store_opener(
+            Arc::clone(&quota),
+            config.output.clone(),
+            Arc::clone(&auto_tagger),
+            Arc::clone(&scrubber),
+            config.ranking.clone(),
+            remote.clone(),
+            Arc::clone(&events),
+        );
+        let tracer = Arc::new(telemetry::Tracer::new(&config.telemetry));
+        let execution = Arc::new(
+            execution::ExecutionService::new(gate, config.profiles.clone())
+                .with_output_cap(config.command_output)
+                .with_concurrency(&config.concurrency)
+                .with_store(Arc::clone(&open_store))
+                .with_tracer(Arc::clone(&tracer)),
+        );
+        let alert_rules = alerts::AlertEngine::new(&config.alerts).unwrap_or_else(|e| {
+            log::warn!("Alert rules disabled: {:#}", e);
+            alerts::AlertEngine::new(&alerts::AlertConfig::default()).expect("no rules compile")
+        });
+        let alerts = Arc::new(alerts::AlertService::new(
+            alert_rules,
+            config::config_path(),
+        ));
+        let webhooks =
+            webhooks::WebhookSender::new(&config.webhooks, webhooks::DeliveryLog::open_default())
+                .unwrap_or_else(|e| {
+                    log::warn!("Webhooks disabled: {:#}", e);
+                    webhooks::WebhookSender::new(
+                        &webhooks::WebhookConfig::default(),
+                        webhooks::DeliveryLog::open_default(),
+                    )
+                    .expect("no hooks are valid")
+                });
+        let webhooks = Arc::new(webhooks);
+        let backup = Arc::new(backup::BackupScheduler::new(
+            config.backup.clone(),
+            Arc::clone(&open_store),
+        ));
+
+        Self {
+            config,
+            quota,
+            auto_tagger,
+            scrubber,
+            audit_log,
+            execution,
+            tracer,
+            open_store,
+            events,
+            alerts,
+            webhooks,
+            backup,
+            remote,
+            config_migration,
+            health: health::HealthRegistry::new(),
+        }
+    }
+}
+
+/// Run without a window: serves the HTTP API (and the capture socket and gRPC
+/// service, if enabled) until interrupted. The API is enabled regardless of
+/// `[server] enabled`.
+pub fn run_headless() {
+    let services = Services::load();
+    tauri::async_runtime::block_on(async move {
+        if let Err(e) = detect_upgrade(
+            Arc::clone(&services.open_store),
+            services.config.updates.clone(),
+            services.config_migration.clone(),
+        )
+        .await
+        {
+            log::warn!("[upgrade] Upgrade detection skipped: {:#}", e);
+        }
+        let tracer = Arc::clone(&services.tracer);
+        spawn_supervised(services.health.component("telemetry"), move |component| {
+            Arc::clone(&tracer).run_flush_loop(component)
+        });
+        spawn_maintenance(
+            &services.config,
+            &services.open_store,
+            &services.backup,
+            services.remote.clone(),
+            None,
+            &services.health,
+        );
+        spawn_alerts(
+            &services.alerts,
+            &services.events,
+            &services.open_store,
+            alerts::AlertActions::new(
+                Arc::clone(&services.execution),
+                None,
+                Arc::clone(&services.webhooks),
+            ),
+            &services.health,
+        );
+        spawn_digest(
+            &services.config.webhooks.digest,
+            &services.webhooks,
+            &services.open_store,
+            &services.health,
+        );
+        // Shared with the API, which serves the feeds of shared sessions
+        let live = Arc::new(memory::LiveOutputs::new());
+        if services.config.ipc.enabled {
+            let (open_store, path, live, health) = (
+                Arc::clone(&services.open_store),
+                services.config.ipc.socket_path(),
+                Arc::clone(&live),
+                services.health.clone(),
+            );
+            spawn_supervised(services.health.component("capture_socket"), move |_| {
+                serve_capture_socket(
+                    Arc::clone(&open_store),
+                    path.clone(),
+                    Arc::clone(&live),
+                    None,
+                    health.clone(),
+                )
+            });
+        }
+        #[cfg(feature = "grpc")]
+        if services.config.grpc.enabled {
+            let (config, execution, open_store, health) = (
+                services.config.clone(),
+                Arc::clone(&services.execution),
+                Arc::clone(&services.open_store),
+                services.health.clone(),
+            );
+            spawn_supervised(services.health.component("grpc"), move |_| {
+                serve_grpc(
+                    config.clone(),
+                    Arc::clone(&execution),
+                    Arc::clone(&open_store),
+                    health.clone(),
+                )
+            });
+        }
+        if let Err(e) = serve_api(
+            services.config.server.clone(),
+            services.execution,
+            services.open_store,
+            Arc::new(observe::ShareRegistry::new()),
+            live,
+            services.health,
+        )
+        .await
+        {
+            log::error!("[server] {:#}", e);
+            std::process::exit(1);
+        }
+    });
+}
+
+/// `runebook doctor [--json]`: report the running instance's health, or check
+/// what can be checked without it. Returns 1 unless everything is healthy.
+pub fn run_doctor_cli(args: &[String]) -> i32 {
+    let json = match args {
+        [] => false,
+        [flag] if flag == "--json" => true,
+        _ => {
+            eprintln!("Usage: runebook doctor [--json]");
+            return 2;
+        }
+    };
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let running = if config.ipc.enabled {
+        ipc::instance::request_running_instance(&config.ipc.socket_path(), &ipc::IpcRequest::Health)
+    } else {
+        Ok(None)
+    };
+    let report = match running {
+        Ok(Some(ipc::IpcResponse::Health { health })) => health,
+        Ok(Some(other)) => {
+            eprintln!("Unexpected response from the running instance: {:?}", other);
+            return 1;
+        }
+        not_running => {
+            if let Err(e) = not_running {
+                eprintln!("The running instance did not answer: {:#}", e);
+            }
+            let health = health::HealthRegistry::new();
+            health.component("app").disable(if config.ipc.enabled {
+                "RuneBook is not running; only storage and backups were checked"
+            } else {
+                "[ipc] is disabled, so the running instance cannot be asked; only storage and backups were checked"
+            });
+            let backups = health.component("backup");
+            if !config.backup.enabled {
+                backups.disable("Set [backup] enabled to schedule backups");
+            } else if let Some(error) =
+                backup::BackupStatus::load(&backup::status_path()).last_error
+            {
+                backups.error(error);
+            } else {
+                backups.ok();
+            }
+            let open_store = memory::store_opener(memory::open_configured_store);
+            tauri::async_runtime::block_on(health.probe_storage(&open_store));
+            health.snapshot()
+        }
+    };
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(text) => println!("{}", text),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    } else {
+        print!("{}", report.render());
+    }
+    i32::from(report.status != health::HealthStatus::Ok)
+}
+
+/// `runebook token mint|list|revoke`: manage workspace-scoped API tokens.
+/// Returns the process exit code.
+pub fn run_token_cli(args: &[String]) -> i32 {
+    let result = server::rbac::TokenStore::load(&server::rbac::TokenStore::default_path())
+        .and_then(|mut store| server::rbac::token_command(args, &mut store));
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("{:#}", e);
+            2
+        }
+    }
+}
+
+/// `runebook audit verify`: check the audit log's hash chain. Exits non-zero
+/// when the log was tampered with.
+pub fn run_audit_cli(args: &[String]) -> i32 {
+    if args.first().map(String::as_str) != Some("verify") {
+        eprintln!("Usage: runebook audit verify");
+        return 2;
+    }
+    match audit::AuditLog::open_default().verify() {
+        Ok(report) => {
+            println!(
+                "{}: {} entries ({} from before chaining)",
+                report.path.display(),
+                report.entries,
+                report.legacy_entries
+            );
+            for problem in &report.problems {
+                println!("line {}: {}", problem.line, problem.message);
+            }
+            if let Some(head) = &report.head_hash {
+                println!("head {}", head);
+            }
+            if report.is_intact() {
+                println!("Audit log intact");
+                0
+            } else {
+                println!("Audit log was modified");
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("{:#}", e);
+            2
+        }
+    }
+}
+
+/// `runebook backup run` takes a backup now, `runebook backup status` shows
+/// the last run, `runebook backup verify <file>` checks a backup archive
+/// against its manifest, and `runebook backup export <file>` writes a
+/// snapshot to `file`, split into parts with `--part-size <MiB>`.
+pub fn run_backup_cli(args: &[String]) -> i32 {
+    let scheduler = || {
+        let config = config::RunebookConfig::load_default().unwrap_or_default();
+        let open_store = memory::store_opener(memory::open_configured_store);
+        backup::BackupScheduler::new(config.backup, open_store)
+    };
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("run"), None) => match tauri::async_runtime::block_on(scheduler().run_now()) {
+            Ok(record) => {
+                println!(
+                    "Stored {} ({} records, {} bytes)",
+                    record.name, record.records, record.bytes
+                );
+                for name in &record.pruned {
+                    println!("Rotated out {}", name);
+                }
+                0
+            }
+            Err(e) => {
+                eprintln!("Backup failed: {:#}", e);
+                1
+            }
+        },
+        (Some("status"), None) => {
+            let status = scheduler().status();
+            println!(
+                "Scheduled backups: {}",
+                if status.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+            println!("Destination: {}", status.destination);
+            match &status.last_backup {
+                Some(last) => println!(
+                    "Last backup: {} ({} records, {} bytes)",
+                    last.name, last.records, last.bytes
+                ),
+                None => println!("Last backup: none"),
+            }
+            if let Some(error) = &status.last_error {
+                println!(
+                    "Last attempt failed ({} in a row): {}",
+                    status.consecutive_failures, error
+                );
+            }
+            if let Some(next) = status.next_run_at {
+                println!("Next backup: {}", next.to_rfc3339());
+            }
+            i32::from(status.last_error.is_some())
+        }
+        (Some("verify"), Some(file)) => match backup::verify_snapshot(std::path::Path::new(file)) {
+            Ok(manifest) => {
+                println!(
+                    "{}: intact, {} records from {} (RuneBook {})",
+                    file,
+                    manifest.records,
+                    manifest.created_at.to_rfc3339(),
+                    manifest.app_version
+                );
+                0
+            }
+            Err(e) => {
+                println!("{}: {:#}", file, e);
+                1
+            }
+        },
+        (Some("export"), Some(file)) => {
+            let mut options = backup::ExportOptions::default();
+            match args[2..] {
+                [] => {}
+                [ref flag, ref mb] if flag == "--part-size" => match mb.parse::<u64>() {
+                    Ok(mb) if mb > 0 => options.part_bytes = Some(mb * 1024 * 1024),
+                    _ => {
+                        eprintln!("--part-size takes a size in MiB, got {}", mb);
+                        return 2;
+                    }
+                },
+                _ => {
+                    eprintln!("Usage: runebook backup export <file> [--part-size <MiB>]");
+                    return 2;
+                }
+            }
+            let result = tauri::async_runtime::block_on(async {
+                let store = memory::open_configured_store().await?;
+                store
+                    .export_snapshot(
+                        Some(&config::config_path()),
+                        std::path::Path::new(file),
+                        &options,
+                        &mut |progress| {
+                            eprint!(
+                                "\r{}/{} records, {:.0} records/s",
+                                progress.records, progress.total, progress.records_per_second
+                            );
+                        },
+                    )
+                    .await
+            });
+            eprintln!();
+            match result {
+                Ok(summary) => {
+                    if summary.resumed {
+                        println!("Resumed an unfinished export");
+                    }
+                    println!(
+                        "Exported {} records to {} ({} bytes in {} part(s), {:.1} MiB/s)",
+                        summary.manifest.records,
+                        file,
+                        summary.archive_bytes,
+                        summary.parts.len(),
+                        summary.bytes_per_second / (1024.0 * 1024.0)
+                    );
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Export failed: {:#}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!(
+                "Usage: runebook backup run|status|verify <file>|export <file> [--part-size <MiB>]"
+            );
+            2
+        }
+    }
+}
+
+/// `runebook storage login <access-key-id>` stores S3 credentials (the secret
+/// is read from stdin) in the keychain; `runebook storage lifecycle` offloads
+/// and expires artifacts now, as `[storage.artifacts]` says.
+pub fn run_storage_cli(args: &[String]) -> i32 {
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("login"), Some(access_key_id)) => {
+            let mut secret = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut secret) {
+                eprintln!("Failed to read the secret access key: {}", e);
+                return 1;
+            }
+            let credentials = storage::S3Credentials {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret.trim().to_string(),
+            };
+            if credentials.secret_access_key.is_empty() {
+                eprintln!("No secret access key on stdin");
+                return 2;
+            }
+            match storage::credentials::save(&credentials) {
+                Ok(()) => {
+                    println!("Stored S3 credentials for {}", access_key_id);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    1
+                }
+            }
+        }
+        (Some("lifecycle"), None) => {
+            let config = config::RunebookConfig::load_default().unwrap_or_default();
+            let remote = match storage::configured_store(&config.storage) {
+                Ok(Some(remote)) => remote,
+                Ok(None) => {
+                    eprintln!("No bucket configured in [storage.s3]");
+                    return 2;
+                }
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    return 1;
+                }
+            };
+            let result = tauri::async_runtime::block_on(async {
+                let store = memory::open_configured_store().await?;
+                store
+                    .apply_artifact_lifecycle(
+                        remote.as_ref(),
+                        &config.storage.artifacts,
+                        chrono::Utc::now(),
+                    )
+                    .await
+            });
+            match result {
+                Ok(report) => {
+                    println!(
+                        "Offloaded {} artifacts ({} bytes) to {}, expired {}",
+                        report.offloaded,
+                        report.offloaded_bytes,
+                        remote.describe(),
+                        report.expired
+                    );
+                    i32::from(report.failed > 0)
+                }
+                Err(e) => {
+                    eprintln!("Artifact lifecycle failed: {:#}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("Usage: runebook storage login <access-key-id> | lifecycle");
+            2
+        }
+    }
+}
+
+/// `runebook webhooks secret <name>` stores the secret header value of a hook
+/// (read from stdin) in the keychain; `runebook webhooks log [name]` prints
+/// the newest deliveries, to one hook if named.
+pub fn run_webhooks_cli(args: &[String]) -> i32 {
+    match (args.first().map(String::as_str), args.get(1), args.len()) {
+        (Some("secret"), Some(name), 2) => {
+            let mut secret = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut secret) {
+                eprintln!("Failed to read the secret: {}", e);
+                return 1;
+            }
+            let secret = secret.trim();
+            if secret.is_empty() {
+                eprintln!("No secret on stdin");
+                return 2;
+            }
+            match webhooks::secrets::save(name, secret) {
+                Ok(()) => {
+                    println!("Stored the secret of webhook {}", name);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    1
+                }
+            }
+        }
+        (Some("log"), hook, 1 | 2) => {
+            let log = webhooks::DeliveryLog::open_default();
+            match log.recent(hook.map(String::as_str), None, 20) {
+                Ok(deliveries) => {
+                    for d in deliveries {
+                        println!(
+                            "{} {} {} {} after {} attempt(s){}",
+                            d.at.to_rfc3339(),
+                            d.hook,
+                            d.event,
+                            if d.delivered { "delivered" } else { "FAILED" },
+                            d.attempts,
+                            d.error.map(|e| format!(": {}", e)).unwrap_or_default()
+                        );
+                    }
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("Usage: runebook webhooks secret <name> | log [name]");
+            2
+        }
+    }
+}
+
+/// `runebook schedule [hours]` lists the backups and digests due within
+/// `hours` (a week by default); `runebook schedule ical [hours]` prints them
+/// as an iCalendar feed (90 days by default).
+pub fn run_schedule_cli(args: &[String]) -> i32 {
+    let (ical, hours) = match args.first().map(String::as_str) {
+        Some("ical") => (true, args.get(1)),
+        _ => (false, args.first()),
+    };
+    let hours = match hours.map(|h| h.parse::<u64>()).transpose() {
+        Ok(hours) if args.len() <= usize::from(ical) + 1 => hours,
+        _ => {
+            eprintln!("Usage: runebook schedule [ical] [hours]");
+            return 2;
+        }
+    };
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let jobs = schedule::configured_jobs(&config);
+    let now = chrono::Utc::now();
+    if ical {
+        print!(
+            "{}",
+            schedule::to_ical(&jobs, now, schedule_horizon(hours, ICAL_HORIZON_HOURS))
+        );
+        return 0;
+    }
+    if jobs.is_empty() {
+        println!("Nothing is scheduled; enable [backup] or [webhooks.digest]");
+        return 0;
+    }
+    for run in schedule::next_runs(&jobs, now, schedule_horizon(hours, SCHEDULE_HORIZON_HOURS)) {
+        println!("{} {} {}", run.at.to_rfc3339(), run.job, run.summary);
+    }
+    0
+}
+
+/// `runebook mirror [path]` refreshes the SQLite mirror of memory once, at
+/// `path` or where `[mirror]` puts it, whether or not the mirror is enabled.
+pub fn run_mirror_cli(args: &[String]) -> i32 {
+    if args.len() > 1 || args.first().is_some_and(|a| a.starts_with('-')) {
+        eprintln!("Usage: runebook mirror [path]");
+        return 2;
+    }
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let path = args
+        .first()
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| config.mirror.path());
+    let result = tauri::async_runtime::block_on(async {
+        let store = memory::open_configured_store().await?;
+        memory::mirror::sync_mirror(&store, path.clone()).await
+    });
+    match result {
+        Ok(report) => {
+            println!(
+                "Mirrored memory to {}: {} rows written, {} unchanged, {} deleted",
+                path.display(),
+                report.written,
+                report.unchanged,
+                report.deleted
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Mirror refresh failed: {:#}", e);
+            1
+        }
+    }
+}
+
+/// `runebook profile list|create|delete` manages profiles; `runebook --profile
+/// <name>` starts one. Returns the process exit code.
+pub fn run_profile_cli(args: &[String]) -> i32 {
+    let profiles = profile::Profiles::default_location();
+    let result = match (args.first().map(String::as_str), args.get(1), args.len()) {
+        (Some("list"), None, _) => profiles.list().map(|list| {
+            for profile in list {
+                let marker = if profile.active { "*" } else { " " };
+                println!("{} {:<20} {}", marker, profile.name, profile.path.display());
+            }
+        }),
+        (Some("create"), Some(name), 2) => profiles
+            .create(name)
+            .map(|profile| println!("Created {} in {}", name, profile.path.display())),
+        (Some("delete"), Some(name), 2) => {
+            profiles.delete(name).map(|()| println!("Deleted {}", name))
+        }
+        _ => {
+            eprintln!("Usage: runebook profile list | create <name> | delete <name>");
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            1
+        }
+    }
+}
+
+/// `runebook demo seed|wipe` adds the demo session and example canvas to
+/// memory, or removes them again; end-to-end tests seed before they run.
+pub fn run_demo_cli(args: &[String]) -> i32 {
+    let seed = match args.first().map(String::as_str) {
+        Some("seed") if args.len() == 1 => true,
+        Some("wipe") if args.len() == 1 => false,
+        _ => {
+            eprintln!("Usage: runebook demo seed|wipe");
+            return 2;
+        }
+    };
+    let result = tauri::async_runtime::block_on(async {
+        let store = memory::open_configured_store().await?;
+        let message = if seed {
+            let summary = store.seed_demo_data().await?;
+            format!(
+                "Seeded {} commands, {} errors, {} insights, {} suggestions and canvas {} in {}",
+                summary.commands,
+                summary.errors,
+                summary.insights,
+                summary.suggestions,
+                summary.canvas_id,
+                summary.workspace
+            )
+        } else {
+            let removed = store.wipe_demo_data().await?;
+            format!("Removed {} demo records", removed)
+        };
+        Ok::<_, anyhow::Error>(message)
+    });
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            0
+        }
+        Err(e) => {
+            eprintln!("Demo data failed: {:#}", e);
+            1
+        }
+    }
+}
+
+/// `runebook export-analytics <dir> [--range R] [--format parquet|csv]` writes
+/// the analytics tables for `R` (default "month") into `dir`.
+pub fn run_export_analytics_cli(args: &[String]) -> i32 {
+    let usage = || {
+        eprintln!("Usage: runebook export-analytics <dir> [--range R] [--format parquet|csv]");
+        2
+    };
+    let mut dir = None;
+    let mut range = "month".to_string();
+    let mut format = memory::AnalyticsFormat::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--range" => match iter.next() {
+                Some(value) => range = value.clone(),
+                None => return usage(),
+            },
+            "--format" => match iter.next().map(String::as_str) {
+                Some("parquet") => format = memory::AnalyticsFormat::Parquet,
+                Some("csv") => format = memory::AnalyticsFormat::Csv,
+                _ => return usage(),
+            },
+            _ if arg.starts_with('-') || dir.is_some() => return usage(),
+            _ => dir = Some(std::path::PathBuf::from(arg)),
+        }
+    }
+    let Some(dir) = dir else {
+        return usage();
+    };
+    let result = tauri::async_runtime::block_on(async {
+        let (from, to) = memory::stats::parse_range(&range, chrono::Utc::now())?;
+        let store = memory::open_configured_store().await?;
+        store.export_analytics(from, to, format, &dir).await
+    });
+    match result {
+        Ok(manifest) => {
+            for table in &manifest.tables {
+                println!("{}: {} rows", dir.join(&table.file).display(), table.rows);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Analytics export failed: {:#}", e);
+            1
+        }
+    }
+}
+
+/// `runebook ssh [ssh options] destination [command]` runs ssh through the
+/// capture proxy, so commands run on the remote host land in local memory.
+/// Returns ssh's exit code.
+pub fn run_ssh_cli(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("Usage: runebook ssh [ssh options] destination [command]");
+        return 2;
+    }
+    let result = tauri::async_runtime::block_on(async {
+        // ssh still runs when memory is unavailable, just without capture
+        let store = match memory::open_configured_store().await {
+            Ok(store) => Some(store),
+            Err(e) => {
+                eprintln!("runebook: not capturing this session: {:#}", e);
+                None
+            }
+        };
+        ssh::run(args, store).await
+    });
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("runebook ssh failed: {:#}", e);
+            255
+        }
+    }
+}
+
+/// Template variables given as `name=value` arguments
+fn cli_variables(args: &[String]) -> Option<HashMap<String, String>> {
+    let mut variables = HashMap::new();
+    for arg in args {
+        let Some((name, value)) = arg.split_once('=') else {
+            eprintln!("Expected name=value, got {}", arg);
+            return None;
+        };
+        variables.insert(name.to_string(), value.to_string());
+    }
+    Some(variables)
+}
+
+/// `runebook bench [--runs N] [--warmup N] [--ignore-failures] <command> [args]...`
+/// benchmarks a command in the current directory and compares it with the
+/// previous benchmark of the same command line.
+pub fn run_bench_cli(args: &[String]) -> i32 {
+    let usage = || {
+        eprintln!(
+            "Usage: runebook bench [--runs N] [--warmup N] [--ignore-failures] <command> [args]..."
+        );
+        2
+    };
+    let mut spec = execution::BenchmarkSpec {
+        request: execution::ExecutionRequest {
+            cwd: std::env::current_dir()
+                .ok()
+                .map(|d| d.display().to_string()),
+            ..Default::default()
+        },
+        runs: 10,
+        warmup: 1,
+        ignore_failures: false,
+    };
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--runs" | "--warmup" => {
+                let Some(count) = rest.next().and_then(|v| v.parse().ok()) else {
+                    return usage();
+                };
+                if arg == "--runs" {
+                    spec.runs = count;
+                } else {
+                    spec.warmup = count;
+                }
+            }
+            "--ignore-failures" => spec.ignore_failures = true,
+            _ if arg.starts_with("--") => return usage(),
+            _ => {
+                spec.request.command = arg.clone();
+                spec.request.args = rest.cloned().collect();
+                break;
+            }
+        }
+    }
+    if spec.request.command.is_empty() {
+        return usage();
+    }
+
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let gate = Arc::new(execution::CommandGate::new(
+        config.safety.clone(),
+        Arc::new(audit::AuditLog::open_default()),
+    ));
+    let execution = execution::ExecutionService::new(gate, config.profiles.clone());
+    let outcome = tauri::async_runtime::block_on(async {
+        let mut result = execution::run_benchmark(&execution, &spec, None, "cli").await?;
+        let saved = match memory::open_configured_store().await {
+            Ok(store) => store.record_benchmark(&mut result).await,
+            Err(e) => Err(e),
+        };
+        Ok::<_, UserMessage>((result, saved))
+    });
+    let (result, saved) = match outcome {
+        Ok(outcome) => outcome,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 1;
+        }
+    };
+
+    let stats = &result.stats;
+    println!("Benchmark: {}", result.command_line);
+    println!(
+        "  Time (mean ± σ):  {:.1} ms ± {:.1} ms",
+        stats.mean_ms, stats.stddev_ms
+    );
+    println!(
+        "  Median / range:   {:.1} ms, {:.1} ms … {:.1} ms ({} runs, {} warmup)",
+        stats.median_ms,
+        stats.min_ms,
+        stats.max_ms,
+        result.times_ms.len(),
+        result.warmup
+    );
+    if result.failures > 0 {
+        println!("  Failed runs:      {}", result.failures);
+    }
+    if !stats.outliers.is_empty() {
+        println!(
+            "  Warning: {} statistical outliers; close other programs or add warmup runs",
+            stats.outliers.len()
+        );
+    }
+    if let Some(comparison) = &result.comparison {
+        println!(
+            "  Versus {}: {:.2}x ({:?})",
+            comparison.previous_started_at.format("%Y-%m-%d %H:%M"),
+            comparison.ratio,
+            comparison.verdict
+        );
+    }
+    if let Err(e) = saved {
+        eprintln!("Benchmark not saved to memory: {:#}", e);
+    }
+    0
+}
+
+/// `runebook sandbox [--copy] <command> [args]...` runs a command against a
+/// shadow of the current directory and prints the changes it would make.
+pub fn run_sandbox_cli(args: &[String]) -> i32 {
+    let usage = || {
+        eprintln!("Usage: runebook sandbox [--copy] <command> [args]...");
+        2
+    };
+    let mut spec = execution::SandboxSpec {
+        request: execution::ExecutionRequest {
+            cwd: std::env::current_dir()
+                .ok()
+                .map(|d| d.display().to_string()),
+            ..Default::default()
+        },
+        strategy: execution::ShadowStrategy::Auto,
+        max_copy_bytes: execution::sandbox::DEFAULT_MAX_COPY_BYTES,
+    };
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--copy" => spec.strategy = execution::ShadowStrategy::Copy,
+            _ if arg.starts_with("--") => return usage(),
+            _ => {
+                spec.request.command = arg.clone();
+                spec.request.args = rest.cloned().collect();
+                break;
+            }
+        }
+    }
+    if spec.request.command.is_empty() {
+        return usage();
+    }
+
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let gate = Arc::new(execution::CommandGate::new(
+        config.safety.clone(),
+        Arc::new(audit::AuditLog::open_default()),
+    ));
+    let execution = execution::ExecutionService::new(gate, config.profiles.clone());
+    let report = match tauri::async_runtime::block_on(execution::run_sandboxed(
+        &execution, &spec, None, "cli",
+    )) {
+        Ok(report) => report,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 1;
+        }
+    };
+
+    for change in &report.changes {
+        match &change.diff {
+            Some(diff) => print!("{}", diff),
+            None => println!(
+                "{:?} {}{}",
+                change.kind,
+                change.path,
+                if change.binary { " (binary)" } else { "" }
+            ),
+        }
+    }
+    eprintln!(
+        "{}: exit {}, {} file(s) would change{} ({} shadow of {})",
+        report.command_line,
+        report
+            .outcome
+            .exit_code
+            .map_or("?".to_string(), |c| c.to_string()),
+        report.changes.len(),
+        if report.truncated {
+            ", more not listed"
+        } else {
+            ""
+        },
+        match report.strategy {
+            execution::ShadowStrategy::Overlay => "overlay",
+            _ => "copy",
+        },
+        report.cwd
+    );
+    0
+}
+
+fn load_canvas_document(path: &str) -> anyhow::Result<execution::CanvasDocument> {
+    let raw = execution::load_canvas_file(std::path::Path::new(path))?;
+    Ok(serde_json::from_value(raw)?)
+}
+
+/// `runebook fixture record <canvas> <fixture> [name=value]...` runs a canvas
+/// and saves a fixture of the run; `runebook fixture replay <fixture>
+/// [--canvas <file>] [--live]` checks a canvas against it and exits 1 on any
+/// difference.
+pub fn run_fixture_cli(args: &[String]) -> i32 {
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let scrubber = load_scrubber(&config.scrub);
+    let gate = Arc::new(execution::CommandGate::new(
+        config.safety.clone(),
+        Arc::new(audit::AuditLog::open_default()),
+    ));
+    let execution = execution::ExecutionService::new(gate, config.profiles.clone());
+    match args.first().map(String::as_str) {
+        Some("record") if args.len() >= 3 => {
+            let Some(variables) = cli_variables(&args[3..]) else {
+                return 2;
+            };
+            let canvas = match load_canvas_document(&args[1]) {
+                Ok(canvas) => canvas,
+                Err(e) => {
+                    eprintln!("{}: {:#}", args[1], e);
+                    return 2;
+                }
+            };
+            let fixture = tauri::async_runtime::block_on(execution::record_canvas(
+                &execution, &scrubber, &canvas, &variables,
+            ));
+            let result = fixture
+                .map_err(anyhow::Error::from)
+                .and_then(|f| f.save(std::path::Path::new(&args[2])).map(|()| f));
+            match result {
+                Ok(fixture) => {
+                    println!(
+                        "Recorded {} nodes of {} to {}",
+                        fixture.nodes.len(),
+                        canvas.id,
+                        args[2]
+                    );
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    1
+                }
+            }
+        }
+        Some("replay") if args.len() >= 2 => {
+            let mut canvas = None;
+            let mut mode = execution::ReplayMode::Mocked;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match (arg.as_str(), rest.clone().next()) {
+                    ("--live", _) => mode = execution::ReplayMode::Live,
+                    ("--canvas", Some(path)) => {
+                        rest.next();
+                        match load_canvas_document(path) {
+                            Ok(document) => canvas = Some(document),
+                            Err(e) => {
+                                eprintln!("{}: {:#}", path, e);
+                                return 2;
+                            }
+                        }
+                    }
+                    _ => {
+                        eprintln!(
+                            "Usage: runebook fixture replay <fixture> [--canvas <file>] [--live]"
+                        );
+                        return 2;
+                    }
+                }
+            }
+            let fixture = match execution::CanvasFixture::load(std::path::Path::new(&args[1])) {
+                Ok(fixture) => fixture,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    return 2;
+                }
+            };
+            let report = tauri::async_runtime::block_on(execution::replay_fixture(
+                &execution,
+                &scrubber,
+                &fixture,
+                canvas.as_ref(),
+                mode,
+            ));
+            match report {
+                Ok(report) => {
+                    for m in &report.mismatches {
+                        println!(
+                            "{} {}: expected {:?}, got {:?}",
+                            m.node_id, m.field, m.expected, m.actual
+                        );
+                    }
+                    if report.passed {
+                        println!("{}: {} nodes match", args[1], fixture.nodes.len());
+                    }
+                    i32::from(!report.passed)
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!(
+                "Usage: runebook fixture record <canvas> <fixture> [name=value]... | replay <fixture> [--canvas <file>] [--live]"
+            );
+            2
+        }
+    }
+}
+
+/// `runebook check <canvas> [--junit <file>] [--tap <file>] [name=value]...`
+/// runs a canvas as a check and writes a JUnit XML and/or TAP report of its
+/// nodes (`-` for stdout; TAP on stdout when neither is given); exits 1 when
+/// any node did not pass.
+pub fn run_check_cli(args: &[String]) -> i32 {
+    let usage = || {
+        eprintln!("Usage: runebook check <canvas> [--junit <file>] [--tap <file>] [name=value]...");
+        2
+    };
+    let Some(path) = args.first() else {
+        return usage();
+    };
+    let mut reports = Vec::new();
+    let mut assignments = Vec::new();
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let format = match arg.as_str() {
+            "--junit" => execution::TestReportFormat::Junit,
+            "--tap" => execution::TestReportFormat::Tap,
+            _ if arg.starts_with("--") => return usage(),
+            _ => {
+                assignments.push(arg.clone());
+                continue;
+            }
+        };
+        let Some(target) = rest.next() else {
+            return usage();
+        };
+        reports.push((format, std::path::PathBuf::from(target)));
+    }
+    if reports.is_empty() {
+        reports.push((execution::TestReportFormat::Tap, "-".into()));
+    }
+    let Some(variables) = cli_variables(&assignments) else {
+        return 2;
+    };
+    let canvas = match load_canvas_document(path) {
+        Ok(canvas) => canvas,
+        Err(e) => {
+            eprintln!("{}: {:#}", path, e);
+            return 2;
+        }
+    };
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let gate = Arc::new(execution::CommandGate::new(
+        config.safety.clone(),
+        Arc::new(audit::AuditLog::open_default()),
+    ));
+    let tracer = Arc::new(telemetry::Tracer::new(&config.telemetry));
+    let execution = execution::ExecutionService::new(gate, config.profiles.clone())
+        .with_concurrency(&config.concurrency)
+        .with_tracer(Arc::clone(&tracer));
+    let report = tauri::async_runtime::block_on(async {
+        let report = execution::run_canvas(&execution, &canvas, &variables, "cli").await;
+        if let Err(e) = tracer.flush().await {
+            log::warn!("[telemetry] {:#}", e);
+        }
+        report
+    });
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
+    let suite = if canvas.name.is_empty() {
+        &canvas.id
+    } else {
+        &canvas.name
+    };
+    for (format, target) in &reports {
+        if let Err(e) = format.write(&report, suite, target) {
+            eprintln!("{:#}", e);
+            return 2;
+        }
+    }
+    i32::from(!report.success)
+}
+
+/// `runebook lint <canvas> [name=value]...` prints the problems found in a
+/// canvas file; exits 1 when any is an error.
+pub fn run_lint_cli(args: &[String]) -> i32 {
+    let Some(path) = args.first() else {
+        eprintln!("Usage: runebook lint <canvas> [name=value]...");
+        return 2;
+    };
+    let Some(variables) = cli_variables(&args[1..]) else {
+        return 2;
+    };
+    let config = config::RunebookConfig::load_default().unwrap_or_default();
+    let scrubber = load_scrubber(&config.scrub);
+    let report = execution::load_canvas_file(std::path::Path::new(path))
+        .and_then(|raw| execution::lint_canvas(&raw, &scrubber, &variables));
+    match report {
+        Ok(report) => {
+            for finding in &report.findings {
+                let severity = match finding.severity {
+                    execution::LintSeverity::Error => "error",
+                    execution::LintSeverity::Warning => "warning",
+                };
+                println!("{}: {}", severity, finding.message);
+            }
+            if report.findings.is_empty() {
+                println!("{}: no problems found", path);
+            }
+            i32::from(report.has_errors())
+        }
+        Err(e) => {
+            eprintln!("{}: {:#}", path, e);
+            2
+        }
+    }
+}
+
+/// `runebook receipt verify <file> [--key <hex>]` checks an exported receipt;
+/// `runebook receipt key` prints this machine's public key.
+pub fn run_receipt_cli(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("key") => match receipts::ReceiptSigner::load_or_create() {
+            Ok(signer) => {
+                println!("{}", signer.public_key());
+                0
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                2
+            }
+        },
+        Some("verify") if args.len() == 2 || (args.len() == 4 && args[2] == "--key") => {
+            let receipt = std::fs::read_to_string(&args[1])
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Ok(serde_json::from_str(&json)?));
+            let receipt: receipts::ExecutionReceipt = match receipt {
+                Ok(receipt) => receipt,
+                Err(e) => {
+                    eprintln!("{}: {:#}", args[1], e);
+                    return 2;
+                }
+            };
+            match receipts::verify(&receipt, args.get(3).map(String::as_str)) {
+                Ok(()) => {
+                    println!(
+                        "Receipt {} is valid, signed by {}",
+                        receipt.body.id, receipt.signature.public_key
+                    );
+                    0
+                }
+                Err(e) => {
+                    println!("Receipt is not valid: {:#}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("Usage: runebook receipt verify <file> [--key <hex>] | runebook receipt key");
+            2
+        }
+    }
+}
+
+/// Ranking weights from a config file: its `[ranking]` section, or the whole
+/// file when it has none
+fn load_ranking_config(path: &str) -> anyhow::Result<memory::RankingConfig> {
+    let text = std::fs::read_to_string(path)?;
+    let mut value: toml::Table = toml::from_str(&text)?;
+    Ok(match value.remove("ranking") {
+        Some(section) => section.try_into()?,
+        None => toml::Value::Table(value).try_into()?,
+    })
+}
+
+/// `runebook rank-eval <weights.toml>...` replays recorded suggestion
+/// outcomes against each file's ranking weights and compares acceptance with
+/// the configured ones.
+pub fn run_rank_eval_cli(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("Usage: runebook rank-eval <weights.toml>...");
+        return 2;
+    }
+    let mut alternatives = Vec::new();
+    for path in args {
+        let name = std::path::Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.clone());
+        match load_ranking_config(path) {
+            Ok(config) => alternatives.push((name, config)),
+            Err(e) => {
+                eprintln!("{}: {:#}", path, e);
+                return 2;
+            }
+        }
+    }
+    let ranking = config::RunebookConfig::load_default()
+        .map(|c| c.ranking)
+        .unwrap_or_default();
+    let report = tauri::async_runtime::block_on(async move {
+        memory::open_configured_store()
+            .await?
+            .with_ranking(ranking)
+            .rank_eval(&alternatives)
+            .await
+    });
+    match report {
+        Ok(report) => {
+            println!(
+                "{} impressions: {} accepted, {} dismissed",
+                report.impressions, report.accepted, report.dismissed
+            );
+            println!(
+                "{:<20} {:>10} {:>8} {:>10} {:>6}",
+                "config", "accepted", "delta", "dismissed", "mrr"
+            );
+            for result in &report.results {
+                println!(
+                    "{:<20} {:>9.1}% {:>+7.1}% {:>9.1}% {:>6.3}",
+                    result.name,
+                    result.acceptance_rate * 100.0,
+                    result.acceptance_delta * 100.0,
+                    result.dismissed_rate * 100.0,
+                    result.mean_reciprocal_rank
+                );
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{:#}", e);
+            2
+        }
+    }
+}
+
+/// `runebook codec-bench [--rounds <n>] [file]...` compresses each file, or a
+/// built-in sample of typical terminal output, with every output codec and
+/// prints ratio and throughput, to help choose `[output] codec`.
+pub fn run_codec_bench_cli(args: &[String]) -> i32 {
+    let mut rounds = 5;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rounds" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => rounds = n,
+                None => {
+                    eprintln!("Usage: runebook codec-bench [--rounds <n>] [file]...");
+                    return 2;
+                }
+            },
+            _ => files.push(arg.clone()),
+        }
+    }
+    let mut samples = Vec::new();
+    if files.is_empty() {
+        samples.push((
+            "sample".to_string(),
+            memory::codec::sample_output(1024 * 1024),
+        ));
+    }
+    for path in files {
+        match std::fs::read(&path) {
+            Ok(bytes) => samples.push((path, bytes)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return 2;
+            }
+        }
+    }
+    for (name, sample) in &samples {
+        let results = match memory::codec::benchmark_codecs(sample, rounds) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("{}: {:#}", name, e);
+                return 1;
+            }
+        };
+        println!("{} ({} bytes, {} rounds)", name, sample.len(), rounds);
+        println!(
+            "{:<8} {:>12} {:>8} {:>14} {:>16}",
+            "codec", "compressed", "ratio", "compress MiB/s", "decompress MiB/s"
+        );
+        for result in &results {
+            println!(
+                "{:<8} {:>12} {:>7.2}x {:>14.1} {:>16.1}",
+                result.codec.as_str(),
+                result.compressed_bytes,
+                result.ratio,
+                result.compress_mib_s,
+                result.decompress_mib_s
+            );
+        }
+    }
+    0
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let _ = env_logger::try_init();
+
+    // A second launch hands its files to the running instance instead of
+    // starting another app on the same data directory. This comes before the
+    // config is loaded, so the second launch never migrates or rewrites it.
+    let launch = ipc::instance::LaunchRequest::from_args(profile::launch_args());
+    let ipc_config = ipc::IpcConfig::load(&config::config_path());
+    if ipc_config.enabled {
+        match ipc::instance::forward_to_running_instance(&ipc_config.socket_path(), &launch) {
+            Ok(true) => {
+                log::info!("RuneBook is already running; forwarded the open request");
+                return;
+            }
+            Ok(false) => {}
+            Err(e) => log::warn!("[ipc] Single-instance hand-off failed: {}", e),
+        }
+    }
+
+    let Services {
+        config,
+        quota,
+        auto_tagger,
+        scrubber,
+        audit_log,
+        execution,
+        tracer,
+        open_store,
+        events,
+        alerts,
+        webhooks,
+        backup,
+        remote,
+        config_migration,
+        health,
+    } = Services::load();
+
+    let page_cache = Arc::new(memory::OutputPageCache::new());
+    let policy = permissions::PermissionPolicy::new(&config.permissions);
+    let validation = validation::ValidationPolicy::new(&config.validation);
+
+    tauri::Builder::default()
+        .manage(Arc::new(Mutex::new(PtyManager::new())) as PtyState)
+        .manage(Arc::clone(&tracer))
+        .manage(Arc::clone(&quota))
+        .manage(config.output.clone())
+        .manage(config.ranking.clone())
+        .manage(auto_tagger)
+        .manage(Arc::clone(&scrubber))
+        .manage(Arc::clone(&page_cache))
+        .manage(Arc::new(memory::LiveOutputs::new()))
+        .manage(Arc::new(observe::ShareRegistry::new()))
+        .manage(Arc::new(observe::client::Observations::new()))
+        .manage(receipts::LazySigner::new(Arc::clone(&audit_log)))
+        .manage(audit_log)
+        .manage(Arc::clone(&execution))
+        .manage(Arc::new(execution::RunningCommands::new()))
+        .manage(Arc::new(execution::ElevationPrompts::default()))
+        .manage(Arc::new(
+            execution::WatchManager::new().with_resume_on_wake(config.power.resume_watches),
+        ))
+        .manage(Arc::new(execution::LogFollowManager::new()))
+        .manage(Arc::new(execution::PtyManager::new()))
+        .manage(Arc::new(execution::DebugManager::new()))
+        .manage(Arc::clone(&backup))
+        .manage(Arc::clone(&open_store))
+        .manage(Arc::clone(&events))
+        .manage(Arc::clone(&alerts))
+        .manage(Arc::clone(&webhooks))
+        .manage(health.clone())
+        .manage(policy.clone())
+        .manage(validation)
+        .manage(Arc::new(onboarding::Onboarding::open(
+            onboarding::Onboarding::default_path(),
+        )))
+        .manage(RemoteStore(remote.clone()))
+        .manage(PendingLaunch(Mutex::new(launch)))
+        .manage(UpgradeState::default())
+        .manage(Arc::new(deeplink::DeepLinkHandler::new(
+            config.deep_links.clone(),
+            config.snippets.clone(),
+        )))
+        .setup(move |app| {
+            app.manage(Arc::new(
+                dispatch::WindowDispatcher::new(Arc::new(WebviewSink(app.handle().clone())))
+                    .with_focus(config.focus.clone()),
+            ));
+
+            // `runebook://` links: macOS delivers them as events; elsewhere the
+            // OS starts RuneBook with the link as an argument (see LaunchRequest)
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+                if let Err(e) = app.deep_link().register_all() {
+                    log::warn!("Failed to register the runebook:// scheme: {}", e);
+                }
+                let on_open = open_handler(app.handle().clone());
+                app.deep_link().on_open_url(move |event| {
+                    on_open(ipc::instance::LaunchRequest {
+                        paths: Vec::new(),
+                        urls: event.urls().iter().map(|u| u.to_string()).collect(),
+                    });
+                });
+            }
+
+            spawn_supervised(health.component("telemetry"), move |component| {
+                Arc::clone(&tracer).run_flush_loop(component)
+            });
+            spawn_supervised(health.component("power"), |component| {
+                power::monitor().run(component)
+            });
+            let handle = app.handle().clone();
+            spawn_supervised(health.component("views"), move |component| {
+                watch_views(handle.clone(), component)
+            });
+            spawn_maintenance(
+                &config,
+                &open_store,
+                &backup,
+                remote,
+                Some(page_cache),
+                &health,
+            );
+
+            // Release suggestions held back during focus once commands stop
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(FOCUS_POLL_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    window_dispatcher(&handle).resume_suggestions();
+                }
+            });
+
+            // Upgrade detection needs the store; the report waits in
+            // `UpgradeState` and is announced with `upgrade-report`
+            let handle = app.handle().clone();
+            let detection = detect_upgrade(
+                Arc::clone(&open_store),
+                config.updates.clone(),
+                config_migration,
+            );
+            tauri::async_runtime::spawn(async move {
+                match detection.await {
+                    Ok(report) => {
+                        let notable = report.is_notable();
+                        *handle
+                            .state::<UpgradeState>()
+                            .0
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner()) = Some(report.clone());
+                        if notable {
+                            window_dispatcher(&handle).dispatch(
+                                dispatch::Topic::App,
+                                None,
+                                dispatch::UPGRADE_REPORT,
+                                &report,
+                            );
+                        }
+                    }
+                    Err(e) => log::warn!("[upgrade] Upgrade detection skipped: {:#}", e),
+                }
+            });
+            if config.ipc.enabled {
+                let (open, path, live, on_open, registry) = (
+                    Arc::clone(&open_store),
+                    config.ipc.socket_path(),
+                    Arc::clone(app.state::<Arc<memory::LiveOutputs>>().inner()),
+                    open_handler(app.handle().clone()),
+                    health.clone(),
+                );
+                spawn_supervised(health.component("capture_socket"), move |_| {
+                    serve_capture_socket(
+                        Arc::clone(&open),
+                        path.clone(),
+                        Arc::clone(&live),
+                        Some(Arc::clone(&on_open)),
+                        registry.clone(),
+                    )
+                });
+            } else {
+                health
+                    .component("capture_socket")
+                    .disable("Set [ipc] enabled to accept shell hooks and editor integrations");
+            }
+            if config.server.enabled {
+                let (server, execution, open, shares, live, registry) = (
+                    config.server.clone(),
+                    Arc::clone(&execution),
+                    Arc::clone(&open_store),
+                    Arc::clone(app.state::<Arc<observe::ShareRegistry>>().inner()),
+                    Arc::clone(app.state::<Arc<memory::LiveOutputs>>().inner()),
+                    health.clone(),
+                );
+                spawn_supervised(health.component("http_api"), move |_| {
+                    let api = serve_api(
+                        server.clone(),
+                        Arc::clone(&execution),
+                        Arc::clone(&open),
+                        Arc::clone(&shares),
+                        Arc::clone(&live),
+                        registry.clone(),
+                    );
+                    async move {
+                        if let Err(e) = api.await {
+                            log::warn!("[server] HTTP API unavailable: {:#}", e);
+                        }
+                    }
+                });
+            } else {
+                health
+                    .component("http_api")
+                    .disable("Set [server] enabled to serve the HTTP API");
+            }
+            #[cfg(feature = "grpc")]
+            if config.grpc.enabled {
+                let (config, execution, open, registry) = (
+                    config.clone(),
+                    Arc::clone(&execution),
+                    Arc::clone(&open_store),
+                    health.clone(),
+                );
+                spawn_supervised(health.component("grpc"), move |_| {
+                    serve_grpc(
+                        config.clone(),
+                        Arc::clone(&execution),
+                        Arc::clone(&open),
+                        registry.clone(),
+                    )
+                });
+            } else {
+                health
+                    .component("grpc")
+                    .disable("Set [grpc] enabled to serve the gRPC API");
+            }
+
+            spawn_alerts(
+                &alerts,
+                &events,
+                &open_store,
+                alerts::AlertActions::new(
+                    Arc::clone(&execution),
+                    Some(window_dispatcher(app.handle())),
+                    Arc::clone(&webhooks),
+                ),
+                &health,
+            );
+            spawn_digest(&config.webhooks.digest, &webhooks, &open_store, &health);
+
+            // Forward quota decisions to the UI as `memory-quota` events
+            let handle = app.handle().clone();
+            let mut quota_events = quota.subscribe();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    match quota_events.recv().await {
+                        Ok(event) => {
+                            window_dispatcher(&handle).dispatch(
+                                dispatch::Topic::Memory,
+                                None,
+                                dispatch::MEMORY_QUOTA,
+                                &event,
+                            );
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                if let Some(dispatcher) = window.try_state::<Arc<dispatch::WindowDispatcher>>() {
+                    dispatcher.unsubscribe(window.label());
+                }
+            }
+        })
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![
-            greet,
-            memory_inspect,
-            spawn_terminal,
-            write_terminal,
-            resize_terminal,
-            kill_terminal
-        ])
+        .plugin(tauri_plugin_deep_link::init())
+        .invoke_handler(permission_checked(
+            policy,
+            tauri::generate_handler![
+                greet,
+                execute_terminal_command,
+                execute_terminal_command_streaming,
+                execute_pipeline,
+                cancel_command,
+                send_signal,
+                preview_execution,
+                execute_dry_run,
+                watch_start,
+                watch_stop,
+                watch_list,
+                logs_follow,
+                logs_stop,
+                logs_list,
+                alert_rules_list,
+                alert_rules_save,
+                webhook_deliveries,
+                pty_start,
+                pty_write,
+                pty_resize,
+                pty_screen,
+                pty_close,
+                pty_list,
+                canvas_debug_start,
+                canvas_debug_command,
+                canvas_debug_inspect,
+                backup_status,
+                backup_run_now,
+                backup_export,
+                schedule_next_runs,
+                schedule_ical,
+                system_health,
+                health_report_analyzers,
+                onboarding_state,
+                onboarding_complete_step,
+                onboarding_skip_step,
+                onboarding_reset,
+                profile_current,
+                profile_list,
+                profile_create,
+                profile_delete,
+                profile_switch,
+                scrub_preview,
+                memory_inspect,
+                spawn_terminal,
+                write_terminal,
+                resize_terminal,
+                kill_terminal,
+                archive_create,
+                archive_extract,
+                attach_clipboard,
+                attach_screenshot,
+                record_trace_span,
+                memory_usage_report,
+                reindex_memory,
+                time_report,
+                export_analytics,
+                history_import,
+                handoff_export,
+                handoff_import,
+                share_session_start,
+                share_session_stop,
+                share_revoke,
+                share_list,
+                observe_session,
+                observe_stop,
+                demo_seed,
+                demo_wipe,
+                tag_add,
+                tag_remove,
+                tag_list,
+                memory_list_sessions,
+                memory_recent_errors,
+                diagnostics_for_path,
+                memory_list_artifacts,
+                memory_get_artifact,
+                read_output_page,
+                suggestion_feedback,
+                explain_suggestion,
+                preferences_get,
+                preferences_set,
+                preferences_reset,
+                suggestion_mute,
+                rank_eval,
+                read_output_tail,
+                tail_output,
+                output_line_count,
+                command_objects,
+                view_save,
+                view_delete,
+                view_list,
+                view_evaluate,
+                bookmark_add,
+                resolve_bookmark,
+                bookmark_list,
+                bookmark_delete,
+                take_launch_request,
+                read_canvas_file,
+                canvas_lint,
+                canvas_simulate,
+                benchmark_run,
+                benchmark_history,
+                execution_queue,
+                sandbox_preview,
+                execute_elevated,
+                elevation_prompt,
+                elevation_respond,
+                canvas_record,
+                canvas_replay,
+                deep_link_prepare,
+                deep_link_run_snippet,
+                deep_link_import_card,
+                upgrade_report,
+                upgrade_run_migrations,
+                verify_audit_log,
+                execute_with_receipt,
+                canvas_run_with_receipt,
+                receipt_verify,
+                receipt_public_key,
+                window_subscribe,
+                window_state,
+                focus_status,
+                list_event_schemas,
+                session_set_current,
+                open_window,
+                permissions_policy
+            ],
+        ))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -2,5 +2,53 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
-    runebook_lib::run()
+    let mut args: Vec<String> = std::env::args().collect();
+    // `--profile <name>` picks the profile for subcommands and the app alike
+    if let Err(e) = runebook_lib::profile::select_from_args(&mut args) {
+        eprintln!("{:#}", e);
+        std::process::exit(2);
+    }
+    // `token ...` manages API tokens; `audit verify` checks the audit log;
+    // `receipt ...` verifies execution receipts; `rank-eval` compares ranking weights;
+    // `codec-bench` compares output compression codecs;
+    // `backup ...` runs, reports on, verifies, and exports backups; `lint` checks a canvas file;
+    // `check` runs a canvas and writes JUnit/TAP reports of its nodes; `fixture ...` records and replays canvas regression fixtures; `mirror`
+    // refreshes the SQLite mirror of memory; `export-analytics` writes Parquet/CSV tables;
+    // `bench` benchmarks a command; `sandbox` previews the files a command would
+    // change; `doctor` reports the health of every subsystem;
+    // `demo` seeds or wipes the demo data; `profile` lists, creates and deletes profiles;
+    // `ssh` runs ssh with the remote session captured into memory; `webhooks`
+    // stores hook secrets and shows the delivery log; `schedule` lists upcoming
+    // backups and digests, or exports them as iCalendar
+    match args.get(1).map(String::as_str) {
+        Some("token") => std::process::exit(runebook_lib::run_token_cli(&args[2..])),
+        Some("audit") => std::process::exit(runebook_lib::run_audit_cli(&args[2..])),
+        Some("receipt") => std::process::exit(runebook_lib::run_receipt_cli(&args[2..])),
+        Some("rank-eval") => std::process::exit(runebook_lib::run_rank_eval_cli(&args[2..])),
+        Some("codec-bench") => std::process::exit(runebook_lib::run_codec_bench_cli(&args[2..])),
+        Some("backup") => std::process::exit(runebook_lib::run_backup_cli(&args[2..])),
+        Some("storage") => std::process::exit(runebook_lib::run_storage_cli(&args[2..])),
+        Some("lint") => std::process::exit(runebook_lib::run_lint_cli(&args[2..])),
+        Some("check") => std::process::exit(runebook_lib::run_check_cli(&args[2..])),
+        Some("fixture") => std::process::exit(runebook_lib::run_fixture_cli(&args[2..])),
+        Some("mirror") => std::process::exit(runebook_lib::run_mirror_cli(&args[2..])),
+        Some("bench") => std::process::exit(runebook_lib::run_bench_cli(&args[2..])),
+        Some("sandbox") => std::process::exit(runebook_lib::run_sandbox_cli(&args[2..])),
+        Some("doctor") => std::process::exit(runebook_lib::run_doctor_cli(&args[2..])),
+        Some("demo") => std::process::exit(runebook_lib::run_demo_cli(&args[2..])),
+        Some("profile") => std::process::exit(runebook_lib::run_profile_cli(&args[2..])),
+        Some("ssh") => std::process::exit(runebook_lib::run_ssh_cli(&args[2..])),
+        Some("webhooks") => std::process::exit(runebook_lib::run_webhooks_cli(&args[2..])),
+        Some("schedule") => std::process::exit(runebook_lib::run_schedule_cli(&args[2..])),
+        Some("export-analytics") => {
+            std::process::exit(runebook_lib::run_export_analytics_cli(&args[2..]))
+        }
+        _ => {}
+    }
+    // `--headless` serves the HTTP API without opening a window
+    if args.iter().any(|arg| arg == "--headless") {
+        runebook_lib::run_headless()
+    } else {
+        runebook_lib::run()
+    }
 }
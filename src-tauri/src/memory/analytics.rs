@@ -0,0 +1,389 @@
+// Analytical export of memory as Parquet or CSV tables
+// Commands, errors and per-day duration stats over a date range, with no output content, plus a manifest documenting every column
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::parquet::{write_parquet, Cell, ColumnKind};
+use crate::memory::schema::*;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use ts_rs::TS;
+
+pub const ANALYTICS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum AnalyticsFormat {
+    #[default]
+    Parquet,
+    Csv,
+}
+
+impl AnalyticsFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            AnalyticsFormat::Parquet => "parquet",
+            AnalyticsFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Name, type and meaning of one exported column
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct ColumnDoc {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub kind: ColumnKind,
+    pub description: &'static str,
+}
+
+const fn col(name: &'static str, kind: ColumnKind, description: &'static str) -> ColumnDoc {
+    ColumnDoc {
+        name,
+        kind,
+        description,
+    }
+}
+
+const COMMAND_COLUMNS: &[ColumnDoc] = &[
+    col("id", ColumnKind::Text, "Command id"),
+    col(
+        "session_id",
+        ColumnKind::Text,
+        "Terminal session the command ran in",
+    ),
+    col(
+        "started_at",
+        ColumnKind::Timestamp,
+        "When the command started (UTC)",
+    ),
+    col(
+        "ended_at",
+        ColumnKind::Timestamp,
+        "When it finished; null while running",
+    ),
+    col("command", ColumnKind::Text, "Program name, e.g. cargo"),
+    col(
+        "command_line",
+        ColumnKind::Text,
+        "Program and arguments, quoted for a POSIX shell, as scrubbed at capture",
+    ),
+    col("cwd", ColumnKind::Text, "Working directory"),
+    col("exit_code", ColumnKind::Int, "Exit code; null when unknown"),
+    col("success", ColumnKind::Bool, "Whether the exit code was 0"),
+    col(
+        "duration_ms",
+        ColumnKind::Int,
+        "Wall-clock duration in milliseconds",
+    ),
+    col(
+        "tags",
+        ColumnKind::Text,
+        "Comma-separated tags, including intent: tags",
+    ),
+];
+
+const ERROR_COLUMNS: &[ColumnDoc] = &[
+    col("id", ColumnKind::Text, "Error id"),
+    col(
+        "command_id",
+        ColumnKind::Text,
+        "Command that failed (commands.id)",
+    ),
+    col("session_id", ColumnKind::Text, "Terminal session"),
+    col(
+        "timestamp",
+        ColumnKind::Timestamp,
+        "When the error was recorded (UTC)",
+    ),
+    col(
+        "error_type",
+        ColumnKind::Text,
+        "Classification, e.g. test_failure, compile_error, exit_code",
+    ),
+    col(
+        "severity",
+        ColumnKind::Text,
+        "low, medium, high or critical",
+    ),
+    col(
+        "message",
+        ColumnKind::Text,
+        "One-line summary; stderr snippets are not exported",
+    ),
+    col(
+        "exit_code",
+        ColumnKind::Int,
+        "Exit code of the failed command",
+    ),
+];
+
+const DURATION_COLUMNS: &[ColumnDoc] = &[
+    col("date", ColumnKind::Text, "UTC day, YYYY-MM-DD"),
+    col("command", ColumnKind::Text, "Program name"),
+    col("runs", ColumnKind::Int, "Finished runs that day"),
+    col("failures", ColumnKind::Int, "Runs that did not succeed"),
+    col(
+        "total_ms",
+        ColumnKind::Int,
+        "Sum of durations in milliseconds",
+    ),
+    col(
+        "mean_ms",
+        ColumnKind::Float,
+        "Mean duration in milliseconds",
+    ),
+    col("p50_ms", ColumnKind::Int, "Median duration in milliseconds"),
+    col(
+        "p95_ms",
+        ColumnKind::Int,
+        "95th percentile duration in milliseconds",
+    ),
+    col("max_ms", ColumnKind::Int, "Longest run in milliseconds"),
+];
+
+/// One exported table, as listed in the manifest
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct AnalyticsTable {
+    pub name: &'static str,
+    pub file: String,
+    pub rows: usize,
+    pub sha256: String,
+    pub columns: &'static [ColumnDoc],
+}
+
+/// `manifest.json` of an export
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct AnalyticsManifest {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub format: AnalyticsFormat,
+    pub tables: Vec<AnalyticsTable>,
+}
+
+fn text(value: impl Into<String>) -> Cell {
+    Cell::Text(value.into())
+}
+
+fn opt<T>(value: Option<T>, into: impl FnOnce(T) -> Cell) -> Cell {
+    value.map(into).unwrap_or(Cell::Null)
+}
+
+fn command_row(c: &Command) -> Vec<Cell> {
+    let words = std::iter::once(c.command.as_str()).chain(c.args.iter().map(String::as_str));
+    let line =
+        shlex::try_join(words.clone()).unwrap_or_else(|_| words.collect::<Vec<_>>().join(" "));
+    vec![
+        text(&c.id),
+        text(&c.session_id),
+        Cell::Timestamp(c.started_at),
+        opt(c.ended_at, Cell::Timestamp),
+        text(&c.command),
+        text(line),
+        text(&c.cwd),
+        opt(c.exit_code, |code| Cell::Int(code.into())),
+        Cell::Bool(c.success),
+        opt(c.duration_ms, |ms| Cell::Int(ms as i64)),
+        text(c.tags.join(",")),
+    ]
+}
+
+fn error_row(e: &Error) -> Vec<Cell> {
+    vec![
+        text(&e.id),
+        text(&e.command_id),
+        text(&e.session_id),
+        Cell::Timestamp(e.timestamp),
+        text(&e.error_type),
+        text(&e.severity),
+        text(&e.message),
+        opt(e.exit_code, |code| Cell::Int(code.into())),
+    ]
+}
+
+/// The value at `q` (0..=1) of sorted `values`, nearest rank
+pub(crate) fn percentile(sorted: &[u64], q: f64) -> u64 {
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Per-day, per-program duration stats of finished commands
+fn duration_rows(commands: &[Command]) -> Vec<Vec<Cell>> {
+    let mut groups: BTreeMap<(NaiveDate, &str), (Vec<u64>, u64)> = BTreeMap::new();
+    for c in commands {
+        let Some(ms) = c.awake_ms() else {
+            continue;
+        };
+        let (durations, failures) = groups
+            .entry((c.started_at.date_naive(), c.command.as_str()))
+            .or_default();
+        durations.push(ms);
+        *failures += u64::from(!c.success);
+    }
+    groups
+        .into_iter()
+        .map(|((date, command), (mut durations, failures))| {
+            durations.sort_unstable();
+            let total: u64 = durations.iter().sum();
+            vec![
+                text(date.to_string()),
+                text(command),
+                Cell::Int(durations.len() as i64),
+                Cell::Int(failures as i64),
+                Cell::Int(total as i64),
+                Cell::Float(total as f64 / durations.len() as f64),
+                Cell::Int(percentile(&durations, 0.5) as i64),
+                Cell::Int(percentile(&durations, 0.95) as i64),
+                Cell::Int(*durations.last().unwrap_or(&0) as i64),
+            ]
+        })
+        .collect()
+}
+
+fn csv_field(cell: &Cell) -> String {
+    let value = match cell {
+        Cell::Null => return String::new(),
+        Cell::Text(text) => text.clone(),
+        Cell::Int(value) => value.to_string(),
+        Cell::Float(value) => value.to_string(),
+        Cell::Bool(value) => value.to_string(),
+        Cell::Timestamp(at) => at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// RFC 4180 CSV with a header row; nulls are empty fields
+pub fn write_csv(columns: &[ColumnDoc], rows: &[Vec<Cell>]) -> Vec<u8> {
+    let mut out = columns.iter().map(|c| c.name).collect::<Vec<_>>().join(",");
+    out.push_str("\r\n");
+    for row in rows {
+        out.push_str(&row.iter().map(csv_field).collect::<Vec<_>>().join(","));
+        out.push_str("\r\n");
+    }
+    out.into_bytes()
+}
+
+fn write_table(
+    dir: &Path,
+    name: &'static str,
+    columns: &'static [ColumnDoc],
+    rows: &[Vec<Cell>],
+    format: AnalyticsFormat,
+) -> Result<AnalyticsTable> {
+    let bytes = match format {
+        AnalyticsFormat::Csv => write_csv(columns, rows),
+        AnalyticsFormat::Parquet => {
+            let schema: Vec<(&str, ColumnKind)> =
+                columns.iter().map(|c| (c.name, c.kind)).collect();
+            write_parquet(
+                &schema,
+                rows,
+                &[("runebook.columns", serde_json::to_string(columns)?)],
+            )?
+        }
+    };
+    let file = format!("{}.{}", name, format.extension());
+    std::fs::write(dir.join(&file), &bytes)
+        .with_context(|| format!("Failed to write {}", dir.join(&file).display()))?;
+    Ok(AnalyticsTable {
+        name,
+        file,
+        rows: rows.len(),
+        sha256: hex::encode(Sha256::digest(&bytes)),
+        columns,
+    })
+}
+
+/// Write `commands`, `errors` and `durations` tables and `manifest.json` to `dir`
+pub fn write_analytics(
+    dir: &Path,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: AnalyticsFormat,
+    commands: &[Command],
+    errors: &[Error],
+) -> Result<AnalyticsManifest> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let command_rows: Vec<Vec<Cell>> = commands.iter().map(command_row).collect();
+    let error_rows: Vec<Vec<Cell>> = errors.iter().map(error_row).collect();
+    let tables = vec![
+        write_table(dir, "commands", COMMAND_COLUMNS, &command_rows, format)?,
+        write_table(dir, "errors", ERROR_COLUMNS, &error_rows, format)?,
+        write_table(
+            dir,
+            "durations",
+            DURATION_COLUMNS,
+            &duration_rows(commands),
+            format,
+        )?,
+    ];
+    let manifest = AnalyticsManifest {
+        version: ANALYTICS_VERSION,
+        exported_at: Utc::now(),
+        from,
+        to,
+        format,
+        tables,
+    };
+    std::fs::write(
+        dir.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)? + "\n",
+    )
+    .with_context(|| format!("Failed to write {}", dir.join("manifest.json").display()))?;
+    Ok(manifest)
+}
+
+impl MemoryStore {
+    /// Export commands started and errors recorded between `from` and `to`
+    /// (UTC days, inclusive) as analytics tables in `dir`
+    pub async fn export_analytics(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        format: AnalyticsFormat,
+        dir: &Path,
+    ) -> Result<AnalyticsManifest> {
+        let in_range = |at: DateTime<Utc>| (from..=to).contains(&at.date_naive());
+        let mut commands = Vec::new();
+        for key in self.client.list(RecordKind::Command.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(command) = serde_json::from_value::<Command>(value) {
+                    if in_range(command.started_at) {
+                        commands.push(command);
+                    }
+                }
+            }
+        }
+        let mut errors = Vec::new();
+        for key in self.client.list(RecordKind::Error.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(error) = serde_json::from_value::<Error>(value) {
+                    if in_range(error.timestamp) {
+                        errors.push(error);
+                    }
+                }
+            }
+        }
+        commands.sort_by_key(|c| c.started_at);
+        errors.sort_by_key(|e| e.timestamp);
+        let dir = dir.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            write_analytics(&dir, from, to, format, &commands, &errors)
+        })
+        .await?
+    }
+}
@@ -0,0 +1,290 @@
+// ANSI escape sequences in command output
+// Strips colors and cursor movement, or parses colors into styled spans the canvas can render
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+const ESC: char = '\x1b';
+/// Single-character CSI, as some tools emit it
+const CSI: char = '\u{9b}';
+
+/// What happens to ANSI escape sequences in output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum AnsiMode {
+    /// Leave output as the command wrote it
+    #[default]
+    Keep,
+    /// Remove every escape sequence
+    Strip,
+    /// Remove every escape sequence, keeping colors and text attributes as
+    /// [`StyledSpan`]s over the remaining text
+    Spans,
+}
+
+impl AnsiMode {
+    pub fn is_keep(&self) -> bool {
+        *self == AnsiMode::Keep
+    }
+}
+
+/// A terminal color: 0-15 are the palette's named colors (black, red, ...,
+/// bright white), 16-255 the xterm cube and grays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export, rename = "AnsiColor")]
+pub enum Color {
+    Indexed(u8),
+    Rgb([u8; 3]),
+}
+
+/// Text attributes set by SGR sequences
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct Style {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub fg: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub bg: Option<Color>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub bold: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub dim: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub italic: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub underline: bool,
+    /// Foreground and background swapped
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[ts(as = "Option<bool>", optional)]
+    pub inverse: bool,
+}
+
+impl Style {
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// Apply the parameters of one SGR sequence (`ESC [ ... m`)
+    fn apply(&mut self, params: &str) {
+        let params: Vec<&str> = params.split(';').collect();
+        let mut i = 0;
+        while i < params.len() {
+            let param = params[i];
+            i += 1;
+            // `38:2::r:g:b` carries its color in one parameter
+            if param.contains(':') {
+                let sub: Vec<&str> = param.split(':').collect();
+                let code = sub[0].parse().unwrap_or(0);
+                let color = match sub.get(1).copied() {
+                    Some("5") => sub.get(2).and_then(|n| n.parse().ok()).map(Color::Indexed),
+                    Some("2") => {
+                        // The color space id before the components is optional
+                        let rgb = &sub[sub.len().saturating_sub(3).max(2)..];
+                        rgb_color(rgb)
+                    }
+                    _ => None,
+                };
+                self.set_extended(code, color);
+                continue;
+            }
+            let code: u16 = if param.is_empty() {
+                0
+            } else {
+                match param.parse() {
+                    Ok(code) => code,
+                    Err(_) => continue,
+                }
+            };
+            match code {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                30..=37 => self.fg = Some(Color::Indexed((code - 30) as u8)),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed((code - 40) as u8)),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Color::Indexed((code - 90 + 8) as u8)),
+                100..=107 => self.bg = Some(Color::Indexed((code - 100 + 8) as u8)),
+                38 | 48 => {
+                    let color = match params.get(i).copied() {
+                        Some("5") => {
+                            let color = params.get(i + 1).and_then(|n| n.parse().ok());
+                            i += 2;
+                            color.map(Color::Indexed)
+                        }
+                        Some("2") => {
+                            let color = params.get(i + 1..i + 4).and_then(rgb_color);
+                            i += 4;
+                            color
+                        }
+                        _ => None,
+                    };
+                    self.set_extended(code, color);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn set_extended(&mut self, code: u16, color: Option<Color>) {
+        match code {
+            38 => self.fg = color.or(self.fg),
+            48 => self.bg = color.or(self.bg),
+            _ => {}
+        }
+    }
+}
+
+fn rgb_color(parts: &[&str]) -> Option<Color> {
+    match parts {
+        [r, g, b] => Some(Color::Rgb([
+            r.parse().ok()?,
+            g.parse().ok()?,
+            b.parse().ok()?,
+        ])),
+        _ => None,
+    }
+}
+
+/// A run of output text in one style
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StyledSpan {
+    pub text: String,
+    #[serde(flatten)]
+    pub style: Style,
+}
+
+/// Where the escape sequence starting at `text[0]` ends, and its SGR
+/// parameters if it is one; `None` when `text` ends before the sequence does
+fn sequence_end(text: &str) -> Option<(usize, Option<&str>)> {
+    let mut chars = text.char_indices();
+    let (_, first) = chars.next()?;
+    let introducer = if first == CSI { '[' } else { chars.next()?.1 };
+    match introducer {
+        '[' => {
+            let params_start = chars.clone().next().map_or(text.len(), |(at, _)| at);
+            // Parameters and intermediates, then one final byte in @..~
+            for (at, c) in chars {
+                if ('@'..='~').contains(&c) {
+                    let end = at + c.len_utf8();
+                    let sgr = (c == 'm').then(|| &text[params_start..at]);
+                    return Some((end, sgr));
+                }
+            }
+            None
+        }
+        // OSC, DCS, SOS, PM and APC strings run to BEL or ST
+        ']' | 'P' | 'X' | '^' | '_' => {
+            let mut previous = '\0';
+            for (at, c) in chars {
+                if c == '\x07' || (previous == ESC && c == '\\') {
+                    return Some((at + c.len_utf8(), None));
+                }
+                previous = c;
+            }
+            None
+        }
+        // Character set designations take one more character
+        '(' | ')' | '*' | '+' => chars.next().map(|(at, c)| (at + c.len_utf8(), None)),
+        c => Some((first.len_utf8() + c.len_utf8(), None)),
+    }
+}
+
+/// Parses output as it arrives. A sequence split across chunks is held back
+/// until the rest comes, and the style carries over from chunk to chunk.
+#[derive(Debug, Default)]
+pub struct AnsiParser {
+    style: Style,
+    pending: String,
+}
+
+impl AnsiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text of `chunk` in styled spans, without escape sequences
+    pub fn feed(&mut self, chunk: &str) -> Vec<StyledSpan> {
+        let input = std::mem::take(&mut self.pending) + chunk;
+        let mut spans = Vec::new();
+        let mut text = String::new();
+        let mut rest = input.as_str();
+        while let Some(at) = rest.find([ESC, CSI]) {
+            text.push_str(&rest[..at]);
+            rest = &rest[at..];
+            let Some((end, sgr)) = sequence_end(rest) else {
+                self.pending = rest.to_string();
+                rest = "";
+                break;
+            };
+            if let Some(params) = sgr {
+                let mut style = self.style;
+                style.apply(params);
+                if style != self.style && !text.is_empty() {
+                    spans.push(StyledSpan {
+                        text: std::mem::take(&mut text),
+                        style: self.style,
+                    });
+                }
+                self.style = style;
+            }
+            rest = &rest[end..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            spans.push(StyledSpan {
+                text,
+                style: self.style,
+            });
+        }
+        spans
+    }
+
+    /// What is left of an unfinished sequence at the end of the output, as
+    /// plain text
+    pub fn finish(&mut self) -> Vec<StyledSpan> {
+        let text = std::mem::take(&mut self.pending);
+        if text.is_empty() {
+            return Vec::new();
+        }
+        vec![StyledSpan {
+            text,
+            style: self.style,
+        }]
+    }
+}
+
+/// `text` without escape sequences
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|span| span.text).collect()
+}
+
+/// `text` in styled spans, without escape sequences
+pub fn parse(text: &str) -> Vec<StyledSpan> {
+    let mut parser = AnsiParser::new();
+    let mut spans = parser.feed(text);
+    spans.extend(parser.finish());
+    spans
+}
+
+/// The text of `spans`, joined
+pub fn plain_text(spans: &[StyledSpan]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
@@ -1,56 +1,308 @@
 // Rust API layer for cognitive memory storage
 // Provides: append_event, list_sessions, query_recent_errors, get_context, persist_suggestion
 
-use crate::memory::client::PluresDBClient;
+use crate::memory::ansi;
+use crate::memory::backend::{MemoryBackend, PutOutcome, WriteBatch};
+use crate::memory::codec::CodecId;
+use crate::memory::content;
+use crate::memory::encoding::{self, EncodingConfig};
 use crate::memory::encryption::EncryptionProvider;
+use crate::memory::events::{EventBus, StoreEvent};
+use crate::memory::keys::RecordKind;
+use crate::memory::pages;
+use crate::memory::quota::QuotaGuard;
+use crate::memory::ranking::RankingConfig;
 use crate::memory::schema::*;
+use crate::memory::scrub::{merge_hits, ScrubHit, ScrubStage, Scrubber};
+use crate::memory::tags::{merge_tags, AutoTagger};
+use crate::storage::ObjectStore;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Attempts a read-modify-write makes before giving up on a contended record
+const MAX_UPDATE_ATTEMPTS: usize = 8;
 
 /// Main memory store API
 pub struct MemoryStore {
-    pub(crate) client: PluresDBClient,
+    pub(crate) client: Arc<dyn MemoryBackend>,
     encryption: Option<Box<dyn EncryptionProvider>>,
+    quota: Option<Arc<QuotaGuard>>,
+    output_encoding: EncodingConfig,
+    auto_tagger: Option<Arc<AutoTagger>>,
+    scrubber: Option<Arc<Scrubber>>,
+    ranking: RankingConfig,
+    remote: Option<Arc<dyn ObjectStore>>,
+    events: Option<Arc<EventBus>>,
 }
 
 impl MemoryStore {
-    pub async fn new(client: PluresDBClient) -> Result<Self> {
+    pub async fn new(client: impl MemoryBackend + 'static) -> Result<Self> {
         // TODO: Initialize encryption if configured
         let encryption: Option<Box<dyn EncryptionProvider>> = None;
 
-        Ok(Self { client, encryption })
+        Ok(Self {
+            client: Arc::new(client),
+            encryption,
+            quota: None,
+            output_encoding: EncodingConfig::default(),
+            auto_tagger: None,
+            scrubber: None,
+            ranking: RankingConfig::default(),
+            remote: None,
+            events: None,
+        })
     }
 
-    /// Append an event to memory storage
-    pub async fn append_event(&self, event: MemoryEvent) -> Result<()> {
-        let key = format!("memory:event:{}", event.id);
-        let value = serde_json::to_value(&event)?;
+    /// Enforce storage quotas on output and artifact writes
+    pub fn with_quota(mut self, quota: Arc<QuotaGuard>) -> Self {
+        self.quota = Some(quota);
+        self
+    }
 
-        // Encrypt if encryption is enabled
-        let value = if let Some(enc) = &self.encryption {
-            enc.encrypt(&value).await?
-        } else {
-            value
+    /// Decode stored text outputs with this encoding instead of auto-detecting
+    pub fn with_output_encoding(mut self, config: EncodingConfig) -> Self {
+        self.output_encoding = config;
+        self
+    }
+
+    /// Apply auto-tag rules to new sessions and commands
+    pub fn with_auto_tagger(mut self, tagger: Arc<AutoTagger>) -> Self {
+        self.auto_tagger = Some(tagger);
+        self
+    }
+
+    /// Scrub command args, text outputs, and errors before they are written
+    pub fn with_scrubber(mut self, scrubber: Arc<Scrubber>) -> Self {
+        self.scrubber = Some(scrubber);
+        self
+    }
+
+    /// Rank surfaced suggestions with these weights
+    pub fn with_ranking(mut self, config: RankingConfig) -> Self {
+        self.ranking = config;
+        self
+    }
+
+    pub(crate) fn ranking(&self) -> &RankingConfig {
+        &self.ranking
+    }
+
+    /// Read offloaded artifact and output content back from this bucket
+    pub fn with_remote_store(mut self, remote: Option<Arc<dyn ObjectStore>>) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    pub(crate) fn remote_store(&self) -> Option<&dyn ObjectStore> {
+        self.remote.as_deref()
+    }
+
+    /// Publish finished commands and recorded errors on this bus
+    pub fn with_event_bus(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub(crate) fn publish(&self, event: StoreEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+
+    /// Apply capture-stage scrub rules to `text`, recording which rules fired.
+    /// Returns whether `text` changed.
+    pub(crate) fn scrub(&self, text: &mut String, scrubbed_by: &mut Vec<ScrubHit>) -> bool {
+        let Some(scrubber) = &self.scrubber else {
+            return false;
         };
+        let (scrubbed, hits) = scrubber.scrub(text, ScrubStage::Capture);
+        if hits.is_empty() {
+            return false;
+        }
+        *text = scrubbed;
+        merge_hits(scrubbed_by, hits);
+        true
+    }
 
-        self.client.put(&key, &value).await?;
+    /// [`Self::scrub`] the UTF-8 runs of `content`. Bytes that are not UTF-8
+    /// are kept as they are rather than replaced, so a scrubbed chunk is
+    /// otherwise the bytes the command wrote.
+    fn scrub_bytes(&self, content: &mut Vec<u8>, scrubbed_by: &mut Vec<ScrubHit>) -> bool {
+        if self.scrubber.is_none() {
+            return false;
+        }
+        let mut scrubbed = Vec::with_capacity(content.len());
+        let mut changed = false;
+        let mut rest = &content[..];
+        while !rest.is_empty() {
+            let (valid, invalid) = match std::str::from_utf8(rest) {
+                Ok(_) => (rest.len(), 0),
+                Err(e) => (
+                    e.valid_up_to(),
+                    e.error_len().unwrap_or(rest.len() - e.valid_up_to()),
+                ),
+            };
+            let text = std::str::from_utf8(&rest[..valid]).expect("checked above");
+            let mut text = text.to_string();
+            changed |= self.scrub(&mut text, scrubbed_by);
+            scrubbed.extend_from_slice(text.as_bytes());
+            scrubbed.extend_from_slice(&rest[valid..valid + invalid]);
+            rest = &rest[valid + invalid..];
+        }
+        if changed {
+            *content = scrubbed;
+        }
+        changed
+    }
+
+    /// Run quota admission for a payload; `true` means it may be written
+    async fn admit_payload(
+        &self,
+        session_id: Option<&str>,
+        value: &serde_json::Value,
+    ) -> Result<bool> {
+        match &self.quota {
+            Some(quota) => {
+                let bytes = serde_json::to_vec(value)?.len() as u64;
+                quota.admit(self, session_id, bytes).await
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// Whether records are encrypted at rest
+    pub(crate) fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Read and decrypt a single record
+    pub(crate) async fn read_value(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        match self.client.get(key).await? {
+            Some(value) => Ok(Some(self.decrypt_value(value).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Encrypt (if enabled) and write a single record
+    pub(crate) async fn write_value(&self, key: &str, value: serde_json::Value) -> Result<()> {
+        let value = self.encrypt_value(value).await?;
+        self.client.put(key, &value).await
+    }
+
+    /// Encrypt a record for storage (no-op when encryption is disabled)
+    pub(crate) async fn encrypt_value(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        if let Some(enc) = &self.encryption {
+            enc.encrypt(&value).await
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Apply the writes staged in `batch` together
+    pub(crate) async fn commit(&self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.client.commit_batch(&batch).await
+    }
+
+    /// Whether the backend answers
+    pub async fn health_check(&self) -> Result<bool> {
+        self.client.health_check().await
+    }
+
+    /// Logged batches not yet fully applied; they are replayed on the next start
+    pub async fn pending_writes(&self) -> Result<usize> {
+        Ok(self.client.list(RecordKind::Wal.prefix()).await?.len())
+    }
+
+    /// Read-modify-write a single record with compare-and-swap: when another
+    /// writer changes the record between the read and the write, `mutate` runs
+    /// again on the fresh copy. Returns the written record, or `None` when
+    /// there is no record at `key`.
+    pub(crate) async fn update_value<T, F>(&self, key: &str, mut mutate: F) -> Result<Option<T>>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(&mut T) -> Result<()>,
+    {
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let Some((stored, revision)) = self.client.get_revision(key).await? else {
+                return Ok(None);
+            };
+            let mut record: T = serde_json::from_value(self.decrypt_value(stored).await?)
+                .with_context(|| format!("Malformed record: {}", key))?;
+            mutate(&mut record)?;
+            let value = serde_json::to_value(&record)?;
+            let value = if let Some(enc) = &self.encryption {
+                enc.encrypt(&value).await?
+            } else {
+                value
+            };
+            match self.client.put_if_revision(key, &value, revision).await? {
+                PutOutcome::Written(_) => return Ok(Some(record)),
+                PutOutcome::Conflict(_) => continue,
+            }
+        }
+        anyhow::bail!(
+            "Record {} kept changing; gave up after {} attempts",
+            key,
+            MAX_UPDATE_ATTEMPTS
+        )
+    }
+
+    /// Decrypt a raw stored value (no-op when encryption is disabled)
+    pub(crate) async fn decrypt_value(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        if let Some(enc) = &self.encryption {
+            enc.decrypt(&value).await
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Append an event to memory storage
+    pub async fn append_event(&self, event: MemoryEvent) -> Result<()> {
+        let key = RecordKind::Event.key(&event.id);
+        let value = self.encrypt_value(serde_json::to_value(&event)?).await?;
+
+        // The event, its session and its provenance land together
+        let mut batch = WriteBatch::new();
+        batch.put(key, value);
 
         // Also update session if it's a session event
         if event.event_type == "session_start" {
-            if let Ok(session) = serde_json::from_value::<Session>(event.data.clone()) {
-                let session_key = format!("memory:session:{}", session.id);
-                self.client
-                    .put(&session_key, &serde_json::to_value(&session)?)
-                    .await?;
+            if let Ok(mut session) = serde_json::from_value::<Session>(event.data.clone()) {
+                if let Some(tagger) = &self.auto_tagger {
+                    let auto = tagger.session_tags(&session);
+                    merge_tags(&mut session.tags, auto);
+                }
+                let session_key = RecordKind::Session.key(&session.id);
+                batch.put(session_key, serde_json::to_value(&session)?);
             }
         }
 
         // Store provenance if provided
-        if let Some(prov) = event.provenance {
-            let prov_key = format!("memory:provenance:{}", prov.id);
-            self.client
-                .put(&prov_key, &serde_json::to_value(&prov)?)
-                .await?;
+        if let Some(prov) = &event.provenance {
+            let prov_key = RecordKind::Provenance.key(&prov.id);
+            batch.put(prov_key, serde_json::to_value(prov)?);
+        }
+        self.commit(batch).await?;
+
+        if event.event_type == "session_end" {
+            let session_key = RecordKind::Session.key(&event.session_id);
+            self.update_value(&session_key, |session: &mut Session| {
+                session.ended_at.get_or_insert(event.timestamp);
+                Ok(())
+            })
+            .await?;
         }
 
         Ok(())
@@ -58,7 +310,7 @@ impl MemoryStore {
 
     /// List all sessions
     pub async fn list_sessions(&self) -> Result<Vec<Session>> {
-        let keys = self.client.list("memory:session:").await?;
+        let keys = self.client.list(RecordKind::Session.prefix()).await?;
         let mut sessions = Vec::new();
 
         for key in keys {
@@ -89,7 +341,7 @@ impl MemoryStore {
         since: Option<DateTime<Utc>>,
         severity: Option<&str>,
     ) -> Result<Vec<Error>> {
-        let keys = self.client.list("memory:error:").await?;
+        let keys = self.client.list(RecordKind::Error.prefix()).await?;
         let mut errors = Vec::new();
 
         for key in keys {
@@ -142,7 +394,7 @@ impl MemoryStore {
         let start_time = end_time - window;
 
         // Get session
-        let session_key = format!("memory:session:{}", session_id);
+        let session_key = RecordKind::Session.key(session_id);
         let _session: Session = if let Some(value) = self.client.get(&session_key).await? {
             let value = if let Some(enc) = &self.encryption {
                 enc.decrypt(&value).await?
@@ -155,7 +407,7 @@ impl MemoryStore {
         };
 
         // Get commands in time window
-        let command_keys = self.client.list("memory:command:").await?;
+        let command_keys = self.client.list(RecordKind::Command.prefix()).await?;
         let mut commands = Vec::new();
         for key in command_keys {
             if let Some(value) = self.client.get(&key).await? {
@@ -178,7 +430,7 @@ impl MemoryStore {
         commands.sort_by_key(|command| command.started_at);
 
         // Get outputs for these commands
-        let output_keys = self.client.list("memory:output:").await?;
+        let output_keys = self.client.list(RecordKind::Output.prefix()).await?;
         let mut outputs = Vec::new();
         let command_ids: std::collections::HashSet<String> =
             commands.iter().map(|c| c.id.clone()).collect();
@@ -200,7 +452,7 @@ impl MemoryStore {
         outputs.sort_by_key(|output| output.chunk_index);
 
         // Get errors in time window
-        let error_keys = self.client.list("memory:error:").await?;
+        let error_keys = self.client.list(RecordKind::Error.prefix()).await?;
         let mut errors = Vec::new();
         for key in error_keys {
             if let Some(value) = self.client.get(&key).await? {
@@ -223,7 +475,7 @@ impl MemoryStore {
         errors.sort_by_key(|error| error.timestamp);
 
         // Get insights
-        let insight_keys = self.client.list("memory:insight:").await?;
+        let insight_keys = self.client.list(RecordKind::Insight.prefix()).await?;
         let mut insights = Vec::new();
         for key in insight_keys {
             if let Some(value) = self.client.get(&key).await? {
@@ -245,6 +497,7 @@ impl MemoryStore {
         }
         insights.sort_by_key(|insight| insight.generated_at);
 
+        let activity = crate::memory::intent::activity(&commands);
         Ok(ContextWindow {
             session_id: session_id.to_string(),
             start_time,
@@ -253,12 +506,13 @@ impl MemoryStore {
             outputs,
             errors,
             insights,
+            activity,
         })
     }
 
     /// Persist a suggestion
     pub async fn persist_suggestion(&self, suggestion: Suggestion) -> Result<()> {
-        let key = format!("memory:suggestion:{}", suggestion.id);
+        let key = RecordKind::Suggestion.key(&suggestion.id);
         let value = serde_json::to_value(&suggestion)?;
 
         // Encrypt if encryption is enabled
@@ -278,7 +532,7 @@ impl MemoryStore {
         priority: Option<&str>,
         limit: Option<usize>,
     ) -> Result<Vec<Suggestion>> {
-        let keys = self.client.list("memory:suggestion:").await?;
+        let keys = self.client.list(RecordKind::Suggestion.prefix()).await?;
         let mut suggestions = Vec::new();
 
         for key in keys {
@@ -323,66 +577,230 @@ impl MemoryStore {
     }
 
     /// Store a command
-    pub async fn store_command(&self, command: Command) -> Result<()> {
-        let key = format!("memory:command:{}", command.id);
-        let value = serde_json::to_value(&command)?;
+    pub async fn store_command(&self, mut command: Command) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        let newly_finished = self.stage_command(&mut batch, &mut command).await?;
+        self.commit(batch).await?;
+        if newly_finished {
+            self.record_command_stats(&command).await?;
+            self.publish(StoreEvent::CommandFinished {
+                command: Box::new(command),
+                error: None,
+            });
+        }
+        Ok(())
+    }
 
-        let value = if let Some(enc) = &self.encryption {
-            enc.encrypt(&value).await?
-        } else {
-            value
-        };
+    /// Scrub and tag `command` and add its record to `batch`. Returns whether
+    /// this is the first time the command is stored finished, in which case
+    /// its time should be counted once the batch is committed.
+    pub(crate) async fn stage_command(
+        &self,
+        batch: &mut WriteBatch,
+        command: &mut Command,
+    ) -> Result<bool> {
+        for arg in &mut command.args {
+            self.scrub(arg, &mut command.scrubbed_by);
+        }
+        if let Some(env) = command.env_summary.as_object_mut() {
+            for value in env.values_mut() {
+                if let Some(text) = value.as_str() {
+                    let mut text = text.to_string();
+                    if self.scrub(&mut text, &mut command.scrubbed_by) {
+                        *value = serde_json::Value::String(text);
+                    }
+                }
+            }
+        }
+        if let Some(tagger) = &self.auto_tagger {
+            let auto = tagger.command_tags(command);
+            merge_tags(&mut command.tags, auto);
+        }
+        if crate::power::monitor().annotate(command) {
+            merge_tags(
+                &mut command.tags,
+                vec![crate::power::SUSPENDED_TAG.to_string()],
+            );
+        }
 
-        self.client.put(&key, &value).await?;
-        Ok(())
+        let key = RecordKind::Command.key(&command.id);
+
+        // Count the command's time once, the first time it is stored finished
+        let newly_finished = command.duration_ms.is_some()
+            && self
+                .read_value(&key)
+                .await?
+                .and_then(|v| serde_json::from_value::<Command>(v).ok())
+                .is_none_or(|previous| previous.duration_ms.is_none());
+
+        batch.put(
+            key,
+            self.encrypt_value(serde_json::to_value(&*command)?).await?,
+        );
+        Ok(newly_finished)
     }
 
     /// Store an output chunk (with optional compression)
+    ///
+    /// Binary chunks are moved into an `output_blob` artifact; the output record
+    /// keeps a hexdump preview and points at the blob via `blob_artifact_id`.
+    /// Text chunks are transcoded to UTF-8 and the source encoding recorded.
     pub async fn store_output(&self, output: &mut Output, compress: bool) -> Result<()> {
-        if compress && !output.compressed {
-            use flate2::write::GzEncoder;
-            use flate2::Compression;
-            use std::io::Write;
+        let mut batch = WriteBatch::new();
+        self.stage_output(&mut batch, output, compress).await?;
+        self.commit(batch).await
+    }
 
-            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-            encoder.write_all(&output.content)?;
-            let compressed = encoder.finish()?;
+    /// [`Self::store_output`] into `batch`: the output, its line index and
+    /// any blob artifact are staged together
+    pub(crate) async fn stage_output(
+        &self,
+        batch: &mut WriteBatch,
+        output: &mut Output,
+        compress: bool,
+    ) -> Result<()> {
+        if !output.compressed && output.blob_artifact_id.is_none() {
+            let info = content::detect_content(&output.content);
+            if info.binary {
+                let session_id = self.command_session(batch, &output.command_id).await?;
+                let preview = content::hexdump(&output.content, content::HEXDUMP_PREVIEW_BYTES);
+
+                let mut blob = Artifact::new(
+                    "output_blob".to_string(),
+                    info.mime_type.clone(),
+                    std::mem::replace(&mut output.content, preview.into_bytes()),
+                );
+                blob.session_id = session_id;
+                blob.command_id = Some(output.command_id.clone());
+                blob.source = "capture".to_string();
+                blob.metadata = serde_json::json!({
+                    "output_id": output.id,
+                    "stream_type": output.stream_type,
+                    "chunk_index": output.chunk_index,
+                });
+                output.blob_artifact_id = Some(blob.id.clone());
+                self.stage_artifact(batch, blob).await?;
+            } else if output.encoding.is_none() {
+                let (text, source) = encoding::decode(&output.content, &self.output_encoding)?;
+                if source != encoding_rs::UTF_8 {
+                    output.content = text.into_bytes();
+                }
+                output.encoding = Some(source.name().to_string());
+            }
+            if !info.binary && output.stream_type != OBJECTS_STREAM {
+                self.stage_ansi(output);
+            }
+            if !info.binary && self.scrub_bytes(&mut output.content, &mut output.scrubbed_by) {
+                output.size_bytes = output.content.len() as u64;
+            }
+            for span in &mut output.spans {
+                self.scrub(&mut span.text, &mut output.scrubbed_by);
+            }
+            output.content_type = Some(if output.stream_type == OBJECTS_STREAM {
+                OBJECTS_CONTENT_TYPE.to_string()
+            } else {
+                info.mime_type
+            });
+        }
 
-            output.content = compressed;
+        // Indexed before compression, so later range reads can seek
+        let line_index = pages::index_output(output)?;
+
+        let codec = self.output_encoding.codec;
+        if compress && !output.compressed && codec != CodecId::None {
+            output.content = codec.codec().compress(&output.content)?;
             output.compressed = true;
+            output.codec = Some(codec);
         }
 
-        let key = format!("memory:output:{}", output.id);
-        let value = serde_json::to_value(output)?;
+        let key = RecordKind::Output.key(&output.id);
+        let value = self.encrypt_value(serde_json::to_value(&*output)?).await?;
 
-        let value = if let Some(enc) = &self.encryption {
-            enc.encrypt(&value).await?
-        } else {
-            value
+        // Over quota: the command record is kept, only the payload is skipped
+        let session_id = match &self.quota {
+            Some(quota) if quota.config().max_session_bytes.is_some() => {
+                self.command_session(batch, &output.command_id).await?
+            }
+            _ => None,
         };
+        if !self.admit_payload(session_id.as_deref(), &value).await? {
+            return Ok(());
+        }
 
-        self.client.put(&key, &value).await?;
+        batch.put(key, value);
+        batch.put(
+            RecordKind::OutputIndex.key(&line_index.output_id),
+            self.encrypt_value(serde_json::to_value(&line_index)?)
+                .await?,
+        );
         Ok(())
     }
 
-    /// Store an error
-    pub async fn store_error(&self, error: Error) -> Result<()> {
-        let key = format!("memory:error:{}", error.id);
-        let value = serde_json::to_value(&error)?;
+    /// Strip the escape sequences of a decoded text chunk per `[output] ansi`,
+    /// keeping its colors in `spans` when asked to. Each chunk starts
+    /// unstyled: a color set in one chunk does not carry into the next.
+    fn stage_ansi(&self, output: &mut Output) {
+        let mode = self.output_encoding.ansi;
+        if mode == ansi::AnsiMode::Keep {
+            return;
+        }
+        let Ok(text) = std::str::from_utf8(&output.content) else {
+            return;
+        };
+        let spans = ansi::parse(text);
+        output.content = ansi::plain_text(&spans).into_bytes();
+        output.size_bytes = output.content.len() as u64;
+        if mode == ansi::AnsiMode::Spans && spans.iter().any(|s| !s.style.is_plain()) {
+            output.spans = spans;
+        }
+    }
 
-        let value = if let Some(enc) = &self.encryption {
-            enc.encrypt(&value).await?
-        } else {
-            value
+    /// Session of a command, whether stored or staged in `batch`
+    async fn command_session(
+        &self,
+        batch: &WriteBatch,
+        command_id: &str,
+    ) -> Result<Option<String>> {
+        let key = RecordKind::Command.key(command_id);
+        let value = match batch.staged(&key) {
+            Some(value) => Some(self.decrypt_value(value.clone()).await?),
+            None => self.read_value(&key).await?,
         };
+        Ok(value
+            .and_then(|v| serde_json::from_value::<Command>(v).ok())
+            .map(|c| c.session_id))
+    }
 
-        self.client.put(&key, &value).await?;
+    /// Store an error
+    pub async fn store_error(&self, error: Error) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        let error = self.stage_error(&mut batch, error).await?;
+        self.commit(batch).await?;
+        self.publish(StoreEvent::ErrorRecorded { error });
         Ok(())
     }
 
+    /// Scrub `error` and add its record to `batch`. Returns the scrubbed error.
+    pub(crate) async fn stage_error(
+        &self,
+        batch: &mut WriteBatch,
+        mut error: Error,
+    ) -> Result<Error> {
+        self.scrub(&mut error.message, &mut error.scrubbed_by);
+        if let Some(snippet) = &mut error.stderr_snippet {
+            self.scrub(snippet, &mut error.scrubbed_by);
+        }
+        let key = RecordKind::Error.key(&error.id);
+        batch.put(
+            key,
+            self.encrypt_value(serde_json::to_value(&error)?).await?,
+        );
+        Ok(error)
+    }
+
     /// Store an insight
     pub async fn store_insight(&self, insight: Insight) -> Result<()> {
-        let key = format!("memory:insight:{}", insight.id);
+        let key = RecordKind::Insight.key(&insight.id);
         let value = serde_json::to_value(&insight)?;
 
         let value = if let Some(enc) = &self.encryption {
@@ -395,21 +813,124 @@ impl MemoryStore {
         Ok(())
     }
 
+    /// Store a provenance record on its own, outside of an event
+    pub async fn store_provenance(&self, provenance: Provenance) -> Result<()> {
+        let key = RecordKind::Provenance.key(&provenance.id);
+        self.write_value(&key, serde_json::to_value(&provenance)?)
+            .await
+    }
+
+    /// Store an artifact attached to a session or command
+    pub async fn store_artifact(&self, artifact: Artifact) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        self.stage_artifact(&mut batch, artifact).await?;
+        self.commit(batch).await
+    }
+
+    /// Run quota admission for `artifact` and add its record to `batch`
+    async fn stage_artifact(&self, batch: &mut WriteBatch, artifact: Artifact) -> Result<()> {
+        let key = RecordKind::Artifact.key(&artifact.id);
+        let value = self.encrypt_value(serde_json::to_value(&artifact)?).await?;
+
+        if !self
+            .admit_payload(artifact.session_id.as_deref(), &value)
+            .await?
+        {
+            anyhow::bail!("Artifact not stored: memory quota exceeded");
+        }
+
+        batch.put(key, value);
+        Ok(())
+    }
+
+    /// List artifacts, optionally filtered by session and/or command
+    pub async fn list_artifacts(
+        &self,
+        session_id: Option<&str>,
+        command_id: Option<&str>,
+    ) -> Result<Vec<Artifact>> {
+        let keys = self.client.list(RecordKind::Artifact.prefix()).await?;
+        let mut artifacts = Vec::new();
+
+        for key in keys {
+            if let Some(value) = self.client.get(&key).await? {
+                let value = if let Some(enc) = &self.encryption {
+                    enc.decrypt(&value).await?
+                } else {
+                    value
+                };
+
+                if let Ok(artifact) = serde_json::from_value::<Artifact>(value) {
+                    if session_id.is_some() && artifact.session_id.as_deref() != session_id {
+                        continue;
+                    }
+                    if command_id.is_some() && artifact.command_id.as_deref() != command_id {
+                        continue;
+                    }
+                    artifacts.push(artifact);
+                }
+            }
+        }
+
+        artifacts.sort_by_key(|artifact| artifact.created_at);
+
+        Ok(artifacts)
+    }
+
+    /// Delete a session and the records that belong to it (commands, their
+    /// outputs, errors, events, artifacts). Aggregated statistics are kept.
+    /// Returns the number of records removed; zero when there is no such session.
+    pub async fn delete_session(&self, session_id: &str) -> Result<usize> {
+        if self
+            .client
+            .get(&RecordKind::Session.key(session_id))
+            .await?
+            .is_none()
+        {
+            return Ok(0);
+        }
+
+        let mut command_ids = std::collections::HashSet::new();
+        let mut removed = 0;
+        for kind in crate::memory::usage::RECORD_KINDS {
+            if matches!(
+                kind,
+                RecordKind::View | RecordKind::Preference | RecordKind::DayStats | RecordKind::Wal
+            ) {
+                continue;
+            }
+            for key in self.client.list(kind.prefix()).await? {
+                let Some(raw) = self.client.get(&key).await? else {
+                    continue;
+                };
+                let value = self.decrypt_value(raw).await?;
+                let field = |name: &str| value.get(name).and_then(|v| v.as_str());
+                let belongs = match kind {
+                    RecordKind::Session => field("id") == Some(session_id),
+                    RecordKind::Output | RecordKind::OutputIndex => {
+                        field("command_id").is_some_and(|c| command_ids.contains(c))
+                    }
+                    _ => field("session_id") == Some(session_id),
+                };
+                if !belongs {
+                    continue;
+                }
+                if *kind == RecordKind::Command {
+                    if let Some(id) = field("id") {
+                        command_ids.insert(id.to_string());
+                    }
+                }
+                self.client.delete(&key).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
     /// Wipe all memory data (for testing/cleanup)
     pub async fn wipe_all(&self) -> Result<()> {
-        let prefixes = vec![
-            "memory:session:",
-            "memory:command:",
-            "memory:output:",
-            "memory:error:",
-            "memory:insight:",
-            "memory:suggestion:",
-            "memory:provenance:",
-            "memory:event:",
-        ];
-
-        for prefix in prefixes {
-            let keys = self.client.list(prefix).await?;
+        for kind in crate::memory::usage::RECORD_KINDS {
+            let keys = self.client.list(kind.prefix()).await?;
             for key in keys {
                 self.client.delete(&key).await?;
             }
@@ -0,0 +1,250 @@
+// Storage backend abstraction
+// Records carry a revision that every write bumps; `put_if_revision` lets concurrent writers detect each other.
+// Multi-key writes go through `commit_batch`, natively where the backend can, otherwise via a write-ahead log.
+
+use crate::memory::keys::RecordKind;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Revision of a key that holds no record
+pub const ABSENT: u64 = 0;
+
+/// Age after which a logged batch is assumed abandoned by a crashed writer
+/// rather than still being applied
+const WAL_GRACE_SECS: i64 = 30;
+
+/// One write in a [`WriteBatch`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOp {
+    Put { key: String, value: Value },
+    Delete { key: String },
+}
+
+/// Writes that land together or not at all
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteBatch {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub ops: Vec<BatchOp>,
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            ops: Vec::new(),
+        }
+    }
+
+    pub fn put(&mut self, key: impl Into<String>, value: Value) {
+        self.ops.push(BatchOp::Put {
+            key: key.into(),
+            value,
+        });
+    }
+
+    pub fn delete(&mut self, key: impl Into<String>) {
+        self.ops.push(BatchOp::Delete { key: key.into() });
+    }
+
+    /// Value the batch will leave at `key`, if it writes one
+    pub fn staged(&self, key: &str) -> Option<&Value> {
+        self.ops.iter().rev().find_map(|op| match op {
+            BatchOp::Put { key: k, value } if k == key => Some(value),
+            _ => None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Result of a compare-and-swap write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutOutcome {
+    /// The record was written and now has this revision
+    Written(u64),
+    /// Another writer got there first; the record has this revision
+    Conflict(u64),
+}
+
+/// Key-value store behind [`crate::memory::MemoryStore`]
+#[async_trait]
+pub trait MemoryBackend: Send + Sync {
+    /// Write `value` regardless of the stored revision
+    async fn put(&self, key: &str, value: &Value) -> Result<()>;
+
+    async fn get(&self, key: &str) -> Result<Option<Value>>;
+
+    /// The record and its revision
+    async fn get_revision(&self, key: &str) -> Result<Option<(Value, u64)>>;
+
+    /// Write `value` only if the record is still at `expected`; pass
+    /// [`ABSENT`] to create a record that must not exist yet
+    async fn put_if_revision(&self, key: &str, value: &Value, expected: u64) -> Result<PutOutcome>;
+
+    /// Keys starting with `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    async fn health_check(&self) -> Result<bool>;
+
+    /// Apply every write in `batch` or, after a crash, none of them until
+    /// [`replay_wal`] finishes the job. Backends without native transactions
+    /// use the write-ahead log.
+    async fn commit_batch(&self, batch: &WriteBatch) -> Result<()> {
+        wal_commit(self, batch).await
+    }
+}
+
+async fn apply_ops<B: MemoryBackend + ?Sized>(backend: &B, ops: &[BatchOp]) -> Result<()> {
+    for op in ops {
+        match op {
+            BatchOp::Put { key, value } => backend.put(key, value).await?,
+            BatchOp::Delete { key } => backend.delete(key).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Two-phase apply: log the batch, apply its writes, then drop the log
+/// record. Every write is idempotent, so a logged batch can be re-applied.
+pub async fn wal_commit<B: MemoryBackend + ?Sized>(backend: &B, batch: &WriteBatch) -> Result<()> {
+    if batch.ops.len() <= 1 {
+        return apply_ops(backend, &batch.ops).await;
+    }
+    let key = RecordKind::Wal.key(&batch.id);
+    backend.put(&key, &serde_json::to_value(batch)?).await?;
+    apply_ops(backend, &batch.ops).await?;
+    backend.delete(&key).await
+}
+
+/// Finish batches a crashed writer logged but did not finish applying.
+/// Batches younger than a grace period may still be in flight and are left
+/// alone. Returns how many were replayed.
+pub async fn replay_wal<B: MemoryBackend + ?Sized>(backend: &B) -> Result<usize> {
+    let cutoff = Utc::now() - ChronoDuration::seconds(WAL_GRACE_SECS);
+    let mut replayed = 0;
+    for key in backend.list(RecordKind::Wal.prefix()).await? {
+        let Some(value) = backend.get(&key).await? else {
+            continue;
+        };
+        let batch: WriteBatch = match serde_json::from_value(value) {
+            Ok(batch) => batch,
+            Err(e) => {
+                log::warn!("Dropping unreadable write-ahead log record {}: {}", key, e);
+                backend.delete(&key).await?;
+                continue;
+            }
+        };
+        if batch.created_at > cutoff {
+            continue;
+        }
+        apply_ops(backend, &batch.ops).await?;
+        backend.delete(&key).await?;
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+/// Backend that keeps records in process memory, for tests and tools that
+/// must run without a PluresDB server
+#[derive(Default)]
+pub struct InMemoryBackend {
+    records: Mutex<HashMap<String, (Value, u64)>>,
+}
+
+impl InMemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MemoryBackend for InMemoryBackend {
+    async fn put(&self, key: &str, value: &Value) -> Result<()> {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let revision = records.get(key).map_or(ABSENT, |(_, rev)| *rev) + 1;
+        records.insert(key.to_string(), (value.clone(), revision));
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Value>> {
+        Ok(self.get_revision(key).await?.map(|(value, _)| value))
+    }
+
+    async fn get_revision(&self, key: &str) -> Result<Option<(Value, u64)>> {
+        Ok(self
+            .records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .cloned())
+    }
+
+    async fn put_if_revision(&self, key: &str, value: &Value, expected: u64) -> Result<PutOutcome> {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let current = records.get(key).map_or(ABSENT, |(_, rev)| *rev);
+        if current != expected {
+            return Ok(PutOutcome::Conflict(current));
+        }
+        records.insert(key.to_string(), (value.clone(), current + 1));
+        Ok(PutOutcome::Written(current + 1))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys: Vec<String> = self
+            .records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn commit_batch(&self, batch: &WriteBatch) -> Result<()> {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        for op in &batch.ops {
+            match op {
+                BatchOp::Put { key, value } => {
+                    let revision = records.get(key).map_or(ABSENT, |(_, rev)| *rev) + 1;
+                    records.insert(key.clone(), (value.clone(), revision));
+                }
+                BatchOp::Delete { key } => {
+                    records.remove(key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+// Benchmark results
+// `memory:benchmark:<id>` holds each result; the latest one with the same signature is the baseline for the next
+
+use crate::execution::BenchmarkResult;
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use anyhow::Result;
+
+impl MemoryStore {
+    /// Stored benchmarks, oldest first, optionally only those of `signature`
+    pub async fn list_benchmarks(&self, signature: Option<&str>) -> Result<Vec<BenchmarkResult>> {
+        let mut results = Vec::new();
+        for key in self.client.list(RecordKind::Benchmark.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(result) = serde_json::from_value::<BenchmarkResult>(value) {
+                    if signature.is_none_or(|s| s == result.signature) {
+                        results.push(result);
+                    }
+                }
+            }
+        }
+        results.sort_by_key(|r| r.started_at);
+        Ok(results)
+    }
+
+    /// Compare `result` with the latest stored benchmark of its signature,
+    /// when there is one, then store it
+    pub async fn record_benchmark(&self, result: &mut BenchmarkResult) -> Result<()> {
+        if let Some(previous) = self
+            .list_benchmarks(Some(&result.signature))
+            .await?
+            .into_iter()
+            .rfind(|r| r.id != result.id)
+        {
+            result.compare_to(&previous);
+        }
+        self.write_value(
+            &RecordKind::Benchmark.key(&result.id),
+            serde_json::to_value(&*result)?,
+        )
+        .await
+    }
+}
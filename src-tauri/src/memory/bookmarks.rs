@@ -0,0 +1,168 @@
+// Bookmarks of command runs and output line ranges
+// Each bookmark pins a copy of its excerpt, so it still resolves after retention or quota GC drops the output
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::pages::MAX_PAGE_LINES;
+use crate::memory::schema::Command;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ts_rs::TS;
+
+/// Output lines `start..end`, counted from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A bookmarked command run
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Bookmark {
+    /// Permalink id: the same command and range always get the same id
+    pub id: String,
+    pub command_id: String,
+    pub session_id: String,
+    pub command_line: String,
+    pub cwd: String,
+    /// The bookmarked lines; `None` for the whole run
+    pub lines: Option<LineRange>,
+    /// Copy of the bookmarked output, at most [`MAX_PAGE_LINES`] lines
+    pub excerpt: Vec<String>,
+    /// The output had more lines than `excerpt` keeps
+    pub truncated: bool,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A bookmark with the command it points at, if that is still stored
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ResolvedBookmark {
+    pub bookmark: Bookmark,
+    pub command: Option<Command>,
+}
+
+/// Permalink id of a bookmark of `command_id`
+pub fn bookmark_id(command_id: &str, lines: Option<LineRange>) -> String {
+    let target = match lines {
+        Some(range) => format!("{}#L{}-{}", command_id, range.start, range.end),
+        None => command_id.to_string(),
+    };
+    hex::encode(&Sha256::digest(target.as_bytes())[..16])
+}
+
+impl MemoryStore {
+    /// Bookmark a command run, or `lines` of its output. Bookmarking the same
+    /// target again only updates the note; the excerpt stays as first pinned.
+    pub async fn bookmark_add(
+        &self,
+        command_id: &str,
+        lines: Option<LineRange>,
+        note: Option<String>,
+    ) -> Result<Bookmark> {
+        let id = bookmark_id(command_id, lines);
+        if let Some(mut bookmark) = self.bookmark_get(&id).await? {
+            bookmark.note = note;
+            self.write_value(
+                &RecordKind::Bookmark.key(&id),
+                serde_json::to_value(&bookmark)?,
+            )
+            .await?;
+            return Ok(bookmark);
+        }
+
+        let value = self
+            .read_value(&RecordKind::Command.key(command_id))
+            .await?
+            .with_context(|| format!("Command not found: {}", command_id))?;
+        let command: Command =
+            serde_json::from_value(value).context("Failed to deserialize command")?;
+        let (offset, limit) = match lines {
+            Some(range) if range.start >= range.end => {
+                anyhow::bail!("Empty line range {}..{}", range.start, range.end)
+            }
+            Some(range) if range.end - range.start > MAX_PAGE_LINES => {
+                anyhow::bail!("A bookmark keeps at most {} lines", MAX_PAGE_LINES)
+            }
+            Some(range) => (range.start, range.end - range.start),
+            None => (0, MAX_PAGE_LINES),
+        };
+        let indexes = self.output_line_indexes(command_id).await?;
+        let page = self
+            .read_output_lines(command_id, &indexes, offset, limit)
+            .await?;
+        if lines.is_some() && page.lines.is_empty() {
+            anyhow::bail!(
+                "Command {} has {} output lines, none in the range",
+                command_id,
+                page.total_lines
+            );
+        }
+
+        let bookmark = Bookmark {
+            id,
+            command_line: std::iter::once(command.command.as_str())
+                .chain(command.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" "),
+            command_id: command.id,
+            session_id: command.session_id,
+            cwd: command.cwd,
+            lines,
+            truncated: lines.is_none() && page.has_more,
+            excerpt: page.lines,
+            note,
+            created_at: Utc::now(),
+        };
+        self.write_value(
+            &RecordKind::Bookmark.key(&bookmark.id),
+            serde_json::to_value(&bookmark)?,
+        )
+        .await?;
+        Ok(bookmark)
+    }
+
+    async fn bookmark_get(&self, id: &str) -> Result<Option<Bookmark>> {
+        self.read_value(&RecordKind::Bookmark.key(id))
+            .await?
+            .map(|value| serde_json::from_value(value).context("Failed to deserialize bookmark"))
+            .transpose()
+    }
+
+    /// Look up a bookmark by its permalink id
+    pub async fn resolve_bookmark(&self, id: &str) -> Result<Option<ResolvedBookmark>> {
+        let Some(bookmark) = self.bookmark_get(id).await? else {
+            return Ok(None);
+        };
+        let command = self
+            .read_value(&RecordKind::Command.key(&bookmark.command_id))
+            .await?
+            .and_then(|value| serde_json::from_value(value).ok());
+        Ok(Some(ResolvedBookmark { bookmark, command }))
+    }
+
+    pub async fn bookmark_delete(&self, id: &str) -> Result<()> {
+        self.client.delete(&RecordKind::Bookmark.key(id)).await
+    }
+
+    /// Bookmarks, newest first, optionally only those of one session
+    pub async fn bookmark_list(&self, session_id: Option<&str>) -> Result<Vec<Bookmark>> {
+        let mut bookmarks = Vec::new();
+        for key in self.client.list(RecordKind::Bookmark.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(bookmark) = serde_json::from_value::<Bookmark>(value) {
+                    if session_id.is_none_or(|id| bookmark.session_id == id) {
+                        bookmarks.push(bookmark);
+                    }
+                }
+            }
+        }
+        bookmarks.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+        Ok(bookmarks)
+    }
+}
@@ -0,0 +1,82 @@
+// Canvas documents in PluresDB
+// Same layout as the frontend's PluresDBAdapter: `runebook:canvas:<id>` holds {canvas, timestamp}, `runebook:meta:<id>` the listing entry
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Listing entry for a saved canvas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanvasSummary {
+    pub id: String,
+    pub name: String,
+    /// Milliseconds since the epoch, as written by the frontend
+    pub timestamp: i64,
+}
+
+impl MemoryStore {
+    /// Save a canvas document (the JSON form of the frontend `Canvas` type).
+    /// The document is stored as-is so fields Rust does not model survive.
+    pub async fn canvas_save(&self, canvas: &Value) -> Result<CanvasSummary> {
+        let id = canvas
+            .get("id")
+            .and_then(Value::as_str)
+            .filter(|id| !id.is_empty())
+            .context("Canvas has no id")?;
+        let name = canvas.get("name").and_then(Value::as_str).unwrap_or(id);
+        if !canvas.get("nodes").is_some_and(Value::is_array) {
+            anyhow::bail!("Canvas {} has no nodes array", id);
+        }
+
+        let summary = CanvasSummary {
+            id: id.to_string(),
+            name: name.to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+        };
+        self.client
+            .put(
+                &RecordKind::Canvas.key(id),
+                &serde_json::json!({ "canvas": canvas, "timestamp": summary.timestamp }),
+            )
+            .await?;
+        self.client
+            .put(
+                &RecordKind::CanvasMeta.key(id),
+                &serde_json::to_value(&summary)?,
+            )
+            .await?;
+        Ok(summary)
+    }
+
+    pub async fn canvas_load(&self, id: &str) -> Result<Option<Value>> {
+        Ok(self
+            .client
+            .get(&RecordKind::Canvas.key(id))
+            .await?
+            .and_then(|mut data| data.get_mut("canvas").map(Value::take)))
+    }
+
+    /// Saved canvases, newest first
+    pub async fn canvas_list(&self) -> Result<Vec<CanvasSummary>> {
+        let mut canvases = Vec::new();
+        for key in self.client.list(RecordKind::CanvasMeta.prefix()).await? {
+            if let Some(summary) = self
+                .client
+                .get(&key)
+                .await?
+                .and_then(|v| serde_json::from_value::<CanvasSummary>(v).ok())
+            {
+                canvases.push(summary);
+            }
+        }
+        canvases.sort_by_key(|c| std::cmp::Reverse(c.timestamp));
+        Ok(canvases)
+    }
+
+    pub async fn canvas_delete(&self, id: &str) -> Result<()> {
+        self.client.delete(&RecordKind::Canvas.key(id)).await?;
+        self.client.delete(&RecordKind::CanvasMeta.key(id)).await
+    }
+}
@@ -7,14 +7,19 @@
 // Alternatively, consider using a Rust FFI binding to PluresDB
 // if available, or the SQLiteCompatibleAPI via FFI.
 
+use crate::memory::backend::{wal_commit, MemoryBackend, PutOutcome, WriteBatch, ABSENT};
 use anyhow::{Context, Result};
-use reqwest::Client;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
 use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 pub struct PluresDBClient {
     client: Client,
     base_url: String,
+    /// Set once the server turns out not to have the batch endpoint
+    no_native_batches: AtomicBool,
 }
 
 impl PluresDBClient {
@@ -26,7 +31,11 @@ impl PluresDBClient {
             .build()
             .context("Failed to create HTTP client")?;
 
-        Ok(Self { client, base_url })
+        Ok(Self {
+            client,
+            base_url,
+            no_native_batches: AtomicBool::new(false),
+        })
     }
 
     /// Put a value into PluresDB
@@ -83,6 +92,87 @@ impl PluresDBClient {
         Ok(result.get("value").cloned())
     }
 
+    /// Get a value and its revision. A server that does not report revisions
+    /// yields [`ABSENT`], which makes conditional writes unconditional.
+    pub async fn get_revision(&self, key: &str) -> Result<Option<(Value, u64)>> {
+        let url = format!("{}/api/v1/get", self.base_url);
+        let payload = serde_json::json!({
+            "key": key,
+        });
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&payload)
+            .send()
+            .await
+            .context("Failed to send GET request")?;
+
+        if response.status() == 404 {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("PluresDB GET failed with status {}: {}", status, text);
+        }
+
+        let result: Value = response.json().await.context("Failed to parse response")?;
+        let revision = result
+            .get("revision")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(ABSENT);
+        Ok(result.get("value").cloned().map(|value| (value, revision)))
+    }
+
+    /// Put a value only if the stored revision is still `expected`. The
+    /// server answers 409 or 412 with the current revision on a mismatch.
+    pub async fn put_if_revision(
+        &self,
+        key: &str,
+        value: &Value,
+        expected: u64,
+    ) -> Result<PutOutcome> {
+        let url = format!("{}/api/v1/put", self.base_url);
+        let payload = serde_json::json!({
+            "key": key,
+            "value": value,
+            "if_revision": expected,
+        });
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&payload)
+            .send()
+            .await
+            .context("Failed to send PUT request")?;
+
+        let status = response.status();
+        if status == StatusCode::CONFLICT || status == StatusCode::PRECONDITION_FAILED {
+            let result: Value = response.json().await.unwrap_or_default();
+            let current = result
+                .get("revision")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(ABSENT);
+            return Ok(PutOutcome::Conflict(current));
+        }
+
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("PluresDB PUT failed with status {}: {}", status, text);
+        }
+
+        let result: Value = response.json().await.unwrap_or_default();
+        Ok(PutOutcome::Written(
+            result
+                .get("revision")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(expected + 1),
+        ))
+    }
+
     /// List keys with a prefix
     pub async fn list(&self, prefix: &str) -> Result<Vec<String>> {
         let url = format!("{}/api/v1/list", self.base_url);
@@ -140,6 +230,42 @@ impl PluresDBClient {
         Ok(())
     }
 
+    /// Apply a batch in one server-side transaction. Servers without the
+    /// batch endpoint get the write-ahead log instead.
+    pub async fn commit_batch(&self, batch: &WriteBatch) -> Result<()> {
+        if batch.ops.len() <= 1 || self.no_native_batches.load(Ordering::Relaxed) {
+            return wal_commit(self, batch).await;
+        }
+        let url = format!("{}/api/v1/batch", self.base_url);
+        let payload = serde_json::json!({
+            "ops": batch.ops,
+        });
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&payload)
+            .send()
+            .await
+            .context("Failed to send BATCH request")?;
+
+        let status = response.status();
+        if matches!(
+            status,
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+        ) {
+            self.no_native_batches.store(true, Ordering::Relaxed);
+            return wal_commit(self, batch).await;
+        }
+
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("PluresDB BATCH failed with status {}: {}", status, text);
+        }
+
+        Ok(())
+    }
+
     /// Check if PluresDB server is available
     pub async fn health_check(&self) -> Result<bool> {
         let url = format!("{}/health", self.base_url);
@@ -149,3 +275,38 @@ impl PluresDBClient {
         }
     }
 }
+
+#[async_trait]
+impl MemoryBackend for PluresDBClient {
+    async fn put(&self, key: &str, value: &Value) -> Result<()> {
+        PluresDBClient::put(self, key, value).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Value>> {
+        PluresDBClient::get(self, key).await
+    }
+
+    async fn get_revision(&self, key: &str) -> Result<Option<(Value, u64)>> {
+        PluresDBClient::get_revision(self, key).await
+    }
+
+    async fn put_if_revision(&self, key: &str, value: &Value, expected: u64) -> Result<PutOutcome> {
+        PluresDBClient::put_if_revision(self, key, value, expected).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        PluresDBClient::list(self, prefix).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        PluresDBClient::delete(self, key).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        PluresDBClient::health_check(self).await
+    }
+
+    async fn commit_batch(&self, batch: &WriteBatch) -> Result<()> {
+        PluresDBClient::commit_batch(self, batch).await
+    }
+}
@@ -0,0 +1,234 @@
+// Compression codecs for stored output chunks
+// Each chunk records the codec it was written with, so the configured one can change without rewriting old chunks
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::time::Instant;
+use ts_rs::TS;
+
+/// A codec chunks can be stored with, as recorded on each chunk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum CodecId {
+    /// Stored as captured
+    None,
+    Gzip,
+    #[default]
+    Zstd,
+    Lz4,
+}
+
+impl CodecId {
+    pub const ALL: [CodecId; 4] = [CodecId::None, CodecId::Gzip, CodecId::Zstd, CodecId::Lz4];
+
+    /// The codec at its default level
+    pub fn codec(self) -> Box<dyn Codec> {
+        match self {
+            CodecId::None => Box::new(Identity),
+            CodecId::Gzip => Box::new(Gzip),
+            CodecId::Zstd => Box::new(Zstd { level: 3 }),
+            CodecId::Lz4 => Box::new(Lz4),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CodecId::None => "none",
+            CodecId::Gzip => "gzip",
+            CodecId::Zstd => "zstd",
+            CodecId::Lz4 => "lz4",
+        }
+    }
+}
+
+/// Compresses chunks and reads them back. Adding a codec takes an
+/// implementation and a [`CodecId`] for chunks to be tagged with.
+pub trait Codec: Send + Sync {
+    fn id(&self) -> CodecId;
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>>;
+
+    /// A reader of the uncompressed bytes of `compressed`
+    fn decoder<'a>(&self, compressed: &'a [u8]) -> Result<Box<dyn Read + 'a>>;
+
+    fn decompress(&self, compressed: &[u8]) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.decoder(compressed)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+struct Identity;
+
+impl Codec for Identity {
+    fn id(&self) -> CodecId {
+        CodecId::None
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+
+    fn decoder<'a>(&self, compressed: &'a [u8]) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(compressed))
+    }
+}
+
+struct Gzip;
+
+impl Codec for Gzip {
+    fn id(&self) -> CodecId {
+        CodecId::Gzip
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes)?;
+        Ok(encoder.finish()?)
+    }
+
+    fn decoder<'a>(&self, compressed: &'a [u8]) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(flate2::read::GzDecoder::new(compressed)))
+    }
+}
+
+/// zstd at `level`; any level reads back the same way
+pub struct Zstd {
+    pub level: i32,
+}
+
+impl Codec for Zstd {
+    fn id(&self) -> CodecId {
+        CodecId::Zstd
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(zstd::encode_all(bytes, self.level)?)
+    }
+
+    fn decoder<'a>(&self, compressed: &'a [u8]) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(zstd::stream::read::Decoder::new(compressed)?))
+    }
+}
+
+/// LZ4 frames
+struct Lz4;
+
+impl Codec for Lz4 {
+    fn id(&self) -> CodecId {
+        CodecId::Lz4
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(bytes)?;
+        Ok(encoder.finish()?)
+    }
+
+    fn decoder<'a>(&self, compressed: &'a [u8]) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(lz4_flex::frame::FrameDecoder::new(compressed)))
+    }
+}
+
+/// How one codec fared on a sample
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodecBenchmark {
+    pub codec: CodecId,
+    pub input_bytes: u64,
+    pub compressed_bytes: u64,
+    /// Uncompressed over compressed size
+    pub ratio: f64,
+    /// Uncompressed MiB per second
+    pub compress_mib_s: f64,
+    pub decompress_mib_s: f64,
+}
+
+/// Compress and decompress `sample` `rounds` times with every codec
+pub fn benchmark_codecs(sample: &[u8], rounds: usize) -> Result<Vec<CodecBenchmark>> {
+    let rounds = rounds.max(1);
+    let mib = (sample.len() * rounds) as f64 / (1024.0 * 1024.0);
+    let mut results = Vec::new();
+    for id in CodecId::ALL {
+        let codec = id.codec();
+        let started = Instant::now();
+        let mut compressed = Vec::new();
+        for _ in 0..rounds {
+            compressed = codec.compress(sample)?;
+        }
+        let compress_secs = started.elapsed().as_secs_f64();
+        let started = Instant::now();
+        for _ in 0..rounds {
+            anyhow::ensure!(
+                codec.decompress(&compressed)? == sample,
+                "{} did not round-trip",
+                id.as_str()
+            );
+        }
+        let decompress_secs = started.elapsed().as_secs_f64();
+        results.push(CodecBenchmark {
+            codec: id,
+            input_bytes: sample.len() as u64,
+            compressed_bytes: compressed.len() as u64,
+            ratio: sample.len() as f64 / compressed.len().max(1) as f64,
+            compress_mib_s: mib / compress_secs.max(f64::EPSILON),
+            decompress_mib_s: mib / decompress_secs.max(f64::EPSILON),
+        });
+    }
+    Ok(results)
+}
+
+/// About `bytes` of made-up but typical terminal output: a coloured build
+/// log, test results, JSON log lines and a backtrace, repeated with varying
+/// numbers so no codec gets an unrealistically easy ride
+pub fn sample_output(bytes: usize) -> Vec<u8> {
+    let mut out = String::with_capacity(bytes + 512);
+    let mut i: u64 = 0;
+    while out.len() < bytes {
+        // A small LCG keeps the sample the same on every run
+        i = i
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let n = i >> 33;
+        match n % 5 {
+            0 => out.push_str(&format!(
+                "\x1b[1m\x1b[32m   Compiling\x1b[0m crate-{} v0.{}.{} (/home/dev/src/crate-{})\n",
+                n % 97,
+                n % 13,
+                n % 7,
+                n % 97
+            )),
+            1 => out.push_str(&format!(
+                "test module_{}::tests::case_{} ... \x1b[32mok\x1b[0m\n",
+                n % 41,
+                n % 1000
+            )),
+            2 => out.push_str(&format!(
+                "{{\"ts\":\"2026-03-{:02}T{:02}:{:02}:{:02}.{:03}Z\",\"level\":\"info\",\"msg\":\"request handled\",\"status\":{},\"ms\":{}}}\n",
+                n % 28 + 1,
+                n % 24,
+                n % 60,
+                (n / 60) % 60,
+                n % 1000,
+                [200, 201, 204, 404, 500][(n % 5) as usize],
+                n % 900
+            )),
+            3 => out.push_str(&format!(
+                "warning: unused variable: `value_{}`\n  --> src/module_{}.rs:{}:{}\n   |\n",
+                n % 50,
+                n % 41,
+                n % 800,
+                n % 80
+            )),
+            _ => out.push_str(&format!(
+                "   {}: runebook::handler::process_{}\n             at ./src/handler.rs:{}:{}\n",
+                n % 30,
+                n % 17,
+                n % 600,
+                n % 40
+            )),
+        }
+    }
+    out.into_bytes()
+}
@@ -0,0 +1,136 @@
+// Content-type detection for captured output
+// Sniffs binary payloads so they are stored as blobs with a hexdump preview
+
+use serde::{Deserialize, Serialize};
+
+/// Share of undecodable bytes above which a chunk may be binary
+const INVALID_UTF8_RATIO: f64 = 0.1;
+
+/// Share of non-text control bytes that, together with invalid UTF-8, marks a
+/// chunk as binary; legacy-encoded text (Windows-1252, Shift-JIS) has almost none
+const CONTROL_RATIO: f64 = 0.05;
+
+/// Bytes shown in the hexdump preview of a binary payload
+pub const HEXDUMP_PREVIEW_BYTES: usize = 256;
+
+/// Detected content type of a captured chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentInfo {
+    pub mime_type: String,
+    pub binary: bool,
+}
+
+/// Known file signatures, checked in order. Those made of printable ASCII
+/// (`MZ`, `%PDF-`, `GIF89a`) also start ordinary text, so they only count
+/// when the chunk is not text anyway.
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\x7fELF", "application/x-elf"),
+    (b"MZ", "application/x-msdownload"),
+    (b"SQLite format 3\0", "application/vnd.sqlite3"),
+];
+
+/// Detect the content type of a chunk from magic numbers, NUL bytes,
+/// and the ratio of invalid UTF-8
+pub fn detect_content(bytes: &[u8]) -> ContentInfo {
+    let text = is_text(bytes);
+    let signature = MAGIC.iter().find(|(magic, _)| {
+        bytes.starts_with(magic) && !(text && magic.iter().all(u8::is_ascii_graphic))
+    });
+    if let Some((_, mime)) = signature {
+        return ContentInfo {
+            mime_type: mime.to_string(),
+            binary: true,
+        };
+    }
+
+    let binary = is_binary(bytes);
+    ContentInfo {
+        mime_type: if binary {
+            "application/octet-stream"
+        } else {
+            "text/plain"
+        }
+        .to_string(),
+        binary,
+    }
+}
+
+/// Whether a chunk is UTF-8 without NULs; a sequence cut off at the end of
+/// the chunk still counts
+fn is_text(bytes: &[u8]) -> bool {
+    !bytes.contains(&0)
+        && match std::str::from_utf8(bytes) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        }
+}
+
+/// Whether a chunk looks like binary rather than (possibly ANSI-coloured) text
+pub fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.is_empty() {
+        return false;
+    }
+    if bytes.contains(&0) {
+        return true;
+    }
+
+    let mut invalid = 0usize;
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(_) => break,
+            Err(e) => {
+                // A sequence cut off at the end of the chunk is not an error
+                let Some(len) = e.error_len() else { break };
+                invalid += len;
+                rest = &rest[e.valid_up_to() + len..];
+            }
+        }
+    }
+    if (invalid as f64 / bytes.len() as f64) <= INVALID_UTF8_RATIO {
+        return false;
+    }
+
+    let controls = bytes
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x07 | 0x08 | 0x0c | 0x1b))
+        .count();
+    controls as f64 / bytes.len() as f64 > CONTROL_RATIO
+}
+
+/// Render the first `max_bytes` as a classic `offset  hex  |ascii|` dump
+pub fn hexdump(bytes: &[u8], max_bytes: usize) -> String {
+    let shown = &bytes[..bytes.len().min(max_bytes)];
+    let mut out = String::new();
+    for (row, line) in shown.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{:08x}  {:<47}  |{}|\n",
+            row * 16,
+            hex.join(" "),
+            ascii
+        ));
+    }
+    if bytes.len() > shown.len() {
+        out.push_str(&format!("... {} more bytes\n", bytes.len() - shown.len()));
+    }
+    out
+}
@@ -0,0 +1,275 @@
+// Demo data for onboarding and end-to-end tests
+// One made-up shell session in the demo workspace, with failures, insights, suggestions and an example canvas; wipe_demo_data removes it all
+
+use crate::memory::api::MemoryStore;
+use crate::memory::backend::WriteBatch;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use ts_rs::TS;
+
+/// Workspace every demo record belongs to; no real session runs here
+pub const DEMO_WORKSPACE: &str = "/demo/runebook";
+/// Tag on the demo session and its commands
+pub const DEMO_TAG: &str = "demo";
+pub const DEMO_SESSION_ID: &str = "demo-session";
+pub const DEMO_CANVAS_ID: &str = "demo-hello-world";
+
+const DEMO_CANVAS: &str = include_str!("../../../static/examples/hello-world.yaml");
+
+/// What `seed_demo_data` wrote
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DemoSummary {
+    pub workspace: String,
+    pub session_id: String,
+    pub commands: usize,
+    pub errors: usize,
+    pub insights: usize,
+    pub suggestions: usize,
+    pub canvas_id: String,
+}
+
+/// A command of the demo session, with its exit code, how many seconds it
+/// took, and the severity and stderr of a failure
+struct DemoCommand {
+    line: &'static str,
+    exit_code: i32,
+    secs: i64,
+    stderr: Option<(&'static str, &'static str)>,
+}
+
+const COMMANDS: &[DemoCommand] = &[
+    DemoCommand {
+        line: "git pull --rebase",
+        exit_code: 0,
+        secs: 2,
+        stderr: None,
+    },
+    DemoCommand {
+        line: "npm install",
+        exit_code: 0,
+        secs: 41,
+        stderr: None,
+    },
+    DemoCommand {
+        line: "npm test",
+        exit_code: 1,
+        secs: 18,
+        stderr: Some((
+            "medium",
+            "FAIL src/api/client.test.ts\n  TypeError: fetch is not a function\n    at getUser (src/api/client.ts:12:10)",
+        )),
+    },
+    DemoCommand {
+        line: "npm test",
+        exit_code: 0,
+        secs: 17,
+        stderr: None,
+    },
+    DemoCommand {
+        line: "cargo build --release",
+        exit_code: 101,
+        secs: 96,
+        stderr: Some((
+            "high",
+            "error[E0425]: cannot find value `config` in this scope\n --> server/src/main.rs:27:18",
+        )),
+    },
+    DemoCommand {
+        line: "cargo build --release",
+        exit_code: 0,
+        secs: 104,
+        stderr: None,
+    },
+    DemoCommand {
+        line: "docker compose up -d",
+        exit_code: 1,
+        secs: 3,
+        stderr: Some((
+            "medium",
+            "Error response from daemon: driver failed programming external connectivity: Bind for 0.0.0.0:5432 failed: port is already allocated",
+        )),
+    },
+];
+
+impl MemoryStore {
+    /// Fill the store with a realistic demo session and install an example
+    /// canvas. Seeding again replaces the earlier demo data. Demo commands do
+    /// not count towards the daily statistics.
+    pub async fn seed_demo_data(&self) -> Result<DemoSummary> {
+        self.wipe_demo_data().await?;
+
+        let dir = format!("{}/webapp", DEMO_WORKSPACE);
+        let start = Utc::now() - Duration::minutes(50);
+        let mut session = Session::new("zsh".to_string(), dir.clone());
+        session.id = DEMO_SESSION_ID.to_string();
+        session.started_at = start;
+        session.metadata = json!({ "workspace": DEMO_WORKSPACE, "demo": true });
+        session.tags = vec![DEMO_TAG.to_string()];
+
+        let mut batch = WriteBatch::new();
+        batch.put(
+            RecordKind::Session.key(&session.id),
+            serde_json::to_value(&session)?,
+        );
+
+        let mut at = start;
+        let mut failed = Vec::new();
+        for (index, demo) in COMMANDS.iter().enumerate() {
+            let mut words = demo.line.split(' ').map(String::from);
+            let program = words.next().unwrap_or_default();
+            let mut command =
+                Command::new(session.id.clone(), program, words.collect(), dir.clone());
+            command.id = format!("{}-{}", DEMO_SESSION_ID, index);
+            command.started_at = at;
+            command.ended_at = Some(at + Duration::seconds(demo.secs));
+            command.duration_ms = Some(demo.secs as u64 * 1000);
+            command.exit_code = Some(demo.exit_code);
+            command.success = demo.exit_code == 0;
+            command.tags = vec![DEMO_TAG.to_string()];
+            at += Duration::seconds(demo.secs + 90);
+
+            if let Some((severity, stderr)) = demo.stderr {
+                let mut output = Output::new(
+                    command.id.clone(),
+                    "stderr".to_string(),
+                    0,
+                    stderr.as_bytes().to_vec(),
+                );
+                output.timestamp = command.ended_at.unwrap_or(at);
+                self.stage_output(&mut batch, &mut output, false).await?;
+
+                let mut error = Error::new(
+                    command.id.clone(),
+                    session.id.clone(),
+                    "exit_code".to_string(),
+                    severity.to_string(),
+                    stderr.lines().next().unwrap_or(stderr).to_string(),
+                );
+                error.id = format!("demo-error-{}", index);
+                error.stderr_snippet = Some(stderr.to_string());
+                error.exit_code = Some(demo.exit_code);
+                error.timestamp = output.timestamp;
+                self.stage_error(&mut batch, error).await?;
+                failed.push(command.id.clone());
+            }
+            // Staged directly, so the demo never lands in the day counters
+            self.stage_command(&mut batch, &mut command).await?;
+        }
+
+        let insights = [
+            (
+                "pattern",
+                "Tests break after dependency updates",
+                "npm test failed right after npm install pulled new packages; pin or review updated dependencies.",
+                Some(&failed[0]),
+            ),
+            (
+                "optimization",
+                "Release builds dominate this session",
+                "cargo build --release took over three minutes in total. A debug build is enough while fixing compile errors.",
+                None,
+            ),
+        ];
+        let insight_count = insights.len();
+        for (index, (kind, title, description, command_id)) in insights.into_iter().enumerate() {
+            let mut insight = Insight::new(
+                kind.to_string(),
+                title.to_string(),
+                description.to_string(),
+                0.8,
+                "heuristic".to_string(),
+            );
+            insight.id = format!("demo-insight-{}", index);
+            insight.session_id = Some(session.id.clone());
+            insight.command_id = command_id.cloned();
+            insight.metadata = json!({ "demo": true });
+            batch.put(
+                RecordKind::Insight.key(&insight.id),
+                self.encrypt_value(serde_json::to_value(&insight)?).await?,
+            );
+        }
+
+        let suggestions = [
+            (
+                "command",
+                "high",
+                "Free port 5432 before starting the stack",
+                "Another process is listening on the Postgres port docker compose needs.",
+                "lsof",
+                vec!["-i", ":5432"],
+            ),
+            (
+                "optimization",
+                "medium",
+                "Use npm ci in this project",
+                "npm ci installs exactly what package-lock.json records, so updates cannot break the tests unnoticed.",
+                "npm",
+                vec!["ci"],
+            ),
+        ];
+        let suggestion_count = suggestions.len();
+        for (index, (kind, priority, title, description, program, args)) in
+            suggestions.into_iter().enumerate()
+        {
+            let mut suggestion = Suggestion::new(
+                kind.to_string(),
+                priority.to_string(),
+                0.9 - index as f64 * 0.2,
+                title.to_string(),
+                description.to_string(),
+            );
+            suggestion.id = format!("demo-suggestion-{}", index);
+            suggestion.command = Some(program.to_string());
+            suggestion.args = Some(args.into_iter().map(String::from).collect());
+            suggestion.context = json!({ "workspace": DEMO_WORKSPACE, "demo": true });
+            batch.put(
+                RecordKind::Suggestion.key(&suggestion.id),
+                self.encrypt_value(serde_json::to_value(&suggestion)?)
+                    .await?,
+            );
+        }
+        self.commit(batch).await?;
+
+        let mut canvas: Value =
+            serde_yaml::from_str(DEMO_CANVAS).context("Example canvas is not valid YAML")?;
+        canvas["id"] = json!(DEMO_CANVAS_ID);
+        canvas["name"] = json!("Demo: Hello World");
+        self.canvas_save(&canvas).await?;
+
+        Ok(DemoSummary {
+            workspace: DEMO_WORKSPACE.to_string(),
+            session_id: session.id,
+            commands: COMMANDS.len(),
+            errors: failed.len(),
+            insights: insight_count,
+            suggestions: suggestion_count,
+            canvas_id: DEMO_CANVAS_ID.to_string(),
+        })
+    }
+
+    /// Remove everything `seed_demo_data` wrote. Returns the number of records
+    /// removed; zero when there was no demo data.
+    pub async fn wipe_demo_data(&self) -> Result<usize> {
+        let mut removed = self.delete_session(DEMO_SESSION_ID).await?;
+        for key in self.client.list(RecordKind::Suggestion.prefix()).await? {
+            let Some(raw) = self.client.get(&key).await? else {
+                continue;
+            };
+            let value = self.decrypt_value(raw).await?;
+            if value.pointer("/context/demo") == Some(&Value::Bool(true)) {
+                self.client.delete(&key).await?;
+                removed += 1;
+            }
+        }
+        if self.canvas_load(DEMO_CANVAS_ID).await?.is_some() {
+            self.canvas_delete(DEMO_CANVAS_ID).await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,294 @@
+// Editor diagnostics from classified errors
+// Finds the file locations failed commands complained about and exports them as LSP publishDiagnostics params
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use ts_rs::TS;
+
+/// A file position an error refers to. Parsers store these in
+/// `Error.context.locations`; 1-based like compiler output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>,
+    /// What is wrong at this location; the error message when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// "error", "warning", "info" or "hint"; from the error severity when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    /// Compiler code such as "E0308"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+/// LSP `Position`, 0-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// LSP `Range`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// LSP `Diagnostic`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Diagnostic {
+    pub range: Range,
+    /// 1 error, 2 warning, 3 information, 4 hint
+    pub severity: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub code: Option<String>,
+    pub source: String,
+    pub message: String,
+    /// `error_id` and `command_id` of the record it came from
+    pub data: serde_json::Value,
+}
+
+/// LSP `PublishDiagnosticsParams` for one file
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileDiagnostics {
+    pub uri: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+fn location_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        // `path:line[:col][: message]`, rustc's `--> path:line:col` and
+        // Python's `File "path", line N`
+        Regex::new(
+            r#"(?:File "(?P<py_file>[^"]+)", line (?P<py_line>\d+))|(?P<file>[\w.~/\\-]*\.[A-Za-z0-9]+):(?P<line>\d+)(?::(?P<col>\d+))?(?::\s*(?P<msg>.+))?"#,
+        )
+        .unwrap()
+    })
+}
+
+fn header_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"^(?P<level>error|warning)(?:\[(?P<code>[A-Z]+\d+)\])?: (?P<msg>.+)$").unwrap()
+    })
+}
+
+/// File locations mentioned in compiler-style text. A location on an
+/// rustc `-->` line takes its message from the `error:` line above it.
+pub fn scan_locations(text: &str) -> Vec<SourceLocation> {
+    let mut locations = Vec::new();
+    let mut header: Option<(String, Option<String>, String)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(caps) = header_pattern().captures(trimmed) {
+            header = Some((
+                caps["level"].to_string(),
+                caps.name("code").map(|c| c.as_str().to_string()),
+                caps["msg"].to_string(),
+            ));
+            continue;
+        }
+        let Some(caps) = location_pattern().captures(trimmed) else {
+            continue;
+        };
+        let (file, line_no) = match (caps.name("py_file"), caps.name("file")) {
+            (Some(file), _) => (file.as_str(), &caps["py_line"]),
+            (None, Some(file)) if !file.as_str().starts_with("//") => {
+                (file.as_str(), &caps["line"])
+            }
+            _ => continue,
+        };
+        let Ok(line_no) = line_no.parse::<u32>() else {
+            continue;
+        };
+        let arrow = trimmed.starts_with("-->");
+        let (severity, code, header_message) = match (&header, arrow) {
+            (Some((level, code, msg)), true) => {
+                (Some(level.clone()), code.clone(), Some(msg.clone()))
+            }
+            _ => (None, None, None),
+        };
+        locations.push(SourceLocation {
+            file: file.to_string(),
+            line: line_no,
+            column: caps.name("col").and_then(|c| c.as_str().parse().ok()),
+            end_line: None,
+            end_column: None,
+            message: header_message.or_else(|| caps.name("msg").map(|m| m.as_str().to_string())),
+            severity,
+            code,
+        });
+    }
+    locations
+}
+
+/// Locations of an error: structured ones from `context.locations` when a
+/// parser stored them, else those mentioned in its message and stderr
+pub fn error_locations(error: &Error) -> Vec<SourceLocation> {
+    if let Some(locations) = error
+        .context
+        .get("locations")
+        .and_then(|l| serde_json::from_value::<Vec<SourceLocation>>(l.clone()).ok())
+    {
+        return locations;
+    }
+    let mut text = error.message.clone();
+    if let Some(snippet) = &error.stderr_snippet {
+        text.push('\n');
+        text.push_str(snippet);
+    }
+    let mut locations = scan_locations(&text);
+    locations.dedup_by(|a, b| a.file == b.file && a.line == b.line && a.column == b.column);
+    locations
+}
+
+/// Resolve `file` against `cwd` and drop `.` and `..` components
+fn resolve(cwd: &str, file: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in Path::new(cwd).join(file).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => resolved.push(other),
+        }
+    }
+    resolved
+}
+
+fn lsp_severity(location: &SourceLocation, error: &Error) -> u8 {
+    match location.severity.as_deref() {
+        Some("error") => 1,
+        Some("warning") => 2,
+        Some("info") | Some("note") => 3,
+        Some("hint") | Some("help") => 4,
+        _ => match error.severity.as_str() {
+            "critical" | "high" => 1,
+            "medium" => 2,
+            _ => 3,
+        },
+    }
+}
+
+fn diagnostic(location: &SourceLocation, error: &Error) -> Diagnostic {
+    let line = location.line.saturating_sub(1);
+    let start = Position {
+        line,
+        character: location.column.map_or(0, |c| c.saturating_sub(1)),
+    };
+    // Without an end, the rest of the line
+    let end = match location.end_line {
+        Some(end_line) => Position {
+            line: end_line.saturating_sub(1),
+            character: location.end_column.map_or(0, |c| c.saturating_sub(1)),
+        },
+        None => Position {
+            line: line + 1,
+            character: 0,
+        },
+    };
+    Diagnostic {
+        range: Range { start, end },
+        severity: lsp_severity(location, error),
+        code: location
+            .code
+            .clone()
+            .or_else(|| Some(error.error_type.clone())),
+        source: "runebook".to_string(),
+        message: location
+            .message
+            .clone()
+            .unwrap_or_else(|| error.message.clone()),
+        data: serde_json::json!({
+            "error_id": error.id,
+            "command_id": error.command_id,
+        }),
+    }
+}
+
+fn file_uri(path: &Path) -> String {
+    url::Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|_| format!("file://{}", path.display()))
+}
+
+impl MemoryStore {
+    /// Diagnostics for `path` (a file, a directory, or a `file://` URI),
+    /// one entry per file. Each file only gets the diagnostics of the latest
+    /// command that reported on it, so fixed errors do not linger.
+    pub async fn diagnostics_for_path(&self, path: &str) -> Result<Vec<FileDiagnostics>> {
+        let target = match url::Url::parse(path) {
+            Ok(uri) if uri.scheme() == "file" => uri
+                .to_file_path()
+                .map_err(|_| anyhow::anyhow!("Not a file path: {}", path))?,
+            _ => resolve("/", path),
+        };
+
+        let mut cwds: HashMap<String, Option<String>> = HashMap::new();
+        // file -> (command that last reported on it, its diagnostics)
+        let mut files: HashMap<PathBuf, (String, Vec<Diagnostic>)> = HashMap::new();
+        let mut errors = self.query_recent_errors(None, None, None).await?;
+        errors.sort_by_key(|e| e.timestamp);
+        for error in &errors {
+            let locations = error_locations(error);
+            if locations.is_empty() {
+                continue;
+            }
+            if !cwds.contains_key(&error.command_id) {
+                let cwd = self
+                    .read_value(&RecordKind::Command.key(&error.command_id))
+                    .await?
+                    .and_then(|v| serde_json::from_value::<Command>(v).ok())
+                    .map(|c| c.cwd);
+                cwds.insert(error.command_id.clone(), cwd);
+            }
+            let cwd = cwds[&error.command_id].as_deref().unwrap_or("/");
+            for location in &locations {
+                let file = resolve(cwd, &location.file);
+                if !file.starts_with(&target) {
+                    continue;
+                }
+                let entry = files
+                    .entry(file)
+                    .or_insert_with(|| (error.command_id.clone(), Vec::new()));
+                if entry.0 != error.command_id {
+                    // Errors are oldest first, so this command is newer
+                    *entry = (error.command_id.clone(), Vec::new());
+                }
+                entry.1.push(diagnostic(location, error));
+            }
+        }
+
+        let mut result: Vec<FileDiagnostics> = files
+            .into_iter()
+            .map(|(file, (_, diagnostics))| FileDiagnostics {
+                uri: file_uri(&file),
+                diagnostics,
+            })
+            .collect();
+        result.sort_by(|a, b| a.uri.cmp(&b.uri));
+        Ok(result)
+    }
+}
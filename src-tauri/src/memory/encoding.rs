@@ -0,0 +1,139 @@
+// Output decoding for non-UTF-8 locales
+// Decodes captured bytes with a configured encoding, or detects one when set to "auto"
+
+use crate::memory::ansi::AnsiMode;
+use crate::memory::codec::CodecId;
+use anyhow::Result;
+use encoding_rs::{Decoder, Encoding, UTF_8};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// `[output]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct EncodingConfig {
+    /// "auto", or a WHATWG encoding label such as "utf-8", "windows-1252", "shift_jis"
+    pub encoding: String,
+    /// Escape sequences in stored text output: "keep", "strip", or "spans" to
+    /// strip them and keep their colors alongside
+    pub ansi: AnsiMode,
+    /// Codec new output chunks are compressed with: "zstd", "gzip", "lz4" or
+    /// "none"
+    pub codec: CodecId,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        Self {
+            encoding: "auto".to_string(),
+            ansi: AnsiMode::Keep,
+            codec: CodecId::default(),
+        }
+    }
+}
+
+impl EncodingConfig {
+    /// The configured encoding, or `None` for automatic detection
+    pub fn resolve(&self) -> Result<Option<&'static Encoding>> {
+        if self.encoding.eq_ignore_ascii_case("auto") {
+            return Ok(None);
+        }
+        Encoding::for_label(self.encoding.as_bytes())
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("Unknown output encoding: {}", self.encoding))
+    }
+}
+
+/// Guess the encoding of a chunk: the one its byte order mark names, UTF-8
+/// if it decodes cleanly, otherwise chardetng's best guess
+pub fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if valid_utf8_prefix(bytes) {
+        return UTF_8;
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, false)
+}
+
+/// Decode a complete buffer, returning the text and the encoding used. A
+/// byte order mark of that encoding is dropped.
+pub fn decode(bytes: &[u8], config: &EncodingConfig) -> Result<(String, &'static Encoding)> {
+    let encoding = config.resolve()?.unwrap_or_else(|| detect_encoding(bytes));
+    let (text, _) = encoding.decode_with_bom_removal(bytes);
+    Ok((text.into_owned(), encoding))
+}
+
+/// Valid UTF-8, allowing a multi-byte sequence cut off at the end
+fn valid_utf8_prefix(bytes: &[u8]) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Streaming decoder for PTY output; sequences split across reads are carried over.
+///
+/// In auto mode it starts as UTF-8 and switches once to a detected encoding
+/// the first time the output is not valid UTF-8.
+pub struct OutputDecoder {
+    decoder: Decoder,
+    auto: bool,
+    /// In auto mode, the start of a UTF-8 sequence the last read ended in
+    pending: Vec<u8>,
+}
+
+impl OutputDecoder {
+    pub fn new(config: &EncodingConfig) -> Result<Self> {
+        let configured = config.resolve()?;
+        Ok(Self {
+            decoder: configured
+                .unwrap_or(UTF_8)
+                .new_decoder_without_bom_handling(),
+            auto: configured.is_none(),
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        self.decoder.encoding()
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut input = std::borrow::Cow::Borrowed(bytes);
+        if self.auto {
+            // Checked together with what the last read left unfinished, so a
+            // character split across reads is not taken for another encoding
+            let mut joined = std::mem::take(&mut self.pending);
+            joined.extend_from_slice(bytes);
+            match std::str::from_utf8(&joined) {
+                Ok(_) => {}
+                Err(e) if e.error_len().is_none() => {
+                    self.pending = joined[e.valid_up_to()..].to_vec();
+                }
+                Err(_) => {
+                    let detected = detect_encoding(&joined);
+                    log::info!(
+                        "[encoding] Switching output decoding to {}",
+                        detected.name()
+                    );
+                    // The UTF-8 decoder's carried-over bytes go to the new one
+                    self.decoder = detected.new_decoder_with_bom_removal();
+                    self.auto = false;
+                    input = std::borrow::Cow::Owned(joined);
+                }
+            }
+        }
+
+        let capacity = self
+            .decoder
+            .max_utf8_buffer_length(input.len())
+            .unwrap_or(input.len() * 3);
+        let mut text = String::with_capacity(capacity);
+        let _ = self.decoder.decode_to_string(&input, &mut text, false);
+        text
+    }
+}
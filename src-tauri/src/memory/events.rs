@@ -0,0 +1,83 @@
+// Store event bus
+// Finished commands and recorded errors, broadcast once their batch is committed, for consumers like alert rules
+
+use crate::memory::schema::*;
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+/// Events a slow consumer may fall behind by before it misses some
+const EVENT_BUFFER: usize = 256;
+
+/// Something a store wrote, as consumers see it: scrubbed, like the record
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum StoreEvent {
+    /// A command was stored finished for the first time, with the error
+    /// classified from its output
+    CommandFinished {
+        command: Box<Command>,
+        error: Option<Error>,
+    },
+    /// An error recorded on its own, such as one found in a followed log
+    ErrorRecorded { error: Error },
+}
+
+/// Shared event channel; attach to stores with [`MemoryStore::with_event_bus`](crate::memory::MemoryStore::with_event_bus)
+pub struct EventBus {
+    events: broadcast::Sender<StoreEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StoreEvent> {
+        self.events.subscribe()
+    }
+
+    /// Send `event` to current subscribers; nobody listening is fine
+    pub fn publish(&self, event: StoreEvent) {
+        let _ = self.events.send(event);
+    }
+}
+
+fn variable_part() -> &'static Regex {
+    static VARIABLE: OnceLock<Regex> = OnceLock::new();
+    VARIABLE.get_or_init(|| {
+        // UUIDs and hashes, then paths, then any other number
+        Regex::new(concat!(
+            r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b",
+            r"|\b(?:0x)?[0-9a-f]*\d[0-9a-f]*[a-f][0-9a-f]*\b",
+            r"|(?:[A-Za-z]:)?[\\/][^\s:'\x22]+",
+            r"|\d+(?:\.\d+)*",
+        ))
+        .unwrap()
+    })
+}
+
+impl Error {
+    /// What repeats of this error have in common, as 16 hex digits: its type
+    /// and its message without numbers, ids, hashes and paths. Rules and
+    /// searches can name an error by it across commands and sessions.
+    pub fn fingerprint(&self) -> String {
+        let message = variable_part().replace_all(self.message.trim(), "#");
+        let digest = Sha256::new()
+            .chain_update(self.error_type.as_bytes())
+            .chain_update([0])
+            .chain_update(message.to_lowercase().as_bytes())
+            .finalize();
+        hex::encode(&digest[..8])
+    }
+}
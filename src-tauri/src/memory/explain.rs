@@ -0,0 +1,300 @@
+// Suggestion explanations
+// Why a suggestion exists (its analyzer and the records that triggered it), how it ranks, and what would make it stop appearing
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::ranking::{features, rank, score};
+use crate::memory::rerun::RERUN_KIND;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use ts_rs::TS;
+
+/// Context keys that link a suggestion to the records behind it
+const EVIDENCE_KEYS: [(&str, RecordKind); 5] = [
+    ("error_id", RecordKind::Error),
+    ("command_id", RecordKind::Command),
+    ("insight_id", RecordKind::Insight),
+    ("session_id", RecordKind::Session),
+    ("expired_by", RecordKind::Command),
+];
+
+/// A record that triggered a suggestion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Evidence {
+    /// Record type, e.g. `error`
+    pub kind: String,
+    pub id: String,
+    /// The context key that links it, e.g. `error_id`
+    pub link: String,
+    /// One line about the record; `None` when it no longer exists, as after
+    /// retention removed it
+    pub summary: Option<String>,
+}
+
+/// How a suggestion scores under the current ranking weights
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RankingBreakdown {
+    pub score: f64,
+    pub features: BTreeMap<String, f64>,
+    /// Each weighted feature's part of `score`
+    pub contributions: BTreeMap<String, f64>,
+    /// Its position among the open suggestions, from 1; `None` once dismissed
+    pub position: Option<usize>,
+    pub open_suggestions: usize,
+    /// Times a surface showed it
+    pub times_shown: usize,
+    pub last_shown_at: Option<DateTime<Utc>>,
+}
+
+/// A condition under which the suggestion stops appearing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StopCondition {
+    /// Stable name of the condition, e.g. `dismissed` or `outranked`
+    pub key: String,
+    pub description: String,
+    /// Whether it already holds
+    pub met: bool,
+}
+
+/// Why a suggestion is shown, and what would stop it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SuggestionExplanation {
+    pub suggestion: Suggestion,
+    /// What produced it, e.g. `rerun_failed`, `deep-link`, or the tool of its
+    /// provenance record; `unknown` when nothing says
+    pub analyzer: String,
+    /// The reason [`reason_key`] gives it, which muting it names
+    pub reason_key: String,
+    /// Confidence its producer recorded, if any
+    pub confidence: Option<f64>,
+    pub evidence: Vec<Evidence>,
+    pub ranking: RankingBreakdown,
+    pub stop_conditions: Vec<StopCondition>,
+}
+
+/// The analyzer a suggestion's context names, when its provenance does not
+fn context_analyzer(context: &Value) -> Option<String> {
+    ["analyzer", "kind", "source"]
+        .iter()
+        .find_map(|key| context.get(*key).and_then(Value::as_str))
+        .map(String::from)
+        .or_else(|| (context["demo"] == true).then(|| "demo".to_string()))
+}
+
+/// `<analyzer>:<suggestion type>`, from the suggestion alone: what muting a
+/// suggestion hides, so every suggestion produced the same way is hidden too
+pub fn reason_key(suggestion: &Suggestion) -> String {
+    format!(
+        "{}:{}",
+        context_analyzer(&suggestion.context).unwrap_or_else(|| "unknown".to_string()),
+        suggestion.suggestion_type
+    )
+}
+
+fn summarize(kind: RecordKind, value: Value) -> Option<String> {
+    match kind {
+        RecordKind::Error => {
+            let error: Error = serde_json::from_value(value).ok()?;
+            Some(format!("{} error: {}", error.severity, error.message))
+        }
+        RecordKind::Command => {
+            let command: Command = serde_json::from_value(value).ok()?;
+            let line = std::iter::once(command.command.as_str())
+                .chain(command.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(match command.exit_code {
+                Some(code) => format!("{} (exit {}) in {}", line, code, command.cwd),
+                None => format!("{} in {}", line, command.cwd),
+            })
+        }
+        RecordKind::Insight => {
+            let insight: Insight = serde_json::from_value(value).ok()?;
+            Some(format!(
+                "{} insight: {}",
+                insight.insight_type, insight.title
+            ))
+        }
+        RecordKind::Session => {
+            let session: Session = serde_json::from_value(value).ok()?;
+            Some(format!(
+                "{} session started in {}",
+                session.shell_type, session.initial_cwd
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// What would make `suggestion` stop appearing, given its analyzer and where
+/// it ranks
+fn stop_conditions(
+    suggestion: &Suggestion,
+    analyzer: &str,
+    ranking: &RankingBreakdown,
+    next_above: Option<f64>,
+    muted: bool,
+) -> Vec<StopCondition> {
+    let context = &suggestion.context;
+    let condition = |key: &str, description: String, met: bool| StopCondition {
+        key: key.to_string(),
+        description,
+        met,
+    };
+    let mut conditions = vec![condition(
+        "dismissed",
+        "Dismissing it hides it everywhere".to_string(),
+        suggestion.dismissed,
+    )];
+    conditions.push(condition(
+        "muted",
+        format!(
+            "Muting its reason, {}, hides it and every suggestion like it",
+            reason_key(suggestion)
+        ),
+        muted,
+    ));
+    if analyzer == RERUN_KIND {
+        let tool = context["tool"].as_str().unwrap_or("the same tool");
+        let cwd = context["cwd"].as_str().unwrap_or("the same directory");
+        conditions.push(condition(
+            "test_run_finished",
+            format!(
+                "The next {} test run in {} retires it, whether it passes or fails anew",
+                tool, cwd
+            ),
+            context.get("expired_at").is_some(),
+        ));
+    }
+    if let Some(dir) = ["cwd", "workspace"]
+        .iter()
+        .find_map(|key| context.get(*key).and_then(Value::as_str))
+    {
+        conditions.push(condition(
+            "outside_workspace",
+            format!(
+                "Exit summaries only offer it for commands run under {}",
+                dir
+            ),
+            false,
+        ));
+    }
+    // Exit summaries show one suggestion, the best ranked
+    if !suggestion.dismissed {
+        let description = match (ranking.position, next_above) {
+            (Some(1), _) => format!(
+                "A suggestion scoring above {:.2} would take its place at the top",
+                ranking.score
+            ),
+            (_, Some(above)) => format!(
+                "It ranks below {:.2}, the score of the suggestion above it, so single-suggestion surfaces skip it",
+                above
+            ),
+            _ => "Other suggestions outrank it".to_string(),
+        };
+        conditions.push(condition(
+            "outranked",
+            description,
+            ranking.position.is_some_and(|p| p > 1),
+        ));
+    }
+    conditions
+}
+
+impl MemoryStore {
+    /// Explain suggestion `id`: its analyzer, the records that triggered it,
+    /// its ranking under the current weights, and what would make it stop
+    /// appearing. `None` when there is no such suggestion.
+    pub async fn explain_suggestion(&self, id: &str) -> Result<Option<SuggestionExplanation>> {
+        let Some(suggestion) = self
+            .read_value(&RecordKind::Suggestion.key(id))
+            .await?
+            .and_then(|v| serde_json::from_value::<Suggestion>(v).ok())
+        else {
+            return Ok(None);
+        };
+
+        let mut provenance = None;
+        for key in self.client.list(RecordKind::Provenance.prefix()).await? {
+            if let Some(record) = self
+                .read_value(&key)
+                .await?
+                .and_then(|v| serde_json::from_value::<Provenance>(v).ok())
+                .filter(|p| p.entity_type == "suggestion" && p.entity_id == id)
+            {
+                provenance = Some(record);
+                break;
+            }
+        }
+        let analyzer = provenance
+            .as_ref()
+            .and_then(|p| p.tool.clone().or_else(|| p.model.clone()))
+            .or_else(|| context_analyzer(&suggestion.context))
+            .or_else(|| provenance.as_ref().map(|p| p.source.clone()))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let mut evidence = Vec::new();
+        for (link, kind) in EVIDENCE_KEYS {
+            let Some(record_id) = suggestion.context.get(link).and_then(Value::as_str) else {
+                continue;
+            };
+            let summary = self
+                .read_value(&kind.key(record_id))
+                .await?
+                .and_then(|value| summarize(kind, value));
+            evidence.push(Evidence {
+                kind: kind.name().to_string(),
+                id: record_id.to_string(),
+                link: link.to_string(),
+                summary,
+            });
+        }
+
+        let preferences = self.preferences().await?;
+        let features = features(&suggestion, Utc::now(), None, Some(&preferences));
+        let (score, contributions) = score(&features, self.ranking());
+        let open = rank(
+            self.get_suggestions(None, None).await?,
+            self.ranking(),
+            None,
+            Some(&preferences),
+        );
+        let position = open.iter().position(|r| r.suggestion.id == id);
+        let next_above = position.filter(|p| *p > 0).map(|p| open[p - 1].score);
+        let shown: Vec<DateTime<Utc>> = self
+            .list_impressions()
+            .await?
+            .into_iter()
+            .filter(|i| i.showed(id))
+            .map(|i| i.surfaced_at)
+            .collect();
+        let ranking = RankingBreakdown {
+            score,
+            features,
+            contributions,
+            position: position.map(|p| p + 1),
+            open_suggestions: open.len(),
+            times_shown: shown.len(),
+            last_shown_at: shown.last().copied(),
+        };
+
+        let muted = preferences.is_muted(&suggestion);
+        Ok(Some(SuggestionExplanation {
+            reason_key: reason_key(&suggestion),
+            confidence: provenance.and_then(|p| p.confidence),
+            stop_conditions: stop_conditions(&suggestion, &analyzer, &ranking, next_above, muted),
+            analyzer,
+            evidence,
+            ranking,
+            suggestion,
+        }))
+    }
+}
@@ -0,0 +1,270 @@
+// Flaky command detection
+// Tracks outcomes per command signature and flags commands whose result flips at an unchanged git commit
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::sync::Mutex;
+
+/// Runs kept per signature, newest last
+const MAX_RUNS: usize = 50;
+
+/// Outcome changes at one commit before a command counts as flaky. One change
+/// is usually a fix in the working tree; changing back is not.
+const MIN_FLIPS: usize = 2;
+
+/// Serializes read-modify-write updates of run histories within this process
+static FLAKY_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Environment variable names that never leave the capturing process
+const SECRET_NAME_PARTS: &[&str] = &[
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "API_KEY",
+    "ACCESS_KEY",
+    "PRIVATE_KEY",
+    "AUTH",
+];
+
+/// One finished run of a command signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlakyRun {
+    pub command_id: String,
+    pub git_commit: String,
+    pub success: bool,
+    pub at: DateTime<Utc>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Recent runs of one command line in one directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlakyHistory {
+    pub signature: String,
+    pub command_line: String,
+    pub cwd: String,
+    pub runs: Vec<FlakyRun>,
+    /// Insight raised for this signature, updated as runs come in
+    #[serde(default)]
+    pub insight_id: Option<String>,
+}
+
+/// Values an environment variable had in passing and in failing runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvDifference {
+    pub name: String,
+    /// `None` stands for unset
+    pub passing: BTreeSet<Option<String>>,
+    pub failing: BTreeSet<Option<String>>,
+}
+
+/// Flakiness of a command at one commit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlakyVerdict {
+    pub git_commit: String,
+    pub runs: usize,
+    pub failures: usize,
+    /// Outcome changes between consecutive runs
+    pub flips: usize,
+    /// Share of runs that failed
+    pub flake_rate: f64,
+    pub env_differences: Vec<EnvDifference>,
+}
+
+/// Signature of a command: its program, arguments and directory
+pub fn command_signature(command: &Command) -> String {
+    let line = std::iter::once(command.command.as_str())
+        .chain(command.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join("\0");
+    format!("{}\0{}", line, command.cwd)
+}
+
+/// Environment to keep on a command record: variables whose names suggest a
+/// secret are dropped; the store scrubs the values of the rest
+pub fn sanitize_env(env: &BTreeMap<String, String>) -> serde_json::Value {
+    env.iter()
+        .filter(|(name, _)| {
+            let upper = name.to_uppercase();
+            !SECRET_NAME_PARTS.iter().any(|part| upper.contains(part))
+        })
+        .map(|(name, value)| (name.clone(), serde_json::json!(value)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn env_of(command: &Command) -> BTreeMap<String, String> {
+    command
+        .env_summary
+        .as_object()
+        .map(|env| {
+            env.iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Variables whose values never overlap between passing and failing runs
+pub fn env_differences(runs: &[&FlakyRun]) -> Vec<EnvDifference> {
+    let names: BTreeSet<&String> = runs.iter().flat_map(|r| r.env.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let values = |success: bool| -> BTreeSet<Option<String>> {
+                runs.iter()
+                    .filter(|r| r.success == success)
+                    .map(|r| r.env.get(name).cloned())
+                    .collect()
+            };
+            let (passing, failing) = (values(true), values(false));
+            passing.is_disjoint(&failing).then(|| EnvDifference {
+                name: name.clone(),
+                passing,
+                failing,
+            })
+        })
+        .collect()
+}
+
+/// Whether the runs at the latest run's commit flip between passing and
+/// failing often enough to call the command flaky
+pub fn flaky_verdict(runs: &[FlakyRun]) -> Option<FlakyVerdict> {
+    let commit = &runs.last()?.git_commit;
+    let same: Vec<&FlakyRun> = runs.iter().filter(|r| &r.git_commit == commit).collect();
+    let flips = same
+        .windows(2)
+        .filter(|pair| pair[0].success != pair[1].success)
+        .count();
+    if flips < MIN_FLIPS {
+        return None;
+    }
+    let failures = same.iter().filter(|r| !r.success).count();
+    Some(FlakyVerdict {
+        git_commit: commit.clone(),
+        runs: same.len(),
+        failures,
+        flips,
+        flake_rate: failures as f64 / same.len() as f64,
+        env_differences: env_differences(&same),
+    })
+}
+
+fn history_key(signature: &str) -> String {
+    RecordKind::Flaky.key(&hex::encode(&Sha256::digest(signature.as_bytes())[..16]))
+}
+
+impl MemoryStore {
+    /// Add a finished command to its signature's history and raise or update
+    /// a flaky-command insight when its outcome flips at one commit. Commands
+    /// without a known commit are not tracked.
+    pub async fn track_flaky_run(&self, command: &Command) -> Result<Option<Insight>> {
+        let Some(git_commit) = command.git_commit.clone() else {
+            return Ok(None);
+        };
+        if command.ended_at.is_none() {
+            return Ok(None);
+        }
+        let signature = command_signature(command);
+        let key = history_key(&signature);
+
+        let _guard = FLAKY_LOCK.lock().await;
+        let mut history = self
+            .read_value(&key)
+            .await?
+            .and_then(|v| serde_json::from_value::<FlakyHistory>(v).ok())
+            .unwrap_or_else(|| FlakyHistory {
+                signature,
+                command_line: std::iter::once(command.command.as_str())
+                    .chain(command.args.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                cwd: command.cwd.clone(),
+                ..Default::default()
+            });
+        if history.runs.iter().any(|r| r.command_id == command.id) {
+            return Ok(None);
+        }
+        let mut env = env_of(command);
+        for value in env.values_mut() {
+            self.scrub(value, &mut Vec::new());
+        }
+        history.runs.push(FlakyRun {
+            command_id: command.id.clone(),
+            git_commit,
+            success: command.success,
+            at: command.ended_at.unwrap_or(command.started_at),
+            env,
+        });
+        let excess = history.runs.len().saturating_sub(MAX_RUNS);
+        history.runs.drain(..excess);
+
+        let insight = flaky_verdict(&history.runs).map(|verdict| {
+            let mut insight = flaky_insight(&history, &verdict);
+            if let Some(id) = &history.insight_id {
+                insight.id = id.clone();
+            }
+            insight.command_id = Some(command.id.clone());
+            insight.session_id = Some(command.session_id.clone());
+            insight
+        });
+        if let Some(insight) = &insight {
+            history.insight_id = Some(insight.id.clone());
+            self.store_insight(insight.clone()).await?;
+        }
+        self.write_value(&key, serde_json::to_value(&history)?)
+            .await?;
+        Ok(insight)
+    }
+}
+
+fn flaky_insight(history: &FlakyHistory, verdict: &FlakyVerdict) -> Insight {
+    let short_commit: String = verdict.git_commit.chars().take(8).collect();
+    let mut description = format!(
+        "Failed {} of {} runs at commit {} in {} ({:.0}% flake rate) without code changes.",
+        verdict.failures,
+        verdict.runs,
+        short_commit,
+        history.cwd,
+        verdict.flake_rate * 100.0
+    );
+    if !verdict.env_differences.is_empty() {
+        let names: Vec<&str> = verdict
+            .env_differences
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        description.push_str(&format!(
+            " Environment differed between passing and failing runs: {}.",
+            names.join(", ")
+        ));
+    }
+    let mut insight = Insight::new(
+        "warning".to_string(),
+        format!("`{}` is flaky", history.command_line),
+        description,
+        // More flips at the same commit make a real fix less likely
+        (0.5 + 0.1 * verdict.flips as f64).min(0.95),
+        "heuristic".to_string(),
+    );
+    insight.metadata = serde_json::json!({
+        "kind": "flaky_command",
+        "command_line": history.command_line,
+        "cwd": history.cwd,
+        "git_commit": verdict.git_commit,
+        "runs": verdict.runs,
+        "failures": verdict.failures,
+        "flips": verdict.flips,
+        "flake_rate": verdict.flake_rate,
+        "env_differences": verdict.env_differences,
+    });
+    insight
+}
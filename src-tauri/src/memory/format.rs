@@ -0,0 +1,154 @@
+// Suggestion rendering for each surface
+// One canonical card per suggestion, rendered as UI JSON, an ANSI line, a tmux fragment, Markdown or plain text
+
+use crate::memory::schema::Suggestion;
+use serde::{Deserialize, Serialize};
+
+/// Longest tmux status fragment, in characters
+const TMUX_WIDTH: usize = 40;
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_BOLD: &str = "\x1b[1m";
+const ANSI_DIM: &str = "\x1b[2m";
+const ANSI_CYAN: &str = "\x1b[36m";
+
+/// Where a suggestion is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionFormat {
+    /// JSON card for the app UI
+    Rich,
+    /// One ANSI-colored line for a terminal
+    Terminal,
+    /// Short `#[fg=...]` fragment for a tmux status line
+    Tmux,
+    /// Markdown section for exports and reports
+    Markdown,
+    /// One line without markup, for notifications and logs
+    Plain,
+}
+
+/// The canonical model every format renders from. Text is cleaned of
+/// control characters, since suggestions can come from shared cards.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SuggestionCard {
+    pub id: String,
+    pub kind: String,
+    pub priority: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// The suggested command line, arguments quoted for a POSIX shell
+    pub command: Option<String>,
+}
+
+impl SuggestionCard {
+    pub fn new(suggestion: &Suggestion) -> Self {
+        let command = suggestion.command.as_ref().map(|command| {
+            let args = suggestion.args.as_deref().unwrap_or_default();
+            if args.is_empty() {
+                return command.clone();
+            }
+            // The command is often a full line already; only arguments are quoted
+            let quoted =
+                shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" "));
+            format!("{} {}", command, quoted)
+        });
+        let description = clean(&suggestion.description);
+        Self {
+            id: suggestion.id.clone(),
+            kind: suggestion.suggestion_type.clone(),
+            priority: suggestion.priority.clone(),
+            title: clean(&suggestion.title),
+            description: (!description.is_empty()).then_some(description),
+            command: command.map(|c| clean(&c)).filter(|c| !c.is_empty()),
+        }
+    }
+
+    pub fn render(&self, format: SuggestionFormat) -> String {
+        match format {
+            SuggestionFormat::Rich => serde_json::to_string_pretty(self).unwrap_or_default(),
+            SuggestionFormat::Terminal => self.terminal(),
+            SuggestionFormat::Tmux => self.tmux(),
+            SuggestionFormat::Markdown => self.markdown(),
+            SuggestionFormat::Plain => self.plain(),
+        }
+    }
+
+    fn terminal(&self) -> String {
+        let marker = match self.priority.as_str() {
+            "high" => "\x1b[31m!",
+            "medium" => "\x1b[33m●",
+            _ => "\x1b[2m·",
+        };
+        let mut line = format!(
+            "{}{} {}{}{}",
+            marker, ANSI_RESET, ANSI_BOLD, self.title, ANSI_RESET
+        );
+        if let Some(description) = &self.description {
+            line.push_str(&format!(" {}- {}{}", ANSI_DIM, description, ANSI_RESET));
+        }
+        if let Some(command) = &self.command {
+            line.push_str(&format!("  {}$ {}{}", ANSI_CYAN, command, ANSI_RESET));
+        }
+        line
+    }
+
+    fn tmux(&self) -> String {
+        let (color, symbol) = match self.priority.as_str() {
+            "high" => ("red", "⚠"),
+            "medium" => ("yellow", "●"),
+            _ => ("default", "·"),
+        };
+        let mut title: String = self.title.chars().take(TMUX_WIDTH).collect();
+        if title.len() < self.title.len() {
+            title.pop();
+            title.push('…');
+        }
+        // `#` starts a tmux format sequence
+        format!(
+            "#[fg={}]{} {}#[default]",
+            color,
+            symbol,
+            title.replace('#', "##")
+        )
+    }
+
+    fn markdown(&self) -> String {
+        let mut out = format!("### {}\n\n", self.title);
+        if let Some(description) = &self.description {
+            out.push_str(&format!("{}\n\n", description));
+        }
+        if let Some(command) = &self.command {
+            out.push_str(&format!("```sh\n{}\n```\n\n", command));
+        }
+        out.push_str(&format!("_{}, {} priority_\n", self.kind, self.priority));
+        out
+    }
+
+    fn plain(&self) -> String {
+        let mut line = self.title.clone();
+        if let Some(description) = &self.description {
+            line.push_str(&format!(" - {}", description));
+        }
+        if let Some(command) = &self.command {
+            line.push_str(&format!(" (run: {})", command));
+        }
+        line
+    }
+}
+
+/// Render `suggestion` for one surface
+pub fn format_suggestion(suggestion: &Suggestion, format: SuggestionFormat) -> String {
+    SuggestionCard::new(suggestion).render(format)
+}
+
+/// Collapse whitespace and drop control characters, so no escape sequence
+/// from a shared card reaches a terminal
+fn clean(text: &str) -> String {
+    let printable: String = text
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .collect();
+    printable.split_whitespace().collect::<Vec<_>>().join(" ")
+}
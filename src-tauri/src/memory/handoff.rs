@@ -0,0 +1,389 @@
+// Session handoff between machines
+// Packs a session's recent context into a passphrase-encrypted bundle that another machine imports
+
+use crate::memory::api::MemoryStore;
+use crate::memory::backend::WriteBatch;
+use crate::memory::keys::RecordKind;
+use crate::memory::pages::chunk_bytes;
+use crate::memory::schema::*;
+use crate::memory::tags::{git_branch, git_head, git_project_name, merge_tags};
+use anyhow::{Context, Result};
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use ts_rs::TS;
+
+/// Start of every bundle, naming its format
+pub const BUNDLE_PREFIX: &str = "runebook-handoff-1:";
+/// Tag on sessions and commands that came in a handoff
+pub const HANDOFF_TAG: &str = "handoff";
+/// Shortest passphrase a bundle is encrypted with
+pub const MIN_PASSPHRASE_CHARS: usize = 8;
+/// How far back the bundle reaches
+const WINDOW_HOURS: i64 = 24;
+/// Newest commands of the session that are carried
+const MAX_COMMANDS: usize = 50;
+/// Open suggestions carried, the best ranked
+const MAX_SUGGESTIONS: usize = 20;
+/// Commands whose output tail is carried: the failed ones and the last one
+const MAX_SNIPPETS: usize = 10;
+/// Bytes at the end of each stream a snippet keeps
+const SNIPPET_BYTES: usize = 4096;
+/// PBKDF2-HMAC-SHA256 rounds deriving the key from the passphrase
+const KDF_ROUNDS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where the session was working, as far as the files tell
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WorkspaceState {
+    pub cwd: String,
+    pub project: Option<String>,
+    pub branch: Option<String>,
+    pub commit: Option<String>,
+    /// One line for people, e.g. `~/src/app (app) on fix-login at 1a2b3c4`
+    pub description: String,
+}
+
+impl WorkspaceState {
+    /// State of the git checkout containing `cwd`, read without running git
+    pub fn of(cwd: &str) -> Self {
+        let dir = Path::new(cwd);
+        let project = git_project_name(dir);
+        let branch = git_branch(dir);
+        let commit = git_head(dir);
+        let mut description = cwd.to_string();
+        if let Some(project) = &project {
+            description.push_str(&format!(" ({})", project));
+        }
+        match (&branch, &commit) {
+            (Some(branch), Some(commit)) => {
+                description.push_str(&format!(" on {} at {}", branch, short(commit)))
+            }
+            (Some(branch), None) => description.push_str(&format!(" on {}", branch)),
+            (None, Some(commit)) => {
+                description.push_str(&format!(" at {} (detached)", short(commit)))
+            }
+            (None, None) => {}
+        }
+        Self {
+            cwd: cwd.to_string(),
+            project,
+            branch,
+            commit,
+            description,
+        }
+    }
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+/// The end of one stream of a command's output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub command_id: String,
+    pub stream_type: String,
+    pub text: String,
+    /// Earlier output was left out
+    pub truncated: bool,
+}
+
+/// What a bundle carries, before it is compressed and encrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handoff {
+    pub created_at: DateTime<Utc>,
+    pub from_host: Option<String>,
+    pub session: Session,
+    pub commands: Vec<Command>,
+    pub errors: Vec<Error>,
+    pub insights: Vec<Insight>,
+    pub suggestions: Vec<Suggestion>,
+    pub snippets: Vec<Snippet>,
+    pub workspace: WorkspaceState,
+}
+
+/// What `import_handoff` brought in
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct HandoffSummary {
+    pub session_id: String,
+    pub from_host: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub commands: usize,
+    pub errors: usize,
+    pub suggestions: usize,
+    pub snippets: usize,
+    /// Where the session was working on the machine it came from
+    pub workspace: WorkspaceState,
+    /// The same directory on this machine; `None` when it does not exist here
+    pub workspace_here: Option<WorkspaceState>,
+}
+
+/// Compress, encrypt and encode `handoff` as a bundle
+pub fn seal(handoff: &Handoff, passphrase: &str) -> Result<String> {
+    check_passphrase(passphrase)?;
+    let json = serde_json::to_vec(handoff)?;
+    let plain = zstd::encode_all(json.as_slice(), 19).context("Failed to compress bundle")?;
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut salt)
+        .and_then(|_| getrandom::getrandom(&mut nonce))
+        .map_err(|e| anyhow::anyhow!("No system randomness for the bundle key: {}", e))?;
+    let sealed = cipher(passphrase, &salt)
+        .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt bundle"))?;
+
+    let mut bytes = Vec::with_capacity(SALT_LEN + NONCE_LEN + sealed.len());
+    bytes.extend_from_slice(&salt);
+    bytes.extend_from_slice(&nonce);
+    bytes.extend_from_slice(&sealed);
+    Ok(format!(
+        "{}{}",
+        BUNDLE_PREFIX,
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    ))
+}
+
+/// Decode, decrypt and decompress a bundle made by [`seal`]
+pub fn open(bundle: &str, passphrase: &str) -> Result<Handoff> {
+    let encoded = bundle
+        .trim()
+        .strip_prefix(BUNDLE_PREFIX)
+        .context("Not a Runebook handoff bundle")?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .context("Bundle is damaged")?;
+    anyhow::ensure!(
+        bytes.len() > SALT_LEN + NONCE_LEN,
+        "Bundle is damaged: too short"
+    );
+    let (salt, rest) = bytes.split_at(SALT_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let plain = cipher(passphrase, salt)
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase, or the bundle was changed"))?;
+    let json = zstd::decode_all(plain.as_slice()).context("Failed to decompress bundle")?;
+    serde_json::from_slice(&json).context("Bundle contents are not valid")
+}
+
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    anyhow::ensure!(
+        passphrase.chars().count() >= MIN_PASSPHRASE_CHARS,
+        "Passphrase must be at least {} characters",
+        MIN_PASSPHRASE_CHARS
+    );
+    Ok(())
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+/// The last [`SNIPPET_BYTES`] of `bytes` as text, starting at a line when
+/// one starts close enough
+fn tail_text(bytes: &[u8]) -> (String, bool) {
+    if bytes.len() <= SNIPPET_BYTES {
+        return (String::from_utf8_lossy(bytes).into_owned(), false);
+    }
+    let mut tail = &bytes[bytes.len() - SNIPPET_BYTES..];
+    if let Some(newline) = tail.iter().position(|&b| b == b'\n') {
+        tail = &tail[newline + 1..];
+    }
+    (String::from_utf8_lossy(tail).into_owned(), true)
+}
+
+impl MemoryStore {
+    /// Gather what another machine needs to pick up `session_id`: its
+    /// commands of the last day, their errors and insights, the open
+    /// suggestions about them, the output tails of failed commands and of the
+    /// last one, and the state of its working directory
+    pub async fn export_handoff(&self, session_id: &str) -> Result<Handoff> {
+        let session: Session = self
+            .read_value(&RecordKind::Session.key(session_id))
+            .await?
+            .map(serde_json::from_value)
+            .transpose()
+            .context("Failed to deserialize session")?
+            .with_context(|| format!("Session not found: {}", session_id))?;
+        let context = self
+            .get_context(session_id, Duration::hours(WINDOW_HOURS))
+            .await?;
+
+        let mut commands = context.commands;
+        commands.drain(..commands.len().saturating_sub(MAX_COMMANDS));
+        let ids: HashSet<&str> = commands.iter().map(|c| c.id.as_str()).collect();
+        let cwds: HashSet<&str> = commands.iter().map(|c| c.cwd.as_str()).collect();
+        let errors: Vec<Error> = context
+            .errors
+            .into_iter()
+            .filter(|e| ids.contains(e.command_id.as_str()))
+            .collect();
+        let insights = context.insights;
+
+        // Suggestions name what they are about in their free-form context
+        let mut suggestions: Vec<Suggestion> = self
+            .get_suggestions(None, None)
+            .await?
+            .into_iter()
+            .filter(|s| !s.applied)
+            .filter(|s| {
+                s.context["session_id"].as_str() == Some(session_id)
+                    || s.context["command_id"]
+                        .as_str()
+                        .is_some_and(|id| ids.contains(id))
+                    || s.context["cwd"]
+                        .as_str()
+                        .is_some_and(|cwd| cwds.contains(cwd))
+            })
+            .collect();
+        suggestions.truncate(MAX_SUGGESTIONS);
+
+        let mut snippet_commands: Vec<&Command> = commands
+            .iter()
+            .rev()
+            .filter(|c| !c.success && c.ended_at.is_some())
+            .take(MAX_SNIPPETS)
+            .collect();
+        if let Some(last) = commands.last() {
+            if !snippet_commands.iter().any(|c| c.id == last.id) {
+                snippet_commands.insert(0, last);
+                snippet_commands.truncate(MAX_SNIPPETS);
+            }
+        }
+        let mut snippets = Vec::new();
+        for command in snippet_commands {
+            let mut streams: Vec<(String, Vec<u8>)> = Vec::new();
+            for output in self.command_outputs(&command.id).await? {
+                if output.stream_type == OBJECTS_STREAM || output.blob_artifact_id.is_some() {
+                    continue;
+                }
+                let bytes = chunk_bytes(&output)?;
+                match streams.iter_mut().find(|(s, _)| *s == output.stream_type) {
+                    Some((_, all)) => all.extend_from_slice(&bytes),
+                    None => streams.push((output.stream_type, bytes)),
+                }
+            }
+            for (stream_type, bytes) in streams {
+                let (text, truncated) = tail_text(&bytes);
+                if !text.trim().is_empty() {
+                    snippets.push(Snippet {
+                        command_id: command.id.clone(),
+                        stream_type,
+                        text,
+                        truncated,
+                    });
+                }
+            }
+        }
+
+        let cwd = commands
+            .last()
+            .map(|c| c.cwd.clone())
+            .unwrap_or_else(|| session.initial_cwd.clone());
+        Ok(Handoff {
+            created_at: Utc::now(),
+            from_host: crate::receipts::hostname(),
+            workspace: WorkspaceState::of(&cwd),
+            session,
+            commands,
+            errors,
+            insights,
+            suggestions,
+            snippets,
+        })
+    }
+
+    /// Store a handoff's records under their original ids, so importing the
+    /// same bundle twice changes nothing. The session and its commands are
+    /// tagged `handoff`; commands do not count towards this machine's daily
+    /// statistics.
+    pub async fn import_handoff(&self, handoff: Handoff) -> Result<HandoffSummary> {
+        let Handoff {
+            created_at,
+            from_host,
+            mut session,
+            commands,
+            errors,
+            insights,
+            suggestions,
+            snippets,
+            workspace,
+        } = handoff;
+
+        session.metadata["handoff"] = serde_json::json!({
+            "from_host": from_host,
+            "created_at": created_at,
+            "workspace": workspace.description,
+        });
+        merge_tags(&mut session.tags, vec![HANDOFF_TAG.to_string()]);
+
+        let mut batch = WriteBatch::new();
+        batch.put(
+            RecordKind::Session.key(&session.id),
+            self.encrypt_value(serde_json::to_value(&session)?).await?,
+        );
+        let command_count = commands.len();
+        for mut command in commands {
+            merge_tags(&mut command.tags, vec![HANDOFF_TAG.to_string()]);
+            // Staged directly, so the commands never land in the day counters
+            self.stage_command(&mut batch, &mut command).await?;
+        }
+        let error_count = errors.len();
+        for error in errors {
+            self.stage_error(&mut batch, error).await?;
+        }
+        for insight in insights {
+            batch.put(
+                RecordKind::Insight.key(&insight.id),
+                self.encrypt_value(serde_json::to_value(&insight)?).await?,
+            );
+        }
+        let suggestion_count = suggestions.len();
+        for suggestion in suggestions {
+            batch.put(
+                RecordKind::Suggestion.key(&suggestion.id),
+                self.encrypt_value(serde_json::to_value(&suggestion)?)
+                    .await?,
+            );
+        }
+        let snippet_count = snippets.len();
+        for snippet in snippets {
+            let mut output = Output::new(
+                snippet.command_id.clone(),
+                snippet.stream_type.clone(),
+                0,
+                snippet.text.into_bytes(),
+            );
+            // Stable ids keep a second import from adding the snippet again
+            output.id = format!("handoff-{}-{}", snippet.command_id, snippet.stream_type);
+            output.timestamp = created_at;
+            self.stage_output(&mut batch, &mut output, false).await?;
+        }
+        self.commit(batch).await?;
+
+        let workspace_here = Path::new(&workspace.cwd)
+            .is_dir()
+            .then(|| WorkspaceState::of(&workspace.cwd));
+        Ok(HandoffSummary {
+            session_id: session.id,
+            from_host,
+            created_at,
+            commands: command_count,
+            errors: error_count,
+            suggestions: suggestion_count,
+            snippets: snippet_count,
+            workspace,
+            workspace_here,
+        })
+    }
+}
@@ -0,0 +1,307 @@
+// Shell history import
+// Parses bash/zsh/fish history files and atuin's SQLite database into Command records
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// Supported history sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum HistorySource {
+    Bash,
+    Zsh,
+    Fish,
+    Atuin,
+}
+
+impl HistorySource {
+    pub fn name(self) -> &'static str {
+        match self {
+            HistorySource::Bash => "bash",
+            HistorySource::Zsh => "zsh",
+            HistorySource::Fish => "fish",
+            HistorySource::Atuin => "atuin",
+        }
+    }
+
+    /// Where the history usually lives for the current user
+    pub fn default_path(self) -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        Some(match self {
+            HistorySource::Bash => home.join(".bash_history"),
+            HistorySource::Zsh => std::env::var_os("HISTFILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".zsh_history")),
+            HistorySource::Fish => home.join(".local/share/fish/fish_history"),
+            HistorySource::Atuin => home.join(".local/share/atuin/history.db"),
+        })
+    }
+}
+
+/// One parsed history entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub command_line: String,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    pub exit_code: Option<i32>,
+    pub cwd: Option<String>,
+}
+
+impl HistoryEntry {
+    fn line(command_line: String, timestamp: Option<DateTime<Utc>>) -> Self {
+        Self {
+            command_line,
+            timestamp,
+            duration_ms: None,
+            exit_code: None,
+            cwd: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ImportSummary {
+    pub source: HistorySource,
+    pub path: String,
+    pub session_id: String,
+    pub imported: usize,
+    pub skipped: usize,
+}
+
+fn epoch(secs: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(secs, 0).single()
+}
+
+/// Bash history, with optional `#<epoch>` lines from HISTTIMEFORMAT
+pub fn parse_bash(text: &str) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    let mut pending_ts = None;
+    for line in text.lines() {
+        if let Some(ts) = line
+            .strip_prefix('#')
+            .and_then(|t| t.trim().parse::<i64>().ok())
+        {
+            pending_ts = epoch(ts);
+        } else if !line.trim().is_empty() {
+            entries.push(HistoryEntry::line(line.to_string(), pending_ts.take()));
+        }
+    }
+    entries
+}
+
+/// Zsh history, plain or EXTENDED_HISTORY (`: <start>:<elapsed>;<command>`)
+pub fn parse_zsh(text: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut continuing = false;
+    for line in text.lines() {
+        if continuing {
+            if let Some(last) = entries.last_mut() {
+                last.command_line.push('\n');
+                last.command_line.push_str(line.trim_end_matches('\\'));
+            }
+            continuing = line.ends_with('\\');
+            continue;
+        }
+        continuing = line.ends_with('\\');
+        let line_body = line.trim_end_matches('\\');
+
+        let extended = line_body.strip_prefix(": ").and_then(|rest| {
+            let (meta, command) = rest.split_once(';')?;
+            let (start, elapsed) = meta.split_once(':')?;
+            Some((
+                start.trim().parse::<i64>().ok()?,
+                elapsed.trim().parse::<u64>().ok()?,
+                command,
+            ))
+        });
+        match extended {
+            Some((start, elapsed, command)) => entries.push(HistoryEntry {
+                duration_ms: Some(elapsed * 1000),
+                ..HistoryEntry::line(command.to_string(), epoch(start))
+            }),
+            None if !line_body.trim().is_empty() => {
+                entries.push(HistoryEntry::line(line_body.to_string(), None))
+            }
+            None => {}
+        }
+    }
+    entries
+}
+
+/// Fish history (`- cmd: ...` / `  when: ...` YAML-like records)
+pub fn parse_fish(text: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    for line in text.lines() {
+        if let Some(cmd) = line.strip_prefix("- cmd: ") {
+            let cmd = cmd.replace("\\n", "\n").replace("\\\\", "\\");
+            entries.push(HistoryEntry::line(cmd, None));
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+            if let Some(last) = entries.last_mut() {
+                last.timestamp = when.trim().parse().ok().and_then(epoch);
+            }
+        }
+    }
+    entries
+}
+
+/// Atuin's SQLite history database
+pub fn read_atuin(path: &Path) -> Result<Vec<HistoryEntry>> {
+    let conn =
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open atuin database {}", path.display()))?;
+
+    // `deleted_at` only exists in newer atuin schemas
+    let with_deleted = "SELECT timestamp, duration, exit, command, cwd FROM history \
+                        WHERE deleted_at IS NULL ORDER BY timestamp";
+    let without_deleted =
+        "SELECT timestamp, duration, exit, command, cwd FROM history ORDER BY timestamp";
+    let mut stmt = conn
+        .prepare(with_deleted)
+        .or_else(|_| conn.prepare(without_deleted))?;
+
+    let rows = stmt.query_map([], |row| {
+        let timestamp_ns: i64 = row.get(0)?;
+        let duration_ns: i64 = row.get(1)?;
+        let exit: i64 = row.get(2)?;
+        Ok(HistoryEntry {
+            command_line: row.get(3)?,
+            timestamp: Some(Utc.timestamp_nanos(timestamp_ns)),
+            // atuin stores -1 when the duration is unknown
+            duration_ms: (duration_ns >= 0).then_some(duration_ns as u64 / 1_000_000),
+            exit_code: (exit != -1).then_some(exit as i32),
+            cwd: row.get::<_, Option<String>>(4)?.filter(|c| c != "unknown"),
+        })
+    })?;
+    Ok(rows.filter_map(|r| r.ok()).collect())
+}
+
+/// Parse a history source from `path`
+pub fn read_history(source: HistorySource, path: &Path) -> Result<Vec<HistoryEntry>> {
+    if source == HistorySource::Atuin {
+        return read_atuin(path);
+    }
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    // zsh metafies non-ASCII bytes and bash history may hold any encoding
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(match source {
+        HistorySource::Bash => parse_bash(&text),
+        HistorySource::Zsh => parse_zsh(&text),
+        HistorySource::Fish => parse_fish(&text),
+        HistorySource::Atuin => unreachable!(),
+    })
+}
+
+/// Fill missing timestamps: carry the previous known one forward, falling back to `default`
+fn backfill_timestamps(entries: &mut [HistoryEntry], default: DateTime<Utc>) {
+    let mut last = None;
+    for entry in entries.iter_mut() {
+        match entry.timestamp {
+            Some(ts) => last = Some(ts),
+            None => entry.timestamp = Some(last.unwrap_or(default)),
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Import shell history into a dedicated `imported-<source>` session.
+    ///
+    /// Record ids are derived from the entry position, so re-importing the
+    /// same file updates records instead of duplicating them.
+    pub async fn import_history(
+        &self,
+        source: HistorySource,
+        path: Option<&Path>,
+    ) -> Result<ImportSummary> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => source
+                .default_path()
+                .context("Cannot determine the default history location")?,
+        };
+        let mut entries = read_history(source, &path)?;
+
+        // Entries without timestamps fall back to the file's modification time
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        backfill_timestamps(&mut entries, modified);
+
+        let home = dirs::home_dir()
+            .map(|h| h.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let session_id = format!("imported-{}", source.name());
+        let mut session = Session::new(source.name().to_string(), home.clone());
+        session.id = session_id.clone();
+        session.started_at = entries
+            .iter()
+            .filter_map(|e| e.timestamp)
+            .min()
+            .unwrap_or(modified);
+        session.ended_at = entries.iter().filter_map(|e| e.timestamp).max();
+        session.metadata = serde_json::json!({
+            "imported": true,
+            "source": source.name(),
+            "path": path.to_string_lossy(),
+        });
+        session.tags = vec!["imported".to_string()];
+        self.write_value(
+            &RecordKind::Session.key(&session.id),
+            serde_json::to_value(&session)?,
+        )
+        .await?;
+
+        let mut imported = 0;
+        let mut skipped = 0;
+        for (index, entry) in entries.into_iter().enumerate() {
+            let words = shlex::split(&entry.command_line).unwrap_or_else(|| {
+                entry
+                    .command_line
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect()
+            });
+            let Some((program, args)) = words.split_first() else {
+                skipped += 1;
+                continue;
+            };
+
+            let mut command = Command::new(
+                session_id.clone(),
+                program.clone(),
+                args.to_vec(),
+                entry.cwd.clone().unwrap_or_else(|| home.clone()),
+            );
+            command.id = format!("{}-{}", session_id, index);
+            command.started_at = entry.timestamp.unwrap_or(modified);
+            command.duration_ms = entry.duration_ms;
+            command.ended_at = entry
+                .duration_ms
+                .map(|ms| command.started_at + chrono::Duration::milliseconds(ms as i64));
+            command.exit_code = entry.exit_code;
+            // Unknown exit status counts as success; history only records what was run
+            command.success = entry.exit_code.is_none_or(|c| c == 0);
+            command.tags = vec!["imported".to_string()];
+            self.store_command(command).await?;
+            imported += 1;
+        }
+
+        Ok(ImportSummary {
+            source,
+            path: path.to_string_lossy().into_owned(),
+            session_id,
+            imported,
+            skipped,
+        })
+    }
+}
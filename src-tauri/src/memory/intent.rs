@@ -0,0 +1,336 @@
+// Command intent labeling
+// Heuristic classifier tagging commands with what they are for (build, test, deploy, ...), plus user rules
+
+use crate::memory::schema::Command;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+
+/// Tag prefix for intent labels, e.g. `intent:test`
+pub const INTENT_TAG_PREFIX: &str = "intent:";
+
+/// User-defined intent rule; every pattern that is set must match
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct IntentRule {
+    pub intent: String,
+    /// Regex over the full command line (`command args...`)
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Regex over the working directory
+    #[serde(default)]
+    pub cwd: Option<String>,
+}
+
+/// `[tags.intents]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct IntentConfig {
+    pub enabled: bool,
+    /// Apply the built-in heuristics in addition to `rules`
+    pub builtin: bool,
+    pub rules: Vec<IntentRule>,
+}
+
+impl Default for IntentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            builtin: true,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Subcommand-sensitive tools: (program, subcommands, intent)
+const SUBCOMMAND_INTENTS: &[(&str, &[&str], &str)] = &[
+    ("cargo", &["build", "check", "clippy", "b", "c"], "build"),
+    ("cargo", &["test", "nextest", "bench", "t"], "test"),
+    ("go", &["build", "install", "vet"], "build"),
+    ("go", &["test"], "test"),
+    ("npm", &["test", "t"], "test"),
+    ("yarn", &["test"], "test"),
+    ("pnpm", &["test"], "test"),
+    ("mvn", &["compile", "package", "install"], "build"),
+    ("mvn", &["test", "verify"], "test"),
+    ("gradle", &["build", "assemble", "compileJava"], "build"),
+    ("gradle", &["test", "check"], "test"),
+    ("bazel", &["build"], "build"),
+    ("bazel", &["test"], "test"),
+    ("dotnet", &["build", "publish"], "build"),
+    ("dotnet", &["test"], "test"),
+    ("make", &["test", "check"], "test"),
+    ("docker", &["build"], "build"),
+    ("docker", &["push"], "deploy"),
+    ("nix", &["build"], "build"),
+    (
+        "kubectl",
+        &["apply", "rollout", "set", "scale", "create", "replace"],
+        "deploy",
+    ),
+    ("helm", &["install", "upgrade", "rollback"], "deploy"),
+    ("terraform", &["apply", "destroy"], "deploy"),
+    ("pulumi", &["up", "destroy"], "deploy"),
+    ("fly", &["deploy"], "deploy"),
+    ("flyctl", &["deploy"], "deploy"),
+    ("netlify", &["deploy"], "deploy"),
+    ("serverless", &["deploy"], "deploy"),
+    ("sls", &["deploy"], "deploy"),
+    ("cap", &["deploy"], "deploy"),
+    ("openssl", &["s_client"], "network-debug"),
+];
+
+/// Programs whose intent does not depend on arguments
+const PROGRAM_INTENTS: &[(&str, &str)] = &[
+    ("make", "build"),
+    ("cmake", "build"),
+    ("ninja", "build"),
+    ("tsc", "build"),
+    ("gcc", "build"),
+    ("clang", "build"),
+    ("javac", "build"),
+    ("rustc", "build"),
+    ("pytest", "test"),
+    ("jest", "test"),
+    ("vitest", "test"),
+    ("rspec", "test"),
+    ("tox", "test"),
+    ("ctest", "test"),
+    ("phpunit", "test"),
+    ("ansible-playbook", "deploy"),
+    ("vercel", "deploy"),
+    ("git", "vcs"),
+    ("gh", "vcs"),
+    ("hg", "vcs"),
+    ("svn", "vcs"),
+    ("jj", "vcs"),
+    ("ls", "file-ops"),
+    ("cp", "file-ops"),
+    ("mv", "file-ops"),
+    ("rm", "file-ops"),
+    ("mkdir", "file-ops"),
+    ("rmdir", "file-ops"),
+    ("touch", "file-ops"),
+    ("chmod", "file-ops"),
+    ("chown", "file-ops"),
+    ("ln", "file-ops"),
+    ("find", "file-ops"),
+    ("tar", "file-ops"),
+    ("zip", "file-ops"),
+    ("unzip", "file-ops"),
+    ("rsync", "file-ops"),
+    ("du", "file-ops"),
+    ("tree", "file-ops"),
+    ("ping", "network-debug"),
+    ("curl", "network-debug"),
+    ("wget", "network-debug"),
+    ("dig", "network-debug"),
+    ("nslookup", "network-debug"),
+    ("host", "network-debug"),
+    ("traceroute", "network-debug"),
+    ("tracepath", "network-debug"),
+    ("mtr", "network-debug"),
+    ("netstat", "network-debug"),
+    ("ss", "network-debug"),
+    ("nc", "network-debug"),
+    ("ncat", "network-debug"),
+    ("telnet", "network-debug"),
+    ("tcpdump", "network-debug"),
+    ("nmap", "network-debug"),
+    ("ifconfig", "network-debug"),
+    ("ip", "network-debug"),
+    ("arp", "network-debug"),
+    ("http", "network-debug"),
+];
+
+/// Built-in intent of `command args...`, if the heuristics recognize it
+pub fn builtin_intent(command: &str, args: &[String]) -> Option<&'static str> {
+    let program = match command.rsplit(['/', '\\']).next().unwrap_or(command) {
+        "gradlew" => "gradle",
+        "mvnw" => "mvn",
+        program => program,
+    };
+    // First argument that is not an option, e.g. `test` in `cargo +nightly test`
+    let subcommand = args
+        .iter()
+        .map(String::as_str)
+        .find(|a| !a.starts_with('-') && !a.starts_with('+'));
+
+    // Package-manager scripts: `npm run build`, `yarn build`, `pnpm run test:unit`
+    if matches!(program, "npm" | "yarn" | "pnpm" | "bun") {
+        let script = match subcommand {
+            Some("run" | "run-script") => args
+                .iter()
+                .map(String::as_str)
+                .filter(|a| !a.starts_with('-'))
+                .nth(1),
+            other => other,
+        };
+        if let Some(script) = script {
+            let name = script.split(':').next().unwrap_or(script);
+            match name {
+                "build" | "compile" => return Some("build"),
+                "test" | "e2e" | "coverage" => return Some("test"),
+                "deploy" | "release" => return Some("deploy"),
+                _ => {}
+            }
+        }
+    }
+
+    for (tool, subcommands, intent) in SUBCOMMAND_INTENTS {
+        if *tool == program && subcommand.is_some_and(|s| subcommands.contains(&s)) {
+            return Some(intent);
+        }
+    }
+    PROGRAM_INTENTS
+        .iter()
+        .find(|(p, _)| *p == program)
+        .map(|(_, intent)| *intent)
+}
+
+/// Compiled intent rules
+pub struct IntentClassifier {
+    config: IntentConfig,
+    rules: Vec<(String, Option<Regex>, Option<Regex>)>,
+}
+
+impl IntentClassifier {
+    pub fn new(config: IntentConfig) -> Result<Self> {
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>> {
+            pattern
+                .as_deref()
+                .map(|p| Regex::new(p).with_context(|| format!("Invalid intent rule: {}", p)))
+                .transpose()
+        };
+        let rules = config
+            .rules
+            .iter()
+            .map(|r| Ok((r.intent.clone(), compile(&r.command)?, compile(&r.cwd)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { config, rules })
+    }
+
+    /// Intents of a command: every matching user rule, then the built-in label
+    pub fn classify(&self, command: &str, args: &[String], cwd: &str) -> Vec<String> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let line = std::iter::once(command)
+            .chain(args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut intents: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|(_, command_re, cwd_re)| {
+                (command_re.is_some() || cwd_re.is_some())
+                    && command_re.as_ref().is_none_or(|re| re.is_match(&line))
+                    && cwd_re.as_ref().is_none_or(|re| re.is_match(cwd))
+            })
+            .map(|(intent, _, _)| intent.clone())
+            .collect();
+        if self.config.builtin {
+            if let Some(intent) = builtin_intent(command, args) {
+                if !intents.iter().any(|i| i == intent) {
+                    intents.push(intent.to_string());
+                }
+            }
+        }
+        intents
+    }
+
+    /// `intent:<name>` tags for a command
+    pub fn command_tags(&self, command: &Command) -> Vec<String> {
+        self.classify(&command.command, &command.args, &command.cwd)
+            .into_iter()
+            .map(|intent| format!("{}{}", INTENT_TAG_PREFIX, intent))
+            .collect()
+    }
+}
+
+/// Intents recorded on a command's tags. Commands stored before intent
+/// labeling fall back to the built-in heuristics.
+pub fn intents_of(command: &Command) -> Vec<&str> {
+    let tagged: Vec<&str> = command
+        .tags
+        .iter()
+        .filter_map(|t| t.strip_prefix(INTENT_TAG_PREFIX))
+        .collect();
+    if tagged.is_empty() {
+        builtin_intent(&command.command, &command.args)
+            .into_iter()
+            .collect()
+    } else {
+        tagged
+    }
+}
+
+/// How many recent commands a debugging loop is looked for in
+const LOOP_WINDOW: usize = 10;
+/// Failed build/test runs within the window that make a loop
+const LOOP_FAILURES: usize = 3;
+
+/// What the user has been doing recently, for ranking suggestions
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Activity {
+    /// Intent counts over the commands considered, most frequent first
+    pub intents: Vec<(String, usize)>,
+    pub dominant_intent: Option<String>,
+    /// Build/test keeps failing and being re-run
+    pub debugging_loop: bool,
+    /// One-line description, e.g. for an analyzer prompt
+    pub summary: Option<String>,
+}
+
+/// Summarize `commands` (oldest first) by their intent tags
+pub fn activity(commands: &[Command]) -> Activity {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for command in commands {
+        for intent in intents_of(command) {
+            *counts.entry(intent).or_default() += 1;
+        }
+    }
+    let mut intents: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(intent, count)| (intent.to_string(), count))
+        .collect();
+    intents.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let dominant_intent = intents.first().map(|(intent, _)| intent.clone());
+
+    // A loop: the latest build/test run failed, as did several others recently
+    let recent = &commands[commands.len().saturating_sub(LOOP_WINDOW)..];
+    let checks: Vec<&Command> = recent
+        .iter()
+        .filter(|c| intents_of(c).iter().any(|i| *i == "build" || *i == "test"))
+        .collect();
+    let failures = checks.iter().filter(|c| !c.success).count();
+    let debugging_loop = failures >= LOOP_FAILURES && checks.last().is_some_and(|c| !c.success);
+
+    let summary = if debugging_loop {
+        let last = checks.last().expect("loop has a failed check");
+        Some(format!(
+            "User is in a debugging loop: {} build/test failures in the last {} commands, latest `{}`",
+            failures,
+            recent.len(),
+            std::iter::once(last.command.as_str())
+                .chain(last.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ))
+    } else {
+        dominant_intent
+            .as_ref()
+            .map(|intent| format!("User is mostly doing {} work", intent))
+    };
+
+    Activity {
+        intents,
+        dominant_intent,
+        debugging_loop,
+        summary,
+    }
+}
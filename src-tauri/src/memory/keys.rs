@@ -0,0 +1,219 @@
+// Store key schema
+// Keys are `<namespace>:<kind>:<id>`, or `<namespace>:<kind>:<workspace>:<id>` for workspace-scoped records; see MEMORY.md
+
+use std::fmt;
+
+/// Prefix shared by every record the memory store owns
+pub const MEMORY_PREFIX: &str = "memory:";
+
+/// Type of a stored record; decides the key prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordKind {
+    Session,
+    Command,
+    Output,
+    OutputIndex,
+    Error,
+    Insight,
+    Suggestion,
+    Provenance,
+    Event,
+    Artifact,
+    Impression,
+    Benchmark,
+    Flaky,
+    View,
+    Bookmark,
+    Preference,
+    DayStats,
+    Wal,
+    Schema,
+    App,
+    /// Canvas documents, in the frontend's `runebook:` namespace
+    Canvas,
+    CanvasMeta,
+}
+
+impl RecordKind {
+    pub const ALL: [RecordKind; 22] = [
+        RecordKind::Session,
+        RecordKind::Command,
+        RecordKind::Output,
+        RecordKind::OutputIndex,
+        RecordKind::Error,
+        RecordKind::Insight,
+        RecordKind::Suggestion,
+        RecordKind::Provenance,
+        RecordKind::Event,
+        RecordKind::Artifact,
+        RecordKind::Impression,
+        RecordKind::Benchmark,
+        RecordKind::Flaky,
+        RecordKind::View,
+        RecordKind::Bookmark,
+        RecordKind::Preference,
+        RecordKind::DayStats,
+        RecordKind::Wal,
+        RecordKind::Schema,
+        RecordKind::App,
+        RecordKind::Canvas,
+        RecordKind::CanvasMeta,
+    ];
+
+    /// Record type name used in reports
+    pub const fn name(self) -> &'static str {
+        match self {
+            RecordKind::Session => "session",
+            RecordKind::Command => "command",
+            RecordKind::Output => "output",
+            RecordKind::OutputIndex => "output_index",
+            RecordKind::Error => "error",
+            RecordKind::Insight => "insight",
+            RecordKind::Suggestion => "suggestion",
+            RecordKind::Provenance => "provenance",
+            RecordKind::Event => "event",
+            RecordKind::Artifact => "artifact",
+            RecordKind::Impression => "impression",
+            RecordKind::Benchmark => "benchmark",
+            RecordKind::Flaky => "flaky",
+            RecordKind::View => "view",
+            RecordKind::Bookmark => "bookmark",
+            RecordKind::Preference => "preference",
+            RecordKind::DayStats => "stats",
+            RecordKind::Wal => "wal",
+            RecordKind::Schema => "schema",
+            RecordKind::App => "app",
+            RecordKind::Canvas => "canvas",
+            RecordKind::CanvasMeta => "canvas_meta",
+        }
+    }
+
+    /// Key prefix of every record of this kind, workspace-scoped or not
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            RecordKind::Session => "memory:session:",
+            RecordKind::Command => "memory:command:",
+            RecordKind::Output => "memory:output:",
+            RecordKind::OutputIndex => "memory:output_index:",
+            RecordKind::Error => "memory:error:",
+            RecordKind::Insight => "memory:insight:",
+            RecordKind::Suggestion => "memory:suggestion:",
+            RecordKind::Provenance => "memory:provenance:",
+            RecordKind::Event => "memory:event:",
+            RecordKind::Artifact => "memory:artifact:",
+            RecordKind::Impression => "memory:impression:",
+            RecordKind::Benchmark => "memory:benchmark:",
+            RecordKind::Flaky => "memory:flaky:",
+            RecordKind::View => "memory:view:",
+            RecordKind::Bookmark => "memory:bookmark:",
+            RecordKind::Preference => "memory:preference:",
+            RecordKind::DayStats => "memory:stats:day:",
+            RecordKind::Wal => "memory:wal:",
+            RecordKind::Schema => "memory:schema:",
+            RecordKind::App => "memory:app:",
+            RecordKind::Canvas => "runebook:canvas:",
+            RecordKind::CanvasMeta => "runebook:meta:",
+        }
+    }
+
+    /// Key prefix of the records of this kind scoped to `workspace`
+    pub fn workspace_prefix(self, workspace: &str) -> String {
+        format!("{}{}:", self.prefix(), escape(workspace))
+    }
+
+    /// Key of the record `id` of this kind
+    pub fn key(self, id: &str) -> String {
+        RecordKey::new(self, id).to_string()
+    }
+}
+
+/// A parsed or to-be-built store key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordKey {
+    pub kind: RecordKind,
+    pub workspace: Option<String>,
+    pub id: String,
+}
+
+impl RecordKey {
+    pub fn new(kind: RecordKind, id: impl Into<String>) -> Self {
+        Self {
+            kind,
+            workspace: None,
+            id: id.into(),
+        }
+    }
+
+    /// Scope the record to a workspace
+    pub fn in_workspace(mut self, workspace: impl Into<String>) -> Self {
+        self.workspace = Some(workspace.into());
+        self
+    }
+
+    /// Split a store key into its components; `None` for keys this schema
+    /// did not produce
+    pub fn parse(key: &str) -> Option<Self> {
+        // Longest prefix first, so no kind can swallow another's keys
+        let kind = RecordKind::ALL
+            .into_iter()
+            .filter(|kind| key.starts_with(kind.prefix()))
+            .max_by_key(|kind| kind.prefix().len())?;
+        let rest = &key[kind.prefix().len()..];
+        let mut parts = rest.split(':');
+        let (workspace, id) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), None, _) => (None, id),
+            (Some(workspace), Some(id), None) => (Some(unescape(workspace)?), id),
+            _ => return None,
+        };
+        if id.is_empty() {
+            return None;
+        }
+        Some(Self {
+            kind,
+            workspace,
+            id: unescape(id)?,
+        })
+    }
+}
+
+impl fmt::Display for RecordKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kind.prefix())?;
+        if let Some(workspace) = &self.workspace {
+            write!(f, "{}:", escape(workspace))?;
+        }
+        f.write_str(&escape(&self.id))
+    }
+}
+
+/// Percent-escape the separator (and the escape character) in a component.
+/// Ids in use (UUIDs, hashes, dates) need no escaping, so their keys are
+/// unchanged.
+fn escape(component: &str) -> String {
+    let mut escaped = String::with_capacity(component.len());
+    for c in component.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            ':' => escaped.push_str("%3A"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(component: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(component.len());
+    let mut rest = component;
+    while let Some(at) = rest.find('%') {
+        unescaped.push_str(&rest[..at]);
+        let code = rest.get(at + 1..at + 3)?;
+        unescaped.push(match code {
+            "25" => '%',
+            "3A" | "3a" => ':',
+            _ => return None,
+        });
+        rest = &rest[at + 3..];
+    }
+    unescaped.push_str(rest);
+    Some(unescaped)
+}
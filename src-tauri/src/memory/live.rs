@@ -0,0 +1,365 @@
+// Live output of running commands
+// Stores each chunk as it arrives and fans it out to followers, so one tail covers stored and live output
+// Each session also has a feed of its command starts, chunks and finishes, for read-only observers
+
+use crate::memory::ansi::StyledSpan;
+use crate::memory::api::MemoryStore;
+use crate::memory::backend::WriteBatch;
+use crate::memory::keys::RecordKind;
+use crate::memory::pages::chunk_text;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use ts_rs::TS;
+
+/// Live events buffered per follower before it falls behind
+const FOLLOW_BUFFER: usize = 256;
+
+/// One event of a `tail_output` stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum TailEvent {
+    Chunk {
+        chunk_index: u32,
+        stream_type: String,
+        text: String,
+        /// Colors of `text`, with `[output] ansi = "spans"`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        #[ts(as = "Option<Vec<StyledSpan>>", optional)]
+        spans: Vec<StyledSpan>,
+    },
+    /// Last event of a stream. `running` is set when the command had not
+    /// finished, as when tailing without `follow`.
+    End {
+        running: bool,
+        exit_code: Option<i32>,
+    },
+}
+
+/// A command as observers of its session see it: no environment, args scrubbed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ObservedCommand {
+    pub id: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub cwd: String,
+    pub started_at: DateTime<Utc>,
+}
+
+impl From<&Command> for ObservedCommand {
+    fn from(command: &Command) -> Self {
+        Self {
+            id: command.id.clone(),
+            command: command.command.clone(),
+            args: command.args.clone(),
+            cwd: command.cwd.clone(),
+            started_at: command.started_at,
+        }
+    }
+}
+
+/// One event of a session's live feed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum SessionEvent {
+    CommandStarted {
+        command: ObservedCommand,
+    },
+    Output {
+        command_id: String,
+        chunk_index: u32,
+        stream_type: String,
+        text: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        #[ts(as = "Option<Vec<StyledSpan>>", optional)]
+        spans: Vec<StyledSpan>,
+    },
+    CommandFinished {
+        command_id: String,
+        exit_code: Option<i32>,
+    },
+}
+
+struct LiveCommand {
+    command: ObservedCommand,
+    session_id: String,
+    next_chunk: u32,
+    events: broadcast::Sender<TailEvent>,
+}
+
+/// Commands whose output is still arriving
+#[derive(Default)]
+pub struct LiveOutputs {
+    running: Mutex<HashMap<String, LiveCommand>>,
+    /// Feeds of sessions someone observes, by session id
+    sessions: Mutex<HashMap<String, broadcast::Sender<SessionEvent>>>,
+}
+
+impl LiveOutputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start accepting output for `command`, as stored
+    fn begin(&self, command: &Command) {
+        let observed = ObservedCommand::from(command);
+        // Held while telling observers, so one joining now sees the start once
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running
+            .entry(command.id.clone())
+            .or_insert_with(|| LiveCommand {
+                command: observed.clone(),
+                session_id: command.session_id.clone(),
+                next_chunk: 0,
+                events: broadcast::channel(FOLLOW_BUFFER).0,
+            });
+        self.publish_session(
+            &command.session_id,
+            SessionEvent::CommandStarted { command: observed },
+        );
+        drop(running);
+    }
+
+    pub fn is_running(&self, command_id: &str) -> bool {
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(command_id)
+    }
+
+    /// Commands whose output is still arriving
+    pub fn running_count(&self) -> usize {
+        self.running.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Reserve the next chunk index of a running command
+    fn next_chunk(&self, command_id: &str) -> Option<u32> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let command = running.get_mut(command_id)?;
+        command.next_chunk += 1;
+        Some(command.next_chunk - 1)
+    }
+
+    fn publish(&self, command_id: &str, event: TailEvent) {
+        let session_id = {
+            let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(command) = running.get(command_id) else {
+                return;
+            };
+            // No followers is not an error
+            let _ = command.events.send(event.clone());
+            command.session_id.clone()
+        };
+        if let TailEvent::Chunk {
+            chunk_index,
+            stream_type,
+            text,
+            spans,
+        } = event
+        {
+            self.publish_session(
+                &session_id,
+                SessionEvent::Output {
+                    command_id: command_id.to_string(),
+                    chunk_index,
+                    stream_type,
+                    text,
+                    spans,
+                },
+            );
+        }
+    }
+
+    fn publish_session(&self, session_id: &str, event: SessionEvent) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(feed) = sessions.get(session_id) {
+            // The last observer left: drop the feed
+            if feed.send(event).is_err() {
+                sessions.remove(session_id);
+            }
+        }
+    }
+
+    /// Follow a session's feed, starting with a `command_started` for each of
+    /// its commands still running
+    pub fn observe_session(
+        &self,
+        session_id: &str,
+    ) -> (Vec<SessionEvent>, broadcast::Receiver<SessionEvent>) {
+        // Both locks, in `publish`'s order, so no start falls in between
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(session_id.to_string())
+            .or_insert_with(|| broadcast::channel(FOLLOW_BUFFER).0)
+            .subscribe();
+        let mut started: Vec<ObservedCommand> = running
+            .values()
+            .filter(|c| c.session_id == session_id)
+            .map(|c| c.command.clone())
+            .collect();
+        started.sort_by_key(|c| c.started_at);
+        let started = started
+            .into_iter()
+            .map(|command| SessionEvent::CommandStarted { command })
+            .collect();
+        (started, receiver)
+    }
+
+    fn subscribe(&self, command_id: &str) -> Option<broadcast::Receiver<TailEvent>> {
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(command_id)
+            .map(|c| c.events.subscribe())
+    }
+
+    /// The command finished: followers get their `End` event
+    pub fn finish(&self, command_id: &str, exit_code: Option<i32>) {
+        let Some(command) = self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(command_id)
+        else {
+            return;
+        };
+        let _ = command.events.send(TailEvent::End {
+            running: false,
+            exit_code,
+        });
+        self.publish_session(
+            &command.session_id,
+            SessionEvent::CommandFinished {
+                command_id: command_id.to_string(),
+                exit_code,
+            },
+        );
+    }
+}
+
+fn chunk_event(output: &Output) -> Result<TailEvent> {
+    Ok(TailEvent::Chunk {
+        chunk_index: output.chunk_index,
+        stream_type: output.stream_type.clone(),
+        text: chunk_text(output)?,
+        spans: output.spans.clone(),
+    })
+}
+
+impl MemoryStore {
+    /// Store a command that is starting and accept its live output. Observers
+    /// of its session are told, with the command as stored: scrubbed.
+    pub async fn begin_live_command(&self, live: &LiveOutputs, mut command: Command) -> Result<()> {
+        let mut batch = WriteBatch::new();
+        self.stage_command(&mut batch, &mut command).await?;
+        self.commit(batch).await?;
+        live.begin(&command);
+        Ok(())
+    }
+
+    /// Store the next chunk of a running command's output and send it to
+    /// followers. Fails if the command was not started with
+    /// [`LiveOutputs::begin`].
+    pub async fn append_live_output(
+        &self,
+        live: &LiveOutputs,
+        command_id: &str,
+        stream_type: &str,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let chunk_index = live
+            .next_chunk(command_id)
+            .ok_or_else(|| anyhow::anyhow!("Command {} is not running", command_id))?;
+        let mut output = Output::new(
+            command_id.to_string(),
+            stream_type.to_string(),
+            chunk_index,
+            data,
+        );
+        self.store_output(&mut output, false).await?;
+        live.publish(command_id, chunk_event(&output)?);
+        Ok(())
+    }
+
+    /// Whether a command is still running, and its exit code if not
+    async fn command_state(&self, live: &LiveOutputs, command_id: &str) -> Result<TailEvent> {
+        let command = self
+            .read_value(&RecordKind::Command.key(command_id))
+            .await?
+            .and_then(|v| serde_json::from_value::<Command>(v).ok());
+        Ok(TailEvent::End {
+            running: live.is_running(command_id)
+                || command
+                    .as_ref()
+                    .is_some_and(|c| c.ended_at.is_none() && c.duration_ms.is_none()),
+            exit_code: command.and_then(|c| c.exit_code),
+        })
+    }
+}
+
+/// Send stored chunks from `next_chunk` on, advancing it
+async fn send_stored(
+    store: &MemoryStore,
+    command_id: &str,
+    next_chunk: &mut u32,
+    send: &mut impl FnMut(TailEvent) -> Result<()>,
+) -> Result<()> {
+    for output in store.command_outputs(command_id).await? {
+        if output.chunk_index >= *next_chunk {
+            *next_chunk = output.chunk_index + 1;
+            send(chunk_event(&output)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Send a command's stored output chunk by chunk, then with `follow` its
+/// live chunks until it finishes. The last event is always [`TailEvent::End`].
+pub async fn tail_output(
+    store: &MemoryStore,
+    live: &LiveOutputs,
+    command_id: &str,
+    follow: bool,
+    mut send: impl FnMut(TailEvent) -> Result<()>,
+) -> Result<()> {
+    // Subscribe before reading, so no chunk falls between stored and live
+    let mut events = if follow {
+        live.subscribe(command_id)
+    } else {
+        None
+    };
+    let mut next_chunk = 0;
+    send_stored(store, command_id, &mut next_chunk, &mut send).await?;
+
+    let Some(events) = events.as_mut() else {
+        return send(store.command_state(live, command_id).await?);
+    };
+    loop {
+        match events.recv().await {
+            Ok(TailEvent::Chunk { chunk_index, .. }) if chunk_index < next_chunk => {}
+            Ok(event @ TailEvent::Chunk { chunk_index, .. }) => {
+                next_chunk = chunk_index + 1;
+                send(event)?;
+            }
+            Ok(end @ TailEvent::End { .. }) => return send(end),
+            // Missed live chunks are already stored
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                send_stored(store, command_id, &mut next_chunk, &mut send).await?
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                send_stored(store, command_id, &mut next_chunk, &mut send).await?;
+                return send(store.command_state(live, command_id).await?);
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Container log ingestion
+// Stores a followed log stream as a long-running command's live output and turns error lines into error records as they arrive
+
+use crate::memory::api::MemoryStore;
+use crate::memory::live::LiveOutputs;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::Utc;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+
+/// Error records stored per stream; a crash loop repeating one error is one
+/// record, and a flood of distinct ones stops here
+const MAX_LOG_ERRORS: usize = 100;
+
+/// Longest error message, in characters
+const MESSAGE_CHARS: usize = 300;
+
+/// A line of a log stream that reports an error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogErrorLine {
+    pub line: String,
+    /// "high" for fatal errors and panics, else "medium"
+    pub severity: &'static str,
+}
+
+fn error_line() -> &'static Regex {
+    static ERROR: OnceLock<Regex> = OnceLock::new();
+    ERROR.get_or_init(|| {
+        Regex::new(concat!(
+            r"\b(?:ERROR|ERR|FATAL|CRITICAL|PANIC)\b",
+            r#"|(?i:\blevel="?(?:error|fatal|critical)\b)"#,
+            r#"|(?i:"(?:level|severity)"\s*:\s*"(?:error|err|fatal|critical)")"#,
+            // klog (Kubernetes components): E0102 15:04:05.000000
+            r"|^[EF]\d{4} \d",
+            r"|^panic: ",
+            r"|^Traceback \(most recent call last\)",
+            r"|^Exception in thread ",
+            r"|^[\w.$]+(?:Exception|Error): ",
+        ))
+        .unwrap()
+    })
+}
+
+fn severe_line() -> &'static Regex {
+    static SEVERE: OnceLock<Regex> = OnceLock::new();
+    SEVERE.get_or_init(|| {
+        Regex::new(r"(?i:\b(?:fatal|critical|panic)\b)|^F\d{4} \d|^panic: ").unwrap()
+    })
+}
+
+/// Error lines in log output: ERROR, FATAL and CRITICAL levels, plain or
+/// structured (`level=error`, `"level":"error"`), klog errors, panics,
+/// exceptions and Python tracebacks
+pub fn scan_log_errors(text: &str) -> Vec<LogErrorLine> {
+    text.lines()
+        .map(str::trim_end)
+        .filter(|line| error_line().is_match(line))
+        .map(|line| LogErrorLine {
+            line: line.to_string(),
+            severity: if severe_line().is_match(line) {
+                "high"
+            } else {
+                "medium"
+            },
+        })
+        .collect()
+}
+
+/// What repeats of one error have in common: the line without its numbers,
+/// so timestamps, counters and ids do not make every repeat new
+fn signature(line: &str) -> String {
+    line.chars().filter(|c| !c.is_ascii_digit()).collect()
+}
+
+/// A followed log stream, stored as one running command in its own session
+pub struct LogIngest {
+    store: Arc<MemoryStore>,
+    command: Command,
+    source: String,
+    /// Unterminated last line of each stream, scanned once it completes
+    partial: HashMap<String, String>,
+    seen: HashSet<String>,
+}
+
+impl LogIngest {
+    /// Start a session and a running command for the stream `source`
+    /// (e.g. `docker:web`), read by `command args` in `cwd`
+    pub async fn start(
+        store: Arc<MemoryStore>,
+        live: &LiveOutputs,
+        source: &str,
+        command: &str,
+        args: Vec<String>,
+        cwd: &str,
+    ) -> Result<Self> {
+        let mut session = Session::new("logs".to_string(), cwd.to_string());
+        session.metadata = serde_json::json!({ "client": "logs", "source": source });
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_start".to_string(),
+                timestamp: session.started_at,
+                session_id: session.id.clone(),
+                data: serde_json::to_value(&session)?,
+                provenance: None,
+            })
+            .await?;
+        let mut command = Command::new(
+            session.id.clone(),
+            command.to_string(),
+            args,
+            cwd.to_string(),
+        );
+        command.tags.push(format!("logs:{}", source));
+        store.begin_live_command(live, command.clone()).await?;
+        Ok(Self {
+            store,
+            command,
+            source: source.to_string(),
+            partial: HashMap::new(),
+            seen: HashSet::new(),
+        })
+    }
+
+    pub fn command_id(&self) -> &str {
+        &self.command.id
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.command.session_id
+    }
+
+    /// Store the next chunk of `stream` ("stdout" or "stderr"), and an error
+    /// record for each error line in it not seen before. Returns those errors.
+    pub async fn push(
+        &mut self,
+        live: &LiveOutputs,
+        stream: &str,
+        data: Vec<u8>,
+    ) -> Result<Vec<Error>> {
+        let partial = self.partial.entry(stream.to_string()).or_default();
+        partial.push_str(&String::from_utf8_lossy(&data));
+        let complete = match partial.rfind('\n') {
+            Some(end) => partial.drain(..=end).collect::<String>(),
+            None => String::new(),
+        };
+        self.store
+            .append_live_output(live, &self.command.id, stream, data)
+            .await?;
+        self.store_errors(&complete, stream).await
+    }
+
+    async fn store_errors(&mut self, text: &str, stream: &str) -> Result<Vec<Error>> {
+        let mut stored = Vec::new();
+        for found in scan_log_errors(text) {
+            if self.seen.len() >= MAX_LOG_ERRORS || !self.seen.insert(signature(&found.line)) {
+                continue;
+            }
+            let mut error = Error::new(
+                self.command.id.clone(),
+                self.command.session_id.clone(),
+                "log_error".to_string(),
+                found.severity.to_string(),
+                found.line.trim().chars().take(MESSAGE_CHARS).collect(),
+            );
+            error.stderr_snippet = Some(found.line.chars().take(500).collect());
+            error.context = serde_json::json!({ "source": self.source, "stream": stream });
+            self.store.store_error(error.clone()).await?;
+            stored.push(error);
+        }
+        Ok(stored)
+    }
+
+    /// Finish the command once the stream ends. Following stopped on request
+    /// is a success; otherwise the exit code decides, and a failure is
+    /// classified from the output like any command's.
+    pub async fn finish(
+        mut self,
+        live: &LiveOutputs,
+        exit_code: Option<i32>,
+        stopped: bool,
+    ) -> Result<Vec<Error>> {
+        let rest: Vec<(String, String)> = self.partial.drain().collect();
+        let mut stored = Vec::new();
+        for (stream, text) in rest {
+            stored.extend(self.store_errors(&text, &stream).await?);
+        }
+        let ended = Utc::now();
+        self.command.ended_at = Some(ended);
+        self.command.duration_ms =
+            Some((ended - self.command.started_at).num_milliseconds() as u64);
+        self.command.exit_code = exit_code;
+        self.command.success = stopped || exit_code == Some(0);
+        self.store
+            .store_finished_command(self.command.clone(), Vec::new())
+            .await?;
+        live.finish(&self.command.id, exit_code);
+        self.store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_end".to_string(),
+                timestamp: ended,
+                session_id: self.command.session_id.clone(),
+                data: serde_json::json!({ "client": "logs", "source": self.source }),
+                provenance: None,
+            })
+            .await?;
+        Ok(stored)
+    }
+}
@@ -0,0 +1,149 @@
+// Dashboard metrics
+// Hourly failure counts, per-command durations and suggestion acceptance over a time range, for charting tooling health
+
+use crate::memory::analytics::percentile;
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::ranking::SuggestionImpression;
+use crate::memory::schema::Command;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, DurationRound, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// One value at the start of a time bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MetricPoint {
+    pub at: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// Duration stats of one program's finished commands
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommandDurations {
+    pub command: String,
+    pub runs: u64,
+    pub failures: u64,
+    pub mean_ms: f64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Start of each hour from `from` through `to`
+fn hours(from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let Ok(mut at) = from.duration_trunc(ChronoDuration::hours(1)) else {
+        return Vec::new();
+    };
+    let mut hours = Vec::new();
+    while at <= to {
+        hours.push(at);
+        at += ChronoDuration::hours(1);
+    }
+    hours
+}
+
+fn hour_of(at: DateTime<Utc>) -> DateTime<Utc> {
+    at.duration_trunc(ChronoDuration::hours(1)).unwrap_or(at)
+}
+
+/// Failed commands started in each hour of `from..=to`, zero-filled
+pub fn failures_per_hour(
+    commands: &[Command],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<MetricPoint> {
+    let mut failures: BTreeMap<DateTime<Utc>, u64> = BTreeMap::new();
+    for c in commands {
+        if !c.success && c.ended_at.is_some() && (from..=to).contains(&c.started_at) {
+            *failures.entry(hour_of(c.started_at)).or_default() += 1;
+        }
+    }
+    hours(from, to)
+        .into_iter()
+        .map(|at| MetricPoint {
+            at,
+            value: failures.get(&at).copied().unwrap_or(0) as f64,
+        })
+        .collect()
+}
+
+/// Duration stats per program of finished commands, slowest total first.
+/// Durations leave out time the machine slept.
+pub fn durations_by_command(commands: &[Command]) -> Vec<CommandDurations> {
+    let mut groups: BTreeMap<&str, (Vec<u64>, u64)> = BTreeMap::new();
+    for c in commands {
+        let Some(ms) = c.awake_ms() else {
+            continue;
+        };
+        let (durations, failures) = groups.entry(c.command.as_str()).or_default();
+        durations.push(ms);
+        *failures += u64::from(!c.success);
+    }
+    let mut rows: Vec<(u64, CommandDurations)> = groups
+        .into_iter()
+        .map(|(command, (mut durations, failures))| {
+            durations.sort_unstable();
+            let total: u64 = durations.iter().sum();
+            let row = CommandDurations {
+                command: command.to_string(),
+                runs: durations.len() as u64,
+                failures,
+                mean_ms: total as f64 / durations.len() as f64,
+                p50_ms: percentile(&durations, 0.5),
+                p95_ms: percentile(&durations, 0.95),
+                max_ms: *durations.last().unwrap_or(&0),
+            };
+            (total, row)
+        })
+        .collect();
+    rows.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.command.cmp(&b.1.command)));
+    rows.into_iter().map(|(_, row)| row).collect()
+}
+
+/// Share of shown suggestions that were accepted, per hour of `from..=to`.
+/// Hours without impressions have no point, since there was nothing to accept.
+pub fn suggestion_acceptance(
+    impressions: &[SuggestionImpression],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<MetricPoint> {
+    let mut buckets: BTreeMap<DateTime<Utc>, (usize, usize)> = BTreeMap::new();
+    for impression in impressions {
+        if impression.shown == 0 || !(from..=to).contains(&impression.surfaced_at) {
+            continue;
+        }
+        let (shown, accepted) = buckets.entry(hour_of(impression.surfaced_at)).or_default();
+        *shown += impression.shown;
+        *accepted += impression.accepted.len();
+    }
+    buckets
+        .into_iter()
+        .map(|(at, (shown, accepted))| MetricPoint {
+            at,
+            value: accepted as f64 / shown as f64,
+        })
+        .collect()
+}
+
+impl MemoryStore {
+    /// Commands started between `from` and `to`, oldest first
+    pub async fn commands_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Command>> {
+        let mut commands = Vec::new();
+        for key in self.client.list(RecordKind::Command.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(command) = serde_json::from_value::<Command>(value) {
+                    if (from..=to).contains(&command.started_at) {
+                        commands.push(command);
+                    }
+                }
+            }
+        }
+        commands.sort_by_key(|c| c.started_at);
+        Ok(commands)
+    }
+}
@@ -1,34 +1,87 @@
 // Migration and versioning mechanism for schema evolution
+// Heavy migrations (ones that rewrite many records) can be deferred until the user asks for them
 
 use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
 use anyhow::{Context, Result};
+use serde::Serialize;
+use ts_rs::TS;
 
-const SCHEMA_VERSION_KEY: &str = "memory:schema:version";
-const CURRENT_SCHEMA_VERSION: u32 = 1;
+const CURRENT_SCHEMA_VERSION: u32 = 2;
 
-/// Run all pending migrations
+/// One schema step, from `version - 1` to `version`
+#[derive(Debug, Clone, Copy, Serialize, TS)]
+#[ts(export)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    /// Touches every record of some type; may take a while on large stores
+    pub heavy: bool,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        heavy: false,
+    },
+    Migration {
+        version: 2,
+        description: "Rebuild daily command statistics from stored commands",
+        heavy: true,
+    },
+];
+
+/// Migrations after `current`, split into those to run now and those deferred.
+/// With `defer_heavy`, everything from the first heavy migration on waits, since
+/// migrations run in order.
+pub fn plan(current: u32, defer_heavy: bool) -> (Vec<Migration>, Vec<Migration>) {
+    let pending: Vec<Migration> = MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current)
+        .copied()
+        .collect();
+    let split = if defer_heavy {
+        pending
+            .iter()
+            .position(|m| m.heavy)
+            .unwrap_or(pending.len())
+    } else {
+        pending.len()
+    };
+    let (now, deferred) = pending.split_at(split);
+    (now.to_vec(), deferred.to_vec())
+}
+
+/// Run all pending migrations, heavy ones included
 pub async fn run_migrations(store: &MemoryStore) -> Result<()> {
-    let current_version = get_current_version(store).await?;
+    run_pending_migrations(store, false).await.map(|_| ())
+}
 
-    if current_version < CURRENT_SCHEMA_VERSION {
-        // Run migrations sequentially
-        for version in (current_version + 1)..=CURRENT_SCHEMA_VERSION {
-            migrate_to_version(store, version)
-                .await
-                .with_context(|| format!("Failed to migrate to version {}", version))?;
-        }
+/// Run pending migrations, stopping before the first heavy one when
+/// `defer_heavy` is set. Returns the status afterwards.
+pub async fn run_pending_migrations(
+    store: &MemoryStore,
+    defer_heavy: bool,
+) -> Result<MigrationStatus> {
+    let current_version = get_current_version(store).await?;
+    let (now, _) = plan(current_version, defer_heavy);
 
-        // Update version
-        set_version(store, CURRENT_SCHEMA_VERSION).await?;
+    // Run migrations sequentially, recording each step so an interrupted run resumes
+    for migration in now {
+        migrate_to_version(store, migration.version)
+            .await
+            .with_context(|| format!("Failed to migrate to version {}", migration.version))?;
+        set_version(store, migration.version).await?;
     }
 
-    Ok(())
+    get_migration_status(store).await
 }
 
 async fn get_current_version(store: &MemoryStore) -> Result<u32> {
     let client = &store.client;
 
-    match client.get(SCHEMA_VERSION_KEY).await? {
+    match client.get(&RecordKind::Schema.key("version")).await? {
         Some(value) => {
             if let Some(version) = value.as_u64() {
                 Ok(version as u32)
@@ -43,17 +96,28 @@ async fn get_current_version(store: &MemoryStore) -> Result<u32> {
 async fn set_version(store: &MemoryStore, version: u32) -> Result<()> {
     let client = &store.client;
     let value = serde_json::json!(version);
-    client.put(SCHEMA_VERSION_KEY, &value).await?;
+    client
+        .put(&RecordKind::Schema.key("version"), &value)
+        .await?;
     Ok(())
 }
 
-async fn migrate_to_version(_store: &MemoryStore, version: u32) -> Result<()> {
+async fn migrate_to_version(store: &MemoryStore, version: u32) -> Result<()> {
     match version {
         1 => {
             // Initial schema version - no migration needed
             // This is where we would migrate from version 0 to 1
             Ok(())
         }
+        2 => {
+            // Stores written before time reports have no daily stats for older commands
+            let commands = store.rebuild_command_stats().await?;
+            log::info!(
+                "[migration] Rebuilt command statistics from {} commands",
+                commands
+            );
+            Ok(())
+        }
         _ => {
             anyhow::bail!("Unknown migration version: {}", version);
         }
@@ -63,26 +127,31 @@ async fn migrate_to_version(_store: &MemoryStore, version: u32) -> Result<()> {
 /// Get migration status
 pub async fn get_migration_status(store: &MemoryStore) -> Result<MigrationStatus> {
     let current_version = get_current_version(store).await?;
+    let (pending, _) = plan(current_version, false);
 
     Ok(MigrationStatus {
         current_version,
         target_version: CURRENT_SCHEMA_VERSION,
         is_up_to_date: current_version >= CURRENT_SCHEMA_VERSION,
+        pending,
     })
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
 pub struct MigrationStatus {
     pub current_version: u32,
     pub target_version: u32,
     pub is_up_to_date: bool,
+    /// Migrations not yet applied, in order
+    pub pending: Vec<Migration>,
 }
 
 // Future migration examples:
 //
 // async fn migrate_to_version_2(store: &MemoryStore) -> Result<()> {
 //     // Example: Add a new field to all sessions
-//     let keys = store.client.list("memory:session:").await?;
+//     let keys = store.client.list(RecordKind::Session.prefix()).await?;
 //     for key in keys {
 //         if let Some(mut value) = store.client.get(&key).await? {
 //             // Add new field
@@ -95,7 +164,7 @@ pub struct MigrationStatus {
 //
 // async fn migrate_to_version_3(store: &MemoryStore) -> Result<()> {
 //     // Example: Rename a field across all commands
-//     let keys = store.client.list("memory:command:").await?;
+//     let keys = store.client.list(RecordKind::Command.prefix()).await?;
 //     for key in keys {
 //         if let Some(mut value) = store.client.get(&key).await? {
 //             if let Some(old_value) = value.get("old_field_name").cloned() {
@@ -0,0 +1,500 @@
+// SQLite mirror of memory records for ad-hoc SQL analysis
+// Keeps relational, indexed copies of sessions, commands, outputs (metadata only), errors, insights and suggestions
+
+use crate::health::Component;
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use crate::memory::StoreOpener;
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// Bumped when the mirror's tables change; an older file is rebuilt
+const MIRROR_SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    shell_type TEXT NOT NULL,
+    initial_cwd TEXT NOT NULL,
+    hostname TEXT,
+    user TEXT
+);
+CREATE TABLE commands (
+    id TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    command TEXT NOT NULL,
+    args TEXT NOT NULL,
+    command_line TEXT NOT NULL,
+    cwd TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    duration_ms INTEGER,
+    exit_code INTEGER,
+    success INTEGER NOT NULL
+);
+CREATE TABLE outputs (
+    id TEXT PRIMARY KEY,
+    command_id TEXT NOT NULL,
+    stream TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    tier TEXT NOT NULL,
+    timestamp TEXT NOT NULL
+);
+CREATE TABLE errors (
+    id TEXT PRIMARY KEY,
+    command_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    error_type TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    message TEXT NOT NULL,
+    exit_code INTEGER,
+    timestamp TEXT NOT NULL
+);
+CREATE TABLE insights (
+    id TEXT PRIMARY KEY,
+    command_id TEXT,
+    session_id TEXT,
+    insight_type TEXT NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    confidence REAL NOT NULL,
+    source TEXT NOT NULL,
+    generated_at TEXT NOT NULL
+);
+CREATE TABLE suggestions (
+    id TEXT PRIMARY KEY,
+    suggestion_type TEXT NOT NULL,
+    priority TEXT NOT NULL,
+    rank REAL NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    command TEXT,
+    created_at TEXT NOT NULL,
+    dismissed INTEGER NOT NULL,
+    applied INTEGER NOT NULL
+);
+CREATE TABLE tags (
+    table_name TEXT NOT NULL,
+    record_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (table_name, record_id, tag)
+);
+CREATE TABLE mirror_state (
+    table_name TEXT NOT NULL,
+    record_id TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (table_name, record_id)
+);
+CREATE INDEX commands_session ON commands (session_id);
+CREATE INDEX commands_started ON commands (started_at);
+CREATE INDEX commands_command ON commands (command);
+CREATE INDEX commands_cwd ON commands (cwd);
+CREATE INDEX outputs_command ON outputs (command_id);
+CREATE INDEX errors_command ON errors (command_id);
+CREATE INDEX errors_timestamp ON errors (timestamp);
+CREATE INDEX errors_type ON errors (error_type);
+CREATE INDEX insights_command ON insights (command_id);
+CREATE INDEX tags_tag ON tags (tag);
+";
+
+/// `[mirror]` configuration section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct MirrorConfig {
+    pub enabled: bool,
+    /// Mirror file; defaults to `memory-mirror.sqlite` in the config directory
+    pub path: Option<PathBuf>,
+    pub interval_secs: u64,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            interval_secs: 60,
+        }
+    }
+}
+
+impl MirrorConfig {
+    pub fn path(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| crate::config::config_dir().join("memory-mirror.sqlite"))
+    }
+}
+
+/// One record as a row of a mirror table
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorRow {
+    pub table: &'static str,
+    pub id: String,
+    /// In the table's column order, starting with `id`
+    pub values: Vec<SqlValue>,
+    pub tags: Vec<String>,
+}
+
+impl MirrorRow {
+    fn hash(&self) -> String {
+        let text = format!("{:?}{:?}", self.values, self.tags);
+        hex::encode(Sha256::digest(text.as_bytes()))
+    }
+}
+
+/// Outcome of one mirror refresh
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorReport {
+    pub written: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+}
+
+fn time(value: DateTime<Utc>) -> SqlValue {
+    SqlValue::Text(value.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+fn text(value: impl Into<String>) -> SqlValue {
+    SqlValue::Text(value.into())
+}
+
+fn opt<T>(value: Option<T>, into: impl FnOnce(T) -> SqlValue) -> SqlValue {
+    value.map(into).unwrap_or(SqlValue::Null)
+}
+
+fn int(value: impl Into<i64>) -> SqlValue {
+    SqlValue::Integer(value.into())
+}
+
+fn row(table: &'static str, id: &str, values: Vec<SqlValue>, tags: Vec<String>) -> MirrorRow {
+    let mut all = vec![text(id)];
+    all.extend(values);
+    MirrorRow {
+        table,
+        id: id.to_string(),
+        values: all,
+        tags,
+    }
+}
+
+type RowBuilder = fn(Value) -> Option<MirrorRow>;
+
+/// Key prefix and row builder of every mirrored record type
+const TABLES: &[(&str, RowBuilder)] = &[
+    (RecordKind::Session.prefix(), session_row),
+    (RecordKind::Command.prefix(), command_row),
+    (RecordKind::Output.prefix(), output_row),
+    (RecordKind::Error.prefix(), error_row),
+    (RecordKind::Insight.prefix(), insight_row),
+    (RecordKind::Suggestion.prefix(), suggestion_row),
+];
+
+fn session_row(value: Value) -> Option<MirrorRow> {
+    let s: Session = serde_json::from_value(value).ok()?;
+    Some(row(
+        "sessions",
+        &s.id,
+        vec![
+            time(s.started_at),
+            opt(s.ended_at, time),
+            text(s.shell_type),
+            text(s.initial_cwd),
+            opt(s.hostname, text),
+            opt(s.user, text),
+        ],
+        s.tags,
+    ))
+}
+
+fn command_row(value: Value) -> Option<MirrorRow> {
+    let c: Command = serde_json::from_value(value).ok()?;
+    let command_line = shlex::try_join(
+        std::iter::once(c.command.as_str()).chain(c.args.iter().map(String::as_str)),
+    )
+    .unwrap_or_else(|_| format!("{} {}", c.command, c.args.join(" ")));
+    Some(row(
+        "commands",
+        &c.id,
+        vec![
+            text(c.session_id),
+            text(c.command),
+            text(serde_json::to_string(&c.args).ok()?),
+            text(command_line),
+            text(c.cwd),
+            time(c.started_at),
+            opt(c.ended_at, time),
+            opt(c.duration_ms, |d| SqlValue::Integer(d as i64)),
+            opt(c.exit_code, int),
+            int(c.success),
+        ],
+        c.tags,
+    ))
+}
+
+fn output_row(value: Value) -> Option<MirrorRow> {
+    let o: Output = serde_json::from_value(value).ok()?;
+    let tier = serde_json::to_value(o.tier).ok()?;
+    Some(row(
+        "outputs",
+        &o.id,
+        vec![
+            text(o.command_id),
+            text(o.stream_type),
+            int(o.chunk_index),
+            SqlValue::Integer(o.size_bytes as i64),
+            text(tier.as_str().unwrap_or_default()),
+            time(o.timestamp),
+        ],
+        Vec::new(),
+    ))
+}
+
+fn error_row(value: Value) -> Option<MirrorRow> {
+    let e: Error = serde_json::from_value(value).ok()?;
+    Some(row(
+        "errors",
+        &e.id,
+        vec![
+            text(e.command_id),
+            text(e.session_id),
+            text(e.error_type),
+            text(e.severity),
+            text(e.message),
+            opt(e.exit_code, int),
+            time(e.timestamp),
+        ],
+        Vec::new(),
+    ))
+}
+
+fn insight_row(value: Value) -> Option<MirrorRow> {
+    let i: Insight = serde_json::from_value(value).ok()?;
+    Some(row(
+        "insights",
+        &i.id,
+        vec![
+            opt(i.command_id, text),
+            opt(i.session_id, text),
+            text(i.insight_type),
+            text(i.title),
+            text(i.description),
+            SqlValue::Real(i.confidence),
+            text(i.source),
+            time(i.generated_at),
+        ],
+        Vec::new(),
+    ))
+}
+
+fn suggestion_row(value: Value) -> Option<MirrorRow> {
+    let s: Suggestion = serde_json::from_value(value).ok()?;
+    Some(row(
+        "suggestions",
+        &s.id,
+        vec![
+            text(s.suggestion_type),
+            text(s.priority),
+            SqlValue::Real(s.rank),
+            text(s.title),
+            text(s.description),
+            opt(s.command, text),
+            time(s.created_at),
+            int(s.dismissed),
+            int(s.applied),
+        ],
+        Vec::new(),
+    ))
+}
+
+/// Key prefixes of the record types the mirror holds
+pub fn mirrored_prefixes() -> impl Iterator<Item = &'static str> {
+    TABLES.iter().map(|(prefix, _)| *prefix)
+}
+
+/// The mirror row of the record stored under `key`; `None` for record types
+/// the mirror leaves out
+pub fn mirror_row(key: &str, value: Value) -> Option<MirrorRow> {
+    let (_, build) = TABLES.iter().find(|(prefix, _)| key.starts_with(prefix))?;
+    build(value)
+}
+
+impl MemoryStore {
+    /// Every record the mirror holds, as rows. Output content is left out.
+    pub async fn mirror_rows(&self) -> Result<Vec<MirrorRow>> {
+        anyhow::ensure!(
+            !self.is_encrypted(),
+            "The memory store is encrypted; a SQLite mirror would hold it in the clear"
+        );
+        let mut rows = Vec::new();
+        for (prefix, build) in TABLES {
+            for key in self.client.list(prefix).await? {
+                if let Some(row) = self.read_value(&key).await?.and_then(build) {
+                    rows.push(row);
+                }
+            }
+        }
+        Ok(rows)
+    }
+}
+
+/// Open (or create) a mirror file. A file from another schema version is
+/// rebuilt from scratch; it only ever holds copies.
+pub fn open_mirror(path: &Path) -> Result<rusqlite::Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let conn = rusqlite::Connection::open(path)
+        .with_context(|| format!("Failed to open mirror {}", path.display()))?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    if version != MIRROR_SCHEMA_VERSION {
+        recreate_mirror(&conn)?;
+    }
+    Ok(conn)
+}
+
+/// Open a mirror file emptied of every row, to be filled again from memory
+pub fn reset_mirror(path: &Path) -> Result<rusqlite::Connection> {
+    let conn = open_mirror(path)?;
+    recreate_mirror(&conn)?;
+    Ok(conn)
+}
+
+fn recreate_mirror(conn: &rusqlite::Connection) -> Result<()> {
+    let tables: Vec<String> = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for table in tables {
+        conn.execute_batch(&format!("DROP TABLE \"{}\"", table.replace('"', "\"\"")))?;
+    }
+    conn.execute_batch(SCHEMA)?;
+    conn.pragma_update(None, "user_version", MIRROR_SCHEMA_VERSION)?;
+    Ok(())
+}
+
+/// Bring the mirror in line with `rows`: changed records are rewritten and
+/// records no longer in memory are deleted, all in one transaction
+pub fn apply_mirror(conn: &mut rusqlite::Connection, rows: &[MirrorRow]) -> Result<MirrorReport> {
+    let tx = conn.transaction()?;
+    let mut known: HashMap<(String, String), String> = tx
+        .prepare("SELECT table_name, record_id, hash FROM mirror_state")?
+        .query_map([], |r| Ok(((r.get(0)?, r.get(1)?), r.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut report = MirrorReport::default();
+    for row in rows {
+        let key = (row.table.to_string(), row.id.clone());
+        let hash = row.hash();
+        if known.remove(&key).is_some_and(|old| old == hash) {
+            report.unchanged += 1;
+            continue;
+        }
+        write_row(&tx, row, &hash)?;
+        report.written += 1;
+    }
+
+    // Whatever is left was deleted from memory (sessions wiped, retention, ...)
+    for (table, id) in known.into_keys() {
+        tx.execute(&format!("DELETE FROM {} WHERE id = ?1", table), [&id])?;
+        tx.execute(
+            "DELETE FROM tags WHERE table_name = ?1 AND record_id = ?2",
+            (&table, &id),
+        )?;
+        tx.execute(
+            "DELETE FROM mirror_state WHERE table_name = ?1 AND record_id = ?2",
+            (&table, &id),
+        )?;
+        report.deleted += 1;
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Write `row`, with its tags, over whatever the mirror holds for it
+fn write_row(tx: &rusqlite::Transaction, row: &MirrorRow, hash: &str) -> Result<()> {
+    let placeholders = vec!["?"; row.values.len()].join(", ");
+    tx.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {} VALUES ({})",
+            row.table, placeholders
+        ),
+        rusqlite::params_from_iter(&row.values),
+    )?;
+    tx.execute(
+        "DELETE FROM tags WHERE table_name = ?1 AND record_id = ?2",
+        (row.table, &row.id),
+    )?;
+    for tag in &row.tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO tags VALUES (?1, ?2, ?3)",
+            (row.table, &row.id, tag),
+        )?;
+    }
+    tx.execute(
+        "INSERT OR REPLACE INTO mirror_state VALUES (?1, ?2, ?3)",
+        (row.table, &row.id, hash),
+    )?;
+    Ok(())
+}
+
+/// Write `rows` in one transaction, leaving every other row alone
+pub fn write_mirror_rows(conn: &mut rusqlite::Connection, rows: &[MirrorRow]) -> Result<()> {
+    let tx = conn.transaction()?;
+    for row in rows {
+        write_row(&tx, row, &row.hash())?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Refresh the mirror at `path` from `store` once
+pub async fn sync_mirror(store: &MemoryStore, path: PathBuf) -> Result<MirrorReport> {
+    let rows = store.mirror_rows().await?;
+    tokio::task::spawn_blocking(move || apply_mirror(&mut open_mirror(&path)?, &rows)).await?
+}
+
+/// Refresh the mirror every `interval_secs`. Returns at once when the mirror is off.
+pub async fn run_mirror_loop(config: MirrorConfig, open_store: StoreOpener, health: Component) {
+    if !config.enabled {
+        return health.disable("Set [mirror] enabled to keep a SQLite mirror");
+    }
+    let path = config.path();
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(config.interval_secs.max(5)));
+    loop {
+        interval.tick().await;
+        let result = async {
+            let store = open_store().await?;
+            sync_mirror(&store, path.clone()).await
+        }
+        .await;
+        match result {
+            Ok(report) => {
+                health.ok();
+                if report.written + report.deleted > 0 {
+                    log::debug!(
+                        "[mirror] {} rows written, {} deleted",
+                        report.written,
+                        report.deleted
+                    );
+                }
+            }
+            Err(e) => {
+                log::warn!("[mirror] Refreshing {} failed: {:#}", path.display(), e);
+                health.error(format!("{:#}", e));
+            }
+        }
+    }
+}
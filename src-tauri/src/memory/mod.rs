@@ -1,20 +1,97 @@
 // PluresDB cognitive memory storage module
 // Local-first "cognitive memory" for terminal events, commands, outputs, errors, insights, and suggestions
 
+pub mod analytics;
+pub mod ansi;
 pub mod api;
+pub mod backend;
+pub mod benchmarks;
+pub mod bookmarks;
+pub mod canvases;
 pub mod client;
+pub mod codec;
+pub mod content;
+pub mod demo;
+pub mod diagnostics;
+pub mod encoding;
 pub mod encryption;
+pub mod events;
+pub mod explain;
+pub mod flaky;
+pub mod format;
+pub mod handoff;
+pub mod history;
+pub mod intent;
+pub mod keys;
+pub mod live;
+pub mod logs;
+pub mod metrics;
 pub mod migration;
+pub mod mirror;
+pub mod pages;
+pub mod parquet;
+pub mod parsers;
+pub mod preferences;
+pub mod quota;
+pub mod ranking;
+pub mod reindex;
+pub mod rerun;
+pub mod retention;
 pub mod schema;
+pub mod scrub;
+pub mod shell_integration;
+pub mod stats;
+pub mod tags;
+pub mod usage;
+pub mod views;
 
 #[cfg(test)]
 mod tests;
 
+pub use analytics::{AnalyticsFormat, AnalyticsManifest};
+pub use ansi::{AnsiMode, StyledSpan};
 pub use api::MemoryStore;
+pub use backend::{InMemoryBackend, MemoryBackend, PutOutcome, WriteBatch};
+pub use bookmarks::{Bookmark, LineRange, ResolvedBookmark};
+pub use canvases::CanvasSummary;
 pub use client::PluresDBClient;
+pub use codec::{Codec, CodecBenchmark, CodecId};
+pub use demo::{DemoSummary, DEMO_WORKSPACE};
+pub use diagnostics::{FileDiagnostics, SourceLocation};
+pub use encoding::EncodingConfig;
+pub use events::{EventBus, StoreEvent};
+pub use explain::{Evidence, RankingBreakdown, StopCondition, SuggestionExplanation};
+pub use format::{format_suggestion, SuggestionCard, SuggestionFormat};
+pub use handoff::{Handoff, HandoffSummary, WorkspaceState};
+pub use intent::{Activity, IntentConfig};
+pub use keys::{RecordKey, RecordKind};
+pub use live::{LiveOutputs, ObservedCommand, SessionEvent, TailEvent};
+pub use metrics::{CommandDurations, MetricPoint};
+pub use mirror::{MirrorConfig, MirrorReport};
+pub use pages::{OutputPage, OutputPageCache};
+pub use parsers::{ParsedOutput, StructuredFailure};
+pub use preferences::{
+    FixStyle, Learned, MutedReason, PreferenceChoice, PreferenceName, PreferenceSource,
+    Preferences, Verbosity,
+};
+pub use quota::{QuotaConfig, QuotaEvent, QuotaGuard, QuotaPolicy};
+pub use ranking::{RankEvalReport, RankingConfig};
+pub use reindex::{IndexKind, ReindexProgress, ReindexReport};
+pub use retention::{OutputTier, RetentionConfig};
 pub use schema::*;
+pub use scrub::{ScrubConfig, ScrubStage, Scrubber};
+pub use stats::{TimeGroupBy, TimeReport};
+pub use tags::{AutoTagConfig, AutoTagger, TagCount, TagTarget};
+pub use usage::UsageReport;
+pub use views::{SavedView, ViewChange, ViewFilter, ViewMatch, ViewWatcher};
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use ts_rs::TS;
 
 /// Initialize the memory store with a PluresDB connection.
 ///
@@ -25,8 +102,78 @@ pub async fn init_memory_store(host: &str, port: u16, _data_dir: &str) -> Result
     let client = PluresDBClient::new(host, port)?;
     let store = MemoryStore::new(client).await?;
 
-    // Run migrations
-    migration::run_migrations(&store).await?;
+    // Run migrations; heavy ones wait for an explicit `run_migrations`
+    migration::run_pending_migrations(&store, true).await?;
+
+    // Finish capture batches a crashed writer left half-applied
+    let replayed = backend::replay_wal(store.client.as_ref()).await?;
+    if replayed > 0 {
+        log::info!("Replayed {} interrupted memory write(s)", replayed);
+    }
 
     Ok(store)
 }
+
+/// `[memory]` configuration section: the PluresDB server behind the memory store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct MemoryConfig {
+    pub host: String,
+    pub port: u16,
+    /// Defaults to `pluresdb-data` in the profile's config directory
+    pub data_dir: Option<PathBuf>,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 34567,
+            data_dir: None,
+        }
+    }
+}
+
+impl MemoryConfig {
+    pub fn data_dir(&self) -> PathBuf {
+        self.data_dir
+            .clone()
+            .unwrap_or_else(|| crate::config::config_dir().join("pluresdb-data"))
+    }
+
+    /// The active profile's `[memory]` section, read once per process
+    pub fn configured() -> &'static MemoryConfig {
+        static CONFIGURED: OnceLock<MemoryConfig> = OnceLock::new();
+        CONFIGURED.get_or_init(|| {
+            crate::config::RunebookConfig::load_default()
+                .map(|config| config.memory)
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Open the memory store the active profile is configured for
+pub async fn open_configured_store() -> Result<MemoryStore> {
+    let config = MemoryConfig::configured();
+    init_memory_store(
+        &config.host,
+        config.port,
+        &config.data_dir().to_string_lossy(),
+    )
+    .await
+}
+
+/// Opens a memory store on demand. Long-running services (capture socket, HTTP
+/// API) hold one of these rather than a store, since PluresDB may start later.
+pub type StoreOpener =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<MemoryStore>> + Send>> + Send + Sync>;
+
+/// Wrap an async fn as a [`StoreOpener`]
+pub fn store_opener<F, Fut>(open: F) -> StoreOpener
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<MemoryStore>> + Send + 'static,
+{
+    Arc::new(move || Box::pin(open()))
+}
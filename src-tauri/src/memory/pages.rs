@@ -0,0 +1,379 @@
+// Paged reads of stored command output
+// Serves line ranges and tails from per-chunk line-index sidecars, decompressing only the chunks a range touches
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use ts_rs::TS;
+
+/// Most lines a single page may hold
+pub const MAX_PAGE_LINES: usize = 5_000;
+
+/// Newlines between checkpoints in a line index
+pub const LINE_INDEX_STRIDE: u64 = 256;
+
+/// Commands whose line indexes the page cache keeps
+const CACHED_COMMANDS: usize = 1_024;
+
+/// A range of a command's output lines
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OutputPage {
+    pub command_id: String,
+    pub offset_lines: usize,
+    pub lines: Vec<String>,
+    pub total_lines: usize,
+    pub has_more: bool,
+}
+
+/// Uncompressed content of a stored chunk
+fn chunk_reader(output: &Output) -> Result<Box<dyn Read + '_>> {
+    output.codec().codec().decoder(&output.content)
+}
+
+/// Uncompressed bytes of a stored chunk
+pub(crate) fn chunk_bytes(output: &Output) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    chunk_reader(output)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Text of a stored chunk
+pub(crate) fn chunk_text(output: &Output) -> Result<String> {
+    Ok(String::from_utf8_lossy(&chunk_bytes(output)?).into_owned())
+}
+
+/// Build the line index of an output chunk
+pub fn index_output(output: &Output) -> Result<OutputLineIndex> {
+    let mut reader = BufReader::new(chunk_reader(output)?);
+    let mut newlines = 0;
+    let mut byte_len = 0;
+    let mut last_byte = None;
+    let mut checkpoints = vec![0];
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        for (i, byte) in buf.iter().enumerate() {
+            if *byte == b'\n' {
+                newlines += 1;
+                if newlines % LINE_INDEX_STRIDE == 0 {
+                    checkpoints.push(byte_len + i as u64 + 1);
+                }
+            }
+        }
+        let len = buf.len();
+        last_byte = buf.last().copied();
+        byte_len += len as u64;
+        reader.consume(len);
+    }
+    Ok(OutputLineIndex {
+        output_id: output.id.clone(),
+        command_id: output.command_id.clone(),
+        chunk_index: output.chunk_index,
+        timestamp: output.timestamp,
+        newlines,
+        byte_len,
+        ends_with_newline: last_byte == Some(b'\n'),
+        stride: LINE_INDEX_STRIDE,
+        checkpoints,
+    })
+}
+
+/// Lines in the output the chunk indexes describe, taken in order
+pub fn total_lines(indexes: &[OutputLineIndex]) -> usize {
+    let newlines: u64 = indexes.iter().map(|i| i.newlines).sum();
+    let unterminated = indexes
+        .iter()
+        .rev()
+        .find(|i| i.byte_len > 0)
+        .is_some_and(|i| !i.ends_with_newline);
+    (newlines + u64::from(unterminated)) as usize
+}
+
+/// Chunks holding lines `offset..offset + limit`, and how many newlines of
+/// the first one come before line `offset`
+pub(crate) fn chunk_span(
+    indexes: &[OutputLineIndex],
+    offset: usize,
+    limit: usize,
+) -> Option<(Range<usize>, u64)> {
+    if limit == 0 || offset >= total_lines(indexes) {
+        return None;
+    }
+    let (offset, end) = (offset as u64, offset.saturating_add(limit) as u64);
+    let mut start = None;
+    let mut prior = 0;
+    for (c, index) in indexes.iter().enumerate() {
+        let through = prior + index.newlines;
+        if start.is_none() && (offset == 0 || offset <= through) {
+            start = Some((c, offset - prior));
+        }
+        // The last requested line ends at newline number `end`
+        if let Some((first, skip)) = start {
+            if through >= end {
+                return Some((first..c + 1, skip));
+            }
+        }
+        prior = through;
+    }
+    start.map(|(first, skip)| (first..indexes.len(), skip))
+}
+
+fn line_text(bytes: &[u8]) -> String {
+    let line = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+/// Up to `limit` lines from consecutive chunks, starting after `skip`
+/// newlines of the first. Seeks to the nearest checkpoint before scanning.
+pub(crate) fn read_lines(
+    chunks: &[(&OutputLineIndex, &Output)],
+    skip: u64,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut partial = Vec::new();
+    for (n, (index, output)) in chunks.iter().enumerate() {
+        let mut reader = BufReader::new(chunk_reader(output)?);
+        if n == 0 && skip > 0 {
+            let stride = index.stride.max(1);
+            let (mut skipped, from) = match index.checkpoints.get((skip / stride) as usize) {
+                Some(&from) => (skip / stride * stride, from),
+                None => (0, 0),
+            };
+            io::copy(&mut reader.by_ref().take(from), &mut io::sink())?;
+            let mut scratch = Vec::new();
+            while skipped < skip && reader.read_until(b'\n', &mut scratch)? > 0 {
+                scratch.clear();
+                skipped += 1;
+            }
+        }
+        while lines.len() < limit {
+            if reader.read_until(b'\n', &mut partial)? == 0 {
+                break;
+            }
+            if partial.last() == Some(&b'\n') {
+                lines.push(line_text(&partial));
+                partial.clear();
+            }
+        }
+        if lines.len() == limit {
+            return Ok(lines);
+        }
+    }
+    if !partial.is_empty() {
+        lines.push(line_text(&partial));
+    }
+    Ok(lines)
+}
+
+impl MemoryStore {
+    /// Every output chunk of a command, in capture order
+    pub async fn command_outputs(&self, command_id: &str) -> Result<Vec<Output>> {
+        let mut outputs = Vec::new();
+        for key in self.client.list(RecordKind::Output.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(mut output) = serde_json::from_value::<Output>(value) {
+                    if output.command_id == command_id {
+                        self.hydrate_output(&mut output).await?;
+                        outputs.push(output);
+                    }
+                }
+            }
+        }
+        outputs.sort_by_key(|o| (o.chunk_index, o.timestamp));
+        Ok(outputs)
+    }
+
+    /// Objects captured in a command's [`OBJECTS_STREAM`], in capture order
+    pub async fn command_objects(&self, command_id: &str) -> Result<Vec<serde_json::Value>> {
+        let mut ndjson = String::new();
+        for output in self.command_outputs(command_id).await? {
+            if output.stream_type == OBJECTS_STREAM {
+                ndjson.push_str(&chunk_text(&output)?);
+            }
+        }
+        Ok(crate::execution::pwsh::parse_objects(&ndjson))
+    }
+
+    /// Write the sidecar index of an output chunk
+    pub(crate) async fn store_line_index(&self, index: &OutputLineIndex) -> Result<()> {
+        let key = RecordKind::OutputIndex.key(&index.output_id);
+        self.write_value(&key, serde_json::to_value(index)?).await
+    }
+
+    /// Line indexes of a command's output chunks, in capture order. Outputs
+    /// stored before sidecars existed get theirs built and saved here.
+    pub async fn output_line_indexes(&self, command_id: &str) -> Result<Vec<OutputLineIndex>> {
+        let mut indexes = Vec::new();
+        for key in self.client.list(RecordKind::OutputIndex.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(index) = serde_json::from_value::<OutputLineIndex>(value) {
+                    if index.command_id == command_id {
+                        indexes.push(index);
+                    }
+                }
+            }
+        }
+        if indexes.is_empty() {
+            for output in self.command_outputs(command_id).await? {
+                let index = index_output(&output)?;
+                self.store_line_index(&index).await?;
+                indexes.push(index);
+            }
+        }
+        indexes.sort_by_key(|i| (i.chunk_index, i.timestamp));
+        Ok(indexes)
+    }
+
+    /// Up to `limit_lines` (at most [`MAX_PAGE_LINES`]) lines from
+    /// `offset_lines` of the output `indexes` describe. Only the chunks the
+    /// range touches are read.
+    pub async fn read_output_lines(
+        &self,
+        command_id: &str,
+        indexes: &[OutputLineIndex],
+        offset_lines: usize,
+        limit_lines: usize,
+    ) -> Result<OutputPage> {
+        let limit = limit_lines.min(MAX_PAGE_LINES);
+        let lines = match chunk_span(indexes, offset_lines, limit) {
+            Some((span, skip)) => {
+                let mut outputs = Vec::new();
+                for index in &indexes[span.clone()] {
+                    let value = self
+                        .read_value(&RecordKind::Output.key(&index.output_id))
+                        .await?
+                        .with_context(|| format!("Output chunk {} is missing", index.output_id))?;
+                    let mut output = serde_json::from_value::<Output>(value)?;
+                    self.hydrate_output(&mut output).await?;
+                    outputs.push(output);
+                }
+                let chunks: Vec<_> = indexes[span].iter().zip(&outputs).collect();
+                read_lines(&chunks, skip, limit)?
+            }
+            None => Vec::new(),
+        };
+        let total_lines = total_lines(indexes);
+        Ok(OutputPage {
+            command_id: command_id.to_string(),
+            offset_lines,
+            has_more: offset_lines + lines.len() < total_lines,
+            lines,
+            total_lines,
+        })
+    }
+}
+
+/// Line indexes of finished commands, so scrolling does not re-list the store
+#[derive(Default)]
+pub struct OutputPageCache {
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    indexes: HashMap<String, Arc<Vec<OutputLineIndex>>>,
+    /// Command ids, oldest first
+    order: VecDeque<String>,
+}
+
+impl OutputPageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    async fn indexes(
+        &self,
+        store: &MemoryStore,
+        command_id: &str,
+    ) -> Result<Arc<Vec<OutputLineIndex>>> {
+        if let Some(indexes) = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .indexes
+            .get(command_id)
+        {
+            return Ok(Arc::clone(indexes));
+        }
+        let indexes = Arc::new(store.output_line_indexes(command_id).await?);
+
+        // A running command's output still grows; only cache finished ones
+        let finished = store
+            .read_value(&RecordKind::Command.key(command_id))
+            .await?
+            .and_then(|v| serde_json::from_value::<Command>(v).ok())
+            .is_some_and(|c| c.ended_at.is_some() || c.duration_ms.is_some());
+        if finished {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            let CacheEntries {
+                indexes: cached,
+                order,
+            } = &mut *entries;
+            if cached
+                .insert(command_id.to_string(), Arc::clone(&indexes))
+                .is_none()
+            {
+                order.push_back(command_id.to_string());
+            }
+            while order.len() > CACHED_COMMANDS {
+                if let Some(evicted) = order.pop_front() {
+                    cached.remove(&evicted);
+                }
+            }
+        }
+        Ok(indexes)
+    }
+
+    /// Forget a command's line indexes after its stored output changed
+    pub fn invalidate(&self, command_id: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.indexes.remove(command_id).is_some() {
+            entries.order.retain(|id| id != command_id);
+        }
+    }
+
+    /// Number of output lines a command produced
+    pub async fn line_count(&self, store: &MemoryStore, command_id: &str) -> Result<usize> {
+        Ok(total_lines(&self.indexes(store, command_id).await?))
+    }
+
+    /// Up to `limit_lines` (at most [`MAX_PAGE_LINES`]) lines from `offset_lines`
+    pub async fn page(
+        &self,
+        store: &MemoryStore,
+        command_id: &str,
+        offset_lines: usize,
+        limit_lines: usize,
+    ) -> Result<OutputPage> {
+        let indexes = self.indexes(store, command_id).await?;
+        store
+            .read_output_lines(command_id, &indexes, offset_lines, limit_lines)
+            .await
+    }
+
+    /// The last `limit_lines` (at most [`MAX_PAGE_LINES`]) lines
+    pub async fn tail(
+        &self,
+        store: &MemoryStore,
+        command_id: &str,
+        limit_lines: usize,
+    ) -> Result<OutputPage> {
+        let indexes = self.indexes(store, command_id).await?;
+        let limit = limit_lines.min(MAX_PAGE_LINES);
+        let offset = total_lines(&indexes).saturating_sub(limit);
+        store
+            .read_output_lines(command_id, &indexes, offset, limit)
+            .await
+    }
+}
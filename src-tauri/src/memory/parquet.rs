@@ -0,0 +1,332 @@
+// Minimal Parquet writer: one row group, one PLAIN-encoded data page per column, no compression
+// Enough for pandas, duckdb and Spark to read analytics exports without pulling in arrow
+
+use anyhow::{ensure, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ts_rs::TS;
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Thrift compact protocol type ids
+const T_BOOL_TRUE: u8 = 1;
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+// Parquet enums
+const TYPE_BOOLEAN: i32 = 0;
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const REPETITION_OPTIONAL: i32 = 1;
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MILLIS: i32 = 9;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_DATA: i32 = 0;
+
+/// Column types analytics tables use; every column is nullable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ColumnKind {
+    Text,
+    Int,
+    Float,
+    Bool,
+    /// Milliseconds since the epoch, UTC
+    Timestamp,
+}
+
+impl ColumnKind {
+    fn physical(self) -> i32 {
+        match self {
+            ColumnKind::Text => TYPE_BYTE_ARRAY,
+            ColumnKind::Int | ColumnKind::Timestamp => TYPE_INT64,
+            ColumnKind::Float => TYPE_DOUBLE,
+            ColumnKind::Bool => TYPE_BOOLEAN,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Null,
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Timestamp(DateTime<Utc>),
+}
+
+/// Thrift compact protocol encoder, enough for Parquet metadata
+struct Thrift {
+    buf: Vec<u8>,
+    /// Last field id of each open struct
+    last: Vec<i16>,
+}
+
+impl Thrift {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            last: vec![0],
+        }
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn zigzag(&mut self, value: i64) {
+        self.varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("inside a struct");
+        let delta = id - *last;
+        *last = id;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | kind);
+        } else {
+            self.buf.push(kind);
+            self.zigzag(id as i64);
+        }
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, T_I32);
+        self.zigzag(value as i64);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, T_I64);
+        self.zigzag(value);
+    }
+
+    fn bool_true(&mut self, id: i16) {
+        self.field(id, T_BOOL_TRUE);
+    }
+
+    fn binary(&mut self, bytes: &[u8]) {
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, id: i16, value: &str) {
+        self.field(id, T_BINARY);
+        self.binary(value.as_bytes());
+    }
+
+    fn list(&mut self, id: i16, element: u8, len: usize) {
+        self.field(id, T_LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | element);
+        } else {
+            self.buf.push(0xf0 | element);
+            self.varint(len as u64);
+        }
+    }
+
+    /// A struct field; close it with [`Self::end`]
+    fn begin(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.last.push(0);
+    }
+
+    /// A struct list element; close it with [`Self::end`]
+    fn begin_element(&mut self) {
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.last.pop();
+    }
+
+    /// Close the outermost struct and return the bytes
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0);
+        self.buf
+    }
+}
+
+/// Definition levels (1 = present) as a length-prefixed RLE run sequence
+fn definition_levels(cells: &[&Cell]) -> Vec<u8> {
+    let mut runs = Thrift::new();
+    let mut i = 0;
+    while i < cells.len() {
+        let present = *cells[i] != Cell::Null;
+        let len = cells[i..]
+            .iter()
+            .take_while(|c| (***c != Cell::Null) == present)
+            .count();
+        runs.varint((len as u64) << 1);
+        runs.buf.push(present as u8);
+        i += len;
+    }
+    let mut out = (runs.buf.len() as u32).to_le_bytes().to_vec();
+    out.extend(runs.buf);
+    out
+}
+
+/// Non-null values, PLAIN-encoded
+fn plain_values(kind: ColumnKind, cells: &[&Cell]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bits = Vec::new();
+    for cell in cells {
+        match (kind, cell) {
+            (_, Cell::Null) => {}
+            (ColumnKind::Text, Cell::Text(text)) => {
+                out.extend((text.len() as u32).to_le_bytes());
+                out.extend(text.as_bytes());
+            }
+            (ColumnKind::Int, Cell::Int(value)) => out.extend(value.to_le_bytes()),
+            (ColumnKind::Float, Cell::Float(value)) => out.extend(value.to_le_bytes()),
+            (ColumnKind::Timestamp, Cell::Timestamp(at)) => {
+                out.extend(at.timestamp_millis().to_le_bytes())
+            }
+            (ColumnKind::Bool, Cell::Bool(value)) => bits.push(*value),
+            (kind, cell) => anyhow::bail!("{:?} in a {:?} column", cell, kind),
+        }
+    }
+    // Booleans are bit-packed, least significant bit first
+    for byte in bits.chunks(8) {
+        out.push(
+            byte.iter()
+                .enumerate()
+                .fold(0u8, |acc, (i, bit)| acc | (*bit as u8) << i),
+        );
+    }
+    Ok(out)
+}
+
+fn page_header(num_values: usize, size: usize) -> Vec<u8> {
+    let mut t = Thrift::new();
+    t.i32(1, PAGE_DATA);
+    t.i32(2, size as i32);
+    t.i32(3, size as i32);
+    t.begin(5);
+    t.i32(1, num_values as i32);
+    t.i32(2, ENCODING_PLAIN);
+    t.i32(3, ENCODING_RLE);
+    t.i32(4, ENCODING_RLE);
+    t.end();
+    t.finish()
+}
+
+/// A Parquet file of `rows`, each with one cell per column. `metadata` ends
+/// up in the file's key-value metadata.
+pub fn write_parquet(
+    columns: &[(&str, ColumnKind)],
+    rows: &[Vec<Cell>],
+    metadata: &[(&str, String)],
+) -> Result<Vec<u8>> {
+    ensure!(
+        rows.iter().all(|row| row.len() == columns.len()),
+        "Every row needs {} cells",
+        columns.len()
+    );
+    let mut file = MAGIC.to_vec();
+    // Offset and size of each column chunk
+    let mut chunks = Vec::new();
+    if !rows.is_empty() {
+        for (i, (_, kind)) in columns.iter().enumerate() {
+            let cells: Vec<&Cell> = rows.iter().map(|row| &row[i]).collect();
+            let mut page = definition_levels(&cells);
+            page.extend(plain_values(*kind, &cells)?);
+            let header = page_header(cells.len(), page.len());
+            chunks.push((file.len() as i64, (header.len() + page.len()) as i64));
+            file.extend(header);
+            file.extend(page);
+        }
+    }
+
+    let mut t = Thrift::new();
+    t.i32(1, 1);
+    t.list(2, T_STRUCT, columns.len() + 1);
+    t.begin_element();
+    t.string(4, "schema");
+    t.i32(5, columns.len() as i32);
+    t.end();
+    for (name, kind) in columns {
+        t.begin_element();
+        t.i32(1, kind.physical());
+        t.i32(3, REPETITION_OPTIONAL);
+        t.string(4, name);
+        match kind {
+            ColumnKind::Text => {
+                t.i32(6, CONVERTED_UTF8);
+                // LogicalType STRING
+                t.begin(10);
+                t.begin(1);
+                t.end();
+                t.end();
+            }
+            ColumnKind::Timestamp => {
+                t.i32(6, CONVERTED_TIMESTAMP_MILLIS);
+                // LogicalType TIMESTAMP(isAdjustedToUTC, MILLIS)
+                t.begin(10);
+                t.begin(8);
+                t.bool_true(1);
+                t.begin(2);
+                t.begin(1);
+                t.end();
+                t.end();
+                t.end();
+                t.end();
+            }
+            _ => {}
+        }
+        t.end();
+    }
+    t.i64(3, rows.len() as i64);
+    t.list(4, T_STRUCT, usize::from(!rows.is_empty()));
+    if !rows.is_empty() {
+        t.begin_element();
+        t.list(1, T_STRUCT, columns.len());
+        for ((name, kind), (offset, size)) in columns.iter().zip(&chunks) {
+            t.begin_element();
+            t.i64(2, *offset);
+            t.begin(3);
+            t.i32(1, kind.physical());
+            t.list(2, T_I32, 2);
+            t.zigzag(ENCODING_PLAIN as i64);
+            t.zigzag(ENCODING_RLE as i64);
+            t.list(3, T_BINARY, 1);
+            t.binary(name.as_bytes());
+            t.i32(4, CODEC_UNCOMPRESSED);
+            t.i64(5, rows.len() as i64);
+            t.i64(6, *size);
+            t.i64(7, *size);
+            t.i64(9, *offset);
+            t.end();
+            t.end();
+        }
+        t.i64(2, chunks.iter().map(|(_, size)| size).sum());
+        t.i64(3, rows.len() as i64);
+        t.end();
+    }
+    t.list(5, T_STRUCT, metadata.len());
+    for (key, value) in metadata {
+        t.begin_element();
+        t.string(1, key);
+        t.string(2, value);
+        t.end();
+    }
+    t.string(6, "runebook");
+    let footer = t.finish();
+
+    file.extend(&footer);
+    file.extend((footer.len() as u32).to_le_bytes());
+    file.extend(MAGIC);
+    Ok(file)
+}
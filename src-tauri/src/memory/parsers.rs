@@ -0,0 +1,538 @@
+// Compiler and test output parsers
+// Extracts structured failures (file, line, message, test name) from cargo, rustc, pytest, jest and go test output
+
+use crate::memory::api::MemoryStore;
+use crate::memory::backend::WriteBatch;
+use crate::memory::diagnostics::{scan_locations, SourceLocation};
+use crate::memory::events::StoreEvent;
+use crate::memory::pages::chunk_text;
+use crate::memory::schema::*;
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Failures kept per error record
+const MAX_FAILURES: usize = 200;
+
+/// One failed test or compiler error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredFailure {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+    pub message: String,
+    /// "error" or "warning"; test failures are errors
+    pub level: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+impl StructuredFailure {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            test_name: None,
+            file: None,
+            line: None,
+            column: None,
+            message: message.into(),
+            level: "error".to_string(),
+            code: None,
+        }
+    }
+
+    fn test(name: impl Into<String>) -> Self {
+        Self {
+            test_name: Some(name.into()),
+            ..Self::new("")
+        }
+    }
+
+    fn at(mut self, file: &str, line: u32, column: Option<u32>) -> Self {
+        self.file = Some(file.to_string());
+        self.line = Some(line);
+        self.column = column;
+        self
+    }
+
+    fn location(&self) -> Option<SourceLocation> {
+        Some(SourceLocation {
+            file: self.file.clone()?,
+            line: self.line?,
+            column: self.column,
+            end_line: None,
+            end_column: None,
+            message: Some(match &self.test_name {
+                Some(name) if !self.message.is_empty() => format!("{}: {}", name, self.message),
+                Some(name) => format!("{} failed", name),
+                None => self.message.clone(),
+            }),
+            severity: Some(self.level.clone()),
+            code: self.code.clone(),
+        })
+    }
+}
+
+/// Failures a parser found in a command's output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParsedOutput {
+    /// "cargo-json", "cargo", "rustc", "pytest", "jest" or "go"
+    pub parser: String,
+    pub failures: Vec<StructuredFailure>,
+}
+
+impl ParsedOutput {
+    pub fn failed_tests(&self) -> Vec<&str> {
+        self.failures
+            .iter()
+            .filter_map(|f| f.test_name.as_deref())
+            .collect()
+    }
+}
+
+type Parser = fn(&str) -> Vec<StructuredFailure>;
+
+const PARSERS: &[(&str, Parser)] = &[
+    ("cargo-json", parse_cargo_json),
+    ("cargo", parse_cargo),
+    ("rustc", parse_rustc),
+    ("pytest", parse_pytest),
+    ("jest", parse_jest),
+    ("go", parse_go_test),
+];
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// `cargo build/test --message-format=json`, plus libtest's JSON test events
+pub fn parse_cargo_json(output: &str) -> Vec<StructuredFailure> {
+    let mut failures = Vec::new();
+    for line in output.lines().filter(|l| l.starts_with('{')) {
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        if value["reason"] == "compiler-message" {
+            let message = &value["message"];
+            let level = message["level"].as_str().unwrap_or_default();
+            if level != "error" && level != "warning" {
+                continue;
+            }
+            let Some(span) = message["spans"]
+                .as_array()
+                .and_then(|spans| spans.iter().find(|s| s["is_primary"] == true))
+            else {
+                continue;
+            };
+            let mut failure =
+                StructuredFailure::new(message["message"].as_str().unwrap_or_default()).at(
+                    span["file_name"].as_str().unwrap_or_default(),
+                    span["line_start"].as_u64().unwrap_or(1) as u32,
+                    span["column_start"].as_u64().map(|c| c as u32),
+                );
+            failure.level = level.to_string();
+            failure.code = message["code"]["code"].as_str().map(String::from);
+            failures.push(failure);
+        } else if value["type"] == "test" && value["event"] == "failed" {
+            let name = value["name"].as_str().unwrap_or_default();
+            let stdout = value["stdout"].as_str().unwrap_or_default();
+            failures.push(
+                parse_panics(stdout)
+                    .into_iter()
+                    .next()
+                    .map(|f| StructuredFailure {
+                        test_name: Some(name.to_string()),
+                        ..f
+                    })
+                    .unwrap_or_else(|| StructuredFailure::test(name)),
+            );
+        }
+    }
+    failures
+}
+
+/// libtest panics: `thread 'name' panicked at file:line:col:` followed by
+/// the message, or the older `panicked at 'message', file:line:col`
+fn parse_panics(output: &str) -> Vec<StructuredFailure> {
+    static PANIC: OnceLock<Regex> = OnceLock::new();
+    let panic = regex(
+        &PANIC,
+        r"^thread '(?P<name>[^']+)' panicked at (?:'(?P<msg>.*)', )?(?P<file>[^\s:]+):(?P<line>\d+):(?P<col>\d+):?$",
+    );
+    let lines: Vec<&str> = output.lines().collect();
+    let mut failures = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let Some(caps) = panic.captures(line.trim()) else {
+            continue;
+        };
+        let message = match caps.name("msg") {
+            Some(msg) => msg.as_str().to_string(),
+            None => lines
+                .get(i + 1)
+                .map(|l| l.trim().to_string())
+                .unwrap_or_default(),
+        };
+        let mut failure = StructuredFailure::test(&caps["name"]).at(
+            &caps["file"],
+            caps["line"].parse().unwrap_or(1),
+            caps["col"].parse().ok(),
+        );
+        failure.message = message;
+        failures.push(failure);
+    }
+    failures
+}
+
+/// Human `cargo build/test` output: compiler errors and failed tests
+pub fn parse_cargo(output: &str) -> Vec<StructuredFailure> {
+    static FAILED: OnceLock<Regex> = OnceLock::new();
+    let failed = regex(&FAILED, r"^test (?P<name>\S+) \.\.\. FAILED$");
+
+    let mut failures = parse_rustc(output);
+    let panics = parse_panics(output);
+    for line in output.lines() {
+        let Some(caps) = failed.captures(line.trim()) else {
+            continue;
+        };
+        let name = &caps["name"];
+        failures.push(
+            panics
+                .iter()
+                .find(|p| p.test_name.as_deref() == Some(name))
+                .cloned()
+                .unwrap_or_else(|| StructuredFailure::test(name)),
+        );
+    }
+    failures
+}
+
+/// rustc diagnostics: `error[E0308]: message` and the `--> file:line:col` under it
+pub fn parse_rustc(output: &str) -> Vec<StructuredFailure> {
+    scan_locations(output)
+        .into_iter()
+        .filter_map(|location| {
+            let level = location.severity?;
+            let mut failure = StructuredFailure::new(location.message.unwrap_or_default()).at(
+                &location.file,
+                location.line,
+                location.column,
+            );
+            failure.level = level;
+            failure.code = location.code;
+            Some(failure)
+        })
+        .collect()
+}
+
+/// pytest: `FAILED path::test - message` summary lines, with line numbers
+/// from the `path:line: Error` lines of each test's section
+pub fn parse_pytest(output: &str) -> Vec<StructuredFailure> {
+    static SUMMARY: OnceLock<Regex> = OnceLock::new();
+    static SECTION: OnceLock<Regex> = OnceLock::new();
+    static LOCATION: OnceLock<Regex> = OnceLock::new();
+    let summary = regex(
+        &SUMMARY,
+        r"^(?:FAILED|ERROR) (?P<id>[^\s]+?\.py(?:::\S+)?)(?: - (?P<msg>.*))?$",
+    );
+    let section = regex(&SECTION, r"^_{3,} (?P<name>.+?) _{3,}$");
+    let location = regex(
+        &LOCATION,
+        r"^(?P<file>[^\s:]+\.py):(?P<line>\d+): (?P<msg>.*)$",
+    );
+
+    // Section name ("TestClass.test_name") -> (line, error) of its last location
+    let mut lines_by_section: Vec<(String, u32, String)> = Vec::new();
+    let mut current: Option<String> = None;
+    for line in output.lines() {
+        if let Some(caps) = section.captures(line) {
+            current = Some(caps["name"].to_string());
+        } else if let (Some(caps), Some(name)) = (location.captures(line), &current) {
+            lines_by_section.retain(|(n, _, _)| n != name);
+            lines_by_section.push((
+                name.clone(),
+                caps["line"].parse().unwrap_or(1),
+                caps["msg"].to_string(),
+            ));
+        }
+    }
+
+    let mut failures = Vec::new();
+    for line in output.lines() {
+        let Some(caps) = summary.captures(line) else {
+            continue;
+        };
+        let id = &caps["id"];
+        let (file, test) = id.split_once("::").unwrap_or((id, ""));
+        let section_name = test.replace("::", ".");
+        let found = lines_by_section.iter().find(|(n, _, _)| *n == section_name);
+        let mut failure = StructuredFailure::test(id);
+        failure.file = Some(file.to_string());
+        failure.line = found.map(|(_, line, _)| *line);
+        failure.message = caps
+            .name("msg")
+            .map(|m| m.as_str().to_string())
+            .or_else(|| found.map(|(_, _, msg)| msg.clone()))
+            .unwrap_or_default();
+        failures.push(failure);
+    }
+    failures
+}
+
+/// jest: `● Suite › test` blocks under `FAIL path`, located by their first
+/// stack frame outside node_modules
+pub fn parse_jest(output: &str) -> Vec<StructuredFailure> {
+    static FAIL: OnceLock<Regex> = OnceLock::new();
+    static FRAME: OnceLock<Regex> = OnceLock::new();
+    let fail = regex(&FAIL, r"^FAIL (?P<file>\S+)");
+    let frame = regex(
+        &FRAME,
+        r"at (?:.*\()?(?P<file>[^\s()]+):(?P<line>\d+):(?P<col>\d+)\)?$",
+    );
+
+    let mut failures: Vec<StructuredFailure> = Vec::new();
+    let mut file: Option<String> = None;
+    let mut open: Option<usize> = None;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if let Some(caps) = fail.captures(trimmed) {
+            file = Some(caps["file"].to_string());
+            open = None;
+        } else if let Some(name) = trimmed.strip_prefix("● ") {
+            // The summary at the end repeats failures already seen
+            if failures
+                .iter()
+                .any(|f| f.test_name.as_deref() == Some(name))
+            {
+                open = None;
+                continue;
+            }
+            let mut failure = StructuredFailure::test(name);
+            failure.file = file.clone();
+            failures.push(failure);
+            open = Some(failures.len() - 1);
+        } else if let Some(index) = open {
+            let failure = &mut failures[index];
+            if failure.message.is_empty() && !trimmed.is_empty() {
+                failure.message = trimmed.to_string();
+            } else if failure.line.is_none() {
+                if let Some(caps) = frame.captures(trimmed) {
+                    if !caps["file"].contains("node_modules") {
+                        failure.file = Some(caps["file"].to_string());
+                        failure.line = caps["line"].parse().ok();
+                        failure.column = caps["col"].parse().ok();
+                    }
+                }
+            }
+        }
+    }
+    failures
+}
+
+/// go test: `--- FAIL: TestName` with the `file.go:line: message` lines
+/// under it, and `file.go:line:col: message` build errors
+pub fn parse_go_test(output: &str) -> Vec<StructuredFailure> {
+    static TEST: OnceLock<Regex> = OnceLock::new();
+    static LOG: OnceLock<Regex> = OnceLock::new();
+    static BUILD: OnceLock<Regex> = OnceLock::new();
+    let test = regex(&TEST, r"^\s*--- FAIL: (?P<name>\S+)");
+    let log = regex(
+        &LOG,
+        r"^\s+(?P<file>[\w./-]+\.go):(?P<line>\d+): (?P<msg>.*)$",
+    );
+    let build = regex(
+        &BUILD,
+        r"^(?P<file>[\w./-]+\.go):(?P<line>\d+):(?P<col>\d+): (?P<msg>.*)$",
+    );
+
+    let mut failures: Vec<StructuredFailure> = Vec::new();
+    let mut open: Option<usize> = None;
+    for line in output.lines() {
+        if let Some(caps) = test.captures(line) {
+            failures.push(StructuredFailure::test(&caps["name"]));
+            open = Some(failures.len() - 1);
+        } else if let (Some(caps), Some(index)) = (log.captures(line), open) {
+            let failure = &mut failures[index];
+            if failure.line.is_none() {
+                failure.file = Some(caps["file"].to_string());
+                failure.line = caps["line"].parse().ok();
+                failure.message = caps["msg"].to_string();
+            }
+        } else if let Some(caps) = build.captures(line) {
+            failures.push(StructuredFailure::new(&caps["msg"]).at(
+                &caps["file"],
+                caps["line"].parse().unwrap_or(1),
+                caps["col"].parse().ok(),
+            ));
+            open = None;
+        }
+    }
+    failures
+}
+
+/// Parser for a command line, if it names a tool we know
+pub(crate) fn parser_for(command: &str, args: &[String]) -> Option<&'static str> {
+    let program = command.rsplit('/').next().unwrap_or(command);
+    let has = |word: &str| args.iter().any(|a| a == word);
+    match program {
+        "cargo" if args.iter().any(|a| a.starts_with("--message-format=json")) => {
+            Some("cargo-json")
+        }
+        "cargo" => Some("cargo"),
+        "rustc" => Some("rustc"),
+        "pytest" | "py.test" => Some("pytest"),
+        "python" | "python3" if has("pytest") => Some("pytest"),
+        "jest" => Some("jest"),
+        "npx" | "yarn" | "pnpm" if has("jest") => Some("jest"),
+        "go" if args
+            .first()
+            .is_some_and(|a| a == "test" || a == "build" || a == "vet") =>
+        {
+            Some("go")
+        }
+        _ => None,
+    }
+}
+
+/// Structured failures in a command's output. Known tools get their own
+/// parser; wrappers like `make` or `npm test` get the first parser that
+/// finds anything.
+pub fn parse_output(command: &str, args: &[String], output: &str) -> Option<ParsedOutput> {
+    let candidates: Vec<&(&str, Parser)> = match parser_for(command, args) {
+        Some(name) => PARSERS.iter().filter(|(n, _)| *n == name).collect(),
+        None => PARSERS.iter().collect(),
+    };
+    candidates.into_iter().find_map(|(name, parse)| {
+        let mut failures = parse(output);
+        failures.truncate(MAX_FAILURES);
+        (!failures.is_empty()).then(|| ParsedOutput {
+            parser: name.to_string(),
+            failures,
+        })
+    })
+}
+
+/// Error record for parsed failures; `context` holds the parser, the
+/// failures and their locations for diagnostics
+pub fn failure_error(command: &Command, parsed: &ParsedOutput, output: &str) -> Error {
+    let tests = parsed.failed_tests();
+    let errors = parsed
+        .failures
+        .iter()
+        .filter(|f| f.level == "error")
+        .count();
+    let (error_type, message) = if !tests.is_empty() {
+        let shown: Vec<&str> = tests.iter().take(5).copied().collect();
+        let more = if tests.len() > shown.len() {
+            format!(" and {} more", tests.len() - shown.len())
+        } else {
+            String::new()
+        };
+        (
+            "test_failure",
+            format!(
+                "{} test{} failed: {}{}",
+                tests.len(),
+                if tests.len() == 1 { "" } else { "s" },
+                shown.join(", "),
+                more
+            ),
+        )
+    } else {
+        let first = parsed
+            .failures
+            .iter()
+            .find(|f| f.level == "error")
+            .unwrap_or(&parsed.failures[0]);
+        (
+            "compile_error",
+            format!("{} error(s): {}", errors, first.message),
+        )
+    };
+
+    let mut error = Error::new(
+        command.id.clone(),
+        command.session_id.clone(),
+        error_type.to_string(),
+        if errors > 0 { "high" } else { "medium" }.to_string(),
+        message,
+    );
+    error.exit_code = command.exit_code;
+    error.stderr_snippet = Some(output.chars().take(500).collect());
+    let locations: Vec<SourceLocation> = parsed
+        .failures
+        .iter()
+        .filter_map(StructuredFailure::location)
+        .collect();
+    error.context = serde_json::json!({
+        "parser": parsed.parser,
+        "failures": parsed.failures,
+        "locations": locations,
+    });
+    error
+}
+
+impl MemoryStore {
+    /// Parse the stored output of a failed command and store an error with
+    /// the structured failures. Returns it, or `None` when the command
+    /// succeeded or no parser found anything.
+    pub async fn classify_failure(&self, command: &Command) -> Result<Option<Error>> {
+        let error = self.failure_of(command, &[]).await?;
+        if let Some(error) = &error {
+            self.store_error(error.clone()).await?;
+        }
+        Ok(error)
+    }
+
+    /// Error for a failed command, parsed from its stored output followed by
+    /// `pending` chunks that are not stored yet
+    async fn failure_of(&self, command: &Command, pending: &[Output]) -> Result<Option<Error>> {
+        if command.success {
+            return Ok(None);
+        }
+        let mut chunks = self.command_outputs(&command.id).await?;
+        chunks.extend(pending.iter().cloned());
+        chunks.sort_by_key(|o| (o.chunk_index, o.timestamp));
+        let mut output = String::new();
+        for chunk in &chunks {
+            output.push_str(&chunk_text(chunk)?);
+        }
+        let Some(parsed) = parse_output(&command.command, &command.args, &output) else {
+            return Ok(None);
+        };
+        Ok(Some(failure_error(command, &parsed, &output)))
+    }
+
+    /// Store a finished command, output chunks of it not stored yet, and the
+    /// error classified from its output as one batch, so a crash cannot leave
+    /// a finished command without its output or error. Returns the error.
+    pub async fn store_finished_command(
+        &self,
+        mut command: Command,
+        mut outputs: Vec<Output>,
+    ) -> Result<Option<Error>> {
+        let mut batch = WriteBatch::new();
+        let newly_finished = self.stage_command(&mut batch, &mut command).await?;
+        for output in &mut outputs {
+            self.stage_output(&mut batch, output, false).await?;
+        }
+        let error = self.failure_of(&command, &outputs).await?;
+        let staged = match &error {
+            Some(error) => Some(self.stage_error(&mut batch, error.clone()).await?),
+            None => None,
+        };
+        self.commit(batch).await?;
+        if newly_finished {
+            self.record_command_stats(&command).await?;
+            self.publish(StoreEvent::CommandFinished {
+                command: Box::new(command),
+                error: staged,
+            });
+        }
+        Ok(error)
+    }
+}
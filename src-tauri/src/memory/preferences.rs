@@ -0,0 +1,308 @@
+// Learned user preferences
+// Durable choices (muted suggestion reasons, fix style, package manager, verbosity) that analyzers and the ranker consult, open to inspection and reset
+
+use crate::memory::api::MemoryStore;
+use crate::memory::explain::reason_key;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::Suggestion;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Package managers a preference can name
+pub const PACKAGE_MANAGERS: [&str; 4] = ["npm", "pnpm", "yarn", "bun"];
+
+/// Which fixes the user takes up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum FixStyle {
+    /// Suggestions with a command to run
+    Command,
+    /// Suggestions that explain, without a command
+    Explanation,
+}
+
+/// How much exit summaries say
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum Verbosity {
+    /// The exit code, the error class and the suggested command
+    Terse,
+    #[default]
+    Normal,
+    /// The whole error message
+    Verbose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PreferenceSource {
+    /// Set by the user; learning does not change it
+    Explicit,
+    /// Inferred from accepted suggestions
+    Learned,
+}
+
+/// A preference with where it came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Learned<T> {
+    pub value: T,
+    pub source: PreferenceSource,
+    /// Choices that agreed with `value`, less those that did not; a learned
+    /// value that drops to zero is replaced by the next choice
+    pub evidence: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<T: PartialEq> Learned<T> {
+    fn explicit(value: T) -> Self {
+        Self {
+            value,
+            source: PreferenceSource::Explicit,
+            evidence: 0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Count one choice of `value` towards `learned`
+    fn learn(learned: &mut Option<Self>, value: T) {
+        let now = Utc::now();
+        match learned {
+            Some(current) if current.source == PreferenceSource::Explicit => {}
+            Some(current) if current.value == value => {
+                current.evidence += 1;
+                current.updated_at = now;
+            }
+            Some(current) if current.evidence > 1 => {
+                current.evidence -= 1;
+                current.updated_at = now;
+            }
+            _ => {
+                *learned = Some(Self {
+                    value,
+                    source: PreferenceSource::Learned,
+                    evidence: 1,
+                    updated_at: now,
+                })
+            }
+        }
+    }
+}
+
+/// A suggestion reason the user never wants to see again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MutedReason {
+    /// `<analyzer>:<type>`, as `explain_suggestion` reports it
+    pub reason_key: String,
+    /// Title of the suggestion it was muted from
+    pub example: String,
+    pub muted_at: DateTime<Utc>,
+}
+
+/// Everything learned about one user
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct Preferences {
+    pub muted: Vec<MutedReason>,
+    pub fix_style: Option<Learned<FixStyle>>,
+    pub package_manager: Option<Learned<String>>,
+    pub verbosity: Option<Learned<Verbosity>>,
+}
+
+/// A preference set explicitly with `preferences_set`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "name", content = "value", rename_all = "snake_case")]
+#[ts(export)]
+pub enum PreferenceChoice {
+    FixStyle(FixStyle),
+    PackageManager(String),
+    Verbosity(Verbosity),
+}
+
+/// Preferences `preferences_reset` can clear, or `All`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PreferenceName {
+    Muted,
+    FixStyle,
+    PackageManager,
+    Verbosity,
+    All,
+}
+
+/// The package manager a suggested command runs, if any
+pub fn package_manager_of(suggestion: &Suggestion) -> Option<&'static str> {
+    let program = suggestion.command.as_deref()?.split_whitespace().next()?;
+    PACKAGE_MANAGERS.into_iter().find(|pm| *pm == program)
+}
+
+impl Preferences {
+    pub fn is_muted(&self, suggestion: &Suggestion) -> bool {
+        let key = reason_key(suggestion);
+        self.muted.iter().any(|m| m.reason_key == key)
+    }
+
+    /// Whether `suggestion` fits the preferred fix style and package manager:
+    /// 1 when one applies and none conflicts, 0 otherwise
+    pub fn fit(&self, suggestion: &Suggestion) -> f64 {
+        let style = self.fix_style.as_ref().map(|s| {
+            let offered = match suggestion.command {
+                Some(_) => FixStyle::Command,
+                None => FixStyle::Explanation,
+            };
+            s.value == offered
+        });
+        let manager = match (&self.package_manager, package_manager_of(suggestion)) {
+            (Some(preferred), Some(used)) => Some(preferred.value == used),
+            _ => None,
+        };
+        let matches: Vec<bool> = [style, manager].into_iter().flatten().collect();
+        if !matches.is_empty() && matches.iter().all(|m| *m) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity.as_ref().map(|v| v.value).unwrap_or_default()
+    }
+
+    /// Learn from a suggestion the user accepted
+    fn learn_accepted(&mut self, suggestion: &Suggestion) {
+        let style = match suggestion.command {
+            Some(_) => FixStyle::Command,
+            None => FixStyle::Explanation,
+        };
+        Learned::learn(&mut self.fix_style, style);
+        if let Some(manager) = package_manager_of(suggestion) {
+            Learned::learn(&mut self.package_manager, manager.to_string());
+        }
+    }
+}
+
+/// Whose preferences this process reads: the OS user
+fn current_user() -> String {
+    ["USER", "USERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+impl MemoryStore {
+    /// The current user's preferences; empty until something is learned
+    pub async fn preferences(&self) -> Result<Preferences> {
+        Ok(self
+            .read_value(&RecordKind::Preference.key(&current_user()))
+            .await?
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default())
+    }
+
+    async fn update_preferences(
+        &self,
+        change: impl FnOnce(&mut Preferences),
+    ) -> Result<Preferences> {
+        let mut preferences = self.preferences().await?;
+        change(&mut preferences);
+        self.write_value(
+            &RecordKind::Preference.key(&current_user()),
+            serde_json::to_value(&preferences)?,
+        )
+        .await?;
+        Ok(preferences)
+    }
+
+    /// Never show suggestions for the same reason as `suggestion_id` again,
+    /// and dismiss it. Returns `None` when there is no such suggestion.
+    pub async fn mute_suggestion_reason(&self, suggestion_id: &str) -> Result<Option<MutedReason>> {
+        let Some(suggestion) = self
+            .read_value(&RecordKind::Suggestion.key(suggestion_id))
+            .await?
+            .and_then(|v| serde_json::from_value::<Suggestion>(v).ok())
+        else {
+            return Ok(None);
+        };
+        let muted = MutedReason {
+            reason_key: reason_key(&suggestion),
+            example: suggestion.title.clone(),
+            muted_at: Utc::now(),
+        };
+        self.update_preferences(|preferences| {
+            if !preferences.is_muted(&suggestion) {
+                preferences.muted.push(muted.clone());
+            }
+        })
+        .await?;
+        self.suggestion_feedback(suggestion_id, false).await?;
+        Ok(Some(muted))
+    }
+
+    /// Set a preference, which learning then leaves alone
+    pub async fn set_preference(&self, choice: PreferenceChoice) -> Result<Preferences> {
+        if let PreferenceChoice::PackageManager(manager) = &choice {
+            if !PACKAGE_MANAGERS.contains(&manager.as_str()) {
+                anyhow::bail!(
+                    "Unknown package manager {:?}; expected one of {}",
+                    manager,
+                    PACKAGE_MANAGERS.join(", ")
+                );
+            }
+        }
+        self.update_preferences(|preferences| match choice {
+            PreferenceChoice::FixStyle(style) => {
+                preferences.fix_style = Some(Learned::explicit(style))
+            }
+            PreferenceChoice::PackageManager(manager) => {
+                preferences.package_manager = Some(Learned::explicit(manager))
+            }
+            PreferenceChoice::Verbosity(verbosity) => {
+                preferences.verbosity = Some(Learned::explicit(verbosity))
+            }
+        })
+        .await
+    }
+
+    /// Forget a preference, learned or set, or all of them. With `Muted`
+    /// and a `reason_key`, unmutes just that reason.
+    pub async fn reset_preferences(
+        &self,
+        name: PreferenceName,
+        reason_key: Option<&str>,
+    ) -> Result<Preferences> {
+        self.update_preferences(|preferences| match name {
+            PreferenceName::Muted => match reason_key {
+                Some(key) => preferences.muted.retain(|m| m.reason_key != key),
+                None => preferences.muted.clear(),
+            },
+            PreferenceName::FixStyle => preferences.fix_style = None,
+            PreferenceName::PackageManager => preferences.package_manager = None,
+            PreferenceName::Verbosity => preferences.verbosity = None,
+            PreferenceName::All => *preferences = Preferences::default(),
+        })
+        .await
+    }
+
+    /// Learn from feedback on `suggestion`: accepting one counts towards its
+    /// fix style and package manager
+    pub(crate) async fn learn_from_feedback(
+        &self,
+        suggestion: &Suggestion,
+        accepted: bool,
+    ) -> Result<()> {
+        if !accepted {
+            return Ok(());
+        }
+        self.update_preferences(|preferences| preferences.learn_accepted(suggestion))
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+// Quota enforcement for the memory store
+// Admission control on the bulk write paths (outputs, artifacts) with graceful degradation
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::{broadcast, Mutex};
+use ts_rs::TS;
+
+/// What to do when a write would exceed a quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum QuotaPolicy {
+    /// Keep commands, errors, and insights but stop storing output/artifact payloads
+    DropOutputs,
+    /// Delete the oldest outputs to make room, then drop if still over
+    EmergencyGc,
+}
+
+/// `[quota]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct QuotaConfig {
+    pub max_total_bytes: Option<u64>,
+    pub max_session_bytes: Option<u64>,
+    pub policy: QuotaPolicy,
+    /// Emergency GC frees down to this fraction of the exceeded limit
+    pub gc_target_ratio: f64,
+    /// How often cached usage is recomputed from the store
+    pub resync_interval_secs: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: None,
+            max_session_bytes: None,
+            policy: QuotaPolicy::DropOutputs,
+            gc_target_ratio: 0.9,
+            resync_interval_secs: 600,
+        }
+    }
+}
+
+impl QuotaConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_total_bytes.is_some() || self.max_session_bytes.is_some()
+    }
+}
+
+/// Emitted whenever a quota changes what the write path does
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaEvent {
+    pub scope: String, // "total" or "session"
+    pub session_id: Option<String>,
+    pub limit_bytes: u64,
+    pub used_bytes: u64,
+    pub attempted_bytes: u64,
+    pub policy: QuotaPolicy,
+    pub action: String, // "dropped", "gc"
+    pub freed_bytes: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct UsageCache {
+    synced_at: Option<DateTime<Utc>>,
+    total: u64,
+    sessions: HashMap<String, u64>,
+}
+
+/// Shared quota state; attach to stores with [`MemoryStore::with_quota`]
+pub struct QuotaGuard {
+    config: QuotaConfig,
+    usage: Mutex<UsageCache>,
+    events: broadcast::Sender<QuotaEvent>,
+}
+
+impl QuotaGuard {
+    pub fn new(config: QuotaConfig) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            config,
+            usage: Mutex::new(UsageCache::default()),
+            events,
+        }
+    }
+
+    pub fn config(&self) -> &QuotaConfig {
+        &self.config
+    }
+
+    /// Receive quota events (for forwarding to the UI)
+    pub fn subscribe(&self) -> broadcast::Receiver<QuotaEvent> {
+        self.events.subscribe()
+    }
+
+    /// Decide whether a payload of `bytes` for `session_id` may be written.
+    ///
+    /// Returns `false` when the payload should be skipped; a [`QuotaEvent`]
+    /// describing the decision has been broadcast in that case.
+    pub async fn admit(
+        &self,
+        store: &MemoryStore,
+        session_id: Option<&str>,
+        bytes: u64,
+    ) -> Result<bool> {
+        if !self.config.is_enabled() {
+            return Ok(true);
+        }
+
+        let mut usage = self.usage.lock().await;
+        let stale = usage.synced_at.is_none_or(|at| {
+            Utc::now() - at > ChronoDuration::seconds(self.config.resync_interval_secs as i64)
+        });
+        if stale {
+            let report = store.usage_report(0).await?;
+            usage.total = report.total_bytes;
+            usage.sessions = report
+                .by_session
+                .into_iter()
+                .map(|s| (s.session_id, s.bytes))
+                .collect();
+            usage.synced_at = Some(Utc::now());
+        }
+
+        let session_used = session_id
+            .and_then(|id| usage.sessions.get(id).copied())
+            .unwrap_or(0);
+        let exceeded = match (self.config.max_total_bytes, self.config.max_session_bytes) {
+            (Some(limit), _) if usage.total + bytes > limit => Some(("total", limit, usage.total)),
+            (_, Some(limit)) if session_id.is_some() && session_used + bytes > limit => {
+                Some(("session", limit, session_used))
+            }
+            _ => None,
+        };
+
+        let Some((scope, limit, used)) = exceeded else {
+            usage.total += bytes;
+            if let Some(id) = session_id {
+                *usage.sessions.entry(id.to_string()).or_default() += bytes;
+            }
+            return Ok(true);
+        };
+
+        let mut freed_bytes = 0;
+        let mut admitted = false;
+        if self.config.policy == QuotaPolicy::EmergencyGc {
+            let target = (limit as f64 * self.config.gc_target_ratio) as u64;
+            let needed = (used + bytes).saturating_sub(target);
+            freed_bytes = store
+                .gc_oldest_outputs(needed, (scope == "session").then_some(session_id).flatten())
+                .await?;
+            // Force a resync on the next write so freed space is accounted exactly
+            usage.synced_at = None;
+            admitted = used.saturating_sub(freed_bytes) + bytes <= limit;
+        }
+
+        let _ = self.events.send(QuotaEvent {
+            scope: scope.to_string(),
+            session_id: session_id.map(|s| s.to_string()),
+            limit_bytes: limit,
+            used_bytes: used,
+            attempted_bytes: bytes,
+            policy: self.config.policy,
+            action: if admitted { "gc" } else { "dropped" }.to_string(),
+            freed_bytes,
+            timestamp: Utc::now(),
+        });
+        if !admitted {
+            log::warn!(
+                "[quota] {} quota exceeded ({} / {} bytes); dropping {} byte payload",
+                scope,
+                used,
+                limit,
+                bytes
+            );
+        }
+        Ok(admitted)
+    }
+}
+
+impl MemoryStore {
+    /// Delete the oldest stored outputs until at least `bytes` have been freed.
+    ///
+    /// Command, error, and insight records are kept; an output's line index
+    /// goes with it. Returns the bytes freed.
+    pub async fn gc_oldest_outputs(&self, bytes: u64, session_id: Option<&str>) -> Result<u64> {
+        let command_sessions: HashMap<String, String> = if session_id.is_some() {
+            let mut map = HashMap::new();
+            for key in self.client.list(RecordKind::Command.prefix()).await? {
+                if let Some(value) = self.read_value(&key).await? {
+                    if let Ok(command) = serde_json::from_value::<Command>(value) {
+                        map.insert(command.id, command.session_id);
+                    }
+                }
+            }
+            map
+        } else {
+            HashMap::new()
+        };
+
+        let mut candidates = Vec::new();
+        for key in self.client.list(RecordKind::Output.prefix()).await? {
+            let Some(raw) = self.client.get(&key).await? else {
+                continue;
+            };
+            let size = serde_json::to_vec(&raw)?.len() as u64;
+            let value = self.decrypt_value(raw).await?;
+            if let Ok(output) = serde_json::from_value::<Output>(value) {
+                if let Some(session) = session_id {
+                    if command_sessions.get(&output.command_id).map(String::as_str) != Some(session)
+                    {
+                        continue;
+                    }
+                }
+                candidates.push((output.timestamp, key, output.id, size));
+            }
+        }
+        candidates.sort_by_key(|(timestamp, _, _, _)| *timestamp);
+
+        let mut freed = 0;
+        for (_, key, output_id, size) in candidates {
+            if freed >= bytes {
+                break;
+            }
+            self.client.delete(&key).await?;
+            self.client
+                .delete(&RecordKind::OutputIndex.key(&output_id))
+                .await?;
+            freed += size;
+        }
+        Ok(freed)
+    }
+}
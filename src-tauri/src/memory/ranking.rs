@@ -0,0 +1,393 @@
+// Suggestion ranking and its instrumentation
+// Scores suggestions from weighted features, records what was surfaced and accepted, and replays that history against other weights
+
+use crate::memory::api::MemoryStore;
+use crate::memory::intent::Activity;
+use crate::memory::keys::RecordKind;
+use crate::memory::preferences::Preferences;
+use crate::memory::schema::Suggestion;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ts_rs::TS;
+
+/// Candidates kept per impression, best first
+const MAX_CANDIDATES: usize = 50;
+
+/// `[ranking]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct RankingConfig {
+    /// Record surfaced suggestions and their outcomes for `rank_eval`
+    pub record: bool,
+    /// Feature weights; a feature without a weight does not count
+    pub weights: BTreeMap<String, f64>,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            record: true,
+            weights: [
+                ("base", 1.0),
+                ("priority", 0.5),
+                ("warning", 0.3),
+                ("actionable", 0.2),
+                ("recency", 0.3),
+                ("debugging_loop", 0.4),
+                ("preferred", 0.3),
+            ]
+            .into_iter()
+            .map(|(name, weight)| (name.to_string(), weight))
+            .collect(),
+        }
+    }
+}
+
+/// Ranking features of a suggestion. `base` is the rank its producer gave
+/// it; the others are between 0 and 1.
+pub fn features(
+    suggestion: &Suggestion,
+    now: DateTime<Utc>,
+    activity: Option<&Activity>,
+    preferences: Option<&Preferences>,
+) -> BTreeMap<String, f64> {
+    let flag = |set: bool| if set { 1.0 } else { 0.0 };
+    let priority = match suggestion.priority.as_str() {
+        "high" => 1.0,
+        "medium" => 0.5,
+        _ => 0.0,
+    };
+    let age_hours = (now - suggestion.created_at).num_minutes().max(0) as f64 / 60.0;
+    // Fixes matter more while the user keeps re-running a failing build
+    let fix = matches!(suggestion.suggestion_type.as_str(), "command" | "warning");
+    [
+        ("base", suggestion.rank),
+        ("priority", priority),
+        ("warning", flag(suggestion.suggestion_type == "warning")),
+        ("actionable", flag(suggestion.command.is_some())),
+        ("recency", (-age_hours / 24.0).exp()),
+        (
+            "debugging_loop",
+            flag(fix && activity.is_some_and(|a| a.debugging_loop)),
+        ),
+        // The fix style and package manager the user keeps choosing
+        ("preferred", preferences.map_or(0.0, |p| p.fit(suggestion))),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect()
+}
+
+/// Weighted score of `features`, and each feature's part of it
+pub fn score(
+    features: &BTreeMap<String, f64>,
+    config: &RankingConfig,
+) -> (f64, BTreeMap<String, f64>) {
+    let contributions: BTreeMap<String, f64> = features
+        .iter()
+        .filter_map(|(name, value)| {
+            config
+                .weights
+                .get(name)
+                .map(|weight| (name.clone(), weight * value))
+        })
+        .collect();
+    (contributions.values().sum(), contributions)
+}
+
+/// A suggestion with the score it was ranked by
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedSuggestion {
+    pub suggestion: Suggestion,
+    pub score: f64,
+    pub features: BTreeMap<String, f64>,
+    pub contributions: BTreeMap<String, f64>,
+}
+
+/// Order suggestions by score, best first; ties go to the newer one
+pub fn rank(
+    suggestions: Vec<Suggestion>,
+    config: &RankingConfig,
+    activity: Option<&Activity>,
+    preferences: Option<&Preferences>,
+) -> Vec<RankedSuggestion> {
+    let now = Utc::now();
+    let mut ranked: Vec<RankedSuggestion> = suggestions
+        .into_iter()
+        .map(|suggestion| {
+            let features = features(&suggestion, now, activity, preferences);
+            let (score, contributions) = score(&features, config);
+            RankedSuggestion {
+                suggestion,
+                score,
+                features,
+                contributions,
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.suggestion.created_at.cmp(&a.suggestion.created_at))
+    });
+    ranked
+}
+
+/// One ranked candidate of an impression
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpressionCandidate {
+    pub suggestion_id: String,
+    pub score: f64,
+    pub features: BTreeMap<String, f64>,
+    pub contributions: BTreeMap<String, f64>,
+}
+
+/// Suggestions ranked together for one surface, best first, and what the
+/// user did with the ones shown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestionImpression {
+    pub id: String,
+    /// Where they were shown, e.g. "window" or "ipc"
+    pub surface: String,
+    pub surfaced_at: DateTime<Utc>,
+    /// The first `shown` candidates were surfaced; the rest ranked lower
+    pub shown: usize,
+    pub candidates: Vec<ImpressionCandidate>,
+    pub accepted: Vec<String>,
+    pub dismissed: Vec<String>,
+}
+
+impl SuggestionImpression {
+    pub(crate) fn showed(&self, suggestion_id: &str) -> bool {
+        self.candidates
+            .iter()
+            .take(self.shown)
+            .any(|c| c.suggestion_id == suggestion_id)
+    }
+}
+
+/// How one ranking configuration does on recorded history
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RankEvalResult {
+    pub name: String,
+    /// Share of accepted suggestions this configuration would also have shown
+    pub acceptance_rate: f64,
+    /// `acceptance_rate` minus the current configuration's
+    pub acceptance_delta: f64,
+    /// Share of dismissed suggestions it would still have shown
+    pub dismissed_rate: f64,
+    /// Mean of 1 / position of each accepted suggestion in its re-ranking
+    pub mean_reciprocal_rank: f64,
+}
+
+/// `rank_eval` result; the current configuration comes first
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RankEvalReport {
+    /// Impressions with an accepted or dismissed suggestion
+    pub impressions: usize,
+    pub accepted: usize,
+    pub dismissed: usize,
+    pub results: Vec<RankEvalResult>,
+}
+
+fn replay(
+    name: &str,
+    impressions: &[&SuggestionImpression],
+    config: &RankingConfig,
+) -> RankEvalResult {
+    let (mut accepted, mut accepted_shown, mut reciprocal_ranks) = (0, 0, 0.0);
+    let (mut dismissed, mut dismissed_shown) = (0, 0);
+    for impression in impressions {
+        let mut order: Vec<(f64, &str)> = impression
+            .candidates
+            .iter()
+            .map(|c| (score(&c.features, config).0, c.suggestion_id.as_str()))
+            .collect();
+        // Stable, so equal scores keep their recorded order
+        order.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let position = |id: &String| order.iter().position(|(_, c)| c == id);
+
+        for id in &impression.accepted {
+            accepted += 1;
+            if let Some(position) = position(id) {
+                accepted_shown += usize::from(position < impression.shown);
+                reciprocal_ranks += 1.0 / (position + 1) as f64;
+            }
+        }
+        for id in &impression.dismissed {
+            dismissed += 1;
+            dismissed_shown += usize::from(position(id).is_some_and(|p| p < impression.shown));
+        }
+    }
+    let rate = |part: usize, whole: usize| {
+        if whole == 0 {
+            0.0
+        } else {
+            part as f64 / whole as f64
+        }
+    };
+    RankEvalResult {
+        name: name.to_string(),
+        acceptance_rate: rate(accepted_shown, accepted),
+        acceptance_delta: 0.0,
+        dismissed_rate: rate(dismissed_shown, dismissed),
+        mean_reciprocal_rank: if accepted == 0 {
+            0.0
+        } else {
+            reciprocal_ranks / accepted as f64
+        },
+    }
+}
+
+/// Replay recorded impressions against the current configuration and each
+/// alternative, re-ranking the recorded candidates by their stored features
+pub fn rank_eval(
+    impressions: &[SuggestionImpression],
+    current: &RankingConfig,
+    alternatives: &[(String, RankingConfig)],
+) -> RankEvalReport {
+    let judged: Vec<&SuggestionImpression> = impressions
+        .iter()
+        .filter(|i| !i.accepted.is_empty() || !i.dismissed.is_empty())
+        .collect();
+    let baseline = replay("current", &judged, current);
+    let mut results = vec![baseline.clone()];
+    for (name, config) in alternatives {
+        let mut result = replay(name, &judged, config);
+        result.acceptance_delta = result.acceptance_rate - baseline.acceptance_rate;
+        results.push(result);
+    }
+    RankEvalReport {
+        impressions: judged.len(),
+        accepted: judged.iter().map(|i| i.accepted.len()).sum(),
+        dismissed: judged.iter().map(|i| i.dismissed.len()).sum(),
+        results,
+    }
+}
+
+impl MemoryStore {
+    /// Rank `suggestions` under the user's preferences, leaving out those
+    /// whose reason they muted, keep the best `limit`, and record what was
+    /// shown on `surface` when recording is enabled
+    pub async fn surface_suggestions(
+        &self,
+        surface: &str,
+        suggestions: Vec<Suggestion>,
+        limit: usize,
+        activity: Option<&Activity>,
+    ) -> Result<Vec<Suggestion>> {
+        let preferences = self.preferences().await?;
+        let suggestions: Vec<Suggestion> = suggestions
+            .into_iter()
+            .filter(|s| !preferences.is_muted(s))
+            .collect();
+        let ranked = rank(suggestions, self.ranking(), activity, Some(&preferences));
+        if self.ranking().record && !ranked.is_empty() {
+            let impression = SuggestionImpression {
+                id: uuid::Uuid::new_v4().to_string(),
+                surface: surface.to_string(),
+                surfaced_at: Utc::now(),
+                shown: limit.min(ranked.len()),
+                candidates: ranked
+                    .iter()
+                    .take(MAX_CANDIDATES.max(limit))
+                    .map(|r| ImpressionCandidate {
+                        suggestion_id: r.suggestion.id.clone(),
+                        score: r.score,
+                        features: r.features.clone(),
+                        contributions: r.contributions.clone(),
+                    })
+                    .collect(),
+                accepted: Vec::new(),
+                dismissed: Vec::new(),
+            };
+            self.write_value(
+                &RecordKind::Impression.key(&impression.id),
+                serde_json::to_value(&impression)?,
+            )
+            .await?;
+        }
+        Ok(ranked
+            .into_iter()
+            .take(limit)
+            .map(|r| r.suggestion)
+            .collect())
+    }
+
+    pub async fn list_impressions(&self) -> Result<Vec<SuggestionImpression>> {
+        let mut impressions = Vec::new();
+        for key in self.client.list(RecordKind::Impression.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(impression) = serde_json::from_value::<SuggestionImpression>(value) {
+                    impressions.push(impression);
+                }
+            }
+        }
+        impressions.sort_by_key(|i| i.surfaced_at);
+        Ok(impressions)
+    }
+
+    /// Record that a suggestion was accepted (applied) or dismissed, on the
+    /// suggestion and on the latest impression that showed it, and learn
+    /// from it (see [`MemoryStore::preferences`]). Returns
+    /// `false` when there is no such suggestion.
+    pub async fn suggestion_feedback(&self, suggestion_id: &str, accepted: bool) -> Result<bool> {
+        let key = RecordKind::Suggestion.key(suggestion_id);
+        let Some(suggestion) = self
+            .update_value(&key, |suggestion: &mut Suggestion| {
+                if accepted {
+                    suggestion.applied = true;
+                } else {
+                    suggestion.dismissed = true;
+                }
+                Ok(())
+            })
+            .await?
+        else {
+            return Ok(false);
+        };
+        self.learn_from_feedback(&suggestion, accepted).await?;
+
+        if let Some(impression) = self
+            .list_impressions()
+            .await?
+            .into_iter()
+            .rev()
+            .find(|i| i.showed(suggestion_id))
+        {
+            self.update_value(
+                &RecordKind::Impression.key(&impression.id),
+                |impression: &mut SuggestionImpression| {
+                    let outcome = if accepted {
+                        &mut impression.accepted
+                    } else {
+                        &mut impression.dismissed
+                    };
+                    if !outcome.iter().any(|id| id == suggestion_id) {
+                        outcome.push(suggestion_id.to_string());
+                    }
+                    Ok(())
+                },
+            )
+            .await?;
+        }
+        Ok(true)
+    }
+
+    /// Replay recorded impressions against `alternatives`; see [`rank_eval`]
+    pub async fn rank_eval(
+        &self,
+        alternatives: &[(String, RankingConfig)],
+    ) -> Result<RankEvalReport> {
+        Ok(rank_eval(
+            &self.list_impressions().await?,
+            self.ranking(),
+            alternatives,
+        ))
+    }
+}
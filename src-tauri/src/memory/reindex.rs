@@ -0,0 +1,277 @@
+// Incremental rebuilds of derived indexes
+// Recomputes the SQLite mirror, daily command stats and output line indexes from primary
+// records in batches, checkpointing after each one so an interrupted rebuild resumes
+
+use crate::memory::api::MemoryStore;
+use crate::memory::backend::WriteBatch;
+use crate::memory::keys::{RecordKey, RecordKind};
+use crate::memory::mirror::{self, mirror_row};
+use crate::memory::pages::index_output;
+use crate::memory::schema::*;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use ts_rs::TS;
+
+/// Primary records read between checkpoints, unless the caller picks a size
+pub const DEFAULT_REINDEX_BATCH: usize = 500;
+
+/// Where an unfinished rebuild left off
+const CHECKPOINT_ID: &str = "reindex";
+
+/// An index derived from primary records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum IndexKind {
+    /// The SQLite mirror that SQL searches run against
+    Search,
+    /// Daily command counters by project, tag and command
+    Secondary,
+    /// Line-offset sidecars that paged output reads seek with
+    LineOffsets,
+}
+
+impl IndexKind {
+    pub const ALL: [IndexKind; 3] = [
+        IndexKind::Search,
+        IndexKind::Secondary,
+        IndexKind::LineOffsets,
+    ];
+
+    /// Key prefixes of the primary records the index is built from
+    fn sources(self) -> Vec<&'static str> {
+        match self {
+            IndexKind::Search => mirror::mirrored_prefixes().collect(),
+            IndexKind::Secondary => vec![RecordKind::Command.prefix()],
+            IndexKind::LineOffsets => vec![RecordKind::Output.prefix()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReindexCheckpoint {
+    kinds: Vec<IndexKind>,
+    /// Kinds already rebuilt
+    finished: Vec<IndexKind>,
+    /// Last primary record of the kind in progress that was folded in
+    after: Option<String>,
+    done: usize,
+}
+
+/// Sent after every batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReindexProgress {
+    pub kind: IndexKind,
+    /// Primary records of `kind` folded in so far
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReindexReport {
+    /// Primary records folded in per kind, including those an interrupted
+    /// call got through
+    pub records: BTreeMap<IndexKind, usize>,
+    /// Line-offset sidecars whose output no longer exists, removed
+    pub removed: usize,
+    /// Whether this call finished a rebuild an earlier one started
+    pub resumed: bool,
+}
+
+impl MemoryStore {
+    /// Rebuild `kinds` (every kind when empty) from the primary records,
+    /// `batch_size` at a time, calling `progress` after each batch. The
+    /// search index is written to the mirror at `mirror_path`.
+    ///
+    /// Each batch is committed together with a checkpoint, so calling this
+    /// again with the same kinds after an interruption carries on after the
+    /// last batch that landed; calling it with other kinds starts over.
+    pub async fn reindex(
+        &self,
+        kinds: &[IndexKind],
+        batch_size: usize,
+        mirror_path: &Path,
+        progress: &mut (dyn FnMut(&ReindexProgress) + Send),
+    ) -> Result<ReindexReport> {
+        let mut kinds = if kinds.is_empty() {
+            IndexKind::ALL.to_vec()
+        } else {
+            kinds.to_vec()
+        };
+        kinds.sort();
+        kinds.dedup();
+        if kinds.contains(&IndexKind::Search) {
+            anyhow::ensure!(
+                !self.is_encrypted(),
+                "The memory store is encrypted; a SQLite mirror would hold it in the clear"
+            );
+        }
+        let checkpoint_key = RecordKind::App.key(CHECKPOINT_ID);
+        let mut report = ReindexReport::default();
+        let mut checkpoint = match self
+            .read_value(&checkpoint_key)
+            .await?
+            .and_then(|v| serde_json::from_value::<ReindexCheckpoint>(v).ok())
+        {
+            Some(checkpoint) if checkpoint.kinds == kinds => {
+                report.resumed = true;
+                checkpoint
+            }
+            _ => ReindexCheckpoint {
+                kinds: kinds.clone(),
+                finished: Vec::new(),
+                after: None,
+                done: 0,
+            },
+        };
+
+        for kind in kinds {
+            if checkpoint.finished.contains(&kind) {
+                continue;
+            }
+            let mut keys = Vec::new();
+            for prefix in kind.sources() {
+                keys.extend(self.client.list(prefix).await?);
+            }
+            keys.sort();
+            let total = keys.len();
+            if checkpoint.after.is_none() {
+                self.clear_index(kind, mirror_path).await?;
+            }
+            let start = match &checkpoint.after {
+                Some(after) => keys.partition_point(|k| k <= after),
+                None => 0,
+            };
+            for chunk in keys[start..].chunks(batch_size.max(1)) {
+                checkpoint.after = chunk.last().cloned();
+                checkpoint.done += chunk.len();
+                let mut batch = WriteBatch::new();
+                batch.put(
+                    checkpoint_key.clone(),
+                    self.encrypt_value(serde_json::to_value(&checkpoint)?)
+                        .await?,
+                );
+                self.reindex_batch(kind, chunk, batch, mirror_path).await?;
+                progress(&ReindexProgress {
+                    kind,
+                    done: checkpoint.done,
+                    total,
+                });
+            }
+            if kind == IndexKind::LineOffsets {
+                report.removed = self.remove_stale_line_indexes(&keys).await?;
+            }
+            report.records.insert(kind, checkpoint.done);
+            checkpoint.finished.push(kind);
+            checkpoint.after = None;
+            checkpoint.done = 0;
+            self.write_value(&checkpoint_key, serde_json::to_value(&checkpoint)?)
+                .await?;
+        }
+        self.client.delete(&checkpoint_key).await?;
+        Ok(report)
+    }
+
+    /// Empty `kind` before it is built again. Line indexes are overwritten in
+    /// place, so paged reads keep working while they are rebuilt.
+    async fn clear_index(&self, kind: IndexKind, mirror_path: &Path) -> Result<()> {
+        match kind {
+            IndexKind::Search => {
+                let path = mirror_path.to_path_buf();
+                tokio::task::spawn_blocking(move || mirror::reset_mirror(&path).map(drop))
+                    .await??;
+            }
+            IndexKind::Secondary => {
+                for key in self.client.list(RecordKind::DayStats.prefix()).await? {
+                    self.client.delete(&key).await?;
+                }
+            }
+            IndexKind::LineOffsets => {}
+        }
+        Ok(())
+    }
+
+    /// Fold the records under `keys` into `kind`, committing `batch` with them
+    async fn reindex_batch(
+        &self,
+        kind: IndexKind,
+        keys: &[String],
+        mut batch: WriteBatch,
+        mirror_path: &Path,
+    ) -> Result<()> {
+        match kind {
+            IndexKind::Search => {
+                let mut rows = Vec::new();
+                for key in keys {
+                    if let Some(row) = self
+                        .read_value(key)
+                        .await?
+                        .and_then(|value| mirror_row(key, value))
+                    {
+                        rows.push(row);
+                    }
+                }
+                let path = mirror_path.to_path_buf();
+                // Rows are written over, so a batch the checkpoint missed is
+                // simply written again on resume
+                tokio::task::spawn_blocking(move || {
+                    mirror::write_mirror_rows(&mut mirror::open_mirror(&path)?, &rows)
+                })
+                .await??;
+                self.commit(batch).await
+            }
+            IndexKind::Secondary => {
+                let mut commands = Vec::new();
+                for key in keys {
+                    if let Some(command) = self
+                        .read_value(key)
+                        .await?
+                        .and_then(|v| serde_json::from_value::<Command>(v).ok())
+                    {
+                        commands.push(command);
+                    }
+                }
+                self.commit_command_stats(&commands, batch).await.map(drop)
+            }
+            IndexKind::LineOffsets => {
+                for key in keys {
+                    let Some(output) = self
+                        .read_value(key)
+                        .await?
+                        .and_then(|v| serde_json::from_value::<Output>(v).ok())
+                    else {
+                        continue;
+                    };
+                    match index_output(&output) {
+                        Ok(index) => batch.put(
+                            RecordKind::OutputIndex.key(&index.output_id),
+                            self.encrypt_value(serde_json::to_value(&index)?).await?,
+                        ),
+                        Err(e) => log::warn!("[reindex] Output {} not indexed: {:#}", output.id, e),
+                    }
+                }
+                self.commit(batch).await
+            }
+        }
+    }
+
+    /// Delete the line indexes of outputs not among `output_keys`
+    async fn remove_stale_line_indexes(&self, output_keys: &[String]) -> Result<usize> {
+        let outputs: HashSet<String> = output_keys
+            .iter()
+            .filter_map(|key| RecordKey::parse(key))
+            .map(|key| key.id)
+            .collect();
+        let mut removed = 0;
+        for key in self.client.list(RecordKind::OutputIndex.prefix()).await? {
+            if RecordKey::parse(&key).is_some_and(|k| !outputs.contains(&k.id)) {
+                self.client.delete(&key).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,272 @@
+// Re-run suggestions for failed tests
+// Narrows a failed test command to just the tests that failed, and retires the suggestion once they pass
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::parsers::{parser_for, ParsedOutput, StructuredFailure};
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::Utc;
+
+/// `context.kind` of re-run suggestions
+pub const RERUN_KIND: &str = "rerun_failed";
+
+/// cargo options that take a value, kept when narrowing a run
+const CARGO_VALUE_FLAGS: &[&str] = &[
+    "-p",
+    "--package",
+    "--features",
+    "-F",
+    "--test",
+    "--bin",
+    "--example",
+    "--manifest-path",
+    "--target",
+    "--profile",
+];
+
+fn quote(word: &str) -> String {
+    shlex::try_quote(word)
+        .map(|q| q.into_owned())
+        .unwrap_or_else(|_| word.to_string())
+}
+
+fn join(words: &[String]) -> String {
+    words.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ")
+}
+
+/// Tool family of a command; plain and JSON cargo output are one tool
+fn tool_for(command: &Command) -> Option<&'static str> {
+    match parser_for(&command.command, &command.args)? {
+        "cargo-json" => Some("cargo"),
+        tool => Some(tool),
+    }
+}
+
+/// Whether a command runs tests, as opposed to only building
+fn is_test_run(command: &Command) -> bool {
+    match tool_for(command) {
+        Some("cargo") | Some("go") => command
+            .args
+            .first()
+            .is_some_and(|a| a == "test" || a == "t"),
+        Some("pytest") | Some("jest") => true,
+        _ => false,
+    }
+}
+
+fn unique(names: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut seen = Vec::new();
+    for name in names {
+        if !seen.contains(&name) {
+            seen.push(name);
+        }
+    }
+    seen
+}
+
+/// The options of a cargo command line, without test filters
+fn cargo_options(args: &[String]) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut iter = args.iter().skip(1).take_while(|a| *a != "--");
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with('-') {
+            continue;
+        }
+        kept.push(arg.clone());
+        if CARGO_VALUE_FLAGS.contains(&arg.as_str()) {
+            kept.extend(iter.next().cloned());
+        }
+    }
+    kept
+}
+
+/// Command line re-running only the failed tests of `command`
+pub fn rerun_command(command: &Command, parsed: &ParsedOutput) -> Option<String> {
+    let failed: Vec<&StructuredFailure> = parsed
+        .failures
+        .iter()
+        .filter(|f| f.test_name.is_some())
+        .collect();
+    if failed.is_empty() {
+        return None;
+    }
+    let names = unique(failed.iter().filter_map(|f| f.test_name.clone()));
+    let program = command.command.clone();
+
+    let words = match parsed.parser.as_str() {
+        "cargo" | "cargo-json" => {
+            let mut words = vec![program, command.args.first()?.clone()];
+            words.extend(cargo_options(&command.args));
+            if names.len() == 1 {
+                words.extend(names);
+            } else {
+                // libtest takes several filters after `--`
+                words.push("--".to_string());
+                words.extend(names);
+            }
+            words
+        }
+        "pytest" => {
+            // Keep `python -m pytest` as it was invoked
+            let mut words = vec![program];
+            if let Some(at) = command.args.iter().position(|a| a == "pytest") {
+                words.extend(command.args[..=at].iter().cloned());
+            }
+            words.extend(names);
+            words.push("-x".to_string());
+            words
+        }
+        "jest" => {
+            let mut words = vec![program];
+            if let Some(at) = command.args.iter().position(|a| a == "jest") {
+                words.extend(command.args[..=at].iter().cloned());
+            }
+            words.extend(unique(failed.iter().filter_map(|f| f.file.clone())));
+            // jest matches `-t` against the full name, blocks joined by spaces
+            let pattern = names
+                .iter()
+                .map(|n| regex::escape(&n.replace(" › ", " ")))
+                .collect::<Vec<_>>()
+                .join("|");
+            words.push("-t".to_string());
+            words.push(format!("^({})$", pattern));
+            words
+        }
+        "go" => {
+            // Subtests re-run with their parent
+            let tests = unique(
+                names
+                    .iter()
+                    .map(|n| n.split('/').next().unwrap_or(n).to_string()),
+            );
+            let mut words = vec![program, "test".to_string()];
+            words.push("-run".to_string());
+            words.push(format!("^({})$", tests.join("|")));
+            // Packages, without the old `-run` and its pattern
+            let mut args = command.args.iter().skip(1);
+            while let Some(arg) = args.next() {
+                if arg == "-run" {
+                    args.next();
+                } else if !arg.starts_with('-') {
+                    words.push(arg.clone());
+                }
+            }
+            words
+        }
+        _ => return None,
+    };
+    Some(join(&words))
+}
+
+/// Suggestion to re-run just the failed tests of `command`
+pub fn rerun_suggestion(
+    command: &Command,
+    error: &Error,
+    parsed: &ParsedOutput,
+) -> Option<Suggestion> {
+    let line = rerun_command(command, parsed)?;
+    let tests = parsed.failed_tests();
+    let shown: Vec<&str> = tests.iter().take(3).copied().collect();
+    Some(Suggestion {
+        id: uuid::Uuid::new_v4().to_string(),
+        suggestion_type: "command".to_string(),
+        priority: "medium".to_string(),
+        rank: 0.8,
+        title: format!(
+            "Re-run only the {} failed test{}",
+            tests.len(),
+            if tests.len() == 1 { "" } else { "s" }
+        ),
+        description: format!(
+            "{}{}",
+            shown.join(", "),
+            if tests.len() > shown.len() {
+                format!(" and {} more", tests.len() - shown.len())
+            } else {
+                String::new()
+            }
+        ),
+        command: Some(line),
+        args: None,
+        context: serde_json::json!({
+            "kind": RERUN_KIND,
+            "tool": tool_for(command),
+            "command_id": command.id,
+            "error_id": error.id,
+            "cwd": command.cwd,
+            "tests": tests,
+        }),
+        created_at: Utc::now(),
+        dismissed: false,
+        applied: false,
+    })
+}
+
+impl MemoryStore {
+    /// Active re-run suggestions for tests run from `cwd` with `tool`
+    async fn rerun_suggestions(&self, cwd: &str, tool: &str) -> Result<Vec<Suggestion>> {
+        Ok(self
+            .get_suggestions(None, None)
+            .await?
+            .into_iter()
+            .filter(|s| {
+                s.context["kind"] == RERUN_KIND
+                    && s.context["cwd"] == cwd
+                    && s.context["tool"] == tool
+            })
+            .collect())
+    }
+
+    /// Classify a finished command's failure and keep re-run suggestions
+    /// current: a passing test run retires the ones for its directory, and a
+    /// failing one replaces them with its own, unless the user muted them.
+    /// Returns the new suggestion.
+    pub async fn track_test_run(&self, command: &Command) -> Result<Option<Suggestion>> {
+        let error = self.classify_failure(command).await?;
+        self.track_classified_run(command, error.as_ref()).await
+    }
+
+    /// [`Self::track_test_run`] for a command whose failure is already
+    /// classified (`error` is what `classify_failure` returned)
+    pub async fn track_classified_run(
+        &self,
+        command: &Command,
+        error: Option<&Error>,
+    ) -> Result<Option<Suggestion>> {
+        let Some(tool) = tool_for(command) else {
+            return Ok(None);
+        };
+        if !is_test_run(command) {
+            return Ok(None);
+        }
+
+        for old in self.rerun_suggestions(&command.cwd, tool).await? {
+            self.update_value(
+                &RecordKind::Suggestion.key(&old.id),
+                |suggestion: &mut Suggestion| {
+                    suggestion.dismissed = true;
+                    suggestion.context["expired_at"] = serde_json::json!(Utc::now());
+                    suggestion.context["expired_by"] = serde_json::json!(command.id);
+                    Ok(())
+                },
+            )
+            .await?;
+        }
+
+        let Some(error) = error else {
+            return Ok(None);
+        };
+        let Ok(parsed) = serde_json::from_value::<ParsedOutput>(error.context.clone()) else {
+            return Ok(None);
+        };
+        let Some(suggestion) = rerun_suggestion(command, error, &parsed) else {
+            return Ok(None);
+        };
+        if self.preferences().await?.is_muted(&suggestion) {
+            return Ok(None);
+        }
+        self.persist_suggestion(suggestion.clone()).await?;
+        Ok(Some(suggestion))
+    }
+}
@@ -0,0 +1,290 @@
+// Output retention tiers: hot chunks stay as captured, warm ones are recompressed with zstd at
+// its highest level, cold ones are offloaded to object storage or cut down to their first and last lines
+
+use crate::health::Component;
+use crate::memory::api::MemoryStore;
+use crate::memory::codec::{Codec, CodecId, Zstd};
+use crate::memory::keys::RecordKind;
+use crate::memory::pages::{chunk_bytes, index_output, OutputPageCache};
+use crate::memory::schema::Output;
+use crate::memory::StoreOpener;
+use crate::storage::RemoteContent;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use ts_rs::TS;
+
+/// Key prefix of offloaded output chunks in object storage
+pub const OUTPUT_PREFIX: &str = "outputs/";
+
+/// Retention tier of a stored output chunk
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, TS,
+)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum OutputTier {
+    /// As captured: plain, or with the `[output]` codec
+    #[default]
+    Hot,
+    /// zstd at the highest level
+    Warm,
+    /// zstd, and either offloaded (`remote` set) or summarized
+    Cold,
+}
+
+/// What happens to output that turns cold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ColdAction {
+    /// Move it to `[storage.s3]`; it stays warm when there is no bucket
+    #[default]
+    Offload,
+    /// Keep the first and last `summary_lines` lines
+    Summarize,
+}
+
+/// `[retention]` configuration section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    pub warm_after_days: u64,
+    /// 0 keeps output warm
+    pub cold_after_days: u64,
+    pub cold: ColdAction,
+    pub summary_lines: usize,
+    pub interval_hours: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            warm_after_days: 7,
+            cold_after_days: 0,
+            cold: ColdAction::Offload,
+            summary_lines: 100,
+            interval_hours: 6,
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// The tier output captured `age` ago belongs in
+    pub fn tier_for(&self, age: Duration) -> OutputTier {
+        if self.cold_after_days > 0 && age >= Duration::days(self.cold_after_days as i64) {
+            OutputTier::Cold
+        } else if age >= Duration::days(self.warm_after_days as i64) {
+            OutputTier::Warm
+        } else {
+            OutputTier::Hot
+        }
+    }
+}
+
+/// The first and last `keep` lines of `text` with a marker between them;
+/// `None` when there is nothing to cut
+pub fn summarize(text: &str, keep: usize) -> Option<String> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    if lines.len() <= keep * 2 + 1 {
+        return None;
+    }
+    let elided = lines.len() - keep * 2;
+    let mut summary: String = lines[..keep].concat();
+    summary.push_str(&format!(
+        "[... {} lines removed by output retention ...]\n",
+        elided
+    ));
+    summary.push_str(&lines[lines.len() - keep..].concat());
+    Some(summary)
+}
+
+fn zstd_max(bytes: &[u8]) -> Result<Vec<u8>> {
+    Zstd {
+        level: zstd::zstd_safe::max_c_level(),
+    }
+    .compress(bytes)
+}
+
+/// Outcome of one retention pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub warmed: usize,
+    pub offloaded: usize,
+    pub summarized: usize,
+    /// Stored content bytes of the changed chunks, before and after
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// Chunks left as they were after an error
+    pub failed: usize,
+    /// Commands whose output lines changed, so cached line indexes are stale
+    pub summarized_commands: Vec<String>,
+}
+
+impl MemoryStore {
+    /// Move every output chunk to the tier its age calls for
+    pub async fn apply_output_retention(
+        &self,
+        config: &RetentionConfig,
+        now: DateTime<Utc>,
+    ) -> Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        for key in self.client.list(RecordKind::Output.prefix()).await? {
+            let Some(value) = self.read_value(&key).await? else {
+                continue;
+            };
+            let Ok(output) = serde_json::from_value::<Output>(value) else {
+                continue;
+            };
+            let tier = config.tier_for(now - output.timestamp);
+            if tier <= output.tier {
+                continue;
+            }
+            if let Err(e) = self.retier_output(output, tier, config, &mut report).await {
+                log::warn!("[retention] {:#}", e);
+                report.failed += 1;
+            }
+        }
+        report.summarized_commands.sort();
+        report.summarized_commands.dedup();
+        Ok(report)
+    }
+
+    async fn retier_output(
+        &self,
+        mut output: Output,
+        tier: OutputTier,
+        config: &RetentionConfig,
+        report: &mut RetentionReport,
+    ) -> Result<()> {
+        // Without a bucket, output due to be offloaded stays warm
+        let offload = tier == OutputTier::Cold && config.cold == ColdAction::Offload;
+        let tier = if offload && (self.remote_store().is_none() || self.is_encrypted()) {
+            OutputTier::Warm
+        } else {
+            tier
+        };
+        if tier <= output.tier {
+            return Ok(());
+        }
+
+        let before = output.content.len() as u64;
+        let mut summarized = false;
+        if output.tier == OutputTier::Hot || tier == OutputTier::Cold && !offload {
+            let bytes = chunk_bytes(&output)
+                .with_context(|| format!("Output chunk {} is unreadable", output.id))?;
+            let summary = (tier == OutputTier::Cold)
+                .then(|| summarize(&String::from_utf8_lossy(&bytes), config.summary_lines))
+                .flatten();
+            output.content = match summary {
+                Some(summary) => {
+                    summarized = true;
+                    output.size_bytes = summary.len() as u64;
+                    zstd_max(summary.as_bytes())?
+                }
+                None => zstd_max(&bytes)?,
+            };
+            output.compressed = true;
+            output.codec = Some(CodecId::Zstd);
+        }
+        output.tier = tier;
+
+        if tier == OutputTier::Cold && offload {
+            let remote = self.remote_store().context("No remote store")?;
+            let content = std::mem::take(&mut output.content);
+            let pointer = RemoteContent {
+                key: format!("{}{}", OUTPUT_PREFIX, output.id),
+                sha256: hex::encode(Sha256::digest(&content)),
+                bytes: content.len() as u64,
+                offloaded_at: Utc::now(),
+            };
+            remote.put(&pointer.key, content).await?;
+            // Only drop the local copy once the remote one reads back intact
+            pointer.fetch(remote).await?;
+            output.remote = Some(pointer);
+            report.offloaded += 1;
+        } else if summarized {
+            self.store_line_index(&index_output(&output)?).await?;
+            report.summarized += 1;
+            report.summarized_commands.push(output.command_id.clone());
+        } else {
+            report.warmed += 1;
+        }
+
+        report.bytes_before += before;
+        report.bytes_after += output.content.len() as u64;
+        self.write_value(
+            &RecordKind::Output.key(&output.id),
+            serde_json::to_value(&output)?,
+        )
+        .await
+    }
+
+    /// Fetch the content of an offloaded chunk back from the remote store
+    pub(crate) async fn hydrate_output(&self, output: &mut Output) -> Result<()> {
+        let Some(pointer) = &output.remote else {
+            return Ok(());
+        };
+        let remote = self.remote_store().with_context(|| {
+            format!(
+                "Output chunk {} is in object storage, but [storage.s3] is not configured",
+                output.id
+            )
+        })?;
+        output.content = pointer.fetch(remote).await?;
+        Ok(())
+    }
+}
+
+/// Apply output retention every `interval_hours`, dropping the cached line
+/// indexes of summarized commands from `cache`. Returns at once when
+/// retention is off.
+pub async fn run_retention_loop(
+    config: RetentionConfig,
+    open_store: StoreOpener,
+    cache: Option<Arc<OutputPageCache>>,
+    health: Component,
+) {
+    if !config.enabled {
+        return health.disable("Set [retention] enabled to apply output retention");
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        config.interval_hours.max(1) * 3600,
+    ));
+    loop {
+        interval.tick().await;
+        let result = async {
+            let store = open_store().await?;
+            store.apply_output_retention(&config, Utc::now()).await
+        }
+        .await;
+        match result {
+            Ok(report) => {
+                if let Some(cache) = &cache {
+                    for command_id in &report.summarized_commands {
+                        cache.invalidate(command_id);
+                    }
+                }
+                health.ok();
+                log::info!(
+                    "[retention] {} chunks warmed, {} offloaded, {} summarized ({} -> {} bytes), {} failed",
+                    report.warmed,
+                    report.offloaded,
+                    report.summarized,
+                    report.bytes_before,
+                    report.bytes_after,
+                    report.failed
+                );
+            }
+            Err(e) => {
+                log::warn!("[retention] Output retention failed: {:#}", e);
+                health.error(format!("{:#}", e));
+            }
+        }
+    }
+}
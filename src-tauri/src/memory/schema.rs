@@ -1,12 +1,19 @@
 // Schema definitions for cognitive memory storage
-// Defines tables/collections: sessions, commands, outputs, errors, insights, suggestions, provenance
+// Defines tables/collections: sessions, commands, outputs, errors, insights, suggestions, provenance, artifacts
 
+use crate::memory::ansi::StyledSpan;
+use crate::memory::codec::CodecId;
+use crate::memory::retention::OutputTier;
+use crate::memory::scrub::ScrubHit;
+use crate::storage::RemoteContent;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use ts_rs::TS;
 use uuid::Uuid;
 
 /// Session metadata - represents a terminal session
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct Session {
     pub id: String,
     pub started_at: DateTime<Utc>,
@@ -16,10 +23,13 @@ pub struct Session {
     pub hostname: Option<String>,
     pub user: Option<String>,
     pub metadata: serde_json::Value, // Additional session metadata
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 /// Normalized command record
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct Command {
     pub id: String,
     pub session_id: String,
@@ -33,23 +43,85 @@ pub struct Command {
     pub success: bool,
     pub duration_ms: Option<u64>,
     pub pid: Option<u32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub scrubbed_by: Vec<ScrubHit>, // Scrub rules that changed the args
+    #[serde(default)]
+    pub git_commit: Option<String>, // Commit checked out in cwd when the command ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub suspended_ms: Option<u64>, // Time the machine slept while the command ran
 }
 
+/// Stream type of captured objects, such as a PowerShell pipeline's: one JSON
+/// document per line
+pub const OBJECTS_STREAM: &str = "objects";
+pub const OBJECTS_CONTENT_TYPE: &str = "application/x-ndjson";
+
 /// Output chunk - stdout/stderr output, optionally compressed
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct Output {
     pub id: String,
     pub command_id: String,
-    pub stream_type: String, // "stdout" or "stderr"
+    pub stream_type: String, // "stdout", "stderr" or OBJECTS_STREAM
     pub chunk_index: u32,
     pub content: Vec<u8>, // Raw bytes (may be compressed)
-    pub compressed: bool, // Whether content is gzip-compressed
+    pub compressed: bool, // Whether content is compressed, with `codec`
     pub size_bytes: u64,  // Uncompressed size
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub content_type: Option<String>, // Detected MIME type, e.g. "text/plain"
+    #[serde(default)]
+    pub blob_artifact_id: Option<String>, // Binary payload moved to an artifact
+    #[serde(default)]
+    pub encoding: Option<String>, // Source encoding text was decoded from, e.g. "Shift_JIS"
+    #[serde(default)]
+    pub scrubbed_by: Vec<ScrubHit>, // Scrub rules that changed the content
+    #[serde(default)]
+    pub tier: OutputTier, // Retention tier
+    #[serde(default)]
+    pub remote: Option<RemoteContent>, // Cold content offloaded to object storage
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<StyledSpan>>", optional)]
+    pub spans: Vec<StyledSpan>, // Colors of the text, when escape sequences are stored as spans
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub codec: Option<CodecId>, // Unset on chunks from before codecs were recorded
 }
 
-/// Classified error record
+impl Output {
+    /// The codec `content` is stored with. Chunks from before codecs were
+    /// recorded are gzip when hot and zstd when warm or cold.
+    pub fn codec(&self) -> CodecId {
+        match (self.compressed, self.codec, self.tier) {
+            (false, _, _) => CodecId::None,
+            (true, Some(codec), _) => codec,
+            (true, None, OutputTier::Hot) => CodecId::Gzip,
+            (true, None, _) => CodecId::Zstd,
+        }
+    }
+}
+
+/// Sparse line-offset index of one output chunk, stored beside it so line
+/// ranges can be read without decompressing every chunk
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputLineIndex {
+    pub output_id: String,
+    pub command_id: String,
+    pub chunk_index: u32,
+    pub timestamp: DateTime<Utc>,
+    pub newlines: u64,
+    pub byte_len: u64, // Uncompressed length
+    pub ends_with_newline: bool,
+    pub stride: u64,
+    pub checkpoints: Vec<u64>, // Byte offset after newline i * stride (0 for i = 0)
+}
+
+/// Classified error record
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export, rename = "ErrorRecord")]
 pub struct Error {
     pub id: String,
     pub command_id: String,
@@ -61,10 +133,13 @@ pub struct Error {
     pub exit_code: Option<i32>,
     pub timestamp: DateTime<Utc>,
     pub context: serde_json::Value, // Additional error context
+    #[serde(default)]
+    pub scrubbed_by: Vec<ScrubHit>, // Scrub rules that changed the message or snippet
 }
 
 /// AI/heuristic annotation/insight
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct Insight {
     pub id: String,
     pub command_id: Option<String>, // Optional: linked to specific command
@@ -79,7 +154,8 @@ pub struct Insight {
 }
 
 /// Ranked suggestion
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct Suggestion {
     pub id: String,
     pub suggestion_type: String, // "command", "optimization", "shortcut", "warning", "tip"
@@ -109,6 +185,22 @@ pub struct Provenance {
     pub metadata: serde_json::Value,
 }
 
+/// User-attached artifact (clipboard content, screenshot, file) linked to a session or command
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Artifact {
+    pub id: String,
+    pub session_id: Option<String>,
+    pub command_id: Option<String>,
+    pub artifact_type: String, // "clipboard_text", "clipboard_image", "screenshot", "file", "output_blob"
+    pub mime_type: String,     // e.g. "text/plain", "image/png"
+    pub content: Vec<u8>,
+    pub size_bytes: u64,
+    pub source: String, // "user", "capture", etc.
+    pub created_at: DateTime<Utc>,
+    pub metadata: serde_json::Value,
+}
+
 /// Event wrapper for append_event API
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemoryEvent {
@@ -121,7 +213,8 @@ pub struct MemoryEvent {
 }
 
 /// Context window for analysis
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct ContextWindow {
     pub session_id: String,
     pub start_time: DateTime<Utc>,
@@ -130,6 +223,9 @@ pub struct ContextWindow {
     pub outputs: Vec<Output>,
     pub errors: Vec<Error>,
     pub insights: Vec<Insight>,
+    /// Recent intents and whether the user is stuck in a build/test loop
+    #[serde(default)]
+    pub activity: crate::memory::intent::Activity,
 }
 
 impl Session {
@@ -143,6 +239,7 @@ impl Session {
             hostname: None,
             user: None,
             metadata: serde_json::json!({}),
+            tags: Vec::new(),
         }
     }
 }
@@ -162,8 +259,19 @@ impl Command {
             success: false,
             duration_ms: None,
             pid: None,
+            tags: Vec::new(),
+            scrubbed_by: Vec::new(),
+            git_commit: None,
+            suspended_ms: None,
         }
     }
+
+    /// Time the command ran while the machine was awake: its duration less
+    /// any sleep it ran across
+    pub fn awake_ms(&self) -> Option<u64> {
+        self.duration_ms
+            .map(|ms| ms.saturating_sub(self.suspended_ms.unwrap_or(0)))
+    }
 }
 
 impl Output {
@@ -174,6 +282,11 @@ impl Output {
         content: Vec<u8>,
     ) -> Self {
         let size_bytes = content.len() as u64;
+        let content_type = if stream_type == OBJECTS_STREAM {
+            OBJECTS_CONTENT_TYPE.to_string()
+        } else {
+            crate::memory::content::detect_content(&content).mime_type
+        };
         Self {
             id: Uuid::new_v4().to_string(),
             command_id,
@@ -183,6 +296,14 @@ impl Output {
             compressed: false,
             size_bytes,
             timestamp: Utc::now(),
+            content_type: Some(content_type),
+            blob_artifact_id: None,
+            encoding: None,
+            scrubbed_by: Vec::new(),
+            tier: OutputTier::Hot,
+            remote: None,
+            spans: Vec::new(),
+            codec: None,
         }
     }
 }
@@ -206,6 +327,7 @@ impl Error {
             exit_code: None,
             timestamp: Utc::now(),
             context: serde_json::json!({}),
+            scrubbed_by: Vec::new(),
         }
     }
 }
@@ -273,3 +395,21 @@ impl Provenance {
         }
     }
 }
+
+impl Artifact {
+    pub fn new(artifact_type: String, mime_type: String, content: Vec<u8>) -> Self {
+        let size_bytes = content.len() as u64;
+        Self {
+            id: Uuid::new_v4().to_string(),
+            session_id: None,
+            command_id: None,
+            artifact_type,
+            mime_type,
+            content,
+            size_bytes,
+            source: "user".to_string(),
+            created_at: Utc::now(),
+            metadata: serde_json::json!({}),
+        }
+    }
+}
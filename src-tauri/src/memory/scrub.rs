@@ -0,0 +1,271 @@
+// Terminal output scrubbing
+// Built-in secret patterns plus user regex rules, applied to captured records and/or displayed output
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Where a rule applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ScrubStage {
+    /// Before commands, outputs, and errors are written to memory
+    Capture,
+    /// Before terminal and command output reaches the UI
+    Display,
+}
+
+/// Sample input and the output a rule must produce from it on its own
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScrubFixture {
+    pub input: String,
+    pub expected: String,
+}
+
+/// User-defined scrubbing rule, from `[[scrub.rules]]`
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScrubRule {
+    /// Recorded on every record the rule changes
+    pub name: String,
+    pub pattern: String,
+    /// Replacement text; `$1` / `${name}` refer to capture groups
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+    #[serde(default = "all_stages")]
+    pub apply_to: Vec<ScrubStage>,
+    /// Checked when the config loads and by `scrub_preview`
+    #[serde(default)]
+    pub fixtures: Vec<ScrubFixture>,
+}
+
+fn default_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+fn all_stages() -> Vec<ScrubStage> {
+    vec![ScrubStage::Capture, ScrubStage::Display]
+}
+
+/// `[scrub]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ScrubConfig {
+    pub enabled: bool,
+    /// Apply the built-in secret patterns (key=value secrets, bearer tokens, private keys)
+    pub builtin: bool,
+    /// Applied after the built-in patterns, in order
+    pub rules: Vec<ScrubRule>,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            builtin: true,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Built-in rules, matching the observer's secret redaction
+fn builtin_rules() -> Vec<ScrubRule> {
+    let rule = |name: &str, pattern: &str, replacement: &str| ScrubRule {
+        name: format!("builtin:{}", name),
+        pattern: pattern.to_string(),
+        replacement: replacement.to_string(),
+        apply_to: all_stages(),
+        fixtures: Vec::new(),
+    };
+    vec![
+        rule(
+            "key-value",
+            r"(?i)\b(token|secret|password|api[_-]?key|auth[_-]?token|access[_-]?token)(\s*[:=]\s*)\S{8,}",
+            "$1$2[REDACTED]",
+        ),
+        rule(
+            "bearer",
+            r"(?i)\b(bearer)\s+[A-Za-z0-9\-._~+/]+=*",
+            "$1 [REDACTED]",
+        ),
+        rule(
+            "private-key",
+            r"(?s)-----BEGIN ([A-Z]+ )?PRIVATE KEY-----.*?-----END ([A-Z]+ )?PRIVATE KEY-----",
+            "[REDACTED PRIVATE KEY]",
+        ),
+    ]
+}
+
+/// A rule that changed a record, and how many times it matched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScrubHit {
+    pub rule: String,
+    pub count: usize,
+}
+
+/// Add `hits` to a record's provenance, merging counts per rule
+pub(crate) fn merge_hits(existing: &mut Vec<ScrubHit>, hits: Vec<ScrubHit>) {
+    for hit in hits {
+        match existing.iter_mut().find(|h| h.rule == hit.rule) {
+            Some(h) => h.count += hit.count,
+            None => existing.push(hit),
+        }
+    }
+}
+
+/// Result of checking one fixture
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FixtureResult {
+    pub rule: String,
+    pub input: String,
+    pub expected: String,
+    pub actual: String,
+    pub passed: bool,
+}
+
+/// What each rule matched in a sample
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RuleMatches {
+    pub rule: String,
+    pub builtin: bool,
+    /// Matched text, as the rule saw it after the rules before it ran
+    pub matches: Vec<String>,
+}
+
+/// `scrub_preview` result
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScrubPreview {
+    pub stage: ScrubStage,
+    pub output: String,
+    pub rules: Vec<RuleMatches>,
+    pub fixtures: Vec<FixtureResult>,
+}
+
+struct CompiledRule {
+    rule: ScrubRule,
+    regex: Regex,
+    builtin: bool,
+}
+
+impl CompiledRule {
+    fn applies_to(&self, stage: ScrubStage) -> bool {
+        self.rule.apply_to.contains(&stage)
+    }
+}
+
+/// Compiled scrubbing rules
+pub struct Scrubber {
+    enabled: bool,
+    rules: Vec<CompiledRule>,
+}
+
+impl Scrubber {
+    pub fn new(config: &ScrubConfig) -> Result<Self> {
+        let builtin = if config.builtin {
+            builtin_rules()
+        } else {
+            Vec::new()
+        };
+        let rules = builtin
+            .into_iter()
+            .map(|r| (r, true))
+            .chain(config.rules.iter().cloned().map(|r| (r, false)))
+            .map(|(rule, builtin)| {
+                let regex = Regex::new(&rule.pattern).with_context(|| {
+                    format!("Invalid scrub rule {}: {}", rule.name, rule.pattern)
+                })?;
+                Ok(CompiledRule {
+                    rule,
+                    regex,
+                    builtin,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            enabled: config.enabled,
+            rules,
+        })
+    }
+
+    /// Scrub `text` for `stage`; the hits name every rule that changed it
+    pub fn scrub(&self, text: &str, stage: ScrubStage) -> (String, Vec<ScrubHit>) {
+        let mut text = text.to_string();
+        let mut hits = Vec::new();
+        if !self.enabled {
+            return (text, hits);
+        }
+        for rule in self.rules.iter().filter(|r| r.applies_to(stage)) {
+            let count = rule.regex.find_iter(&text).count();
+            if count > 0 {
+                text = rule
+                    .regex
+                    .replace_all(&text, rule.rule.replacement.as_str())
+                    .into_owned();
+                hits.push(ScrubHit {
+                    rule: rule.rule.name.clone(),
+                    count,
+                });
+            }
+        }
+        (text, hits)
+    }
+
+    /// Run every fixture against its rule alone
+    pub fn check_fixtures(&self) -> Vec<FixtureResult> {
+        self.rules
+            .iter()
+            .flat_map(|rule| {
+                rule.rule.fixtures.iter().map(|fixture| {
+                    let actual = rule
+                        .regex
+                        .replace_all(&fixture.input, rule.rule.replacement.as_str())
+                        .into_owned();
+                    FixtureResult {
+                        rule: rule.rule.name.clone(),
+                        input: fixture.input.clone(),
+                        expected: fixture.expected.clone(),
+                        passed: actual == fixture.expected,
+                        actual,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Show what scrubbing `sample` for `stage` would do and which rule did it.
+    /// Runs even when scrubbing is disabled, so rules can be tried out first.
+    pub fn preview(&self, sample: &str, stage: ScrubStage) -> ScrubPreview {
+        let mut output = sample.to_string();
+        let mut rules = Vec::new();
+        for rule in self.rules.iter().filter(|r| r.applies_to(stage)) {
+            let matches = rule
+                .regex
+                .find_iter(&output)
+                .map(|m| m.as_str().to_string())
+                .collect();
+            output = rule
+                .regex
+                .replace_all(&output, rule.rule.replacement.as_str())
+                .into_owned();
+            rules.push(RuleMatches {
+                rule: rule.rule.name.clone(),
+                builtin: rule.builtin,
+                matches,
+            });
+        }
+        ScrubPreview {
+            stage,
+            output,
+            rules,
+            fixtures: self.check_fixtures(),
+        }
+    }
+}
@@ -0,0 +1,440 @@
+// Shell integration markers (OSC 133 / OSC 633)
+// Splits PTY streams into prompt, command, and output using the marks emitted by WezTerm, Kitty, VS Code, etc.
+
+use crate::memory::api::MemoryStore;
+use crate::memory::schema::*;
+use crate::memory::tags::git_head;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Escape sequences longer than this without a terminator are passed through as text
+const MAX_SEQUENCE_LEN: usize = 8192;
+
+/// A shell integration mark
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mark", rename_all = "snake_case")]
+pub enum ShellMark {
+    /// `133;A` – prompt is about to be drawn
+    PromptStart,
+    /// `133;B` – prompt done, user input begins
+    CommandStart,
+    /// `133;C` – input accepted, command output begins
+    CommandExecuted,
+    /// `133;D[;exit]` – command finished
+    CommandFinished { exit_code: Option<i32> },
+    /// `633;E;<line>` – the exact command line (VS Code)
+    CommandLine { command_line: String },
+    /// `633;P;Cwd=<dir>` or `7;file://host/<dir>` – current directory
+    Cwd { cwd: String },
+}
+
+/// Piece of a PTY stream: plain bytes or a recognised mark
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(Vec<u8>),
+    Mark(ShellMark),
+}
+
+/// Decode VS Code's `\xHH` / `\\` escaping in 633 payloads
+fn unescape_633(value: &str) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    let bytes = value.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            if bytes.get(i + 1) == Some(&b'\\') {
+                out.push(b'\\');
+                i += 2;
+                continue;
+            }
+            if bytes.get(i + 1) == Some(&b'x') {
+                if let Some(byte) = value
+                    .get(i + 2..i + 4)
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    out.push(byte);
+                    i += 4;
+                    continue;
+                }
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Path component of an OSC 7 `file://host/path` URL, percent-decoded
+fn cwd_from_file_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let mut out = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        let decoded = (path.as_bytes()[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(path.as_bytes()[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Interpret an OSC payload (between `ESC ]` and the terminator)
+fn parse_osc(payload: &str) -> Option<ShellMark> {
+    let (code, rest) = payload.split_once(';').unwrap_or((payload, ""));
+    match code {
+        "133" | "633" => {
+            let mut params = rest.splitn(2, ';');
+            let kind = params.next()?;
+            let arg = params.next();
+            match kind {
+                "A" => Some(ShellMark::PromptStart),
+                "B" => Some(ShellMark::CommandStart),
+                "C" => Some(ShellMark::CommandExecuted),
+                "D" => Some(ShellMark::CommandFinished {
+                    exit_code: arg
+                        .and_then(|a| a.split(';').next())
+                        .and_then(|a| a.trim().parse().ok()),
+                }),
+                "E" if code == "633" => {
+                    // A trailing `;<nonce>` is not part of the command line
+                    let line = arg.unwrap_or("");
+                    let line = line.split_once(';').map_or(line, |(l, _)| l);
+                    Some(ShellMark::CommandLine {
+                        command_line: unescape_633(line),
+                    })
+                }
+                "P" if code == "633" => {
+                    let (key, value) = arg?.split_once('=')?;
+                    (key == "Cwd").then(|| ShellMark::Cwd {
+                        cwd: unescape_633(value),
+                    })
+                }
+                _ => None,
+            }
+        }
+        "7" => cwd_from_file_url(rest).map(|cwd| ShellMark::Cwd { cwd }),
+        _ => None,
+    }
+}
+
+/// Streaming OSC parser. Feed raw PTY chunks; marks split across chunks are
+/// reassembled, and escape sequences it does not understand stay in the text.
+#[derive(Debug, Default)]
+pub struct OscParser {
+    pending: Vec<u8>,
+}
+
+impl OscParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Segment> {
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(chunk);
+
+        let mut segments = Vec::new();
+        let mut text = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            if data[i] != 0x1b {
+                text.push(data[i]);
+                i += 1;
+                continue;
+            }
+            match data.get(i + 1) {
+                // Lone ESC at the end of the chunk: wait for more
+                None => {
+                    self.pending = data[i..].to_vec();
+                    break;
+                }
+                Some(b']') => {}
+                Some(_) => {
+                    text.push(data[i]);
+                    i += 1;
+                    continue;
+                }
+            }
+
+            // Terminated by BEL or ST (ESC \)
+            let body = &data[i + 2..];
+            let end = body
+                .iter()
+                .enumerate()
+                .find(|(j, b)| **b == 0x07 || (**b == 0x1b && body.get(j + 1) == Some(&b'\\')))
+                .map(|(j, b)| (j, if *b == 0x07 { 1 } else { 2 }));
+            let Some((len, terminator)) = end else {
+                if data.len() - i > MAX_SEQUENCE_LEN {
+                    text.extend_from_slice(&data[i..]);
+                } else {
+                    self.pending = data[i..].to_vec();
+                }
+                break;
+            };
+
+            let sequence_end = i + 2 + len + terminator;
+            match parse_osc(&String::from_utf8_lossy(&body[..len])) {
+                Some(mark) => {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Mark(mark));
+                }
+                None => text.extend_from_slice(&data[i..sequence_end]),
+            }
+            i = sequence_end;
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        segments
+    }
+}
+
+/// Remove CSI/OSC escape sequences and carriage returns from echoed input
+pub fn strip_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    // Parameters and intermediates, then one final byte in @..~
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' | '\x07' => {}
+            // Backspace erases the previous echoed character
+            '\x08' => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// One command delimited by shell integration marks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedCommand {
+    pub command_line: String,
+    pub cwd: Option<String>,
+    /// Raw output between `C` and `D`, escape sequences included
+    pub output: Vec<u8>,
+    pub exit_code: Option<i32>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    /// Remote host the command ran on, for commands captured over ssh
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Unknown,
+    Prompt,
+    Input,
+    Output,
+}
+
+/// Turns a stream of [`Segment`]s into [`CapturedCommand`]s
+#[derive(Debug)]
+pub struct CommandSegmenter {
+    phase: Phase,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    explicit_line: Option<String>,
+    command_line: String,
+    cwd: Option<String>,
+    started_at: DateTime<Utc>,
+}
+
+impl Default for CommandSegmenter {
+    fn default() -> Self {
+        Self {
+            phase: Phase::Unknown,
+            input: Vec::new(),
+            output: Vec::new(),
+            explicit_line: None,
+            command_line: String::new(),
+            cwd: None,
+            started_at: Utc::now(),
+        }
+    }
+}
+
+impl CommandSegmenter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the stream has carried any marks yet
+    pub fn saw_marks(&self) -> bool {
+        self.phase != Phase::Unknown || self.cwd.is_some()
+    }
+
+    /// Consume segments, returning commands that finished within them
+    pub fn push(&mut self, segments: Vec<Segment>) -> Vec<CapturedCommand> {
+        let mut finished = Vec::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(bytes) => match self.phase {
+                    Phase::Input => self.input.extend_from_slice(&bytes),
+                    Phase::Output => self.output.extend_from_slice(&bytes),
+                    Phase::Unknown | Phase::Prompt => {}
+                },
+                Segment::Mark(mark) => {
+                    if let Some(command) = self.apply(mark) {
+                        finished.push(command);
+                    }
+                }
+            }
+        }
+        finished
+    }
+
+    fn apply(&mut self, mark: ShellMark) -> Option<CapturedCommand> {
+        match mark {
+            ShellMark::PromptStart => {
+                // A new prompt without `D` still ends the previous command
+                let unfinished = (self.phase == Phase::Output).then(|| self.finish(None));
+                self.phase = Phase::Prompt;
+                unfinished
+            }
+            ShellMark::CommandStart => {
+                self.phase = Phase::Input;
+                self.input.clear();
+                None
+            }
+            ShellMark::CommandExecuted => {
+                self.command_line = match self.explicit_line.take() {
+                    Some(line) => line,
+                    None => strip_escapes(&String::from_utf8_lossy(&self.input))
+                        .trim()
+                        .to_string(),
+                };
+                self.input.clear();
+                self.output.clear();
+                self.started_at = Utc::now();
+                self.phase = Phase::Output;
+                None
+            }
+            ShellMark::CommandFinished { exit_code } => {
+                // `D` straight after a prompt means nothing ran (empty input)
+                let command = (self.phase == Phase::Output).then(|| self.finish(exit_code));
+                self.phase = Phase::Unknown;
+                command
+            }
+            ShellMark::CommandLine { command_line } => {
+                self.explicit_line = Some(command_line);
+                None
+            }
+            ShellMark::Cwd { cwd } => {
+                self.cwd = Some(cwd);
+                None
+            }
+        }
+    }
+
+    fn finish(&mut self, exit_code: Option<i32>) -> CapturedCommand {
+        CapturedCommand {
+            command_line: std::mem::take(&mut self.command_line),
+            cwd: self.cwd.clone(),
+            output: std::mem::take(&mut self.output),
+            exit_code,
+            started_at: self.started_at,
+            ended_at: Utc::now(),
+            host: None,
+        }
+    }
+}
+
+/// Segment a complete captured stream (e.g. a recorded transcript)
+pub fn segment_transcript(data: &[u8]) -> Vec<CapturedCommand> {
+    let mut parser = OscParser::new();
+    let mut segmenter = CommandSegmenter::new();
+    segmenter.push(parser.feed(data))
+}
+
+impl MemoryStore {
+    /// Store a command delimited by shell integration marks, with its output
+    /// as a single stdout chunk. Returns the command id.
+    pub async fn store_captured_command(
+        &self,
+        session_id: &str,
+        captured: CapturedCommand,
+        default_cwd: &str,
+    ) -> Result<String> {
+        let words = shlex::split(&captured.command_line).unwrap_or_else(|| {
+            captured
+                .command_line
+                .split_whitespace()
+                .map(String::from)
+                .collect()
+        });
+        let (program, args) = words
+            .split_first()
+            .map(|(p, a)| (p.clone(), a.to_vec()))
+            .unwrap_or_default();
+
+        let mut command = Command::new(
+            session_id.to_string(),
+            program,
+            args,
+            captured.cwd.unwrap_or_else(|| default_cwd.to_string()),
+        );
+        command.started_at = captured.started_at;
+        command.ended_at = Some(captured.ended_at);
+        command.duration_ms =
+            Some((captured.ended_at - captured.started_at).num_milliseconds() as u64);
+        command.exit_code = captured.exit_code;
+        command.success = captured.exit_code == Some(0);
+        match &captured.host {
+            // The cwd is on the remote host, not in a local checkout
+            Some(host) => command.tags.push(format!("host:{}", host)),
+            None => command.git_commit = git_head(Path::new(&command.cwd)),
+        }
+        let command_id = command.id.clone();
+
+        let mut outputs = Vec::new();
+        if !captured.output.is_empty() {
+            outputs.push(Output::new(
+                command_id.clone(),
+                "stdout".to_string(),
+                0,
+                captured.output,
+            ));
+        }
+        let error = self
+            .store_finished_command(command.clone(), outputs)
+            .await?;
+        self.track_classified_run(&command, error.as_ref()).await?;
+        self.track_flaky_run(&command).await?;
+        Ok(command_id)
+    }
+}
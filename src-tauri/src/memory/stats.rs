@@ -0,0 +1,387 @@
+// Incremental stats counters
+// Per-day command time counters keyed by project, tag, and command, so reports read a
+// handful of day buckets instead of scanning every command record
+
+use crate::memory::api::MemoryStore;
+use crate::memory::backend::WriteBatch;
+use crate::memory::intent::INTENT_TAG_PREFIX;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::Mutex;
+use ts_rs::TS;
+
+/// Serializes read-modify-write updates of day buckets within this process
+static STATS_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Counter {
+    pub count: u64,
+    pub failures: u64,
+    pub total_ms: u64,
+}
+
+impl Counter {
+    fn add(&mut self, other: &Counter) {
+        self.count += other.count;
+        self.failures += other.failures;
+        self.total_ms += other.total_ms;
+    }
+}
+
+/// Counters for one UTC day, each keyed by group then command name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DayStats {
+    pub date: String,
+    pub by_command: HashMap<String, Counter>,
+    pub by_project: HashMap<String, HashMap<String, Counter>>,
+    pub by_tag: HashMap<String, HashMap<String, Counter>>,
+}
+
+/// Grouping for [`MemoryStore::time_report`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum TimeGroupBy {
+    Project,
+    Tag,
+    /// `intent:` tags (build, test, deploy, ...)
+    Intent,
+    Command,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CommandTime {
+    pub command: String,
+    pub count: u64,
+    pub failures: u64,
+    pub total_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TimeGroup {
+    pub key: String,
+    pub count: u64,
+    pub total_ms: u64,
+    pub top_commands: Vec<CommandTime>,
+}
+
+/// Where command time went over a date range
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TimeReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub group_by: TimeGroupBy,
+    pub total_ms: u64,
+    pub groups: Vec<TimeGroup>,
+}
+
+impl TimeReport {
+    /// Human-readable highlights, e.g. for a digest
+    pub fn summary_lines(&self, max: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for group in &self.groups {
+            let Some(top) = group.top_commands.first() else {
+                continue;
+            };
+            let scope = match self.group_by {
+                TimeGroupBy::Project => format!(" in project {}", group.key),
+                TimeGroupBy::Tag => format!(" in #{}", group.key),
+                TimeGroupBy::Intent => format!(" on {} work", group.key),
+                TimeGroupBy::Command => String::new(),
+            };
+            lines.push(format!(
+                "You spent {} waiting on `{}`{} ({} runs)",
+                format_duration_ms(top.total_ms),
+                top.command,
+                scope,
+                top.count
+            ));
+            if lines.len() >= max {
+                break;
+            }
+        }
+        lines
+    }
+}
+
+/// "3h 12m", "12m 5s", "850ms"
+pub fn format_duration_ms(ms: u64) -> String {
+    let secs = ms / 1000;
+    match (secs / 3600, (secs % 3600) / 60, secs % 60) {
+        (0, 0, 0) => format!("{}ms", ms),
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+fn day_key(date: NaiveDate) -> String {
+    RecordKind::DayStats.key(&date.format("%Y-%m-%d").to_string())
+}
+
+/// Project of a command: a `project:` tag on the command or its session
+fn project_of(tags: &[String]) -> Option<String> {
+    tags.iter()
+        .find_map(|t| t.strip_prefix("project:"))
+        .map(|p| p.to_string())
+}
+
+/// What one finished command adds to its day's counters
+struct StatsEntry {
+    date: NaiveDate,
+    command: String,
+    project: Option<String>,
+    tags: Vec<String>,
+    delta: Counter,
+}
+
+impl DayStats {
+    fn fold(&mut self, entry: &StatsEntry) {
+        let name = &entry.command;
+        self.by_command
+            .entry(name.clone())
+            .or_default()
+            .add(&entry.delta);
+        if let Some(project) = &entry.project {
+            self.by_project
+                .entry(project.clone())
+                .or_default()
+                .entry(name.clone())
+                .or_default()
+                .add(&entry.delta);
+        }
+        for tag in &entry.tags {
+            self.by_tag
+                .entry(tag.clone())
+                .or_default()
+                .entry(name.clone())
+                .or_default()
+                .add(&entry.delta);
+        }
+    }
+}
+
+impl MemoryStore {
+    /// What `command` adds to its day's counters; `None` while it runs
+    async fn stats_entry(&self, command: &Command) -> Result<Option<StatsEntry>> {
+        let Some(duration_ms) = command.awake_ms() else {
+            return Ok(None);
+        };
+        let session_tags = self
+            .read_value(&RecordKind::Session.key(&command.session_id))
+            .await?
+            .and_then(|v| serde_json::from_value::<Session>(v).ok())
+            .map(|s| s.tags)
+            .unwrap_or_default();
+        let tags: Vec<String> = command
+            .tags
+            .iter()
+            .chain(session_tags.iter())
+            .filter(|t| !t.starts_with("project:"))
+            .cloned()
+            .collect();
+        Ok(Some(StatsEntry {
+            date: command.ended_at.unwrap_or(command.started_at).date_naive(),
+            command: command.command.clone(),
+            project: project_of(&command.tags).or_else(|| project_of(&session_tags)),
+            tags,
+            delta: Counter {
+                count: 1,
+                failures: u64::from(!command.success),
+                total_ms: duration_ms,
+            },
+        }))
+    }
+
+    /// The stored counters of `date`, or empty ones
+    async fn day_stats(&self, date: NaiveDate) -> Result<DayStats> {
+        Ok(self
+            .read_value(&day_key(date))
+            .await?
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_else(|| DayStats {
+                date: date.to_string(),
+                ..Default::default()
+            }))
+    }
+
+    /// Fold a finished command into its day's counters.
+    ///
+    /// Call once per command, when its duration is first known. Time the
+    /// machine slept while it ran is not counted.
+    pub async fn record_command_stats(&self, command: &Command) -> Result<()> {
+        let Some(entry) = self.stats_entry(command).await? else {
+            return Ok(());
+        };
+        let _guard = STATS_LOCK.lock().await;
+        let mut day = self.day_stats(entry.date).await?;
+        day.fold(&entry);
+        self.write_value(&day_key(entry.date), serde_json::to_value(&day)?)
+            .await
+    }
+
+    /// Fold `commands` into their days' counters and commit the result with
+    /// `batch`, so the counters and whatever else `batch` holds land
+    /// together. Returns the number of commands folded in.
+    pub(crate) async fn commit_command_stats(
+        &self,
+        commands: &[Command],
+        mut batch: WriteBatch,
+    ) -> Result<usize> {
+        let mut entries = Vec::new();
+        for command in commands {
+            entries.extend(self.stats_entry(command).await?);
+        }
+        let _guard = STATS_LOCK.lock().await;
+        let mut days: BTreeMap<NaiveDate, DayStats> = BTreeMap::new();
+        for entry in &entries {
+            if !days.contains_key(&entry.date) {
+                days.insert(entry.date, self.day_stats(entry.date).await?);
+            }
+            days.get_mut(&entry.date).unwrap().fold(entry);
+        }
+        for (date, day) in days {
+            batch.put(
+                day_key(date),
+                self.encrypt_value(serde_json::to_value(&day)?).await?,
+            );
+        }
+        self.commit(batch).await?;
+        Ok(entries.len())
+    }
+
+    /// Recompute every day's counters from the stored commands. Returns the
+    /// number of commands folded in.
+    pub async fn rebuild_command_stats(&self) -> Result<usize> {
+        for key in self.client.list(RecordKind::DayStats.prefix()).await? {
+            self.client.delete(&key).await?;
+        }
+        let mut folded = 0;
+        for key in self.client.list(RecordKind::Command.prefix()).await? {
+            let Some(command) = self
+                .read_value(&key)
+                .await?
+                .and_then(|v| serde_json::from_value::<Command>(v).ok())
+            else {
+                continue;
+            };
+            if command.duration_ms.is_some() {
+                self.record_command_stats(&command).await?;
+                folded += 1;
+            }
+        }
+        Ok(folded)
+    }
+
+    /// Command time between two dates (inclusive), grouped and sorted by total time
+    pub async fn time_report(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        group_by: TimeGroupBy,
+        top_commands: usize,
+    ) -> Result<TimeReport> {
+        let mut groups: BTreeMap<String, HashMap<String, Counter>> = BTreeMap::new();
+        let mut date = from;
+        while date <= to {
+            if let Some(value) = self.read_value(&day_key(date)).await? {
+                if let Ok(day) = serde_json::from_value::<DayStats>(value) {
+                    let buckets: Vec<(String, HashMap<String, Counter>)> = match group_by {
+                        TimeGroupBy::Project => day.by_project.into_iter().collect(),
+                        TimeGroupBy::Tag => day.by_tag.into_iter().collect(),
+                        TimeGroupBy::Intent => day
+                            .by_tag
+                            .into_iter()
+                            .filter_map(|(tag, commands)| {
+                                tag.strip_prefix(INTENT_TAG_PREFIX)
+                                    .map(|intent| (intent.to_string(), commands))
+                            })
+                            .collect(),
+                        TimeGroupBy::Command => day
+                            .by_command
+                            .into_iter()
+                            .map(|(command, counter)| {
+                                (command.clone(), HashMap::from([(command, counter)]))
+                            })
+                            .collect(),
+                    };
+                    for (key, commands) in buckets {
+                        let group = groups.entry(key).or_default();
+                        for (command, counter) in commands {
+                            group.entry(command).or_default().add(&counter);
+                        }
+                    }
+                }
+            }
+            date += ChronoDuration::days(1);
+        }
+
+        let mut groups: Vec<TimeGroup> = groups
+            .into_iter()
+            .map(|(key, commands)| {
+                let mut commands: Vec<CommandTime> = commands
+                    .into_iter()
+                    .map(|(command, c)| CommandTime {
+                        command,
+                        count: c.count,
+                        failures: c.failures,
+                        total_ms: c.total_ms,
+                    })
+                    .collect();
+                commands.sort_by_key(|c| std::cmp::Reverse(c.total_ms));
+                let total_ms = commands.iter().map(|c| c.total_ms).sum();
+                let count = commands.iter().map(|c| c.count).sum();
+                commands.truncate(top_commands);
+                TimeGroup {
+                    key,
+                    count,
+                    total_ms,
+                    top_commands: commands,
+                }
+            })
+            .collect();
+        groups.sort_by_key(|g| std::cmp::Reverse(g.total_ms));
+
+        // Tags overlap, so only command/project groups sum to a meaningful total
+        let total_ms = match group_by {
+            TimeGroupBy::Tag | TimeGroupBy::Intent => 0,
+            _ => groups.iter().map(|g| g.total_ms).sum(),
+        };
+        Ok(TimeReport {
+            from,
+            to,
+            group_by,
+            total_ms,
+            groups,
+        })
+    }
+}
+
+/// Resolve a report range: "today", "week", "month", "<n>d", or "YYYY-MM-DD..YYYY-MM-DD"
+pub fn parse_range(range: &str, now: DateTime<Utc>) -> Result<(NaiveDate, NaiveDate)> {
+    let today = now.date_naive();
+    let range = range.trim();
+    let from = match range {
+        "today" => today,
+        "week" => today - ChronoDuration::days(6),
+        "month" => today - ChronoDuration::days(29),
+        _ => {
+            if let Some((from, to)) = range.split_once("..") {
+                return Ok((from.trim().parse()?, to.trim().parse()?));
+            }
+            let days: i64 = range
+                .strip_suffix('d')
+                .and_then(|d| d.parse().ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid report range: {}", range))?;
+            today - ChronoDuration::days(days.saturating_sub(1))
+        }
+    };
+    Ok((from, today))
+}
@@ -0,0 +1,487 @@
+// Session and command tagging
+// Manual tag CRUD, rule-based auto-tags, and tag filters for listing APIs
+
+use crate::memory::api::MemoryStore;
+use crate::memory::intent::{IntentClassifier, IntentConfig};
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use ts_rs::TS;
+
+/// Record types that can carry tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum TagTarget {
+    Session,
+    Command,
+}
+
+impl TagTarget {
+    fn key(self, id: &str) -> String {
+        match self {
+            TagTarget::Session => RecordKind::Session.key(id),
+            TagTarget::Command => RecordKind::Command.key(id),
+        }
+    }
+}
+
+/// Usage count of a tag across sessions and commands
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TagCount {
+    pub tag: String,
+    pub sessions: u64,
+    pub commands: u64,
+}
+
+/// User-defined auto-tag rule; every pattern that is set must match
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TagMatcher {
+    pub tag: String,
+    /// Regex over the full command line (`command args...`)
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Regex over the working directory
+    #[serde(default)]
+    pub cwd: Option<String>,
+}
+
+/// `[tags]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct AutoTagConfig {
+    pub enabled: bool,
+    /// Tag sessions with `project:<name>` from the git remote of their cwd
+    pub git_project: bool,
+    /// Tag sessions with `lang:<language>` from marker files in their cwd
+    pub languages: bool,
+    pub matchers: Vec<TagMatcher>,
+    /// `intent:<name>` labels for commands
+    pub intents: IntentConfig,
+}
+
+impl Default for AutoTagConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            git_project: true,
+            languages: true,
+            matchers: vec![
+                TagMatcher {
+                    tag: "ci-repro".to_string(),
+                    command: Some(r"^(act|gitlab-runner exec|circleci local)\b".to_string()),
+                    cwd: None,
+                },
+                TagMatcher {
+                    tag: "prod-incident".to_string(),
+                    command: Some(r"(--context[= ]\S*prod|\bprod(uction)?-\S+)".to_string()),
+                    cwd: None,
+                },
+            ],
+            intents: IntentConfig::default(),
+        }
+    }
+}
+
+/// Files whose presence in a directory implies a language
+const LANGUAGE_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("package.json", "javascript"),
+    ("tsconfig.json", "typescript"),
+    ("pyproject.toml", "python"),
+    ("requirements.txt", "python"),
+    ("go.mod", "go"),
+    ("pom.xml", "java"),
+    ("build.gradle", "java"),
+    ("Gemfile", "ruby"),
+    ("mix.exs", "elixir"),
+    ("composer.json", "php"),
+    ("CMakeLists.txt", "cpp"),
+    ("flake.nix", "nix"),
+];
+
+/// Lowercase and trim a tag; `None` for empty or whitespace-containing tags
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.chars().any(char::is_whitespace) {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+/// Merge normalized tags into an existing sorted tag set
+pub(crate) fn merge_tags(existing: &mut Vec<String>, new: impl IntoIterator<Item = String>) {
+    for tag in new.into_iter().filter_map(|t| normalize_tag(&t)) {
+        if !existing.contains(&tag) {
+            existing.push(tag);
+        }
+    }
+    existing.sort();
+}
+
+/// Compiled auto-tag rules
+pub struct AutoTagger {
+    config: AutoTagConfig,
+    matchers: Vec<(String, Option<Regex>, Option<Regex>)>,
+    intents: IntentClassifier,
+}
+
+impl AutoTagger {
+    pub fn new(config: AutoTagConfig) -> Result<Self> {
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>> {
+            pattern
+                .as_deref()
+                .map(|p| Regex::new(p).with_context(|| format!("Invalid tag matcher: {}", p)))
+                .transpose()
+        };
+        let matchers = config
+            .matchers
+            .iter()
+            .map(|m| Ok((m.tag.clone(), compile(&m.command)?, compile(&m.cwd)?)))
+            .collect::<Result<_>>()?;
+        let intents = IntentClassifier::new(config.intents.clone())?;
+        Ok(Self {
+            config,
+            matchers,
+            intents,
+        })
+    }
+
+    /// Tags for a new session: project, languages, and cwd-only matchers
+    pub fn session_tags(&self, session: &Session) -> Vec<String> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let cwd = Path::new(&session.initial_cwd);
+        let mut tags = Vec::new();
+        if self.config.git_project {
+            if let Some(project) = git_project_name(cwd) {
+                tags.push(format!("project:{}", project));
+            }
+        }
+        if self.config.languages {
+            tags.extend(
+                detect_languages(cwd)
+                    .into_iter()
+                    .map(|l| format!("lang:{}", l)),
+            );
+        }
+        for (tag, command, cwd_re) in &self.matchers {
+            if command.is_none()
+                && cwd_re
+                    .as_ref()
+                    .is_some_and(|re| re.is_match(&session.initial_cwd))
+            {
+                tags.push(tag.clone());
+            }
+        }
+        tags
+    }
+
+    /// Tags for a new command: user-defined matchers and intent labels
+    pub fn command_tags(&self, command: &Command) -> Vec<String> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let line = std::iter::once(command.command.as_str())
+            .chain(command.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.matchers
+            .iter()
+            .filter(|(_, command_re, cwd_re)| {
+                command_re.as_ref().is_some_and(|re| re.is_match(&line))
+                    && cwd_re.as_ref().is_none_or(|re| re.is_match(&command.cwd))
+            })
+            .map(|(tag, _, _)| tag.clone())
+            .chain(self.intents.command_tags(command))
+            .collect()
+    }
+}
+
+/// Repository name from the `origin` remote of the git checkout containing `dir`
+pub fn git_project_name(dir: &Path) -> Option<String> {
+    let git_dir = dir
+        .ancestors()
+        .map(|d| d.join(".git"))
+        .find(|d| d.is_dir())?;
+    let config = std::fs::read_to_string(git_dir.join("config")).ok()?;
+
+    let mut in_origin = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_origin = line == r#"[remote "origin"]"#;
+        } else if in_origin {
+            if let Some(url) = line
+                .strip_prefix("url")
+                .map(|r| r.trim_start_matches([' ', '=']))
+            {
+                let name = url
+                    .trim_end_matches('/')
+                    .rsplit(['/', ':'])
+                    .next()?
+                    .trim_end_matches(".git");
+                return normalize_tag(name);
+            }
+        }
+    }
+    // No remote: fall back to the checkout directory name
+    git_dir
+        .parent()?
+        .file_name()
+        .and_then(|n| normalize_tag(&n.to_string_lossy()))
+}
+
+/// Commit checked out in the git checkout containing `dir`, read from
+/// `HEAD` and the refs without running git
+pub fn git_head(dir: &Path) -> Option<String> {
+    let git_dir = dir
+        .ancestors()
+        .map(|d| d.join(".git"))
+        .find(|d| d.is_dir())?;
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let Some(reference) = head.strip_prefix("ref: ") else {
+        // Detached HEAD holds the commit itself
+        return Some(head.to_string()).filter(|h| !h.is_empty());
+    };
+    if let Ok(commit) = std::fs::read_to_string(git_dir.join(reference)) {
+        return Some(commit.trim().to_string());
+    }
+    // Refs moved into `packed-refs` by `git gc`
+    std::fs::read_to_string(git_dir.join("packed-refs"))
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let (commit, name) = line.split_once(' ')?;
+            (name == reference).then(|| commit.to_string())
+        })
+}
+
+/// Branch checked out in the git checkout containing `dir`; `None` on a
+/// detached HEAD
+pub fn git_branch(dir: &Path) -> Option<String> {
+    let git_dir = dir
+        .ancestors()
+        .map(|d| d.join(".git"))
+        .find(|d| d.is_dir())?;
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    head.trim()
+        .strip_prefix("ref: refs/heads/")
+        .map(String::from)
+}
+
+/// Languages implied by marker files directly in `dir`
+pub fn detect_languages(dir: &Path) -> Vec<String> {
+    let mut languages: Vec<String> = LANGUAGE_MARKERS
+        .iter()
+        .filter(|(file, _)| dir.join(file).exists())
+        .map(|(_, language)| language.to_string())
+        .collect();
+    languages.dedup();
+    languages
+}
+
+/// Tags of every session and command, for filtering listings
+pub struct TagIndex {
+    sessions: HashMap<String, Vec<String>>,
+    commands: HashMap<String, (String, Vec<String>)>,
+}
+
+impl TagIndex {
+    /// Whether a session carries all of `tags`
+    pub fn session_matches(&self, session_id: &str, tags: &[String]) -> bool {
+        let own = self.sessions.get(session_id);
+        tags.iter().all(|t| own.is_some_and(|o| o.contains(t)))
+    }
+
+    /// Whether a command, together with its session, carries all of `tags`
+    pub fn command_matches(&self, command_id: &str, tags: &[String]) -> bool {
+        let Some((session_id, own)) = self.commands.get(command_id) else {
+            return tags.is_empty();
+        };
+        let session = self.sessions.get(session_id);
+        tags.iter()
+            .all(|t| own.contains(t) || session.is_some_and(|s| s.contains(t)))
+    }
+}
+
+fn normalize_filter(tags: &[String]) -> Vec<String> {
+    tags.iter().filter_map(|t| normalize_tag(t)).collect()
+}
+
+impl MemoryStore {
+    async fn update_tags(
+        &self,
+        target: TagTarget,
+        id: &str,
+        mut update: impl FnMut(&mut Vec<String>),
+    ) -> Result<Vec<String>> {
+        let key = target.key(id);
+        let mut tags = Vec::new();
+        self.update_value(&key, |value: &mut serde_json::Value| {
+            tags = value
+                .get("tags")
+                .cloned()
+                .map(serde_json::from_value)
+                .transpose()?
+                .unwrap_or_default();
+            update(&mut tags);
+            value["tags"] = serde_json::to_value(&tags)?;
+            Ok(())
+        })
+        .await?
+        .with_context(|| format!("{:?} not found: {}", target, id))?;
+        Ok(tags)
+    }
+
+    /// Add tags to a session or command; returns the resulting tag set
+    pub async fn tag_add(
+        &self,
+        target: TagTarget,
+        id: &str,
+        tags: &[String],
+    ) -> Result<Vec<String>> {
+        let new = tags.to_vec();
+        self.update_tags(target, id, |existing| merge_tags(existing, new.clone()))
+            .await
+    }
+
+    /// Remove tags from a session or command; returns the resulting tag set
+    pub async fn tag_remove(
+        &self,
+        target: TagTarget,
+        id: &str,
+        tags: &[String],
+    ) -> Result<Vec<String>> {
+        let remove = normalize_filter(tags);
+        self.update_tags(target, id, |existing| {
+            existing.retain(|t| !remove.contains(t))
+        })
+        .await
+    }
+
+    /// Tags with usage counts, for one record or across the whole store
+    pub async fn tag_list(&self, target: Option<(TagTarget, &str)>) -> Result<Vec<TagCount>> {
+        let index = self.tag_index().await?;
+        let mut counts: BTreeMap<String, TagCount> = BTreeMap::new();
+        let mut count = |tag: &String, session: bool| {
+            let entry = counts.entry(tag.clone()).or_insert_with(|| TagCount {
+                tag: tag.clone(),
+                ..Default::default()
+            });
+            if session {
+                entry.sessions += 1;
+            } else {
+                entry.commands += 1;
+            }
+        };
+
+        match target {
+            Some((TagTarget::Session, id)) => index
+                .sessions
+                .get(id)
+                .into_iter()
+                .flatten()
+                .for_each(|t| count(t, true)),
+            Some((TagTarget::Command, id)) => index
+                .commands
+                .get(id)
+                .into_iter()
+                .flat_map(|(_, tags)| tags)
+                .for_each(|t| count(t, false)),
+            None => {
+                index
+                    .sessions
+                    .values()
+                    .flatten()
+                    .for_each(|t| count(t, true));
+                index
+                    .commands
+                    .values()
+                    .flat_map(|(_, tags)| tags)
+                    .for_each(|t| count(t, false));
+            }
+        }
+        Ok(counts.into_values().collect())
+    }
+
+    /// Load the tags of all sessions and commands
+    pub async fn tag_index(&self) -> Result<TagIndex> {
+        let mut sessions = HashMap::new();
+        for key in self.client.list(RecordKind::Session.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(session) = serde_json::from_value::<Session>(value) {
+                    sessions.insert(session.id, session.tags);
+                }
+            }
+        }
+        let mut commands = HashMap::new();
+        for key in self.client.list(RecordKind::Command.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(command) = serde_json::from_value::<Command>(value) {
+                    commands.insert(command.id, (command.session_id, command.tags));
+                }
+            }
+        }
+        Ok(TagIndex { sessions, commands })
+    }
+
+    /// [`MemoryStore::list_sessions`] restricted to sessions carrying all `tags`
+    pub async fn list_sessions_tagged(&self, tags: &[String]) -> Result<Vec<Session>> {
+        let tags = normalize_filter(tags);
+        let mut sessions = self.list_sessions().await?;
+        sessions.retain(|s| tags.iter().all(|t| s.tags.contains(t)));
+        Ok(sessions)
+    }
+
+    /// [`MemoryStore::query_recent_errors`] restricted to errors whose command
+    /// or session carries all `tags`
+    pub async fn query_recent_errors_tagged(
+        &self,
+        limit: Option<usize>,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        severity: Option<&str>,
+        tags: &[String],
+    ) -> Result<Vec<Error>> {
+        let tags = normalize_filter(tags);
+        let mut errors = self.query_recent_errors(None, since, severity).await?;
+        if !tags.is_empty() {
+            let index = self.tag_index().await?;
+            errors.retain(|e| index.command_matches(&e.command_id, &tags));
+        }
+        if let Some(limit) = limit {
+            errors.truncate(limit);
+        }
+        Ok(errors)
+    }
+
+    /// [`MemoryStore::list_artifacts`] restricted to artifacts whose command
+    /// or session carries all `tags`
+    pub async fn list_artifacts_tagged(
+        &self,
+        session_id: Option<&str>,
+        command_id: Option<&str>,
+        tags: &[String],
+    ) -> Result<Vec<Artifact>> {
+        let tags = normalize_filter(tags);
+        let mut artifacts = self.list_artifacts(session_id, command_id).await?;
+        if !tags.is_empty() {
+            let index = self.tag_index().await?;
+            artifacts.retain(|a| match (&a.command_id, &a.session_id) {
+                (Some(command_id), _) => index.command_matches(command_id, &tags),
+                (None, Some(session_id)) => index.session_matches(session_id, &tags),
+                (None, None) => false,
+            });
+        }
+        Ok(artifacts)
+    }
+}
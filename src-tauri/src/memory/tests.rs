@@ -34,7 +34,7 @@ mod tests {
 
         // Create a test session
         let session = Session::new("bash".to_string(), "/tmp".to_string());
-        let session_key = format!("memory:session:{}", session.id);
+        let session_key = RecordKind::Session.key(&session.id);
         store
             .client
             .put(&session_key, &serde_json::to_value(&session).unwrap())
@@ -110,7 +110,7 @@ mod tests {
 
         // Test Session roundtrip
         let session = Session::new("zsh".to_string(), "/home/user".to_string());
-        let session_key = format!("memory:session:{}", session.id);
+        let session_key = RecordKind::Session.key(&session.id);
         store
             .client
             .put(&session_key, &serde_json::to_value(&session).unwrap())
@@ -130,7 +130,7 @@ mod tests {
             vec!["-la".to_string()],
             "/home/user".to_string(),
         );
-        let command_key = format!("memory:command:{}", command.id);
+        let command_key = RecordKind::Command.key(&command.id);
         store
             .client
             .put(&command_key, &serde_json::to_value(&command).unwrap())
@@ -189,4 +189,2660 @@ mod tests {
         // Cleanup
         store.wipe_all().await.unwrap();
     }
+
+    // Shell history parsing: timestamps, durations, and multi-line commands
+    #[test]
+    fn test_parse_shell_history() {
+        let bash = history::parse_bash("#1700000000\nls -la\ngit status\n");
+        assert_eq!(bash.len(), 2);
+        assert_eq!(bash[0].timestamp.unwrap().timestamp(), 1700000000);
+        assert!(bash[1].timestamp.is_none());
+
+        let zsh =
+            history::parse_zsh(": 1700000000:3;cargo build\\\n--release\n: 1700000010:0;ls\n");
+        assert_eq!(zsh.len(), 2);
+        assert_eq!(zsh[0].command_line, "cargo build\n--release");
+        assert_eq!(zsh[0].duration_ms, Some(3000));
+
+        let fish = history::parse_fish("- cmd: echo hi\n  when: 1700000000\n- cmd: ls\n");
+        assert_eq!(fish.len(), 2);
+        assert_eq!(fish[0].timestamp.unwrap().timestamp(), 1700000000);
+    }
+
+    // OSC 133/633 marks delimit commands even when split across chunks
+    #[test]
+    fn test_shell_integration_segments_commands() {
+        use crate::memory::shell_integration::*;
+
+        let stream: &[u8] = b"\x1b]633;P;Cwd=/src/app\x07\x1b]133;A\x07$ \x1b]133;B\x07ls -l\r\n\
+            \x1b]633;E;ls -l\\x3b echo\x07\x1b]133;C\x07total 0\r\n\x1b]133;D;2\x07\
+            \x1b]133;A\x07$ \x1b]133;B\x07\x1b]133;D\x07\x1b]133;A\x07$ \x1b]133;B\x07pwd\r\n\x1b]133;C\x07/src/app\r\n";
+
+        let mut parser = OscParser::new();
+        let mut segmenter = CommandSegmenter::new();
+        let mut commands = Vec::new();
+        for chunk in stream.chunks(7) {
+            commands.extend(segmenter.push(parser.feed(chunk)));
+        }
+        commands.extend(segmenter.push(parser.feed(b"\x1b]133;D;0\x1b\\")));
+
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].command_line, "ls -l; echo");
+        assert_eq!(commands[0].exit_code, Some(2));
+        assert_eq!(commands[0].cwd.as_deref(), Some("/src/app"));
+        assert_eq!(commands[0].output, b"total 0\r\n");
+        assert_eq!(commands[1].command_line, "pwd");
+        assert_eq!(commands[1].exit_code, Some(0));
+
+        // Unknown OSC sequences are left in the text
+        let segments = OscParser::new().feed(b"a\x1b]0;title\x07b");
+        assert_eq!(
+            segments,
+            vec![Segment::Text(b"a\x1b]0;title\x07b".to_vec())]
+        );
+    }
+
+    // Custom rules run after the built-ins, per stage, and report what they changed
+    #[test]
+    fn test_scrub_rules_and_fixtures() {
+        use crate::memory::scrub::*;
+
+        let config: ScrubConfig = toml::from_str(
+            r#"
+            [[rules]]
+            name = "internal-host"
+            pattern = '\b[a-z0-9-]+\.corp\.example\.com\b'
+            replacement = "<host>"
+            apply_to = ["display"]
+            fixtures = [
+                { input = "ssh db1.corp.example.com", expected = "ssh <host>" },
+                { input = "ssh example.com", expected = "ssh <host>" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let scrubber = Scrubber::new(&config).unwrap();
+        let sample = "password=hunter2hunter2 on db1.corp.example.com";
+
+        let (captured, hits) = scrubber.scrub(sample, ScrubStage::Capture);
+        assert_eq!(captured, "password=[REDACTED] on db1.corp.example.com");
+        assert_eq!(
+            hits,
+            vec![ScrubHit {
+                rule: "builtin:key-value".to_string(),
+                count: 1
+            }]
+        );
+        let (displayed, hits) = scrubber.scrub(sample, ScrubStage::Display);
+        assert_eq!(displayed, "password=[REDACTED] on <host>");
+        assert_eq!(hits.len(), 2);
+
+        let preview = scrubber.preview(sample, ScrubStage::Display);
+        let host = preview
+            .rules
+            .iter()
+            .find(|r| r.rule == "internal-host")
+            .unwrap();
+        assert_eq!(host.matches, vec!["db1.corp.example.com"]);
+        let passed: Vec<bool> = preview.fixtures.iter().map(|f| f.passed).collect();
+        assert_eq!(passed, vec![true, false]);
+
+        let invalid = ScrubConfig {
+            rules: vec![ScrubRule {
+                pattern: "(".to_string(),
+                ..config.rules[0].clone()
+            }],
+            ..Default::default()
+        };
+        assert!(Scrubber::new(&invalid).is_err());
+    }
+
+    // Built-in intents, user rules, and debugging-loop detection from intent tags
+    #[test]
+    fn test_intent_labels_and_activity() {
+        use crate::memory::intent::*;
+
+        let args = |a: &str| a.split_whitespace().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            builtin_intent("cargo", &args("+nightly test -p core")),
+            Some("test")
+        );
+        assert_eq!(
+            builtin_intent("npm", &args("run build:prod")),
+            Some("build")
+        );
+        assert_eq!(builtin_intent("/usr/bin/git", &args("status")), Some("vcs"));
+        assert_eq!(builtin_intent("kubectl", &args("get pods")), None);
+        assert_eq!(
+            builtin_intent("kubectl", &args("apply -f x.yaml")),
+            Some("deploy")
+        );
+
+        let classifier = IntentClassifier::new(IntentConfig {
+            rules: vec![IntentRule {
+                intent: "db-migrate".to_string(),
+                command: Some("^sqlx migrate".to_string()),
+                cwd: None,
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            classifier.classify("sqlx", &args("migrate run"), "/src"),
+            vec!["db-migrate"]
+        );
+
+        let run = |line: &str, success: bool| {
+            let mut parts = line.split_whitespace();
+            let mut command = Command::new(
+                "s".to_string(),
+                parts.next().unwrap().to_string(),
+                parts.map(String::from).collect(),
+                "/src".to_string(),
+            );
+            command.success = success;
+            command.tags = classifier.command_tags(&command);
+            command
+        };
+        let commands = vec![
+            run("cargo test", false),
+            run("vim src/lib.rs", true),
+            run("cargo test", false),
+            run("git diff", true),
+            run("cargo build", false),
+            run("cargo test", false),
+        ];
+        let activity = activity(&commands);
+        assert!(activity.debugging_loop);
+        assert_eq!(activity.dominant_intent.as_deref(), Some("test"));
+
+        let fixed = [&commands[..], &[run("cargo test", true)]].concat();
+        assert!(!crate::memory::intent::activity(&fixed).debugging_loop);
+    }
+
+    // Line-index sidecars let ranges and tails be read across chunks, compressed or not
+    #[test]
+    fn test_output_line_index_ranges() {
+        use crate::memory::pages::{chunk_span, index_output, read_lines, total_lines};
+        use std::io::Write;
+
+        let text: String = (0..1000).map(|i| format!("line {}\r\n", i)).collect();
+        let (first, second) = text.split_at(text.len() / 2 + 3);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(second.as_bytes()).unwrap();
+        let mut compressed = Output::new("c".to_string(), "stdout".to_string(), 1, Vec::new());
+        compressed.content = encoder.finish().unwrap();
+        compressed.compressed = true;
+        let mut unterminated = Output::new("c".to_string(), "stdout".to_string(), 2, Vec::new());
+        unterminated.content = b"tail".to_vec();
+        let outputs = [
+            Output::new(
+                "c".to_string(),
+                "stdout".to_string(),
+                0,
+                first.as_bytes().to_vec(),
+            ),
+            compressed,
+            unterminated,
+        ];
+        let indexes: Vec<_> = outputs.iter().map(|o| index_output(o).unwrap()).collect();
+        assert_eq!(total_lines(&indexes), 1001);
+
+        let range = |offset: usize, limit: usize| {
+            let Some((span, skip)) = chunk_span(&indexes, offset, limit) else {
+                return Vec::new();
+            };
+            let chunks: Vec<_> = indexes[span.clone()].iter().zip(&outputs[span]).collect();
+            read_lines(&chunks, skip, limit).unwrap()
+        };
+        assert_eq!(range(0, 2), vec!["line 0", "line 1"]);
+        // Line 505 is split between its \r and \n across the first two chunks
+        assert_eq!(range(504, 3), vec!["line 504", "line 505", "line 506"]);
+        assert_eq!(range(700, 1), vec!["line 700"]);
+        assert_eq!(range(998, 10), vec!["line 998", "line 999", "tail"]);
+        assert!(range(1001, 5).is_empty());
+    }
+
+    // Warm chunks read back through zstd; cold summaries keep both ends of the output
+    #[test]
+    fn test_output_retention_tiers() {
+        use crate::memory::pages::{chunk_text, index_output};
+        use crate::memory::retention::*;
+
+        let config = RetentionConfig {
+            cold_after_days: 30,
+            ..Default::default()
+        };
+        assert_eq!(config.tier_for(ChronoDuration::days(1)), OutputTier::Hot);
+        assert_eq!(config.tier_for(ChronoDuration::days(8)), OutputTier::Warm);
+        assert_eq!(config.tier_for(ChronoDuration::days(31)), OutputTier::Cold);
+        assert_eq!(
+            RetentionConfig::default().tier_for(ChronoDuration::days(400)),
+            OutputTier::Warm
+        );
+
+        let text: String = (0..10).map(|i| format!("line {}\n", i)).collect();
+        let mut warm = Output::new("c".to_string(), "stdout".to_string(), 0, Vec::new());
+        warm.content = zstd::encode_all(text.as_bytes(), 19).unwrap();
+        warm.compressed = true;
+        warm.tier = OutputTier::Warm;
+        assert_eq!(chunk_text(&warm).unwrap(), text);
+        assert_eq!(index_output(&warm).unwrap().newlines, 10);
+
+        let summary = summarize(&text, 2).unwrap();
+        assert_eq!(
+            summary,
+            "line 0\nline 1\n[... 6 lines removed by output retention ...]\nline 8\nline 9\n"
+        );
+        assert!(summarize(&text, 5).is_none());
+    }
+
+    // Chunks read back with the codec they were tagged with, whatever is configured now
+    #[tokio::test]
+    async fn test_output_codecs_tag_chunks() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::codec::sample_output;
+        use crate::memory::encoding::EncodingConfig;
+        use crate::memory::pages::chunk_text;
+
+        let sample = sample_output(64 * 1024);
+        for id in CodecId::ALL {
+            let codec = id.codec();
+            assert_eq!(codec.id(), id);
+            let compressed = codec.compress(&sample).unwrap();
+            assert_eq!(codec.decompress(&compressed).unwrap(), sample);
+        }
+
+        let text: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let mut stored = Vec::new();
+        for id in [CodecId::Lz4, CodecId::Gzip] {
+            let store = MemoryStore::new(InMemoryBackend::new())
+                .await
+                .unwrap()
+                .with_output_encoding(EncodingConfig {
+                    codec: id,
+                    ..Default::default()
+                });
+            let mut output = Output::new(
+                "c".to_string(),
+                "stdout".to_string(),
+                0,
+                text.clone().into_bytes(),
+            );
+            store.store_output(&mut output, true).await.unwrap();
+            let value = store
+                .read_value(&RecordKind::Output.key(&output.id))
+                .await
+                .unwrap()
+                .unwrap();
+            stored.push(serde_json::from_value::<Output>(value).unwrap());
+        }
+        assert_eq!(stored[0].codec, Some(CodecId::Lz4));
+        assert_eq!(stored[1].codec, Some(CodecId::Gzip));
+        for output in &stored {
+            assert!(output.compressed);
+            assert_eq!(chunk_text(output).unwrap(), text);
+        }
+
+        // Untagged hot chunks predate codecs and are gzip
+        let mut legacy = stored[1].clone();
+        legacy.codec = None;
+        assert_eq!(legacy.codec(), CodecId::Gzip);
+        assert_eq!(chunk_text(&legacy).unwrap(), text);
+    }
+
+    // Replaying recorded impressions shows which weights would have surfaced what users accepted
+    #[test]
+    fn test_rank_eval_replays_impressions() {
+        use crate::memory::ranking::*;
+
+        let mut tip = Suggestion::new(
+            "tip".to_string(),
+            "high".to_string(),
+            0.5,
+            "Tip".to_string(),
+            String::new(),
+        );
+        tip.id = "tip".to_string();
+        let mut fix = Suggestion::new(
+            "command".to_string(),
+            "low".to_string(),
+            0.5,
+            "Fix".to_string(),
+            String::new(),
+        );
+        fix.id = "fix".to_string();
+        fix.command = Some("cargo clean".to_string());
+
+        let current = RankingConfig::default();
+        let ranked = rank(vec![fix, tip], &current, None, None);
+        assert_eq!(ranked[0].suggestion.id, "tip");
+        assert_eq!(ranked[0].contributions["priority"], 0.5);
+
+        let impression = SuggestionImpression {
+            id: "i".to_string(),
+            surface: "ipc".to_string(),
+            surfaced_at: Utc::now(),
+            shown: 1,
+            candidates: ranked
+                .iter()
+                .map(|r| ImpressionCandidate {
+                    suggestion_id: r.suggestion.id.clone(),
+                    score: r.score,
+                    features: r.features.clone(),
+                    contributions: r.contributions.clone(),
+                })
+                .collect(),
+            accepted: vec!["fix".to_string()],
+            dismissed: vec!["tip".to_string()],
+        };
+        let mut actionable = RankingConfig::default();
+        actionable.weights.insert("actionable".to_string(), 1.0);
+        let report = rank_eval(
+            &[impression],
+            &current,
+            &[("actionable".to_string(), actionable)],
+        );
+        assert_eq!(report.impressions, 1);
+        assert_eq!(report.results[0].acceptance_rate, 0.0);
+        assert_eq!(report.results[1].acceptance_rate, 1.0);
+        assert_eq!(report.results[1].acceptance_delta, 1.0);
+        assert_eq!(report.results[1].dismissed_rate, 0.0);
+    }
+
+    // File locations in compiler output become LSP diagnostics with 0-based ranges
+    #[test]
+    fn test_error_locations_to_diagnostics() {
+        use crate::memory::diagnostics::{error_locations, scan_locations};
+
+        let locations = scan_locations(
+            "error[E0308]: mismatched types\n  --> src/main.rs:4:18\n\
+             main.go:12:3: undefined: x\n\
+             see https://example.com:443/docs\n\
+             File \"app/models.py\", line 7, in <module>",
+        );
+        let found: Vec<_> = locations
+            .iter()
+            .map(|l| (l.file.as_str(), l.line, l.column))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("src/main.rs", 4, Some(18)),
+                ("main.go", 12, Some(3)),
+                ("app/models.py", 7, None),
+            ]
+        );
+        assert_eq!(locations[0].code.as_deref(), Some("E0308"));
+        assert_eq!(locations[0].message.as_deref(), Some("mismatched types"));
+        assert_eq!(locations[1].message.as_deref(), Some("undefined: x"));
+
+        // Structured locations from a parser win over scanning the text
+        let mut error = Error::new(
+            "c".to_string(),
+            "s".to_string(),
+            "stderr".to_string(),
+            "high".to_string(),
+            "src/lib.rs:1:1".to_string(),
+        );
+        error.context = serde_json::json!({
+            "locations": [{ "file": "tests/a.rs", "line": 3, "severity": "warning" }]
+        });
+        let locations = error_locations(&error);
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].file, "tests/a.rs");
+    }
+
+    // Each parser pulls test names and locations out of its tool's output
+    #[test]
+    fn test_parse_test_and_compiler_output() {
+        use crate::memory::parsers::{failure_error, parse_output};
+
+        let cargo = "running 2 tests\n\
+            test math::adds ... ok\n\
+            test math::divides ... FAILED\n\
+            \n\
+            ---- math::divides stdout ----\n\
+            thread 'math::divides' panicked at src/math.rs:12:9:\n\
+            attempt to divide by zero\n";
+        let parsed = parse_output("cargo", &["test".to_string()], cargo).unwrap();
+        assert_eq!(parsed.parser, "cargo");
+        assert_eq!(parsed.failed_tests(), vec!["math::divides"]);
+        assert_eq!(parsed.failures[0].file.as_deref(), Some("src/math.rs"));
+        assert_eq!(parsed.failures[0].line, Some(12));
+        assert_eq!(parsed.failures[0].message, "attempt to divide by zero");
+
+        let json = r#"{"reason":"compiler-message","message":{"message":"mismatched types","level":"error","code":{"code":"E0308"},"spans":[{"file_name":"src/main.rs","line_start":4,"column_start":18,"is_primary":true}]}}"#;
+        let args = vec!["build".to_string(), "--message-format=json".to_string()];
+        let parsed = parse_output("cargo", &args, json).unwrap();
+        assert_eq!(parsed.parser, "cargo-json");
+        assert_eq!(parsed.failures[0].code.as_deref(), Some("E0308"));
+        assert_eq!(parsed.failures[0].column, Some(18));
+
+        let pytest = "____________ TestCart.test_total ____________\n\
+            \n\
+            >       assert cart.total() == 10\n\
+            E       assert 9 == 10\n\
+            \n\
+            tests/test_cart.py:14: AssertionError\n\
+            ===== short test summary info =====\n\
+            FAILED tests/test_cart.py::TestCart::test_total - assert 9 == 10\n";
+        let parsed = parse_output("pytest", &[], pytest).unwrap();
+        assert_eq!(
+            parsed.failed_tests(),
+            vec!["tests/test_cart.py::TestCart::test_total"]
+        );
+        assert_eq!(parsed.failures[0].line, Some(14));
+        assert_eq!(parsed.failures[0].message, "assert 9 == 10");
+
+        // Found through `npm test` by sniffing the output
+        let jest = "FAIL src/sum.test.js\n\
+            \u{20} ● sum › adds numbers\n\
+            \n\
+            \u{20}   expect(received).toBe(expected)\n\
+            \n\
+            \u{20}     at Object.<anonymous> (src/sum.test.js:5:17)\n";
+        let parsed = parse_output("npm", &["test".to_string()], jest).unwrap();
+        assert_eq!(parsed.parser, "jest");
+        assert_eq!(parsed.failed_tests(), vec!["sum › adds numbers"]);
+        assert_eq!(parsed.failures[0].line, Some(5));
+
+        let go = "--- FAIL: TestParse (0.00s)\n\
+            \u{20}   parse_test.go:21: got 3, want 4\n\
+            FAIL\texample.com/parse\t0.01s\n";
+        let parsed = parse_output("go", &["test".to_string(), "./...".to_string()], go).unwrap();
+        assert_eq!(parsed.failed_tests(), vec!["TestParse"]);
+        assert_eq!(parsed.failures[0].file.as_deref(), Some("parse_test.go"));
+        assert_eq!(parsed.failures[0].message, "got 3, want 4");
+
+        let mut command = Command::new(
+            "s".to_string(),
+            "go".to_string(),
+            vec!["test".to_string()],
+            "/src/parse".to_string(),
+        );
+        command.exit_code = Some(1);
+        let error = failure_error(&command, &parsed, go);
+        assert_eq!(error.error_type, "test_failure");
+        assert_eq!(error.message, "1 test failed: TestParse");
+        assert_eq!(error.context["locations"][0]["line"], 21);
+        assert_eq!(error.context["failures"][0]["test_name"], "TestParse");
+        assert!(parse_output("ls", &[], "total 0\n").is_none());
+    }
+
+    // Failed tests narrow to a re-run command that keeps the original scope
+    #[test]
+    fn test_rerun_failed_tests_command() {
+        use crate::memory::parsers::parse_output;
+        use crate::memory::rerun::rerun_command;
+
+        let run = |program: &str, args: &[&str], output: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let command = Command::new(
+                "s".to_string(),
+                program.to_string(),
+                args.clone(),
+                "/src".to_string(),
+            );
+            let parsed = parse_output(program, &args, output).unwrap();
+            rerun_command(&command, &parsed)
+        };
+
+        let cargo = "test math::adds ... FAILED\ntest math::divides ... FAILED\n";
+        assert_eq!(
+            run("cargo", &["test", "-p", "calc", "math"], cargo).as_deref(),
+            Some("cargo test -p calc -- math::adds math::divides")
+        );
+        assert_eq!(
+            run("cargo", &["test"], "test math::adds ... FAILED\n").as_deref(),
+            Some("cargo test math::adds")
+        );
+        assert_eq!(
+            run(
+                "python",
+                &["-m", "pytest", "tests"],
+                "FAILED tests/test_cart.py::test_total - assert 9 == 10\n"
+            )
+            .as_deref(),
+            Some("python -m pytest tests/test_cart.py::test_total -x")
+        );
+        assert_eq!(
+            run(
+                "npx",
+                &["jest"],
+                "FAIL src/sum.test.js\n  ● sum › adds numbers\n"
+            )
+            .as_deref(),
+            Some("npx jest src/sum.test.js -t '^(sum adds numbers)$'")
+        );
+        assert_eq!(
+            run(
+                "go",
+                &["test", "-run", "Parse", "./parse/..."],
+                "--- FAIL: TestParse/empty (0.00s)\n"
+            )
+            .as_deref(),
+            Some("go test -run '^(TestParse)$' ./parse/...")
+        );
+        // Compile errors have nothing to narrow to
+        let json = r#"{"reason":"compiler-message","message":{"message":"mismatched types","level":"error","spans":[{"file_name":"src/main.rs","line_start":4,"column_start":18,"is_primary":true}]}}"#;
+        assert!(run("cargo", &["test", "--message-format=json"], json).is_none());
+    }
+
+    fn formatted_suggestion() -> Suggestion {
+        Suggestion {
+            id: "3f2a".to_string(),
+            suggestion_type: "command".to_string(),
+            priority: "high".to_string(),
+            rank: 0.8,
+            title: "Re-run only the 2 failed tests in #parse".to_string(),
+            description: "parse::empty,\n parse::nested\x1b[2J".to_string(),
+            command: Some("cargo test".to_string()),
+            args: Some(vec!["parse::empty".to_string(), "it's nested".to_string()]),
+            context: serde_json::json!({}),
+            created_at: Utc::now(),
+            dismissed: false,
+            applied: false,
+        }
+    }
+
+    #[test]
+    fn test_suggestion_format_snapshots() {
+        let suggestion = formatted_suggestion();
+        let snapshots = [
+            (
+                SuggestionFormat::Rich,
+                include_str!("snapshots/suggestion.rich.json"),
+            ),
+            (
+                SuggestionFormat::Terminal,
+                include_str!("snapshots/suggestion.terminal.txt"),
+            ),
+            (
+                SuggestionFormat::Tmux,
+                include_str!("snapshots/suggestion.tmux.txt"),
+            ),
+            (
+                SuggestionFormat::Markdown,
+                include_str!("snapshots/suggestion.md"),
+            ),
+            (
+                SuggestionFormat::Plain,
+                include_str!("snapshots/suggestion.plain.txt"),
+            ),
+        ];
+        for (format, expected) in snapshots {
+            // Snapshot files end with a newline for editors
+            let expected = expected.strip_suffix('\n').unwrap_or(expected);
+            assert_eq!(
+                format_suggestion(&suggestion, format),
+                expected,
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_sqlite_mirror_tracks_changes() {
+        use crate::memory::mirror::{apply_mirror, mirror_row, open_mirror};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirror.sqlite");
+        let mut command = Command::new(
+            "s1".to_string(),
+            "cargo".to_string(),
+            vec!["test".to_string(), "it's".to_string()],
+            "/src/app".to_string(),
+        );
+        command.tags = vec!["rust".to_string()];
+        let row = |c: &Command| {
+            mirror_row(
+                &RecordKind::Command.key(&c.id),
+                serde_json::to_value(c).unwrap(),
+            )
+            .unwrap()
+        };
+        let session = Session::new("bash".to_string(), "/src/app".to_string());
+        let session_row = mirror_row(
+            &RecordKind::Session.key(&session.id),
+            serde_json::to_value(&session).unwrap(),
+        )
+        .unwrap();
+        assert!(mirror_row("memory:event:x", serde_json::json!({})).is_none());
+
+        let mut conn = open_mirror(&path).unwrap();
+        let report = apply_mirror(&mut conn, &[row(&command), session_row.clone()]).unwrap();
+        assert_eq!(
+            (report.written, report.unchanged, report.deleted),
+            (2, 0, 0)
+        );
+        let report = apply_mirror(&mut conn, &[row(&command), session_row.clone()]).unwrap();
+        assert_eq!(
+            (report.written, report.unchanged, report.deleted),
+            (0, 2, 0)
+        );
+
+        command.exit_code = Some(1);
+        let report = apply_mirror(&mut conn, &[row(&command)]).unwrap();
+        assert_eq!(
+            (report.written, report.unchanged, report.deleted),
+            (1, 0, 1)
+        );
+        drop(conn);
+
+        // Reopening keeps the data; it is plain SQL from here
+        let conn = open_mirror(&path).unwrap();
+        let (line, exit_code, tag): (String, i64, String) = conn
+            .query_row(
+                "SELECT c.command_line, c.exit_code, t.tag FROM commands c \
+                 JOIN tags t ON t.table_name = 'commands' AND t.record_id = c.id",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(line, "cargo test \"it's\"");
+        assert_eq!(exit_code, 1);
+        assert_eq!(tag, "rust");
+        let sessions: i64 = conn
+            .query_row("SELECT COUNT(*) FROM sessions", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(sessions, 0);
+    }
+
+    #[test]
+    fn test_analytics_export_tables() {
+        use crate::memory::analytics::write_analytics;
+
+        let dir = tempfile::tempdir().unwrap();
+        let day = Utc::now().date_naive();
+        let mut commands = Vec::new();
+        for (ms, exit_code) in [(100, 0), (300, 1), (200, 0)] {
+            let mut c = Command::new(
+                "s1".to_string(),
+                "cargo".to_string(),
+                vec!["test".to_string(), "a, \"b\"".to_string()],
+                "/src/app".to_string(),
+            );
+            c.duration_ms = Some(ms);
+            c.exit_code = Some(exit_code);
+            c.success = exit_code == 0;
+            commands.push(c);
+        }
+        let mut error = Error::new(
+            commands[1].id.clone(),
+            "s1".to_string(),
+            "test_failure".to_string(),
+            "medium".to_string(),
+            "1 test failed".to_string(),
+        );
+        error.stderr_snippet = Some("raw stderr".to_string());
+
+        let csv = dir.path().join("csv");
+        let manifest = write_analytics(
+            &csv,
+            day,
+            day,
+            AnalyticsFormat::Csv,
+            &commands,
+            std::slice::from_ref(&error),
+        )
+        .unwrap();
+        let rows: Vec<usize> = manifest.tables.iter().map(|t| t.rows).collect();
+        assert_eq!(rows, vec![3, 1, 1]);
+        let durations = std::fs::read_to_string(csv.join("durations.csv")).unwrap();
+        assert_eq!(
+            durations,
+            format!(
+                "date,command,runs,failures,total_ms,mean_ms,p50_ms,p95_ms,max_ms\r\n\
+                 {},cargo,3,1,600,200,200,300,300\r\n",
+                day
+            )
+        );
+        let commands_csv = std::fs::read_to_string(csv.join("commands.csv")).unwrap();
+        assert!(commands_csv.contains(r#""cargo test 'a, ""b""'""#));
+        let errors_csv = std::fs::read_to_string(csv.join("errors.csv")).unwrap();
+        assert!(!errors_csv.contains("raw stderr"));
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(csv.join("manifest.json")).unwrap())
+                .unwrap();
+        assert_eq!(written["tables"][0]["columns"][2]["type"], "timestamp");
+
+        let parquet = dir.path().join("parquet");
+        write_analytics(&parquet, day, day, AnalyticsFormat::Parquet, &commands, &[]).unwrap();
+        let bytes = std::fs::read(parquet.join("commands.parquet")).unwrap();
+        assert!(bytes.starts_with(b"PAR1") && bytes.ends_with(b"PAR1"));
+        let footer =
+            u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap());
+        assert!((footer as usize) < bytes.len() - 12);
+        // No rows: only the schema and metadata
+        let empty = std::fs::read(parquet.join("errors.parquet")).unwrap();
+        assert!(empty.len() < bytes.len());
+    }
+
+    #[test]
+    fn test_dashboard_metrics() {
+        use crate::memory::metrics::*;
+        use crate::memory::ranking::SuggestionImpression;
+        use chrono::TimeZone;
+
+        let from = Utc.with_ymd_and_hms(2026, 3, 2, 9, 30, 0).unwrap();
+        let to = from + ChronoDuration::hours(2);
+        let mut commands = Vec::new();
+        for (minutes, ms, success, program) in [
+            (10, 100, true, "cargo"),
+            (20, 300, false, "cargo"),
+            (100, 50, false, "npm"),
+            (200, 900, false, "npm"),
+        ] {
+            let mut c = Command::new(
+                "s1".to_string(),
+                program.to_string(),
+                Vec::new(),
+                "/src/app".to_string(),
+            );
+            c.started_at = from + ChronoDuration::minutes(minutes);
+            c.ended_at = Some(c.started_at);
+            c.duration_ms = Some(ms);
+            c.success = success;
+            commands.push(c);
+        }
+
+        // Failures at 09:50 and 11:10; the one at 12:50 is outside the range
+        let failures: Vec<f64> = failures_per_hour(&commands, from, to)
+            .iter()
+            .map(|p| p.value)
+            .collect();
+        assert_eq!(failures, vec![1.0, 0.0, 1.0]);
+
+        let durations = durations_by_command(&commands[..3]);
+        assert_eq!(durations[0].command, "cargo");
+        assert_eq!((durations[0].runs, durations[0].failures), (2, 1));
+        assert_eq!((durations[0].mean_ms, durations[0].p95_ms), (200.0, 300));
+        assert_eq!(durations[1].command, "npm");
+
+        let impression = |minutes, shown, accepted: &[&str]| SuggestionImpression {
+            id: uuid::Uuid::new_v4().to_string(),
+            surface: "window".to_string(),
+            surfaced_at: from + ChronoDuration::minutes(minutes),
+            shown,
+            candidates: Vec::new(),
+            accepted: accepted.iter().map(|id| id.to_string()).collect(),
+            dismissed: Vec::new(),
+        };
+        let acceptance = suggestion_acceptance(
+            &[
+                impression(40, 3, &["a"]),
+                impression(50, 1, &[]),
+                impression(100, 0, &[]),
+            ],
+            from,
+            to,
+        );
+        assert_eq!(acceptance.len(), 1);
+        assert_eq!(acceptance[0].at, from + ChronoDuration::minutes(30));
+        assert_eq!(acceptance[0].value, 0.25);
+    }
+
+    #[test]
+    fn test_flaky_detection() {
+        use crate::memory::flaky::*;
+        use crate::memory::tags::git_head;
+        use std::collections::BTreeMap;
+
+        let run = |commit: &str, success: bool, ci: Option<&str>| FlakyRun {
+            command_id: uuid::Uuid::new_v4().to_string(),
+            git_commit: commit.to_string(),
+            success,
+            at: Utc::now(),
+            env: ci
+                .map(|v| BTreeMap::from([("CI".to_string(), v.to_string())]))
+                .unwrap_or_default(),
+        };
+        // Failing once, then passing after an uncommitted fix, is not flaky
+        let fixed = vec![run("a1", false, None), run("a1", true, None)];
+        assert!(flaky_verdict(&fixed).is_none());
+
+        // Failures at other commits do not count; CI=1 only in failing runs
+        let runs = vec![
+            run("a0", false, None),
+            run("a1", true, None),
+            run("a1", false, Some("1")),
+            run("a1", true, None),
+            run("a1", false, Some("1")),
+        ];
+        let verdict = flaky_verdict(&runs).unwrap();
+        assert_eq!((verdict.runs, verdict.failures, verdict.flips), (4, 2, 3));
+        assert_eq!(verdict.flake_rate, 0.5);
+        assert_eq!(verdict.env_differences.len(), 1);
+        assert_eq!(verdict.env_differences[0].name, "CI");
+        assert!(verdict.env_differences[0].passing.contains(&None));
+
+        let env = BTreeMap::from([
+            ("GITHUB_TOKEN".to_string(), "x".to_string()),
+            ("RUST_LOG".to_string(), "debug".to_string()),
+        ]);
+        assert_eq!(
+            sanitize_env(&env),
+            serde_json::json!({ "RUST_LOG": "debug" })
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let git = dir.path().join(".git");
+        std::fs::create_dir_all(&git).unwrap();
+        std::fs::write(git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(
+            git.join("packed-refs"),
+            "# pack-refs\nabc123 refs/heads/main\n",
+        )
+        .unwrap();
+        let nested = dir.path().join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_eq!(git_head(&nested).as_deref(), Some("abc123"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_revisioned_writes_do_not_clobber() {
+        use crate::memory::backend::{InMemoryBackend, PutOutcome};
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let backend = store.client.clone();
+        let session = Session::new("bash".to_string(), "/tmp".to_string());
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_start".to_string(),
+                timestamp: session.started_at,
+                session_id: session.id.clone(),
+                data: serde_json::to_value(&session).unwrap(),
+                provenance: None,
+            })
+            .await
+            .unwrap();
+        let key = RecordKind::Session.key(&session.id);
+
+        // A stale revision is refused
+        let (value, revision) = backend.get_revision(&key).await.unwrap().unwrap();
+        backend.put(&key, &value).await.unwrap();
+        assert_eq!(
+            backend
+                .put_if_revision(&key, &value, revision)
+                .await
+                .unwrap(),
+            PutOutcome::Conflict(revision + 1)
+        );
+
+        // Another writer tags the session between our read and our write: the
+        // edit is retried on top of theirs instead of overwriting it
+        let mut attempts = 0;
+        store
+            .update_value(&key, |session: &mut Session| {
+                if attempts == 0 {
+                    let mut theirs = session.clone();
+                    theirs.tags.push("sync".to_string());
+                    let theirs = serde_json::to_value(&theirs).unwrap();
+                    tokio::task::block_in_place(|| {
+                        tokio::runtime::Handle::current().block_on(backend.put(&key, &theirs))
+                    })
+                    .unwrap();
+                }
+                attempts += 1;
+                session.tags.push("ui".to_string());
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(attempts, 2);
+        let tags = store
+            .tag_add(TagTarget::Session, &session.id, &["review".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(tags, vec!["review", "sync", "ui"]);
+
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_end".to_string(),
+                timestamp: Utc::now(),
+                session_id: session.id.clone(),
+                data: serde_json::json!({}),
+                provenance: None,
+            })
+            .await
+            .unwrap();
+        let sessions = store.list_sessions().await.unwrap();
+        assert!(sessions[0].ended_at.is_some());
+        assert_eq!(sessions[0].tags, vec!["review", "sync", "ui"]);
+    }
+
+    #[tokio::test]
+    async fn test_capture_batches_and_wal_replay() {
+        use crate::memory::backend::{replay_wal, InMemoryBackend};
+
+        // A failed command, its output, line index and error land as one batch
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let mut command = Command::new(
+            "s1".to_string(),
+            "cargo".to_string(),
+            vec!["test".to_string()],
+            "/tmp".to_string(),
+        );
+        command.ended_at = Some(Utc::now());
+        command.duration_ms = Some(1200);
+        command.exit_code = Some(101);
+        let output = Output::new(
+            command.id.clone(),
+            "stdout".to_string(),
+            0,
+            b"test math::divides ... FAILED\n".to_vec(),
+        );
+        let error = store
+            .store_finished_command(command.clone(), vec![output.clone()])
+            .await
+            .unwrap()
+            .unwrap();
+        for key in [
+            RecordKind::Command.key(&command.id),
+            RecordKind::Output.key(&output.id),
+            RecordKind::OutputIndex.key(&output.id),
+            RecordKind::Error.key(&error.id),
+        ] {
+            assert!(store.client.get(&key).await.unwrap().is_some(), "{}", key);
+        }
+
+        // A batch logged by a writer that crashed is finished on replay; one
+        // still within the grace period is left to its writer
+        let backend = InMemoryBackend::new();
+        let mut stale = WriteBatch::new();
+        stale.created_at = Utc::now() - ChronoDuration::minutes(5);
+        stale.put("memory:command:c1", serde_json::json!({ "id": "c1" }));
+        stale.put("memory:error:e1", serde_json::json!({ "id": "e1" }));
+        let fresh = WriteBatch::new();
+        for batch in [&stale, &fresh] {
+            backend
+                .put(
+                    &RecordKind::Wal.key(&batch.id),
+                    &serde_json::to_value(batch).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(replay_wal(&backend).await.unwrap(), 1);
+        assert!(backend.get("memory:error:e1").await.unwrap().is_some());
+        assert_eq!(
+            backend.list(RecordKind::Wal.prefix()).await.unwrap(),
+            vec![RecordKind::Wal.key(&fresh.id)]
+        );
+    }
+
+    #[test]
+    fn test_record_keys() {
+        // Existing ids keep their keys
+        assert_eq!(
+            RecordKind::Command.key("4b1d"),
+            "memory:command:4b1d".to_string()
+        );
+        assert_eq!(
+            RecordKind::DayStats.key("2024-05-01"),
+            "memory:stats:day:2024-05-01"
+        );
+
+        // Separators in ids and workspaces are escaped and parse back
+        let key = RecordKey::new(RecordKind::View, "a:b%c").in_workspace("team:web");
+        assert_eq!(key.to_string(), "memory:view:team%3Aweb:a%3Ab%25c");
+        assert_eq!(RecordKey::parse(&key.to_string()), Some(key));
+        assert!(RecordKind::View
+            .key("x")
+            .starts_with(RecordKind::View.prefix()));
+        assert_eq!(
+            RecordKind::View.workspace_prefix("team:web"),
+            "memory:view:team%3Aweb:"
+        );
+
+        let parsed = RecordKey::parse("memory:output_index:o1").unwrap();
+        assert_eq!(
+            (parsed.kind, parsed.id.as_str()),
+            (RecordKind::OutputIndex, "o1")
+        );
+        assert_eq!(RecordKey::parse("memory:unknown:x"), None);
+        assert_eq!(RecordKey::parse("memory:command:a:b:c"), None);
+
+        // No kind's keys can be listed under another kind's prefix
+        for a in RecordKind::ALL {
+            for b in RecordKind::ALL {
+                if a != b {
+                    assert!(!a.prefix().starts_with(b.prefix()), "{:?} {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_demo_data_seeds_and_wipes_cleanly() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let mine = Session::new("bash".to_string(), "/home/me".to_string());
+        store
+            .write_value(
+                &RecordKind::Session.key(&mine.id),
+                serde_json::to_value(&mine).unwrap(),
+            )
+            .await
+            .unwrap();
+        let tip = Suggestion::new(
+            "tip".to_string(),
+            "low".to_string(),
+            0.5,
+            "Mine".to_string(),
+            "Not a demo".to_string(),
+        );
+        store.persist_suggestion(tip).await.unwrap();
+
+        // Seeding twice leaves one copy of the demo
+        store.seed_demo_data().await.unwrap();
+        let summary = store.seed_demo_data().await.unwrap();
+        let sessions = store.list_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        let demo = sessions
+            .iter()
+            .find(|s| s.id == summary.session_id)
+            .unwrap();
+        assert_eq!(usage::session_workspace(demo), DEMO_WORKSPACE);
+        let errors = store.query_recent_errors(None, None, None).await.unwrap();
+        assert_eq!(errors.len(), summary.errors);
+        assert!(errors.iter().any(|e| e.severity == "high"));
+        assert_eq!(
+            store.get_suggestions(None, None).await.unwrap().len(),
+            summary.suggestions + 1
+        );
+        assert_eq!(store.canvas_list().await.unwrap()[0].id, summary.canvas_id);
+        // Demo commands stay out of the daily statistics
+        assert!(store
+            .client
+            .list(RecordKind::DayStats.prefix())
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(store.wipe_demo_data().await.unwrap() > 0);
+        assert_eq!(store.wipe_demo_data().await.unwrap(), 0);
+        let sessions = store.list_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, mine.id);
+        assert_eq!(store.get_suggestions(None, None).await.unwrap().len(), 1);
+        assert!(store.canvas_list().await.unwrap().is_empty());
+        for kind in [RecordKind::Command, RecordKind::Output, RecordKind::Insight] {
+            assert!(store.client.list(kind.prefix()).await.unwrap().is_empty());
+        }
+    }
+
+    // Bookmarks keep their excerpt after the output they point at is collected
+    #[tokio::test]
+    async fn test_bookmarks_survive_output_gc() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::views::ViewRecordType;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let command = Command::new(
+            "s".to_string(),
+            "cargo".to_string(),
+            vec!["build".to_string()],
+            "/src".to_string(),
+        );
+        store.store_command(command.clone()).await.unwrap();
+        let text: String = (0..10).map(|i| format!("line {}\n", i)).collect();
+        let mut output = Output::new(
+            command.id.clone(),
+            "stdout".to_string(),
+            0,
+            text.into_bytes(),
+        );
+        store.store_output(&mut output, true).await.unwrap();
+
+        let lines = LineRange { start: 2, end: 4 };
+        let bookmark = store
+            .bookmark_add(&command.id, Some(lines), None)
+            .await
+            .unwrap();
+        assert_eq!(bookmark.excerpt, ["line 2", "line 3"]);
+        let whole = store.bookmark_add(&command.id, None, None).await.unwrap();
+        assert_eq!(whole.excerpt.len(), 10);
+        assert_ne!(whole.id, bookmark.id);
+        let empty = LineRange { start: 20, end: 30 };
+        assert!(store
+            .bookmark_add(&command.id, Some(empty), None)
+            .await
+            .is_err());
+
+        store.gc_oldest_outputs(u64::MAX, None).await.unwrap();
+        assert!(store.command_outputs(&command.id).await.unwrap().is_empty());
+        // Bookmarking again keeps the permalink and the pinned lines
+        let again = store
+            .bookmark_add(&command.id, Some(lines), Some("linker error".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(again.id, bookmark.id);
+        let resolved = store.resolve_bookmark(&bookmark.id).await.unwrap().unwrap();
+        assert_eq!(resolved.bookmark.excerpt, ["line 2", "line 3"]);
+        assert_eq!(resolved.bookmark.note.as_deref(), Some("linker error"));
+        assert_eq!(resolved.command.unwrap().id, command.id);
+        assert_eq!(store.bookmark_list(Some("s")).await.unwrap().len(), 2);
+
+        let filter = ViewFilter {
+            record_types: vec![ViewRecordType::Bookmark],
+            text: Some("LINE 3".to_string()),
+            ..Default::default()
+        };
+        let matches = store.evaluate_filter(&filter).await.unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            store
+                .evaluate_filter(&ViewFilter::default())
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+
+        store.bookmark_delete(&bookmark.id).await.unwrap();
+        assert!(store
+            .resolve_bookmark(&bookmark.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    // Scrubbing rewrites secrets but keeps bytes that are not UTF-8
+    #[tokio::test]
+    async fn test_scrubbed_output_keeps_raw_bytes() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::scrub::{ScrubConfig, Scrubber};
+        use std::sync::Arc;
+
+        let store = MemoryStore::new(InMemoryBackend::new())
+            .await
+            .unwrap()
+            .with_output_encoding(EncodingConfig {
+                encoding: "utf-8".to_string(),
+                ..Default::default()
+            })
+            .with_scrubber(Arc::new(Scrubber::new(&ScrubConfig::default()).unwrap()));
+        let command = Command::new("s".to_string(), "env".to_string(), vec![], "/".to_string());
+        store.store_command(command.clone()).await.unwrap();
+
+        let raw = b"password=hunter2hunter2 then \xff and the rest of a long enough line\n";
+        let mut output = Output::new(command.id.clone(), "stdout".to_string(), 0, raw.to_vec());
+        store.store_output(&mut output, false).await.unwrap();
+        assert_eq!(
+            output.content,
+            b"password=[REDACTED] then \xff and the rest of a long enough line\n".to_vec()
+        );
+        assert_eq!(output.size_bytes, output.content.len() as u64);
+        assert_eq!(output.scrubbed_by.len(), 1);
+    }
+
+    // Signatures that are plain ASCII only mark a chunk binary when it is not text
+    #[test]
+    fn test_text_starting_with_a_signature_stays_text() {
+        use crate::memory::content::detect_content;
+
+        for text in [&b"MZ\n"[..], b"%PDF-1.7 notes\n", b"GIF89a is a format\n"] {
+            let info = detect_content(text);
+            assert!(!info.binary, "{:?}", String::from_utf8_lossy(text));
+            assert_eq!(info.mime_type, "text/plain");
+        }
+
+        let exe = detect_content(b"MZ\x90\0\x03\0\0\0\x04\0");
+        assert!(exe.binary);
+        assert_eq!(exe.mime_type, "application/x-msdownload");
+        assert_eq!(detect_content(b"\x89PNG\r\n\x1a\n").mime_type, "image/png");
+    }
+
+    #[test]
+    fn test_ansi_sequences_parse_into_spans() {
+        use crate::memory::ansi::*;
+
+        // Colors in 16, 256 and 24-bit forms, and sequences that only move
+        // the cursor or set the title
+        let spans = parse("\x1b[32mok\x1b[0m \x1b[38;5;208mwarn\x1b[m \x1b[48:2::1:2:3;4mx\x1b[0m\x1b[2K\x1b]0;t\x1b\\!");
+        let styles: Vec<(&str, Style)> = spans.iter().map(|s| (s.text.as_str(), s.style)).collect();
+        let fg = |color| Style {
+            fg: Some(color),
+            ..Default::default()
+        };
+        assert_eq!(
+            styles,
+            vec![
+                ("ok", fg(Color::Indexed(2))),
+                (" ", Style::default()),
+                ("warn", fg(Color::Indexed(208))),
+                (" ", Style::default()),
+                (
+                    "x",
+                    Style {
+                        bg: Some(Color::Rgb([1, 2, 3])),
+                        underline: true,
+                        ..Default::default()
+                    }
+                ),
+                ("!", Style::default()),
+            ]
+        );
+        assert_eq!(strip("\x1b[1mbold\x1b[22m \u{9b}31mred"), "bold red");
+        assert_eq!(
+            serde_json::to_value(&spans[2]).unwrap(),
+            serde_json::json!({ "text": "warn", "fg": { "indexed": 208 } })
+        );
+
+        // A sequence split across chunks is held back; the style carries over
+        let mut parser = AnsiParser::new();
+        assert_eq!(plain_text(&parser.feed("a\x1b[3")), "a");
+        let spans = parser.feed("1mb");
+        assert_eq!(spans[0].text, "b");
+        assert_eq!(spans[0].style.fg, Some(Color::Indexed(1)));
+        assert_eq!(parser.feed("c")[0].style.fg, Some(Color::Indexed(1)));
+        // An unfinished sequence at the end is kept as text
+        assert!(parser.feed("\x1b[").is_empty());
+        assert_eq!(plain_text(&parser.finish()), "\x1b[");
+    }
+
+    #[tokio::test]
+    async fn test_stored_output_keeps_colors_as_spans() {
+        use crate::memory::ansi::AnsiMode;
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new())
+            .await
+            .unwrap()
+            .with_output_encoding(EncodingConfig {
+                ansi: AnsiMode::Spans,
+                ..Default::default()
+            });
+        let command = Command::new(
+            "s".to_string(),
+            "cargo".to_string(),
+            vec![],
+            "/".to_string(),
+        );
+        store.store_command(command.clone()).await.unwrap();
+
+        let raw = b"\x1b[1;31merror\x1b[0m: mismatched types\r\x1b[K\n".to_vec();
+        let mut output = Output::new(command.id.clone(), "stdout".to_string(), 0, raw);
+        store.store_output(&mut output, false).await.unwrap();
+        assert_eq!(output.content, b"error: mismatched types\r\n".to_vec());
+        assert_eq!(output.size_bytes, output.content.len() as u64);
+        assert_eq!(output.spans.len(), 2);
+        assert!(output.spans[0].style.bold);
+
+        // Plain text needs no spans
+        let mut plain = Output::new(
+            command.id.clone(),
+            "stdout".to_string(),
+            1,
+            b"done\n".to_vec(),
+        );
+        store.store_output(&mut plain, false).await.unwrap();
+        assert!(plain.spans.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_object_stream_is_stored_as_ndjson() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let command = Command::new(
+            "s".to_string(),
+            "Get-Service".to_string(),
+            vec![],
+            "/src".to_string(),
+        );
+        store.store_command(command.clone()).await.unwrap();
+        let chunks = [
+            ("stdout", "Status  Name\n"),
+            (OBJECTS_STREAM, "{\"Name\":\"sshd\",\"Status\":4}\n"),
+            (OBJECTS_STREAM, "{\"Name\":\"cron\",\"Status\":1}\n"),
+        ];
+        for (index, (stream, text)) in chunks.into_iter().enumerate() {
+            let mut output = Output::new(
+                command.id.clone(),
+                stream.to_string(),
+                index as u32,
+                text.as_bytes().to_vec(),
+            );
+            store.store_output(&mut output, true).await.unwrap();
+            if stream == OBJECTS_STREAM {
+                assert_eq!(output.content_type.as_deref(), Some(OBJECTS_CONTENT_TYPE));
+            }
+        }
+
+        let objects = store.command_objects(&command.id).await.unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[1]["Name"], "cron");
+    }
+
+    #[tokio::test]
+    async fn test_followed_logs_store_new_errors_once() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::live::LiveOutputs;
+        use crate::memory::logs::{scan_log_errors, LogIngest};
+        use std::sync::Arc;
+
+        let found = scan_log_errors(
+            "GET /health 200\n\
+             2026-01-02 ERROR db: connection refused\n\
+             {\"level\":\"error\",\"msg\":\"retry\"}\n\
+             E0102 15:04:05.000000 1 reflector.go:138] watch failed\n\
+             panic: runtime error: index out of range\n\
+             terrors are not errors\n",
+        );
+        assert_eq!(found.len(), 4);
+        assert_eq!(found[0].severity, "medium");
+        assert_eq!(found[3].severity, "high");
+
+        let store = Arc::new(MemoryStore::new(InMemoryBackend::new()).await.unwrap());
+        let live = LiveOutputs::new();
+        let mut ingest = LogIngest::start(
+            Arc::clone(&store),
+            &live,
+            "docker:web",
+            "docker",
+            vec!["logs".into(), "-f".into(), "--".into(), "web".into()],
+            "/srv",
+        )
+        .await
+        .unwrap();
+        let command_id = ingest.command_id().to_string();
+        let session_id = ingest.session_id().to_string();
+
+        // The error line is only scanned once it is complete
+        let errors = ingest
+            .push(
+                &live,
+                "stderr",
+                b"12:00:01 ERROR upstream timed out after 3".to_vec(),
+            )
+            .await
+            .unwrap();
+        assert!(errors.is_empty());
+        let errors = ingest
+            .push(
+                &live,
+                "stderr",
+                b"0s\n12:00:02 ERROR upstream timed out after 45s\n".to_vec(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error_type, "log_error");
+        assert!(live.is_running(&command_id));
+        ingest.finish(&live, Some(137), true).await.unwrap();
+        assert!(!live.is_running(&command_id));
+
+        // Stopping the follow is not a failure of the stream
+        let context = store
+            .get_context(&session_id, ChronoDuration::hours(1))
+            .await
+            .unwrap();
+        assert!(context.commands[0].success);
+        assert!(context.commands[0]
+            .tags
+            .contains(&"logs:docker:web".to_string()));
+        assert_eq!(context.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handoff_bundle_carries_a_session_to_another_store() {
+        use crate::memory::demo::DEMO_SESSION_ID;
+        use crate::memory::handoff::{self, HANDOFF_TAG};
+
+        let desktop = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        desktop.seed_demo_data().await.unwrap();
+        let mut open = Suggestion::new(
+            "command".to_string(),
+            "high".to_string(),
+            0.9,
+            "Run the failing test alone".to_string(),
+            String::new(),
+        );
+        open.context = serde_json::json!({ "cwd": "/demo/runebook/webapp" });
+        desktop.persist_suggestion(open.clone()).await.unwrap();
+        let mut elsewhere = open.clone();
+        elsewhere.id = "elsewhere".to_string();
+        elsewhere.context = serde_json::json!({ "cwd": "/somewhere/else" });
+        desktop.persist_suggestion(elsewhere).await.unwrap();
+
+        let exported = desktop.export_handoff(DEMO_SESSION_ID).await.unwrap();
+        assert!(!exported.errors.is_empty());
+        assert_eq!(exported.suggestions.len(), 1);
+        assert!(exported.snippets.iter().any(|s| s.stream_type == "stderr"));
+        assert_eq!(exported.workspace.cwd, "/demo/runebook/webapp");
+
+        assert!(handoff::seal(&exported, "short").is_err());
+        let bundle = handoff::seal(&exported, "correct horse").unwrap();
+        assert!(bundle.starts_with(handoff::BUNDLE_PREFIX));
+        assert!(handoff::open(&bundle, "wrong horse").is_err());
+
+        let laptop = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let opened = handoff::open(&bundle, "correct horse").unwrap();
+        let summary = laptop.import_handoff(opened).await.unwrap();
+        assert_eq!(summary.session_id, DEMO_SESSION_ID);
+        assert_eq!(summary.commands, exported.commands.len());
+        assert_eq!(summary.suggestions, 1);
+        assert!(summary.workspace_here.is_none());
+
+        let context = laptop
+            .get_context(DEMO_SESSION_ID, ChronoDuration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(context.commands.len(), exported.commands.len());
+        assert!(context.commands[0].tags.contains(&HANDOFF_TAG.to_string()));
+        assert_eq!(context.errors.len(), exported.errors.len());
+        let failed = &exported.snippets[0].command_id;
+        assert!(!laptop.command_outputs(failed).await.unwrap().is_empty());
+        assert_eq!(
+            laptop.get_suggestions(None, None).await.unwrap()[0].id,
+            open.id
+        );
+
+        // Importing again replaces rather than duplicates
+        let again = handoff::open(&bundle, "correct horse").unwrap();
+        laptop.import_handoff(again).await.unwrap();
+        let outputs = laptop.command_outputs(failed).await.unwrap();
+        assert_eq!(
+            outputs.len(),
+            exported
+                .snippets
+                .iter()
+                .filter(|s| &s.command_id == failed)
+                .count()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explain_suggestion_links_evidence_and_stop_conditions() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let mut command = Command::new(
+            "s1".to_string(),
+            "cargo".to_string(),
+            vec!["test".to_string()],
+            "/work/app".to_string(),
+        );
+        command.exit_code = Some(101);
+        store.store_command(command.clone()).await.unwrap();
+        let error = Error::new(
+            command.id.clone(),
+            "s1".to_string(),
+            "test_failure".to_string(),
+            "high".to_string(),
+            "2 tests failed".to_string(),
+        );
+        store.store_error(error.clone()).await.unwrap();
+
+        let mut rerun = Suggestion::new(
+            "command".to_string(),
+            "medium".to_string(),
+            0.8,
+            "Re-run only the 2 failed tests".to_string(),
+            String::new(),
+        );
+        rerun.context = serde_json::json!({
+            "kind": rerun::RERUN_KIND,
+            "tool": "cargo",
+            "command_id": command.id,
+            "error_id": error.id,
+            "cwd": "/work/app",
+        });
+        store.persist_suggestion(rerun.clone()).await.unwrap();
+        let mut tip = Suggestion::new(
+            "tip".to_string(),
+            "low".to_string(),
+            0.1,
+            "Alias cargo test".to_string(),
+            String::new(),
+        );
+        tip.context = serde_json::json!({ "analyzer": "shortcuts" });
+        store.persist_suggestion(tip.clone()).await.unwrap();
+        store
+            .surface_suggestions("ipc", vec![rerun.clone(), tip.clone()], 1, None)
+            .await
+            .unwrap();
+
+        let explained = store.explain_suggestion(&rerun.id).await.unwrap().unwrap();
+        assert_eq!(explained.analyzer, rerun::RERUN_KIND);
+        assert_eq!(explained.reason_key, "rerun_failed:command");
+        assert_eq!(
+            explained
+                .evidence
+                .iter()
+                .map(|e| (e.link.as_str(), e.summary.is_some()))
+                .collect::<Vec<_>>(),
+            vec![("error_id", true), ("command_id", true)]
+        );
+        assert!(explained.evidence[1]
+            .summary
+            .as_ref()
+            .unwrap()
+            .contains("cargo test (exit 101)"));
+        assert_eq!(explained.ranking.position, Some(1));
+        assert_eq!(explained.ranking.times_shown, 1);
+        let total: f64 = explained.ranking.contributions.values().sum();
+        assert!((total - explained.ranking.score).abs() < 1e-9);
+        let keys: Vec<&str> = explained
+            .stop_conditions
+            .iter()
+            .map(|c| c.key.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "dismissed",
+                "muted",
+                "test_run_finished",
+                "outside_workspace",
+                "outranked"
+            ]
+        );
+        assert!(explained.stop_conditions.iter().all(|c| !c.met));
+
+        let explained = store.explain_suggestion(&tip.id).await.unwrap().unwrap();
+        assert_eq!(explained.reason_key, "shortcuts:tip");
+        assert_eq!(explained.ranking.position, Some(2));
+        assert_eq!(explained.ranking.times_shown, 0);
+        assert!(explained.stop_conditions.last().unwrap().met);
+        assert!(store.explain_suggestion("missing").await.unwrap().is_none());
+    }
+
+    // Accepted suggestions teach the fix style and package manager; muting hides a reason until reset
+    #[tokio::test]
+    async fn test_preferences_learn_mute_and_reset() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::preferences::*;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let suggestion = |kind: &str, analyzer: &str, command: Option<&str>| {
+            let mut s = Suggestion::new(
+                kind.to_string(),
+                "low".to_string(),
+                0.5,
+                format!("{} from {}", kind, analyzer),
+                String::new(),
+            );
+            s.context = serde_json::json!({ "analyzer": analyzer });
+            s.command = command.map(String::from);
+            s
+        };
+        let install = suggestion("command", "deps", Some("pnpm install"));
+        store.persist_suggestion(install.clone()).await.unwrap();
+        assert!(store.suggestion_feedback(&install.id, true).await.unwrap());
+        let learned = store.preferences().await.unwrap();
+        let style = learned.fix_style.as_ref().unwrap();
+        assert_eq!(
+            (style.value, style.source, style.evidence),
+            (FixStyle::Command, PreferenceSource::Learned, 1)
+        );
+        assert_eq!(learned.package_manager.as_ref().unwrap().value, "pnpm");
+        assert_eq!(
+            learned.fit(&suggestion("command", "deps", Some("pnpm add"))),
+            1.0
+        );
+        assert_eq!(
+            learned.fit(&suggestion("command", "deps", Some("npm install"))),
+            0.0
+        );
+
+        // An explicit choice outlasts anything learned afterwards
+        store
+            .set_preference(PreferenceChoice::PackageManager("npm".to_string()))
+            .await
+            .unwrap();
+        let yarn = suggestion("command", "deps", Some("yarn add"));
+        store.persist_suggestion(yarn.clone()).await.unwrap();
+        store.suggestion_feedback(&yarn.id, true).await.unwrap();
+        let manager = store.preferences().await.unwrap().package_manager.unwrap();
+        assert_eq!(
+            (manager.value.as_str(), manager.source),
+            ("npm", PreferenceSource::Explicit)
+        );
+        assert!(store
+            .set_preference(PreferenceChoice::PackageManager("make".to_string()))
+            .await
+            .is_err());
+
+        let tip = suggestion("tip", "shortcuts", None);
+        let other_tip = suggestion("tip", "shortcuts", None);
+        let fix = suggestion("command", "deps", Some("npm ci"));
+        for s in [&tip, &other_tip, &fix] {
+            store.persist_suggestion(s.clone()).await.unwrap();
+        }
+        let muted = store
+            .mute_suggestion_reason(&tip.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(muted.reason_key, "shortcuts:tip");
+        assert!(store
+            .mute_suggestion_reason("missing")
+            .await
+            .unwrap()
+            .is_none());
+        let shown = store
+            .surface_suggestions("ipc", vec![other_tip.clone(), fix.clone()], 10, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            shown.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(),
+            vec![fix.id.as_str()]
+        );
+        let explained = store
+            .explain_suggestion(&other_tip.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(explained
+            .stop_conditions
+            .iter()
+            .any(|c| c.key == "muted" && c.met));
+        assert_eq!(explained.ranking.features["preferred"], 0.0);
+
+        let reset = store
+            .reset_preferences(PreferenceName::Muted, Some("shortcuts:tip"))
+            .await
+            .unwrap();
+        assert!(reset.muted.is_empty());
+        assert!(reset.fix_style.is_some());
+        let shown = store
+            .surface_suggestions("ipc", vec![other_tip.clone()], 10, None)
+            .await
+            .unwrap();
+        assert_eq!(shown.len(), 1);
+        let reset = store
+            .reset_preferences(PreferenceName::All, None)
+            .await
+            .unwrap();
+        assert_eq!(reset, Preferences::default());
+    }
+
+    // Reindexing repairs index drift, and picks up after an interrupted run
+    #[tokio::test]
+    async fn test_reindex_rebuilds_and_resumes() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::pages::index_output;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mirror_path = dir.path().join("mirror.sqlite");
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let mut commands = Vec::new();
+        for (i, name) in ["cargo", "npm", "make"].into_iter().enumerate() {
+            let mut command = Command::new(
+                "s".to_string(),
+                name.to_string(),
+                Vec::new(),
+                "/src".to_string(),
+            );
+            command.ended_at = Some(command.started_at);
+            command.duration_ms = Some(100 * (i as u64 + 1));
+            store
+                .write_value(
+                    &RecordKind::Command.key(&command.id),
+                    serde_json::to_value(&command).unwrap(),
+                )
+                .await
+                .unwrap();
+            let mut output = Output::new(
+                command.id.clone(),
+                "stdout".to_string(),
+                0,
+                b"a\nb\n".to_vec(),
+            );
+            store.store_output(&mut output, true).await.unwrap();
+            commands.push((command, output));
+        }
+        // A lost line index, and one whose output is gone
+        let lost = RecordKind::OutputIndex.key(&commands[0].1.id);
+        store.client.delete(&lost).await.unwrap();
+        let mut stale = index_output(&commands[1].1).unwrap();
+        stale.output_id = "gone".to_string();
+        store.store_line_index(&stale).await.unwrap();
+
+        let mut batches = Vec::new();
+        let report = store
+            .reindex(&[], 2, &mirror_path, &mut |p| {
+                batches.push((p.kind, p.done, p.total))
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            report.records.into_iter().collect::<Vec<_>>(),
+            [
+                (IndexKind::Search, 6),
+                (IndexKind::Secondary, 3),
+                (IndexKind::LineOffsets, 3)
+            ]
+        );
+        assert_eq!(report.removed, 1);
+        assert!(!report.resumed);
+        assert_eq!(
+            batches,
+            [
+                (IndexKind::Search, 2, 6),
+                (IndexKind::Search, 4, 6),
+                (IndexKind::Search, 6, 6),
+                (IndexKind::Secondary, 2, 3),
+                (IndexKind::Secondary, 3, 3),
+                (IndexKind::LineOffsets, 2, 3),
+                (IndexKind::LineOffsets, 3, 3)
+            ]
+        );
+        assert!(store.read_value(&lost).await.unwrap().is_some());
+        assert!(store
+            .read_value(&RecordKind::OutputIndex.key("gone"))
+            .await
+            .unwrap()
+            .is_none());
+        let mirrored: i64 = mirror::open_mirror(&mirror_path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM commands", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(mirrored, 3);
+        let today = Utc::now().date_naive();
+        let total_ms = || async {
+            store
+                .time_report(today, today, TimeGroupBy::Command, 5)
+                .await
+                .unwrap()
+                .total_ms
+        };
+        assert_eq!(total_ms().await, 600);
+
+        // Interrupted after its first batch: that command is already counted
+        for key in store
+            .client
+            .list(RecordKind::DayStats.prefix())
+            .await
+            .unwrap()
+        {
+            store.client.delete(&key).await.unwrap();
+        }
+        let mut keys = store
+            .client
+            .list(RecordKind::Command.prefix())
+            .await
+            .unwrap();
+        keys.sort();
+        let first = commands
+            .iter()
+            .find(|(c, _)| RecordKind::Command.key(&c.id) == keys[0])
+            .unwrap();
+        store.record_command_stats(&first.0).await.unwrap();
+        let checkpoint = RecordKind::App.key("reindex");
+        store
+            .write_value(
+                &checkpoint,
+                serde_json::json!({
+                    "kinds": ["secondary"],
+                    "finished": [],
+                    "after": keys[0],
+                    "done": 1
+                }),
+            )
+            .await
+            .unwrap();
+        let report = store
+            .reindex(&[IndexKind::Secondary], 10, &mirror_path, &mut |_| {})
+            .await
+            .unwrap();
+        assert!(report.resumed);
+        assert_eq!(report.records[&IndexKind::Secondary], 3);
+        assert_eq!(total_ms().await, 600);
+        assert!(store.read_value(&checkpoint).await.unwrap().is_none());
+    }
+
+    /// A session with one command whose outputs are `sizes` bytes of text,
+    /// stamped a minute apart from `start`; returns the output ids, oldest first
+    async fn seed_outputs(
+        store: &MemoryStore,
+        session_id: &str,
+        start: chrono::DateTime<Utc>,
+        sizes: &[usize],
+    ) -> Vec<String> {
+        let command = Command::new(
+            session_id.to_string(),
+            "make".to_string(),
+            Vec::new(),
+            "/tmp".to_string(),
+        );
+        store.store_command(command.clone()).await.unwrap();
+        let mut ids = Vec::new();
+        for (i, size) in sizes.iter().enumerate() {
+            let mut output = Output::new(
+                command.id.clone(),
+                "stdout".to_string(),
+                i as u32,
+                "x".repeat(*size).into_bytes(),
+            );
+            output.timestamp = start + ChronoDuration::minutes(i as i64);
+            store.store_output(&mut output, false).await.unwrap();
+            ids.push(output.id);
+        }
+        ids
+    }
+
+    /// Stored size of each output, by id
+    async fn stored_sizes(store: &MemoryStore) -> std::collections::HashMap<String, u64> {
+        let report = store.usage_report(100).await.unwrap();
+        report
+            .largest_outputs
+            .into_iter()
+            .map(|o| (o.output_id, o.stored_bytes))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_quota_drop_outputs_refuses_writes_over_the_limit() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let outputs = seed_outputs(&store, "s1", Utc::now(), &[1000, 1000, 1000]).await;
+        let used = store.usage_report(0).await.unwrap().total_bytes;
+
+        // Without a limit everything is admitted
+        let unlimited = QuotaGuard::new(QuotaConfig::default());
+        assert!(unlimited
+            .admit(&store, Some("s1"), u64::MAX / 2)
+            .await
+            .unwrap());
+
+        let guard = QuotaGuard::new(QuotaConfig {
+            max_total_bytes: Some(used + 500),
+            ..Default::default()
+        });
+        let mut events = guard.subscribe();
+
+        // Admitted writes are counted without going back to the store
+        assert!(guard.admit(&store, Some("s1"), 400).await.unwrap());
+        assert!(events.try_recv().is_err());
+
+        assert!(!guard.admit(&store, Some("s1"), 200).await.unwrap());
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.scope, "total");
+        assert_eq!(event.session_id.as_deref(), Some("s1"));
+        assert_eq!(event.limit_bytes, used + 500);
+        assert_eq!(event.used_bytes, used + 400);
+        assert_eq!(event.attempted_bytes, 200);
+        assert_eq!(event.policy, QuotaPolicy::DropOutputs);
+        assert_eq!(event.action, "dropped");
+        assert_eq!(event.freed_bytes, 0);
+
+        // Nothing was collected to make room
+        assert_eq!(stored_sizes(&store).await.len(), outputs.len());
+    }
+
+    #[tokio::test]
+    async fn test_quota_emergency_gc_collects_the_oldest_outputs() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let start = Utc::now() - ChronoDuration::hours(1);
+        let outputs = seed_outputs(&store, "s1", start, &[1000, 1000, 1000]).await;
+        let sizes = stored_sizes(&store).await;
+        let used = store.usage_report(0).await.unwrap().total_bytes;
+
+        // Already at the limit: the oldest output goes to make room for the write
+        let guard = QuotaGuard::new(QuotaConfig {
+            max_total_bytes: Some(used),
+            policy: QuotaPolicy::EmergencyGc,
+            gc_target_ratio: 1.0,
+            ..Default::default()
+        });
+        let mut events = guard.subscribe();
+        assert!(guard.admit(&store, Some("s1"), 100).await.unwrap());
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.scope, "total");
+        assert_eq!(event.policy, QuotaPolicy::EmergencyGc);
+        assert_eq!(event.action, "gc");
+        assert_eq!(event.used_bytes, used);
+        assert_eq!(event.freed_bytes, sizes[&outputs[0]]);
+
+        let left = stored_sizes(&store).await;
+        assert!(!left.contains_key(&outputs[0]));
+        assert!(left.contains_key(&outputs[1]) && left.contains_key(&outputs[2]));
+        assert!(store
+            .client
+            .get(&RecordKind::OutputIndex.key(&outputs[0]))
+            .await
+            .unwrap()
+            .is_none());
+        // The command itself is kept
+        assert_eq!(
+            store
+                .client
+                .list(RecordKind::Command.prefix())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // A write no collection can make room for is still dropped
+        assert!(!guard.admit(&store, Some("s1"), used * 2).await.unwrap());
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.action, "dropped");
+        assert!(stored_sizes(&store).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quota_session_cap_counts_and_collects_that_session_only() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        // The other session's outputs are older, so a store-wide collection
+        // would take them first
+        let start = Utc::now() - ChronoDuration::hours(2);
+        let other = seed_outputs(&store, "s2", start, &[200]).await;
+        let mine = seed_outputs(
+            &store,
+            "s1",
+            start + ChronoDuration::hours(1),
+            &[1000, 1000],
+        )
+        .await;
+        let report = store.usage_report(0).await.unwrap();
+        let session_bytes = |id: &str| {
+            report
+                .by_session
+                .iter()
+                .find(|s| s.session_id == id)
+                .unwrap()
+                .bytes
+        };
+        let limit = session_bytes("s1") + 10;
+        assert!(session_bytes("s2") < limit);
+
+        let guard = QuotaGuard::new(QuotaConfig {
+            max_session_bytes: Some(limit),
+            policy: QuotaPolicy::EmergencyGc,
+            gc_target_ratio: 1.0,
+            ..Default::default()
+        });
+        let mut events = guard.subscribe();
+
+        // Writes outside any session, or to a session under its cap, pass
+        assert!(guard.admit(&store, None, limit * 10).await.unwrap());
+        assert!(guard.admit(&store, Some("s2"), 10).await.unwrap());
+        assert!(events.try_recv().is_err());
+
+        assert!(guard.admit(&store, Some("s1"), 20).await.unwrap());
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.scope, "session");
+        assert_eq!(event.session_id.as_deref(), Some("s1"));
+        assert_eq!(event.limit_bytes, limit);
+        assert_eq!(event.used_bytes, session_bytes("s1"));
+        assert_eq!(event.action, "gc");
+
+        let left = stored_sizes(&store).await;
+        assert!(left.contains_key(&other[0]));
+        assert!(!left.contains_key(&mine[0]));
+        assert!(left.contains_key(&mine[1]));
+    }
+
+    /// Record the start of a session in `cwd`
+    async fn start_session(store: &MemoryStore, cwd: &str) -> Session {
+        let session = Session::new("bash".to_string(), cwd.to_string());
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_start".to_string(),
+                timestamp: session.started_at,
+                session_id: session.id.clone(),
+                data: serde_json::to_value(&session).unwrap(),
+                provenance: None,
+            })
+            .await
+            .unwrap();
+        session
+    }
+
+    #[tokio::test]
+    async fn test_tags_round_trip_and_auto_tag_rules() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::tags::TagMatcher;
+        use std::sync::Arc;
+
+        let tags = |list: &[&str]| list.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let tagger = AutoTagger::new(AutoTagConfig {
+            git_project: false,
+            languages: false,
+            matchers: vec![
+                TagMatcher {
+                    tag: "deploy".to_string(),
+                    command: Some(r"^kubectl apply\b".to_string()),
+                    cwd: None,
+                },
+                TagMatcher {
+                    tag: "scratch".to_string(),
+                    command: None,
+                    cwd: Some("^/tmp".to_string()),
+                },
+            ],
+            intents: IntentConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let store = MemoryStore::new(InMemoryBackend::new())
+            .await
+            .unwrap()
+            .with_auto_tagger(Arc::new(tagger));
+
+        // cwd-only rules tag sessions as they start
+        let scratch = start_session(&store, "/tmp/work").await;
+        let project = start_session(&store, "/srv/app").await;
+        let sessions = store.list_sessions().await.unwrap();
+        let stored = |id: &str| sessions.iter().find(|s| s.id == id).unwrap().tags.clone();
+        assert_eq!(stored(&scratch.id), tags(&["scratch"]));
+        assert!(stored(&project.id).is_empty());
+
+        // Tags are normalized; adding one twice or removing a missing one
+        // leaves the set as it was
+        let added = store
+            .tag_add(
+                TagTarget::Session,
+                &project.id,
+                &tags(&["Review", " urgent ", "two words"]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(added, tags(&["review", "urgent"]));
+        let again = store
+            .tag_add(TagTarget::Session, &project.id, &tags(&["review"]))
+            .await
+            .unwrap();
+        assert_eq!(again, added);
+        let unchanged = store
+            .tag_remove(TagTarget::Session, &project.id, &tags(&["missing"]))
+            .await
+            .unwrap();
+        assert_eq!(unchanged, added);
+        let removed = store
+            .tag_remove(TagTarget::Session, &project.id, &tags(&["URGENT"]))
+            .await
+            .unwrap();
+        assert_eq!(removed, tags(&["review"]));
+        assert!(store
+            .tag_add(TagTarget::Session, "no-such-session", &tags(&["x"]))
+            .await
+            .is_err());
+
+        // Command rules match the full command line
+        let deploy = Command::new(
+            project.id.clone(),
+            "kubectl".to_string(),
+            tags(&["apply", "-f", "app.yaml"]),
+            "/srv/app".to_string(),
+        );
+        let listing = Command::new(
+            project.id.clone(),
+            "kubectl".to_string(),
+            tags(&["get", "pods"]),
+            "/srv/app".to_string(),
+        );
+        store.store_command(deploy.clone()).await.unwrap();
+        store.store_command(listing.clone()).await.unwrap();
+        store
+            .tag_add(TagTarget::Command, &listing.id, &tags(&["review"]))
+            .await
+            .unwrap();
+        let counts = |list: Vec<TagCount>| {
+            list.into_iter()
+                .map(|c| (c.tag, c.sessions, c.commands))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            counts(
+                store
+                    .tag_list(Some((TagTarget::Command, &deploy.id)))
+                    .await
+                    .unwrap()
+            ),
+            vec![("deploy".to_string(), 0, 1)]
+        );
+        assert_eq!(
+            counts(store.tag_list(None).await.unwrap()),
+            vec![
+                ("deploy".to_string(), 0, 1),
+                ("review".to_string(), 1, 1),
+                ("scratch".to_string(), 1, 0),
+            ]
+        );
+
+        // Listing filters need every tag, compared normalized
+        let tagged = |list: Vec<Session>| list.into_iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(
+            tagged(
+                store
+                    .list_sessions_tagged(&tags(&["REVIEW"]))
+                    .await
+                    .unwrap()
+            ),
+            vec![project.id.clone()]
+        );
+        assert!(store
+            .list_sessions_tagged(&tags(&["review", "scratch"]))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.list_sessions_tagged(&[]).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_since_bounds() {
+        use crate::memory::views::parse_since;
+
+        let now = Utc::now();
+        assert_eq!(
+            parse_since("15m", now).unwrap(),
+            now - ChronoDuration::minutes(15)
+        );
+        assert_eq!(
+            parse_since("2h", now).unwrap(),
+            now - ChronoDuration::hours(2)
+        );
+        assert_eq!(
+            parse_since(" 7d ", now).unwrap(),
+            now - ChronoDuration::days(7)
+        );
+        assert_eq!(
+            parse_since("2w", now).unwrap(),
+            now - ChronoDuration::weeks(2)
+        );
+        assert_eq!(
+            parse_since("2024-05-01T12:00:00+02:00", now).unwrap(),
+            "2024-05-01T10:00:00Z"
+                .parse::<chrono::DateTime<Utc>>()
+                .unwrap()
+        );
+        for invalid in ["", "x", "m", "15", "3y", "1.5h"] {
+            assert!(parse_since(invalid, now).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_views_filter_by_each_field_and_report_new_matches() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::views::ViewRecordType;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let session = start_session(&store, "/srv/app").await;
+        let mut old = Command::new(
+            session.id.clone(),
+            "cargo".to_string(),
+            vec!["build".to_string()],
+            "/srv/app".to_string(),
+        );
+        old.started_at = Utc::now() - ChronoDuration::hours(3);
+        let mut recent = Command::new(
+            session.id.clone(),
+            "npm".to_string(),
+            vec!["test".to_string()],
+            "/srv/app/web".to_string(),
+        );
+        recent.started_at = Utc::now() - ChronoDuration::minutes(5);
+        store.store_command(old.clone()).await.unwrap();
+        store.store_command(recent.clone()).await.unwrap();
+        let mut warning = Error::new(
+            old.id.clone(),
+            session.id.clone(),
+            "compile".to_string(),
+            "warning".to_string(),
+            "Unused variable `x`".to_string(),
+        );
+        warning.timestamp = old.started_at;
+        let failure = Error::new(
+            recent.id.clone(),
+            session.id.clone(),
+            "test_failure".to_string(),
+            "error".to_string(),
+            "3 tests failed".to_string(),
+        );
+        store.store_error(warning.clone()).await.unwrap();
+        store.store_error(failure.clone()).await.unwrap();
+        store
+            .tag_add(TagTarget::Command, &recent.id, &["ci".to_string()])
+            .await
+            .unwrap();
+
+        let ids = |filter: ViewFilter| {
+            let store = &store;
+            async move {
+                store
+                    .evaluate_filter(&filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|m| m.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let commands_and_errors = vec![ViewRecordType::Command, ViewRecordType::Error];
+
+        // Record types, newest first
+        assert_eq!(
+            ids(ViewFilter {
+                record_types: vec![ViewRecordType::Command],
+                ..Default::default()
+            })
+            .await,
+            vec![recent.id.clone(), old.id.clone()]
+        );
+        // Severity applies to errors
+        assert_eq!(
+            ids(ViewFilter {
+                record_types: vec![ViewRecordType::Error],
+                severity: vec!["error".to_string()],
+                ..Default::default()
+            })
+            .await,
+            vec![failure.id.clone()]
+        );
+        // Since
+        assert_eq!(
+            ids(ViewFilter {
+                record_types: commands_and_errors.clone(),
+                since: Some("1h".to_string()),
+                ..Default::default()
+            })
+            .await,
+            vec![failure.id.clone(), recent.id.clone()]
+        );
+        // Text, case-insensitive, across every record type
+        assert_eq!(
+            ids(ViewFilter {
+                text: Some("UNUSED".to_string()),
+                ..Default::default()
+            })
+            .await,
+            vec![warning.id.clone()]
+        );
+        // Tags, carried by an error's command
+        let mut tagged = ids(ViewFilter {
+            record_types: commands_and_errors.clone(),
+            tags: vec!["CI".to_string()],
+            ..Default::default()
+        })
+        .await;
+        tagged.sort();
+        let mut expected = vec![failure.id.clone(), recent.id.clone()];
+        expected.sort();
+        assert_eq!(tagged, expected);
+        // Limit keeps the newest
+        assert_eq!(
+            ids(ViewFilter {
+                record_types: vec![ViewRecordType::Command],
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await,
+            vec![recent.id.clone()]
+        );
+        assert!(store
+            .evaluate_filter(&ViewFilter {
+                since: Some("soon".to_string()),
+                ..Default::default()
+            })
+            .await
+            .is_err());
+
+        // The first poll only records what matches; later polls report
+        // records that were not there before, once
+        let view = store
+            .view_save(SavedView::new(
+                "failures".to_string(),
+                ViewFilter {
+                    record_types: vec![ViewRecordType::Error],
+                    severity: vec!["error".to_string()],
+                    ..Default::default()
+                },
+            ))
+            .await
+            .unwrap();
+        let mut watcher = ViewWatcher::new();
+        assert!(watcher.poll(&store).await.unwrap().is_empty());
+        let another = Error::new(
+            recent.id.clone(),
+            session.id.clone(),
+            "test_failure".to_string(),
+            "error".to_string(),
+            "1 test failed".to_string(),
+        );
+        store.store_error(another.clone()).await.unwrap();
+        let changes = watcher.poll(&store).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].view_id, view.id);
+        assert_eq!(changes[0].view_name, "failures");
+        let new: Vec<_> = changes[0].new_matches.iter().map(|m| &m.id).collect();
+        assert_eq!(new, vec![&another.id]);
+        assert!(watcher.poll(&store).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_output_encodings_are_detected_decoded_and_recorded() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::encoding::{decode, detect_encoding, EncodingConfig, OutputDecoder};
+        use encoding_rs::{SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+        let auto = EncodingConfig::default();
+        let latin1 = b"caf\xe9 cr\xe8me br\xfbl\xe9e\n";
+
+        // UTF-8, including a character cut off at the end of the chunk
+        let snowman = "snow \u{2603}\n".as_bytes();
+        assert_eq!(detect_encoding(snowman), UTF_8);
+        assert_eq!(detect_encoding(&snowman[..7]), UTF_8);
+        assert_eq!(
+            decode(snowman, &auto).unwrap(),
+            ("snow \u{2603}\n".to_string(), UTF_8)
+        );
+
+        // UTF-16 is known by its byte order mark, which is not kept
+        let utf16le: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain("h\u{e9}llo".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(
+            decode(&utf16le, &auto).unwrap(),
+            ("h\u{e9}llo".to_string(), UTF_16LE)
+        );
+        let utf16be: Vec<u8> = [0xFE, 0xFF]
+            .into_iter()
+            .chain("hello".encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        assert_eq!(detect_encoding(&utf16be), UTF_16BE);
+
+        // Anything else gets the detector's guess
+        assert_eq!(
+            decode(latin1, &auto).unwrap(),
+            (
+                "caf\u{e9} cr\u{e8}me br\u{fb}l\u{e9}e\n".to_string(),
+                WINDOWS_1252
+            )
+        );
+
+        // A configured encoding is used as is; an unknown one is an error
+        let sjis = EncodingConfig {
+            encoding: "shift_jis".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(sjis.resolve().unwrap(), Some(SHIFT_JIS));
+        assert_eq!(decode(b"\x82\xa0", &sjis).unwrap().0, "\u{3042}");
+        let bogus = EncodingConfig {
+            encoding: "no-such-encoding".to_string(),
+            ..Default::default()
+        };
+        assert!(bogus.resolve().is_err());
+
+        // Streaming: a character split across reads stays UTF-8, and the
+        // first output that is not UTF-8 switches the decoder once
+        let mut decoder = OutputDecoder::new(&auto).unwrap();
+        let first = decoder.decode(&snowman[..6]);
+        let rest = decoder.decode(&snowman[6..]);
+        assert_eq!(first + &rest, "snow \u{2603}\n");
+        assert_eq!(decoder.encoding(), UTF_8);
+        assert_eq!(
+            decoder.decode(latin1),
+            "caf\u{e9} cr\u{e8}me br\u{fb}l\u{e9}e\n"
+        );
+        assert_eq!(decoder.encoding(), WINDOWS_1252);
+
+        // Stored text output is transcoded to UTF-8 and keeps the encoding it
+        // was read in
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        for (bytes, text, encoding) in [
+            (snowman.to_vec(), "snow \u{2603}\n", "UTF-8"),
+            (
+                latin1.to_vec(),
+                "caf\u{e9} cr\u{e8}me br\u{fb}l\u{e9}e\n",
+                "windows-1252",
+            ),
+        ] {
+            let mut output = Output::new("c".to_string(), "stdout".to_string(), 0, bytes);
+            store.store_output(&mut output, false).await.unwrap();
+            let value = store
+                .read_value(&RecordKind::Output.key(&output.id))
+                .await
+                .unwrap()
+                .unwrap();
+            let stored: Output = serde_json::from_value(value).unwrap();
+            assert_eq!(stored.encoding.as_deref(), Some(encoding));
+            assert_eq!(String::from_utf8(stored.content).unwrap(), text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_report_breaks_down_by_type_session_and_workspace() {
+        use crate::memory::backend::InMemoryBackend;
+        use crate::memory::usage::session_workspace;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        // Two sessions in the app workspace, one named by metadata, and one
+        // elsewhere
+        let mut named = Session::new("bash".to_string(), "/home/me".to_string());
+        named.metadata = serde_json::json!({ "workspace": "/srv/app" });
+        assert_eq!(session_workspace(&named), "/srv/app");
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_start".to_string(),
+                timestamp: named.started_at,
+                session_id: named.id.clone(),
+                data: serde_json::to_value(&named).unwrap(),
+                provenance: None,
+            })
+            .await
+            .unwrap();
+        let app = start_session(&store, "/srv/app").await;
+        assert_eq!(session_workspace(&app), "/srv/app");
+        let scratch = start_session(&store, "/tmp/scratch").await;
+
+        let start = Utc::now();
+        seed_outputs(&store, &named.id, start, &[100]).await;
+        let large = seed_outputs(&store, &app.id, start, &[5000]).await;
+        let medium = seed_outputs(&store, &scratch.id, start, &[1000]).await;
+        let report = store.usage_report(2).await.unwrap();
+
+        // By type: each record counted once, at the size it is stored at
+        let by_type = |name: &str| {
+            report
+                .by_type
+                .iter()
+                .find(|t| t.record_type == name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(by_type("session").records, 3);
+        assert_eq!(by_type("command").records, 3);
+        assert_eq!(by_type("output").records, 3);
+        let mut output_bytes = 0;
+        for key in store
+            .client
+            .list(RecordKind::Output.prefix())
+            .await
+            .unwrap()
+        {
+            let raw = store.client.get(&key).await.unwrap().unwrap();
+            output_bytes += serde_json::to_vec(&raw).unwrap().len() as u64;
+        }
+        assert_eq!(by_type("output").bytes, output_bytes);
+        assert_eq!(
+            report.total_bytes,
+            report.by_type.iter().map(|t| t.bytes).sum::<u64>()
+        );
+        assert_eq!(
+            report.total_records,
+            report.by_type.iter().map(|t| t.records).sum::<u64>()
+        );
+
+        // By session, largest first, each with its workspace
+        let sessions: Vec<_> = report
+            .by_session
+            .iter()
+            .map(|s| (s.session_id.as_str(), s.workspace.as_deref()))
+            .collect();
+        assert_eq!(
+            sessions,
+            vec![
+                (app.id.as_str(), Some("/srv/app")),
+                (scratch.id.as_str(), Some("/tmp/scratch")),
+                (named.id.as_str(), Some("/srv/app")),
+            ]
+        );
+        let session_bytes = |id: &str| {
+            report
+                .by_session
+                .iter()
+                .find(|s| s.session_id == id)
+                .unwrap()
+                .bytes
+        };
+        assert!(session_bytes(&app.id) > session_bytes(&scratch.id));
+
+        // By workspace: the sessions in it and their bytes summed
+        let workspaces: Vec<_> = report
+            .by_workspace
+            .iter()
+            .map(|w| (w.workspace.as_str(), w.sessions, w.bytes))
+            .collect();
+        assert_eq!(
+            workspaces,
+            vec![
+                (
+                    "/srv/app",
+                    2,
+                    session_bytes(&app.id) + session_bytes(&named.id)
+                ),
+                ("/tmp/scratch", 1, session_bytes(&scratch.id)),
+            ]
+        );
+
+        // Largest outputs, biggest first, cut to the requested number
+        let largest: Vec<_> = report
+            .largest_outputs
+            .iter()
+            .map(|o| (o.output_id.as_str(), o.session_id.as_deref()))
+            .collect();
+        assert_eq!(
+            largest,
+            vec![
+                (large[0].as_str(), Some(app.id.as_str())),
+                (medium[0].as_str(), Some(scratch.id.as_str())),
+            ]
+        );
+        assert!(report.largest_outputs[0].stored_bytes > report.largest_outputs[1].stored_bytes);
+        assert_eq!(report.largest_outputs[0].uncompressed_bytes, 5000);
+    }
+
+    #[tokio::test]
+    async fn test_artifacts_list_by_session_and_command() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let first = start_session(&store, "/srv/app").await;
+        let second = start_session(&store, "/srv/app").await;
+
+        let mut stored = Vec::new();
+        for (session, command) in [
+            (&first, Some("cmd-1")),
+            (&first, Some("cmd-2")),
+            (&first, None),
+            (&second, Some("cmd-1")),
+        ] {
+            let mut artifact = Artifact::new(
+                "note".to_string(),
+                "text/plain".to_string(),
+                b"captured".to_vec(),
+            );
+            artifact.session_id = Some(session.id.clone());
+            artifact.command_id = command.map(str::to_string);
+            stored.push(artifact.id.clone());
+            store.store_artifact(artifact).await.unwrap();
+        }
+
+        let ids = |artifacts: Vec<Artifact>| -> Vec<String> {
+            let mut ids: Vec<_> = artifacts.into_iter().map(|a| a.id).collect();
+            ids.sort();
+            ids
+        };
+        let expect = |indexes: &[usize]| -> Vec<String> {
+            let mut ids: Vec<_> = indexes.iter().map(|&i| stored[i].clone()).collect();
+            ids.sort();
+            ids
+        };
+
+        let all = store.list_artifacts(None, None).await.unwrap();
+        assert_eq!(ids(all), expect(&[0, 1, 2, 3]));
+        let in_first = store.list_artifacts(Some(&first.id), None).await.unwrap();
+        assert_eq!(ids(in_first), expect(&[0, 1, 2]));
+        let for_command = store.list_artifacts(None, Some("cmd-1")).await.unwrap();
+        assert_eq!(ids(for_command), expect(&[0, 3]));
+        let both = store
+            .list_artifacts(Some(&second.id), Some("cmd-1"))
+            .await
+            .unwrap();
+        assert_eq!(ids(both), expect(&[3]));
+        let none = store
+            .list_artifacts(Some(&second.id), Some("cmd-2"))
+            .await
+            .unwrap();
+        assert!(none.is_empty());
+
+        // Oldest first, content intact
+        let listed = store.list_artifacts(Some(&first.id), None).await.unwrap();
+        assert!(listed
+            .windows(2)
+            .all(|w| w[0].created_at <= w[1].created_at));
+        assert_eq!(listed[0].content, b"captured");
+        assert_eq!(listed[0].size_bytes, 8);
+    }
+
+    #[cfg(feature = "capture")]
+    #[tokio::test]
+    async fn test_png_artifact_round_trips_through_the_store() {
+        use crate::memory::backend::InMemoryBackend;
+
+        let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+        let session = start_session(&store, "/srv/app").await;
+
+        // 2x2 pixels: red, green, blue, transparent
+        let rgba = vec![255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 0, 0, 0, 0];
+        let mut artifact = crate::capture::png_artifact("screenshot", 2, 2, rgba.clone()).unwrap();
+        assert_eq!(artifact.mime_type, "image/png");
+        assert_eq!(artifact.metadata["width"], 2);
+        assert_eq!(artifact.metadata["height"], 2);
+        assert!(crate::capture::png_artifact("screenshot", 3, 3, rgba.clone()).is_err());
+
+        artifact.session_id = Some(session.id.clone());
+        artifact.command_id = Some("cmd-1".to_string());
+        let id = artifact.id.clone();
+        store.store_artifact(artifact).await.unwrap();
+
+        let listed = store
+            .list_artifacts(Some(&session.id), Some("cmd-1"))
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, id);
+        assert_eq!(listed[0].artifact_type, "screenshot");
+        assert_eq!(listed[0].size_bytes, listed[0].content.len() as u64);
+
+        let decoded =
+            image::load_from_memory_with_format(&listed[0].content, image::ImageFormat::Png)
+                .unwrap()
+                .to_rgba8();
+        assert_eq!(decoded.dimensions(), (2, 2));
+        assert_eq!(decoded.into_raw(), rgba);
+    }
 }
@@ -0,0 +1,210 @@
+// Disk usage reporting for the memory store
+// Sums stored record sizes per record type, per session, and per workspace
+
+use crate::memory::api::MemoryStore;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+
+/// Record types covered by the usage report
+pub const RECORD_KINDS: &[RecordKind] = &[
+    RecordKind::Session,
+    RecordKind::Command,
+    RecordKind::Output,
+    RecordKind::OutputIndex,
+    RecordKind::Error,
+    RecordKind::Insight,
+    RecordKind::Suggestion,
+    RecordKind::Provenance,
+    RecordKind::Event,
+    RecordKind::Artifact,
+    RecordKind::Impression,
+    RecordKind::Benchmark,
+    RecordKind::Flaky,
+    RecordKind::View,
+    RecordKind::Bookmark,
+    RecordKind::Preference,
+    RecordKind::DayStats,
+    RecordKind::Wal,
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TypeUsage {
+    pub record_type: String,
+    pub records: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SessionUsage {
+    pub session_id: String,
+    pub workspace: Option<String>,
+    pub records: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WorkspaceUsage {
+    pub workspace: String,
+    pub sessions: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OutputUsage {
+    pub output_id: String,
+    pub command_id: String,
+    pub session_id: Option<String>,
+    pub stored_bytes: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed: bool,
+}
+
+/// Storage consumed by the memory store, broken down for pruning decisions
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UsageReport {
+    pub total_records: u64,
+    pub total_bytes: u64,
+    pub by_type: Vec<TypeUsage>,
+    pub by_session: Vec<SessionUsage>,
+    pub by_workspace: Vec<WorkspaceUsage>,
+    pub largest_outputs: Vec<OutputUsage>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Workspace a session belongs to: explicit `metadata.workspace`, else its initial cwd
+pub fn session_workspace(session: &Session) -> String {
+    session
+        .metadata
+        .get("workspace")
+        .and_then(|w| w.as_str())
+        .map(|w| w.to_string())
+        .unwrap_or_else(|| session.initial_cwd.clone())
+}
+
+impl MemoryStore {
+    /// Compute storage usage by summing the size of every stored record.
+    ///
+    /// Sizes are the serialized size of the value as held by the backend
+    /// (i.e. after compression/encryption), which is what pruning would free.
+    pub async fn usage_report(&self, top_n: usize) -> Result<UsageReport> {
+        let mut by_type = Vec::new();
+        let mut session_bytes: HashMap<String, SessionUsage> = HashMap::new();
+        let mut command_sessions: HashMap<String, String> = HashMap::new();
+        let mut workspaces: HashMap<String, String> = HashMap::new();
+        let mut outputs = Vec::new();
+
+        for kind in RECORD_KINDS {
+            let mut usage = TypeUsage {
+                record_type: kind.name().to_string(),
+                ..Default::default()
+            };
+
+            for key in self.client.list(kind.prefix()).await? {
+                let Some(raw) = self.client.get(&key).await? else {
+                    continue;
+                };
+                let bytes = serde_json::to_vec(&raw)?.len() as u64;
+                usage.records += 1;
+                usage.bytes += bytes;
+
+                let value = self.decrypt_value(raw).await?;
+                let session_id = match kind {
+                    RecordKind::Session => serde_json::from_value::<Session>(value).ok().map(|s| {
+                        workspaces.insert(s.id.clone(), session_workspace(&s));
+                        s.id
+                    }),
+                    RecordKind::Command => serde_json::from_value::<Command>(value).ok().map(|c| {
+                        command_sessions.insert(c.id.clone(), c.session_id.clone());
+                        c.session_id
+                    }),
+                    RecordKind::Output => {
+                        serde_json::from_value::<Output>(value).ok().and_then(|o| {
+                            let session_id = command_sessions.get(&o.command_id).cloned();
+                            outputs.push(OutputUsage {
+                                output_id: o.id,
+                                command_id: o.command_id,
+                                session_id: session_id.clone(),
+                                stored_bytes: bytes,
+                                uncompressed_bytes: o.size_bytes,
+                                compressed: o.compressed,
+                            });
+                            session_id
+                        })
+                    }
+                    RecordKind::OutputIndex => value
+                        .get("command_id")
+                        .and_then(|c| c.as_str())
+                        .and_then(|c| command_sessions.get(c).cloned()),
+                    _ => value
+                        .get("session_id")
+                        .and_then(|s| s.as_str())
+                        .map(|s| s.to_string()),
+                };
+
+                if let Some(session_id) = session_id {
+                    let entry =
+                        session_bytes
+                            .entry(session_id.clone())
+                            .or_insert_with(|| SessionUsage {
+                                session_id,
+                                ..Default::default()
+                            });
+                    entry.records += 1;
+                    entry.bytes += bytes;
+                }
+            }
+
+            by_type.push(usage);
+        }
+
+        let mut by_session: Vec<SessionUsage> = session_bytes
+            .into_values()
+            .map(|mut usage| {
+                usage.workspace = workspaces.get(&usage.session_id).cloned();
+                usage
+            })
+            .collect();
+        by_session.sort_by_key(|usage| std::cmp::Reverse(usage.bytes));
+
+        let mut workspace_totals: HashMap<String, WorkspaceUsage> = HashMap::new();
+        for usage in &by_session {
+            let workspace = usage
+                .workspace
+                .clone()
+                .unwrap_or_else(|| "(unknown)".to_string());
+            let entry = workspace_totals
+                .entry(workspace.clone())
+                .or_insert_with(|| WorkspaceUsage {
+                    workspace,
+                    ..Default::default()
+                });
+            entry.sessions += 1;
+            entry.bytes += usage.bytes;
+        }
+        let mut by_workspace: Vec<WorkspaceUsage> = workspace_totals.into_values().collect();
+        by_workspace.sort_by_key(|usage| std::cmp::Reverse(usage.bytes));
+
+        outputs.sort_by_key(|output| std::cmp::Reverse(output.stored_bytes));
+        outputs.truncate(top_n);
+
+        Ok(UsageReport {
+            total_records: by_type.iter().map(|t| t.records).sum(),
+            total_bytes: by_type.iter().map(|t| t.bytes).sum(),
+            by_type,
+            by_session,
+            by_workspace,
+            largest_outputs: outputs,
+            generated_at: Utc::now(),
+        })
+    }
+}
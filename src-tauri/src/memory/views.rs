@@ -0,0 +1,404 @@
+// Saved queries ("smart views") over memory
+// Named filters persisted in the store, evaluated on demand and watched for new matches
+
+use crate::memory::api::MemoryStore;
+use crate::memory::bookmarks::Bookmark;
+use crate::memory::keys::RecordKind;
+use crate::memory::schema::*;
+use crate::memory::tags::normalize_tag;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// Record types a view can match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ViewRecordType {
+    Session,
+    Command,
+    Error,
+    Insight,
+    Artifact,
+    Bookmark,
+}
+
+impl ViewRecordType {
+    const ALL: [ViewRecordType; 6] = [
+        ViewRecordType::Session,
+        ViewRecordType::Command,
+        ViewRecordType::Error,
+        ViewRecordType::Insight,
+        ViewRecordType::Artifact,
+        ViewRecordType::Bookmark,
+    ];
+
+    fn prefix(self) -> &'static str {
+        match self {
+            ViewRecordType::Session => RecordKind::Session.prefix(),
+            ViewRecordType::Command => RecordKind::Command.prefix(),
+            ViewRecordType::Error => RecordKind::Error.prefix(),
+            ViewRecordType::Insight => RecordKind::Insight.prefix(),
+            ViewRecordType::Artifact => RecordKind::Artifact.prefix(),
+            ViewRecordType::Bookmark => RecordKind::Bookmark.prefix(),
+        }
+    }
+}
+
+/// Filter criteria of a view; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ViewFilter {
+    /// Record types to include (empty = all)
+    pub record_types: Vec<ViewRecordType>,
+    /// Tags the record's command or session must all carry
+    pub tags: Vec<String>,
+    /// Error severities to include (empty = any); other record types ignore this
+    pub severity: Vec<String>,
+    /// Relative ("30m", "24h", "7d", "2w") or RFC 3339 lower time bound
+    pub since: Option<String>,
+    /// Case-insensitive substring searched in the record's text fields
+    pub text: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// A named, persisted query
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SavedView {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub filter: ViewFilter,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SavedView {
+    pub fn new(name: String, filter: ViewFilter) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            description: None,
+            filter,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// One record matched by a view
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ViewMatch {
+    pub record_type: ViewRecordType,
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub title: String,
+    pub record: Value,
+}
+
+/// New matches for a view since the last poll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewChange {
+    pub view_id: String,
+    pub view_name: String,
+    pub new_matches: Vec<ViewMatch>,
+}
+
+/// Parse a `since` bound: "<n>m", "<n>h", "<n>d", "<n>w", or an RFC 3339 timestamp
+pub fn parse_since(since: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let since = since.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(since) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let split = since.char_indices().last().map_or(0, |(i, _)| i);
+    let (amount, unit) = since.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("Invalid time bound: {}", since))?;
+    let duration = match unit {
+        "m" => ChronoDuration::minutes(amount),
+        "h" => ChronoDuration::hours(amount),
+        "d" => ChronoDuration::days(amount),
+        "w" => ChronoDuration::weeks(amount),
+        _ => anyhow::bail!("Invalid time unit in {}", since),
+    };
+    Ok(now - duration)
+}
+
+/// Fields common to all matchable records
+struct Candidate {
+    id: String,
+    timestamp: DateTime<Utc>,
+    title: String,
+    text: String,
+    severity: Option<String>,
+    session_id: Option<String>,
+    command_id: Option<String>,
+}
+
+fn candidate(record_type: ViewRecordType, value: &Value) -> Option<Candidate> {
+    Some(match record_type {
+        ViewRecordType::Session => {
+            let s: Session = serde_json::from_value(value.clone()).ok()?;
+            Candidate {
+                title: format!("{} session in {}", s.shell_type, s.initial_cwd),
+                text: s.initial_cwd.clone(),
+                timestamp: s.started_at,
+                session_id: Some(s.id.clone()),
+                id: s.id,
+                severity: None,
+                command_id: None,
+            }
+        }
+        ViewRecordType::Command => {
+            let c: Command = serde_json::from_value(value.clone()).ok()?;
+            let line = std::iter::once(c.command.clone())
+                .chain(c.args.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Candidate {
+                title: line.clone(),
+                text: format!("{} {}", line, c.cwd),
+                timestamp: c.started_at,
+                session_id: Some(c.session_id),
+                command_id: Some(c.id.clone()),
+                id: c.id,
+                severity: None,
+            }
+        }
+        ViewRecordType::Error => {
+            let e: Error = serde_json::from_value(value.clone()).ok()?;
+            Candidate {
+                title: e.message.clone(),
+                text: format!(
+                    "{} {} {}",
+                    e.error_type,
+                    e.message,
+                    e.stderr_snippet.as_deref().unwrap_or_default()
+                ),
+                timestamp: e.timestamp,
+                severity: Some(e.severity),
+                session_id: Some(e.session_id),
+                command_id: Some(e.command_id),
+                id: e.id,
+            }
+        }
+        ViewRecordType::Insight => {
+            let i: Insight = serde_json::from_value(value.clone()).ok()?;
+            Candidate {
+                title: i.title.clone(),
+                text: format!("{} {}", i.title, i.description),
+                timestamp: i.generated_at,
+                severity: None,
+                session_id: i.session_id,
+                command_id: i.command_id,
+                id: i.id,
+            }
+        }
+        ViewRecordType::Artifact => {
+            let a: Artifact = serde_json::from_value(value.clone()).ok()?;
+            Candidate {
+                title: format!("{} ({})", a.artifact_type, a.mime_type),
+                text: a.artifact_type.clone(),
+                timestamp: a.created_at,
+                severity: None,
+                session_id: a.session_id,
+                command_id: a.command_id,
+                id: a.id,
+            }
+        }
+        ViewRecordType::Bookmark => {
+            let b: Bookmark = serde_json::from_value(value.clone()).ok()?;
+            Candidate {
+                title: b.note.clone().unwrap_or_else(|| b.command_line.clone()),
+                text: format!(
+                    "{} {} {}",
+                    b.note.as_deref().unwrap_or_default(),
+                    b.command_line,
+                    b.excerpt.join("\n")
+                ),
+                timestamp: b.created_at,
+                severity: None,
+                session_id: Some(b.session_id),
+                command_id: Some(b.command_id),
+                id: b.id,
+            }
+        }
+    })
+}
+
+impl MemoryStore {
+    /// Create or replace a saved view
+    pub async fn view_save(&self, mut view: SavedView) -> Result<SavedView> {
+        if let Some(since) = &view.filter.since {
+            parse_since(since, Utc::now())?;
+        }
+        view.updated_at = Utc::now();
+        self.write_value(
+            &RecordKind::View.key(&view.id),
+            serde_json::to_value(&view)?,
+        )
+        .await?;
+        Ok(view)
+    }
+
+    pub async fn view_delete(&self, view_id: &str) -> Result<()> {
+        self.client.delete(&RecordKind::View.key(view_id)).await
+    }
+
+    pub async fn view_get(&self, view_id: &str) -> Result<SavedView> {
+        let value = self
+            .read_value(&RecordKind::View.key(view_id))
+            .await?
+            .with_context(|| format!("View not found: {}", view_id))?;
+        serde_json::from_value(value).context("Failed to deserialize view")
+    }
+
+    /// All saved views, by name
+    pub async fn view_list(&self) -> Result<Vec<SavedView>> {
+        let mut views = Vec::new();
+        for key in self.client.list(RecordKind::View.prefix()).await? {
+            if let Some(value) = self.read_value(&key).await? {
+                if let Ok(view) = serde_json::from_value::<SavedView>(value) {
+                    views.push(view);
+                }
+            }
+        }
+        views.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(views)
+    }
+
+    /// Run a view's filter, newest matches first
+    pub async fn view_evaluate(&self, view_id: &str) -> Result<Vec<ViewMatch>> {
+        let view = self.view_get(view_id).await?;
+        self.evaluate_filter(&view.filter).await
+    }
+
+    pub async fn evaluate_filter(&self, filter: &ViewFilter) -> Result<Vec<ViewMatch>> {
+        let since = filter
+            .since
+            .as_deref()
+            .map(|s| parse_since(s, Utc::now()))
+            .transpose()?;
+        let text = filter.text.as_deref().map(str::to_lowercase);
+        let tags: Vec<String> = filter
+            .tags
+            .iter()
+            .filter_map(|t| normalize_tag(t))
+            .collect();
+        let index = if tags.is_empty() {
+            None
+        } else {
+            Some(self.tag_index().await?)
+        };
+        let record_types: &[ViewRecordType] = if filter.record_types.is_empty() {
+            &ViewRecordType::ALL
+        } else {
+            &filter.record_types
+        };
+
+        let mut matches = Vec::new();
+        for &record_type in record_types {
+            for key in self.client.list(record_type.prefix()).await? {
+                let Some(value) = self.read_value(&key).await? else {
+                    continue;
+                };
+                let Some(c) = candidate(record_type, &value) else {
+                    continue;
+                };
+
+                if since.is_some_and(|since| c.timestamp < since) {
+                    continue;
+                }
+                if !filter.severity.is_empty()
+                    && !c
+                        .severity
+                        .as_ref()
+                        .is_some_and(|s| filter.severity.contains(s))
+                {
+                    continue;
+                }
+                if let Some(text) = &text {
+                    if !c.text.to_lowercase().contains(text) {
+                        continue;
+                    }
+                }
+                if let Some(index) = &index {
+                    let tagged = match (&c.command_id, &c.session_id) {
+                        (Some(command_id), _) => index.command_matches(command_id, &tags),
+                        (None, Some(session_id)) => index.session_matches(session_id, &tags),
+                        (None, None) => false,
+                    };
+                    if !tagged {
+                        continue;
+                    }
+                }
+
+                matches.push(ViewMatch {
+                    record_type,
+                    id: c.id,
+                    timestamp: c.timestamp,
+                    title: c.title,
+                    record: value,
+                });
+            }
+        }
+
+        matches.sort_by_key(|m| std::cmp::Reverse(m.timestamp));
+        if let Some(limit) = filter.limit {
+            matches.truncate(limit);
+        }
+        Ok(matches)
+    }
+}
+
+/// Tracks which records each view has already reported, to surface new matches
+#[derive(Default)]
+pub struct ViewWatcher {
+    seen: HashMap<String, HashSet<String>>,
+}
+
+impl ViewWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate every view and return those with records not seen before.
+    ///
+    /// The first poll of a view only records its current matches.
+    pub async fn poll(&mut self, store: &MemoryStore) -> Result<Vec<ViewChange>> {
+        let views = store.view_list().await?;
+        let live: HashSet<&str> = views.iter().map(|v| v.id.as_str()).collect();
+        self.seen.retain(|id, _| live.contains(id.as_str()));
+
+        let mut changes = Vec::new();
+        for view in views {
+            let matches = store.evaluate_filter(&view.filter).await?;
+            let ids: HashSet<String> = matches.iter().map(|m| m.id.clone()).collect();
+            let Some(seen) = self.seen.insert(view.id.clone(), ids) else {
+                continue;
+            };
+
+            let new_matches: Vec<ViewMatch> = matches
+                .into_iter()
+                .filter(|m| !seen.contains(&m.id))
+                .collect();
+            if !new_matches.is_empty() {
+                changes.push(ViewChange {
+                    view_id: view.id,
+                    view_name: view.name,
+                    new_matches,
+                });
+            }
+        }
+        Ok(changes)
+    }
+}
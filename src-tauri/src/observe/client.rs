@@ -0,0 +1,130 @@
+//! Following a session shared by another RuneBook instance.
+
+use crate::memory::SessionEvent;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Follow `session_id` on the RuneBook at `base_url` (e.g.
+/// `http://10.0.0.5:7717`) with its share token, passing each event to `send`.
+/// Returns when the share ends or `send` fails.
+pub async fn follow(
+    base_url: &str,
+    session_id: &str,
+    token: &str,
+    mut send: impl FnMut(SessionEvent) -> Result<()>,
+) -> Result<()> {
+    let url = format!(
+        "{}{}",
+        base_url.trim_end_matches('/'),
+        super::feed_path(session_id)
+    );
+    let mut response = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(token)
+        .header(reqwest::header::ACCEPT, "text/event-stream")
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("{} refused to share the session ({})", url, status);
+    }
+
+    let mut parser = EventParser::default();
+    while let Some(bytes) = response
+        .chunk()
+        .await
+        .context("Connection to the shared session dropped")?
+    {
+        for data in parser.feed(&bytes) {
+            match serde_json::from_str::<SessionEvent>(&data) {
+                Ok(event) => send(event)?,
+                Err(e) => log::warn!("[observe] Skipping unreadable event: {}", e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sessions this instance observes, by observation id, so the UI can stop them
+#[derive(Default)]
+pub struct Observations {
+    running: Mutex<HashMap<String, tokio::task::AbortHandle>>,
+}
+
+impl Observations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// [`follow`] until the share ends or [`Self::stop`] is called with `id`
+    pub async fn follow(
+        &self,
+        id: &str,
+        base_url: String,
+        session_id: String,
+        token: String,
+        send: impl FnMut(SessionEvent) -> Result<()> + Send + 'static,
+    ) -> Result<()> {
+        let task = tokio::spawn(async move { follow(&base_url, &session_id, &token, send).await });
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.to_string(), task.abort_handle());
+        let result = task.await;
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id);
+        match result {
+            Ok(followed) => followed,
+            Err(e) if e.is_cancelled() => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stop observing. Returns false when `id` is not being followed.
+    pub fn stop(&self, id: &str) -> bool {
+        match self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+        {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Splits a server-sent event stream into the `data` of its events
+#[derive(Default)]
+pub(super) struct EventParser {
+    buffer: Vec<u8>,
+}
+
+impl EventParser {
+    /// The data of each event completed by `bytes`
+    pub(super) fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let text = String::from_utf8_lossy(&block);
+            let lines: Vec<&str> = text
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            // Comments and keep-alives carry no data
+            if !lines.is_empty() {
+                events.push(lines.join("\n"));
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,220 @@
+//! Read-only observers of live sessions.
+//!
+//! For remote pairing or an incident review, a session can be shared.
+//! Sharing mints a token for that one session, with which its holder follows
+//! the session's live feed ([`SessionEvent`]s: command starts, output chunks
+//! and finishes) over the HTTP API, `GET /api/v1/observe/{session_id}` as
+//! server-sent events, or from another RuneBook instance with
+//! [`client::follow`]. The token opens nothing else: observers cannot run
+//! commands, query memory, or see environments. Arguments and output reach
+//! them as the memory store keeps them, scrubbed.
+//!
+//! Every session starts unshared. Share tokens live only in memory, so
+//! stopping a share, revoking its token, or quitting RuneBook ends the
+//! observations it allowed at once.
+
+pub mod client;
+
+#[cfg(test)]
+mod tests;
+
+use crate::memory::{LiveOutputs, SessionEvent};
+use crate::server::rbac::{hash_secret, same_secret};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use ts_rs::TS;
+
+/// Start of every share token, telling it apart from API tokens
+pub const SHARE_TOKEN_PREFIX: &str = "rbs_";
+
+/// Path of a session's feed on the HTTP API
+pub fn feed_path(session_id: &str) -> String {
+    format!("/api/v1/observe/{}", session_id)
+}
+
+/// A session shared with observers, without its token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Share {
+    pub id: String,
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A new share with its token, which is shown only this once
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ShareGrant {
+    pub share: Share,
+    pub token: String,
+    /// Where observers follow the session on the HTTP API
+    pub path: String,
+}
+
+struct ShareRecord {
+    share: Share,
+    /// Hex SHA-256 of the token
+    hash: String,
+}
+
+/// Sharing state of every session
+pub struct ShareRegistry {
+    shares: Mutex<Vec<ShareRecord>>,
+    /// Ids of shares as they are revoked, ending their observations
+    revoked: broadcast::Sender<String>,
+}
+
+impl Default for ShareRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShareRegistry {
+    pub fn new() -> Self {
+        Self {
+            shares: Mutex::new(Vec::new()),
+            revoked: broadcast::channel(16).0,
+        }
+    }
+
+    /// Share `session_id`, minting a token for it
+    pub fn start(&self, session_id: &str) -> ShareGrant {
+        let token = format!(
+            "{}{}{}",
+            SHARE_TOKEN_PREFIX,
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let share = Share {
+            id: uuid::Uuid::new_v4().simple().to_string()[..12].to_string(),
+            session_id: session_id.to_string(),
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        self.shares
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(ShareRecord {
+                share: share.clone(),
+                hash: hash_secret(&token),
+            });
+        log::info!("[observe] Session {} shared ({})", session_id, share.id);
+        ShareGrant {
+            share,
+            token,
+            path: feed_path(session_id),
+        }
+    }
+
+    /// Stop sharing `session_id`, revoking all its tokens. Returns how many
+    /// were active.
+    pub fn stop(&self, session_id: &str) -> usize {
+        self.revoke_where(|share| share.session_id == session_id)
+    }
+
+    /// Revoke one share by id. Returns false when no active share has it.
+    pub fn revoke(&self, id: &str) -> bool {
+        self.revoke_where(|share| share.id == id) > 0
+    }
+
+    fn revoke_where(&self, matches: impl Fn(&Share) -> bool) -> usize {
+        let revoked: Vec<String> = {
+            let mut shares = self.shares.lock().unwrap_or_else(|e| e.into_inner());
+            shares
+                .iter_mut()
+                .filter(|r| r.share.revoked_at.is_none() && matches(&r.share))
+                .map(|r| {
+                    r.share.revoked_at = Some(Utc::now());
+                    r.share.id.clone()
+                })
+                .collect()
+        };
+        for id in &revoked {
+            log::info!("[observe] Share {} revoked", id);
+            let _ = self.revoked.send(id.clone());
+        }
+        revoked.len()
+    }
+
+    /// Every share of this run, newest first
+    pub fn list(&self) -> Vec<Share> {
+        let mut shares: Vec<Share> = self
+            .shares
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|r| r.share.clone())
+            .collect();
+        shares.reverse();
+        shares
+    }
+
+    pub fn is_shared(&self, session_id: &str) -> bool {
+        self.shares
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|r| r.share.session_id == session_id && r.share.revoked_at.is_none())
+    }
+
+    /// The active share of `session_id` that `token` belongs to
+    pub fn authenticate(&self, session_id: &str, token: &str) -> Option<Share> {
+        let hash = hash_secret(token);
+        self.shares
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|r| r.share.session_id == session_id && r.share.revoked_at.is_none())
+            .find(|r| same_secret(&r.hash, &hash))
+            .map(|r| r.share.clone())
+    }
+
+    /// Pass the shared session's feed to `send` until `share` is revoked or
+    /// `send` fails, as when the observer goes away. Starts with the commands
+    /// still running. Events an observer falls too far behind on are skipped.
+    pub async fn observe(
+        &self,
+        live: &LiveOutputs,
+        share: &Share,
+        mut send: impl FnMut(SessionEvent) -> Result<()>,
+    ) -> Result<()> {
+        // Subscribe first, so a revocation while joining is not missed
+        let mut revoked = self.revoked.subscribe();
+        if !self.is_active(&share.id) {
+            return Ok(());
+        }
+        let (running, mut events) = live.observe_session(&share.session_id);
+        for event in running {
+            send(event)?;
+        }
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => send(event)?,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("[observe] Observer of share {} skipped {} events", share.id, skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                id = revoked.recv() => match id {
+                    Ok(id) if id != share.id => {}
+                    _ => return Ok(()),
+                },
+            }
+        }
+    }
+
+    fn is_active(&self, id: &str) -> bool {
+        self.shares
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|r| r.share.id == id && r.share.revoked_at.is_none())
+    }
+}
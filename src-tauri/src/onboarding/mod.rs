@@ -0,0 +1,342 @@
+//! First-run onboarding.
+//!
+//! The setup wizard walks through [`OnboardingStep::ALL`] in order: where
+//! RuneBook keeps its data, which PluresDB server backs the memory store,
+//! whether to opt in to encryption at rest, which shells get the capture hook,
+//! and which example canvases to import. Progress lives in `onboarding.json`
+//! in the config directory, so a wizard closed halfway resumes at the first
+//! unfinished step and the frontend never keeps its own copy.
+//!
+//! Completing or skipping a step is idempotent: repeating it with the same
+//! answer changes nothing, and a new answer replaces the old one while keeping
+//! the time the step was first finished.
+
+#[cfg(test)]
+mod tests;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use ts_rs::TS;
+
+/// Shells with a capture hook the wizard can install
+pub const SUPPORTED_SHELLS: [&str; 2] = ["bash", "zsh"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum OnboardingStep {
+    DataDir,
+    Backend,
+    Encryption,
+    ShellHook,
+    ExampleCanvas,
+}
+
+impl OnboardingStep {
+    /// Every step, in wizard order
+    pub const ALL: [OnboardingStep; 5] = [
+        OnboardingStep::DataDir,
+        OnboardingStep::Backend,
+        OnboardingStep::Encryption,
+        OnboardingStep::ShellHook,
+        OnboardingStep::ExampleCanvas,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OnboardingStep::DataDir => "data_dir",
+            OnboardingStep::Backend => "backend",
+            OnboardingStep::Encryption => "encryption",
+            OnboardingStep::ShellHook => "shell_hook",
+            OnboardingStep::ExampleCanvas => "example_canvas",
+        }
+    }
+
+    /// Steps the app cannot run without; these cannot be skipped
+    pub fn required(self) -> bool {
+        matches!(self, OnboardingStep::DataDir | OnboardingStep::Backend)
+    }
+}
+
+/// The user's answer to one step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "step", rename_all = "snake_case")]
+#[ts(export)]
+pub enum StepAnswer {
+    /// Directory for local data; created when the step completes
+    DataDir { path: PathBuf },
+    /// PluresDB server backing the memory store
+    Backend { host: String, port: u16 },
+    /// Opt-in to encrypting memory records at rest
+    Encryption { enabled: bool },
+    /// Shells the capture hook was installed into
+    ShellHook { shells: Vec<String> },
+    /// Example canvases that were imported, by name
+    ExampleCanvas { canvases: Vec<String> },
+}
+
+impl StepAnswer {
+    pub fn step(&self) -> OnboardingStep {
+        match self {
+            StepAnswer::DataDir { .. } => OnboardingStep::DataDir,
+            StepAnswer::Backend { .. } => OnboardingStep::Backend,
+            StepAnswer::Encryption { .. } => OnboardingStep::Encryption,
+            StepAnswer::ShellHook { .. } => OnboardingStep::ShellHook,
+            StepAnswer::ExampleCanvas { .. } => OnboardingStep::ExampleCanvas,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            StepAnswer::DataDir { path } if path.as_os_str().is_empty() => {
+                anyhow::bail!("The data directory is empty")
+            }
+            StepAnswer::Backend { host, port } if host.trim().is_empty() || *port == 0 => {
+                anyhow::bail!("The backend needs a host and a port")
+            }
+            StepAnswer::ShellHook { shells } => {
+                if let Some(unknown) = shells
+                    .iter()
+                    .find(|s| !SUPPORTED_SHELLS.contains(&s.as_str()))
+                {
+                    anyhow::bail!(
+                        "No shell hook for {} (supported: {})",
+                        unknown,
+                        SUPPORTED_SHELLS.join(", ")
+                    );
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum StepStatus {
+    #[default]
+    Pending,
+    Completed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StepState {
+    pub step: OnboardingStep,
+    #[serde(default)]
+    pub status: StepStatus,
+    #[serde(default)]
+    pub answer: Option<StepAnswer>,
+    /// When the step was first completed or skipped
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl StepState {
+    fn pending(step: OnboardingStep) -> Self {
+        Self {
+            step,
+            status: StepStatus::Pending,
+            answer: None,
+            finished_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct OnboardingState {
+    /// One entry per step, in wizard order
+    pub steps: Vec<StepState>,
+    /// The first pending step; `None` once setup is done
+    pub current_step: Option<OnboardingStep>,
+    /// When the first step was finished
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the last pending step was finished
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl Default for OnboardingState {
+    fn default() -> Self {
+        let mut state = Self {
+            steps: Vec::new(),
+            current_step: None,
+            started_at: None,
+            completed_at: None,
+        };
+        state.normalize();
+        state
+    }
+}
+
+impl OnboardingState {
+    pub fn is_complete(&self) -> bool {
+        self.current_step.is_none()
+    }
+
+    pub fn step(&self, step: OnboardingStep) -> &StepState {
+        self.steps
+            .iter()
+            .find(|s| s.step == step)
+            .expect("every step has an entry")
+    }
+
+    /// The answer a completed step was given
+    pub fn answer(&self, step: OnboardingStep) -> Option<&StepAnswer> {
+        let state = self.step(step);
+        (state.status == StepStatus::Completed)
+            .then_some(state.answer.as_ref())
+            .flatten()
+    }
+
+    fn step_mut(&mut self, step: OnboardingStep) -> &mut StepState {
+        self.steps
+            .iter_mut()
+            .find(|s| s.step == step)
+            .expect("every step has an entry")
+    }
+
+    /// Put the steps in wizard order, adding ones missing from an older file,
+    /// and derive the current step from them
+    fn normalize(&mut self) {
+        let mut steps = Vec::with_capacity(OnboardingStep::ALL.len());
+        for step in OnboardingStep::ALL {
+            let state = match self.steps.iter().position(|s| s.step == step) {
+                Some(i) => self.steps.swap_remove(i),
+                None => StepState::pending(step),
+            };
+            steps.push(state);
+        }
+        self.steps = steps;
+        self.current_step = self
+            .steps
+            .iter()
+            .find(|s| s.status == StepStatus::Pending)
+            .map(|s| s.step);
+        if self.current_step.is_some() {
+            self.completed_at = None;
+        }
+    }
+
+    fn finish(&mut self, step: OnboardingStep, status: StepStatus, answer: Option<StepAnswer>) {
+        let now = Utc::now();
+        let state = self.step_mut(step);
+        if state.status != status {
+            state.finished_at = Some(now);
+        }
+        state.status = status;
+        state.answer = answer;
+        self.started_at.get_or_insert(now);
+        self.normalize();
+        if self.is_complete() {
+            self.completed_at.get_or_insert(now);
+        }
+    }
+}
+
+/// Persisted onboarding progress, shared by the wizard's commands
+pub struct Onboarding {
+    path: PathBuf,
+    state: Mutex<OnboardingState>,
+}
+
+impl Onboarding {
+    /// `onboarding.json` in the config directory
+    pub fn default_path() -> PathBuf {
+        crate::config::config_dir().join("onboarding.json")
+    }
+
+    /// Load progress from `path`; a missing or unreadable file starts over
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let state = match load_state(&path) {
+            Ok(state) => state.unwrap_or_default(),
+            Err(e) => {
+                log::warn!("[onboarding] Starting over: {:#}", e);
+                OnboardingState::default()
+            }
+        };
+        Self {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn state(&self) -> OnboardingState {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Record the answer to a step, performing its side effect first
+    pub fn complete_step(&self, answer: StepAnswer) -> Result<OnboardingState> {
+        answer.validate()?;
+        let step = answer.step();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.answer(step) == Some(&answer) {
+            return Ok(state.clone());
+        }
+        if let StepAnswer::DataDir { path } = &answer {
+            std::fs::create_dir_all(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+        }
+        let mut updated = state.clone();
+        updated.finish(step, StepStatus::Completed, Some(answer));
+        self.save(&updated)?;
+        *state = updated;
+        Ok(state.clone())
+    }
+
+    /// Pass over an optional step, dropping any earlier answer to it
+    pub fn skip_step(&self, step: OnboardingStep) -> Result<OnboardingState> {
+        if step.required() {
+            anyhow::bail!("The {} step cannot be skipped", step.as_str());
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.step(step).status == StepStatus::Skipped {
+            return Ok(state.clone());
+        }
+        let mut updated = state.clone();
+        updated.finish(step, StepStatus::Skipped, None);
+        self.save(&updated)?;
+        *state = updated;
+        Ok(state.clone())
+    }
+
+    /// Forget all progress, so the wizard runs again from the first step
+    pub fn reset(&self) -> Result<OnboardingState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let fresh = OnboardingState::default();
+        self.save(&fresh)?;
+        *state = fresh;
+        Ok(state.clone())
+    }
+
+    /// Write through a temporary file, so a crash mid-write keeps the old state
+    fn save(&self, state: &OnboardingState) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let partial = self.path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_vec_pretty(state)?)
+            .and_then(|()| std::fs::rename(&partial, &self.path))
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+fn load_state(path: &Path) -> Result<Option<OnboardingState>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut state: OnboardingState = serde_json::from_slice(&bytes)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    state.normalize();
+    Ok(Some(state))
+}
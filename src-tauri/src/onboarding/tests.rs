@@ -0,0 +1,73 @@
+use super::*;
+
+#[test]
+fn progress_survives_a_restart_and_steps_are_idempotent() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("onboarding.json");
+    let data_dir = dir.path().join("data");
+
+    let onboarding = Onboarding::open(&path);
+    assert_eq!(
+        onboarding.state().current_step,
+        Some(OnboardingStep::DataDir)
+    );
+    let answer = StepAnswer::DataDir {
+        path: data_dir.clone(),
+    };
+    let first = onboarding.complete_step(answer.clone()).unwrap();
+    assert!(data_dir.is_dir());
+    assert_eq!(first.current_step, Some(OnboardingStep::Backend));
+    // Completing again with the same answer changes nothing
+    assert_eq!(onboarding.complete_step(answer).unwrap(), first);
+    assert!(onboarding.skip_step(OnboardingStep::Backend).is_err());
+    assert!(onboarding
+        .complete_step(StepAnswer::ShellHook {
+            shells: vec!["tcsh".to_string()]
+        })
+        .is_err());
+    onboarding.skip_step(OnboardingStep::Encryption).unwrap();
+
+    // The wizard was closed; a new launch resumes at the backend step
+    let onboarding = Onboarding::open(&path);
+    let state = onboarding.state();
+    assert_eq!(state.current_step, Some(OnboardingStep::Backend));
+    assert_eq!(
+        state.step(OnboardingStep::DataDir).finished_at,
+        first.step(OnboardingStep::DataDir).finished_at
+    );
+    assert_eq!(
+        state.step(OnboardingStep::Encryption).status,
+        StepStatus::Skipped
+    );
+
+    onboarding
+        .complete_step(StepAnswer::Backend {
+            host: "localhost".to_string(),
+            port: 34567,
+        })
+        .unwrap();
+    // Changing their mind about encryption replaces the skip
+    onboarding
+        .complete_step(StepAnswer::Encryption { enabled: true })
+        .unwrap();
+    onboarding
+        .complete_step(StepAnswer::ShellHook {
+            shells: vec!["zsh".to_string()],
+        })
+        .unwrap();
+    let done = onboarding.skip_step(OnboardingStep::ExampleCanvas).unwrap();
+    assert!(done.is_complete());
+    assert!(done.completed_at.is_some());
+    assert_eq!(
+        done.answer(OnboardingStep::Encryption),
+        Some(&StepAnswer::Encryption { enabled: true })
+    );
+    assert_eq!(Onboarding::open(&path).state(), done);
+
+    let fresh = onboarding.reset().unwrap();
+    assert_eq!(fresh.current_step, Some(OnboardingStep::DataDir));
+    assert_eq!(
+        Onboarding::open(&path).state().current_step,
+        Some(OnboardingStep::DataDir)
+    );
+}
@@ -0,0 +1,246 @@
+//! Capability-scoped permissions for the frontend's commands.
+//!
+//! Every Tauri command that can change something, or read what was captured,
+//! belongs to one or more [`Capability`] groups. The `[permissions]` config
+//! section denies whole groups, and [`PermissionPolicy`] checks each invoke
+//! before it reaches the command, so a kiosk or a shared workstation can turn
+//! off running commands or editing memory without a custom build:
+//!
+//! ```toml
+//! [permissions]
+//! deny = ["execute", "orchestration", "config.write"]
+//! ```
+//!
+//! Running a canvas runs its commands, so orchestration commands need
+//! `execute` as well. Commands that only report on the app itself (health,
+//! windows, previews) need no capability.
+
+#[cfg(test)]
+mod tests;
+
+use crate::i18n::UserMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use ts_rs::TS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum Capability {
+    /// Run commands, drive terminals, and read or write files on the host
+    #[serde(rename = "execute")]
+    Execute,
+    /// Read sessions, commands, output, errors and other captured records
+    #[serde(rename = "memory.read")]
+    MemoryRead,
+    /// Add, change or delete memory records
+    #[serde(rename = "memory.write")]
+    MemoryWrite,
+    /// Change how RuneBook is set up
+    #[serde(rename = "config.write")]
+    ConfigWrite,
+    /// Run and debug canvases
+    #[serde(rename = "orchestration")]
+    Orchestration,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Execute,
+        Capability::MemoryRead,
+        Capability::MemoryWrite,
+        Capability::ConfigWrite,
+        Capability::Orchestration,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Execute => "execute",
+            Capability::MemoryRead => "memory.read",
+            Capability::MemoryWrite => "memory.write",
+            Capability::ConfigWrite => "config.write",
+            Capability::Orchestration => "orchestration",
+        }
+    }
+}
+
+/// `[permissions]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct PermissionsConfig {
+    /// Capability groups the frontend may not use
+    pub deny: Vec<Capability>,
+}
+
+use Capability::*;
+
+/// The capabilities each command needs; commands missing here cannot be
+/// invoked at all
+pub const COMMANDS: &[(&str, &[Capability])] = &[
+    ("greet", &[]),
+    ("execute_terminal_command", &[Execute]),
+    ("execute_terminal_command_streaming", &[Execute]),
+    ("execute_pipeline", &[Execute]),
+    ("cancel_command", &[Execute]),
+    ("send_signal", &[Execute]),
+    ("preview_execution", &[]),
+    ("execute_dry_run", &[]),
+    ("watch_start", &[Execute]),
+    ("watch_stop", &[Execute]),
+    ("watch_list", &[]),
+    ("logs_follow", &[Execute]),
+    ("logs_stop", &[Execute]),
+    ("logs_list", &[]),
+    ("alert_rules_list", &[]),
+    ("alert_rules_save", &[ConfigWrite, Execute, Orchestration]),
+    ("webhook_deliveries", &[]),
+    ("pty_start", &[Execute]),
+    ("pty_write", &[Execute]),
+    ("pty_resize", &[Execute]),
+    ("pty_screen", &[Execute]),
+    ("pty_close", &[Execute]),
+    ("pty_list", &[]),
+    ("canvas_debug_start", &[Orchestration, Execute]),
+    ("canvas_debug_command", &[Orchestration, Execute]),
+    ("canvas_debug_inspect", &[Orchestration]),
+    ("backup_status", &[]),
+    ("backup_run_now", &[MemoryRead]),
+    ("backup_export", &[MemoryRead]),
+    ("schedule_next_runs", &[]),
+    ("schedule_ical", &[]),
+    ("system_health", &[]),
+    ("health_report_analyzers", &[]),
+    ("onboarding_state", &[]),
+    ("onboarding_complete_step", &[ConfigWrite]),
+    ("onboarding_skip_step", &[ConfigWrite]),
+    ("onboarding_reset", &[ConfigWrite]),
+    ("profile_current", &[]),
+    ("profile_list", &[]),
+    ("profile_create", &[ConfigWrite]),
+    ("profile_delete", &[ConfigWrite]),
+    ("profile_switch", &[ConfigWrite]),
+    ("scrub_preview", &[]),
+    ("memory_inspect", &[MemoryRead]),
+    ("spawn_terminal", &[Execute]),
+    ("write_terminal", &[Execute]),
+    ("resize_terminal", &[Execute]),
+    ("kill_terminal", &[Execute]),
+    ("archive_create", &[Execute]),
+    ("archive_extract", &[Execute]),
+    ("attach_clipboard", &[MemoryWrite]),
+    ("attach_screenshot", &[MemoryWrite]),
+    ("record_trace_span", &[]),
+    ("memory_usage_report", &[MemoryRead]),
+    ("reindex_memory", &[MemoryWrite]),
+    ("time_report", &[MemoryRead]),
+    ("export_analytics", &[MemoryRead, Execute]),
+    ("history_import", &[MemoryWrite, Execute]),
+    ("handoff_export", &[MemoryRead]),
+    ("handoff_import", &[MemoryWrite]),
+    ("share_session_start", &[MemoryRead]),
+    ("share_session_stop", &[]),
+    ("share_revoke", &[]),
+    ("share_list", &[]),
+    ("observe_session", &[]),
+    ("observe_stop", &[]),
+    ("demo_seed", &[MemoryWrite]),
+    ("demo_wipe", &[MemoryWrite]),
+    ("tag_add", &[MemoryWrite]),
+    ("tag_remove", &[MemoryWrite]),
+    ("tag_list", &[MemoryRead]),
+    ("memory_list_sessions", &[MemoryRead]),
+    ("memory_recent_errors", &[MemoryRead]),
+    ("diagnostics_for_path", &[MemoryRead]),
+    ("memory_list_artifacts", &[MemoryRead]),
+    ("memory_get_artifact", &[MemoryRead]),
+    ("read_output_page", &[MemoryRead]),
+    ("suggestion_feedback", &[MemoryWrite]),
+    ("rank_eval", &[MemoryRead]),
+    ("explain_suggestion", &[MemoryRead]),
+    ("preferences_get", &[MemoryRead]),
+    ("preferences_set", &[MemoryWrite]),
+    ("preferences_reset", &[MemoryWrite]),
+    ("suggestion_mute", &[MemoryWrite]),
+    ("read_output_tail", &[MemoryRead]),
+    ("tail_output", &[MemoryRead]),
+    ("output_line_count", &[MemoryRead]),
+    ("command_objects", &[MemoryRead]),
+    ("view_save", &[MemoryWrite]),
+    ("view_delete", &[MemoryWrite]),
+    ("view_list", &[MemoryRead]),
+    ("view_evaluate", &[MemoryRead]),
+    ("bookmark_add", &[MemoryWrite]),
+    ("resolve_bookmark", &[MemoryRead]),
+    ("bookmark_list", &[MemoryRead]),
+    ("bookmark_delete", &[MemoryWrite]),
+    ("take_launch_request", &[]),
+    ("read_canvas_file", &[Execute]),
+    ("canvas_lint", &[]),
+    ("canvas_simulate", &[Orchestration]),
+    ("benchmark_run", &[Execute, MemoryWrite]),
+    ("benchmark_history", &[MemoryRead]),
+    ("execution_queue", &[]),
+    ("sandbox_preview", &[Execute]),
+    ("execute_elevated", &[Execute, MemoryWrite]),
+    ("elevation_prompt", &[]),
+    ("elevation_respond", &[]),
+    ("canvas_record", &[Orchestration, Execute]),
+    ("canvas_replay", &[Orchestration]),
+    ("deep_link_prepare", &[]),
+    ("deep_link_run_snippet", &[Execute]),
+    ("deep_link_import_card", &[MemoryWrite]),
+    ("upgrade_report", &[]),
+    ("upgrade_run_migrations", &[MemoryWrite]),
+    ("verify_audit_log", &[]),
+    ("execute_with_receipt", &[Execute]),
+    ("canvas_run_with_receipt", &[Orchestration, Execute]),
+    ("receipt_verify", &[]),
+    ("receipt_public_key", &[]),
+    ("window_subscribe", &[]),
+    ("window_state", &[]),
+    ("focus_status", &[]),
+    ("list_event_schemas", &[]),
+    ("session_set_current", &[]),
+    ("open_window", &[]),
+    ("permissions_policy", &[]),
+];
+
+/// The capabilities each command needs, or `None` for unknown commands
+pub fn required_capabilities(command: &str) -> Option<&'static [Capability]> {
+    COMMANDS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, capabilities)| *capabilities)
+}
+
+/// Which capability groups are enabled, as the frontend sees it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PermissionPolicy {
+    pub denied: BTreeSet<Capability>,
+}
+
+impl PermissionPolicy {
+    pub fn new(config: &PermissionsConfig) -> Self {
+        Self {
+            denied: config.deny.iter().copied().collect(),
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        !self.denied.contains(&capability)
+    }
+
+    /// Whether the frontend may invoke `command`
+    pub fn check(&self, command: &str) -> Result<(), UserMessage> {
+        let Some(required) = required_capabilities(command) else {
+            return Err(UserMessage::new("command-unknown").with("command", command));
+        };
+        match required.iter().find(|c| !self.allows(**c)) {
+            Some(denied) => Err(UserMessage::new("capability-denied")
+                .with("command", command)
+                .with("capability", denied.as_str())),
+            None => Ok(()),
+        }
+    }
+}
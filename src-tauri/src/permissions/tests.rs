@@ -0,0 +1,50 @@
+use super::*;
+
+#[test]
+fn every_registered_command_has_a_capability_entry() {
+    let lib = include_str!("../lib.rs");
+    let start = lib.find("generate_handler![").unwrap() + "generate_handler![".len();
+    let end = start + lib[start..].find(']').unwrap();
+    let registered: Vec<&str> = lib[start..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    assert!(registered.len() > 50);
+    for name in &registered {
+        assert!(
+            required_capabilities(name).is_some(),
+            "{} is registered but missing from permissions::COMMANDS",
+            name
+        );
+    }
+    for (name, _) in COMMANDS {
+        assert!(
+            registered.contains(name),
+            "{} is not a registered command",
+            name
+        );
+    }
+}
+
+#[test]
+fn denied_groups_block_their_commands() {
+    let config: PermissionsConfig =
+        toml::from_str(r#"deny = ["execute", "memory.write"]"#).unwrap();
+    let kiosk = PermissionPolicy::new(&config);
+
+    assert!(kiosk.check("memory_list_sessions").is_ok());
+    assert!(kiosk.check("system_health").is_ok());
+    let denied = kiosk.check("spawn_terminal").unwrap_err();
+    assert_eq!(denied.code, "capability-denied");
+    assert_eq!(denied.params["capability"], "execute");
+    // Orchestration is on, but running a canvas still needs execute
+    assert!(kiosk.check("canvas_simulate").is_ok());
+    assert!(kiosk.check("canvas_run_with_receipt").is_err());
+    assert!(kiosk.check("tag_add").is_err());
+    assert_eq!(kiosk.check("rm_rf").unwrap_err().code, "command-unknown");
+
+    assert!(toml::from_str::<PermissionsConfig>(r#"deny = ["shell"]"#).is_err());
+    let open = PermissionPolicy::new(&PermissionsConfig::default());
+    assert!(COMMANDS.iter().all(|(name, _)| open.check(name).is_ok()));
+}
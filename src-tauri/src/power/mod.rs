@@ -0,0 +1,245 @@
+//! Sleep and wake of the machine.
+//!
+//! Closing a laptop's lid mid-build leaves a command that "took" all night, a
+//! backup that fires the moment the machine wakes, before the network is
+//! back, and a watch whose run hangs on a dead connection. The
+//! [`PowerMonitor`] notices each sleep and keeps a log of recent ones, which
+//! the rest of the backend consults:
+//!
+//! - the memory store sets [`Command::suspended_ms`] on commands that were
+//!   running across a sleep and tags them `suspended`, and duration
+//!   statistics count only the time they were awake
+//!   ([`Command::awake_ms`]);
+//! - scheduled backups and digests wait until the machine has been awake for
+//!   [`SETTLE`] before deciding what is due;
+//! - watches restart a run the sleep interrupted, or stop, per
+//!   `[power] resume_watches`.
+//!
+//! Sleeps are found from the clocks rather than from OS notifications, which
+//! would take a window (Windows), IOKit (macOS) or logind over D-Bus (Linux).
+//! The monitor probes every few seconds: on Linux and macOS the monotonic
+//! clock stops while the machine sleeps, so the wall clock gets ahead of it;
+//! on Windows both keep going, and the probe comes back late. A clock set
+//! forward by hand looks the same, and is taken for a sleep.
+
+#[cfg(test)]
+mod tests;
+
+use crate::health::Component;
+use crate::memory::Command;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tokio::sync::broadcast;
+use ts_rs::TS;
+
+/// How often the monitor probes the clocks
+const PROBE: std::time::Duration = std::time::Duration::from_secs(5);
+/// Time unaccounted for between probes beyond which it was a sleep, in seconds
+const SLEEP_SECS: i64 = 60;
+/// How long after waking the machine counts as settling: scheduled jobs wait
+/// it out so the network is back when they run
+pub const SETTLE: std::time::Duration = std::time::Duration::from_secs(30);
+/// Sleeps kept in the log, the newest ones
+const MAX_SLEEPS: usize = 256;
+/// Tag of commands that ran across a sleep
+pub const SUSPENDED_TAG: &str = "suspended";
+
+/// `[power]` section of the config
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct PowerConfig {
+    /// Keep watches going after a sleep, restarting the run it interrupted.
+    /// When off, a watch stops once the machine wakes.
+    pub resume_watches: bool,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            resume_watches: true,
+        }
+    }
+}
+
+/// A stretch of time the machine slept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sleep {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+impl Sleep {
+    pub fn duration_ms(&self) -> u64 {
+        (self.to - self.from).num_milliseconds().max(0) as u64
+    }
+
+    /// Milliseconds of this sleep between `start` and `end`
+    pub fn overlap_ms(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> u64 {
+        let (from, to) = (self.from.max(start), self.to.min(end));
+        (to - from).num_milliseconds().max(0) as u64
+    }
+}
+
+/// How long the machine slept between two probes, given how far the wall
+/// and monotonic clocks moved and how long the prober meant to wait; `None`
+/// when it did not
+fn slept(wall: Duration, monotonic: Duration, expected: Duration) -> Option<Duration> {
+    // Monotonic time stopped (Linux, macOS), and the wait ran late (Windows)
+    let stopped = (wall - monotonic).max(Duration::zero());
+    let late = (monotonic - expected).max(Duration::zero());
+    let slept = stopped + late;
+    (slept.num_seconds() > SLEEP_SECS).then_some(slept)
+}
+
+struct Probe {
+    wall: DateTime<Utc>,
+    monotonic: Instant,
+}
+
+/// Notices sleeps and keeps the recent ones
+pub struct PowerMonitor {
+    sleeps: Mutex<VecDeque<Sleep>>,
+    probe: Mutex<Probe>,
+    events: broadcast::Sender<Sleep>,
+}
+
+impl Default for PowerMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The process-wide monitor
+pub fn monitor() -> &'static PowerMonitor {
+    static MONITOR: OnceLock<PowerMonitor> = OnceLock::new();
+    MONITOR.get_or_init(PowerMonitor::new)
+}
+
+impl PowerMonitor {
+    pub fn new() -> Self {
+        Self {
+            sleeps: Mutex::new(VecDeque::new()),
+            probe: Mutex::new(Probe {
+                wall: Utc::now(),
+                monotonic: Instant::now(),
+            }),
+            events: broadcast::channel(16).0,
+        }
+    }
+
+    /// Sleeps as they are noticed, once the machine is awake again
+    pub fn subscribe(&self) -> broadcast::Receiver<Sleep> {
+        self.events.subscribe()
+    }
+
+    /// Log a sleep and tell subscribers
+    pub fn record(&self, sleep: Sleep) {
+        log::info!(
+            "[power] Slept {}s, from {} to {}",
+            sleep.duration_ms() / 1000,
+            sleep.from,
+            sleep.to
+        );
+        {
+            let mut sleeps = self.sleeps.lock().unwrap_or_else(|e| e.into_inner());
+            if sleeps.len() == MAX_SLEEPS {
+                sleeps.pop_front();
+            }
+            sleeps.push_back(sleep);
+        }
+        let _ = self.events.send(sleep);
+    }
+
+    /// Compare the clocks with the last probe, logging a sleep if one went
+    /// by. Returns it.
+    pub fn check(&self) -> Option<Sleep> {
+        let now = Utc::now();
+        let sleep = {
+            let mut probe = self.probe.lock().unwrap_or_else(|e| e.into_inner());
+            let monotonic =
+                Duration::from_std(probe.monotonic.elapsed()).unwrap_or(Duration::zero());
+            let sleep = slept(
+                now - probe.wall,
+                monotonic,
+                Duration::from_std(PROBE).unwrap_or(Duration::zero()),
+            )
+            .map(|slept| Sleep {
+                from: now - slept,
+                to: now,
+            });
+            *probe = Probe {
+                wall: now,
+                monotonic: Instant::now(),
+            };
+            sleep
+        }?;
+        self.record(sleep);
+        Some(sleep)
+    }
+
+    /// Logged sleeps, oldest first
+    pub fn sleeps(&self) -> Vec<Sleep> {
+        self.sleeps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Milliseconds the machine slept between `start` and `end`
+    pub fn slept_ms(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> u64 {
+        self.sleeps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|sleep| sleep.overlap_ms(start, end))
+            .sum()
+    }
+
+    /// Set `command`'s [`Command::suspended_ms`] from the log, unless its
+    /// recorder already did. Returns whether it ran across a sleep.
+    pub fn annotate(&self, command: &mut Command) -> bool {
+        if command.suspended_ms.is_none() {
+            if let Some(ended_at) = command.ended_at {
+                let slept = self.slept_ms(command.started_at, ended_at);
+                command.suspended_ms = (slept > 0).then_some(slept);
+            }
+        }
+        command.suspended_ms.is_some_and(|ms| ms > 0)
+    }
+
+    /// Wait until the machine has been awake for [`SETTLE`]. Returns at once
+    /// unless it just woke.
+    pub async fn settled(&self) {
+        self.check();
+        let Some(woke) = self
+            .sleeps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .back()
+            .map(|s| s.to)
+        else {
+            return;
+        };
+        let awake = (Utc::now() - woke).to_std().unwrap_or_default();
+        if let Some(left) = SETTLE.checked_sub(awake) {
+            tokio::time::sleep(left).await;
+        }
+    }
+
+    /// Probe the clocks until the process exits
+    pub async fn run(&self, health: Component) {
+        health.ok();
+        let mut interval = tokio::time::interval(PROBE);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+}
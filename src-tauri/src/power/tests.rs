@@ -0,0 +1,77 @@
+use super::*;
+use crate::memory::{InMemoryBackend, MemoryStore, TimeGroupBy};
+
+fn at(time: &str) -> DateTime<Utc> {
+    format!("2001-03-04T{}Z", time).parse().unwrap()
+}
+
+#[test]
+fn sleeps_are_found_from_either_clock() {
+    let expected = Duration::seconds(5);
+    // On time
+    assert_eq!(
+        slept(Duration::seconds(5), Duration::seconds(5), expected),
+        None
+    );
+    // Linux and macOS: the monotonic clock stopped for an hour
+    assert_eq!(
+        slept(Duration::seconds(3605), Duration::seconds(5), expected),
+        Some(Duration::hours(1))
+    );
+    // Windows: both clocks went on, the probe came back an hour late
+    assert_eq!(
+        slept(Duration::seconds(3605), Duration::seconds(3605), expected),
+        Some(Duration::hours(1))
+    );
+    // The clock set back is no sleep
+    assert_eq!(
+        slept(Duration::hours(-2), Duration::seconds(5), expected),
+        None
+    );
+
+    let sleep = Sleep {
+        from: at("10:30:00"),
+        to: at("11:30:00"),
+    };
+    assert_eq!(sleep.overlap_ms(at("10:00:00"), at("12:00:00")), 3_600_000);
+    assert_eq!(sleep.overlap_ms(at("11:00:00"), at("12:00:00")), 1_800_000);
+    assert_eq!(sleep.overlap_ms(at("12:00:00"), at("13:00:00")), 0);
+}
+
+#[tokio::test]
+async fn commands_across_a_sleep_count_their_awake_time() {
+    // The process-wide monitor, as the store uses it; far enough in the past
+    // not to touch other tests' commands
+    monitor().record(Sleep {
+        from: at("10:30:00"),
+        to: at("11:30:00"),
+    });
+
+    let store = MemoryStore::new(InMemoryBackend::new()).await.unwrap();
+    let mut build = Command::new(
+        "s1".to_string(),
+        "cargo".to_string(),
+        vec!["build".to_string()],
+        "/repo".to_string(),
+    );
+    build.started_at = at("10:00:00");
+    build.ended_at = Some(at("12:00:00"));
+    build.duration_ms = Some(7_200_000);
+    build.success = true;
+    store.store_command(build).await.unwrap();
+
+    let stored = store
+        .commands_between(at("00:00:00"), at("23:59:59"))
+        .await
+        .unwrap();
+    assert_eq!(stored[0].suspended_ms, Some(3_600_000));
+    assert_eq!(stored[0].awake_ms(), Some(3_600_000));
+    assert!(stored[0].tags.contains(&SUSPENDED_TAG.to_string()));
+
+    let date = at("00:00:00").date_naive();
+    let report = store
+        .time_report(date, date, TimeGroupBy::Command, 5)
+        .await
+        .unwrap();
+    assert_eq!(report.total_ms, 3_600_000);
+}
@@ -0,0 +1,216 @@
+//! Named profiles.
+//!
+//! `runebook --profile work` (or `RUNEBOOK_PROFILE=work`) runs against a
+//! separate set of app data. Everything kept in the config directory follows
+//! the profile: `config.toml`, the audit log, API tokens, onboarding progress,
+//! backups, the capture socket and the memory store's data directory. Keychain
+//! entries get a profile-specific service name, and each profile's `[memory]`
+//! section can point it at its own PluresDB, so workspaces and captured
+//! history never mix.
+//!
+//! The `default` profile lives directly in the base directory
+//! (`$RUNEBOOK_CONFIG_DIR`, or the platform config dir + `runebook`), so
+//! existing installs keep their data; named profiles live in
+//! `profiles/<name>` below it. A process runs one profile for its whole life:
+//! switching relaunches RuneBook with the new profile after its sessions are
+//! torn down, so no service ever sees another profile's data.
+
+#[cfg(test)]
+mod tests;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use ts_rs::TS;
+
+/// Profile that lives in the base directory
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Keychain service of the default profile
+const KEYCHAIN_SERVICE: &str = "runebook";
+
+static ACTIVE: OnceLock<String> = OnceLock::new();
+
+/// Profile names are short and safe as directory names
+pub fn valid_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Take `--profile <name>` or `--profile=<name>` out of `args`
+pub fn take_profile_arg(args: &mut Vec<String>) -> Result<Option<String>> {
+    let Some(at) = args
+        .iter()
+        .position(|arg| arg == "--profile" || arg.starts_with("--profile="))
+    else {
+        return Ok(None);
+    };
+    let flag = args.remove(at);
+    let name = match flag.strip_prefix("--profile=") {
+        Some(name) => name.to_string(),
+        None if at < args.len() => args.remove(at),
+        None => anyhow::bail!("--profile needs a profile name"),
+    };
+    if !valid_name(&name) {
+        anyhow::bail!(
+            "Invalid profile name {:?}: use letters, digits, - and _",
+            name
+        );
+    }
+    Ok(Some(name))
+}
+
+/// Choose the profile for this process: `--profile` from `args` (which is
+/// removed), else `$RUNEBOOK_PROFILE`, else the default. Call before anything
+/// reads the config directory.
+pub fn select_from_args(args: &mut Vec<String>) -> Result<&'static str> {
+    let name = match take_profile_arg(args)? {
+        Some(name) => name,
+        None => match std::env::var("RUNEBOOK_PROFILE") {
+            Ok(name) if valid_name(&name) => name,
+            Ok(name) => anyhow::bail!("Invalid RUNEBOOK_PROFILE {:?}", name),
+            Err(_) => DEFAULT_PROFILE.to_string(),
+        },
+    };
+    let active = ACTIVE.get_or_init(|| name.clone());
+    if *active != name {
+        anyhow::bail!("The {} profile is already selected", active);
+    }
+    Ok(active)
+}
+
+/// The profile this process runs
+pub fn active() -> &'static str {
+    ACTIVE.get_or_init(|| {
+        std::env::var("RUNEBOOK_PROFILE")
+            .ok()
+            .filter(|name| valid_name(name))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    })
+}
+
+/// Command-line arguments with the profile flag removed
+pub fn launch_args() -> Vec<String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let _ = take_profile_arg(&mut args);
+    args
+}
+
+/// Keychain service name of the active profile
+pub fn keychain_service() -> String {
+    match active() {
+        DEFAULT_PROFILE => KEYCHAIN_SERVICE.to_string(),
+        name => format!("{}-{}", KEYCHAIN_SERVICE, name),
+    }
+}
+
+/// A profile, as listed by [`Profiles::list`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ProfileInfo {
+    pub name: String,
+    /// Its config directory
+    pub path: PathBuf,
+    pub active: bool,
+}
+
+/// The profiles below one base directory
+pub struct Profiles {
+    base: PathBuf,
+}
+
+impl Profiles {
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self { base: base.into() }
+    }
+
+    /// `$RUNEBOOK_CONFIG_DIR`, or the platform config dir + `runebook`
+    pub fn default_location() -> Self {
+        Self::new(match std::env::var("RUNEBOOK_CONFIG_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => dirs::config_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("runebook"),
+        })
+    }
+
+    /// Config directory of the profile `name`
+    pub fn dir(&self, name: &str) -> PathBuf {
+        match name {
+            DEFAULT_PROFILE => self.base.clone(),
+            name => self.base.join("profiles").join(name),
+        }
+    }
+
+    fn info(&self, name: &str) -> ProfileInfo {
+        ProfileInfo {
+            name: name.to_string(),
+            path: self.dir(name),
+            active: name == active(),
+        }
+    }
+
+    /// The default profile, then the named ones by name
+    pub fn list(&self) -> Result<Vec<ProfileInfo>> {
+        let mut names = Vec::new();
+        match std::fs::read_dir(self.base.join("profiles")) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if entry.file_type()?.is_dir() && valid_name(&name) && name != DEFAULT_PROFILE {
+                        names.push(name);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to list profiles"),
+        }
+        names.sort();
+        Ok(std::iter::once(DEFAULT_PROFILE.to_string())
+            .chain(names)
+            .map(|name| self.info(&name))
+            .collect())
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        name == DEFAULT_PROFILE || self.dir(name).is_dir()
+    }
+
+    /// Create an empty profile; it starts with default settings and onboarding
+    pub fn create(&self, name: &str) -> Result<ProfileInfo> {
+        if !valid_name(name) {
+            anyhow::bail!(
+                "Invalid profile name {:?}: use letters, digits, - and _",
+                name
+            );
+        }
+        if self.exists(name) {
+            anyhow::bail!("Profile {} already exists", name);
+        }
+        let dir = self.dir(name);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(self.info(name))
+    }
+
+    /// Delete a profile and everything in its directory. The default profile
+    /// and the active one cannot be deleted.
+    pub fn delete(&self, name: &str) -> Result<()> {
+        if name == DEFAULT_PROFILE {
+            anyhow::bail!("The default profile cannot be deleted");
+        }
+        if name == active() {
+            anyhow::bail!("Profile {} is in use; switch to another one first", name);
+        }
+        if !valid_name(name) || !self.exists(name) {
+            anyhow::bail!("No profile named {}", name);
+        }
+        let dir = self.dir(name);
+        std::fs::remove_dir_all(&dir).with_context(|| format!("Failed to delete {}", dir.display()))
+    }
+}
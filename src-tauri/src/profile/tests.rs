@@ -0,0 +1,55 @@
+use super::*;
+
+#[test]
+fn profile_flag_is_taken_out_of_the_arguments() {
+    let mut args: Vec<String> = ["--profile", "work", "notes.md"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(
+        take_profile_arg(&mut args).unwrap().as_deref(),
+        Some("work")
+    );
+    assert_eq!(args, ["notes.md"]);
+
+    let mut args = vec!["--profile=home".to_string()];
+    assert_eq!(
+        take_profile_arg(&mut args).unwrap().as_deref(),
+        Some("home")
+    );
+    assert!(args.is_empty());
+
+    assert!(take_profile_arg(&mut vec!["--profile".to_string()]).is_err());
+    assert!(take_profile_arg(&mut vec!["--profile=../etc".to_string()]).is_err());
+    assert_eq!(
+        take_profile_arg(&mut vec!["demo".to_string()]).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn named_profiles_get_their_own_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let profiles = Profiles::new(dir.path());
+    assert_eq!(profiles.dir(DEFAULT_PROFILE), dir.path());
+
+    let work = profiles.create("work").unwrap();
+    assert_eq!(work.path, dir.path().join("profiles").join("work"));
+    assert!(work.path.is_dir());
+    assert!(profiles.create("work").is_err());
+    profiles.create("home").unwrap();
+
+    let names: Vec<String> = profiles
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    assert_eq!(names, ["default", "home", "work"]);
+
+    assert!(profiles.delete(DEFAULT_PROFILE).is_err());
+    assert!(profiles.delete("missing").is_err());
+    profiles.delete("work").unwrap();
+    assert!(!profiles.exists("work"));
+    assert!(profiles.exists(DEFAULT_PROFILE));
+}
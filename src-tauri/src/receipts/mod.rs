@@ -0,0 +1,393 @@
+//! Signed execution receipts.
+//!
+//! A receipt is a JSON summary of a command or canvas run: the resolved
+//! command spec of every step, SHA-256 digests of its stdout and stderr, the
+//! exit code, and timestamps, signed with an ed25519 key. It can be attached
+//! to a change ticket as proof of exactly what was run, and checked later with
+//! [`verify`] (or `runebook receipt verify <file>`) without RuneBook's data.
+//!
+//! Environment values can hold secrets, so receipts carry only their digests.
+//! Output is digested as captured (UTF-8, with invalid bytes replaced).
+//!
+//! The signing key is generated on first use and kept in the OS keychain on
+//! macOS and Windows, and in `receipt-signing.key` (mode 0600) in the config
+//! directory elsewhere. Each receipt names its public key; compare it with
+//! [`ReceiptSigner::public_key`] to know the receipt came from this machine.
+
+use crate::audit::{AuditEntry, AuditLog};
+use crate::execution::canvas::{CanvasDocument, CanvasRunReport, NodeRunStatus};
+use crate::execution::{CommandOutcome, ResolvedExecution, Termination};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use ts_rs::TS;
+
+/// Receipt format version
+pub const RECEIPT_VERSION: u32 = 1;
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ReceiptKind {
+    Command,
+    Canvas,
+}
+
+/// One command in a receipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReceiptStep {
+    /// Canvas node, for canvas receipts
+    pub node_id: Option<String>,
+    /// How the run ended: "succeeded", "failed", "timed_out",
+    /// "cancelled", "refused", or "skipped"
+    pub status: String,
+    pub command_line: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    pub profile: Option<String>,
+    /// Environment variable names with `sha256:<digest>` of their values
+    pub env: BTreeMap<String, String>,
+    pub exit_code: Option<i32>,
+    pub stdout_sha256: Option<String>,
+    pub stdout_bytes: Option<u64>,
+    pub stderr_sha256: Option<String>,
+    pub stderr_bytes: Option<u64>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl ReceiptStep {
+    fn new(resolved: &ResolvedExecution, status: &str, outcome: Option<&CommandOutcome>) -> Self {
+        let spec = &resolved.spec;
+        Self {
+            node_id: None,
+            status: status.to_string(),
+            command_line: resolved.command_line.clone(),
+            command: spec.command.clone(),
+            args: spec.args.clone(),
+            cwd: spec.cwd.clone(),
+            profile: resolved.profile.clone(),
+            env: spec
+                .env
+                .iter()
+                .map(|(k, v)| (k.clone(), format!("sha256:{}", sha256_hex(v.as_bytes()))))
+                .collect(),
+            exit_code: outcome.and_then(|o| o.exit_code),
+            stdout_sha256: outcome.map(|o| sha256_hex(o.stdout.as_bytes())),
+            stdout_bytes: outcome.map(|o| o.stdout.len() as u64),
+            stderr_sha256: outcome.map(|o| sha256_hex(o.stderr.as_bytes())),
+            stderr_bytes: outcome.map(|o| o.stderr.len() as u64),
+            started_at: outcome.map(|o| o.started_at),
+            finished_at: outcome
+                .map(|o| o.started_at + Duration::milliseconds(o.duration_ms as i64)),
+        }
+    }
+}
+
+/// The signed part of a receipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReceiptBody {
+    pub version: u32,
+    pub id: String,
+    pub kind: ReceiptKind,
+    pub issued_at: DateTime<Utc>,
+    /// Who started the run ("user", "api", ...)
+    pub actor: String,
+    pub host: Option<String>,
+    pub runebook_version: String,
+    pub canvas_id: Option<String>,
+    pub canvas_name: Option<String>,
+    pub success: bool,
+    pub steps: Vec<ReceiptStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ReceiptSignature {
+    pub algorithm: String,
+    /// Hex ed25519 public key
+    pub public_key: String,
+    /// Hex signature over the JSON serialization of `body`
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ExecutionReceipt {
+    pub body: ReceiptBody,
+    pub signature: ReceiptSignature,
+}
+
+/// Check a receipt's signature against the public key it names. Pass
+/// `expected_key` to also require a particular signer.
+pub fn verify(receipt: &ExecutionReceipt, expected_key: Option<&str>) -> Result<()> {
+    if receipt.signature.algorithm != "ed25519" {
+        bail!(
+            "Unsupported signature algorithm {}",
+            receipt.signature.algorithm
+        );
+    }
+    if let Some(expected) = expected_key {
+        if !expected.eq_ignore_ascii_case(&receipt.signature.public_key) {
+            bail!("Receipt was signed by a different key");
+        }
+    }
+    let key: [u8; 32] = hex::decode(&receipt.signature.public_key)
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .context("Invalid public key")?;
+    let key = VerifyingKey::from_bytes(&key).context("Invalid public key")?;
+    let signature: [u8; 64] = hex::decode(&receipt.signature.signature)
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .context("Invalid signature encoding")?;
+    key.verify(
+        &serde_json::to_vec(&receipt.body)?,
+        &Signature::from_bytes(&signature),
+    )
+    .context("Signature does not match the receipt contents")
+}
+
+/// Issues receipts signed with this machine's key
+pub struct ReceiptSigner {
+    key: SigningKey,
+    audit: Option<Arc<AuditLog>>,
+}
+
+impl ReceiptSigner {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(&seed),
+            audit: None,
+        }
+    }
+
+    /// The machine's signing key, created on first use
+    pub fn load_or_create() -> Result<Self> {
+        Ok(Self::from_seed(keystore::load_or_create_seed()?))
+    }
+
+    /// Record each issued receipt in the audit log
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Hex public key, as it appears in receipts
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.verifying_key().as_bytes())
+    }
+
+    fn issue(&self, body: ReceiptBody) -> Result<ExecutionReceipt> {
+        let signature = self.key.sign(&serde_json::to_vec(&body)?);
+        if let Some(audit) = &self.audit {
+            audit.record(
+                AuditEntry::new(
+                    "receipt.issued",
+                    &body.actor,
+                    body.steps
+                        .iter()
+                        .map(|s| s.command_line.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" && "),
+                )
+                .with_details(serde_json::json!({
+                    "receipt_id": body.id,
+                    "kind": body.kind,
+                    "success": body.success,
+                })),
+            );
+        }
+        Ok(ExecutionReceipt {
+            body,
+            signature: ReceiptSignature {
+                algorithm: "ed25519".to_string(),
+                public_key: self.public_key(),
+                signature: hex::encode(signature.to_bytes()),
+            },
+        })
+    }
+
+    fn body(&self, kind: ReceiptKind, actor: &str, steps: Vec<ReceiptStep>) -> ReceiptBody {
+        ReceiptBody {
+            version: RECEIPT_VERSION,
+            id: uuid::Uuid::new_v4().to_string(),
+            kind,
+            issued_at: Utc::now(),
+            actor: actor.to_string(),
+            host: hostname(),
+            runebook_version: env!("CARGO_PKG_VERSION").to_string(),
+            canvas_id: None,
+            canvas_name: None,
+            success: steps.iter().all(|s| s.status == "succeeded"),
+            steps,
+        }
+    }
+
+    /// Receipt for one command, from its resolution and outcome
+    pub fn command_receipt(
+        &self,
+        resolved: &ResolvedExecution,
+        outcome: &CommandOutcome,
+        actor: &str,
+    ) -> Result<ExecutionReceipt> {
+        let status = match outcome.termination {
+            Termination::TimedOut => "timed_out",
+            Termination::Cancelled => "cancelled",
+            Termination::LimitExceeded => "limit_exceeded",
+            Termination::Exited if outcome.success => "succeeded",
+            Termination::Exited => "failed",
+        };
+        let step = ReceiptStep::new(resolved, status, Some(outcome));
+        self.issue(self.body(ReceiptKind::Command, actor, vec![step]))
+    }
+
+    /// Receipt for a canvas run. `resolve` gives the resolved command of a
+    /// terminal node; nodes it cannot resolve are left out.
+    pub fn canvas_receipt(
+        &self,
+        canvas: &CanvasDocument,
+        report: &CanvasRunReport,
+        actor: &str,
+        resolve: impl Fn(&str) -> Option<ResolvedExecution>,
+    ) -> Result<ExecutionReceipt> {
+        let steps = report
+            .nodes
+            .iter()
+            .filter_map(|run| {
+                let resolved = resolve(&run.node_id)?;
+                let status = match run.status {
+                    NodeRunStatus::Succeeded => "succeeded",
+                    NodeRunStatus::Failed => "failed",
+                    NodeRunStatus::Refused => "refused",
+                    NodeRunStatus::Skipped => "skipped",
+                };
+                Some(ReceiptStep {
+                    node_id: Some(run.node_id.clone()),
+                    ..ReceiptStep::new(&resolved, status, run.outcome.as_ref())
+                })
+            })
+            .collect();
+        let mut body = self.body(ReceiptKind::Canvas, actor, steps);
+        body.success = report.success;
+        body.canvas_id = Some(canvas.id.clone());
+        body.canvas_name = Some(canvas.name.clone()).filter(|n| !n.is_empty());
+        self.issue(body)
+    }
+}
+
+/// The machine's signer, loaded on first use so startup never waits on a
+/// keychain prompt
+pub struct LazySigner {
+    signer: Mutex<Option<Arc<ReceiptSigner>>>,
+    audit: Arc<AuditLog>,
+}
+
+impl LazySigner {
+    pub fn new(audit: Arc<AuditLog>) -> Self {
+        Self {
+            signer: Mutex::new(None),
+            audit,
+        }
+    }
+
+    pub fn get(&self) -> Result<Arc<ReceiptSigner>> {
+        let mut signer = self.signer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(signer) = signer.as_ref() {
+            return Ok(Arc::clone(signer));
+        }
+        let loaded = Arc::new(ReceiptSigner::load_or_create()?.with_audit(Arc::clone(&self.audit)));
+        *signer = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+}
+
+/// Name of this machine, from the environment or `/etc/hostname`
+pub(crate) fn hostname() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|v| std::env::var(v).ok())
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|h| h.trim().to_string())
+        })
+        .filter(|h| !h.is_empty())
+}
+
+/// Where the signing key seed is kept
+mod keystore {
+    use anyhow::{Context, Result};
+
+    fn generate() -> Result<[u8; 32]> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed)
+            .map_err(|e| anyhow::anyhow!("No system randomness for the signing key: {}", e))?;
+        Ok(seed)
+    }
+
+    fn decode(hex_seed: &str) -> Result<[u8; 32]> {
+        hex::decode(hex_seed.trim())
+            .ok()
+            .and_then(|s| s.try_into().ok())
+            .context("Stored receipt signing key is corrupt")
+    }
+
+    #[cfg(any(target_os = "macos", windows))]
+    pub fn load_or_create_seed() -> Result<[u8; 32]> {
+        let entry =
+            keyring::Entry::new(&crate::profile::keychain_service(), "receipt-signing-key")?;
+        match entry.get_password() {
+            Ok(stored) => decode(&stored),
+            Err(keyring::Error::NoEntry) => {
+                let seed = generate()?;
+                entry
+                    .set_password(&hex::encode(seed))
+                    .context("Failed to store the receipt signing key in the keychain")?;
+                Ok(seed)
+            }
+            Err(e) => Err(e).context("Failed to read the receipt signing key from the keychain"),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    pub fn load_or_create_seed() -> Result<[u8; 32]> {
+        let path = crate::config::config_dir().join("receipt-signing.key");
+        match std::fs::read_to_string(&path) {
+            Ok(stored) => return decode(&stored),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+        let seed = generate()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match crate::server::create_private(&path) {
+            Ok(mut file) => {
+                use std::io::Write;
+                file.write_all(hex::encode(seed).as_bytes())
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                Ok(seed)
+            }
+            // Another process starting at the same time made it first
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                decode(&std::fs::read_to_string(&path)?)
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to create {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,55 @@
+use super::*;
+use crate::execution::{resolve_execution, CommandPolicy, ExecutionRequest, Termination};
+use std::collections::HashMap;
+
+#[test]
+fn receipt_verifies_and_detects_tampering() {
+    let request = ExecutionRequest {
+        command: "echo".to_string(),
+        args: vec!["hello".to_string()],
+        env: HashMap::from([("API_TOKEN".to_string(), "secret".to_string())]),
+        ..Default::default()
+    };
+    let resolved = resolve_execution(&request, &HashMap::new(), &CommandPolicy::default()).unwrap();
+    let outcome = CommandOutcome {
+        stdout: "hello\n".to_string(),
+        stderr: String::new(),
+        stdout_encoding: Default::default(),
+        stderr_encoding: Default::default(),
+        content_type: "text/plain".to_string(),
+        exit_code: Some(0),
+        success: true,
+        duration_ms: 12,
+        started_at: Utc::now(),
+        termination: Termination::Exited,
+        objects: None,
+        limit_exceeded: None,
+        truncated: false,
+        total_bytes: 0,
+        spilled: Vec::new(),
+        suspended_ms: None,
+        stdout_spans: Vec::new(),
+        stderr_spans: Vec::new(),
+    };
+
+    let signer = ReceiptSigner::from_seed([7; 32]);
+    let receipt = signer.command_receipt(&resolved, &outcome, "user").unwrap();
+    let step = &receipt.body.steps[0];
+    assert_eq!(
+        step.stdout_sha256.as_deref(),
+        Some(sha256_hex(b"hello\n").as_str())
+    );
+    assert!(!serde_json::to_string(&receipt).unwrap().contains("secret"));
+
+    // Survives a JSON round trip, as when attached to a ticket
+    let exported: ExecutionReceipt =
+        serde_json::from_str(&serde_json::to_string_pretty(&receipt).unwrap()).unwrap();
+    verify(&exported, Some(&signer.public_key())).unwrap();
+
+    let other = ReceiptSigner::from_seed([8; 32]);
+    assert!(verify(&exported, Some(&other.public_key())).is_err());
+
+    let mut tampered = exported;
+    tampered.body.steps[0].exit_code = Some(1);
+    assert!(verify(&tampered, None).is_err());
+}
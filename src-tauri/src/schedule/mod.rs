@@ -0,0 +1,342 @@
+//! Timing of the recurring jobs: scheduled backups and webhook digests.
+//!
+//! A job runs every `interval_hours` after its last run. Runs missed while
+//! the machine slept or Runebook was not running are handled by the job's
+//! `catch_up` policy: `skip` them and wait for the next slot, `run_once` for
+//! all of them (the default), or `run_all`, once per missed slot, so a digest
+//! is sent for each period that went by.
+//!
+//! The job loops are paced by a [`Ticker`], which watches the wall clock
+//! besides the monotonic one. The monotonic clock stops while the machine
+//! sleeps on Linux and macOS, so a plain interval would wake up to one whole
+//! period late after a resume; the ticker notices the jump and checks at once.
+//! A last run that lies in the future means the clock was set back, and is not
+//! trusted. After a sleep, the loops hold off until the machine has
+//! [settled](crate::power::PowerMonitor::settled), so a backup does not start
+//! before the network is back.
+//!
+//! [`next_runs`] lists upcoming runs of every enabled job, and [`to_ical`]
+//! exports them as an iCalendar feed calendars can subscribe to.
+
+#[cfg(test)]
+mod tests;
+
+use crate::config::RunebookConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use ts_rs::TS;
+
+/// Most missed slots `run_all` catches up on, the newest ones
+const MAX_CATCH_UP: i64 = 30;
+/// How late a slot may be and still be run on time, in seconds
+const ON_TIME_SECS: i64 = 900;
+/// Most runs [`next_runs`] lists per job
+const MAX_UPCOMING: usize = 1000;
+/// How often a [`Ticker`] compares the wall clock with the monotonic clock
+const PROBE: std::time::Duration = std::time::Duration::from_secs(15);
+/// Drift between the two clocks beyond which a [`Ticker`] returns early, in seconds
+const JUMP_SECS: i64 = 60;
+
+/// What to do about runs missed while the machine slept or Runebook was closed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum CatchUp {
+    /// Wait for the next slot
+    Skip,
+    /// Run once for all of them
+    #[default]
+    RunOnce,
+    /// Run once for each, oldest first
+    RunAll,
+}
+
+/// When a recurring job runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub interval: Duration,
+    pub catch_up: CatchUp,
+}
+
+impl Schedule {
+    pub fn every_hours(hours: u64, catch_up: CatchUp) -> Self {
+        Self {
+            interval: Duration::hours(hours.max(1) as i64),
+            catch_up,
+        }
+    }
+
+    /// Slots since `last` that have come by `now`, and the newest of them
+    fn elapsed(&self, last: DateTime<Utc>, now: DateTime<Utc>) -> (i64, DateTime<Utc>) {
+        let count = (now - last).num_seconds() / self.interval.num_seconds();
+        (count, last + self.interval * count as i32)
+    }
+
+    /// The runs due at `now`, each as the time it stands for: the missed slots
+    /// `run_all` catches up on, then `now`. Empty when nothing is due.
+    pub fn due(&self, last: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        let last = match last {
+            Some(last) if last <= now => last,
+            _ => return vec![now],
+        };
+        let (count, newest) = self.elapsed(last, now);
+        if count == 0 {
+            return Vec::new();
+        }
+        let on_time = now - newest <= Duration::seconds(ON_TIME_SECS);
+        match self.catch_up {
+            CatchUp::Skip if !on_time => Vec::new(),
+            CatchUp::Skip | CatchUp::RunOnce => vec![now],
+            CatchUp::RunAll => {
+                let missed = (count - 1).min(MAX_CATCH_UP);
+                (0..missed)
+                    .rev()
+                    .map(|back| newest - self.interval * (back + 1) as i32)
+                    .chain(std::iter::once(now))
+                    .collect()
+            }
+        }
+    }
+
+    /// When the job runs next: `now` when a run is due
+    pub fn next_run(&self, last: Option<DateTime<Utc>>, now: DateTime<Utc>) -> DateTime<Utc> {
+        let last = match last {
+            Some(last) if last <= now => last,
+            _ => return now,
+        };
+        let (count, newest) = self.elapsed(last, now);
+        match count {
+            0 => last + self.interval,
+            _ if self.due(Some(last), now).is_empty() => newest + self.interval,
+            _ => now,
+        }
+    }
+
+    /// Runs from the next one until `until`
+    pub fn upcoming(
+        &self,
+        last: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<DateTime<Utc>> {
+        let mut at = self.next_run(last, now);
+        let mut runs = Vec::new();
+        while at <= until && runs.len() < MAX_UPCOMING {
+            runs.push(at);
+            at += self.interval;
+        }
+        runs
+    }
+}
+
+/// A recurring job and when it last ran
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    /// `backup` or `digest`
+    pub name: String,
+    pub summary: String,
+    pub schedule: Schedule,
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+/// An upcoming run of a job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ScheduledRun {
+    pub job: String,
+    pub summary: String,
+    pub at: DateTime<Utc>,
+}
+
+/// The jobs `config` enables, with their last runs as recorded on disk
+pub fn configured_jobs(config: &RunebookConfig) -> Vec<ScheduledJob> {
+    let mut jobs = Vec::new();
+    if config.backup.enabled {
+        jobs.push(ScheduledJob {
+            name: "backup".to_string(),
+            summary: "Runebook backup".to_string(),
+            schedule: Schedule::every_hours(config.backup.interval_hours, config.backup.catch_up),
+            last_run: crate::backup::BackupStatus::load(&crate::backup::status_path())
+                .last_success_at,
+        });
+    }
+    let digest = &config.webhooks.digest;
+    if !digest.hooks.is_empty() {
+        let last_run = crate::webhooks::DeliveryLog::open_default()
+            .recent(None, Some("digest"), 1)
+            .unwrap_or_else(|e| {
+                log::warn!("[schedule] {:#}", e);
+                Vec::new()
+            })
+            .first()
+            .map(|d| d.at);
+        jobs.push(ScheduledJob {
+            name: "digest".to_string(),
+            summary: format!("Runebook digest to {}", digest.hooks.join(", ")),
+            schedule: Schedule::every_hours(digest.interval_hours, digest.catch_up),
+            last_run,
+        });
+    }
+    jobs
+}
+
+/// Runs of `jobs` from `now` until `horizon` later, in time order
+pub fn next_runs(
+    jobs: &[ScheduledJob],
+    now: DateTime<Utc>,
+    horizon: Duration,
+) -> Vec<ScheduledRun> {
+    let mut runs: Vec<ScheduledRun> = jobs
+        .iter()
+        .flat_map(|job| {
+            job.schedule
+                .upcoming(job.last_run, now, now + horizon)
+                .into_iter()
+                .map(|at| ScheduledRun {
+                    job: job.name.clone(),
+                    summary: job.summary.clone(),
+                    at,
+                })
+        })
+        .collect();
+    runs.sort_by(|a, b| a.at.cmp(&b.at).then_with(|| a.job.cmp(&b.job)));
+    runs
+}
+
+/// `jobs` as an iCalendar feed: one recurring event per job, from its next
+/// run until `horizon` after `now`. Event ids are stable, so a subscribed
+/// calendar updates its events rather than adding new ones.
+pub fn to_ical(jobs: &[ScheduledJob], now: DateTime<Utc>, horizon: Duration) -> String {
+    const FORMAT: &str = "%Y%m%dT%H%M%SZ";
+    let until = now + horizon;
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Runebook//Schedule//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for job in jobs {
+        let next = job.schedule.next_run(job.last_run, now);
+        if next > until {
+            continue;
+        }
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:runebook-{}@schedule", job.name),
+            format!("DTSTAMP:{}", now.format(FORMAT)),
+            format!("DTSTART:{}", next.format(FORMAT)),
+            format!(
+                "RRULE:FREQ=HOURLY;INTERVAL={};UNTIL={}",
+                job.schedule.interval.num_hours(),
+                until.format(FORMAT)
+            ),
+            format!("SUMMARY:{}", escape_text(&job.summary)),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ical = String::new();
+    for line in lines {
+        fold_line(&mut ical, &line);
+    }
+    ical
+}
+
+/// Escape a TEXT value (RFC 5545 §3.3.11)
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append `line` folded at 75 octets, without splitting a character, and
+/// ended with CRLF (RFC 5545 §3.1)
+fn fold_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Why [`Ticker::tick`] returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tick {
+    /// The period went by
+    Elapsed,
+    /// The wall clock moved this much further than the monotonic clock: the
+    /// machine slept, or the clock was changed
+    Jumped(Duration),
+}
+
+/// Paces a job loop: returns every `period`, and early when the wall clock
+/// jumps
+pub struct Ticker {
+    period: std::time::Duration,
+    started: bool,
+    wall: DateTime<Utc>,
+    monotonic: Instant,
+}
+
+impl Ticker {
+    pub fn new(period: std::time::Duration) -> Self {
+        Self {
+            period,
+            started: false,
+            wall: Utc::now(),
+            monotonic: Instant::now(),
+        }
+    }
+
+    /// Wait for the next check. The first tick returns at once.
+    pub async fn tick(&mut self) -> Tick {
+        if !self.started {
+            self.started = true;
+            self.reset();
+            return Tick::Elapsed;
+        }
+        let deadline = self.monotonic + self.period;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                self.reset();
+                return Tick::Elapsed;
+            }
+            tokio::time::sleep(PROBE.min(deadline - now)).await;
+            let drift = self.drift();
+            if drift.num_seconds().abs() > JUMP_SECS {
+                self.reset();
+                return Tick::Jumped(drift);
+            }
+        }
+    }
+
+    /// How far the wall clock got ahead of the monotonic clock since the last tick
+    fn drift(&self) -> Duration {
+        let monotonic = Duration::from_std(self.monotonic.elapsed()).unwrap_or(Duration::zero());
+        (Utc::now() - self.wall) - monotonic
+    }
+
+    fn reset(&mut self) {
+        self.wall = Utc::now();
+        self.monotonic = Instant::now();
+    }
+}
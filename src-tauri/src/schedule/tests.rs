@@ -0,0 +1,88 @@
+use super::*;
+
+fn at(time: &str) -> DateTime<Utc> {
+    format!("2026-10-{}Z", time).parse().unwrap()
+}
+
+#[test]
+fn missed_runs_follow_the_catch_up_policy() {
+    let daily = |catch_up| Schedule::every_hours(24, catch_up);
+    let last = Some(at("10T09:00:00"));
+
+    // Never run, or not due yet
+    let now = at("10T12:00:00");
+    assert_eq!(daily(CatchUp::Skip).due(None, now), vec![now]);
+    assert!(daily(CatchUp::RunAll).due(last, now).is_empty());
+    assert_eq!(
+        daily(CatchUp::RunOnce).next_run(last, now),
+        at("11T09:00:00")
+    );
+
+    // A slot run on time is run whatever the policy
+    let now = at("11T09:05:00");
+    for catch_up in [CatchUp::Skip, CatchUp::RunOnce, CatchUp::RunAll] {
+        assert_eq!(daily(catch_up).due(last, now), vec![now]);
+    }
+
+    // Asleep from the 11th to the 13th at noon: three slots went by
+    let now = at("13T12:00:00");
+    assert!(daily(CatchUp::Skip).due(last, now).is_empty());
+    assert_eq!(daily(CatchUp::Skip).next_run(last, now), at("14T09:00:00"));
+    assert_eq!(daily(CatchUp::RunOnce).due(last, now), vec![now]);
+    assert_eq!(daily(CatchUp::RunOnce).next_run(last, now), now);
+    assert_eq!(
+        daily(CatchUp::RunAll).due(last, now),
+        vec![at("11T09:00:00"), at("12T09:00:00"), now]
+    );
+
+    // A last run in the future means the clock went back; it is not trusted
+    let now = at("09T12:00:00");
+    assert_eq!(daily(CatchUp::Skip).due(last, now), vec![now]);
+}
+
+#[test]
+fn upcoming_runs_list_and_export_as_ical() {
+    let now = at("10T12:00:00");
+    let jobs = vec![
+        ScheduledJob {
+            name: "backup".to_string(),
+            summary: "Runebook backup".to_string(),
+            schedule: Schedule::every_hours(24, CatchUp::RunOnce),
+            last_run: Some(at("10T09:00:00")),
+        },
+        ScheduledJob {
+            name: "digest".to_string(),
+            summary: "Runebook digest to ops, pager".to_string(),
+            schedule: Schedule::every_hours(12, CatchUp::Skip),
+            last_run: None,
+        },
+    ];
+    let runs = next_runs(&jobs, now, Duration::hours(30));
+    let listed: Vec<_> = runs.iter().map(|r| (r.job.as_str(), r.at)).collect();
+    assert_eq!(
+        listed,
+        vec![
+            ("digest", now),
+            ("digest", at("11T00:00:00")),
+            ("backup", at("11T09:00:00")),
+            ("digest", at("11T12:00:00")),
+        ]
+    );
+
+    let ical = to_ical(&jobs, now, Duration::days(90));
+    assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(ical.ends_with("END:VCALENDAR\r\n"));
+    assert!(ical.contains("UID:runebook-backup@schedule\r\n"));
+    assert!(ical.contains("DTSTART:20261011T090000Z\r\n"));
+    assert!(ical.contains("RRULE:FREQ=HOURLY;INTERVAL=12;UNTIL=20270108T120000Z\r\n"));
+    assert!(ical.contains("SUMMARY:Runebook digest to ops\\, pager\r\n"));
+    assert!(ical.split("\r\n").all(|line| line.len() <= 75));
+
+    // Long values are folded onto continuation lines
+    let mut folded = String::new();
+    fold_line(&mut folded, &format!("SUMMARY:{}", "é".repeat(50)));
+    let lines: Vec<_> = folded.trim_end().split("\r\n").collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with(' '));
+    assert!(lines.iter().all(|line| line.len() <= 75));
+}
@@ -0,0 +1,206 @@
+//! Grafana JSON datasource.
+//!
+//! Speaks the protocol of the Grafana JSON datasource plugin
+//! (`simpod-json-datasource`) under `/api/v1/grafana`, so teams can chart
+//! their tooling health: `GET /` for the connection test, `POST /metrics` (and
+//! the older `POST /search`) to list metrics, and `POST /query` for data.
+//!
+//! | Metric | Shape |
+//! |--------|-------|
+//! | `failures_per_hour` | time series of failed commands per hour |
+//! | `suggestion_acceptance` | time series of the share of shown suggestions accepted, per hour |
+//! | `durations_by_command` | table of runs, failures and mean/p50/p95/max duration per program |
+//! | `service_restarts` | table of status, watchdog restarts, errors and uptime per background service |
+//!
+//! Metrics span every workspace, so scoped tokens cannot read them.
+
+use super::rbac::{Action, Grant};
+use super::routes::{open_store, ApiError};
+use super::ApiState;
+use crate::health::SystemHealth;
+use crate::i18n::UserMessage;
+use crate::memory::metrics::{durations_by_command, failures_per_hour, suggestion_acceptance};
+use crate::memory::MetricPoint;
+use axum::extract::{Extension, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const METRICS: &[(&str, &str)] = &[
+    ("failures_per_hour", "Failed commands per hour"),
+    (
+        "suggestion_acceptance",
+        "Share of shown suggestions accepted",
+    ),
+    ("durations_by_command", "Command durations by program"),
+    ("service_restarts", "Background service restarts"),
+];
+
+/// Routes to merge into `/api/v1`. The plugin tests the connection with a
+/// trailing slash, so both forms of the base URL answer.
+pub fn router() -> Router<Arc<ApiState>> {
+    Router::new()
+        .route("/grafana", get(test_connection))
+        .route("/grafana/", get(test_connection))
+        .route("/grafana/metrics", post(metrics))
+        .route("/grafana/search", post(search))
+        .route("/grafana/query", post(query))
+}
+
+async fn test_connection(Extension(grant): Extension<Grant>) -> Result<Json<Value>, ApiError> {
+    grant.require(Action::QueryMemory, None)?;
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+async fn metrics(Extension(grant): Extension<Grant>) -> Result<Json<Value>, ApiError> {
+    grant.require(Action::QueryMemory, None)?;
+    Ok(Json(Value::Array(
+        METRICS
+            .iter()
+            .map(|(value, label)| json!({ "value": value, "label": label, "payloads": [] }))
+            .collect(),
+    )))
+}
+
+async fn search(Extension(grant): Extension<Grant>) -> Result<Json<Vec<&'static str>>, ApiError> {
+    grant.require(Action::QueryMemory, None)?;
+    Ok(Json(METRICS.iter().map(|(value, _)| *value).collect()))
+}
+
+#[derive(Deserialize)]
+struct QueryRange {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct QueryTarget {
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    hide: bool,
+}
+
+#[derive(Deserialize)]
+struct QueryBody {
+    range: QueryRange,
+    #[serde(default)]
+    targets: Vec<QueryTarget>,
+}
+
+fn time_series(target: &str, points: Vec<MetricPoint>) -> Value {
+    let datapoints: Vec<Value> = points
+        .iter()
+        .map(|p| json!([p.value, p.at.timestamp_millis()]))
+        .collect();
+    json!({ "target": target, "datapoints": datapoints })
+}
+
+fn restarts_table(health: &SystemHealth) -> Value {
+    let rows: Vec<Value> = health
+        .components
+        .iter()
+        .map(|c| {
+            json!([
+                c.name,
+                c.status.as_str(),
+                c.restarts,
+                c.error_count,
+                c.uptime_secs
+            ])
+        })
+        .collect();
+    json!({
+        "type": "table",
+        "columns": [
+            { "text": "service", "type": "string" },
+            { "text": "status", "type": "string" },
+            { "text": "restarts", "type": "number" },
+            { "text": "errors", "type": "number" },
+            { "text": "uptime_secs", "type": "number" },
+        ],
+        "rows": rows,
+    })
+}
+
+async fn query(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Json(body): Json<QueryBody>,
+) -> Result<Json<Vec<Value>>, ApiError> {
+    grant.require(Action::QueryMemory, None)?;
+    let (from, to) = (body.range.from, body.range.to);
+    let targets: Vec<&str> = body
+        .targets
+        .iter()
+        .filter(|t| !t.hide && !t.target.is_empty())
+        .map(|t| t.target.as_str())
+        .collect();
+    if let Some(unknown) = targets
+        .iter()
+        .find(|t| !METRICS.iter().any(|(name, _)| name == *t))
+    {
+        return Err(UserMessage::new("metric-unknown")
+            .with("metric", unknown)
+            .into());
+    }
+    if targets.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+
+    // Service health needs no store, so it charts even while the store is down
+    let mut data = None;
+    let mut results = Vec::new();
+    for target in targets {
+        if target == "service_restarts" {
+            results.push(restarts_table(&state.health.snapshot()));
+            continue;
+        }
+        if data.is_none() {
+            let store = open_store(&state).await?;
+            let commands = store
+                .commands_between(from, to)
+                .await
+                .map_err(UserMessage::wrap("metrics-failed"))?;
+            data = Some((store, commands));
+        }
+        let (store, commands) = data.as_ref().expect("opened above");
+        results.push(match target {
+            "failures_per_hour" => time_series(target, failures_per_hour(commands, from, to)),
+            "suggestion_acceptance" => {
+                let impressions = store
+                    .list_impressions()
+                    .await
+                    .map_err(UserMessage::wrap("metrics-failed"))?;
+                time_series(target, suggestion_acceptance(&impressions, from, to))
+            }
+            _ => {
+                let rows: Vec<Value> = durations_by_command(commands)
+                    .into_iter()
+                    .map(|d| {
+                        json!([
+                            d.command, d.runs, d.failures, d.mean_ms, d.p50_ms, d.p95_ms, d.max_ms
+                        ])
+                    })
+                    .collect();
+                json!({
+                    "type": "table",
+                    "columns": [
+                        { "text": "command", "type": "string" },
+                        { "text": "runs", "type": "number" },
+                        { "text": "failures", "type": "number" },
+                        { "text": "mean_ms", "type": "number" },
+                        { "text": "p50_ms", "type": "number" },
+                        { "text": "p95_ms", "type": "number" },
+                        { "text": "max_ms", "type": "number" },
+                    ],
+                    "rows": rows,
+                })
+            }
+        });
+    }
+    Ok(Json(results))
+}
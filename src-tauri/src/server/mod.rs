@@ -0,0 +1,240 @@
+//! HTTP API for headless deployments.
+//!
+//! An optional axum server exposing command execution, canvas runs, and memory
+//! queries, so RuneBook can run on a jump box without a window and be driven
+//! from scripts or a remote GUI. Requests go through the same services as the
+//! Tauri commands ([`ExecutionService`], the guarded memory store), so policy,
+//! auditing, and quotas apply identically.
+//!
+//! Off by default. It binds to loopback unless configured otherwise, and every
+//! `/api/v1` request needs `Authorization: Bearer <token>`: the `[server]`
+//! token, or a workspace-scoped token from [`rbac`]. The one exception is a
+//! shared session's feed, which takes that session's share token
+//! ([`crate::observe`]).
+
+pub mod grafana;
+pub mod rbac;
+pub mod routes;
+
+use crate::execution::{ExecutionRequest, ExecutionService};
+use crate::health::HealthRegistry;
+use crate::i18n::UserMessage;
+use crate::memory::{LiveOutputs, StoreOpener};
+use crate::observe::ShareRegistry;
+use anyhow::{Context, Result};
+use rbac::{Action, Grant, TokenRegistry};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use ts_rs::TS;
+
+/// `[server]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ServerConfig {
+    pub enabled: bool,
+    pub bind: String,
+    /// Bearer token; when unset one is generated and kept in `api-token`
+    /// in the config directory
+    pub token: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:7717".to_string(),
+            token: None,
+        }
+    }
+}
+
+/// `[grpc]` configuration section. The service is only built with the `grpc`
+/// feature; it shares the HTTP API's token.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub bind: String,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:7718".to_string(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The configured token, or the persisted generated one
+    pub fn resolve_token(&self) -> Result<String> {
+        if let Some(token) = self.token.as_ref().filter(|t| !t.is_empty()) {
+            return Ok(token.clone());
+        }
+        load_or_create_token(&crate::config::config_dir().join("api-token"))
+    }
+}
+
+fn load_or_create_token(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        // Left empty by a crash; make a new one in its place
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    let token = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match create_private(path) {
+        Ok(mut file) => {
+            file.write_all(token.as_bytes())
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(token)
+        }
+        // Another process starting at the same time made it first
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            Ok(std::fs::read_to_string(path)?.trim().to_string())
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to create {}", path.display())),
+    }
+}
+
+/// Create `path`, which must not exist, readable and writable by its owner
+/// only. On Unix the mode is set as the file is created, so there is no
+/// moment at which others can open it.
+pub(crate) fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Replace `path` with `contents` through an owner-only `.partial` file
+/// beside it, so the contents are never readable by others and a crash
+/// mid-write keeps the old file
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = std::path::PathBuf::from(partial);
+    match std::fs::remove_file(&partial) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    create_private(&partial)
+        .and_then(|mut file| file.write_all(contents))
+        .and_then(|()| std::fs::rename(&partial, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Services shared by the API handlers
+pub struct ApiState {
+    pub execution: Arc<ExecutionService>,
+    pub open_store: StoreOpener,
+    token: String,
+    pub tokens: Option<Arc<TokenRegistry>>,
+    pub health: HealthRegistry,
+    pub shares: Arc<ShareRegistry>,
+    pub live: Arc<LiveOutputs>,
+}
+
+impl ApiState {
+    pub fn new(execution: Arc<ExecutionService>, open_store: StoreOpener, token: String) -> Self {
+        Self {
+            execution,
+            open_store,
+            token,
+            tokens: None,
+            health: HealthRegistry::new(),
+            shares: Arc::new(ShareRegistry::new()),
+            live: Arc::new(LiveOutputs::new()),
+        }
+    }
+
+    /// Serve shared sessions from the app's share registry and live outputs
+    pub fn with_sharing(mut self, shares: Arc<ShareRegistry>, live: Arc<LiveOutputs>) -> Self {
+        self.shares = shares;
+        self.live = live;
+        self
+    }
+
+    /// Report into the app's health registry rather than a private one
+    pub fn with_health(mut self, health: HealthRegistry) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Also accept tokens minted with `runebook token mint`
+    pub fn with_tokens(mut self, tokens: Arc<TokenRegistry>) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    /// What the presented bearer token may do, if it is valid
+    pub(crate) fn authenticate(&self, presented: &str) -> Option<Grant> {
+        if rbac::same_secret(&self.token, presented) {
+            return Some(Grant::owner());
+        }
+        self.tokens.as_ref()?.authenticate(presented)
+    }
+
+    /// Check that `grant` may run `request`: its working directory, after
+    /// profile and template resolution, must lie in a workspace where the
+    /// token is an operator
+    pub(crate) fn authorize_run(
+        &self,
+        grant: &Grant,
+        request: &ExecutionRequest,
+    ) -> Result<(), UserMessage> {
+        if grant.scope(Action::Run).is_none() {
+            return Ok(());
+        }
+        let resolved = self.execution.preview(request)?;
+        grant.require(
+            Action::Run,
+            Some(resolved.spec.cwd.as_deref().unwrap_or("")),
+        )
+    }
+}
+
+/// Serve the API until the task is dropped
+pub async fn serve(config: &ServerConfig, state: ApiState) -> Result<()> {
+    let addr: SocketAddr = config
+        .bind
+        .parse()
+        .with_context(|| format!("Invalid server bind address {}", config.bind))?;
+    if !addr.ip().is_loopback() {
+        log::warn!(
+            "[server] Listening on non-loopback address {}; anyone who can reach it needs only the API token",
+            addr
+        );
+    }
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", addr))?;
+    log::info!("[server] HTTP API listening on http://{}", addr);
+    state.health.component("http_api").ok();
+    axum::serve(listener, routes::router(Arc::new(state)))
+        .await
+        .context("HTTP API server failed")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,412 @@
+//! Workspace roles for the HTTP and gRPC APIs.
+//!
+//! Shared deployments mint one token per person or script with `runebook token
+//! mint`. Each token grants a [`Role`] per workspace (a directory; `*` means
+//! every workspace):
+//!
+//! - `viewer` queries memory for sessions in its workspaces
+//! - `operator` also runs commands and canvases whose working directory is
+//!   inside its workspaces
+//! - `admin` also lists and revokes tokens and deletes saved data
+//!
+//! Tokens live in `api-tokens.json` in the config directory as SHA-256
+//! hashes; the secret is shown once when minted. The file is re-read when it
+//! changes, so `runebook token revoke` takes effect on a running server. The
+//! `[server]` token keeps working as an admin token for every workspace.
+
+use crate::i18n::UserMessage;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Grant key covering every workspace
+pub const ALL_WORKSPACES: &str = "*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => bail!("Unknown role {:?} (expected viewer, operator, or admin)", s),
+        }
+    }
+}
+
+/// What a request wants to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    QueryMemory,
+    Run,
+    Administer,
+}
+
+impl Action {
+    pub fn required_role(self) -> Role {
+        match self {
+            Action::QueryMemory => Role::Viewer,
+            Action::Run => Role::Operator,
+            Action::Administer => Role::Admin,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Action::QueryMemory => "query memory",
+            Action::Run => "run commands",
+            Action::Administer => "administer",
+        }
+    }
+}
+
+/// Whether `path` is `workspace` or inside it, once symlinks and `..` are
+/// resolved. A path that does not exist is in no workspace.
+pub fn within(path: &str, workspace: &str) -> bool {
+    if workspace == ALL_WORKSPACES {
+        return true;
+    }
+    match (
+        std::fs::canonicalize(path),
+        std::fs::canonicalize(workspace),
+    ) {
+        (Ok(path), Ok(workspace)) => path.starts_with(workspace),
+        _ => false,
+    }
+}
+
+/// What an authenticated request may do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    /// Token id; `None` for the `[server]` token
+    pub token_id: Option<String>,
+    pub roles: BTreeMap<String, Role>,
+}
+
+impl Grant {
+    /// Admin in every workspace (the `[server]` token)
+    pub fn owner() -> Self {
+        Self {
+            token_id: None,
+            roles: BTreeMap::from([(ALL_WORKSPACES.to_string(), Role::Admin)]),
+        }
+    }
+
+    /// The strongest role that applies at `path`; `None` asks for a role that
+    /// applies everywhere
+    pub fn role_at(&self, path: Option<&str>) -> Option<Role> {
+        self.roles
+            .iter()
+            .filter(|(workspace, _)| match path {
+                Some(path) => within(path, workspace),
+                None => workspace.as_str() == ALL_WORKSPACES,
+            })
+            .map(|(_, role)| *role)
+            .max()
+    }
+
+    pub fn require(&self, action: Action, path: Option<&str>) -> Result<(), UserMessage> {
+        if self.role_at(path) >= Some(action.required_role()) {
+            return Ok(());
+        }
+        Err(UserMessage::new("forbidden")
+            .with("action", action.name())
+            .with("workspace", path.unwrap_or("all workspaces")))
+    }
+
+    /// Workspaces where `action` is allowed: `None` when it is allowed everywhere
+    pub fn scope(&self, action: Action) -> Option<Vec<String>> {
+        if self.role_at(None) >= Some(action.required_role()) {
+            return None;
+        }
+        Some(
+            self.roles
+                .iter()
+                .filter(|(_, role)| **role >= action.required_role())
+                .map(|(workspace, _)| workspace.clone())
+                .collect(),
+        )
+    }
+}
+
+/// A minted token, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    pub roles: BTreeMap<String, Role>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TokenRecord {
+    #[serde(flatten)]
+    info: TokenInfo,
+    /// Hex SHA-256 of the secret
+    hash: String,
+}
+
+pub(crate) fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Constant-time comparison
+pub(crate) fn same_secret(expected: &str, presented: &str) -> bool {
+    let (expected, presented) = (expected.as_bytes(), presented.as_bytes());
+    expected.len() == presented.len()
+        && expected
+            .iter()
+            .zip(presented)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// The token file
+#[derive(Debug, Default)]
+pub struct TokenStore {
+    path: PathBuf,
+    tokens: Vec<TokenRecord>,
+}
+
+impl TokenStore {
+    pub fn default_path() -> PathBuf {
+        crate::config::config_dir().join("api-tokens.json")
+    }
+
+    /// Load the token file; a missing file has no tokens
+    pub fn load(path: &Path) -> Result<Self> {
+        let tokens = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid token file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            tokens,
+        })
+    }
+
+    /// Write the token file, readable by its owner only
+    pub fn save(&self) -> Result<()> {
+        super::write_private(
+            &self.path,
+            serde_json::to_string_pretty(&self.tokens)?.as_bytes(),
+        )
+    }
+
+    /// Create a token; returns it with its secret, which is not stored
+    pub fn mint(
+        &mut self,
+        name: &str,
+        roles: BTreeMap<String, Role>,
+    ) -> Result<(TokenInfo, String)> {
+        if roles.is_empty() {
+            bail!("A token needs at least one workspace role");
+        }
+        for workspace in roles.keys() {
+            if workspace != ALL_WORKSPACES && !Path::new(workspace).is_absolute() {
+                bail!("Workspace {} must be an absolute path or *", workspace);
+            }
+        }
+        let secret = format!(
+            "rbk_{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let info = TokenInfo {
+            id: uuid::Uuid::new_v4().simple().to_string()[..12].to_string(),
+            name: name.to_string(),
+            roles,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        self.tokens.push(TokenRecord {
+            info: info.clone(),
+            hash: hash_secret(&secret),
+        });
+        Ok((info, secret))
+    }
+
+    /// Revoke a token by id. Returns false when no active token has that id.
+    pub fn revoke(&mut self, id: &str) -> bool {
+        match self
+            .tokens
+            .iter_mut()
+            .find(|t| t.info.id == id && t.info.revoked_at.is_none())
+        {
+            Some(token) => {
+                token.info.revoked_at = Some(Utc::now());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<TokenInfo> {
+        self.tokens.iter().map(|t| t.info.clone()).collect()
+    }
+
+    pub fn authenticate(&self, secret: &str) -> Option<Grant> {
+        let hash = hash_secret(secret);
+        self.tokens
+            .iter()
+            .filter(|t| t.info.revoked_at.is_none())
+            .find(|t| same_secret(&t.hash, &hash))
+            .map(|t| Grant {
+                token_id: Some(t.info.id.clone()),
+                roles: t.info.roles.clone(),
+            })
+    }
+}
+
+/// The token file as seen by a running server, re-read when it changes
+pub struct TokenRegistry {
+    path: PathBuf,
+    /// File modification time and length the cached store was read at
+    cached: Mutex<(Option<(SystemTime, u64)>, TokenStore)>,
+}
+
+impl TokenRegistry {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            cached: Mutex::new((None, TokenStore::default())),
+        }
+    }
+
+    fn with_current<T>(&self, f: impl FnOnce(&mut TokenStore) -> T) -> T {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| Ok((m.modified()?, m.len())))
+            .ok();
+        if modified.is_none() || modified != cached.0 {
+            match TokenStore::load(&self.path) {
+                Ok(store) => *cached = (modified, store),
+                Err(e) => log::warn!("[server] Keeping previous API tokens: {:#}", e),
+            }
+        }
+        f(&mut cached.1)
+    }
+
+    pub fn authenticate(&self, secret: &str) -> Option<Grant> {
+        self.with_current(|store| store.authenticate(secret))
+    }
+
+    pub fn list(&self) -> Vec<TokenInfo> {
+        self.with_current(|store| store.list())
+    }
+
+    pub fn revoke(&self, id: &str) -> Result<bool> {
+        self.with_current(|store| {
+            if !store.revoke(id) {
+                return Ok(false);
+            }
+            store.save()?;
+            Ok(true)
+        })
+    }
+}
+
+const TOKEN_USAGE: &str = "\
+Usage:
+  runebook token mint --name <name> --role <viewer|operator|admin> [--workspace <dir>]...
+  runebook token list
+  runebook token revoke <id>
+
+Without --workspace the role applies to every workspace.";
+
+/// Run a `runebook token ...` command against `store`, returning what to print
+pub fn token_command(args: &[String], store: &mut TokenStore) -> Result<String> {
+    match args.first().map(String::as_str) {
+        Some("mint") => {
+            let mut name = None;
+            let mut role = None;
+            let mut workspaces = Vec::new();
+            let mut rest = args[1..].iter();
+            while let Some(flag) = rest.next() {
+                let value = rest
+                    .next()
+                    .with_context(|| format!("{} needs a value\n\n{}", flag, TOKEN_USAGE))?;
+                match flag.as_str() {
+                    "--name" => name = Some(value.clone()),
+                    "--role" => role = Some(value.parse::<Role>()?),
+                    "--workspace" => workspaces.push(value.clone()),
+                    _ => bail!("Unknown option {}\n\n{}", flag, TOKEN_USAGE),
+                }
+            }
+            let name = name.with_context(|| format!("--name is required\n\n{}", TOKEN_USAGE))?;
+            let role = role.with_context(|| format!("--role is required\n\n{}", TOKEN_USAGE))?;
+            if workspaces.is_empty() {
+                workspaces.push(ALL_WORKSPACES.to_string());
+            }
+            let roles = workspaces.into_iter().map(|w| (w, role)).collect();
+            let (info, secret) = store.mint(&name, roles)?;
+            store.save()?;
+            Ok(format!(
+                "Minted {} token {} ({}). It is shown only once:\n{}",
+                role.name(),
+                info.id,
+                info.name,
+                secret
+            ))
+        }
+        Some("list") => Ok(store
+            .list()
+            .iter()
+            .map(|t| {
+                let roles: Vec<String> = t
+                    .roles
+                    .iter()
+                    .map(|(w, r)| format!("{}={}", w, r.name()))
+                    .collect();
+                let state = match t.revoked_at {
+                    Some(at) => format!("revoked {}", at.format("%Y-%m-%d")),
+                    None => "active".to_string(),
+                };
+                format!("{}  {}  {}  {}", t.id, t.name, roles.join(","), state)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        Some("revoke") => {
+            let id = args
+                .get(1)
+                .with_context(|| format!("revoke needs a token id\n\n{}", TOKEN_USAGE))?;
+            if !store.revoke(id) {
+                bail!("No active token with id {}", id);
+            }
+            store.save()?;
+            Ok(format!("Revoked token {}", id))
+        }
+        _ => bail!("{}", TOKEN_USAGE),
+    }
+}
@@ -0,0 +1,501 @@
+//! API routes.
+//!
+//! Errors are [`UserMessage`] JSON bodies (`code`, `params`, `message`), the same
+//! shape the desktop frontend receives, with an HTTP status derived from the code.
+//!
+//! Every handler checks the caller's [`Grant`]; memory queries from tokens
+//! scoped to workspaces only return records from those workspaces. The
+//! observe feed instead checks the session's share token.
+
+use super::rbac;
+use super::rbac::{Action, Grant, TokenInfo};
+use super::ApiState;
+use crate::execution::{
+    run_canvas, CanvasDocument, CanvasRunReport, CommandOutcome, DryRun, ExecutionRequest,
+    ResolvedExecution,
+};
+use crate::health::SystemHealth;
+use crate::i18n::UserMessage;
+use crate::memory::{self, MemoryStore};
+use crate::observe::{Share, ShareGrant};
+use axum::extract::{Extension, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+pub struct ApiError(pub UserMessage);
+
+impl From<UserMessage> for ApiError {
+    fn from(message: UserMessage) -> Self {
+        ApiError(message)
+    }
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self.0.code {
+            "unauthorized" => StatusCode::UNAUTHORIZED,
+            "forbidden" => StatusCode::FORBIDDEN,
+            "session-not-found" | "token-not-found" | "share-not-found" => StatusCode::NOT_FOUND,
+            "confirmation-required" => StatusCode::CONFLICT,
+            "command-blocked" => StatusCode::FORBIDDEN,
+            "template-unresolved"
+            | "command-resolve-failed"
+            | "canvas-invalid"
+            | "metric-unknown" => StatusCode::UNPROCESSABLE_ENTITY,
+            "memory-store-unavailable" => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self.0)).into_response()
+    }
+}
+
+pub fn router(state: Arc<ApiState>) -> Router {
+    let api = Router::new()
+        .route("/execute", post(execute))
+        .route("/execute/preview", post(preview))
+        .route("/canvas/run", post(canvas_run))
+        .route("/memory/sessions", get(list_sessions))
+        .route("/memory/errors", get(recent_errors))
+        .route("/memory/diagnostics", get(diagnostics))
+        .route("/memory/suggestions", get(suggestions))
+        .route("/memory/time-report", get(time_report))
+        .route("/memory/sessions/{id}", delete(delete_session))
+        .route("/tokens", get(list_tokens))
+        .route("/tokens/{id}", delete(revoke_token))
+        .route("/shares", get(list_shares).post(start_share))
+        .route("/shares/{id}", delete(revoke_share))
+        .route("/system/health", get(system_health))
+        .merge(super::grafana::router())
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            require_token,
+        ));
+    Router::new()
+        .route("/health", get(health))
+        .route("/api/v1/observe/{session_id}", get(observe))
+        .nest("/api/v1", api)
+        .with_state(state)
+}
+
+async fn require_token(
+    State(state): State<Arc<ApiState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let grant = bearer(request.headers()).and_then(|token| state.authenticate(token));
+    match grant {
+        Some(grant) => {
+            request.extensions_mut().insert(grant);
+            next.run(request).await
+        }
+        None => ApiError(UserMessage::new("unauthorized")).into_response(),
+    }
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok", "version": env!("CARGO_PKG_VERSION") }))
+}
+
+/// Component health; error messages can name hosts and paths, so unlike
+/// `/health` this needs an admin token
+async fn system_health(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+) -> ApiResult<SystemHealth> {
+    grant.require(Action::Administer, None)?;
+    state.health.probe_storage(&state.open_store).await;
+    Ok(Json(state.health.snapshot()))
+}
+
+pub(super) async fn open_store(state: &ApiState) -> Result<MemoryStore, ApiError> {
+    (state.open_store)()
+        .await
+        .map_err(|e| ApiError(UserMessage::wrap("memory-store-unavailable")(e)))
+}
+
+/// Sessions the grant may see, or `None` when it may see all of them
+async fn visible_sessions(
+    grant: &Grant,
+    store: &MemoryStore,
+) -> Result<Option<HashSet<String>>, ApiError> {
+    let Some(workspaces) = grant.scope(Action::QueryMemory) else {
+        return Ok(None);
+    };
+    if workspaces.is_empty() {
+        grant.require(Action::QueryMemory, None)?;
+    }
+    let sessions = store
+        .list_sessions()
+        .await
+        .map_err(UserMessage::wrap("memory-list-sessions-failed"))?;
+    Ok(Some(
+        sessions
+            .iter()
+            .filter(|s| {
+                let workspace = memory::usage::session_workspace(s);
+                workspaces.iter().any(|w| rbac::within(&workspace, w))
+            })
+            .map(|s| s.id.clone())
+            .collect(),
+    ))
+}
+
+/// Comma-separated tag list from a query parameter
+fn split_tags(tags: Option<&str>) -> Vec<String> {
+    tags.map(|t| {
+        t.split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+struct ExecuteBody {
+    #[serde(flatten)]
+    request: ExecutionRequest,
+    #[serde(default)]
+    confirmation_token: Option<String>,
+    /// Report what would run instead of running it
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ExecuteResponse {
+    Ran(CommandOutcome),
+    DryRun(DryRun),
+}
+
+/// Run a command. A non-zero exit is still `200`; check `success`. With
+/// `dry_run`, nothing runs and the body is the [`DryRun`].
+async fn execute(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Json(body): Json<ExecuteBody>,
+) -> ApiResult<ExecuteResponse> {
+    state.authorize_run(&grant, &body.request)?;
+    if body.dry_run {
+        return Ok(Json(ExecuteResponse::DryRun(
+            state.execution.dry_run(&body.request)?,
+        )));
+    }
+    let outcome = state
+        .execution
+        .execute(&body.request, body.confirmation_token.as_deref(), "api")
+        .await?;
+    Ok(Json(ExecuteResponse::Ran(outcome)))
+}
+
+async fn preview(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Json(request): Json<ExecutionRequest>,
+) -> ApiResult<ResolvedExecution> {
+    state.authorize_run(&grant, &request)?;
+    Ok(Json(state.execution.preview(&request)?))
+}
+
+#[derive(Deserialize)]
+struct CanvasRunBody {
+    canvas: CanvasDocument,
+    #[serde(default)]
+    variables: HashMap<String, String>,
+}
+
+async fn canvas_run(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Json(body): Json<CanvasRunBody>,
+) -> ApiResult<CanvasRunReport> {
+    for request in body.canvas.terminal_requests(&body.variables) {
+        state.authorize_run(&grant, &request)?;
+    }
+    let report = run_canvas(&state.execution, &body.canvas, &body.variables, "api").await?;
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+struct SessionsQuery {
+    tags: Option<String>,
+}
+
+async fn list_sessions(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Query(query): Query<SessionsQuery>,
+) -> ApiResult<Vec<memory::Session>> {
+    let store = open_store(&state).await?;
+    let visible = visible_sessions(&grant, &store).await?;
+    let mut sessions = store
+        .list_sessions_tagged(&split_tags(query.tags.as_deref()))
+        .await
+        .map_err(UserMessage::wrap("memory-list-sessions-failed"))?;
+    if let Some(visible) = visible {
+        sessions.retain(|s| visible.contains(&s.id));
+    }
+    Ok(Json(sessions))
+}
+
+#[derive(Deserialize)]
+struct ErrorsQuery {
+    limit: Option<usize>,
+    severity: Option<String>,
+    tags: Option<String>,
+}
+
+async fn recent_errors(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Query(query): Query<ErrorsQuery>,
+) -> ApiResult<Vec<memory::Error>> {
+    let store = open_store(&state).await?;
+    let visible = visible_sessions(&grant, &store).await?;
+    let mut errors = store
+        .query_recent_errors_tagged(
+            query.limit,
+            None,
+            query.severity.as_deref(),
+            &split_tags(query.tags.as_deref()),
+        )
+        .await
+        .map_err(UserMessage::wrap("memory-query-errors-failed"))?;
+    if let Some(visible) = visible {
+        errors.retain(|e| visible.contains(&e.session_id));
+    }
+    Ok(Json(errors))
+}
+
+#[derive(Deserialize)]
+struct DiagnosticsQuery {
+    path: String,
+}
+
+/// LSP diagnostics for a file or directory, for editor integrations
+async fn diagnostics(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Query(query): Query<DiagnosticsQuery>,
+) -> ApiResult<Vec<memory::FileDiagnostics>> {
+    grant.require(Action::QueryMemory, Some(&query.path))?;
+    let store = open_store(&state).await?;
+    let diagnostics = store
+        .diagnostics_for_path(&query.path)
+        .await
+        .map_err(UserMessage::wrap("diagnostics-failed"))?;
+    Ok(Json(diagnostics))
+}
+
+#[derive(Deserialize)]
+struct SuggestionsQuery {
+    priority: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn suggestions(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Query(query): Query<SuggestionsQuery>,
+) -> ApiResult<Vec<memory::Suggestion>> {
+    let workspaces = grant.scope(Action::QueryMemory);
+    if workspaces.as_ref().is_some_and(Vec::is_empty) {
+        grant.require(Action::QueryMemory, None)?;
+    }
+    let store = open_store(&state).await?;
+    let mut suggestions = store
+        .get_suggestions(query.priority.as_deref(), query.limit)
+        .await
+        .map_err(UserMessage::wrap("memory-suggestions-failed"))?;
+    if let Some(workspaces) = workspaces {
+        suggestions.retain(|s| {
+            workspaces
+                .iter()
+                .any(|w| crate::ipc::protocol::suggestion_in_workspace(s, w))
+        });
+    }
+    Ok(Json(suggestions))
+}
+
+#[derive(Deserialize)]
+struct TimeReportQuery {
+    range: Option<String>,
+    group_by: Option<memory::TimeGroupBy>,
+    top_commands: Option<usize>,
+}
+
+/// Totals span every workspace, so scoped tokens cannot read them
+async fn time_report(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Query(query): Query<TimeReportQuery>,
+) -> ApiResult<memory::TimeReport> {
+    grant.require(Action::QueryMemory, None)?;
+    let (from, to) = memory::stats::parse_range(
+        query.range.as_deref().unwrap_or("month"),
+        chrono::Utc::now(),
+    )
+    .map_err(UserMessage::wrap("time-report-failed"))?;
+    let store = open_store(&state).await?;
+    let report = store
+        .time_report(
+            from,
+            to,
+            query.group_by.unwrap_or(memory::TimeGroupBy::Project),
+            query.top_commands.unwrap_or(5),
+        )
+        .await
+        .map_err(UserMessage::wrap("time-report-failed"))?;
+    Ok(Json(report))
+}
+
+/// Delete a session and its records (admin of the session's workspace)
+async fn delete_session(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Path(id): Path<String>,
+) -> ApiResult<serde_json::Value> {
+    let store = open_store(&state).await?;
+    let session = store
+        .list_sessions()
+        .await
+        .map_err(UserMessage::wrap("memory-list-sessions-failed"))?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| UserMessage::new("session-not-found").with("id", &id))?;
+    grant.require(
+        Action::Administer,
+        Some(&memory::usage::session_workspace(&session)),
+    )?;
+    let removed = store
+        .delete_session(&id)
+        .await
+        .map_err(UserMessage::wrap("memory-delete-failed"))?;
+    Ok(Json(serde_json::json!({ "removed": removed })))
+}
+
+async fn list_tokens(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+) -> ApiResult<Vec<TokenInfo>> {
+    grant.require(Action::Administer, None)?;
+    Ok(Json(
+        state.tokens.as_ref().map(|t| t.list()).unwrap_or_default(),
+    ))
+}
+
+async fn revoke_token(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Path(id): Path<String>,
+) -> ApiResult<serde_json::Value> {
+    grant.require(Action::Administer, None)?;
+    let revoked = match &state.tokens {
+        Some(tokens) => tokens
+            .revoke(&id)
+            .map_err(UserMessage::wrap("token-store-failed"))?,
+        None => false,
+    };
+    if !revoked {
+        return Err(UserMessage::new("token-not-found").with("id", &id).into());
+    }
+    Ok(Json(serde_json::json!({ "revoked": id })))
+}
+
+/// A shared session's live feed as server-sent events, one JSON
+/// [`memory::SessionEvent`] each. Takes the session's share token, not an API
+/// token, and ends when the share is revoked.
+async fn observe(
+    State(state): State<Arc<ApiState>>,
+    Path(session_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let share = bearer(&headers)
+        .and_then(|token| state.shares.authenticate(&session_id, token))
+        .ok_or_else(|| ApiError(UserMessage::new("unauthorized")))?;
+    let (events, received) = tokio::sync::mpsc::unbounded_channel();
+    let (shares, live) = (Arc::clone(&state.shares), Arc::clone(&state.live));
+    tokio::spawn(async move {
+        let _ = shares
+            .observe(&live, &share, |event| {
+                let event = Event::default().json_data(&event)?;
+                events
+                    .send(Ok::<_, Infallible>(event))
+                    .map_err(|_| anyhow::anyhow!("Observer left"))
+            })
+            .await;
+    });
+    Ok(Sse::new(UnboundedReceiverStream::new(received)).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize)]
+struct ShareBody {
+    session_id: String,
+}
+
+/// Share a session with observers (admin of the session's workspace). The
+/// token in the response is not shown again.
+async fn start_share(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Json(body): Json<ShareBody>,
+) -> ApiResult<ShareGrant> {
+    let store = open_store(&state).await?;
+    let session = store
+        .list_sessions()
+        .await
+        .map_err(UserMessage::wrap("memory-list-sessions-failed"))?
+        .into_iter()
+        .find(|s| s.id == body.session_id)
+        .ok_or_else(|| UserMessage::new("session-not-found").with("id", &body.session_id))?;
+    grant.require(
+        Action::Administer,
+        Some(&memory::usage::session_workspace(&session)),
+    )?;
+    Ok(Json(state.shares.start(&session.id)))
+}
+
+async fn list_shares(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+) -> ApiResult<Vec<Share>> {
+    grant.require(Action::Administer, None)?;
+    Ok(Json(state.shares.list()))
+}
+
+async fn revoke_share(
+    State(state): State<Arc<ApiState>>,
+    Extension(grant): Extension<Grant>,
+    Path(id): Path<String>,
+) -> ApiResult<serde_json::Value> {
+    grant.require(Action::Administer, None)?;
+    if !state.shares.revoke(&id) {
+        return Err(UserMessage::new("share-not-found").with("id", &id).into());
+    }
+    Ok(Json(serde_json::json!({ "revoked": id })))
+}
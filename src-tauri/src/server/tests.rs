@@ -0,0 +1,291 @@
+use super::*;
+use crate::audit::AuditLog;
+use crate::execution::{CommandGate, SafetyConfig};
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use tower::ServiceExt;
+
+fn state(dir: &Path) -> ApiState {
+    let audit = Arc::new(AuditLog::new(dir.join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution = Arc::new(ExecutionService::new(gate, Default::default()));
+    let open_store =
+        crate::memory::store_opener(|| async { Err(anyhow::anyhow!("store offline")) });
+    ApiState::new(execution, open_store, "secret".to_string())
+}
+
+fn preview(token: Option<&str>) -> Request<Body> {
+    let mut request =
+        Request::post("/api/v1/execute/preview").header("content-type", "application/json");
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    request
+        .body(Body::from(r#"{"command":"rm","args":["-rf","*"]}"#))
+        .unwrap()
+}
+
+#[test]
+fn generated_token_is_kept_private() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("api-token");
+
+    let token = load_or_create_token(&path).unwrap();
+    assert_eq!(token.len(), 64);
+    assert_eq!(load_or_create_token(&path).unwrap(), token);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // An empty file, as a crash mid-write leaves, is replaced
+    std::fs::write(&path, "").unwrap();
+    let replaced = load_or_create_token(&path).unwrap();
+    assert_ne!(replaced, token);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), replaced);
+}
+
+#[tokio::test]
+async fn api_requires_bearer_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = routes::router(Arc::new(state(dir.path())));
+
+    let health = Request::get("/health").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(health).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(preview(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.clone().oneshot(preview(Some("wrong"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.clone().oneshot(preview(Some("secret"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let resolved: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(resolved["policy"]["decision"], "confirm");
+
+    // Memory routes report an unavailable store as 503 with a message code
+    let sessions = Request::get("/api/v1/memory/sessions")
+        .header("authorization", "Bearer secret")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(sessions).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+fn preview_in(token: &str, cwd: &str) -> Request<Body> {
+    Request::post("/api/v1/execute/preview")
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::from(
+            serde_json::json!({ "command": "ls", "cwd": cwd }).to_string(),
+        ))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn workspace_roles_limit_tokens() {
+    use rbac::{Role, TokenRegistry, TokenStore};
+    use std::collections::BTreeMap;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("api-tokens.json");
+    let app_dir = dir.path().join("app");
+    let other = dir.path().join("other");
+    std::fs::create_dir_all(app_dir.join("web")).unwrap();
+    std::fs::create_dir_all(&other).unwrap();
+    let app_dir = app_dir.to_str().unwrap();
+    let other = other.to_str().unwrap();
+
+    let mut store = TokenStore::load(&path).unwrap();
+    let minted = rbac::token_command(
+        &[
+            "mint",
+            "--name",
+            "ci",
+            "--role",
+            "operator",
+            "--workspace",
+            app_dir,
+        ]
+        .map(String::from),
+        &mut store,
+    )
+    .unwrap();
+    let operator = minted.lines().last().unwrap().to_string();
+    let (viewer_info, viewer) = store
+        .mint("dash", BTreeMap::from([("*".to_string(), Role::Viewer)]))
+        .unwrap();
+    store.save().unwrap();
+
+    let state = state(dir.path()).with_tokens(Arc::new(TokenRegistry::new(path.clone())));
+    let app = routes::router(Arc::new(state));
+    let status = |request: Request<Body>| {
+        let app = app.clone();
+        async move { app.oneshot(request).await.unwrap().status() }
+    };
+
+    // Operators run inside their workspace only; neither `..` nor a symlink
+    // escapes it, and a directory that does not exist is in no workspace
+    assert_eq!(
+        status(preview_in(&operator, &format!("{app_dir}/web"))).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(preview_in(&operator, other)).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(preview_in(&operator, &format!("{app_dir}/../other"))).await,
+        StatusCode::FORBIDDEN
+    );
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(other, format!("{app_dir}/link")).unwrap();
+        assert_eq!(
+            status(preview_in(&operator, &format!("{app_dir}/link"))).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+    assert_eq!(
+        status(preview_in(&operator, &format!("{app_dir}/missing"))).await,
+        StatusCode::FORBIDDEN
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    // Viewers can query but not run
+    assert_eq!(
+        status(preview_in(&viewer, app_dir)).await,
+        StatusCode::FORBIDDEN
+    );
+
+    // Token administration needs admin; the [server] token is admin everywhere
+    let tokens = |token: &str| {
+        Request::get("/api/v1/tokens")
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(status(tokens(&operator)).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(tokens("secret")).await, StatusCode::OK);
+
+    // Revoking from the CLI takes effect without a restart
+    let mut store = TokenStore::load(&path).unwrap();
+    rbac::token_command(&["revoke".to_string(), viewer_info.id], &mut store).unwrap();
+    assert_eq!(
+        status(preview_in(&viewer, "/")).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn grafana_datasource_lists_and_checks_metrics() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = routes::router(Arc::new(state(dir.path())));
+    let request = |method: &str, path: &str, body: serde_json::Value| {
+        Request::builder()
+            .method(method)
+            .uri(format!("/api/v1/grafana{}", path))
+            .header("authorization", "Bearer secret")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    // The plugin's connection test uses a trailing slash
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/", serde_json::Value::Null))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(request(
+            "POST",
+            "/search",
+            serde_json::json!({ "target": "" }),
+        ))
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let metrics: Vec<String> = serde_json::from_slice(&body).unwrap();
+    assert!(metrics.contains(&"failures_per_hour".to_string()));
+
+    let query = |target: &str| {
+        serde_json::json!({
+            "range": { "from": "2026-03-02T09:00:00.000Z", "to": "2026-03-02T12:00:00.000Z" },
+            "targets": [{ "refId": "A", "target": target }],
+        })
+    };
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/query", query("cpu")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/query", query("failures_per_hour")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    // Service health does not need the store
+    let response = app
+        .oneshot(request("POST", "/query", query("service_restarts")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn share_tokens_open_only_the_shared_feed() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = Arc::new(state(dir.path()));
+    let grant = state.shares.start("s1");
+    let app = routes::router(Arc::clone(&state));
+    let get = |path: &str, token: &str| {
+        Request::get(path)
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(get("/api/v1/observe/s1", &grant.token))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    // Not another session's feed, nor any other route; API tokens open no feed
+    for (path, token) in [
+        ("/api/v1/observe/s2", grant.token.as_str()),
+        ("/api/v1/memory/sessions", grant.token.as_str()),
+        ("/api/v1/observe/s1", "secret"),
+    ] {
+        let response = app.clone().oneshot(get(path, token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", path);
+    }
+
+    state.shares.stop("s1");
+    let response = app
+        .oneshot(get("/api/v1/observe/s1", &grant.token))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
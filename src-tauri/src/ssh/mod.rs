@@ -0,0 +1,370 @@
+//! `runebook ssh`: capture work done on remote machines.
+//!
+//! `runebook ssh [ssh options] destination [command]` runs the system `ssh` on
+//! a local pseudo-terminal and relays it to the user's terminal unchanged. The
+//! proxy reads the stream on the way through: a remote shell with shell
+//! integration marks its own commands (OSC 133/633), and for any other shell
+//! [`PromptDetector`] finds the boundaries from its prompts. Each command is
+//! stored in local memory, in a session whose `hostname` is the remote host,
+//! and tagged `host:<name>`. Nothing is installed or run on the remote side.
+
+pub mod prompt;
+
+#[cfg(test)]
+mod tests;
+
+pub use prompt::PromptDetector;
+
+use crate::memory::shell_integration::{CommandSegmenter, OscParser, Segment};
+use crate::memory::{MemoryEvent, MemoryStore, Session};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::sync::Arc;
+
+/// ssh options that take a value
+const OPTIONS_WITH_VALUE: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+
+/// Directory recorded for commands when the remote shell does not report one
+const REMOTE_HOME: &str = "~";
+
+/// The destination among ssh's arguments: the first one that is neither an
+/// option nor an option's value
+pub fn destination(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return args.next().map(String::as_str);
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|f| !f.is_empty()) else {
+            return Some(arg);
+        };
+        // `-p22` carries its value, `-p 22` takes the next argument
+        if let Some(at) = flags.find(|c| OPTIONS_WITH_VALUE.contains(c)) {
+            if at + 1 == flags.len() {
+                args.next();
+            }
+        }
+    }
+    None
+}
+
+/// The machine an ssh session logs in to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteHost {
+    pub hostname: String,
+    pub user: Option<String>,
+}
+
+impl RemoteHost {
+    /// `[user@]host[:port]` or `ssh://[user@]host[:port]`, as written
+    pub fn from_destination(destination: &str) -> Self {
+        let rest = destination.strip_prefix("ssh://").unwrap_or(destination);
+        let (user, host) = match rest.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, rest),
+        };
+        let host = match host.strip_prefix('[') {
+            Some(bracketed) => bracketed.split(']').next().unwrap_or(bracketed),
+            None if destination.starts_with("ssh://") => host.split(':').next().unwrap_or(host),
+            None => host,
+        };
+        Self {
+            hostname: host.to_string(),
+            user,
+        }
+    }
+
+    /// The `hostname` and `user` lines of `ssh -G` output
+    pub fn from_config(config: &str) -> Option<Self> {
+        let value = |key: &str| {
+            config.lines().find_map(|line| {
+                line.strip_prefix(key)
+                    .and_then(|rest| rest.strip_prefix(' '))
+                    .map(|value| value.trim().to_string())
+            })
+        };
+        Some(Self {
+            hostname: value("hostname").filter(|h| !h.is_empty())?,
+            user: value("user"),
+        })
+    }
+
+    /// The host `ssh` with `args` connects to, after aliases and `HostName`
+    /// in the ssh config (`ssh -G`); else the destination as written
+    pub fn resolve(args: &[String]) -> Option<Self> {
+        let destination = destination(args)?;
+        std::process::Command::new("ssh")
+            .arg("-G")
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| Self::from_config(&String::from_utf8_lossy(&output.stdout)))
+            .or_else(|| Some(Self::from_destination(destination)))
+    }
+}
+
+/// Commands of one ssh session, on their way into memory
+pub struct Capture {
+    store: Arc<MemoryStore>,
+    session_id: String,
+    hostname: String,
+    parser: OscParser,
+    prompts: PromptDetector,
+    segmenter: CommandSegmenter,
+}
+
+impl Capture {
+    /// Start the session for a connection to `remote`
+    pub async fn start(
+        store: Arc<MemoryStore>,
+        remote: &RemoteHost,
+        destination: &str,
+    ) -> Result<Self> {
+        let mut session = Session::new("unknown".to_string(), REMOTE_HOME.to_string());
+        session.hostname = Some(remote.hostname.clone());
+        session.user = remote.user.clone();
+        session.metadata = serde_json::json!({
+            "client": "ssh",
+            "destination": destination,
+        });
+        let session_id = session.id.clone();
+        store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_start".to_string(),
+                timestamp: session.started_at,
+                session_id: session_id.clone(),
+                data: serde_json::to_value(&session)?,
+                provenance: None,
+            })
+            .await?;
+        Ok(Self {
+            store,
+            session_id,
+            hostname: remote.hostname.clone(),
+            parser: OscParser::new(),
+            prompts: PromptDetector::new(),
+            segmenter: CommandSegmenter::new(),
+        })
+    }
+
+    /// Output from the remote side. Returns the ids of commands it finished.
+    pub async fn output(&mut self, data: &[u8]) -> Result<Vec<String>> {
+        let segments = self.prompts.output(self.parser.feed(data));
+        self.store(segments).await
+    }
+
+    /// Keystrokes sent to the remote side
+    pub async fn input(&mut self, keys: &[u8]) -> Result<Vec<String>> {
+        let segments = self.prompts.input(keys);
+        self.store(segments).await
+    }
+
+    async fn store(&mut self, segments: Vec<Segment>) -> Result<Vec<String>> {
+        let mut command_ids = Vec::new();
+        for mut captured in self.segmenter.push(segments) {
+            captured.host = Some(self.hostname.clone());
+            command_ids.push(
+                self.store
+                    .store_captured_command(&self.session_id, captured, REMOTE_HOME)
+                    .await?,
+            );
+        }
+        Ok(command_ids)
+    }
+
+    /// End the session once ssh exits
+    pub async fn finish(self, exit_code: Option<i32>) -> Result<()> {
+        self.store
+            .append_event(MemoryEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                event_type: "session_end".to_string(),
+                timestamp: Utc::now(),
+                session_id: self.session_id,
+                data: serde_json::json!({ "client": "ssh", "exit_code": exit_code }),
+                provenance: None,
+            })
+            .await
+    }
+}
+
+/// What the relay threads report
+#[cfg(unix)]
+enum Event {
+    Input(Vec<u8>),
+    Output(Vec<u8>),
+    Closed,
+}
+
+/// Run `ssh` with `args` through the proxy, capturing into `store` unless it
+/// is `None`. Returns ssh's exit code.
+#[cfg(unix)]
+pub async fn run(args: &[String], store: Option<MemoryStore>) -> Result<i32> {
+    use portable_pty::{native_pty_system, CommandBuilder};
+    use std::io::{Read, Write};
+
+    let remote = RemoteHost::resolve(args);
+    let mut capture = match (store, &remote, destination(args)) {
+        (Some(store), Some(remote), Some(destination)) => {
+            match Capture::start(Arc::new(store), remote, destination).await {
+                Ok(capture) => Some(capture),
+                Err(e) => {
+                    eprintln!("runebook: not capturing this session: {:#}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let mut size = terminal::size();
+    let pair = native_pty_system()
+        .openpty(size)
+        .context("Failed to open a PTY")?;
+    let mut cmd = CommandBuilder::new("ssh");
+    cmd.args(args);
+    if let Ok(cwd) = std::env::current_dir() {
+        cmd.cwd(cwd);
+    }
+    let mut child = pair.slave.spawn_command(cmd).context("Failed to run ssh")?;
+    drop(pair.slave);
+    let mut reader = pair.master.try_clone_reader()?;
+    let mut writer = pair.master.take_writer()?;
+    let raw_mode = terminal::RawMode::enable();
+
+    let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+    {
+        let events = events.clone();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut buf = [0u8; 4096];
+            while let Ok(n @ 1..) = stdin.read(&mut buf) {
+                if writer
+                    .write_all(&buf[..n])
+                    .and_then(|_| writer.flush())
+                    .is_err()
+                {
+                    break;
+                }
+                let _ = events.send(Event::Input(buf[..n].to_vec()));
+            }
+        });
+    }
+    std::thread::spawn(move || {
+        let mut stdout = std::io::stdout();
+        let mut buf = [0u8; 8192];
+        // Linux reports EIO rather than EOF once ssh is gone
+        while let Ok(n @ 1..) = reader.read(&mut buf) {
+            let _ = stdout.write_all(&buf[..n]).and_then(|_| stdout.flush());
+            let _ = events.send(Event::Output(buf[..n].to_vec()));
+        }
+        let _ = events.send(Event::Closed);
+    });
+
+    // The terminal size is polled; SIGWINCH belongs to the user's terminal
+    let mut resize = tokio::time::interval(std::time::Duration::from_millis(250));
+    loop {
+        let event = tokio::select! {
+            event = received.recv() => event.unwrap_or(Event::Closed),
+            _ = resize.tick() => {
+                let current = terminal::size();
+                if current != size {
+                    size = current;
+                    let _ = pair.master.resize(size);
+                }
+                continue;
+            }
+        };
+        let result = match (&mut capture, event) {
+            (_, Event::Closed) => break,
+            (Some(capture), Event::Input(keys)) => capture.input(&keys).await,
+            (Some(capture), Event::Output(data)) => capture.output(&data).await,
+            (None, _) => continue,
+        };
+        // Printing would garble the session, so capture just stops
+        if let Err(e) = result {
+            log::warn!("[ssh] Capture stopped: {:#}", e);
+            capture = None;
+        }
+    }
+
+    let exit_code = child
+        .wait()
+        .map(|status| status.exit_code() as i32)
+        .unwrap_or(255);
+    drop(raw_mode);
+    if let Some(capture) = capture {
+        capture.finish(Some(exit_code)).await?;
+    }
+    Ok(exit_code)
+}
+
+#[cfg(not(unix))]
+pub async fn run(_args: &[String], _store: Option<MemoryStore>) -> Result<i32> {
+    anyhow::bail!("runebook ssh is only available on Unix platforms")
+}
+
+/// The user's terminal
+#[cfg(unix)]
+mod terminal {
+    use portable_pty::PtySize;
+
+    /// Size of the terminal on stdout; 80x24 when it is not one
+    pub fn size() -> PtySize {
+        // SAFETY: TIOCGWINSZ fills in the winsize it is given
+        let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut winsize) } == 0;
+        PtySize {
+            rows: if ok && winsize.ws_row > 0 {
+                winsize.ws_row
+            } else {
+                24
+            },
+            cols: if ok && winsize.ws_col > 0 {
+                winsize.ws_col
+            } else {
+                80
+            },
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+
+    /// Raw mode on stdin, so keystrokes reach ssh unprocessed; the previous
+    /// mode is restored on drop
+    pub struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        /// `None` when stdin is not a terminal
+        pub fn enable() -> Option<Self> {
+            // SAFETY: tcgetattr fills in the termios it is given, and
+            // tcsetattr only reads it
+            unsafe {
+                let mut original: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return None;
+                }
+                let mut raw = original;
+                libc::cfmakeraw(&mut raw);
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return None;
+                }
+                Some(Self { original })
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the termios tcgetattr returned
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+            }
+        }
+    }
+}
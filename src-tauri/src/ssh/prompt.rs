@@ -0,0 +1,140 @@
+//! Command boundaries from prompts.
+//!
+//! A remote shell without shell integration emits no OSC 133 marks, so the
+//! proxy makes them up. Output whose last line looks like a prompt
+//! (`user@host:~$ `, `[root@db ~]# `, `% `, `❯ `) starts a prompt, and Enter
+//! typed at a prompt executes whatever follows the prompt on that line. The
+//! marks go to the same [`CommandSegmenter`](crate::memory::shell_integration::CommandSegmenter)
+//! the capture socket uses. Exit codes are unknown this way, so a remote shell
+//! that marks its own commands is trusted instead.
+
+use crate::memory::shell_integration::{strip_escapes, Segment, ShellMark};
+
+/// Characters a prompt ends with, before its trailing space
+const TERMINATORS: &[char] = &['$', '#', '%', '>', '❯', '»'];
+
+/// Longest line still taken for a prompt, in characters
+const MAX_PROMPT_CHARS: usize = 200;
+
+/// Bytes of the current line kept; a longer line is no prompt anyway
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Unknown,
+    AtPrompt,
+    Running,
+}
+
+/// Adds prompt and command marks to a remote shell's output
+#[derive(Debug, Default)]
+pub struct PromptDetector {
+    state: State,
+    /// Output since the last newline, escape sequences included
+    line: Vec<u8>,
+    /// What the first prompt a command was typed at ends with; later prompts
+    /// must end the same way
+    terminator: Option<char>,
+    /// The remote shell marks commands itself
+    native: bool,
+}
+
+impl PromptDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass on output from [`OscParser`](crate::memory::shell_integration::OscParser),
+    /// with a prompt mark before a prompt
+    pub fn output(&mut self, segments: Vec<Segment>) -> Vec<Segment> {
+        // OSC 7 alone is common without shell integration
+        self.native |= segments
+            .iter()
+            .any(|s| matches!(s, Segment::Mark(mark) if !matches!(mark, ShellMark::Cwd { .. })));
+        if self.native {
+            return segments;
+        }
+        let mut out = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => self.text(text, &mut out),
+                mark => out.push(mark),
+            }
+        }
+        out
+    }
+
+    /// Look at keystrokes sent to the remote shell; Enter at a prompt
+    /// executes the command typed after it
+    pub fn input(&mut self, keys: &[u8]) -> Vec<Segment> {
+        if self.native || self.state != State::AtPrompt || !keys.contains(&b'\r') {
+            return Vec::new();
+        }
+        let line = strip_escapes(&String::from_utf8_lossy(&self.line));
+        let Some((terminator, command)) = self.split_prompt(&line) else {
+            return Vec::new();
+        };
+        let command = command.trim();
+        // A bare Enter just draws another prompt
+        if command.is_empty() {
+            return Vec::new();
+        }
+        self.terminator = Some(terminator);
+        self.state = State::Running;
+        vec![
+            Segment::Mark(ShellMark::CommandLine {
+                command_line: command.to_string(),
+            }),
+            Segment::Mark(ShellMark::CommandExecuted),
+        ]
+    }
+
+    fn text(&mut self, text: Vec<u8>, out: &mut Vec<Segment>) {
+        match text.iter().rposition(|b| *b == b'\n') {
+            Some(at) => self.line = text[at + 1..].to_vec(),
+            None => self.line.extend_from_slice(&text),
+        }
+        let excess = self.line.len().saturating_sub(MAX_LINE_BYTES);
+        self.line.drain(..excess);
+
+        if self.state == State::AtPrompt || !self.at_prompt() {
+            out.push(Segment::Text(text));
+            return;
+        }
+        // The prompt belongs after the mark, the output before it
+        let split = text.len().saturating_sub(self.line.len());
+        if split > 0 {
+            out.push(Segment::Text(text[..split].to_vec()));
+        }
+        out.push(Segment::Mark(ShellMark::PromptStart));
+        if split < text.len() {
+            out.push(Segment::Text(text[split..].to_vec()));
+        }
+        self.state = State::AtPrompt;
+    }
+
+    fn is_terminator(&self, c: char) -> bool {
+        match self.terminator {
+            Some(terminator) => c == terminator,
+            None => TERMINATORS.contains(&c),
+        }
+    }
+
+    /// Whether the current line is a prompt waiting for input
+    fn at_prompt(&self) -> bool {
+        let line = strip_escapes(&String::from_utf8_lossy(&self.line));
+        let prompt = line.trim_end();
+        prompt.len() < line.len()
+            && prompt.chars().count() <= MAX_PROMPT_CHARS
+            && prompt.chars().last().is_some_and(|c| self.is_terminator(c))
+    }
+
+    /// The prompt's terminator and what was typed after it
+    fn split_prompt<'l>(&self, line: &'l str) -> Option<(char, &'l str)> {
+        line.char_indices()
+            .take(MAX_PROMPT_CHARS)
+            .find(|&(at, c)| self.is_terminator(c) && line[at + c.len_utf8()..].starts_with(' '))
+            .map(|(at, c)| (c, &line[at + c.len_utf8() + 1..]))
+    }
+}
@@ -0,0 +1,123 @@
+use super::*;
+use crate::memory::shell_integration::ShellMark;
+use crate::memory::InMemoryBackend;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn finds_the_destination_and_remote_host() {
+    assert_eq!(destination(&args(&["db1"])), Some("db1"));
+    assert_eq!(
+        destination(&args(&["-p", "2222", "-A", "deploy@db1", "uptime"])),
+        Some("deploy@db1")
+    );
+    assert_eq!(destination(&args(&["-vp2222", "db1"])), Some("db1"));
+    assert_eq!(
+        destination(&args(&["-o", "ConnectTimeout=5", "-J", "bastion", "db1"])),
+        Some("db1")
+    );
+    assert_eq!(destination(&args(&["-V"])), None);
+
+    assert_eq!(
+        RemoteHost::from_destination("deploy@db1"),
+        RemoteHost {
+            hostname: "db1".into(),
+            user: Some("deploy".into()),
+        }
+    );
+    assert_eq!(
+        RemoteHost::from_destination("ssh://[::1]:2222").hostname,
+        "::1"
+    );
+    assert_eq!(
+        RemoteHost::from_config("user deploy\nhostname db1.internal\nport 22\n"),
+        Some(RemoteHost {
+            hostname: "db1.internal".into(),
+            user: Some("deploy".into()),
+        })
+    );
+}
+
+fn text(data: &str) -> Vec<Segment> {
+    vec![Segment::Text(data.as_bytes().to_vec())]
+}
+
+#[test]
+fn prompts_delimit_commands() {
+    let mut prompts = PromptDetector::new();
+    let mut segmenter = CommandSegmenter::new();
+
+    let segments = prompts.output(text("Last login: Mon\r\nuser@db1:~$ "));
+    assert_eq!(segments[1], Segment::Mark(ShellMark::PromptStart));
+    assert!(segmenter.push(segments).is_empty());
+    assert!(segmenter.push(prompts.output(text("ls -l"))).is_empty());
+    let mut segments = prompts.input(b"\r");
+    assert_eq!(
+        segments[0],
+        Segment::Mark(ShellMark::CommandLine {
+            command_line: "ls -l".into()
+        })
+    );
+    segments.extend(prompts.output(text("\r\ntotal 0\r\n")));
+    assert!(segmenter.push(segments).is_empty());
+    let commands = segmenter.push(prompts.output(text("a.txt\r\nuser@db1:~$ ")));
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].command_line, "ls -l");
+    assert_eq!(commands[0].exit_code, None);
+    assert_eq!(
+        String::from_utf8_lossy(&commands[0].output),
+        "\r\ntotal 0\r\na.txt\r\n"
+    );
+
+    // A bare Enter runs nothing
+    assert!(prompts.input(b"\r").is_empty());
+
+    // A shell that marks its own commands is left alone
+    let mut prompts = PromptDetector::new();
+    let marked = vec![
+        Segment::Mark(ShellMark::PromptStart),
+        Segment::Text(b"$ ".to_vec()),
+    ];
+    assert_eq!(prompts.output(marked.clone()), marked);
+    assert!(prompts.input(b"ls\r").is_empty());
+}
+
+#[tokio::test]
+async fn captured_commands_record_the_remote_host() {
+    let store = Arc::new(MemoryStore::new(InMemoryBackend::new()).await.unwrap());
+    let remote = RemoteHost::from_destination("deploy@db1");
+    let mut capture = Capture::start(Arc::clone(&store), &remote, "deploy@db1")
+        .await
+        .unwrap();
+    let session_id = capture.session_id.clone();
+
+    capture.output(b"deploy@db1:~$ ").await.unwrap();
+    capture.output(b"uptime").await.unwrap();
+    assert!(capture.input(b"\r").await.unwrap().is_empty());
+    capture.output(b"\r\n 10:00 up 3 days\r\n").await.unwrap();
+    let ids = capture.output(b"deploy@db1:~$ ").await.unwrap();
+    assert_eq!(ids.len(), 1);
+    capture.finish(Some(0)).await.unwrap();
+
+    let session = store
+        .list_sessions()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|s| s.id == session_id)
+        .unwrap();
+    assert_eq!(session.hostname.as_deref(), Some("db1"));
+    assert_eq!(session.user.as_deref(), Some("deploy"));
+    assert!(session.ended_at.is_some());
+
+    let context = store
+        .get_context(&session_id, chrono::Duration::hours(1))
+        .await
+        .unwrap();
+    let command = &context.commands[0];
+    assert_eq!(command.command, "uptime");
+    assert!(command.tags.contains(&"host:db1".to_string()));
+    assert_eq!(command.git_commit, None);
+}
@@ -0,0 +1,94 @@
+// S3 credentials: the AWS environment variables, else the OS keychain (macOS, Windows)
+// or s3-credentials.json (mode 0600) in the config directory
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl std::fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+fn from_env() -> Option<S3Credentials> {
+    Some(S3Credentials {
+        access_key_id: std::env::var("AWS_ACCESS_KEY_ID").ok()?,
+        secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+    })
+}
+
+/// Credentials for the bucket: the environment wins over the stored ones
+pub fn load() -> Result<S3Credentials> {
+    if let Some(credentials) = from_env() {
+        return Ok(credentials);
+    }
+    keystore::load()?.context(
+        "No S3 credentials: run `runebook storage login` or set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+    )
+}
+
+/// Store credentials for later runs
+pub fn save(credentials: &S3Credentials) -> Result<()> {
+    keystore::save(&serde_json::to_string(credentials)?)
+}
+
+fn decode(stored: &str) -> Result<S3Credentials> {
+    serde_json::from_str(stored).context("Stored S3 credentials are corrupt")
+}
+
+mod keystore {
+    use super::{decode, S3Credentials};
+    use anyhow::{Context, Result};
+
+    #[cfg(any(target_os = "macos", windows))]
+    fn entry() -> Result<keyring::Entry> {
+        Ok(keyring::Entry::new(
+            &crate::profile::keychain_service(),
+            "s3-credentials",
+        )?)
+    }
+
+    #[cfg(any(target_os = "macos", windows))]
+    pub fn load() -> Result<Option<S3Credentials>> {
+        match entry()?.get_password() {
+            Ok(stored) => Ok(Some(decode(&stored)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).context("Failed to read the S3 credentials from the keychain"),
+        }
+    }
+
+    #[cfg(any(target_os = "macos", windows))]
+    pub fn save(stored: &str) -> Result<()> {
+        entry()?
+            .set_password(stored)
+            .context("Failed to store the S3 credentials in the keychain")
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn path() -> std::path::PathBuf {
+        crate::config::config_dir().join("s3-credentials.json")
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    pub fn load() -> Result<Option<S3Credentials>> {
+        let path = path();
+        match std::fs::read_to_string(&path) {
+            Ok(stored) => Ok(Some(decode(&stored)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    pub fn save(stored: &str) -> Result<()> {
+        crate::server::write_private(&path(), stored.as_bytes())
+    }
+}
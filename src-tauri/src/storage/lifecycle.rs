@@ -0,0 +1,244 @@
+// Artifact lifecycle: move the content of large, old artifacts to object storage and expire
+// very old ones; offloaded content is fetched back, and verified, when the artifact is requested
+
+use super::ObjectStore;
+use crate::health::Component;
+use crate::memory::keys::RecordKind;
+use crate::memory::{Artifact, MemoryStore, StoreOpener};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use ts_rs::TS;
+
+/// Key prefix of offloaded artifact content
+pub const ARTIFACT_PREFIX: &str = "artifacts/";
+
+/// `[storage.artifacts]` configuration section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ArtifactLifecycle {
+    /// Offload artifact content to `[storage.s3]`
+    pub offload: bool,
+    /// Smaller artifacts stay local
+    pub min_bytes: u64,
+    /// Age at which content is offloaded
+    pub offload_after_days: u64,
+    /// Age at which artifacts are deleted, remote copy included; 0 keeps them
+    pub expire_after_days: u64,
+    pub interval_hours: u64,
+}
+
+impl Default for ArtifactLifecycle {
+    fn default() -> Self {
+        Self {
+            offload: false,
+            min_bytes: 1024 * 1024,
+            offload_after_days: 7,
+            expire_after_days: 0,
+            interval_hours: 6,
+        }
+    }
+}
+
+/// What the lifecycle does with one artifact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleAction {
+    Keep,
+    Offload,
+    Expire,
+}
+
+impl ArtifactLifecycle {
+    pub fn action(&self, artifact: &Artifact, now: DateTime<Utc>) -> LifecycleAction {
+        let age = now - artifact.created_at;
+        if self.expire_after_days > 0 && age >= Duration::days(self.expire_after_days as i64) {
+            return LifecycleAction::Expire;
+        }
+        if self.offload
+            && RemoteContent::of(artifact).is_none()
+            && artifact.content.len() as u64 >= self.min_bytes.max(1)
+            && age >= Duration::days(self.offload_after_days as i64)
+        {
+            return LifecycleAction::Offload;
+        }
+        LifecycleAction::Keep
+    }
+}
+
+/// Where an artifact's content went, kept in its metadata under `remote`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RemoteContent {
+    pub key: String,
+    pub sha256: String,
+    pub bytes: u64,
+    pub offloaded_at: DateTime<Utc>,
+}
+
+impl RemoteContent {
+    /// The remote copy of an offloaded artifact; `None` while its content is local
+    pub fn of(artifact: &Artifact) -> Option<Self> {
+        serde_json::from_value(artifact.metadata.get("remote")?.clone()).ok()
+    }
+
+    /// Download the content, failing unless it matches the digest taken at offload
+    pub async fn fetch(&self, store: &dyn ObjectStore) -> Result<Vec<u8>> {
+        let body = store
+            .get(&self.key)
+            .await
+            .with_context(|| format!("Failed to fetch {} from {}", self.key, store.describe()))?;
+        if body.len() as u64 != self.bytes || hex::encode(Sha256::digest(&body)) != self.sha256 {
+            bail!("{} in {} is corrupt", self.key, store.describe());
+        }
+        Ok(body)
+    }
+}
+
+/// Outcome of one lifecycle pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifecycleReport {
+    pub offloaded: usize,
+    pub offloaded_bytes: u64,
+    pub expired: usize,
+    /// Artifacts left as they were after an error
+    pub failed: usize,
+}
+
+impl MemoryStore {
+    /// Offload and expire artifacts as `policy` says
+    pub async fn apply_artifact_lifecycle(
+        &self,
+        remote: &dyn ObjectStore,
+        policy: &ArtifactLifecycle,
+        now: DateTime<Utc>,
+    ) -> Result<LifecycleReport> {
+        let mut report = LifecycleReport::default();
+        for artifact in self.list_artifacts(None, None).await? {
+            let result = match policy.action(&artifact, now) {
+                LifecycleAction::Keep => continue,
+                LifecycleAction::Offload => {
+                    self.offload_artifact(remote, artifact, now)
+                        .await
+                        .map(|bytes| {
+                            report.offloaded += 1;
+                            report.offloaded_bytes += bytes;
+                        })
+                }
+                LifecycleAction::Expire => self
+                    .expire_artifact(remote, &artifact)
+                    .await
+                    .map(|()| report.expired += 1),
+            };
+            if let Err(e) = result {
+                log::warn!("[storage] Artifact lifecycle failed: {:#}", e);
+                report.failed += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    async fn offload_artifact(
+        &self,
+        remote: &dyn ObjectStore,
+        mut artifact: Artifact,
+        now: DateTime<Utc>,
+    ) -> Result<u64> {
+        if self.is_encrypted() {
+            bail!(
+                "Artifact {} not offloaded: the store is encrypted",
+                artifact.id
+            );
+        }
+        let content = std::mem::take(&mut artifact.content);
+        let pointer = RemoteContent {
+            key: format!("{}{}", ARTIFACT_PREFIX, artifact.id),
+            sha256: hex::encode(Sha256::digest(&content)),
+            bytes: content.len() as u64,
+            offloaded_at: now,
+        };
+        remote.put(&pointer.key, content).await?;
+        // Only drop the local copy once the remote one reads back intact
+        pointer.fetch(remote).await?;
+
+        if !artifact.metadata.is_object() {
+            artifact.metadata = serde_json::json!({});
+        }
+        artifact.metadata["remote"] = serde_json::to_value(&pointer)?;
+        self.write_value(
+            &RecordKind::Artifact.key(&artifact.id),
+            serde_json::to_value(&artifact)?,
+        )
+        .await?;
+        Ok(pointer.bytes)
+    }
+
+    async fn expire_artifact(&self, remote: &dyn ObjectStore, artifact: &Artifact) -> Result<()> {
+        if let Some(pointer) = RemoteContent::of(artifact) {
+            remote.delete(&pointer.key).await?;
+        }
+        self.client
+            .delete(&RecordKind::Artifact.key(&artifact.id))
+            .await
+    }
+
+    /// One artifact with its content. Offloaded content is fetched from the
+    /// store's remote bucket; without one the artifact comes back with empty content.
+    pub async fn get_artifact(&self, id: &str) -> Result<Option<Artifact>> {
+        let Some(value) = self.read_value(&RecordKind::Artifact.key(id)).await? else {
+            return Ok(None);
+        };
+        let mut artifact: Artifact = serde_json::from_value(value)?;
+        if let (Some(pointer), Some(remote)) = (RemoteContent::of(&artifact), self.remote_store()) {
+            artifact.content = pointer.fetch(remote).await?;
+        }
+        Ok(Some(artifact))
+    }
+}
+
+/// Apply the artifact lifecycle every `interval_hours`. Returns at once when
+/// offloading and expiry are off or there is no bucket.
+pub async fn run_lifecycle_loop(
+    remote: Option<Arc<dyn ObjectStore>>,
+    policy: ArtifactLifecycle,
+    open_store: StoreOpener,
+    health: Component,
+) {
+    let Some(remote) = remote else {
+        return health.disable("No [storage] bucket is configured");
+    };
+    if !policy.offload && policy.expire_after_days == 0 {
+        return health.disable("Artifact offloading and expiry are off");
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        policy.interval_hours.max(1) * 3600,
+    ));
+    loop {
+        interval.tick().await;
+        let result = async {
+            let store = open_store().await?;
+            store
+                .apply_artifact_lifecycle(remote.as_ref(), &policy, Utc::now())
+                .await
+        }
+        .await;
+        match result {
+            Ok(report) => {
+                health.ok();
+                log::info!(
+                    "[storage] Offloaded {} artifacts ({} bytes), expired {}, {} failed",
+                    report.offloaded,
+                    report.offloaded_bytes,
+                    report.expired,
+                    report.failed
+                );
+            }
+            Err(e) => {
+                log::warn!("[storage] Artifact lifecycle failed: {:#}", e);
+                health.error(format!("{:#}", e));
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Remote object storage for large blobs.
+//!
+//! An [`ObjectStore`] is a flat key/value blob store. In builds with the `s3`
+//! feature it is an S3-compatible bucket (AWS, MinIO, R2, ...), configured in
+//! `[storage.s3]`. The backup scheduler can store backups there, and the
+//! artifact lifecycle moves the content of large or old artifacts there:
+//! the artifact record keeps its metadata and a pointer to the object, and
+//! the content is downloaded again, and checked against its SHA-256, when the
+//! artifact is requested ([`crate::memory::MemoryStore::get_artifact`]).
+//!
+//! Credentials are never kept in `config.toml`. They come from
+//! `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` when set, otherwise from the
+//! OS keychain on macOS and Windows and `s3-credentials.json` (mode 0600) in
+//! the config directory elsewhere; `runebook storage login` stores them.
+
+pub mod credentials;
+pub mod lifecycle;
+#[cfg(feature = "s3")]
+pub mod s3;
+
+#[cfg(test)]
+mod tests;
+
+pub use credentials::S3Credentials;
+pub use lifecycle::{run_lifecycle_loop, ArtifactLifecycle, LifecycleReport, RemoteContent};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use ts_rs::TS;
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_prefix() -> String {
+    "runebook/".to_string()
+}
+
+/// An S3-compatible bucket (AWS, MinIO, R2, ...), addressed path-style
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct S3Config {
+    /// e.g. "https://s3.eu-central-1.amazonaws.com" or "http://localhost:9000"
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// Prepended to every object key
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+/// `[storage]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct StorageConfig {
+    /// The bucket; no remote storage when unset
+    pub s3: Option<S3Config>,
+    /// When artifact content moves to the bucket
+    pub artifacts: ArtifactLifecycle,
+}
+
+/// An object in a store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    /// Key relative to the store's prefix
+    pub key: String,
+    pub bytes: u64,
+}
+
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Where objects go, for status output
+    fn describe(&self) -> String;
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+    /// Objects whose key starts with `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>>;
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// The bucket `config` describes, with credentials from the environment or keychain
+pub fn open_object_store(config: &S3Config) -> Result<Arc<dyn ObjectStore>> {
+    #[cfg(feature = "s3")]
+    {
+        Ok(Arc::new(s3::S3Client::new(config, credentials::load()?)?))
+    }
+    #[cfg(not(feature = "s3"))]
+    {
+        let _ = config;
+        anyhow::bail!("Object storage needs a build with the `s3` feature")
+    }
+}
+
+/// The `[storage.s3]` bucket, or `None` when none is configured
+pub fn configured_store(config: &StorageConfig) -> Result<Option<Arc<dyn ObjectStore>>> {
+    config.s3.as_ref().map(open_object_store).transpose()
+}
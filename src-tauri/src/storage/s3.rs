@@ -0,0 +1,257 @@
+// Minimal S3 client: path-style PUT, GET, DELETE and ListObjectsV2 signed with AWS Signature V4
+// Enough for MinIO, R2 and AWS itself without pulling in an SDK
+
+use super::{ObjectInfo, ObjectStore, S3Config, S3Credentials};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// SHA-256 of an empty body, for requests without one
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut padded = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        padded[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        padded[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(padded.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(padded.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// SigV4 signing key for `date` (YYYYMMDD)
+pub(crate) fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    let key = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// URI-encode per SigV4: everything but unreserved characters, and `/` unless `path`
+fn uri_encode(text: &str, path: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            b'/' if path => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+pub struct S3Client {
+    http: reqwest::Client,
+    endpoint: url::Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    credentials: S3Credentials,
+}
+
+impl S3Client {
+    pub fn new(config: &S3Config, credentials: S3Credentials) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::new(),
+            endpoint: url::Url::parse(&config.endpoint)
+                .with_context(|| format!("Invalid S3 endpoint {}", config.endpoint))?,
+            bucket: config.bucket.clone(),
+            region: config.region.clone(),
+            prefix: config.prefix.clone(),
+            credentials,
+        })
+    }
+
+    /// Send a signed request for `key` (empty for the bucket itself)
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response> {
+        let now: DateTime<Utc> = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = if body.is_empty() {
+            EMPTY_SHA256.to_string()
+        } else {
+            hex::encode(Sha256::digest(&body))
+        };
+
+        let path = format!("/{}/{}", self.bucket, uri_encode(key, true));
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or(""), port),
+            None => self.endpoint.host_str().unwrap_or("").to_string(),
+        };
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = hex::encode(hmac_sha256(
+            &signing_key(
+                &self.credentials.secret_access_key,
+                &date,
+                &self.region,
+                "s3",
+            ),
+            string_to_sign.as_bytes(),
+        ));
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+        let response = self
+            .http
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            // S3 rejects a body that does not match this digest
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.credentials.access_key_id, scope, signed_headers, signature
+                ),
+            )
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            bail!("S3 request failed ({}): {}", status, text.trim());
+        }
+        Ok(response)
+    }
+
+    pub async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.send(reqwest::Method::PUT, key, &[], body).await?;
+        Ok(())
+    }
+
+    pub async fn get_object(&self, key: &str) -> Result<Vec<u8>> {
+        let response = self
+            .send(reqwest::Method::GET, key, &[], Vec::new())
+            .await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<()> {
+        self.send(reqwest::Method::DELETE, key, &[], Vec::new())
+            .await?;
+        Ok(())
+    }
+
+    /// Keys and sizes of the objects under `prefix`
+    pub async fn list_objects(&self, prefix: &str) -> Result<Vec<(String, u64)>> {
+        static CONTENTS: OnceLock<Regex> = OnceLock::new();
+        static TOKEN: OnceLock<Regex> = OnceLock::new();
+        let contents = CONTENTS.get_or_init(|| {
+            Regex::new(r"(?s)<Contents>.*?<Key>(?P<key>.*?)</Key>.*?<Size>(?P<size>\d+)</Size>.*?</Contents>")
+                .unwrap()
+        });
+        let token = TOKEN.get_or_init(|| {
+            Regex::new(r"<NextContinuationToken>(?P<token>.*?)</NextContinuationToken>").unwrap()
+        });
+
+        let mut objects = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(next) = &continuation {
+                query.push(("continuation-token", next));
+            }
+            let xml = self
+                .send(reqwest::Method::GET, "", &query, Vec::new())
+                .await?
+                .text()
+                .await?;
+            for caps in contents.captures_iter(&xml) {
+                objects.push((
+                    xml_unescape(&caps["key"]),
+                    caps["size"].parse().unwrap_or(0),
+                ));
+            }
+            match token.captures(&xml) {
+                Some(caps) => continuation = Some(xml_unescape(&caps["token"])),
+                None => return Ok(objects),
+            }
+        }
+    }
+}
+
+/// Objects under the configured prefix
+#[async_trait]
+impl ObjectStore for S3Client {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        // The signed payload digest makes S3 verify what it received
+        self.put_object(&format!("{}{}", self.prefix, key), body)
+            .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        self.get_object(&format!("{}{}", self.prefix, key)).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        Ok(self
+            .list_objects(&format!("{}{}", self.prefix, prefix))
+            .await?
+            .into_iter()
+            .filter_map(|(key, bytes)| {
+                Some(ObjectInfo {
+                    key: key.strip_prefix(&self.prefix)?.to_string(),
+                    bytes,
+                })
+            })
+            .collect())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.delete_object(&format!("{}{}", self.prefix, key)).await
+    }
+}
@@ -0,0 +1,160 @@
+// Tests for the artifact lifecycle and object store helpers
+
+use crate::backup::destination::{BackupDestination, ObjectStoreDestination};
+use crate::memory::Artifact;
+use crate::storage::lifecycle::LifecycleAction;
+use crate::storage::*;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct MemoryObjects(Mutex<BTreeMap<String, Vec<u8>>>);
+
+#[async_trait]
+impl ObjectStore for MemoryObjects {
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.0.lock().unwrap().insert(key.to_string(), body);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .context("no object")
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, body)| ObjectInfo {
+                key: key.clone(),
+                bytes: body.len() as u64,
+            })
+            .collect())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.0.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+fn artifact(bytes: usize, age_days: i64) -> Artifact {
+    Artifact {
+        id: "a1".to_string(),
+        session_id: None,
+        command_id: None,
+        artifact_type: "output_blob".to_string(),
+        mime_type: "application/octet-stream".to_string(),
+        content: vec![7; bytes],
+        size_bytes: bytes as u64,
+        source: "capture".to_string(),
+        created_at: Utc::now() - Duration::days(age_days),
+        metadata: serde_json::json!({}),
+    }
+}
+
+#[test]
+fn lifecycle_offloads_large_old_artifacts_once() {
+    let policy = ArtifactLifecycle {
+        offload: true,
+        min_bytes: 100,
+        offload_after_days: 7,
+        expire_after_days: 90,
+        ..Default::default()
+    };
+    let now = Utc::now();
+    assert_eq!(policy.action(&artifact(10, 30), now), LifecycleAction::Keep);
+    assert_eq!(policy.action(&artifact(200, 1), now), LifecycleAction::Keep);
+    assert_eq!(
+        policy.action(&artifact(200, 30), now),
+        LifecycleAction::Offload
+    );
+    assert_eq!(
+        policy.action(&artifact(10, 100), now),
+        LifecycleAction::Expire
+    );
+
+    let mut offloaded = artifact(0, 30);
+    offloaded.metadata["remote"] = serde_json::json!({
+        "key": "artifacts/a1",
+        "sha256": "00",
+        "bytes": 200,
+        "offloaded_at": now,
+    });
+    assert_eq!(policy.action(&offloaded, now), LifecycleAction::Keep);
+
+    let off = ArtifactLifecycle::default();
+    assert_eq!(
+        off.action(&artifact(1 << 24, 365), now),
+        LifecycleAction::Keep
+    );
+}
+
+#[tokio::test]
+async fn remote_content_is_verified_on_fetch() {
+    let store = MemoryObjects::default();
+    let body = b"screenshot bytes".to_vec();
+    let pointer = RemoteContent {
+        key: "artifacts/a1".to_string(),
+        sha256: hex::encode(Sha256::digest(&body)),
+        bytes: body.len() as u64,
+        offloaded_at: Utc::now(),
+    };
+    assert!(pointer.fetch(&store).await.is_err());
+
+    store.put(&pointer.key, body.clone()).await.unwrap();
+    assert_eq!(pointer.fetch(&store).await.unwrap(), body);
+
+    store
+        .put(&pointer.key, b"screenshot bytez".to_vec())
+        .await
+        .unwrap();
+    let error = pointer.fetch(&store).await.unwrap_err().to_string();
+    assert!(error.contains("corrupt"), "{}", error);
+}
+
+#[tokio::test]
+async fn backups_ignore_offloaded_artifacts() {
+    let store = Arc::new(MemoryObjects::default());
+    store.put("artifacts/a1", b"blob".to_vec()).await.unwrap();
+    store
+        .put("runebook-backup-20260318T200000Z.tar.zst", b"tar".to_vec())
+        .await
+        .unwrap();
+    let destination = ObjectStoreDestination::new(store);
+    let backups = destination.list().await.unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0].name, "runebook-backup-20260318T200000Z.tar.zst");
+}
+
+// AWS's published SigV4 example (secret, 20120215, us-east-1, iam)
+#[cfg(feature = "s3")]
+#[test]
+fn s3_signing_key_matches_aws_example() {
+    let key = s3::signing_key(
+        "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        "20120215",
+        "us-east-1",
+        "iam",
+    );
+    assert_eq!(
+        hex::encode(key),
+        "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+    );
+}
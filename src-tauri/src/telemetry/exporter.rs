@@ -0,0 +1,120 @@
+//! OTLP/HTTP JSON exporter.
+//!
+//! Uses the JSON encoding of `ExportTraceServiceRequest` so no protobuf
+//! toolchain is needed; any OpenTelemetry Collector accepts it on `/v1/traces`.
+
+use super::{Span, SpanKind, SpanStatus, TelemetryConfig};
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+pub struct OtlpExporter {
+    client: Client,
+    traces_url: String,
+    service_name: String,
+    headers: Vec<(String, String)>,
+}
+
+impl OtlpExporter {
+    pub fn new(config: &TelemetryConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            client,
+            traces_url: format!("{}/v1/traces", config.endpoint.trim_end_matches('/')),
+            service_name: config.service_name.clone(),
+            headers: config
+                .headers
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        })
+    }
+
+    /// Send a batch of finished spans to the collector
+    pub async fn export(&self, spans: &[Span]) -> Result<()> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        let mut request = self
+            .client
+            .post(&self.traces_url)
+            .json(&encode_request(&self.service_name, spans));
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await.context("Failed to send OTLP export")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("OTLP export failed with status {}: {}", status, text);
+        }
+        Ok(())
+    }
+}
+
+/// Build the OTLP JSON `ExportTraceServiceRequest` body
+pub fn encode_request(service_name: &str, spans: &[Span]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": service_name } },
+                    { "key": "service.version", "value": { "stringValue": env!("CARGO_PKG_VERSION") } },
+                ]
+            },
+            "scopeSpans": [{
+                "scope": { "name": "runebook" },
+                "spans": spans.iter().map(encode_span).collect::<Vec<_>>(),
+            }]
+        }]
+    })
+}
+
+fn encode_span(span: &Span) -> Value {
+    let nanos =
+        |t: chrono::DateTime<chrono::Utc>| t.timestamp_nanos_opt().unwrap_or_default().to_string();
+    let (code, message) = match &span.status {
+        SpanStatus::Unset => (0, String::new()),
+        SpanStatus::Ok => (1, String::new()),
+        SpanStatus::Error(message) => (2, message.clone()),
+    };
+
+    let mut encoded = json!({
+        "traceId": span.trace_id,
+        "spanId": span.span_id,
+        "name": span.name,
+        "kind": match span.kind {
+            SpanKind::Internal => 1,
+            SpanKind::Client => 3,
+        },
+        "startTimeUnixNano": nanos(span.start_time),
+        "endTimeUnixNano": nanos(span.end_time.unwrap_or(span.start_time)),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": encode_value(value) }))
+            .collect::<Vec<_>>(),
+        "status": { "code": code, "message": message },
+    });
+    if let Some(parent) = &span.parent_span_id {
+        encoded["parentSpanId"] = json!(parent);
+    }
+    encoded
+}
+
+fn encode_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
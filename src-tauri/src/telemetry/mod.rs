@@ -0,0 +1,51 @@
+//! OpenTelemetry trace export.
+//!
+//! Command executions, orchestration runs, and analyzer work are recorded as
+//! spans and shipped in batches to an OTLP/HTTP collector configured by the
+//! user (`[telemetry]` in `config.toml`). Export is off by default; when
+//! disabled, recording a span is a no-op.
+
+pub mod exporter;
+pub mod span;
+pub mod tracer;
+
+#[cfg(test)]
+mod tests;
+
+pub use exporter::OtlpExporter;
+pub use span::*;
+pub use tracer::Tracer;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use ts_rs::TS;
+
+/// `[telemetry]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// OTLP/HTTP base URL; spans are POSTed to `{endpoint}/v1/traces`
+    pub endpoint: String,
+    pub service_name: String,
+    /// Extra headers sent with every export (e.g. collector auth)
+    pub headers: HashMap<String, String>,
+    /// Flush interval for buffered spans
+    pub flush_interval_secs: u64,
+    /// Spans buffered beyond this are dropped (oldest first)
+    pub max_buffered_spans: usize,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4318".to_string(),
+            service_name: "runebook".to_string(),
+            headers: HashMap::new(),
+            flush_interval_secs: 5,
+            max_buffered_spans: 2048,
+        }
+    }
+}
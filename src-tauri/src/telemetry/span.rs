@@ -0,0 +1,93 @@
+//! Span model, independent of the wire format.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// Span kind, mirroring the OTLP enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+    Internal,
+    Client,
+}
+
+/// Final status of a span
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(tag = "code", content = "message", rename_all = "snake_case")]
+pub enum SpanStatus {
+    Unset,
+    Ok,
+    Error(String),
+}
+
+/// A finished or in-flight trace span
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Span {
+    pub trace_id: String, // 32 hex chars
+    pub span_id: String,  // 16 hex chars
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+    pub status: SpanStatus,
+}
+
+fn new_span_id() -> String {
+    Uuid::new_v4().simple().to_string()[..16].to_string()
+}
+
+impl Span {
+    /// Start a root span in a new trace
+    pub fn root(name: impl Into<String>) -> Self {
+        Self {
+            trace_id: Uuid::new_v4().simple().to_string(),
+            span_id: new_span_id(),
+            parent_span_id: None,
+            name: name.into(),
+            kind: SpanKind::Internal,
+            start_time: Utc::now(),
+            end_time: None,
+            attributes: serde_json::Map::new(),
+            status: SpanStatus::Unset,
+        }
+    }
+
+    /// Start a child span in the same trace
+    pub fn child(&self, name: impl Into<String>) -> Self {
+        Self {
+            trace_id: self.trace_id.clone(),
+            parent_span_id: Some(self.span_id.clone()),
+            ..Self::root(name)
+        }
+    }
+
+    pub fn with_kind(mut self, kind: SpanKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn set_attribute(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+        self.attributes.insert(key.to_string(), value.into());
+    }
+
+    /// Mark the span successful and stamp its end time
+    pub fn finish(&mut self) {
+        if self.status == SpanStatus::Unset {
+            self.status = SpanStatus::Ok;
+        }
+        self.end_time.get_or_insert_with(Utc::now);
+    }
+
+    /// Mark the span failed and stamp its end time
+    pub fn fail(&mut self, message: impl Into<String>) {
+        self.status = SpanStatus::Error(message.into());
+        self.end_time.get_or_insert_with(Utc::now);
+    }
+}
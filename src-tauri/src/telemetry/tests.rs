@@ -0,0 +1,104 @@
+use super::*;
+use crate::audit::AuditLog;
+use crate::execution::{CanvasDocument, CommandGate, ExecutionService, SafetyConfig};
+use axum::extract::State;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+/// A collector that keeps every export request it is sent
+async fn collector() -> (String, Received) {
+    let received: Received = Arc::default();
+    let app = axum::Router::new()
+        .route(
+            "/v1/traces",
+            axum::routing::post(
+                |State(received): State<Received>, axum::Json(body): axum::Json<Value>| async move {
+                    received.lock().unwrap().push(body);
+                },
+            ),
+        )
+        .with_state(Arc::clone(&received));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (endpoint, received)
+}
+
+/// Spans of every export `received`, by name
+fn exported(received: &Received) -> HashMap<String, Vec<Value>> {
+    let mut spans: HashMap<String, Vec<Value>> = HashMap::new();
+    for request in received.lock().unwrap().iter() {
+        let batch = &request["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        for span in batch.as_array().unwrap() {
+            let name = span["name"].as_str().unwrap().to_string();
+            spans.entry(name).or_default().push(span.clone());
+        }
+    }
+    spans
+}
+
+#[tokio::test]
+async fn canvas_runs_reach_the_exporter() {
+    let (endpoint, received) = collector().await;
+    let tracer = Arc::new(Tracer::new(&TelemetryConfig {
+        enabled: true,
+        endpoint,
+        ..Default::default()
+    }));
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path().join("audit.jsonl")));
+    let gate = Arc::new(CommandGate::new(SafetyConfig::default(), audit));
+    let execution =
+        ExecutionService::new(gate, Default::default()).with_tracer(Arc::clone(&tracer));
+    let canvas: CanvasDocument = serde_json::from_value(serde_json::json!({
+        "id": "traced",
+        "nodes": [
+            { "id": "ok", "type": "terminal", "command": "echo", "args": ["hi"] },
+            { "id": "fail", "type": "terminal", "command": "sh", "args": ["-c", "exit 3"] }
+        ]
+    }))
+    .unwrap();
+
+    let report = crate::execution::run_canvas(&execution, &canvas, &HashMap::new(), "test")
+        .await
+        .unwrap();
+    assert!(!report.success);
+    assert_eq!(tracer.pending(), 5);
+    tracer.flush().await.unwrap();
+    assert_eq!(tracer.pending(), 0);
+
+    let spans = exported(&received);
+    let run = &spans["canvas_run"][0];
+    assert_eq!(run["status"]["code"], 2);
+    let nodes = &spans["canvas_node"];
+    let commands = &spans["command_execution"];
+    assert_eq!((nodes.len(), commands.len()), (2, 2));
+    for node in nodes {
+        assert_eq!(node["traceId"], run["traceId"]);
+        assert_eq!(node["parentSpanId"], run["spanId"]);
+    }
+    // Each command sits under its node, and a failed exit fails its span
+    let failed = commands
+        .iter()
+        .find(|span| span["status"]["code"] == 2)
+        .unwrap();
+    assert_eq!(failed["status"]["message"], "command-failed");
+    assert!(nodes
+        .iter()
+        .any(|node| node["spanId"] == failed["parentSpanId"]));
+    assert!(failed["attributes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|a| a["key"] == "process.exit_code" && a["value"]["intValue"] == "3"));
+}
+
+#[test]
+fn disabled_tracer_drops_spans() {
+    let tracer = Tracer::disabled();
+    tracer.record(Span::root("dropped"));
+    assert_eq!(tracer.pending(), 0);
+}
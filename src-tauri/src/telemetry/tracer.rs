@@ -0,0 +1,102 @@
+//! Span buffering and periodic export.
+
+use super::{OtlpExporter, Span, TelemetryConfig};
+use crate::health::Component;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Collects finished spans and exports them in batches
+pub struct Tracer {
+    exporter: Option<OtlpExporter>,
+    buffer: Mutex<VecDeque<Span>>,
+    max_buffered_spans: usize,
+    flush_interval: Duration,
+}
+
+impl Tracer {
+    pub fn new(config: &TelemetryConfig) -> Self {
+        let exporter = if config.enabled {
+            match OtlpExporter::new(config) {
+                Ok(exporter) => Some(exporter),
+                Err(e) => {
+                    log::warn!("[telemetry] Export disabled: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Self {
+            exporter,
+            buffer: Mutex::new(VecDeque::new()),
+            max_buffered_spans: config.max_buffered_spans.max(1),
+            flush_interval: Duration::from_secs(config.flush_interval_secs.max(1)),
+        }
+    }
+
+    /// A tracer that drops every span
+    pub fn disabled() -> Self {
+        Self::new(&TelemetryConfig::default())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.exporter.is_some()
+    }
+
+    /// Queue a span for export, finishing it if the caller has not
+    pub fn record(&self, mut span: Span) {
+        if !self.is_enabled() {
+            return;
+        }
+        if span.end_time.is_none() {
+            span.finish();
+        }
+
+        let Ok(mut buffer) = self.buffer.lock() else {
+            return;
+        };
+        if buffer.len() >= self.max_buffered_spans {
+            buffer.pop_front();
+        }
+        buffer.push_back(span);
+    }
+
+    /// Number of spans waiting for export
+    pub fn pending(&self) -> usize {
+        self.buffer.lock().map(|b| b.len()).unwrap_or(0)
+    }
+
+    /// Export everything buffered so far. Failed batches are dropped, not retried,
+    /// so an unreachable collector cannot grow memory without bound.
+    pub async fn flush(&self) -> Result<()> {
+        let Some(exporter) = &self.exporter else {
+            return Ok(());
+        };
+        let batch: Vec<Span> = match self.buffer.lock() {
+            Ok(mut buffer) => buffer.drain(..).collect(),
+            Err(_) => return Ok(()),
+        };
+        exporter.export(&batch).await
+    }
+
+    /// Flush on the configured interval until the process exits
+    pub async fn run_flush_loop(self: Arc<Self>, health: Component) {
+        if !self.is_enabled() {
+            return health.disable("Set [telemetry] enabled to export traces");
+        }
+        loop {
+            tokio::time::sleep(self.flush_interval).await;
+            match self.flush().await {
+                Ok(()) => health.ok(),
+                Err(e) => {
+                    log::warn!("[telemetry] {}", e);
+                    health.error(format!("{:#}", e));
+                }
+            }
+            health.counter("pending_spans", self.pending() as u64);
+        }
+    }
+}
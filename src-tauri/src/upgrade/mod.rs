@@ -0,0 +1,293 @@
+//! Upgrade detection.
+//!
+//! The memory store keeps a record of the last RuneBook version (and update
+//! channel) that opened it. At startup [`detect`] compares it with the running
+//! build, gathers what the config and store migrations did, and produces an
+//! [`UpgradeReport`] describing what changed for the user's data. Heavy store
+//! migrations are left pending unless `[updates] defer_heavy_migrations` is
+//! off; the report lists them so the user can run them when convenient.
+
+use crate::config::migrate::ConfigMigrationReport;
+use crate::memory::keys::RecordKind;
+use crate::memory::migration::{self, MigrationStatus};
+use crate::memory::MemoryStore;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use ts_rs::TS;
+
+/// Release channel this build updates from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl UpdateChannel {
+    pub fn name(self) -> &'static str {
+        match self {
+            UpdateChannel::Stable => "stable",
+            UpdateChannel::Beta => "beta",
+            UpdateChannel::Nightly => "nightly",
+        }
+    }
+}
+
+/// `[updates]` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct UpdateConfig {
+    pub channel: UpdateChannel,
+    /// Leave heavy store migrations for an explicit user action
+    pub defer_heavy_migrations: bool,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            channel: UpdateChannel::Stable,
+            defer_heavy_migrations: true,
+        }
+    }
+}
+
+/// Last version that opened the store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppVersionRecord {
+    pub version: String,
+    pub channel: UpdateChannel,
+    pub schema_version: u32,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub previous_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum UpgradeKind {
+    FirstRun,
+    Unchanged,
+    Upgrade,
+    Downgrade,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum DataArea {
+    Config,
+    Memory,
+    Updates,
+}
+
+/// One line of the "what changed for your data" report
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct DataChange {
+    pub area: DataArea,
+    pub summary: String,
+    /// The user should act (run a deferred migration, check a downgrade)
+    pub action_required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct UpgradeReport {
+    pub kind: UpgradeKind,
+    pub from_version: Option<String>,
+    pub to_version: String,
+    pub channel: UpdateChannel,
+    pub previous_channel: Option<UpdateChannel>,
+    pub config: ConfigMigrationReport,
+    /// Schema status after startup; `pending` migrations wait for the user
+    pub store: MigrationStatus,
+    pub changes: Vec<DataChange>,
+}
+
+impl UpgradeReport {
+    /// Whether the report has anything worth showing
+    pub fn is_notable(&self) -> bool {
+        self.kind != UpgradeKind::Unchanged || !self.changes.is_empty()
+    }
+}
+
+/// Compare dotted versions numerically; pre-release suffixes are ignored
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+pub async fn read_version_record(store: &MemoryStore) -> Result<Option<AppVersionRecord>> {
+    Ok(store
+        .client
+        .get(&RecordKind::App.key("version"))
+        .await?
+        .and_then(|v| serde_json::from_value(v).ok()))
+}
+
+async fn write_version_record(store: &MemoryStore, record: &AppVersionRecord) -> Result<()> {
+    store
+        .client
+        .put(
+            &RecordKind::App.key("version"),
+            &serde_json::to_value(record)?,
+        )
+        .await
+}
+
+/// Compare the running build with the store's version record, run (or defer)
+/// store migrations, and update the record. `config` is the result of the
+/// config-file migration done before the config was loaded.
+pub async fn detect(
+    store: &MemoryStore,
+    version: &str,
+    updates: &UpdateConfig,
+    config: ConfigMigrationReport,
+) -> Result<UpgradeReport> {
+    let previous = read_version_record(store).await?;
+    let kind = match &previous {
+        None => UpgradeKind::FirstRun,
+        Some(record) => match compare_versions(version, &record.version) {
+            Ordering::Greater => UpgradeKind::Upgrade,
+            Ordering::Less => UpgradeKind::Downgrade,
+            Ordering::Equal => UpgradeKind::Unchanged,
+        },
+    };
+
+    let store_status =
+        migration::run_pending_migrations(store, updates.defer_heavy_migrations).await?;
+    let mut changes = Vec::new();
+
+    for step in &config.applied {
+        changes.push(DataChange {
+            area: DataArea::Config,
+            summary: format!(
+                "config.toml: {} (format v{})",
+                step.description, step.version
+            ),
+            action_required: false,
+        });
+    }
+    if let Some(backup) = &config.backup {
+        changes.push(DataChange {
+            area: DataArea::Config,
+            summary: format!("The previous config.toml was saved as {}", backup.display()),
+            action_required: false,
+        });
+    }
+    if config.newer_than_supported {
+        changes.push(DataChange {
+            area: DataArea::Config,
+            summary: format!(
+                "config.toml uses format v{}, newer than this version understands; settings it does not know are ignored",
+                config.from_version
+            ),
+            action_required: true,
+        });
+    }
+
+    let schema_before = previous.as_ref().map_or(0, |r| r.schema_version);
+    if previous.is_some() && store_status.current_version > schema_before {
+        changes.push(DataChange {
+            area: DataArea::Memory,
+            summary: format!(
+                "Memory schema upgraded from v{} to v{}",
+                schema_before, store_status.current_version
+            ),
+            action_required: false,
+        });
+    }
+    for migration in &store_status.pending {
+        changes.push(DataChange {
+            area: DataArea::Memory,
+            summary: format!(
+                "Pending: {} (v{}). Run it from the upgrade report when convenient; it may take a while on large stores",
+                migration.description, migration.version
+            ),
+            action_required: true,
+        });
+    }
+
+    let previous_channel = previous.as_ref().map(|r| r.channel);
+    if let Some(channel) = previous_channel.filter(|c| *c != updates.channel) {
+        changes.push(DataChange {
+            area: DataArea::Updates,
+            summary: format!(
+                "Update channel changed from {} to {}",
+                channel.name(),
+                updates.channel.name()
+            ),
+            action_required: false,
+        });
+    }
+    if kind == UpgradeKind::Downgrade {
+        changes.push(DataChange {
+            area: DataArea::Memory,
+            summary: format!(
+                "This data was last opened by RuneBook {}, newer than {}; records written by the newer version may not display fully",
+                previous.as_ref().map_or("?", |r| r.version.as_str()),
+                version
+            ),
+            action_required: true,
+        });
+    }
+
+    write_version_record(
+        store,
+        &AppVersionRecord {
+            version: version.to_string(),
+            channel: updates.channel,
+            schema_version: store_status.current_version,
+            updated_at: Utc::now(),
+            previous_version: match kind {
+                UpgradeKind::Unchanged => {
+                    previous.as_ref().and_then(|r| r.previous_version.clone())
+                }
+                _ => previous.as_ref().map(|r| r.version.clone()),
+            },
+        },
+    )
+    .await?;
+
+    Ok(UpgradeReport {
+        kind,
+        from_version: previous.map(|r| r.version),
+        to_version: version.to_string(),
+        channel: updates.channel,
+        previous_channel,
+        config,
+        store: store_status,
+        changes,
+    })
+}
+
+/// Run the store migrations a report deferred, returning the new status
+pub async fn run_deferred_migrations(store: &MemoryStore) -> Result<MigrationStatus> {
+    let status = migration::run_pending_migrations(store, false).await?;
+    if let Some(mut record) = read_version_record(store).await? {
+        record.schema_version = status.current_version;
+        write_version_record(store, &record).await?;
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests;
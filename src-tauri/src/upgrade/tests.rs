@@ -0,0 +1,76 @@
+use super::*;
+use crate::config::migrate::{migrate_file, migrate_text, CONFIG_VERSION};
+use crate::memory::migration::plan;
+
+#[test]
+fn compares_versions_numerically() {
+    assert_eq!(compare_versions("0.17.0", "0.9.3"), Ordering::Greater);
+    assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+    assert_eq!(compare_versions("0.18.0-beta.2", "0.18.0"), Ordering::Equal);
+    assert_eq!(compare_versions("0.17.1", "0.17.10"), Ordering::Less);
+}
+
+#[test]
+fn config_migration_keeps_comments_and_stamps_version() {
+    let original = "# my settings\n[safety]\ndestructive = \"block\" # careful\n";
+    let (migrated, report) = migrate_text(original).unwrap();
+    assert_eq!(report.from_version, 0);
+    assert_eq!(report.to_version, CONFIG_VERSION);
+    assert!(!report.applied.is_empty());
+    assert!(migrated.contains("# my settings"));
+    assert!(migrated.contains("# careful"));
+
+    // The stamp is a top-level key, and the result still loads
+    let value: toml::Value = toml::from_str(&migrated).unwrap();
+    assert_eq!(
+        value["config_version"].as_integer(),
+        Some(i64::from(CONFIG_VERSION))
+    );
+    let config: crate::config::RunebookConfig = toml::from_str(&migrated).unwrap();
+    assert_eq!(
+        config.safety.destructive,
+        crate::execution::DestructivePolicy::Block
+    );
+
+    // Already current: untouched
+    let (again, report) = migrate_text(&migrated).unwrap();
+    assert_eq!(again, migrated);
+    assert!(report.applied.is_empty());
+
+    // Newer than supported: untouched and flagged
+    let newer = format!("config_version = {}\n", CONFIG_VERSION + 1);
+    let (text, report) = migrate_text(&newer).unwrap();
+    assert_eq!(text, newer);
+    assert!(report.newer_than_supported);
+}
+
+#[test]
+fn config_file_migration_replaces_the_file_whole() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    let original = "[safety]\ndestructive = \"block\"\n";
+    std::fs::write(&path, original).unwrap();
+
+    let report = migrate_file(&path).unwrap();
+    let backup = report.backup.unwrap();
+    assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+    let migrated = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(migrate_text(original).unwrap().0, migrated);
+    assert!(!path.with_extension("toml.partial").exists());
+}
+
+#[test]
+fn heavy_store_migrations_wait_when_deferred() {
+    let (now, deferred) = plan(0, true);
+    assert!(now.iter().all(|m| !m.heavy));
+    assert!(deferred.first().is_some_and(|m| m.heavy));
+    // Later steps wait behind a deferred heavy one
+    assert_eq!(now.len() + deferred.len(), plan(0, false).0.len());
+
+    let (now, deferred) = plan(0, false);
+    assert!(deferred.is_empty());
+    assert_eq!(
+        now.last().unwrap().version,
+        migration::MIGRATIONS.last().unwrap().version
+    );
+}
@@ -0,0 +1,609 @@
+//! Checks on the arguments the frontend passes to commands.
+//!
+//! Command arguments come from the webview and are not trusted. A command
+//! takes an argument as [`Valid<T, R>`] to have it checked before its body
+//! runs. The raw JSON is held to the `[validation]` size limits. Then every
+//! string in the value is checked by the [`Rule`] `R`:
+//!
+//! - [`Text`]: free text; control and bidirectional-override characters are
+//!   stripped
+//! - [`Raw`]: bytes passed through untouched, such as terminal input
+//! - [`Id`]: record ids and host names, without whitespace or controls
+//! - [`Uuid`]: ids the backend handed out itself
+//! - [`FilePath`]: files to read or write; canonicalized and kept under
+//!   `allowed_roots`
+//! - [`Env`]: environment maps, whose keys must be variable names
+//!
+//! A failed check rejects the invoke with an `invalid-argument` message that
+//! names the argument and the rule it broke:
+//!
+//! ```toml
+//! [validation]
+//! allowed_roots = ["~/projects", "/tmp"]
+//! max_string_bytes = 1048576
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use crate::alerts::{AlertAction, AlertCondition, AlertRule};
+use crate::execution::{
+    BenchmarkSpec, CanvasDocument, DebugCommand, DebugSpec, ExecutionRequest, LogFollowSpec,
+    LogSource, Password, PipelineSpec, PipelineStage, SandboxSpec, SimulationSpec, WatchSpec,
+};
+use crate::i18n::UserMessage;
+use crate::onboarding::StepAnswer;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use ts_rs::TS;
+
+/// `[validation]` configuration section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct ValidationConfig {
+    /// Directories path arguments must be under; the home and temp
+    /// directories when empty. `~` expands to the home directory.
+    pub allowed_roots: Vec<PathBuf>,
+    /// Longest string argument, in bytes
+    pub max_string_bytes: usize,
+    /// Most entries in one list or map
+    pub max_items: usize,
+    /// Largest argument as JSON, in bytes
+    pub max_payload_bytes: usize,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            allowed_roots: Vec::new(),
+            max_string_bytes: 1024 * 1024,
+            max_items: 10_000,
+            max_payload_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Why an argument was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationError {
+    /// Argument name as the frontend sent it
+    pub argument: String,
+    /// The check that failed, e.g. `max_string_bytes` or `allowed_roots`
+    pub rule: &'static str,
+    pub error: String,
+}
+
+impl ValidationError {
+    pub fn new(rule: &'static str, error: impl ToString) -> Self {
+        Self {
+            argument: String::new(),
+            rule,
+            error: error.to_string(),
+        }
+    }
+
+    fn at(mut self, argument: &str) -> Self {
+        self.argument = argument.to_string();
+        self
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.argument, self.rule, self.error)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl From<ValidationError> for UserMessage {
+    fn from(e: ValidationError) -> Self {
+        UserMessage::new("invalid-argument")
+            .with("argument", &e.argument)
+            .with("rule", e.rule)
+            .with("error", &e.error)
+    }
+}
+
+/// The limits in effect, with `allowed_roots` resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationPolicy {
+    pub roots: Vec<PathBuf>,
+    pub max_string_bytes: usize,
+    pub max_items: usize,
+    pub max_payload_bytes: usize,
+}
+
+impl ValidationPolicy {
+    pub fn new(config: &ValidationConfig) -> Self {
+        let home = dirs::home_dir();
+        let roots = if config.allowed_roots.is_empty() {
+            home.iter().cloned().chain([std::env::temp_dir()]).collect()
+        } else {
+            config
+                .allowed_roots
+                .iter()
+                .map(|root| match (root.strip_prefix("~"), &home) {
+                    (Ok(rest), Some(home)) => home.join(rest),
+                    _ => root.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+        Self {
+            // Roots that do not exist yet are kept as written
+            roots: roots
+                .into_iter()
+                .map(|root| root.canonicalize().unwrap_or(root))
+                .collect(),
+            max_string_bytes: config.max_string_bytes,
+            max_items: config.max_items,
+            max_payload_bytes: config.max_payload_bytes,
+        }
+    }
+
+    /// Hold an argument's raw JSON to the size limits, before it is parsed
+    pub fn check_raw(&self, argument: &str, raw: &Value) -> Result<(), ValidationError> {
+        let size = serde_json::to_vec(raw)
+            .map(|bytes| bytes.len())
+            .unwrap_or(0);
+        if size > self.max_payload_bytes {
+            return Err(ValidationError::new(
+                "max_payload_bytes",
+                format!("{} bytes, at most {} allowed", size, self.max_payload_bytes),
+            )
+            .at(argument));
+        }
+        self.check_json(raw).map_err(|e| e.at(argument))
+    }
+
+    fn check_json(&self, value: &Value) -> Result<(), ValidationError> {
+        match value {
+            Value::String(s) => self.check_len(s),
+            Value::Array(items) => {
+                self.check_items(items.len())?;
+                items.iter().try_for_each(|item| self.check_json(item))
+            }
+            Value::Object(fields) => {
+                self.check_items(fields.len())?;
+                fields.iter().try_for_each(|(key, field)| {
+                    self.check_len(key)?;
+                    self.check_json(field)
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_len(&self, s: &str) -> Result<(), ValidationError> {
+        if s.len() > self.max_string_bytes {
+            return Err(ValidationError::new(
+                "max_string_bytes",
+                format!(
+                    "{} bytes, at most {} allowed",
+                    s.len(),
+                    self.max_string_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_items(&self, count: usize) -> Result<(), ValidationError> {
+        if count > self.max_items {
+            return Err(ValidationError::new(
+                "max_items",
+                format!("{} entries, at most {} allowed", count, self.max_items),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Canonicalize `path` and check it is under an allowed root. Parts of
+    /// the path that do not exist yet are kept, but may not contain `..`.
+    pub fn resolve_path(&self, path: &str) -> Result<PathBuf, ValidationError> {
+        if path.is_empty() || path.contains('\0') {
+            return Err(ValidationError::new("path", "not a usable path"));
+        }
+        let path = Path::new(path);
+        let (base, rest) = path
+            .ancestors()
+            .find_map(|ancestor| {
+                let base = if ancestor.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    ancestor
+                };
+                let canonical = base.canonicalize().ok()?;
+                Some((canonical, path.strip_prefix(ancestor).ok()?))
+            })
+            .ok_or_else(|| ValidationError::new("path", "no part of the path exists"))?;
+        if rest.components().any(|c| c == Component::ParentDir) {
+            return Err(ValidationError::new(
+                "path",
+                "'..' below a directory that does not exist",
+            ));
+        }
+        let resolved = base.join(rest);
+        if !self.roots.iter().any(|root| resolved.starts_with(root)) {
+            return Err(ValidationError::new(
+                "allowed_roots",
+                format!("{} is outside the allowed directories", resolved.display()),
+            ));
+        }
+        Ok(resolved)
+    }
+}
+
+/// How each string in an argument is checked
+pub trait Rule {
+    fn check(value: &mut String, policy: &ValidationPolicy) -> Result<(), ValidationError>;
+
+    /// Check a map key; map keys are only held to the size limit by default
+    fn check_key(key: &str, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_len(key)
+    }
+}
+
+/// Free text: names, descriptions, tags, search strings
+pub struct Text;
+
+impl Rule for Text {
+    fn check(value: &mut String, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_len(value)?;
+        if value.chars().any(is_unsafe_char) {
+            value.retain(|c| !is_unsafe_char(c));
+        }
+        Ok(())
+    }
+}
+
+/// Control characters other than tab and newlines, and the characters that
+/// reorder how text is displayed
+fn is_unsafe_char(c: char) -> bool {
+    (c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+        || matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Strings passed through as sent: terminal input, command lines
+pub struct Raw;
+
+impl Rule for Raw {
+    fn check(value: &mut String, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_len(value)
+    }
+}
+
+/// Record ids, names and host names
+pub struct Id;
+
+impl Rule for Id {
+    fn check(value: &mut String, _policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        if value.is_empty() || value.len() > 256 {
+            return Err(ValidationError::new("id", "must be 1 to 256 bytes long"));
+        }
+        if value
+            .chars()
+            .any(|c| c.is_whitespace() || is_unsafe_char(c))
+        {
+            return Err(ValidationError::new(
+                "id",
+                "may not contain whitespace or control characters",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Ids the backend generated: terminals, watches, debug sessions, views
+pub struct Uuid;
+
+impl Rule for Uuid {
+    fn check(value: &mut String, _policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        uuid::Uuid::parse_str(value)
+            .map(|_| ())
+            .map_err(|e| ValidationError::new("uuid", e))
+    }
+}
+
+/// Files and directories to read or write; replaced by the canonical path
+pub struct FilePath;
+
+impl Rule for FilePath {
+    fn check(value: &mut String, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_len(value)?;
+        let resolved = policy.resolve_path(value)?;
+        *value = resolved
+            .into_os_string()
+            .into_string()
+            .map_err(|_| ValidationError::new("path", "not valid UTF-8"))?;
+        Ok(())
+    }
+}
+
+/// Environment variables: names as `execution` accepts them, raw values
+pub struct Env;
+
+impl Rule for Env {
+    fn check(value: &mut String, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        Raw::check(value, policy)
+    }
+
+    fn check_key(key: &str, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_len(key)?;
+        if !crate::execution::command::valid_env_name(key) {
+            return Err(ValidationError::new(
+                "env_name",
+                format!("'{}' is not a variable name", key),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Values whose strings a [`Rule`] can check
+pub trait Validate {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError>;
+}
+
+impl Validate for String {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        R::check(self, policy)
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        match self {
+            Some(value) => value.validate::<R>(policy),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_items(self.len())?;
+        self.iter_mut()
+            .try_for_each(|item| item.validate::<R>(policy))
+    }
+}
+
+impl<T: Validate> Validate for HashMap<String, T> {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_items(self.len())?;
+        self.iter_mut().try_for_each(|(key, value)| {
+            R::check_key(key, policy)?;
+            value.validate::<R>(policy)
+        })
+    }
+}
+
+impl<T: Validate> Validate for BTreeMap<String, T> {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        policy.check_items(self.len())?;
+        self.iter_mut().try_for_each(|(key, value)| {
+            R::check_key(key, policy)?;
+            value.validate::<R>(policy)
+        })
+    }
+}
+
+impl Validate for ExecutionRequest {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.env.validate::<Env>(policy)?;
+        self.stdin.validate::<Raw>(policy)
+    }
+}
+
+impl Validate for PipelineSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.request.validate::<R>(policy)?;
+        self.stages.validate::<R>(policy)
+    }
+}
+
+impl Validate for PipelineStage {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.env.validate::<Env>(policy)?;
+        self.confirmation_token.validate::<Uuid>(policy)
+    }
+}
+
+impl Validate for WatchSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.request.validate::<R>(policy)?;
+        self.node_id.validate::<Id>(policy)
+    }
+}
+
+impl Validate for LogFollowSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        match &mut self.source {
+            LogSource::Docker { container } => Id::check(container, policy)?,
+            LogSource::Kubernetes {
+                pod,
+                namespace,
+                container,
+                context,
+            } => {
+                Id::check(pod, policy)?;
+                namespace.validate::<Id>(policy)?;
+                container.validate::<Id>(policy)?;
+                context.validate::<Id>(policy)?;
+            }
+        }
+        self.since.validate::<Id>(policy)?;
+        self.node_id.validate::<Id>(policy)
+    }
+}
+
+impl Validate for AlertRule {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        Text::check(&mut self.name, policy)?;
+        match &mut self.when {
+            AlertCondition::ErrorFingerprint { fingerprint } => Id::check(fingerprint, policy)?,
+            AlertCondition::Duration { command, .. } => command.validate::<Id>(policy)?,
+            AlertCondition::OutputContains { pattern, command } => {
+                Raw::check(pattern, policy)?;
+                command.validate::<Id>(policy)?;
+            }
+        }
+        policy.check_items(self.actions.len())?;
+        for action in &mut self.actions {
+            match action {
+                AlertAction::Notify => {}
+                AlertAction::RunCanvas { path, variables } => {
+                    FilePath::check(path, policy)?;
+                    variables.validate::<Raw>(policy)?;
+                }
+                AlertAction::Webhook { url } => Text::check(url, policy)?,
+                AlertAction::Hook { name } => Text::check(name, policy)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for BenchmarkSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.request.validate::<R>(policy)
+    }
+}
+
+impl Validate for SandboxSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.request.validate::<R>(policy)
+    }
+}
+
+impl Validate for Password {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        // Passed to sudo as typed; only its size is checked
+        policy.check_len(self.expose())
+    }
+}
+
+impl Validate for CanvasDocument {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        Id::check(&mut self.id, policy)?;
+        Text::check(&mut self.name, policy)
+    }
+}
+
+impl Validate for DebugSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.canvas.validate::<R>(policy)?;
+        self.breakpoints.validate::<Id>(policy)
+    }
+}
+
+impl Validate for DebugCommand {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        match self {
+            DebugCommand::SetBreakpoints { node_ids } => node_ids.validate::<Id>(policy),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Validate for SimulationSpec {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        self.canvas.validate::<R>(policy)
+    }
+}
+
+impl Validate for StepAnswer {
+    fn validate<R: Rule>(&mut self, policy: &ValidationPolicy) -> Result<(), ValidationError> {
+        match self {
+            StepAnswer::DataDir { path } => {
+                let raw = path
+                    .to_str()
+                    .ok_or_else(|| ValidationError::new("path", "not valid UTF-8"))?;
+                *path = policy.resolve_path(raw)?;
+                Ok(())
+            }
+            StepAnswer::Backend { host, .. } => Id::check(host, policy),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Structured arguments whose parts are only held to the size limits
+macro_rules! size_limited {
+    ($($ty:ty),* $(,)?) => {
+        $(impl Validate for $ty {
+            fn validate<R: Rule>(&mut self, _policy: &ValidationPolicy) -> Result<(), ValidationError> {
+                Ok(())
+            }
+        })*
+    };
+}
+
+size_limited!(
+    crate::memory::ViewFilter,
+    crate::memory::RankingConfig,
+    crate::health::AnalyzerHealth,
+    crate::telemetry::Span,
+    crate::receipts::ExecutionReceipt,
+    crate::dispatch::WindowFilter,
+);
+
+/// An argument that passed validation. `R` is the rule its strings were
+/// checked with; structured arguments apply their own rules to their fields.
+pub struct Valid<T, R: Rule = Text> {
+    value: T,
+    rule: PhantomData<fn() -> R>,
+}
+
+impl<T: Validate, R: Rule> Valid<T, R> {
+    /// Check `value`, reporting failures against `argument`
+    pub fn new(
+        mut value: T,
+        argument: &str,
+        policy: &ValidationPolicy,
+    ) -> Result<Self, ValidationError> {
+        value.validate::<R>(policy).map_err(|e| e.at(argument))?;
+        Ok(Self {
+            value,
+            rule: PhantomData,
+        })
+    }
+}
+
+impl<T, R: Rule> Valid<T, R> {
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, R: Rule> Deref for Valid<T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: AsRef<U>, U: ?Sized, R: Rule> AsRef<U> for Valid<T, R> {
+    fn as_ref(&self) -> &U {
+        self.value.as_ref()
+    }
+}
+
+impl<T: fmt::Display, R: Rule> fmt::Display for Valid<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<T: fmt::Debug, R: Rule> fmt::Debug for Valid<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+use serde_json::json;
+
+fn policy(root: &Path) -> ValidationPolicy {
+    ValidationPolicy::new(&ValidationConfig {
+        allowed_roots: vec![root.to_path_buf()],
+        max_string_bytes: 64,
+        max_items: 3,
+        ..ValidationConfig::default()
+    })
+}
+
+#[test]
+fn paths_are_canonicalized_and_kept_under_allowed_roots() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    std::fs::create_dir(root.join("canvases")).unwrap();
+    let policy = policy(&root);
+
+    // Through an existing directory, into a file that does not exist yet
+    let path = format!("{}/canvases/../canvases/new.yaml", root.display());
+    let valid = Valid::<String, FilePath>::new(path, "path", &policy).unwrap();
+    assert_eq!(
+        valid.into_inner(),
+        root.join("canvases/new.yaml").to_string_lossy()
+    );
+
+    let outside = format!("{}/canvases/../..", root.display());
+    let error = Valid::<String, FilePath>::new(outside, "path", &policy).unwrap_err();
+    assert_eq!(error.rule, "allowed_roots");
+    let sneaky = format!("{}/missing/../../etc", root.display());
+    let error = Valid::<String, FilePath>::new(sneaky, "path", &policy).unwrap_err();
+    assert_eq!(error.rule, "path");
+}
+
+#[test]
+fn strings_and_lists_are_checked_by_their_rule() {
+    let dir = tempfile::tempdir().unwrap();
+    let policy = policy(dir.path());
+
+    let text = Valid::<Vec<String>>::new(
+        vec!["build\u{1b}[2J".to_string(), "a\u{202E}b\tc".to_string()],
+        "tags",
+        &policy,
+    )
+    .unwrap();
+    assert_eq!(*text, ["build[2J", "ab\tc"]);
+    // Terminal input keeps its escape sequences
+    let raw = Valid::<String, Raw>::new("\u{1b}[A\r".to_string(), "data", &policy).unwrap();
+    assert_eq!(raw.into_inner(), "\u{1b}[A\r");
+
+    let error = Valid::<Vec<String>>::new(vec![String::new(); 4], "tags", &policy).unwrap_err();
+    assert_eq!((error.argument.as_str(), error.rule), ("tags", "max_items"));
+    let error = Valid::<String>::new("x".repeat(65), "name", &policy).unwrap_err();
+    assert_eq!(error.rule, "max_string_bytes");
+    assert!(Valid::<String, Id>::new("demo-session".to_string(), "id", &policy).is_ok());
+    assert!(Valid::<String, Id>::new("two words".to_string(), "id", &policy).is_err());
+    assert!(Valid::<Option<String>, Uuid>::new(None, "id", &policy).is_ok());
+    let error = Valid::<String, Uuid>::new("42".to_string(), "terminalId", &policy).unwrap_err();
+    assert_eq!(error.rule, "uuid");
+
+    let env = HashMap::from([("BAD NAME".to_string(), "1".to_string())]);
+    let error = Valid::<HashMap<String, String>, Env>::new(env, "env", &policy).unwrap_err();
+    assert_eq!(error.rule, "env_name");
+    let message = UserMessage::from(error);
+    assert_eq!(message.code, "invalid-argument");
+    assert_eq!(message.params["argument"], "env");
+}
+
+#[test]
+fn raw_arguments_are_held_to_the_size_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let policy = policy(dir.path());
+
+    assert!(policy
+        .check_raw("spec", &json!({ "command": "ls", "args": ["-l"] }))
+        .is_ok());
+    let error = policy
+        .check_raw("spec", &json!({ "args": ["1", "2", "3", "4"] }))
+        .unwrap_err();
+    assert_eq!((error.argument.as_str(), error.rule), ("spec", "max_items"));
+    let error = policy
+        .check_raw("spec", &json!({ "command": "y".repeat(65) }))
+        .unwrap_err();
+    assert_eq!(error.rule, "max_string_bytes");
+}
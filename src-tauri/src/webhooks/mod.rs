@@ -0,0 +1,682 @@
+//! Outbound webhooks.
+//!
+//! `[[webhooks.hooks]]` names an endpoint and how to talk to it: a Slack or
+//! Discord incoming webhook, or a generic JSON receiver. Alert rules send to a
+//! hook with a `hook` action, and `[webhooks.digest]` sends a periodic summary
+//! of where command time went. A hook may replace the built-in payload with a
+//! JSON `template`, and send a secret header whose value is kept in the OS
+//! keychain, never in `config.toml`. Failed deliveries are retried with
+//! backoff, and every delivery, successful or not, is appended to
+//! `webhook-deliveries.jsonl` for debugging.
+//!
+//! ```toml
+//! [[webhooks.hooks]]
+//! name = "ops"
+//! url = "https://hooks.slack.com/services/T000/B000/XXXX"
+//! format = "slack"
+//!
+//! [[webhooks.hooks]]
+//! name = "pager"
+//! url = "https://events.example.com/v1"
+//! template = '{"title": "{{rule}}", "body": "{{summary}}"}'
+//! secret_header = "Authorization"   # runebook webhooks secret pager
+//!
+//! [webhooks.digest]
+//! hooks = ["ops"]
+//! interval_hours = 24
+//! catch_up = "run_all"   # one digest per period missed while asleep
+//! ```
+
+#[cfg(test)]
+mod tests;
+
+use crate::alerts::Alert;
+use crate::health::Component;
+use crate::memory::{StoreOpener, TimeGroupBy};
+use crate::schedule::{CatchUp, Schedule, Tick, Ticker};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use ts_rs::TS;
+
+/// How long one delivery attempt may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before the first retry; doubled for each one after it
+const BACKOFF: Duration = Duration::from_secs(1);
+/// Size at which the delivery log is rotated to `.1`
+const MAX_LOG_BYTES: u64 = 1 << 20;
+/// Longest Discord message, in characters
+const DISCORD_CHARS: usize = 2000;
+/// How often the digest loop checks whether a digest is due
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// `[webhooks]` configuration section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct WebhookConfig {
+    pub hooks: Vec<Webhook>,
+    pub digest: DigestConfig,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+/// One `[[webhooks.hooks]]` endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Webhook {
+    /// Unique among the hooks; alert rules and the digest refer to it
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// JSON body with `{{field}}` placeholders, replacing `format`'s payload.
+    /// Values are JSON-escaped, so placeholders go inside string literals.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Header sent with the secret stored by `runebook webhooks secret <name>`
+    #[serde(default)]
+    pub secret_header: Option<String>,
+    /// Retries after a network error, a 429 or a 5xx response
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+impl Webhook {
+    /// An unnamed generic JSON hook, for alert actions that only give a URL
+    pub fn url(url: &str) -> Self {
+        Self {
+            name: url.to_string(),
+            url: url.to_string(),
+            format: WebhookFormat::Json,
+            template: None,
+            secret_header: None,
+            retries: default_retries(),
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("Webhook without a name");
+        }
+        let parsed = url::Url::parse(&self.url)
+            .with_context(|| format!("Webhook {}: invalid URL", self.name))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            bail!("Webhook {}: URL must be http or https", self.name);
+        }
+        if let Some(header) = &self.secret_header {
+            reqwest::header::HeaderName::from_bytes(header.as_bytes())
+                .with_context(|| format!("Webhook {}: invalid secret header", self.name))?;
+        }
+        if let Some(template) = &self.template {
+            let sample = render(template, &BTreeMap::new())
+                .with_context(|| format!("Webhook {}: invalid template", self.name))?;
+            serde_json::from_str::<serde_json::Value>(&sample)
+                .with_context(|| format!("Webhook {}: template is not JSON", self.name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Built-in payload shapes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum WebhookFormat {
+    /// The [`WebhookEvent`] itself
+    #[default]
+    Json,
+    /// `{"text": ...}` for a Slack incoming webhook
+    Slack,
+    /// `{"content": ...}` for a Discord webhook
+    Discord,
+}
+
+/// `[webhooks.digest]`: a periodic summary of where command time went
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct DigestConfig {
+    /// Hooks the digest goes to; none turns it off
+    pub hooks: Vec<String>,
+    pub interval_hours: u64,
+    /// What to do about digests missed while the machine slept
+    pub catch_up: CatchUp,
+    /// Period covered, as `time_report` takes it ("today", "week", "<n>d")
+    pub range: String,
+    /// Most highlights in one digest
+    pub highlights: usize,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            hooks: Vec::new(),
+            interval_hours: 24,
+            catch_up: CatchUp::default(),
+            range: "1d".to_string(),
+            highlights: 5,
+        }
+    }
+}
+
+/// Where command time went over a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Digest {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total_ms: u64,
+    pub highlights: Vec<String>,
+}
+
+/// What a webhook is sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum WebhookEvent {
+    Alert(Alert),
+    Digest(Digest),
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WebhookEvent::Alert(_) => "alert",
+            WebhookEvent::Digest(_) => "digest",
+        }
+    }
+
+    /// One message for chat formats
+    pub fn text(&self) -> String {
+        match self {
+            WebhookEvent::Alert(alert) => format!("[{}] {}", alert.rule, alert.summary),
+            WebhookEvent::Digest(digest) => {
+                let mut text = format!(
+                    "Runebook digest {} to {}: {} of commands",
+                    digest.from,
+                    digest.to,
+                    crate::memory::stats::format_duration_ms(digest.total_ms)
+                );
+                for line in &digest.highlights {
+                    text.push_str("\n• ");
+                    text.push_str(line);
+                }
+                text
+            }
+        }
+    }
+
+    /// Values for template placeholders: `event` and `text` always, plus the
+    /// event's own fields
+    pub fn fields(&self) -> BTreeMap<&'static str, String> {
+        let mut fields =
+            BTreeMap::from([("event", self.kind().to_string()), ("text", self.text())]);
+        match self {
+            WebhookEvent::Alert(alert) => {
+                fields.insert("rule", alert.rule.clone());
+                fields.insert("summary", alert.summary.clone());
+                fields.insert("fired_at", alert.fired_at.to_rfc3339());
+                fields.insert("command_id", alert.command_id.clone().unwrap_or_default());
+                fields.insert("session_id", alert.session_id.clone().unwrap_or_default());
+                fields.insert("fingerprint", alert.fingerprint.clone().unwrap_or_default());
+            }
+            WebhookEvent::Digest(digest) => {
+                fields.insert("from", digest.from.to_string());
+                fields.insert("to", digest.to.to_string());
+                fields.insert("total_ms", digest.total_ms.to_string());
+                fields.insert("highlights", digest.highlights.join("\n"));
+            }
+        }
+        fields
+    }
+}
+
+/// Every placeholder a template may use
+const FIELDS: &[&str] = &[
+    "event",
+    "text",
+    "rule",
+    "summary",
+    "fired_at",
+    "command_id",
+    "session_id",
+    "fingerprint",
+    "from",
+    "to",
+    "total_ms",
+    "highlights",
+];
+
+/// Fill `{{field}}` placeholders with JSON-escaped values. Fields the event
+/// does not have are empty; names no event has are an error.
+pub fn render(template: &str, fields: &BTreeMap<&'static str, String>) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").context("Unclosed {{ in template")?;
+        let name = after[..end].trim();
+        if !FIELDS.contains(&name) {
+            bail!("Unknown template field {:?}", name);
+        }
+        let value = fields.get(name).map(String::as_str).unwrap_or_default();
+        let escaped = serde_json::to_string(value)?;
+        out.push_str(&escaped[1..escaped.len() - 1]);
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The body `hook` sends for `event`
+pub fn payload(hook: &Webhook, event: &WebhookEvent) -> Result<serde_json::Value> {
+    if let Some(template) = &hook.template {
+        let body = render(template, &event.fields())?;
+        return serde_json::from_str(&body)
+            .with_context(|| format!("Webhook {}: template did not render to JSON", hook.name));
+    }
+    Ok(match hook.format {
+        WebhookFormat::Json => serde_json::to_value(event)?,
+        WebhookFormat::Slack => serde_json::json!({ "text": event.text() }),
+        WebhookFormat::Discord => {
+            serde_json::json!({ "content": event.text().chars().take(DISCORD_CHARS).collect::<String>() })
+        }
+    })
+}
+
+/// One delivery, after its last attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Delivery {
+    pub hook: String,
+    /// `alert` or `digest`
+    pub event: String,
+    pub at: DateTime<Utc>,
+    pub attempts: u32,
+    /// HTTP status of the last attempt, if it got a response
+    pub status: Option<u16>,
+    pub delivered: bool,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Append-only JSON lines of deliveries, rotated to `.1` past 1 MiB
+pub struct DeliveryLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl DeliveryLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// `webhook-deliveries.jsonl` in the config directory
+    pub fn open_default() -> Self {
+        Self::new(crate::config::config_dir().join("webhook-deliveries.jsonl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, delivery: &Delivery) -> Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if std::fs::metadata(&self.path).is_ok_and(|m| m.len() >= MAX_LOG_BYTES) {
+            std::fs::rename(&self.path, self.path.with_extension("jsonl.1"))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(delivery)?)?;
+        Ok(())
+    }
+
+    /// The newest `limit` deliveries, newest first, optionally to one hook
+    /// or of one event kind
+    pub fn recent(
+        &self,
+        hook: Option<&str>,
+        event: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<Delivery>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+        let mut deliveries: Vec<Delivery> = std::io::BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .filter(|d: &Delivery| hook.is_none_or(|h| d.hook == h))
+            .filter(|d: &Delivery| event.is_none_or(|e| d.event == e))
+            .collect();
+        deliveries.reverse();
+        deliveries.truncate(limit);
+        Ok(deliveries)
+    }
+}
+
+/// Looks up the secret of a hook by name
+pub type SecretSource = Arc<dyn Fn(&str) -> Result<Option<String>> + Send + Sync>;
+
+/// Sends events to the configured hooks and logs every delivery
+pub struct WebhookSender {
+    hooks: Vec<Webhook>,
+    http: reqwest::Client,
+    log: DeliveryLog,
+    backoff: Duration,
+    secrets: SecretSource,
+}
+
+impl WebhookSender {
+    /// Check `config`'s hooks; any invalid hook fails the whole set
+    pub fn new(config: &WebhookConfig, log: DeliveryLog) -> Result<Self> {
+        let mut names = std::collections::HashSet::new();
+        for hook in &config.hooks {
+            hook.check()?;
+            if !names.insert(hook.name.as_str()) {
+                bail!("Two webhooks are named {}", hook.name);
+            }
+        }
+        for name in &config.digest.hooks {
+            if !names.contains(name.as_str()) {
+                bail!("The digest goes to {}, which is not a webhook", name);
+            }
+        }
+        Ok(Self {
+            hooks: config.hooks.clone(),
+            http: reqwest::Client::new(),
+            log,
+            backoff: BACKOFF,
+            secrets: Arc::new(secrets::load),
+        })
+    }
+
+    /// Wait `backoff` before the first retry instead of a second
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Read hook secrets from `secrets` instead of the keychain
+    pub fn with_secrets(mut self, secrets: SecretSource) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    pub fn log(&self) -> &DeliveryLog {
+        &self.log
+    }
+
+    /// Send `event` to the hook named `name`
+    pub async fn send(&self, name: &str, event: &WebhookEvent) -> Result<Delivery> {
+        let hook = self
+            .hooks
+            .iter()
+            .find(|h| h.name == name)
+            .with_context(|| format!("No webhook named {}", name))?;
+        self.deliver(hook, event).await
+    }
+
+    /// Send `event` as generic JSON to `url`, which need not be configured
+    pub async fn send_to_url(&self, url: &str, event: &WebhookEvent) -> Result<Delivery> {
+        let hook = Webhook::url(url);
+        hook.check()?;
+        self.deliver(&hook, event).await
+    }
+
+    /// Post to `hook`, retrying what may succeed later, and log the outcome.
+    /// The returned delivery says whether it arrived.
+    async fn deliver(&self, hook: &Webhook, event: &WebhookEvent) -> Result<Delivery> {
+        let started = Instant::now();
+        let mut delivery = Delivery {
+            hook: hook.name.clone(),
+            event: event.kind().to_string(),
+            at: Utc::now(),
+            attempts: 0,
+            status: None,
+            delivered: false,
+            error: None,
+            duration_ms: 0,
+        };
+        let request = async {
+            let body = payload(hook, event)?;
+            let secret = match &hook.secret_header {
+                Some(header) => {
+                    let value = (self.secrets)(&hook.name)?.with_context(|| {
+                        format!(
+                            "No secret stored for webhook {}: run `runebook webhooks secret {}`",
+                            hook.name, hook.name
+                        )
+                    })?;
+                    Some((header.clone(), value))
+                }
+                None => None,
+            };
+            anyhow::Ok((body, secret))
+        }
+        .await;
+
+        match request {
+            Err(e) => delivery.error = Some(format!("{:#}", e)),
+            Ok((body, secret)) => {
+                let mut delay = self.backoff;
+                loop {
+                    delivery.attempts += 1;
+                    let mut post = self.http.post(&hook.url).json(&body).timeout(TIMEOUT);
+                    if let Some((header, value)) = &secret {
+                        post = post.header(header.as_str(), value.as_str());
+                    }
+                    let retry = match post.send().await {
+                        Ok(response) => {
+                            let status = response.status();
+                            delivery.status = Some(status.as_u16());
+                            delivery.delivered = status.is_success();
+                            delivery.error =
+                                (!status.is_success()).then(|| format!("HTTP {}", status));
+                            status.is_server_error()
+                                || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        }
+                        Err(e) => {
+                            delivery.status = None;
+                            delivery.error = Some(e.to_string());
+                            true
+                        }
+                    };
+                    if !retry || delivery.attempts > hook.retries {
+                        break;
+                    }
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+
+        delivery.duration_ms = started.elapsed().as_millis() as u64;
+        if let Err(e) = self.log.append(&delivery) {
+            log::warn!("[webhooks] Delivery log unavailable: {:#}", e);
+        }
+        Ok(delivery)
+    }
+}
+
+/// The digest `config` describes as of `now`
+pub async fn build_digest(
+    open_store: &StoreOpener,
+    config: &DigestConfig,
+    now: DateTime<Utc>,
+) -> Result<Digest> {
+    let (from, to) = crate::memory::stats::parse_range(&config.range, now)?;
+    let store = open_store().await?;
+    let report = store.time_report(from, to, TimeGroupBy::Project, 1).await?;
+    Ok(Digest {
+        from,
+        to,
+        total_ms: report.total_ms,
+        highlights: report.summary_lines(config.highlights),
+    })
+}
+
+impl DigestConfig {
+    pub fn schedule(&self) -> Schedule {
+        Schedule::every_hours(self.interval_hours, self.catch_up)
+    }
+}
+
+/// When the next digest is due, given the last one sent
+pub fn next_digest(
+    config: &DigestConfig,
+    last: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    config.schedule().next_run(last, now)
+}
+
+/// Send a digest to every digest hook whenever one is due. The delivery log
+/// remembers the last one across restarts. Returns at once without hooks.
+pub async fn run_digest_loop(
+    config: DigestConfig,
+    sender: Arc<WebhookSender>,
+    open_store: StoreOpener,
+    health: Component,
+) {
+    if config.hooks.is_empty() {
+        return health.disable("Set [webhooks.digest] hooks to send digests");
+    }
+    health.ok();
+    let schedule = config.schedule();
+    let mut ticker = Ticker::new(DIGEST_CHECK_INTERVAL);
+    loop {
+        if let Tick::Jumped(by) = ticker.tick().await {
+            log::info!(
+                "[webhooks] Clock jumped {}s, checking for a missed digest",
+                by.num_seconds()
+            );
+        }
+        crate::power::monitor().settled().await;
+        let last = match sender.log().recent(None, Some("digest"), 1) {
+            Ok(last) => last.first().map(|d| d.at),
+            Err(e) => {
+                health.error(format!("{:#}", e));
+                continue;
+            }
+        };
+        for at in schedule.due(last, Utc::now()) {
+            // A failed digest waits for the next interval rather than
+            // retrying every check
+            match send_digest(&config, &sender, &open_store, at).await {
+                Ok(()) => health.ok(),
+                Err(e) => health.error(e),
+            }
+        }
+    }
+}
+
+/// Send the digest as of `at` to every digest hook
+async fn send_digest(
+    config: &DigestConfig,
+    sender: &WebhookSender,
+    open_store: &StoreOpener,
+    at: DateTime<Utc>,
+) -> Result<(), String> {
+    let digest = match build_digest(open_store, config, at).await {
+        Ok(digest) => WebhookEvent::Digest(digest),
+        Err(e) => {
+            log::warn!("[webhooks] Building the digest failed: {:#}", e);
+            return Err(format!("{:#}", e));
+        }
+    };
+    let mut failed = Vec::new();
+    for hook in &config.hooks {
+        match sender.send(hook, &digest).await {
+            Ok(delivery) if delivery.delivered => {}
+            Ok(delivery) => {
+                failed.push(format!("{}: {}", hook, delivery.error.unwrap_or_default()))
+            }
+            Err(e) => failed.push(format!("{}: {:#}", hook, e)),
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        log::warn!("[webhooks] Digest not delivered to {}", failed.join("; "));
+        Err(failed.join("; "))
+    }
+}
+
+/// Where hook secrets are kept
+pub mod secrets {
+    use anyhow::{Context, Result};
+
+    fn account(hook: &str) -> String {
+        format!("webhook-secret-{}", hook)
+    }
+
+    #[cfg(any(target_os = "macos", windows))]
+    pub fn load(hook: &str) -> Result<Option<String>> {
+        let entry = keyring::Entry::new(&crate::profile::keychain_service(), &account(hook))?;
+        match entry.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => {
+                Err(e).with_context(|| format!("Failed to read the secret of webhook {}", hook))
+            }
+        }
+    }
+
+    #[cfg(any(target_os = "macos", windows))]
+    pub fn save(hook: &str, secret: &str) -> Result<()> {
+        keyring::Entry::new(&crate::profile::keychain_service(), &account(hook))?
+            .set_password(secret)
+            .with_context(|| format!("Failed to store the secret of webhook {}", hook))
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn path() -> std::path::PathBuf {
+        crate::config::config_dir().join("webhook-secrets.json")
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn read_all() -> Result<std::collections::BTreeMap<String, String>> {
+        let path = path();
+        match std::fs::read_to_string(&path) {
+            Ok(stored) => {
+                serde_json::from_str(&stored).context("Stored webhook secrets are corrupt")
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    pub fn load(hook: &str) -> Result<Option<String>> {
+        Ok(read_all()?.remove(&account(hook)))
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    pub fn save(hook: &str, secret: &str) -> Result<()> {
+        let mut all = read_all()?;
+        all.insert(account(hook), secret.to_string());
+        crate::server::write_private(&path(), serde_json::to_string_pretty(&all)?.as_bytes())
+    }
+}
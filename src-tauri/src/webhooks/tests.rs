@@ -0,0 +1,191 @@
+use super::*;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+
+fn alert() -> WebhookEvent {
+    WebhookEvent::Alert(Alert {
+        rule: "slow \"builds\"".to_string(),
+        fired_at: "2026-10-16T12:00:00Z".parse().unwrap(),
+        summary: "cargo build took 700.0s".to_string(),
+        command_id: Some("c1".to_string()),
+        session_id: None,
+        fingerprint: None,
+    })
+}
+
+fn hook(name: &str, url: &str) -> Webhook {
+    Webhook {
+        name: name.to_string(),
+        ..Webhook::url(url)
+    }
+}
+
+#[test]
+fn payloads_follow_the_format_or_template() {
+    let event = alert();
+    let mut slack = hook("ops", "https://hooks.example.com/a");
+    slack.format = WebhookFormat::Slack;
+    assert_eq!(
+        payload(&slack, &event).unwrap(),
+        serde_json::json!({ "text": "[slow \"builds\"] cargo build took 700.0s" })
+    );
+
+    let json = payload(&hook("raw", "https://hooks.example.com/b"), &event).unwrap();
+    assert_eq!(json["event"], "alert");
+    assert_eq!(json["command_id"], "c1");
+
+    // Values are escaped into the string literals they sit in
+    let mut templated = hook("pager", "https://hooks.example.com/c");
+    templated.template = Some(r#"{"title": "{{rule}}", "session": "{{ session_id }}"}"#.into());
+    assert_eq!(
+        payload(&templated, &event).unwrap(),
+        serde_json::json!({ "title": "slow \"builds\"", "session": "" })
+    );
+
+    let digest = WebhookEvent::Digest(Digest {
+        from: "2026-10-15".parse().unwrap(),
+        to: "2026-10-16".parse().unwrap(),
+        total_ms: 90_000,
+        highlights: vec!["You spent 1m 0s waiting on `cargo`".to_string()],
+    });
+    assert_eq!(
+        digest.text(),
+        "Runebook digest 2026-10-15 to 2026-10-16: 1m 30s of commands\n• You spent 1m 0s waiting on `cargo`"
+    );
+}
+
+#[test]
+fn invalid_hooks_fail_the_whole_set() {
+    let dir = tempfile::tempdir().unwrap();
+    let sender = |config: WebhookConfig| {
+        WebhookSender::new(&config, DeliveryLog::new(dir.path().join("d.jsonl"))).map(|_| ())
+    };
+    let valid = hook("ops", "https://hooks.example.com/a");
+    assert!(sender(WebhookConfig {
+        hooks: vec![valid.clone()],
+        digest: DigestConfig {
+            hooks: vec!["ops".to_string()],
+            ..Default::default()
+        },
+    })
+    .is_ok());
+
+    let mut unknown_field = valid.clone();
+    unknown_field.template = Some(r#"{"x": "{{nope}}"}"#.into());
+    let mut not_json = valid.clone();
+    not_json.template = Some("{{text}}".into());
+    let mut bad_header = valid.clone();
+    bad_header.secret_header = Some("Bad Header".into());
+    for invalid in [
+        vec![unknown_field],
+        vec![not_json],
+        vec![bad_header],
+        vec![hook("ftp", "ftp://example.com")],
+        vec![valid.clone(), valid.clone()],
+    ] {
+        assert!(sender(WebhookConfig {
+            hooks: invalid,
+            ..Default::default()
+        })
+        .is_err());
+    }
+    assert!(sender(WebhookConfig {
+        hooks: vec![valid],
+        digest: DigestConfig {
+            hooks: vec!["missing".to_string()],
+            ..Default::default()
+        },
+    })
+    .is_err());
+}
+
+type Received = Arc<Mutex<Vec<(Option<String>, serde_json::Value)>>>;
+
+/// A receiver answering with `statuses` in turn, then 200
+async fn receiver(statuses: Vec<u16>) -> (String, Received) {
+    let received: Received = Arc::default();
+    let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+    let state = (Arc::clone(&received), statuses);
+    let app = axum::Router::new()
+        .route(
+            "/",
+            axum::routing::post(
+                |State((received, statuses)): State<(
+                    Received,
+                    Arc<Mutex<std::vec::IntoIter<u16>>>,
+                )>,
+                 headers: HeaderMap,
+                 axum::Json(body): axum::Json<serde_json::Value>| async move {
+                    let token = headers
+                        .get("x-token")
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string);
+                    received.lock().unwrap().push((token, body));
+                    let status = statuses.lock().unwrap().next().unwrap_or(200);
+                    StatusCode::from_u16(status).unwrap()
+                },
+            ),
+        )
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (url, received)
+}
+
+#[tokio::test]
+async fn deliveries_are_retried_and_logged() {
+    let dir = tempfile::tempdir().unwrap();
+    let (url, received) = receiver(vec![503, 429]).await;
+    let mut secured = hook("secured", &url);
+    secured.secret_header = Some("X-Token".to_string());
+    let config = WebhookConfig {
+        hooks: vec![secured, hook("plain", &url)],
+        ..Default::default()
+    };
+    let sender = WebhookSender::new(&config, DeliveryLog::new(dir.path().join("d.jsonl")))
+        .unwrap()
+        .with_backoff(Duration::from_millis(1))
+        .with_secrets(Arc::new(|hook: &str| {
+            Ok((hook == "secured").then(|| "s3cret".to_string()))
+        }));
+
+    let delivery = sender.send("secured", &alert()).await.unwrap();
+    assert!(delivery.delivered);
+    assert_eq!((delivery.attempts, delivery.status), (3, Some(200)));
+    {
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].0.as_deref(), Some("s3cret"));
+        assert_eq!(received[0].1["rule"], "slow \"builds\"");
+    }
+
+    // A client error is not retried
+    let (url, _) = receiver(vec![400]).await;
+    let failed = sender.send_to_url(&url, &alert()).await.unwrap();
+    assert!(!failed.delivered);
+    assert_eq!(failed.attempts, 1);
+    assert_eq!(failed.error.as_deref(), Some("HTTP 400 Bad Request"));
+    assert!(sender.send("missing", &alert()).await.is_err());
+
+    let log = sender.log();
+    let recent = log.recent(None, None, 10).unwrap();
+    assert_eq!(recent.len(), 2);
+    assert_eq!(recent[0].hook, url);
+    assert_eq!(
+        log.recent(Some("secured"), Some("alert"), 10)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(log.recent(None, Some("digest"), 10).unwrap().is_empty());
+
+    // The digest is due right away, then once per interval
+    let config = DigestConfig::default();
+    let now = Utc::now();
+    assert_eq!(next_digest(&config, None, now), now);
+    assert_eq!(
+        next_digest(&config, Some(now), now),
+        now + chrono::Duration::hours(24)
+    );
+}
@@ -726,6 +726,48 @@ async function showLastAnalysis(): Promise<void> {
   observer.stop();
 }
 
+/**
+ * Re-analyze past failures with the given analyzers
+ */
+async function backfillAnalysis(analyzers: string[], hours: number): Promise<void> {
+  const obsConfig = loadObserverConfig();
+
+  if (!obsConfig.enabled) {
+    console.log('Observer is not enabled. Run: runebook observer enable');
+    return;
+  }
+
+  const { createEventStore } = await import('../lib/core/storage');
+  const store = createEventStore(obsConfig);
+
+  const analysisService = getAnalysisService();
+  analysisService.initialize(store, obsConfig);
+
+  const result = await analysisService.analysisBackfill(
+    { since: Date.now() - hours * 60 * 60 * 1000 },
+    analyzers,
+    {
+      onProgress: progress => {
+        process.stdout.write(
+          `\rAnalyzed ${progress.processed}/${progress.total} failures, ${progress.suggestions} suggestions`
+        );
+      },
+    }
+  );
+
+  console.log(`\n\n=== Backfill Results (${result.jobIds.length} failures) ===\n`);
+  for (const jobId of result.jobIds) {
+    const job = analysisService.getJob(jobId);
+    if (!job || job.suggestions.length === 0) {
+      continue;
+    }
+    console.log(`${new Date(job.events[0]?.timestamp ?? job.timestamp).toLocaleString()}  ${job.command} ${job.args.join(' ')}`);
+    for (const suggestion of job.suggestions) {
+      console.log(`  [${suggestion.provenance.analyzer}] ${suggestion.title} (confidence: ${(suggestion.confidence * 100).toFixed(0)}%)`);
+    }
+  }
+}
+
 /**
  * Main CLI handler
  */
@@ -746,7 +788,7 @@ Commands:
   --tui [file]        Launch terminal UI (TUI) mode — optionally load a canvas YAML
   agent <command>     Agent commands (enable, disable, status, suggestions, events, clear, config)
   observer <command>  Observer commands (enable, disable, status, events, tail)
-  analyze <command>   Analysis commands (last)
+  analyze <command>   Analysis commands (last, backfill)
   memory <command>    Memory commands (inspect)
   llm <command>       LLM/MCP commands (status)
 
@@ -962,6 +1004,9 @@ Usage:
 
 Commands:
   last                Analyze the last command failure
+  backfill <analyzers> [hours]
+                      Re-analyze failures of the last [hours] (default: 168)
+                      with comma-separated analyzers
           `);
           process.exit(0);
         }
@@ -970,6 +1015,15 @@ Commands:
           case 'last':
             await showLastAnalysis();
             break;
+
+          case 'backfill':
+            if (!args[2]) {
+              console.error('Usage: runebook analyze backfill <analyzers> [hours]');
+              process.exit(1);
+            }
+            const hours = args[3] ? parseFloat(args[3]) : 168;
+            await backfillAnalysis(args[2].split(','), hours);
+            break;
             
           default:
             console.error(`Unknown analyze command: ${subcommand}`);
@@ -411,5 +411,116 @@ describe('Analysis Pipeline', () => {
       expect(suggestion.actionableSnippet).toContain('GITHUB_TOKEN');
     });
   });
+
+  describe('Analyzer Isolation', () => {
+    it('should time out a hanging analyzer and disable it after repeated failures', async () => {
+      queue.setAnalyzerLimits({ timeoutMs: 50, maxFailures: 2 });
+      queue.registerAnalyzer({
+        name: 'hangs',
+        layer: 1,
+        analyze: () => new Promise(() => {}),
+      });
+
+      for (const commandId of ['cmd_hang_1', 'cmd_hang_2', 'cmd_hang_3']) {
+        const events: TerminalObserverEvent[] = [
+          {
+            id: commandId,
+            type: 'command_start',
+            timestamp: Date.now(),
+            sessionId: 'session_1',
+            shellType: 'bash',
+            command: 'nix',
+            args: ['build'],
+            cwd: '/tmp',
+            envSummary: {},
+          },
+          {
+            id: `${commandId}_exit`,
+            type: 'exit_status',
+            timestamp: Date.now() + 100,
+            sessionId: 'session_1',
+            shellType: 'bash',
+            commandId,
+            exitCode: 1,
+            success: false,
+          },
+        ];
+        const jobId = await queue.enqueueFailure(commandId, events, store);
+        await new Promise(resolve => setTimeout(resolve, 200));
+        expect(queue.getJob(jobId!)!.status).toBe('completed');
+      }
+
+      const health = queue.getAnalyzerHealth()['hangs'];
+      expect(health.timeouts).toBe(2);
+      expect(health.runs).toBe(2);
+      expect(health.disabled).toBe(true);
+
+      expect(queue.resetAnalyzer('hangs')).toBe(true);
+      expect(queue.getAnalyzerHealth()['hangs']).toBeUndefined();
+    });
+  });
+
+  describe('Backfill', () => {
+    it('should replay past failures through the named analyzers only', async () => {
+      queue.registerAnalyzer({
+        name: 'new-analyzer',
+        layer: 1,
+        analyze: async context => [
+          {
+            id: `suggestion_${context.command}`,
+            type: 'tip',
+            priority: 'low',
+            title: `Backfilled ${context.command}`,
+            description: context.stderr,
+            confidence: 0.5,
+            provenance: { analyzer: 'new-analyzer', layer: 1, timestamp: Date.now() },
+            timestamp: Date.now(),
+          },
+        ],
+      });
+
+      const now = Date.now();
+      for (const [commandId, success, at] of [
+        ['cmd_old', false, now - 10_000],
+        ['cmd_ok', true, now - 5_000],
+        ['cmd_new', false, now - 1_000],
+      ] as const) {
+        await store.saveEvent({
+          id: commandId,
+          type: 'command_start',
+          timestamp: at,
+          sessionId: 'session_1',
+          shellType: 'bash',
+          command: commandId,
+          args: [],
+          cwd: '/tmp',
+          envSummary: {},
+        });
+        await store.saveEvent({
+          id: `${commandId}_exit`,
+          type: 'exit_status',
+          timestamp: at + 100,
+          sessionId: 'session_1',
+          shellType: 'bash',
+          commandId,
+          exitCode: success ? 0 : 1,
+          success,
+        });
+      }
+
+      const progress: number[] = [];
+      const result = await queue.backfill(store, { since: now - 60_000 }, ['new-analyzer'], {
+        maxJobsPerSecond: 100,
+        onProgress: p => progress.push(p.processed),
+      });
+
+      expect(result.total).toBe(2);
+      expect(progress).toEqual([1, 2]);
+      const titles = result.jobIds.flatMap(id => queue.getJob(id)!.suggestions.map(s => s.title));
+      expect(titles).toEqual(['Backfilled cmd_old', 'Backfilled cmd_new']);
+
+      await expect(queue.backfill(store, {}, ['missing'])).rejects.toThrow('Unknown analyzers');
+    });
+  });
 });
 
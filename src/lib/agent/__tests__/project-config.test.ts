@@ -0,0 +1,91 @@
+// Tests for agent/project-config.ts
+
+import { describe, it, expect } from 'vitest';
+import { parseProjectConfig, ProjectConfigError } from '../project-config';
+import { createHeuristicAnalyzers, createLocalSearchAnalyzer, StderrPatternAnalyzer } from '../analyzers';
+import type { AnalysisContext } from '../analysis-pipeline';
+import type { EventStore } from '../../core/types';
+
+const analyzers = [...createHeuristicAnalyzers(), createLocalSearchAnalyzer()];
+
+const problemsOf = (text: string): string[] => {
+  try {
+    parseProjectConfig('/repo/.runebook.toml', text, analyzers);
+  } catch (error) {
+    expect(error).toBeInstanceOf(ProjectConfigError);
+    return (error as ProjectConfigError).problems;
+  }
+  return [];
+};
+
+describe('parseProjectConfig', () => {
+  it('reads pipeline order, severities and analyzer options', () => {
+    const config = parseProjectConfig(
+      '/repo/.runebook.toml',
+      `
+[analysis]
+pipeline = ["stderr-patterns", "syntax-error", "local-search"]
+
+[analysis.severity]
+syntax-error = "low"
+
+[[analyzers.stderr-patterns.patterns]]
+match = "Error acquiring the state lock"
+title = "Terraform state is locked"
+command = "terraform force-unlock"
+`,
+      analyzers
+    );
+    expect(config.pipeline).toEqual(['stderr-patterns', 'syntax-error', 'local-search']);
+    expect(config.severity).toEqual({ 'syntax-error': 'low' });
+    expect(config.options['stderr-patterns'].patterns).toHaveLength(1);
+  });
+
+  it('reports every problem at once', () => {
+    const problems = problemsOf(`
+[analysis]
+pipeline = ["nix-error", "cargo-error", "nix-error"]
+order = []
+
+[analysis.severity]
+git-auth = "urgent"
+
+[[analyzers.stderr-patterns.patterns]]
+match = "unclosed ("
+`);
+    expect(problems).toEqual([
+      '[analysis]: unknown key "order" (expected: pipeline, severity)',
+      expect.stringContaining('analysis.pipeline: unknown analyzer "cargo-error"'),
+      'analysis.pipeline: "nix-error" is listed more than once',
+      'analysis.severity.git-auth: "urgent" is not one of low, medium, high',
+      expect.stringContaining('[analyzers.stderr-patterns]: patterns[0].match is not a valid regular expression'),
+      '[analyzers.stderr-patterns]: patterns[0].title is required',
+    ]);
+  });
+
+  it('reports TOML syntax errors', () => {
+    expect(problemsOf('[analysis\npipeline = [')).toHaveLength(1);
+  });
+});
+
+describe('StderrPatternAnalyzer', () => {
+  it('suggests the configured fix when stderr matches', async () => {
+    const context: AnalysisContext = {
+      command: 'terraform',
+      args: ['apply'],
+      cwd: '/repo',
+      env: {},
+      exitCode: 1,
+      stdout: '',
+      stderr: 'Error: Error acquiring the state lock',
+      previousCommands: [],
+      options: {
+        patterns: [{ match: 'state lock', title: 'Terraform state is locked', priority: 'high' }],
+      },
+    };
+    const suggestions = await new StderrPatternAnalyzer().analyze(context, {} as EventStore);
+    expect(suggestions).toHaveLength(1);
+    expect(suggestions[0].title).toBe('Terraform state is locked');
+    expect(suggestions[0].priority).toBe('high');
+  });
+});
@@ -1,8 +1,10 @@
 // Analysis Pipeline - Background job system for failure analysis
 // Runs analyzers in layers: heuristic → local search → optional LLM/MCP
 
-import type { TerminalObserverEvent, EventStore } from '../core/types';
+import type { TerminalObserverEvent, EventStore, ExitStatusEvent } from '../core/types';
 import type { Suggestion } from '../types/agent';
+import { loadProjectConfig, type ProjectAnalysisConfig } from './project-config';
+import { endSpan, recordSpan, startSpan, type Span } from '../utils/telemetry';
 
 /**
  * Extended suggestion with confidence, actionable snippet, and provenance
@@ -35,6 +37,7 @@ export interface AnalysisJob {
   status: 'pending' | 'running' | 'completed' | 'cancelled' | 'failed';
   suggestions: AnalysisSuggestion[];
   error?: string;
+  backfill?: boolean; // Re-analysis of a past failure
 }
 
 /**
@@ -55,6 +58,8 @@ export interface AnalysisContext {
     timestamp: number;
   }>;
   repoFiles?: string[]; // Relevant files in the repo
+  options?: Record<string, unknown>; // The analyzer's `[analyzers.<name>]` table from .runebook.toml
+  signal?: AbortSignal; // Aborted when the analyzer runs out of time
 }
 
 /**
@@ -64,6 +69,60 @@ export interface Analyzer {
   name: string;
   layer: number; // 1, 2, or 3
   analyze(context: AnalysisContext, store: EventStore): Promise<AnalysisSuggestion[]>;
+  validateOptions?(options: Record<string, unknown>): string[]; // Problems with a project's options
+}
+
+/**
+ * Failure record of one analyzer
+ */
+export interface AnalyzerHealth {
+  runs: number;
+  failures: number; // Throws and timeouts
+  timeouts: number;
+  consecutiveFailures: number;
+  disabled: boolean; // Set after `maxFailures` consecutive failures
+  lastError?: string;
+}
+
+/**
+ * Limits each analyzer runs under
+ */
+export interface AnalyzerLimits {
+  timeoutMs: number;
+  maxFailures: number; // Consecutive failures before the analyzer is disabled
+}
+
+/**
+ * Time range of a backfill, in epoch milliseconds; open ends are unbounded
+ */
+export interface BackfillRange {
+  since?: number;
+  until?: number;
+}
+
+export interface BackfillProgress {
+  total: number; // Failures in the range
+  processed: number;
+  suggestions: number; // Generated so far
+  commandId?: string; // Failure just analyzed
+}
+
+export interface BackfillOptions {
+  maxJobsPerSecond?: number; // Caps store reads; defaults to 2
+  onProgress?: (progress: BackfillProgress) => void;
+  signal?: AbortSignal; // Stops the backfill after the current failure
+}
+
+export interface BackfillResult extends BackfillProgress {
+  jobIds: string[];
+  cancelled: boolean;
+}
+
+class AnalyzerTimeoutError extends Error {
+  constructor(name: string, timeoutMs: number) {
+    super(`Analyzer ${name} timed out after ${timeoutMs}ms`);
+    this.name = 'AnalyzerTimeoutError';
+  }
 }
 
 /**
@@ -76,6 +135,8 @@ export class AnalysisJobQueue {
   private store: EventStore | null = null;
   private maxConcurrentJobs = 1;
   private enableLLM = false; // Gate for LLM/MCP calls
+  private limits: AnalyzerLimits = { timeoutMs: 10_000, maxFailures: 3 };
+  private health: Map<string, AnalyzerHealth> = new Map();
 
   constructor(store: EventStore | null = null) {
     this.store = store;
@@ -97,6 +158,29 @@ export class AnalysisJobQueue {
     this.enableLLM = enabled;
   }
 
+  /**
+   * Set the per-analyzer timeout and failure limit
+   */
+  setAnalyzerLimits(limits: Partial<AnalyzerLimits>): void {
+    this.limits = { ...this.limits, ...limits };
+  }
+
+  /**
+   * Failure records of the analyzers that have run
+   */
+  getAnalyzerHealth(): Record<string, AnalyzerHealth> {
+    return Object.fromEntries(
+      Array.from(this.health.entries()).map(([name, health]) => [name, { ...health }])
+    );
+  }
+
+  /**
+   * Re-enable an analyzer disabled after repeated failures
+   */
+  resetAnalyzer(name: string): boolean {
+    return this.health.delete(name);
+  }
+
   /**
    * Detect failure and enqueue analysis job
    */
@@ -105,6 +189,89 @@ export class AnalysisJobQueue {
     events: TerminalObserverEvent[],
     store: EventStore
   ): Promise<string | null> {
+    const job = await this.buildJob(commandId, events, store);
+    if (!job) {
+      return null;
+    }
+
+    this.jobs.set(job.id, job);
+    this.processQueue(store);
+
+    return job.id;
+  }
+
+  /**
+   * Replay past failures in `range` through `analyzers` only, e.g. ones
+   * added since those failures were first analyzed. Failures are analyzed
+   * one at a time, oldest first, at most `maxJobsPerSecond` per second.
+   */
+  async backfill(
+    store: EventStore,
+    range: BackfillRange,
+    analyzers: string[],
+    options: BackfillOptions = {}
+  ): Promise<BackfillResult> {
+    const known = this.analyzers.map(a => a.name);
+    const unknown = analyzers.filter(name => !known.includes(name));
+    if (unknown.length > 0) {
+      throw new Error(`Unknown analyzers: ${unknown.join(', ')} (known: ${known.join(', ')})`);
+    }
+
+    const failures = (await store.getEvents('exit_status', range.since))
+      .filter((e): e is ExitStatusEvent => e.type === 'exit_status' && !e.success)
+      .filter(e => range.until === undefined || e.timestamp <= range.until)
+      .sort((a, b) => a.timestamp - b.timestamp);
+    const interval = 1000 / Math.max(options.maxJobsPerSecond ?? 2, 0.001);
+    const result: BackfillResult = {
+      total: failures.length,
+      processed: 0,
+      suggestions: 0,
+      jobIds: [],
+      cancelled: false,
+    };
+
+    for (const failure of failures) {
+      if (options.signal?.aborted) {
+        result.cancelled = true;
+        break;
+      }
+      const started = Date.now();
+      const commandId = failure.commandId;
+      const job = await this.buildJob(commandId, await store.getEventsByCommand(commandId), store);
+      if (job) {
+        job.backfill = true;
+        job.status = 'running';
+        this.jobs.set(job.id, job);
+        this.running.add(job.id);
+        await this.runAnalysis(job, store, analyzers);
+        result.jobIds.push(job.id);
+        result.suggestions += job.suggestions.length;
+      }
+      result.processed++;
+      options.onProgress?.({
+        total: result.total,
+        processed: result.processed,
+        suggestions: result.suggestions,
+        commandId,
+      });
+
+      const wait = interval - (Date.now() - started);
+      if (wait > 0 && result.processed < result.total) {
+        await new Promise(resolve => setTimeout(resolve, wait));
+      }
+    }
+
+    return result;
+  }
+
+  /**
+   * Build an analysis job from a failed command's events
+   */
+  private async buildJob(
+    commandId: string,
+    events: TerminalObserverEvent[],
+    store: EventStore
+  ): Promise<AnalysisJob | null> {
     // Find command_start, exit_status, and stderr events
     const commandStart = events.find(e => e.type === 'command_start' && e.id === commandId);
     const exitStatus = events.find(e => e.type === 'exit_status' && e.commandId === commandId);
@@ -177,10 +344,7 @@ export class AnalysisJobQueue {
       suggestions: [],
     };
 
-    this.jobs.set(job.id, job);
-    this.processQueue(store);
-
-    return job.id;
+    return job;
   }
 
   /**
@@ -208,9 +372,14 @@ export class AnalysisJobQueue {
   }
 
   /**
-   * Run analysis on a job (runs analyzers in layers)
+   * Run analysis on a job (runs analyzers in layers), optionally only the
+   * named analyzers
    */
-  private async runAnalysis(job: AnalysisJob, store: EventStore): Promise<void> {
+  private async runAnalysis(job: AnalysisJob, store: EventStore, only?: string[]): Promise<void> {
+    // Traced as an `analysis` span with an `analyzer` child per analyzer run
+    const span = startSpan('analysis');
+    span.attributes['analysis.command'] = job.command;
+    span.attributes['analysis.exit_code'] = job.exitCode;
     try {
       // Build analysis context
       const context: AnalysisContext = {
@@ -224,53 +393,44 @@ export class AnalysisJobQueue {
         previousCommands: await this.getPreviousCommands(job, store),
       };
 
-      // Run analyzers by layer
+      const project = loadProjectConfig(job.cwd, this.analyzers);
       const suggestions: AnalysisSuggestion[] = [];
 
-      // Layer 1: Heuristic classifiers
-      const layer1Analyzers = this.analyzers.filter(a => a.layer === 1);
-      for (const analyzer of layer1Analyzers) {
-        try {
-          const analyzerSuggestions = await analyzer.analyze(context, store);
-          suggestions.push(...analyzerSuggestions);
-        } catch (error) {
-          console.error(`Analyzer ${analyzer.name} failed:`, error);
+      // Layers gate each other: local search only runs without a confident
+      // heuristic answer, and the LLM layer only when enabled
+      for (const analyzer of this.pipeline(project)) {
+        if (only && !only.includes(analyzer.name)) {
+          continue;
         }
-      }
-
-      // If we have high-confidence suggestions from layer 1, we might skip layer 2
-      const highConfidence = suggestions.filter(s => s.confidence >= 0.8);
-      if (highConfidence.length === 0) {
-        // Layer 2: Local search
-        const layer2Analyzers = this.analyzers.filter(a => a.layer === 2);
-        for (const analyzer of layer2Analyzers) {
-          try {
-            const analyzerSuggestions = await analyzer.analyze(context, store);
-            suggestions.push(...analyzerSuggestions);
-          } catch (error) {
-            console.error(`Analyzer ${analyzer.name} failed:`, error);
-          }
+        if (analyzer.layer === 2 && suggestions.some(s => s.confidence >= 0.8)) {
+          continue;
         }
-      }
-
-      // Layer 3: Optional LLM/MCP (gated)
-      if (this.enableLLM) {
-        const layer3Analyzers = this.analyzers.filter(a => a.layer === 3);
-        for (const analyzer of layer3Analyzers) {
-          try {
-            const analyzerSuggestions = await analyzer.analyze(context, store);
-            suggestions.push(...analyzerSuggestions);
-          } catch (error) {
-            console.error(`Analyzer ${analyzer.name} failed:`, error);
-          }
+        if (analyzer.layer === 3 && !this.enableLLM) {
+          continue;
+        }
+        if (this.health.get(analyzer.name)?.disabled) {
+          continue;
+        }
+        const analyzerSuggestions = await this.runAnalyzer(
+          analyzer,
+          { ...context, options: project?.options[analyzer.name] },
+          store,
+          span
+        );
+        const severity = project?.severity[analyzer.name];
+        for (const suggestion of analyzerSuggestions) {
+          suggestions.push(severity ? { ...suggestion, priority: severity } : suggestion);
         }
       }
 
       job.suggestions = suggestions;
       job.status = 'completed';
+      span.attributes['analysis.suggestions'] = suggestions.length;
+      void recordSpan(endSpan(span));
     } catch (error) {
       job.status = 'failed';
       job.error = String(error);
+      void recordSpan(endSpan(span, job.error));
     } finally {
       this.running.delete(job.id);
       // Process next job
@@ -278,6 +438,83 @@ export class AnalysisJobQueue {
     }
   }
 
+  /**
+   * Run one analyzer under the timeout. A throw or timeout only loses that
+   * analyzer's suggestions; repeated ones disable it.
+   */
+  private async runAnalyzer(
+    analyzer: Analyzer,
+    context: AnalysisContext,
+    store: EventStore,
+    parent?: Span
+  ): Promise<AnalysisSuggestion[]> {
+    const span = startSpan('analyzer', parent);
+    span.attributes['analyzer.name'] = analyzer.name;
+    span.attributes['analyzer.layer'] = analyzer.layer;
+    const health = this.health.get(analyzer.name) ?? {
+      runs: 0,
+      failures: 0,
+      timeouts: 0,
+      consecutiveFailures: 0,
+      disabled: false,
+    };
+    this.health.set(analyzer.name, health);
+    health.runs++;
+
+    const controller = new AbortController();
+    let timer: ReturnType<typeof setTimeout> | undefined;
+    const timeout = new Promise<never>((_, reject) => {
+      timer = setTimeout(() => {
+        controller.abort();
+        reject(new AnalyzerTimeoutError(analyzer.name, this.limits.timeoutMs));
+      }, this.limits.timeoutMs);
+    });
+    try {
+      // `Promise.resolve().then` turns a synchronous throw into a rejection
+      const result = await Promise.race([
+        Promise.resolve().then(() =>
+          analyzer.analyze({ ...context, signal: controller.signal }, store)
+        ),
+        timeout,
+      ]);
+      health.consecutiveFailures = 0;
+      span.attributes['analyzer.suggestions'] = result.length;
+      void recordSpan(endSpan(span));
+      return result;
+    } catch (error) {
+      void recordSpan(endSpan(span, String(error)));
+      health.failures++;
+      health.consecutiveFailures++;
+      health.lastError = String(error);
+      if (error instanceof AnalyzerTimeoutError) {
+        health.timeouts++;
+      }
+      if (health.consecutiveFailures >= this.limits.maxFailures) {
+        health.disabled = true;
+        console.error(
+          `Analyzer ${analyzer.name} disabled after ${health.consecutiveFailures} consecutive failures`
+        );
+      }
+      console.error(`Analyzer ${analyzer.name} failed:`, error);
+      return [];
+    } finally {
+      clearTimeout(timer);
+    }
+  }
+
+  /**
+   * Analyzers to run for a project, in order. Without a pipeline in its
+   * .runebook.toml, every registered analyzer runs by layer.
+   */
+  private pipeline(project: ProjectAnalysisConfig | null): Analyzer[] {
+    if (!project?.pipeline) {
+      return this.analyzers;
+    }
+    return project.pipeline
+      .map(name => this.analyzers.find(a => a.name === name))
+      .filter((a): a is Analyzer => a !== undefined);
+  }
+
   /**
    * Get previous commands for context
    */
@@ -2,6 +2,7 @@
 // Monitors observer events and triggers analysis jobs
 
 import { AnalysisJobQueue } from './analysis-pipeline';
+import type { BackfillRange, BackfillOptions, BackfillResult } from './analysis-pipeline';
 import { createHeuristicAnalyzers, createLocalSearchAnalyzer, createLLMAnalyzer } from './analyzers';
 import type { TerminalObserverEvent, EventStore } from '../core/types';
 import type { ObserverConfig } from '../core/types';
@@ -105,6 +106,20 @@ export class AnalysisService {
     return await this.queue.enqueueFailure(event.commandId, commandEvents, this.store);
   }
 
+  /**
+   * Replay past failures in `range` through the named analyzers
+   */
+  async analysisBackfill(
+    range: BackfillRange,
+    analyzers: string[],
+    options?: BackfillOptions
+  ): Promise<BackfillResult> {
+    if (!this.store) {
+      throw new Error('Analysis service is not initialized');
+    }
+    return this.queue.backfill(this.store, range, analyzers, options);
+  }
+
   /**
    * Get the last analysis job
    */
@@ -126,6 +141,20 @@ export class AnalysisService {
     return this.queue.getAllJobs();
   }
 
+  /**
+   * Get per-analyzer failure records
+   */
+  getAnalyzerHealth() {
+    return this.queue.getAnalyzerHealth();
+  }
+
+  /**
+   * Re-enable an analyzer disabled after repeated failures
+   */
+  resetAnalyzer(name: string): boolean {
+    return this.queue.resetAnalyzer(name);
+  }
+
   /**
    * Cancel a job
    */
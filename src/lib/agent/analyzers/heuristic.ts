@@ -3,6 +3,7 @@
 
 import type { Analyzer, AnalysisContext, AnalysisSuggestion } from '../analysis-pipeline';
 import type { EventStore } from '../../core/types';
+import { StderrPatternAnalyzer } from './patterns';
 
 /**
  * Heuristic analyzer for common Nix errors
@@ -284,6 +285,7 @@ export function createHeuristicAnalyzers(): Analyzer[] {
     new NixErrorAnalyzer(),
     new GitAuthAnalyzer(),
     new SyntaxErrorAnalyzer(),
+    new StderrPatternAnalyzer(),
   ];
 }
 
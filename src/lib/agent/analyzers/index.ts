@@ -1,6 +1,7 @@
 // Analyzer exports
 
 export * from './heuristic';
+export * from './patterns';
 export * from './local-search';
 export * from './llm';
 export type { Analyzer, AnalysisContext, AnalysisSuggestion, AnalysisJob } from '../analysis-pipeline';
@@ -0,0 +1,101 @@
+// Layer 1: Stderr Pattern Analyzer
+// Matches project-defined stderr patterns from .runebook.toml
+
+import type { Analyzer, AnalysisContext, AnalysisSuggestion } from '../analysis-pipeline';
+import type { EventStore } from '../../core/types';
+import type { Suggestion } from '../../types/agent';
+
+const TYPES: Suggestion['type'][] = ['command', 'optimization', 'shortcut', 'warning', 'tip'];
+const PRIORITIES: Suggestion['priority'][] = ['low', 'medium', 'high'];
+
+/**
+ * One `[[analyzers.stderr-patterns.patterns]]` entry
+ */
+export interface StderrPattern {
+  match: string; // Regular expression, matched case-insensitively
+  title: string;
+  description?: string;
+  command?: string;
+  type?: Suggestion['type'];
+  priority?: Suggestion['priority'];
+  confidence?: number;
+}
+
+/**
+ * Suggests fixes for errors a project knows about, e.g. a Terraform state lock
+ */
+export class StderrPatternAnalyzer implements Analyzer {
+  name = 'stderr-patterns';
+  layer = 1;
+
+  validateOptions(options: Record<string, unknown>): string[] {
+    const problems: string[] = [];
+    const patterns = options.patterns ?? [];
+    if (!Array.isArray(patterns)) {
+      return ['patterns must be a list of tables'];
+    }
+    patterns.forEach((pattern, i) => {
+      const where = `patterns[${i}]`;
+      if (typeof pattern !== 'object' || pattern === null) {
+        problems.push(`${where} must be a table`);
+        return;
+      }
+      const p = pattern as Record<string, unknown>;
+      if (typeof p.match !== 'string') {
+        problems.push(`${where}.match is required`);
+      } else {
+        try {
+          new RegExp(p.match, 'i');
+        } catch (error) {
+          problems.push(`${where}.match is not a valid regular expression: ${(error as Error).message}`);
+        }
+      }
+      if (typeof p.title !== 'string') {
+        problems.push(`${where}.title is required`);
+      }
+      if (p.type !== undefined && !TYPES.includes(p.type as Suggestion['type'])) {
+        problems.push(`${where}.type must be one of ${TYPES.join(', ')}`);
+      }
+      if (p.priority !== undefined && !PRIORITIES.includes(p.priority as Suggestion['priority'])) {
+        problems.push(`${where}.priority must be one of ${PRIORITIES.join(', ')}`);
+      }
+      if (
+        p.confidence !== undefined &&
+        (typeof p.confidence !== 'number' || p.confidence < 0 || p.confidence > 1)
+      ) {
+        problems.push(`${where}.confidence must be a number between 0 and 1`);
+      }
+    });
+    return problems;
+  }
+
+  async analyze(context: AnalysisContext, _store: EventStore): Promise<AnalysisSuggestion[]> {
+    const patterns = (context.options?.patterns ?? []) as StderrPattern[];
+    const suggestions: AnalysisSuggestion[] = [];
+
+    patterns.forEach((pattern, i) => {
+      const match = context.stderr.match(new RegExp(pattern.match, 'i'));
+      if (!match) {
+        return;
+      }
+      suggestions.push({
+        id: `suggestion_${Date.now()}_stderr_pattern_${i}`,
+        type: pattern.type ?? (pattern.command ? 'command' : 'warning'),
+        priority: pattern.priority ?? 'medium',
+        title: pattern.title,
+        description: pattern.description ?? match[0],
+        command: pattern.command,
+        confidence: pattern.confidence ?? 0.8,
+        actionableSnippet: pattern.command,
+        provenance: {
+          analyzer: this.name,
+          layer: this.layer,
+          timestamp: Date.now(),
+        },
+        timestamp: Date.now(),
+      });
+    });
+
+    return suggestions;
+  }
+}
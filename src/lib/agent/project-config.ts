@@ -0,0 +1,189 @@
+// Project Analysis Config - Per-project analyzer pipelines from .runebook.toml
+// Picks which analyzers run and in what order, hands them their options, and overrides severities
+
+import { existsSync, readFileSync, statSync } from 'fs';
+import { dirname, join } from 'path';
+import { parse } from 'smol-toml';
+import type { Analyzer } from './analysis-pipeline';
+import type { Suggestion } from '../types/agent';
+
+export const PROJECT_CONFIG_FILE = '.runebook.toml';
+
+const SEVERITIES: Suggestion['priority'][] = ['low', 'medium', 'high'];
+const ANALYSIS_KEYS = ['pipeline', 'severity'];
+
+/**
+ * Analysis settings of one project
+ *
+ * ```toml
+ * [analysis]
+ * pipeline = ["stderr-patterns", "syntax-error", "local-search"]
+ *
+ * [analysis.severity]
+ * syntax-error = "low"
+ *
+ * [[analyzers.stderr-patterns.patterns]]
+ * match = "Error acquiring the state lock"
+ * title = "Terraform state is locked"
+ * ```
+ */
+export interface ProjectAnalysisConfig {
+  path: string;
+  pipeline?: string[]; // Analyzers to run, in order; all registered ones when unset
+  severity: Record<string, Suggestion['priority']>; // Priority override per analyzer
+  options: Record<string, Record<string, unknown>>; // `[analyzers.<name>]` tables
+}
+
+/**
+ * A `.runebook.toml` that cannot be used, with every problem found in it
+ */
+export class ProjectConfigError extends Error {
+  constructor(
+    public readonly path: string,
+    public readonly problems: string[]
+  ) {
+    super(`Invalid ${path}:\n${problems.map(p => `  - ${p}`).join('\n')}`);
+    this.name = 'ProjectConfigError';
+  }
+}
+
+function isTable(value: unknown): value is Record<string, unknown> {
+  return typeof value === 'object' && value !== null && !Array.isArray(value);
+}
+
+/**
+ * Nearest `.runebook.toml` at or above `cwd`
+ */
+export function findProjectConfig(cwd: string): string | null {
+  let dir = cwd;
+  for (;;) {
+    const candidate = join(dir, PROJECT_CONFIG_FILE);
+    if (existsSync(candidate)) {
+      return candidate;
+    }
+    const parent = dirname(dir);
+    if (parent === dir) {
+      return null;
+    }
+    dir = parent;
+  }
+}
+
+/**
+ * Parse and validate a `.runebook.toml` against the registered analyzers
+ */
+export function parseProjectConfig(
+  path: string,
+  text: string,
+  analyzers: Analyzer[]
+): ProjectAnalysisConfig {
+  let doc: Record<string, unknown>;
+  try {
+    doc = parse(text) as Record<string, unknown>;
+  } catch (error) {
+    throw new ProjectConfigError(path, [error instanceof Error ? error.message : String(error)]);
+  }
+
+  const problems: string[] = [];
+  const known = new Map(analyzers.map(a => [a.name, a]));
+  const knownNames = Array.from(known.keys()).join(', ');
+  const checkName = (name: string, where: string): boolean => {
+    if (!known.has(name)) {
+      problems.push(`${where}: unknown analyzer "${name}" (known: ${knownNames})`);
+      return false;
+    }
+    return true;
+  };
+
+  const config: ProjectAnalysisConfig = { path, severity: {}, options: {} };
+  const analysis = doc.analysis ?? {};
+  if (!isTable(analysis)) {
+    problems.push('[analysis] must be a table');
+  } else {
+    for (const key of Object.keys(analysis)) {
+      if (!ANALYSIS_KEYS.includes(key)) {
+        problems.push(`[analysis]: unknown key "${key}" (expected: ${ANALYSIS_KEYS.join(', ')})`);
+      }
+    }
+
+    const pipeline = analysis.pipeline;
+    if (pipeline !== undefined) {
+      if (!Array.isArray(pipeline) || pipeline.some(n => typeof n !== 'string')) {
+        problems.push('analysis.pipeline must be a list of analyzer names');
+      } else {
+        const seen = new Set<string>();
+        for (const name of pipeline as string[]) {
+          if (seen.has(name)) {
+            problems.push(`analysis.pipeline: "${name}" is listed more than once`);
+          }
+          seen.add(name);
+          checkName(name, 'analysis.pipeline');
+        }
+        config.pipeline = pipeline as string[];
+      }
+    }
+
+    const severity = analysis.severity ?? {};
+    if (!isTable(severity)) {
+      problems.push('[analysis.severity] must be a table');
+    } else {
+      for (const [name, value] of Object.entries(severity)) {
+        if (!checkName(name, 'analysis.severity')) {
+          continue;
+        }
+        if (!SEVERITIES.includes(value as Suggestion['priority'])) {
+          problems.push(
+            `analysis.severity.${name}: "${String(value)}" is not one of ${SEVERITIES.join(', ')}`
+          );
+          continue;
+        }
+        config.severity[name] = value as Suggestion['priority'];
+      }
+    }
+  }
+
+  const options = doc.analyzers ?? {};
+  if (!isTable(options)) {
+    problems.push('[analyzers] must be a table');
+  } else {
+    for (const [name, table] of Object.entries(options)) {
+      if (!checkName(name, '[analyzers]')) {
+        continue;
+      }
+      if (!isTable(table)) {
+        problems.push(`[analyzers.${name}] must be a table`);
+        continue;
+      }
+      const analyzer = known.get(name)!;
+      const errors = analyzer.validateOptions?.(table) ?? [];
+      problems.push(...errors.map(e => `[analyzers.${name}]: ${e}`));
+      config.options[name] = table;
+    }
+  }
+
+  if (problems.length > 0) {
+    throw new ProjectConfigError(path, problems);
+  }
+  return config;
+}
+
+const cache = new Map<string, { mtimeMs: number; config: ProjectAnalysisConfig }>();
+
+/**
+ * Analysis config of the project `cwd` is in, or null when it has none.
+ * Re-read only when the file changes; throws `ProjectConfigError` when invalid.
+ */
+export function loadProjectConfig(cwd: string, analyzers: Analyzer[]): ProjectAnalysisConfig | null {
+  const path = findProjectConfig(cwd);
+  if (!path) {
+    return null;
+  }
+  const { mtimeMs } = statSync(path);
+  const cached = cache.get(path);
+  if (cached && cached.mtimeMs === mtimeMs) {
+    return cached.config;
+  }
+  const config = parseProjectConfig(path, readFileSync(path, 'utf-8'), analyzers);
+  cache.set(path, { mtimeMs, config });
+  return config;
+}
@@ -164,6 +164,14 @@ const BUILTIN_COMPONENTS: Array<Omit<ComponentCapability, 'lifecycle'>> = [
       { id: 'output', direction: 'output' },
     ],
   },
+  {
+    type: 'archive',
+    label: 'Archive',
+    ports: [
+      { id: 'paths', direction: 'input' },
+      { id: 'summary', direction: 'output' },
+    ],
+  },
   {
     type: 'sub-canvas',
     label: 'Sub-Canvas',
@@ -0,0 +1,86 @@
+// Backend state shared by every RuneBook window
+// The backend broadcasts session, job, and suggestion changes to all windows; these stores mirror them
+
+import { writable } from 'svelte/store';
+import type { Topic } from '../bindings/Topic';
+import type { FocusStatus } from '../bindings/FocusStatus';
+import type { JobInfo } from '../bindings/JobInfo';
+import type { SharedState } from '../bindings/SharedState';
+
+export type { FocusStatus, JobInfo };
+
+interface JobsChanged {
+  running: JobInfo[];
+  finished: { job: JobInfo; success: boolean } | null;
+}
+
+export interface Suggestion {
+  id: string;
+  title: string;
+  description: string;
+  command: string | null;
+  priority: string;
+}
+
+/** Suggestions held back while the user was focused, released together */
+export interface FocusSummary {
+  started_at: string;
+  ended_at: string;
+  commands: number;
+  failures: number;
+  suggestions: Suggestion[];
+}
+
+/** Topics a window can limit itself to (see `window_subscribe`) */
+export type { Topic };
+
+export const currentSession = writable<string | null>(null);
+export const runningJobs = writable<JobInfo[]>([]);
+export const newSuggestions = writable<Suggestion[]>([]);
+/** Set while suggestions are held back for focused work */
+export const focusMode = writable<FocusStatus | null>(null);
+/** The latest batch released when focus ended */
+export const focusSummary = writable<FocusSummary | null>(null);
+
+/**
+ * Catch up with the backend state and follow its changes.
+ * Pass `topics` / `sessionIds` to receive only part of the broadcast.
+ */
+export async function initWindowSync(topics: Topic[] = [], sessionIds: string[] = []): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const { listen } = await import('@tauri-apps/api/event');
+
+  await invoke('window_subscribe', { filter: { topics, session_ids: sessionIds } });
+  await listen<{ session_id: string | null }>('session-changed', event =>
+    currentSession.set(event.payload.session_id),
+  );
+  await listen<JobsChanged>('jobs-changed', event => runningJobs.set(event.payload.running));
+  await listen<Suggestion>('suggestion-added', event =>
+    newSuggestions.update(list => [event.payload, ...list]),
+  );
+  await listen<FocusStatus>('focus-changed', event =>
+    focusMode.set(event.payload.focused ? event.payload : null),
+  );
+  await listen<FocusSummary>('suggestions-resumed', event => {
+    focusSummary.set(event.payload);
+    newSuggestions.update(list => [...event.payload.suggestions, ...list]);
+  });
+
+  const state = await invoke<SharedState>('window_state');
+  currentSession.set(state.current_session);
+  runningJobs.set(state.running_jobs);
+  const focus = await invoke<FocusStatus>('focus_status');
+  focusMode.set(focus.focused ? focus : null);
+}
+
+/** Make `sessionId` the current session in every window */
+export async function setCurrentSession(sessionId: string | null): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('session_set_current', { sessionId });
+}
+
+/** Open another RuneBook window */
+export async function openWindow(): Promise<string> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string>('open_window');
+}
@@ -1,5 +1,7 @@
 // Canvas node types for RuneBook
-export type NodeType = 'text' | 'terminal' | 'input' | 'display' | 'transform' | 'sub-canvas';
+import type { ArchiveFormat } from '../bindings/ArchiveFormat';
+
+export type NodeType = 'text' | 'terminal' | 'input' | 'display' | 'transform' | 'sub-canvas' | 'archive';
 
 export interface Position {
   x: number;
@@ -48,9 +50,36 @@ export interface TerminalNode extends BaseNode {
   args?: string[];
   env?: Record<string, string>;
   cwd?: string;
+  /** `pwsh` runs the command line as PowerShell and passes on its objects */
+  mode?: 'direct' | 'pwsh';
+  /**
+   * Escape sequences in the output: kept (the default), stripped, or parsed
+   * into `spans`, which passes `{ text, spans }` on for display nodes to color
+   */
+  ansi?: 'keep' | 'strip' | 'spans';
+  /**
+   * How the command is started: `cmd` or `powershell` go through that Windows
+   * shell; `auto` (the default) picks `cmd` on Windows, `posix` elsewhere
+   */
+  backend?: 'auto' | 'posix' | 'cmd' | 'powershell';
+  /** Where the command runs: this machine (the default) or a WSL distro */
+  target?: { kind: 'local' } | { kind: 'wsl'; distro: string };
+  /** What the result must satisfy for the node to pass and nodes below it to run */
+  expect?: Assertion[];
   autoStart?: boolean;
 }
 
+/** An expectation on a terminal node's result, checked in Rust after it runs */
+export type Assertion =
+  | { kind: 'exit_code'; code: number }
+  | { kind: 'stdout_matches'; pattern: string }
+  | { kind: 'stderr_matches'; pattern: string }
+  /** `path` is JSONPath; without `equals` it only has to select something */
+  | { kind: 'stdout_json'; path: string; equals?: unknown }
+  | { kind: 'max_duration'; ms: number }
+  /** Relative paths are taken from the node's `cwd` */
+  | { kind: 'file_exists'; path: string };
+
 export interface InputNode extends BaseNode {
   type: 'input';
   inputType: 'text' | 'number' | 'checkbox' | 'slider';
@@ -77,7 +106,28 @@ export interface SubCanvasNode extends BaseNode {
   children: Canvas;
 }
 
-export type CanvasNode = TextNode | TerminalNode | InputNode | DisplayNode | TransformNode | SubCanvasNode;
+/** Packs `sources` into `archive`, or unpacks `archive` into `destination` */
+export interface ArchiveNode extends BaseNode {
+  type: 'archive';
+  operation: 'create' | 'extract';
+  /** Files and directories `create` packs */
+  sources: string[];
+  /** The archive `create` writes or `extract` reads */
+  archive: string;
+  /** Directory `extract` unpacks into */
+  destination: string;
+  /** Taken from the extension of `archive` when left out */
+  format?: ArchiveFormat;
+}
+
+export type CanvasNode =
+  | TextNode
+  | TerminalNode
+  | InputNode
+  | DisplayNode
+  | TransformNode
+  | SubCanvasNode
+  | ArchiveNode;
 
 export interface Canvas {
   id: string;
@@ -0,0 +1,48 @@
+// Alert rules over what memory records
+// Rules are kept in the `[alerts]` config section; fired alerts arrive as `alert-fired` events
+
+export type AlertCondition =
+  | { kind: 'error_fingerprint'; fingerprint: string }
+  | { kind: 'duration'; min_ms: number; command?: string | null }
+  | { kind: 'output_contains'; pattern: string; command?: string | null };
+
+export type AlertAction =
+  | { kind: 'notify' }
+  | { kind: 'run_canvas'; path: string; variables?: Record<string, string> }
+  | { kind: 'webhook'; url: string }
+  | { kind: 'hook'; name: string };
+
+export interface AlertRule {
+  name: string;
+  enabled?: boolean;
+  when: AlertCondition;
+  actions: AlertAction[];
+  /** At most `max` alerts in any `per_secs` seconds */
+  rate_limit?: { max: number; per_secs: number };
+}
+
+export interface Alert {
+  rule: string;
+  fired_at: string;
+  summary: string;
+  command_id: string | null;
+  session_id: string | null;
+  fingerprint: string | null;
+}
+
+export async function listAlertRules(): Promise<AlertRule[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<AlertRule[]>('alert_rules_list');
+}
+
+/** Replace every rule; nothing changes if any of them is invalid */
+export async function saveAlertRules(rules: AlertRule[]): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('alert_rules_save', { rules });
+}
+
+/** Call `onAlert` for every alert that fires; returns a function that stops listening */
+export async function onAlert(onAlert: (alert: Alert) => void): Promise<() => void> {
+  const { listen } = await import('@tauri-apps/api/event');
+  return listen<Alert>('alert-fired', ({ payload }) => onAlert(payload));
+}
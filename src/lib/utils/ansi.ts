@@ -0,0 +1,81 @@
+// Colored command output, as parsed by the backend with `ansi: 'spans'`
+// Spans carry the text and its style; these helpers turn them into CSS
+
+import type { AnsiColor } from '../bindings/AnsiColor';
+import type { StyledSpan } from '../bindings/StyledSpan';
+
+export type { AnsiMode } from '../bindings/AnsiMode';
+export type { AnsiColor, StyledSpan };
+
+/** Output text with its colors; what a terminal node run with `ansi: 'spans'` passes on */
+export interface StyledOutput {
+  text: string;
+  spans: StyledSpan[];
+}
+
+export function isStyledOutput(value: unknown): value is StyledOutput {
+  return (
+    typeof value === 'object' &&
+    value !== null &&
+    typeof (value as StyledOutput).text === 'string' &&
+    Array.isArray((value as StyledOutput).spans)
+  );
+}
+
+/** The 16 named colors, with fallbacks for themes that do not set `--ansi-*` */
+const NAMED: [string, string][] = [
+  ['black', '#000000'],
+  ['red', '#cd3131'],
+  ['green', '#0dbc79'],
+  ['yellow', '#e5e510'],
+  ['blue', '#2472c8'],
+  ['magenta', '#bc3fbc'],
+  ['cyan', '#11a8cd'],
+  ['white', '#e5e5e5'],
+  ['bright-black', '#666666'],
+  ['bright-red', '#f14c4c'],
+  ['bright-green', '#23d18b'],
+  ['bright-yellow', '#f5f543'],
+  ['bright-blue', '#3b8eea'],
+  ['bright-magenta', '#d670d6'],
+  ['bright-cyan', '#29b8db'],
+  ['bright-white', '#ffffff']
+];
+
+/** xterm's 256-color palette above the 16 named colors */
+function indexedRgb(index: number): [number, number, number] {
+  if (index >= 232) {
+    const level = 8 + (index - 232) * 10;
+    return [level, level, level];
+  }
+  const cube = index - 16;
+  const step = (n: number) => (n === 0 ? 0 : 55 + n * 40);
+  return [step(Math.floor(cube / 36)), step(Math.floor(cube / 6) % 6), step(cube % 6)];
+}
+
+/** CSS color of `color`; the 16 named colors follow the theme's `--ansi-*` variables */
+export function cssColor(color: AnsiColor): string {
+  if ('rgb' in color) return `rgb(${color.rgb.join(', ')})`;
+  if (color.indexed < 16) {
+    const [name, fallback] = NAMED[color.indexed];
+    return `var(--ansi-${name}, ${fallback})`;
+  }
+  return `rgb(${indexedRgb(color.indexed).join(', ')})`;
+}
+
+/** Inline style for a span */
+export function spanStyle(span: StyledSpan): string {
+  let [fg, bg] = [span.fg, span.bg];
+  if (span.inverse) [fg, bg] = [bg, fg];
+  const rules: string[] = [];
+  // Inverse text without colors swaps the theme's text and surface colors
+  if (fg) rules.push(`color: ${cssColor(fg)}`);
+  else if (span.inverse) rules.push('color: var(--surface-1)');
+  if (bg) rules.push(`background-color: ${cssColor(bg)}`);
+  else if (span.inverse) rules.push('background-color: var(--text-1)');
+  if (span.bold) rules.push('font-weight: 600');
+  if (span.dim) rules.push('opacity: 0.7');
+  if (span.italic) rules.push('font-style: italic');
+  if (span.underline) rules.push('text-decoration: underline');
+  return rules.join('; ');
+}
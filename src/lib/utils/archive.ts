@@ -0,0 +1,48 @@
+// Packing and unpacking tar, zip, gzip and zstd archives in the backend
+// Progress arrives as `archive-progress` events tagged with the operation's id
+
+import type { ArchiveFormat } from '../bindings/ArchiveFormat';
+import type { ArchiveProgress } from '../bindings/ArchiveProgress';
+import type { ArchiveSummary } from '../bindings/ArchiveSummary';
+
+export type { ArchiveFormat, ArchiveProgress, ArchiveSummary };
+
+type ArchiveProgressEvent = ArchiveProgress & { operation_id: string };
+
+async function withProgress(
+  command: string,
+  args: Record<string, unknown>,
+  onProgress?: (progress: ArchiveProgress) => void
+): Promise<ArchiveSummary> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const { listen } = await import('@tauri-apps/api/event');
+  const operationId = crypto.randomUUID();
+  const unlisten = await listen<ArchiveProgressEvent>('archive-progress', event => {
+    if (event.payload.operation_id === operationId) onProgress?.(event.payload);
+  });
+  try {
+    return await invoke<ArchiveSummary>(command, { ...args, operationId });
+  } finally {
+    unlisten();
+  }
+}
+
+/** Pack `sources` into `destination`; the format comes from its extension unless given */
+export function createArchive(
+  sources: string[],
+  destination: string,
+  format?: ArchiveFormat,
+  onProgress?: (progress: ArchiveProgress) => void
+): Promise<ArchiveSummary> {
+  return withProgress('archive_create', { sources, destination, format: format ?? null }, onProgress);
+}
+
+/** Unpack `archivePath` into the `destination` directory; entries may not land outside it */
+export function extractArchive(
+  archivePath: string,
+  destination: string,
+  format?: ArchiveFormat,
+  onProgress?: (progress: ArchiveProgress) => void
+): Promise<ArchiveSummary> {
+  return withProgress('archive_extract', { archivePath, destination, format: format ?? null }, onProgress);
+}
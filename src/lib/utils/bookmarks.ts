@@ -0,0 +1,33 @@
+// Bookmarks of command runs and output line ranges
+// A bookmark's id is its permalink; its excerpt stays readable after the output is collected
+
+import type { Bookmark } from '../bindings/Bookmark';
+import type { LineRange } from '../bindings/LineRange';
+import type { ResolvedBookmark } from '../bindings/ResolvedBookmark';
+
+export type { Bookmark, LineRange, ResolvedBookmark };
+
+export async function addBookmark(
+  commandId: string,
+  lines?: LineRange,
+  note?: string
+): Promise<Bookmark> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Bookmark>('bookmark_add', { commandId, lines, note });
+}
+
+/** The bookmark behind a permalink; `command` is null once it is deleted */
+export async function resolveBookmark(id: string): Promise<ResolvedBookmark> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ResolvedBookmark>('resolve_bookmark', { id });
+}
+
+export async function listBookmarks(sessionId?: string): Promise<Bookmark[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Bookmark[]>('bookmark_list', { sessionId });
+}
+
+export async function deleteBookmark(id: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('bookmark_delete', { id });
+}
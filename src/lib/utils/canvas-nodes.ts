@@ -1,4 +1,4 @@
-import type { Port, TextNode, TerminalNode, InputNode, DisplayNode, TransformNode, SubCanvasNode } from '../types/canvas';
+import type { Port, TextNode, TerminalNode, InputNode, DisplayNode, TransformNode, SubCanvasNode, ArchiveNode } from '../types/canvas';
 
 /**
  * Resolve the index of a port by its ID within a port array.
@@ -41,7 +41,7 @@ export function createTerminalNode({ id, x, y, label = 'Terminal' }: CreateNodeO
     command: 'echo',
     args: ['Hello, RuneBook!'],
     autoStart: false,
-    inputs: [],
+    inputs: [{ id: 'stdin', name: 'stdin', type: 'input' }],
     outputs: [{ id: 'stdout', name: 'stdout', type: 'output' }],
   };
 }
@@ -91,6 +91,23 @@ export function createTransformNode({ id, x, y, label = 'Transform' }: CreateNod
   };
 }
 
+/** Factory that creates a fully-initialised ArchiveNode that packs into a `.tar.gz`. */
+export function createArchiveNode({ id, x, y, label = 'Archive' }: CreateNodeOptions): ArchiveNode {
+  return {
+    id,
+    type: 'archive',
+    position: { x, y },
+    size: { width: 320, height: 240 },
+    label,
+    operation: 'create',
+    sources: [],
+    archive: 'archive.tar.gz',
+    destination: '',
+    inputs: [{ id: 'paths', name: 'paths', type: 'input' }],
+    outputs: [{ id: 'summary', name: 'summary', type: 'output' }],
+  };
+}
+
 export interface CreateSubCanvasNodeOptions {
   id: string;
   x: number;
@@ -0,0 +1,107 @@
+// Stored command output for the UI
+// One code path for running and finished commands: tail what is stored, then follow live chunks
+
+import type { StyledSpan } from './ansi';
+import type { OutputPage } from '../bindings/OutputPage';
+import type { Signal } from '../bindings/Signal';
+import type { TailEvent } from '../bindings/TailEvent';
+
+export type { OutputPage, Signal, TailEvent };
+
+/**
+ * Send a command's output to `onEvent`, chunk by chunk. With `follow`, keeps
+ * going until the command finishes; resolves after the `end` event.
+ */
+export async function tailOutput(
+  commandId: string,
+  follow: boolean,
+  onEvent: (event: TailEvent) => void
+): Promise<void> {
+  const { invoke, Channel } = await import('@tauri-apps/api/core');
+  const channel = new Channel<TailEvent>();
+  channel.onmessage = onEvent;
+  await invoke('tail_output', { commandId, follow, onEvent: channel });
+}
+
+/** Lines `offsetLines..offsetLines + limitLines` of a command's stored output */
+export async function readOutputPage(
+  commandId: string,
+  offsetLines: number,
+  limitLines: number
+): Promise<OutputPage> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<OutputPage>('read_output_page', { commandId, offsetLines, limitLines });
+}
+
+/** One `command-output` event from `execute_terminal_command_streaming` */
+export interface CommandOutputEvent {
+  run_id: string;
+  chunk_index: number;
+  stream_type: 'stdout' | 'stderr';
+  text: string;
+  /** Set when `text` is base64: the chunk was binary or not UTF-8 */
+  encoding?: 'base64';
+  /** Colors of `text`, for a run with `ansi: 'spans'` */
+  spans?: StyledSpan[];
+}
+
+/** The bytes of a `command-output` chunk, whether sent as text or base64 */
+export function chunkBytes(event: CommandOutputEvent): Uint8Array {
+  if (event.encoding !== 'base64') return new TextEncoder().encode(event.text);
+  return Uint8Array.from(atob(event.text), c => c.charCodeAt(0));
+}
+
+/** The `command-objects` event of a PowerShell run */
+export interface CommandObjectsEvent {
+  run_id: string;
+  objects: unknown[];
+}
+
+/**
+ * Run a command like `execute_terminal_command`, passing its output to
+ * `onOutput` while it runs. Resolves with the full stdout once every chunk
+ * was delivered; a PowerShell run (`mode: 'pwsh'`) has passed its objects to
+ * `onObjects` by then. `cancelCommand(runId)` stops it.
+ */
+export async function executeStreaming(
+  request: Record<string, unknown>,
+  onOutput: (event: CommandOutputEvent) => void,
+  runId: string = crypto.randomUUID(),
+  onObjects?: (objects: unknown[]) => void
+): Promise<string> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const { listen } = await import('@tauri-apps/api/event');
+  const unlisten = await listen<CommandOutputEvent>('command-output', event => {
+    if (event.payload.run_id === runId) onOutput(event.payload);
+  });
+  const unlistenObjects = await listen<CommandObjectsEvent>('command-objects', event => {
+    if (event.payload.run_id === runId) onObjects?.(event.payload.objects);
+  });
+  try {
+    return await invoke<string>('execute_terminal_command_streaming', { ...request, runId });
+  } finally {
+    unlisten();
+    unlistenObjects();
+  }
+}
+
+/** Objects stored in a command's `objects` stream, e.g. from a PowerShell hook */
+export async function readCommandObjects(commandId: string): Promise<unknown[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<unknown[]>('command_objects', { commandId });
+}
+
+/** Kill a running command; its invoke fails with `command-cancelled` */
+export async function cancelCommand(executionId: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('cancel_command', { executionId });
+}
+
+/**
+ * Deliver `signal` to a running job and everything it started. `jobId` is a
+ * command's execution id or a PTY session's id.
+ */
+export async function sendSignal(jobId: string, signal: Signal): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('send_signal', { jobId, signal });
+}
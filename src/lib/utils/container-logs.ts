@@ -0,0 +1,64 @@
+// Container and pod logs followed into memory
+// The log is a running command: tail it with tailOutput, hear about errors from `log-event`s
+
+import type { LogFollowInfo } from '../bindings/LogFollowInfo';
+
+export type { LogFollowInfo };
+
+export type LogSource =
+  | { kind: 'docker'; container: string }
+  | { kind: 'kubernetes'; pod: string; namespace?: string; container?: string; context?: string };
+
+export type LogFollowSpec = LogSource & {
+  /** Lines of history to start with; all of it when unset */
+  tail?: number;
+  /** Only lines newer than this, e.g. `10m` */
+  since?: string;
+  node_id?: string;
+};
+
+export type LogEvent = { follow_id: string; node_id: string | null; command_id: string } & (
+  | { event: 'error'; severity: string; message: string }
+  | { event: 'ended'; exit_code: number | null; stopped: boolean }
+);
+
+/**
+ * Follow a log and send its events to `onEvent`. Returns the followed stream
+ * and a function that stops listening (not following; see stopLogs).
+ */
+export async function followLogs(
+  spec: LogFollowSpec,
+  onEvent: (event: LogEvent) => void
+): Promise<{ info: LogFollowInfo; unlisten: () => void }> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const { listen } = await import('@tauri-apps/api/event');
+
+  // Events can arrive before the follow id is known; hold them until then
+  let followId: string | null = null;
+  let held: LogEvent[] = [];
+  const unlisten = await listen<LogEvent>('log-event', ({ payload }) => {
+    if (followId === null) held.push(payload);
+    else if (payload.follow_id === followId) onEvent(payload);
+  });
+
+  try {
+    const info = await invoke<LogFollowInfo>('logs_follow', { spec });
+    followId = info.id;
+    held.filter(e => e.follow_id === info.id).forEach(onEvent);
+    held = [];
+    return { info, unlisten };
+  } catch (e) {
+    unlisten();
+    throw e;
+  }
+}
+
+export async function stopLogs(id: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('logs_stop', { id });
+}
+
+export async function listFollowedLogs(): Promise<LogFollowInfo[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<LogFollowInfo[]>('logs_list');
+}
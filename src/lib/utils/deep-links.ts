@@ -0,0 +1,44 @@
+// runebook:// link handling
+// Links are validated in Rust; this only asks the user before acting on one
+
+import { describeError, isBackendMessage } from './messages';
+import type { DeepLinkPrompt } from '../bindings/DeepLinkPrompt';
+
+/** Ask before running the snippet; destructive snippets get the usual second confirmation. */
+async function runSnippet(prompt: DeepLinkPrompt, name: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const commandLine = prompt.preview?.command_line ?? '';
+  if (!confirm(`A link wants to run the snippet "${name}":\n\n${commandLine}\n\nRun it?`)) return;
+  try {
+    await invoke('deep_link_run_snippet', { url: prompt.url });
+  } catch (e) {
+    if (!isBackendMessage(e) || e.code !== 'confirmation-required' || !confirm(e.message)) throw e;
+    await invoke('deep_link_run_snippet', { url: prompt.url, confirmationToken: e.params.token });
+  }
+}
+
+export async function handleDeepLink(url: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  try {
+    const prompt = await invoke<DeepLinkPrompt>('deep_link_prepare', { url });
+    const link = prompt.link;
+    switch (link.action) {
+      case 'run_snippet':
+        await runSnippet(prompt, link.name);
+        break;
+      case 'import_card': {
+        const { title, description, command } = link.card;
+        const details = [description, command && `Command (not run): ${command}`].filter(Boolean).join('\n\n');
+        if (confirm(`Import the shared card "${title}"?\n\n${details}`)) {
+          await invoke('deep_link_import_card', { url: prompt.url });
+        }
+        break;
+      }
+      case 'open_session':
+        window.dispatchEvent(new CustomEvent('runebook:open-session', { detail: link.session_id }));
+        break;
+    }
+  } catch (e) {
+    alert(describeError(e));
+  }
+}
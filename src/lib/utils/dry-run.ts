@@ -0,0 +1,17 @@
+// Dry runs: exactly what a command would execute, without starting anything
+// Used by the canvas preview button and to check suggested commands before they run
+
+import type { DryRun } from '../bindings/DryRun';
+import type { ExecutionRequest } from '../bindings/ExecutionRequest';
+
+export type { DryRun };
+export type { PolicyPreview } from '../bindings/PolicyPreview';
+
+/** A request as sent: the backend fills in what is left out */
+export type DryRunRequest = Pick<ExecutionRequest, 'command'> & Partial<ExecutionRequest>;
+
+/** Resolve and check a command as execution would, without running it */
+export async function dryRun(spec: DryRunRequest): Promise<DryRun> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<DryRun>('execute_dry_run', { spec });
+}
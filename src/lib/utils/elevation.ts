@@ -0,0 +1,28 @@
+// Running commands as administrator
+// sudo asks for its password in a dialog window the backend opens; on Windows the UAC prompt is used instead
+
+import type { ElevatedRun } from '../bindings/ElevatedRun';
+import type { ExecutionRequest } from '../bindings/ExecutionRequest';
+import { isBackendMessage } from './messages';
+
+export type { ElevatedRun };
+
+/** A request as sent: the backend fills in what is left out */
+export type ElevationRequest = Pick<ExecutionRequest, 'command'> & Partial<ExecutionRequest>;
+
+/** Whether a failed run was refused for lack of rights and may work elevated */
+export function needsElevation(error: unknown): boolean {
+  return isBackendMessage(error) && error.code === 'command-needs-elevation';
+}
+
+/** Run a command as administrator, prompting for the password when sudo wants one */
+export async function runElevated(
+  request: ElevationRequest,
+  confirmationToken?: string
+): Promise<ElevatedRun> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ElevatedRun>('execute_elevated', {
+    request,
+    confirmationToken: confirmationToken ?? null,
+  });
+}
@@ -0,0 +1,21 @@
+// The registry of backend events: names, topics and the version of each payload's shape
+// Lets a window check that the events it listens to still carry the payloads it was written against
+
+import type { EventSchema } from '../bindings/EventSchema';
+
+export type { EventSchema };
+export type { Topic } from '../bindings/Topic';
+
+/** Every event the backend dispatches, with example payloads of its current version */
+export async function listEventSchemas(): Promise<EventSchema[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<EventSchema[]>('list_event_schemas');
+}
+
+/** Events whose payload version is not the one given in `expected`, by name */
+export async function changedEvents(expected: Record<string, number>): Promise<string[]> {
+  const schemas = await listEventSchemas();
+  return Object.entries(expected)
+    .filter(([name, version]) => schemas.find((s) => s.name === name)?.version !== version)
+    .map(([name]) => name);
+}
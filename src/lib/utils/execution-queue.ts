@@ -0,0 +1,13 @@
+// The execution queue: commands holding a concurrency slot and those waiting for one
+
+import type { QueueSnapshot } from '../bindings/QueueSnapshot';
+
+export type { QueueOrder } from '../bindings/QueueOrder';
+export type { QueuedExecution } from '../bindings/QueuedExecution';
+export type { QueueSnapshot };
+
+/** Commands running and waiting under the `[concurrency]` limit */
+export async function getExecutionQueue(): Promise<QueueSnapshot> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<QueueSnapshot>('execution_queue');
+}
@@ -0,0 +1,29 @@
+// Session handoff between machines
+// A bundle is one encrypted line of text, carried over any channel and imported with the same passphrase
+
+import type { HandoffSummary } from '../bindings/HandoffSummary';
+
+export type { HandoffSummary };
+export type { WorkspaceState } from '../bindings/WorkspaceState';
+
+/** Pack a session's recent context into a bundle encrypted with `passphrase` */
+export async function exportHandoff(sessionId: string, passphrase: string): Promise<string> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string>('handoff_export', { sessionId, passphrase });
+}
+
+/** Store the session a bundle carries */
+export async function importHandoff(bundle: string, passphrase: string): Promise<HandoffSummary> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<HandoffSummary>('handoff_import', { bundle, passphrase });
+}
+
+/** Whether this machine's checkout differs from the one the session left */
+export function workspaceMoved(summary: HandoffSummary): boolean {
+  const here = summary.workspace_here;
+  return (
+    here === null ||
+    here.branch !== summary.workspace.branch ||
+    here.commit !== summary.workspace.commit
+  );
+}
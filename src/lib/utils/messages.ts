@@ -0,0 +1,39 @@
+// User-facing messages returned by the Rust backend
+// Errors arrive as { code, params, message }; map codes to UI copy here
+
+import type { UserMessage as BackendMessage } from '../bindings/UserMessage';
+
+export type { BackendMessage };
+
+type Template = (params: Record<string, string>) => string;
+
+/**
+ * UI copy per message code. Codes missing here fall back to the
+ * backend-rendered `message`.
+ */
+const COPY: Record<string, Template> = {
+  'terminal-not-found': () => 'This terminal has already closed.',
+  'memory-store-unavailable': () => 'Memory is unavailable — is PluresDB running?',
+  'capture-unsupported': () => 'Clipboard and screenshot capture are not available in this build.',
+  'archive-format-unknown': (p) => `Could not tell the archive type of ${p.path}.`,
+  'capability-denied': (p) => `This action needs the ${p.capability} permission, which is turned off on this machine.`,
+  'invalid-argument': (p) => `The value for ${p.argument} was rejected: ${p.error}`,
+};
+
+export function isBackendMessage(value: unknown): value is BackendMessage {
+  return (
+    typeof value === 'object' &&
+    value !== null &&
+    typeof (value as BackendMessage).code === 'string' &&
+    typeof (value as BackendMessage).message === 'string'
+  );
+}
+
+/** Turn anything thrown by `invoke` into display text */
+export function describeError(error: unknown): string {
+  if (isBackendMessage(error)) {
+    const template = COPY[error.code];
+    return template ? template(error.params ?? {}) : error.message;
+  }
+  return String(error);
+}
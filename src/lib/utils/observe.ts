@@ -0,0 +1,53 @@
+// Read-only sharing of live sessions
+// The sharing side mints a token per session; observers follow its feed over the HTTP API
+
+import type { SessionEvent } from '../bindings/SessionEvent';
+import type { Share } from '../bindings/Share';
+import type { ShareGrant } from '../bindings/ShareGrant';
+
+export type { SessionEvent, Share, ShareGrant };
+export type { ObservedCommand } from '../bindings/ObservedCommand';
+
+/** Share a session read-only; needs the HTTP API enabled */
+export async function shareSession(sessionId: string): Promise<ShareGrant> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ShareGrant>('share_session_start', { sessionId });
+}
+
+/** Stop sharing a session, ending its observations; returns how many shares it had */
+export async function stopSharing(sessionId: string): Promise<number> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<number>('share_session_stop', { sessionId });
+}
+
+export async function revokeShare(shareId: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('share_revoke', { shareId });
+}
+
+export async function listShares(): Promise<Share[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Share[]>('share_list');
+}
+
+/**
+ * Follow a session another RuneBook shares, e.g. at `http://10.0.0.5:7717`.
+ * Resolves when the share ends or `stopObserving(observationId)` is called.
+ */
+export async function observeSession(
+  observationId: string,
+  baseUrl: string,
+  sessionId: string,
+  token: string,
+  onEvent: (event: SessionEvent) => void
+): Promise<void> {
+  const { invoke, Channel } = await import('@tauri-apps/api/core');
+  const channel = new Channel<SessionEvent>();
+  channel.onmessage = onEvent;
+  await invoke('observe_session', { observationId, baseUrl, sessionId, token, onEvent: channel });
+}
+
+export async function stopObserving(observationId: string): Promise<boolean> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<boolean>('observe_stop', { observationId });
+}
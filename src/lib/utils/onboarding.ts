@@ -0,0 +1,40 @@
+// First-run setup wizard state
+// The backend owns the progress; the wizard shows `current_step` and reports each answer back
+
+import type { DemoSummary } from '../bindings/DemoSummary';
+import type { OnboardingState } from '../bindings/OnboardingState';
+import type { OnboardingStep } from '../bindings/OnboardingStep';
+import type { StepAnswer } from '../bindings/StepAnswer';
+
+export type { OnboardingState, OnboardingStep, StepAnswer };
+export type { StepState } from '../bindings/StepState';
+
+async function call(command: string, args?: Record<string, unknown>): Promise<OnboardingState> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<OnboardingState>(command, args);
+}
+
+export function fetchOnboarding(): Promise<OnboardingState> {
+  return call('onboarding_state');
+}
+
+/** Safe to repeat: the same answer twice leaves the state unchanged */
+export function completeStep(answer: StepAnswer): Promise<OnboardingState> {
+  return call('onboarding_complete_step', { answer });
+}
+
+/** Only encryption, shell hooks and example canvases can be skipped */
+export function skipStep(step: OnboardingStep): Promise<OnboardingState> {
+  return call('onboarding_skip_step', { step });
+}
+
+/** The example canvas step: seed the demo workspace, then record its canvas */
+export async function importExampleCanvas(): Promise<OnboardingState> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const demo = await invoke<DemoSummary>('demo_seed');
+  return completeStep({ step: 'example_canvas', canvases: [demo.canvas_id] });
+}
+
+export function resetOnboarding(): Promise<OnboardingState> {
+  return call('onboarding_reset');
+}
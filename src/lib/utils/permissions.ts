@@ -0,0 +1,25 @@
+// Capability groups the backend refuses, from the [permissions] config section
+// Lets the UI hide actions instead of showing them and failing on click
+
+import type { Capability } from '../bindings/Capability';
+import type { PermissionPolicy } from '../bindings/PermissionPolicy';
+
+export type { Capability, PermissionPolicy };
+
+let cached: Promise<PermissionPolicy> | null = null;
+
+/** The policy is fixed for the app's lifetime, so it is fetched once */
+export function fetchPermissions(): Promise<PermissionPolicy> {
+  cached ??= import('@tauri-apps/api/core')
+    .then(({ invoke }) => invoke<PermissionPolicy>('permissions_policy'))
+    .catch((error) => {
+      cached = null;
+      throw error;
+    });
+  return cached;
+}
+
+export async function isAllowed(capability: Capability): Promise<boolean> {
+  const policy = await fetchPermissions();
+  return !policy.denied.includes(capability);
+}
@@ -0,0 +1,28 @@
+// Pipelines without a shell: each stage's stdout is piped into the next one's stdin by the backend
+// Lets canvases run things like `ps aux | grep foo` while commands still never go through a shell
+
+import type { PipelineOutcome } from '../bindings/PipelineOutcome';
+import type { PipelineSpec as Spec } from '../bindings/PipelineSpec';
+import type { PipelineStage as Stage } from '../bindings/PipelineStage';
+
+export type { PipelineOutcome };
+export type { StageOutcome } from '../bindings/StageOutcome';
+
+/** A stage as sent: the backend fills in what is left out */
+export type PipelineStage = Pick<Stage, 'command'> & Partial<Stage>;
+
+/** A pipeline as sent: `stages`, and any of the request fields shared by every stage */
+export type PipelineSpec = { stages: PipelineStage[] } & Partial<Omit<Spec, 'stages'>>;
+
+/** Run `spec.stages` with each one's output piped into the next */
+export async function runPipeline(
+  spec: PipelineSpec,
+  options: { executionId?: string; timeoutMs?: number } = {}
+): Promise<PipelineOutcome> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<PipelineOutcome>('execute_pipeline', {
+    spec,
+    executionId: options.executionId ?? null,
+    timeoutMs: options.timeoutMs ?? null,
+  });
+}
@@ -0,0 +1,41 @@
+// Learned user preferences: muted suggestion reasons, fix style, package
+// manager and summary verbosity
+
+import type { Learned } from '../bindings/Learned';
+import type { MutedReason } from '../bindings/MutedReason';
+import type { PreferenceChoice } from '../bindings/PreferenceChoice';
+import type { PreferenceName } from '../bindings/PreferenceName';
+import type { Preferences } from '../bindings/Preferences';
+
+export type { Learned, MutedReason, PreferenceChoice, PreferenceName, Preferences };
+export type { FixStyle } from '../bindings/FixStyle';
+export type { Verbosity } from '../bindings/Verbosity';
+
+export type PackageManager = 'npm' | 'pnpm' | 'yarn' | 'bun';
+
+/** Everything learned about the user */
+export async function getPreferences(): Promise<Preferences> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Preferences>('preferences_get');
+}
+
+/** Set a preference, which learning then leaves alone */
+export async function setPreference(choice: PreferenceChoice): Promise<Preferences> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Preferences>('preferences_set', { choice });
+}
+
+/** Forget a preference, or `all`; `reasonKey` unmutes a single reason */
+export async function resetPreferences(
+  name: PreferenceName,
+  reasonKey?: string
+): Promise<Preferences> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Preferences>('preferences_reset', { name, reasonKey: reasonKey ?? null });
+}
+
+/** Dismiss a suggestion and hide every one shown for the same reason */
+export async function muteSuggestion(suggestionId: string): Promise<MutedReason> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<MutedReason>('suggestion_mute', { suggestionId });
+}
@@ -0,0 +1,33 @@
+// Profiles: separate config, data and keychain entries per named setup
+// Switching restarts RuneBook on the new profile
+
+import type { ProfileInfo } from '../bindings/ProfileInfo';
+
+export type { ProfileInfo };
+
+export async function currentProfile(): Promise<string> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string>('profile_current');
+}
+
+export async function listProfiles(): Promise<ProfileInfo[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ProfileInfo[]>('profile_list');
+}
+
+export async function createProfile(name: string): Promise<ProfileInfo> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ProfileInfo>('profile_create', { name });
+}
+
+/** Delete a profile and all of its data; not the default or the active one */
+export async function deleteProfile(name: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('profile_delete', { name });
+}
+
+/** Stop this profile's sessions and restart RuneBook on `name` */
+export async function switchProfile(name: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('profile_switch', { name });
+}
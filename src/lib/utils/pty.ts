@@ -0,0 +1,82 @@
+// Interactive commands on a backend pseudo-terminal
+// Repaint from the buffered screen, then apply `pty-event`s numbered after it
+
+import type { PtyInfo } from '../bindings/PtyInfo';
+import type { PtyScreen } from '../bindings/PtyScreen';
+
+export type { PtyInfo, PtyScreen };
+
+export type PtyEvent = { session_id: string } & (
+  | { event: 'output'; seq: number; data: string }
+  | { event: 'exited'; exit_code: number | null }
+);
+
+export interface PtyView {
+  /** Raw terminal output, escape sequences included */
+  write: (data: string) => void;
+  exited: (exitCode: number | null) => void;
+}
+
+/**
+ * Start `request` on a PTY and feed its output to `view`. Returns the
+ * session and a function that stops following it.
+ */
+export async function startPty(
+  request: Record<string, unknown>,
+  size: { cols: number; rows: number },
+  view: PtyView,
+  confirmationToken?: string
+): Promise<{ info: PtyInfo; unlisten: () => void }> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const { listen } = await import('@tauri-apps/api/event');
+
+  // Events can arrive before the session id is known; hold them until then
+  let sessionId: string | null = null;
+  let seq = 0;
+  let held: PtyEvent[] = [];
+  let exited = false;
+  const apply = (event: PtyEvent) => {
+    if (event.event === 'exited') {
+      if (!exited) view.exited(event.exit_code);
+      exited = true;
+    } else if (event.seq > seq) {
+      seq = event.seq;
+      view.write(event.data);
+    }
+  };
+  const unlisten = await listen<PtyEvent>('pty-event', ({ payload }) => {
+    if (sessionId === null) held.push(payload);
+    else if (payload.session_id === sessionId) apply(payload);
+  });
+
+  const info = await invoke<PtyInfo>('pty_start', {
+    spec: request,
+    confirmationToken,
+    cols: size.cols,
+    rows: size.rows
+  });
+  const screen = await invoke<PtyScreen>('pty_screen', { id: info.id });
+  sessionId = info.id;
+  seq = screen.seq;
+  view.write(screen.data);
+  held.filter(e => e.session_id === info.id).forEach(apply);
+  held = [];
+  if (!screen.running) apply({ session_id: info.id, event: 'exited', exit_code: screen.exit_code });
+  return { info, unlisten };
+}
+
+export async function writePty(id: string, data: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('pty_write', { id, data });
+}
+
+export async function resizePty(id: string, cols: number, rows: number): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('pty_resize', { id, cols, rows });
+}
+
+/** End the session, killing the program if it still runs */
+export async function closePty(id: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('pty_close', { id });
+}
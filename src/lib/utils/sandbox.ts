@@ -0,0 +1,21 @@
+// Sandboxed previews of the files a command would change
+// The command runs against a shadow of its working directory; the real files are left alone
+
+import type { SandboxReport } from '../bindings/SandboxReport';
+import type { SandboxSpec as Spec } from '../bindings/SandboxSpec';
+
+export type { SandboxReport };
+export type { FileChange } from '../bindings/FileChange';
+export type { ShadowStrategy } from '../bindings/ShadowStrategy';
+
+/** A preview as sent: the backend fills in what is left out */
+export type SandboxSpec = Pick<Spec, 'command'> & Partial<Spec>;
+
+/** Run a command against a shadow of its directory and report what it would change */
+export async function previewChanges(
+  spec: SandboxSpec,
+  confirmationToken?: string
+): Promise<SandboxReport> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<SandboxReport>('sandbox_preview', { spec, confirmationToken: confirmationToken ?? null });
+}
@@ -0,0 +1,18 @@
+// Suggestion explanations
+// Why a suggestion was made, how it ranks, and what would make it stop appearing
+
+import type { Suggestion } from '../bindings/Suggestion';
+import type { SuggestionExplanation } from '../bindings/SuggestionExplanation';
+
+export type { SuggestionExplanation };
+export type { Evidence } from '../bindings/Evidence';
+export type { RankingBreakdown } from '../bindings/RankingBreakdown';
+export type { StopCondition } from '../bindings/StopCondition';
+
+export type { Suggestion };
+
+/** Why a suggestion is shown, and what would make it stop appearing */
+export async function explainSuggestion(suggestionId: string): Promise<SuggestionExplanation> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<SuggestionExplanation>('explain_suggestion', { suggestionId });
+}
@@ -0,0 +1,26 @@
+// System health for the status bar indicator
+// Reports the analyzers' health to the backend, then asks it for every component's status
+
+import { getAnalysisService } from '../agent/analysis-service';
+import type { SystemHealth } from '../bindings/SystemHealth';
+
+export type { SystemHealth };
+export type { ComponentHealth } from '../bindings/ComponentHealth';
+export type { HealthStatus } from '../bindings/HealthStatus';
+
+export async function fetchSystemHealth(): Promise<SystemHealth> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('health_report_analyzers', {
+    analyzers: getAnalysisService().getAnalyzerHealth(),
+  });
+  return invoke<SystemHealth>('system_health');
+}
+
+/** One line per component that needs attention, for the indicator's tooltip */
+export function describeHealth(health: SystemHealth): string {
+  const problems = health.components
+    .filter(c => c.status === 'degraded' || c.status === 'down')
+    .map(c => `${c.name}: ${c.status}${c.last_error ? ` (${c.last_error.message})` : ''}`);
+  const summary = problems.length > 0 ? problems.join('\n') : 'All systems healthy';
+  return health.queue_depth > 0 ? `${summary}\n${health.queue_depth} queued` : summary;
+}
@@ -0,0 +1,43 @@
+// Trace spans for work done in the webview, such as analyzer runs
+// Recorded spans go to the backend's tracer, which exports them with its own when [telemetry] is enabled
+
+import type { Span } from '../bindings/Span';
+
+export type { Span };
+
+function hexId(length: number): string {
+  return crypto.randomUUID().replace(/-/g, '').slice(0, length);
+}
+
+/** Start a span; with `parent`, in the parent's trace */
+export function startSpan(name: string, parent?: Span): Span {
+  return {
+    trace_id: parent?.trace_id ?? hexId(32),
+    span_id: hexId(16),
+    parent_span_id: parent?.span_id ?? null,
+    name,
+    kind: 'internal',
+    start_time: new Date().toISOString(),
+    end_time: null,
+    attributes: {},
+    status: { code: 'unset' },
+  };
+}
+
+/** Stamp `span`'s end; with `error`, as failed */
+export function endSpan(span: Span, error?: string): Span {
+  span.end_time = new Date().toISOString();
+  span.status = error === undefined ? { code: 'ok' } : { code: 'error', message: error };
+  return span;
+}
+
+/** Hand a finished span to the backend for export; outside the desktop app it is dropped */
+export async function recordSpan(span: Span): Promise<void> {
+  if (typeof window === 'undefined' || !('__TAURI__' in window)) return;
+  try {
+    const { invoke } = await import('@tauri-apps/api/core');
+    await invoke('record_trace_span', { span });
+  } catch (error) {
+    console.warn('Trace span not recorded:', error);
+  }
+}
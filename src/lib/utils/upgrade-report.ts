@@ -0,0 +1,28 @@
+// Startup upgrade report ("what changed for your data")
+// Shown once per notable startup; deferred memory migrations run only when the user agrees
+
+import { describeError } from './messages';
+import type { UpgradeReport } from '../bindings/UpgradeReport';
+
+export type { UpgradeReport };
+
+export async function showUpgradeReport(report: UpgradeReport): Promise<void> {
+  if (report.changes.length === 0) return;
+  const heading =
+    report.kind === 'upgrade' || report.kind === 'downgrade'
+      ? `RuneBook ${report.from_version} → ${report.to_version}`
+      : `RuneBook ${report.to_version}`;
+  const lines = report.changes.map(c => `• ${c.summary}`).join('\n');
+
+  if (report.store.pending.length === 0) {
+    alert(`${heading}\n\nWhat changed for your data:\n${lines}`);
+    return;
+  }
+  if (!confirm(`${heading}\n\nWhat changed for your data:\n${lines}\n\nRun the pending memory migrations now?`)) return;
+  const { invoke } = await import('@tauri-apps/api/core');
+  try {
+    await invoke('upgrade_run_migrations');
+  } catch (e) {
+    alert(describeError(e));
+  }
+}
@@ -0,0 +1,20 @@
+// Outbound webhook deliveries
+// Hooks are configured in the `[webhooks]` config section; this only reads the delivery log
+
+export interface WebhookDelivery {
+  hook: string;
+  event: 'alert' | 'digest';
+  at: string;
+  attempts: number;
+  /** HTTP status of the last attempt, if it got a response */
+  status: number | null;
+  delivered: boolean;
+  error: string | null;
+  duration_ms: number;
+}
+
+/** The newest deliveries, newest first, to one hook when `hook` is set */
+export async function listWebhookDeliveries(hook?: string, limit?: number): Promise<WebhookDelivery[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<WebhookDelivery[]>('webhook_deliveries', { hook: hook ?? null, limit: limit ?? null });
+}